@@ -1,15 +1,16 @@
-use std::{net::SocketAddr, path::PathBuf, str::FromStr};
+use std::{collections::HashSet, net::SocketAddr, path::PathBuf, str::FromStr};
 
 use async_executor::Executor;
 use async_std::sync::{Arc, Mutex};
 use async_trait::async_trait;
 use clap::{IntoApp, Parser};
 use easy_parallel::Parallel;
-use log::{debug, info};
+use log::{debug, error, info, warn};
 use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use simplelog::{ColorChoice, TermLogger, TerminalMode};
+use url::Url;
 
 use darkfi::{
     blockchain::{rocks::columns, Rocks, RocksColumn},
@@ -22,22 +23,61 @@ use darkfi::{
     },
     node::{client::Client, state::State},
     rpc::{
+        acl::RpcAcl,
         jsonrpc::{error as jsonerr, response as jsonresp, ErrorCode::*, JsonRequest, JsonResult},
         rpcserver::{listen_and_serve, RequestHandler, RpcServerConfig},
     },
     util::{
+        async_util::sleep,
+        check::CheckReport,
         cli::{log_config, spawn_config, Config},
-        expand_path, join_config_path,
+        decode_base10, expand_path, join_config_path,
+        lock::DbLock,
         parse::truncate,
         serial::serialize,
-        NetworkName,
+        service::{run_command, running_pid, start_service, ServiceCommand},
+        supervisor::{RestartPolicy, Supervisor, SupervisorPtr},
+        NetworkName, Timestamp,
+    },
+    wallet::{
+        cashierdb::{CashierDb, DepositRecord, DepositStatus},
+        walletdb::WalletDb,
     },
-    wallet::{cashierdb::CashierDb, walletdb::WalletDb},
     zk::circuit::{MintContract, SpendContract},
-    Error, Result,
+    ClientFailed, Error, Result,
+};
+
+use cashierd::{
+    policy::{
+        check_signed_timestamp, deposit_events_message, register_withdraw_message,
+        verify_signature, withdraw_message, WithdrawPolicy,
+    },
+    reserves::{Reserves, ReservesPtr, RESERVES_INTERVAL},
+    service::{
+        bridge,
+        bridge::Bridge,
+        deposit::{DepositState, DepositSubscriptions, DepositSubscriptionsPtr},
+    },
 };
 
-use cashierd::service::{bridge, bridge::Bridge};
+/// Methods only the operator can call, with the `operator_auth_token`
+const OPERATOR_METHODS: &[&str] = &[
+    "list_subscriptions",
+    "cancel_subscription",
+    "extend_subscription",
+    "list_deposits",
+    "refund_deposit",
+    "resolve_refund",
+];
+
+/// Seconds to wait before minting a deposit again after a transient
+/// failure, doubled on every attempt up to `MINT_RETRY_MAX`
+const MINT_RETRY_SECONDS: u64 = 5;
+const MINT_RETRY_MAX: u64 = 300;
+
+/// Seconds `deposit_events` waits for an event to come in, short of the
+/// read timeout of the RPC clients
+const DEPOSIT_EVENTS_WAIT: u64 = 20;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FeatureNetwork {
@@ -47,6 +87,15 @@ pub struct FeatureNetwork {
     pub blockchain: String,
     /// Keypair
     pub keypair: String,
+    /// Custom RPC endpoint, overriding the public one
+    #[serde(default)]
+    pub rpc_server: Option<String>,
+    /// Custom WebSocket endpoint, overriding the public one
+    #[serde(default)]
+    pub wss_server: Option<String>,
+    /// Auth token for paid RPC providers
+    #[serde(default)]
+    pub auth_token: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -79,6 +128,21 @@ pub struct CashierdConfig {
     pub geth_socket: String,
     /// Geth passphrase
     pub geth_passphrase: String,
+    /// Seconds a withdraw address has to be registered before it can be
+    /// used. Unset disables the withdraw address whitelist.
+    #[serde(default)]
+    pub withdraw_whitelist_delay: Option<u64>,
+    /// Let deposit requests carry a user tag, and derive the deposit
+    /// address of a tag from the network's main keypair
+    #[serde(default)]
+    pub tagged_deposits: bool,
+    /// Seconds between two proof of reserves attestations
+    #[serde(default)]
+    pub reserves_interval: Option<u64>,
+    /// Token the operator passes in the `auth` member of requests to call
+    /// the operator methods. Unset disables them.
+    #[serde(default)]
+    pub operator_auth_token: Option<String>,
     /// The configured networks to use
     pub networks: Vec<FeatureNetwork>,
 }
@@ -99,6 +163,22 @@ pub struct CliCashierd {
     /// Refresh the wallet and slabstore
     #[clap(short, long)]
     pub refresh: bool,
+    /// Check the configuration, wallets, database and ports, print a JSON
+    /// report and exit
+    #[clap(long)]
+    pub check: bool,
+    /// With --check, also check the gateway can be reached
+    #[clap(long)]
+    pub check_upstreams: bool,
+    /// Detach from the terminal and run in the background
+    #[clap(long)]
+    pub daemon: bool,
+    /// Take over the database locks left by an instance that crashed
+    #[clap(long)]
+    pub force_unlock: bool,
+    /// Manage the instance running on the database instead: status or stop
+    #[clap(parse(try_from_str))]
+    pub service: Option<ServiceCommand>,
 }
 
 const CONFIG_FILE_CONTENTS: &[u8] = include_bytes!("../cashierd_config.toml");
@@ -112,11 +192,25 @@ fn handle_bridge_error(error_code: u32) -> Result<()> {
     }
 }
 
+/// The DarkFi public key in `user`, if `signature` is its base58 encoded
+/// signature of `message`
+fn signed_by(user: &Value, signature: &Value, message: &[u8]) -> Option<PublicKey> {
+    let user = user.as_str().and_then(|u| Address::from_str(u).ok())?;
+    let user = PublicKey::try_from(user).ok()?;
+    match verify_signature(&user, message, signature.as_str()?) {
+        true => Some(user),
+        false => None,
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Network {
     pub name: NetworkName,
     pub blockchain: String,
     pub keypair: String,
+    pub rpc_server: Option<String>,
+    pub wss_server: Option<String>,
+    pub auth_token: Option<String>,
 }
 
 struct Cashierd {
@@ -124,7 +218,17 @@ struct Cashierd {
     cashier_wallet: Arc<CashierDb>,
     networks: Vec<Network>,
     public_key: Address,
+    policy: WithdrawPolicy,
     config: CashierdConfig,
+    supervisor: SupervisorPtr,
+    deposits: DepositSubscriptionsPtr,
+    reserves: ReservesPtr,
+    /// Checks the token of operator method calls, `None` when there's no
+    /// token configured and they're disabled
+    operator_acl: Option<RpcAcl>,
+    /// Deposits found in the refunding state on start, whose refund was
+    /// cut short and waits for the operator to resolve it
+    stale_refunds: Mutex<HashSet<u64>>,
 }
 
 #[async_trait]
@@ -134,12 +238,38 @@ impl RequestHandler for Cashierd {
             return JsonResult::Err(jsonerr(InvalidParams, None, req.id))
         }
 
-        debug!(target: "RPC", "--> {}", serde_json::to_string(&req).unwrap());
+        // Not the whole request, it may carry the operator token
+        debug!(target: "RPC", "--> {} {}", req.method, req.params);
+
+        let operator = req.method.as_str().map_or(false, |m| OPERATOR_METHODS.contains(&m));
+        if operator && !self.operator_acl.as_ref().map_or(false, |acl| acl.allows(&req)) {
+            warn!(target: "RPC", "Denied {} request without the operator token", req.method);
+            return JsonResult::Err(jsonerr(Unauthorized, None, req.id))
+        }
 
         match req.method.as_str() {
             Some("deposit") => return self.deposit(req.id, req.params, executor).await,
             Some("withdraw") => return self.withdraw(req.id, req.params).await,
+            Some("register_withdraw_address") => {
+                return self.register_withdraw_address(req.id, req.params).await
+            }
             Some("features") => return self.features(req.id, req.params).await,
+            Some("estimated_fee") => return self.estimated_fee(req.id, req.params).await,
+            Some("reserves") => return self.reserves(req.id, req.params).await,
+            Some("tasks") => return self.tasks(req.id, req.params).await,
+            Some("list_subscriptions") => return self.list_subscriptions(req.id, req.params).await,
+            Some("cancel_subscription") => {
+                return self.cancel_subscription(req.id, req.params).await
+            }
+            Some("extend_subscription") => {
+                return self.extend_subscription(req.id, req.params).await
+            }
+            Some("deposit_events") => return self.deposit_events(req.id, req.params).await,
+            Some("list_deposits") => return self.list_deposits(req.id, req.params).await,
+            Some("refund_deposit") => {
+                return self.refund_deposit(req.id, req.params, executor).await
+            }
+            Some("resolve_refund") => return self.resolve_refund(req.id, req.params).await,
             Some(_) => {}
             None => {}
         };
@@ -164,12 +294,37 @@ impl Cashierd {
                 name: NetworkName::from_str(&network.name)?,
                 blockchain: network.blockchain,
                 keypair: network.keypair,
+                rpc_server: network.rpc_server,
+                wss_server: network.wss_server,
+                auth_token: network.auth_token,
             });
         }
 
-        let bridge = bridge::Bridge::new();
-
-        Ok(Self { bridge, cashier_wallet, networks, public_key, config })
+        let supervisor = Supervisor::new();
+        let bridge = bridge::Bridge::new(supervisor.clone());
+        let policy = WithdrawPolicy::new(config.withdraw_whitelist_delay);
+        let deposits = DepositSubscriptions::new();
+        let reserves = Reserves::new();
+        // No method is open, so every operator call needs the token
+        let operator_acl = config
+            .operator_auth_token
+            .clone()
+            .filter(|t| !t.is_empty())
+            .map(|t| RpcAcl::new(Some(t), vec![]));
+
+        Ok(Self {
+            bridge,
+            cashier_wallet,
+            networks,
+            public_key,
+            policy,
+            config,
+            supervisor,
+            deposits,
+            reserves,
+            operator_acl,
+            stale_refunds: Mutex::new(HashSet::new()),
+        })
     }
 
     async fn start(
@@ -180,23 +335,43 @@ impl Cashierd {
     ) -> Result<(smol::Task<Result<()>>, smol::Task<Result<()>>)> {
         self.cashier_wallet.init_db().await?;
 
+        // Nothing is being refunded yet, so a deposit left refunding was cut
+        // short, maybe after its refund was sent. Only the operator can tell.
+        let stale_refunds =
+            self.cashier_wallet.get_deposits(Some(DepositStatus::Refunding)).await?;
+        for deposit in &stale_refunds {
+            warn!(
+                target: "CASHIER DAEMON",
+                "Refund of deposit {} was interrupted, resolve it with resolve_refund", deposit.id
+            );
+        }
+        *self.stale_refunds.lock().await = stale_refunds.iter().map(|d| d.id).collect();
+
         for network in self.networks.iter() {
             match network.name {
                 #[cfg(feature = "sol")]
                 NetworkName::Solana => {
                     debug!(target: "CASHIER DAEMON", "Adding solana network");
-                    use cashierd::service::SolClient;
+                    use cashierd::service::{SolClient, SolEndpoints};
 
                     let _bridge = self.bridge.clone();
 
+                    let endpoints = SolEndpoints {
+                        rpc_server: network.rpc_server.clone(),
+                        wss_server: network.wss_server.clone(),
+                        auth_token: network.auth_token.clone(),
+                    };
+
                     let sol_client = SolClient::new(
                         self.cashier_wallet.clone(),
                         &network.blockchain,
                         &network.keypair,
+                        &endpoints,
+                        self.deposits.clone(),
                     )
                     .await?;
 
-                    _bridge.add_clients(NetworkName::Solana, sol_client).await?;
+                    _bridge.add_clients(sol_client).await?;
                 }
 
                 #[cfg(feature = "eth")]
@@ -213,11 +388,12 @@ impl Cashierd {
                         &network.blockchain,
                         expand_path(&self.config.geth_socket)?.to_str().unwrap(),
                         &passphrase,
+                        self.deposits.clone(),
                     );
 
                     eth_client.setup_keypair(self.cashier_wallet.clone(), &network.keypair).await?;
 
-                    _bridge.add_clients(NetworkName::Ethereum, Arc::new(eth_client)).await?;
+                    _bridge.add_clients(Arc::new(eth_client)).await?;
                 }
 
                 #[cfg(feature = "btc")]
@@ -231,10 +407,11 @@ impl Cashierd {
                         self.cashier_wallet.clone(),
                         &network.blockchain,
                         &network.keypair,
+                        self.deposits.clone(),
                     )
                     .await?;
 
-                    _bridge.add_clients(NetworkName::Bitcoin, btc_client).await?;
+                    _bridge.add_clients(btc_client).await?;
                 }
                 _ => {}
             }
@@ -242,6 +419,9 @@ impl Cashierd {
 
         client.start().await?;
 
+        let secret = client.main_keypair.lock().await.secret;
+        self.spawn_reserves_attestations(secret, &executor);
+
         let (notify, recv_coin) = async_channel::unbounded::<(PublicKey, u64)>();
 
         client
@@ -270,6 +450,8 @@ impl Cashierd {
         });
 
         let bridge2 = self.bridge.clone();
+        let cashier_wallet = self.cashier_wallet.clone();
+        let deposits = self.deposits.clone();
         let listen_for_notification_from_bridge_task: smol::Task<Result<()>> =
             executor.spawn(async move {
                 while let Some(token_notification) = bridge2.clone().listen().await {
@@ -283,15 +465,94 @@ impl Cashierd {
                         token_notification.decimals,
                     )?;
 
-                    client
-                        .send(
-                            token_notification.drk_pub_key,
-                            received_balance,
-                            token_notification.token_id,
-                            true,
-                            state.clone(),
-                        )
-                        .await?;
+                    let mut deposit = DepositRecord {
+                        id: 0,
+                        network: token_notification.network.clone(),
+                        token_id: token_notification.token_id,
+                        drk_public_key: token_notification.drk_pub_key,
+                        amount: received_balance,
+                        sender: token_notification.sender.clone(),
+                        status: DepositStatus::Minted,
+                        reason: None,
+                        refund_amount: None,
+                        refund_tx: None,
+                        received_at: Timestamp::current_time(),
+                        updated_at: Timestamp::current_time(),
+                    };
+
+                    // A deposit that can't be minted stays with the cashier, on
+                    // the deposit address if its memo doesn't bind it to the
+                    // user, and is kept in the ledger as failed so it can be
+                    // refunded. Minting is tried again until it succeeds or
+                    // fails for good.
+                    let verified = token_notification.memo.verify(&token_notification.drk_pub_key);
+                    let mut retry = MINT_RETRY_SECONDS;
+                    let minted = match verified {
+                        Ok(()) => loop {
+                            let result = client
+                                .send(
+                                    token_notification.drk_pub_key,
+                                    received_balance,
+                                    token_notification.token_id,
+                                    true,
+                                    state.clone(),
+                                )
+                                .await;
+
+                            match result {
+                                Err(e) if !is_permanent(&e) => {
+                                    warn!(
+                                        target: "CASHIER DAEMON",
+                                        "Failed minting {} deposit, retrying in {}s: {}",
+                                        token_notification.network, retry, e
+                                    );
+                                    sleep(retry).await;
+                                    retry = (retry * 2).min(MINT_RETRY_MAX);
+                                }
+                                result => break result.map_err(Error::from),
+                            }
+                        },
+                        Err(e) => Err(e),
+                    };
+
+                    match minted {
+                        Ok(()) => {
+                            if let Err(e) = cashier_wallet
+                                .add_minted(
+                                    &token_notification.network,
+                                    &token_notification.token_id,
+                                    received_balance,
+                                )
+                                .await
+                            {
+                                error!(
+                                    target: "CASHIER DAEMON",
+                                    "Failed tracking minted supply: {}", e
+                                );
+                            }
+                        }
+                        Err(e) => {
+                            error!(
+                                target: "CASHIER DAEMON",
+                                "Failed minting {} deposit: {}", token_notification.network, e
+                            );
+                            deposit.status = DepositStatus::Failed;
+                            deposit.reason = Some(e.to_string());
+                            deposits
+                                .publish(
+                                    token_notification.network.clone(),
+                                    &token_notification.address,
+                                    token_notification.drk_pub_key,
+                                    DepositState::Failed,
+                                    Some(e.to_string()),
+                                )
+                                .await;
+                        }
+                    }
+
+                    if let Err(e) = cashier_wallet.put_deposit(&deposit).await {
+                        error!(target: "CASHIER DAEMON", "Failed recording deposit: {}", e);
+                    }
                 }
                 Ok(())
             });
@@ -299,6 +560,18 @@ impl Cashierd {
         Ok((listen_for_receiving_coins_task, listen_for_notification_from_bridge_task))
     }
 
+    /// Attest the reserves of the bridged tokens every `reserves_interval`
+    /// seconds, signing with the cashier's darkfi key.
+    fn spawn_reserves_attestations(&self, secret: SecretKey, executor: &Executor<'_>) {
+        let interval = self.config.reserves_interval.unwrap_or(RESERVES_INTERVAL);
+        let (bridge, cashier_wallet) = (self.bridge.clone(), self.cashier_wallet.clone());
+        let reserves = self.reserves.clone();
+
+        self.supervisor.spawn(executor, "proof of reserves", RestartPolicy::Never, move || {
+            reserves.clone().run(bridge.clone(), cashier_wallet.clone(), secret, interval)
+        });
+    }
+
     async fn listen_for_receiving_coins(
         bridge: Arc<Bridge>,
         cashier_wallet: Arc<CashierDb>,
@@ -341,17 +614,28 @@ impl Cashierd {
             let error_code = res.error as u32;
 
             if error_code != 0 {
+                if let bridge::BridgeResponsePayload::Error(reason) = &res.payload {
+                    error!(target: "CASHIER DAEMON", "Withdrawal failed: {}", reason);
+                }
                 return handle_bridge_error(error_code)
             }
 
             match res.payload {
-                bridge::BridgeResponsePayload::Send => {
+                bridge::BridgeResponsePayload::Send(tx_id) => {
+                    info!(target: "CASHIER DAEMON", "Withdrawal sent, tx: {}", tx_id);
                     cashier_wallet
                         .confirm_withdraw_key_record(
                             &withdraw_token.token_public_key,
                             &withdraw_token.network,
                         )
                         .await?;
+                    // A failure here must not stop the withdrawals of others
+                    if let Err(e) = cashier_wallet
+                        .add_redeemed(&withdraw_token.network, &withdraw_token.token_id, amount)
+                        .await
+                    {
+                        error!(target: "CASHIER DAEMON", "Failed tracking redeemed supply: {}", e);
+                    }
                 }
                 _ => {
                     return Err(Error::CashierError(
@@ -364,44 +648,41 @@ impl Cashierd {
         Ok(())
     }
 
-    fn check_token_id(network: &NetworkName, _token_id: &str) -> Result<Option<String>> {
-        match network {
-            #[cfg(feature = "sol")]
-            NetworkName::Solana => {
-                use cashierd::service::sol::SOL_NATIVE_TOKEN_ID;
-                if _token_id != SOL_NATIVE_TOKEN_ID {
-                    return Ok(Some(_token_id.to_string()))
-                }
-                Ok(None)
-            }
-            #[cfg(feature = "eth")]
-            NetworkName::Ethereum => {
-                use cashierd::service::eth::ETH_NATIVE_TOKEN_ID;
-                if _token_id != ETH_NATIVE_TOKEN_ID {
-                    return Ok(Some(_token_id.to_string()))
-                }
-                Ok(None)
-            }
-            #[cfg(feature = "btc")]
-            NetworkName::Bitcoin => Ok(None),
-            _ => Err(Error::NotSupportedNetwork),
-        }
-    }
-
     // RPCAPI:
     // Executes a deposit request given `network` and `token_id`.
-    // Returns the address where the deposit shall be transferred to.
-    // --> {"jsonrpc": "2.0", "method": "deposit", "params": ["network", "token", "publickey"], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": "Ht5G1RhkcKnpLVLMhqJc5aqZ4wYUEbxbtZwGCVbgU7DL", "id": 1}
+    // Returns the address where the deposit shall be transferred to, and the
+    // memo that must be attached to the deposit transaction (as a memo
+    // instruction on Solana, or as calldata on Ethereum). Deposits without
+    // a matching memo are not minted.
+    // With `tagged_deposits` enabled, an optional user `tag` can be given.
+    // The deposit address is then derived from the tag and the network's
+    // main keypair, so the same tag always gets the same address.
+    // --> {"jsonrpc": "2.0", "method": "deposit", "params": ["network", "token", "publickey", "tag"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"address": "Ht5G1RhkcKnpLVLMhqJc5aqZ4wYUEbxbtZwGCVbgU7DL", "memo": "drk:1DarkFi..."}, "id": 1}
     async fn deposit(&self, id: Value, params: Value, executor: Arc<Executor<'_>>) -> JsonResult {
         info!(target: "CASHIER DAEMON", "Received deposit request");
 
         let args: &Vec<serde_json::Value> = params.as_array().unwrap();
 
-        if args.len() != 3 {
+        if args.len() != 3 && args.len() != 4 {
             return JsonResult::Err(jsonerr(InvalidParams, None, id))
         }
 
+        let tag = match args.get(3) {
+            None => None,
+            Some(_) if !self.config.tagged_deposits => {
+                return JsonResult::Err(jsonerr(
+                    InvalidParams,
+                    Some("Cashier doesn't accept tagged deposits".to_string()),
+                    id,
+                ))
+            }
+            Some(t) => match t.as_str() {
+                Some(t) if !t.is_empty() => Some(t),
+                _ => return JsonResult::Err(jsonerr(InvalidParams, None, id)),
+            },
+        };
+
         let network: NetworkName;
         let mut mint_address: &str;
         let drk_pub_key: &str;
@@ -429,22 +710,31 @@ impl Cashierd {
             ))
         }
 
-        let result: Result<String> = async {
+        let result: Result<(String, String)> = async {
             let token_id = generate_id2(mint_address, &network)?;
 
-            let mint_address_opt = Self::check_token_id(&network, mint_address)?;
+            let mint_address_opt = self.bridge.mint_address(&network, mint_address).await?;
 
             if mint_address_opt.is_none() {
                 mint_address = "";
             }
             let drk_pub_key = Address::from_str(drk_pub_key)?;
             let drk_pub_key: PublicKey = PublicKey::try_from(drk_pub_key)?;
+            let memo = bridge::deposit_memo(&drk_pub_key);
 
-            // check if the drk public key already exist
-            let check = self
-                .cashier_wallet
-                .get_deposit_token_keys_by_dkey_public(&drk_pub_key, &network)
-                .await?;
+            // check if the drk public key, or the tag, already exist
+            let check = match tag {
+                Some(tag) => {
+                    self.cashier_wallet
+                        .get_deposit_token_keys_by_tag(&drk_pub_key, tag, &network)
+                        .await?
+                }
+                None => {
+                    self.cashier_wallet
+                        .get_deposit_token_keys_by_dkey_public(&drk_pub_key, &network)
+                        .await?
+                }
+            };
 
             // start new subscription from the bridge and then cashierd will
             // send a request to the bridge to generate keypair for the desired token
@@ -464,12 +754,13 @@ impl Cashierd {
                 bridge.subscribe(drk_pub_key, mint_address_opt, executor).await;
 
             if check.is_empty() {
+                let payload = match tag {
+                    Some(tag) => bridge::BridgeRequestsPayload::WatchTag(tag.to_string()),
+                    None => bridge::BridgeRequestsPayload::Watch(None),
+                };
                 bridge_subscribtion
                     .sender
-                    .send(bridge::BridgeRequests {
-                        network: network.clone(),
-                        payload: bridge::BridgeRequestsPayload::Watch(None),
-                    })
+                    .send(bridge::BridgeRequests { network: network.clone(), payload })
                     .await?;
             } else {
                 let keypair = check[0].clone();
@@ -487,9 +778,11 @@ impl Cashierd {
             let error_code = bridge_res.error as u32;
 
             if error_code != 0 {
-                return handle_bridge_error(error_code).map(|_| String::new())
+                return handle_bridge_error(error_code).map(|_| (String::new(), String::new()))
             }
 
+            self.cashier_wallet.put_token_supply(&network, &token_id, mint_address.into()).await?;
+
             match bridge_res.payload {
                 bridge::BridgeResponsePayload::Watch(token_key) => {
                     // add pairings to db
@@ -501,19 +794,22 @@ impl Cashierd {
                             &network,
                             &token_id,
                             mint_address.into(),
+                            tag,
                         )
                         .await?;
 
-                    Ok(token_key.public_key)
+                    Ok((token_key.public_key, memo))
                 }
-                bridge::BridgeResponsePayload::Address(token_pub) => Ok(token_pub),
+                bridge::BridgeResponsePayload::Address(token_pub) => Ok((token_pub, memo)),
                 _ => Err(Error::CashierError("Receive unknown value from Subscription".into())),
             }
         }
         .await;
 
         match result {
-            Ok(res) => JsonResult::Resp(jsonresp(json!(res), json!(id))),
+            Ok((address, memo)) => {
+                JsonResult::Resp(jsonresp(json!({"address": address, "memo": memo}), json!(id)))
+            }
             Err(err) => JsonResult::Err(jsonerr(InternalError, Some(err.to_string()), json!(id))),
         }
     }
@@ -521,15 +817,20 @@ impl Cashierd {
     // RPCAPI:
     // Executes a withdraw request given `network`, `token_id`, `publickey`
     // and `amount`. `publickey` is supposed to correspond to `network`.
+    // With a withdraw whitelist, the request also carries the user's DarkFi
+    // address and their base58 signature of the request, see
+    // `policy::withdraw_message`, and `publickey` has to be registered by
+    // that user through `register_withdraw_address`, past its activation
+    // delay.
     // Returns the transaction ID of the processed withdraw.
-    // --> {"jsonrpc": "2.0", "method": "withdraw", "params": ["network", "token", "publickey", "amount"], "id": 1}
+    // --> {"jsonrpc": "2.0", "method": "withdraw", "params": ["network", "token", "publickey", "amount", "1DarkFi...", "sig"], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": "txID", "id": 1}
     async fn withdraw(&self, id: Value, params: Value) -> JsonResult {
         info!(target: "CASHIER DAEMON", "Received withdraw request");
 
         let args: &Vec<serde_json::Value> = params.as_array().unwrap();
 
-        if args.len() != 4 {
+        if args.len() != 4 && args.len() != 6 {
             return JsonResult::Err(jsonerr(InvalidParams, None, id))
         }
 
@@ -560,10 +861,25 @@ impl Cashierd {
             ))
         }
 
+        // With a whitelist, only the user who registered the address can
+        // withdraw to it
+        let user = match (self.policy.whitelist_delay, args.get(4), args.get(5)) {
+            (None, _, _) => None,
+            (Some(_), Some(user), Some(signature)) if args[3].is_string() => {
+                let amount = args[3].as_str().unwrap();
+                let message = withdraw_message(&network, mint_address, address, amount);
+                match signed_by(user, signature, &message) {
+                    Some(v) => Some(v),
+                    None => return JsonResult::Err(jsonerr(Unauthorized, None, id)),
+                }
+            }
+            (Some(_), _, _) => return JsonResult::Err(jsonerr(InvalidParams, None, id)),
+        };
+
         let result: Result<String> = async {
             let token_id: DrkTokenId = generate_id2(mint_address, &network)?;
 
-            let mint_address_opt = Self::check_token_id(&network, mint_address)?;
+            let mint_address_opt = self.bridge.mint_address(&network, mint_address).await?;
 
             if mint_address_opt.is_none() {
                 // empty string
@@ -572,6 +888,15 @@ impl Cashierd {
 
             let address = serialize(&address.to_string());
 
+            if let Some(user) = &user {
+                let registered_at =
+                    self.cashier_wallet.get_whitelisted_address(user, &address, &network).await?;
+                let now = Timestamp::current_time();
+                if let Err(e) = self.policy.check_address(registered_at, now) {
+                    return Err(Error::CashierError(e.to_string()))
+                }
+            }
+
             let cashier_public: PublicKey;
 
             if let Some(addr) = self
@@ -607,6 +932,103 @@ impl Cashierd {
         }
     }
 
+    // RPCAPI:
+    // Registers `address` on `network` as a withdraw address of the user
+    // with the given DarkFi address, who signs the registration with its
+    // key, see `policy::register_withdraw_message`. The signature is base58
+    // encoded. If the cashier enforces a withdraw whitelist, users can only
+    // withdraw to addresses they registered, and only once the configured
+    // delay has passed since registering them.
+    // Returns the registration time and the time the address becomes usable.
+    // --> {"jsonrpc": "2.0", "method": "register_withdraw_address", "params": ["network", "address", "1DarkFi...", "sig"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"registered_at": 1656000000, "active_at": 1656086400}, "id": 1}
+    async fn register_withdraw_address(&self, id: Value, params: Value) -> JsonResult {
+        let args: &Vec<serde_json::Value> = params.as_array().unwrap();
+
+        if args.len() != 4 {
+            return JsonResult::Err(jsonerr(InvalidParams, None, id))
+        }
+
+        let (network, address) = match (args[0].as_str(), args[1].as_str()) {
+            (Some(n), Some(a)) => match NetworkName::from_str(n) {
+                Ok(n) => (n, a),
+                Err(_) => return JsonResult::Err(jsonerr(InvalidNetworkParam, None, id)),
+            },
+            (None, _) => return JsonResult::Err(jsonerr(InvalidNetworkParam, None, id)),
+            (_, None) => return JsonResult::Err(jsonerr(InvalidAddressParam, None, id)),
+        };
+
+        if !self.networks.iter().any(|net| net.name == network) {
+            return JsonResult::Err(jsonerr(
+                InvalidParams,
+                Some(format!("Cashier doesn't support this network: {}", network)),
+                id,
+            ))
+        }
+
+        let message = register_withdraw_message(&network, address);
+        let user = match signed_by(&args[2], &args[3], &message) {
+            Some(v) => v,
+            None => return JsonResult::Err(jsonerr(Unauthorized, None, id)),
+        };
+
+        let address = serialize(&address.to_string());
+
+        match self.cashier_wallet.put_whitelisted_address(&user, &address, &network).await {
+            Ok(registered_at) => {
+                let active_at = self.policy.active_at(registered_at);
+                info!(
+                    target: "CASHIER DAEMON",
+                    "Registered {} withdraw address, usable from {}", network, active_at
+                );
+                JsonResult::Resp(jsonresp(
+                    json!({"registered_at": registered_at.0, "active_at": active_at.0}),
+                    json!(id),
+                ))
+            }
+            Err(err) => JsonResult::Err(jsonerr(InternalError, Some(err.to_string()), json!(id))),
+        }
+    }
+
+    // RPCAPI:
+    // Estimates the fee of withdrawing `amount` of `token` on `network`.
+    // The fee is paid in the network's native token, and is returned in its
+    // base units along with the number of decimals.
+    // --> {"jsonrpc": "2.0", "method": "estimated_fee", "params": ["network", "token", "amount"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"fee": 5000, "decimals": 9}, "id": 1}
+    async fn estimated_fee(&self, id: Value, params: Value) -> JsonResult {
+        let args: &Vec<serde_json::Value> = params.as_array().unwrap();
+
+        if args.len() != 3 {
+            return JsonResult::Err(jsonerr(InvalidParams, None, id))
+        }
+
+        let (network, token, amount) = match (args[0].as_str(), args[1].as_str(), args[2].as_f64())
+        {
+            (Some(n), Some(t), Some(a)) => match NetworkName::from_str(n) {
+                Ok(n) => (n, t, a),
+                Err(_) => return JsonResult::Err(jsonerr(InvalidNetworkParam, None, id)),
+            },
+            (None, _, _) => return JsonResult::Err(jsonerr(InvalidNetworkParam, None, id)),
+            (_, None, _) => return JsonResult::Err(jsonerr(InvalidTokenIdParam, None, id)),
+            (_, _, None) => return JsonResult::Err(jsonerr(InvalidParams, None, id)),
+        };
+
+        let result: Result<bridge::FeeEstimate> = async {
+            let amount: u64 = match decode_base10(&amount.to_string(), 8, true)?.try_into() {
+                Ok(a) => a,
+                Err(_) => return Err(Error::CashierError("Amount out of range".into())),
+            };
+            self.bridge.estimated_fee(&network, token, amount).await
+        }
+        .await;
+
+        match result {
+            Ok(f) => JsonResult::Resp(jsonresp(json!({"fee": f.fee, "decimals": f.decimals}), id)),
+            Err(err) => JsonResult::Err(jsonerr(InternalError, Some(err.to_string()), id)),
+        }
+    }
+
     // RPCAPI:
     // Returns supported cashier features, like network, listening ports, etc.
     // --> {"jsonrpc": "2.0", "method": "features", "params": [], "id": 1}
@@ -659,6 +1081,452 @@ impl Cashierd {
 
         JsonResult::Resp(jsonresp(resp, id))
     }
+
+    // RPCAPI:
+    // Returns the latest proof of reserves: what the main wallets hold of
+    // every bridged token, and the supply minted for it and not yet
+    // redeemed, both with 8 decimals. `token` is empty for a network's
+    // native token. `partial` tokens were bridged before their supply was
+    // tracked, so their supply isn't the real one and they're never reported
+    // as backed. The result without `signature` is signed with the
+    // cashier's darkfi key, over "DarkFi_CashierReserves" followed by its
+    // canonical JSON (RFC 8785, with integers written in full). Returns
+    // `null` until the first one is made.
+    // --> {"jsonrpc": "2.0", "method": "reserves", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"timestamp": 1656000000, "entries": [{"network": "solana", "token": "", "token_id": "Ay1...", "address": "Ht5G...", "reserves": 1500000000, "supply": 1200000000, "partial": false, "backed": true}, ...], "backed": true, "public_key": "1DarkFi...", "signature": "3Xb..."}, "id": 1}
+    async fn reserves(&self, id: Value, _params: Value) -> JsonResult {
+        let attestation = self.reserves.latest().await;
+        JsonResult::Resp(jsonresp(attestation.map_or(Value::Null, |a| a.to_json()), id))
+    }
+
+    // RPCAPI:
+    // Returns the status of the cashier's supervised tasks, like the deposit
+    // subscriptions, ordered by name.
+    // --> {"jsonrpc": "2.0", "method": "tasks", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"name": "btc deposit 1A1z...", "policy": "never", "state": "running", "restarts": 0, "failures": 0, "last_error": null}, ...], "id": 1}
+    async fn tasks(&self, id: Value, _params: Value) -> JsonResult {
+        JsonResult::Resp(jsonresp(json!(self.supervisor.status().await), id))
+    }
+
+    // RPCAPI:
+    // Lists the deposit addresses being watched for a deposit, oldest first.
+    // `token` is the mint address of the expected token, or the network's
+    // native token ID. `age` and `expires_in` are in seconds.
+    // Operator only, needs the `operator_auth_token` in the `auth` member.
+    // --> {"jsonrpc": "2.0", "method": "list_subscriptions", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"network": "solana", "address": "Ht5G...", "token": "So11...", "drk_address": "1DarkFi...", "state": "pending", "age": 120, "expires_in": 480}, ...], "id": 1}
+    async fn list_subscriptions(&self, id: Value, _params: Value) -> JsonResult {
+        let now = Timestamp::current_time().0;
+
+        let subs: Vec<Value> = self
+            .deposits
+            .list()
+            .await
+            .iter()
+            .map(|sub| {
+                json!({
+                    "network": sub.network.to_string().to_lowercase(),
+                    "address": sub.address,
+                    "token": sub.token,
+                    "drk_address": Address::from(sub.drk_pub_key).to_string(),
+                    "state": sub.state.as_str(),
+                    "age": now - sub.started.0,
+                    "expires_in": (sub.expires.0 - now).max(0),
+                })
+            })
+            .collect();
+
+        JsonResult::Resp(jsonresp(json!(subs), id))
+    }
+
+    // RPCAPI:
+    // Returns the events of the deposits for the given darkfi address that
+    // came after the event numbered `seq`, oldest first. When there are
+    // none yet, waits up to 20 seconds for one, and returns an empty list
+    // if none came in. Passing the `seq` of the last event received in the
+    // next call follows the deposits as they go through the states
+    // "pending", "received", "forwarded", and "expired" or "failed" with a
+    // `reason`. Events are kept for a while only, so `seq` can jump.
+    // The user signs the cashier's `public_key` from `features`, the address,
+    // `seq` and the current UNIX timestamp with the key of the address, see
+    // `policy::deposit_events_message`. Timestamps more than 5 minutes away
+    // from the cashier's clock are refused.
+    // --> {"jsonrpc": "2.0", "method": "deposit_events", "params": ["1DarkFi...", 0, 1656000000, "sig"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"seq": 7, "network": "ethereum", "address": "0x5a3e...", "state": "expired", "reason": null, "timestamp": 1656000000}, ...], "id": 1}
+    async fn deposit_events(&self, id: Value, params: Value) -> JsonResult {
+        let args = params.as_array().unwrap();
+
+        if args.len() != 4 {
+            return JsonResult::Err(jsonerr(InvalidParams, None, id))
+        }
+
+        let (after, timestamp) = match (args[1].as_u64(), args[2].as_i64()) {
+            (Some(a), Some(t)) => (a, t),
+            _ => return JsonResult::Err(jsonerr(InvalidParams, None, id)),
+        };
+
+        if !check_signed_timestamp(timestamp, Timestamp::current_time()) {
+            return JsonResult::Err(jsonerr(Unauthorized, None, id))
+        }
+
+        let cashier = self.public_key.to_string();
+        let address = args[0].as_str().unwrap_or_default();
+        let message = deposit_events_message(&cashier, address, after, timestamp);
+        let drk_pub_key = match signed_by(&args[0], &args[3], &message) {
+            Some(v) => v,
+            None => return JsonResult::Err(jsonerr(Unauthorized, None, id)),
+        };
+
+        let events: Vec<Value> = self
+            .deposits
+            .wait_events(&drk_pub_key, after, DEPOSIT_EVENTS_WAIT)
+            .await
+            .iter()
+            .map(|e| {
+                json!({
+                    "seq": e.seq,
+                    "network": e.network.to_string().to_lowercase(),
+                    "address": e.address,
+                    "state": e.state.as_str(),
+                    "reason": e.reason,
+                    "timestamp": e.timestamp.0,
+                })
+            })
+            .collect();
+
+        JsonResult::Resp(jsonresp(json!(events), id))
+    }
+
+    // RPCAPI:
+    // Cancels the subscription watching the given deposit address. Deposits
+    // made to it afterwards won't be minted.
+    // Operator only, needs the `operator_auth_token` in the `auth` member.
+    // Returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "cancel_subscription", "params": ["Ht5G..."], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn cancel_subscription(&self, id: Value, params: Value) -> JsonResult {
+        let args = params.as_array().unwrap();
+
+        let address = match args.get(0).and_then(|a| a.as_str()) {
+            Some(v) if args.len() == 1 => v,
+            _ => return JsonResult::Err(jsonerr(InvalidParams, None, id)),
+        };
+
+        if !self.deposits.cancel(address).await {
+            return JsonResult::Err(jsonerr(
+                InvalidAddressParam,
+                Some(format!("No subscription for {}", address)),
+                id,
+            ))
+        }
+
+        info!(target: "CASHIER DAEMON", "Cancelled deposit subscription for {}", address);
+        JsonResult::Resp(jsonresp(json!(true), id))
+    }
+
+    // RPCAPI:
+    // Extends the timeout of the subscription watching the given deposit
+    // address by the given number of seconds, counted from now if it
+    // already expired. Returns the new expiry as a UNIX timestamp.
+    // Operator only, needs the `operator_auth_token` in the `auth` member.
+    // --> {"jsonrpc": "2.0", "method": "extend_subscription", "params": ["Ht5G...", 600], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": 1656001200, "id": 1}
+    async fn extend_subscription(&self, id: Value, params: Value) -> JsonResult {
+        let args = params.as_array().unwrap();
+
+        let (address, secs) = match (args.get(0).and_then(|a| a.as_str()), args.get(1)) {
+            (Some(a), Some(s)) if args.len() == 2 && s.is_u64() => (a, s.as_u64().unwrap()),
+            _ => return JsonResult::Err(jsonerr(InvalidParams, None, id)),
+        };
+
+        let secs = secs.min(i64::MAX as u64) as i64;
+        let expires = match self.deposits.extend(address, secs).await {
+            Some(v) => v,
+            None => {
+                return JsonResult::Err(jsonerr(
+                    InvalidAddressParam,
+                    Some(format!("No subscription for {}", address)),
+                    id,
+                ))
+            }
+        };
+
+        info!(target: "CASHIER DAEMON", "Extended deposit subscription for {} by {}s", address, secs);
+        JsonResult::Resp(jsonresp(json!(expires.0), id))
+    }
+
+    // RPCAPI:
+    // Lists the deposits received, oldest first, or only the ones in the
+    // given status: "minted", "failed", "refunding" or "refunded". Amounts
+    // have 8 decimals. `sender` is where the deposit came from, if known,
+    // and `reason` why minting or the last refund failed.
+    // Operator only, needs the `operator_auth_token` in the `auth` member.
+    // --> {"jsonrpc": "2.0", "method": "list_deposits", "params": ["failed"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"id": 3, "network": "solana", "token_id": "Ay1...", "drk_address": "1DarkFi...", "amount": 150000000, "sender": "Ht5G...", "status": "failed", "reason": "Deposit memo doesn't match", "refund_amount": null, "refund_tx": null, "received_at": 1656000000, "updated_at": 1656000000}, ...], "id": 1}
+    async fn list_deposits(&self, id: Value, params: Value) -> JsonResult {
+        let args = params.as_array().unwrap();
+
+        let status = match args.get(0) {
+            None => None,
+            Some(Value::String(s)) if args.len() == 1 => match DepositStatus::from_str(s) {
+                Ok(v) => Some(v),
+                Err(e) => return JsonResult::Err(jsonerr(InvalidParams, Some(e.to_string()), id)),
+            },
+            _ => return JsonResult::Err(jsonerr(InvalidParams, None, id)),
+        };
+
+        let deposits = match self.cashier_wallet.get_deposits(status).await {
+            Ok(v) => v,
+            Err(e) => return JsonResult::Err(jsonerr(InternalError, Some(e.to_string()), id)),
+        };
+
+        let deposits: Vec<Value> = deposits
+            .iter()
+            .map(|d| {
+                json!({
+                    "id": d.id,
+                    "network": d.network.to_string().to_lowercase(),
+                    "token_id": bs58::encode(serialize(&d.token_id)).into_string(),
+                    "drk_address": Address::from(d.drk_public_key).to_string(),
+                    "amount": d.amount,
+                    "sender": d.sender,
+                    "status": d.status.as_str(),
+                    "reason": d.reason,
+                    "refund_amount": d.refund_amount,
+                    "refund_tx": d.refund_tx,
+                    "received_at": d.received_at.0,
+                    "updated_at": d.updated_at.0,
+                })
+            })
+            .collect();
+
+        JsonResult::Resp(jsonresp(json!(deposits), id))
+    }
+
+    // RPCAPI:
+    // Approves the refund of a deposit that couldn't be minted, sending it
+    // from the main wallet back to the address it came from. The amount,
+    // with 8 decimals, defaults to the whole deposit and can be lowered to
+    // leave room for the network fee. Refunds that fail to send put the
+    // deposit back in the failed state, so they can be tried again.
+    // Operator only, needs the `operator_auth_token` in the `auth` member.
+    // Returns the ID of the refund transaction.
+    // --> {"jsonrpc": "2.0", "method": "refund_deposit", "params": [3, 149000000], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"tx": "5VER..."}, "id": 1}
+    async fn refund_deposit(
+        &self,
+        id: Value,
+        params: Value,
+        executor: Arc<Executor<'_>>,
+    ) -> JsonResult {
+        let args = params.as_array().unwrap();
+
+        let (deposit_id, amount) = match (args.get(0).and_then(|a| a.as_u64()), args.get(1)) {
+            (Some(d), None) if args.len() == 1 => (d, None),
+            (Some(d), Some(a)) if args.len() == 2 && a.is_u64() => (d, a.as_u64()),
+            _ => return JsonResult::Err(jsonerr(InvalidParams, None, id)),
+        };
+
+        let deposit = match self.cashier_wallet.get_deposit(deposit_id).await {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                return JsonResult::Err(jsonerr(
+                    InvalidParams,
+                    Some(format!("No deposit with ID {}", deposit_id)),
+                    id,
+                ))
+            }
+            Err(e) => return JsonResult::Err(jsonerr(InternalError, Some(e.to_string()), id)),
+        };
+
+        if deposit.status != DepositStatus::Failed {
+            return JsonResult::Err(jsonerr(
+                InvalidParams,
+                Some(format!("Deposit {} is {}", deposit_id, deposit.status.as_str())),
+                id,
+            ))
+        }
+
+        let amount = amount.unwrap_or(deposit.amount);
+        if amount == 0 || amount > deposit.amount {
+            return JsonResult::Err(jsonerr(
+                InvalidParams,
+                Some(format!("Refund amount must be between 1 and {}", deposit.amount)),
+                id,
+            ))
+        }
+
+        let sender = match deposit.sender {
+            Some(v) => v,
+            None => {
+                return JsonResult::Err(jsonerr(
+                    InvalidAddressParam,
+                    Some(format!("Deposit {} has no known sender", deposit_id)),
+                    id,
+                ))
+            }
+        };
+
+        // The token is sent the way withdrawals are, so look up its mint
+        let supplies = match self.cashier_wallet.get_token_supplies().await {
+            Ok(v) => v,
+            Err(e) => return JsonResult::Err(jsonerr(InternalError, Some(e.to_string()), id)),
+        };
+        let mint_address = match supplies
+            .into_iter()
+            .find(|s| s.network == deposit.network && s.token_id == deposit.token_id)
+        {
+            Some(v) => v.mint_address,
+            None => {
+                return JsonResult::Err(jsonerr(
+                    InternalError,
+                    Some(format!("Unknown token for deposit {}", deposit_id)),
+                    id,
+                ))
+            }
+        };
+
+        // Move the deposit out of the failed state first, so it can't be
+        // refunded twice
+        if let Err(e) = self.cashier_wallet.start_refund(deposit_id, amount).await {
+            return JsonResult::Err(jsonerr(InvalidParams, Some(e.to_string()), id))
+        }
+
+        info!(
+            target: "CASHIER DAEMON",
+            "Refunding {} of deposit {} to {}", amount, deposit_id, sender
+        );
+
+        let result = self
+            .send_refund(
+                &deposit.network,
+                deposit.drk_public_key,
+                mint_address,
+                &sender,
+                amount,
+                executor,
+            )
+            .await;
+
+        match result {
+            Ok(tx_id) => {
+                info!(
+                    target: "CASHIER DAEMON",
+                    "Refund of deposit {} sent, tx: {}", deposit_id, tx_id
+                );
+                if let Err(e) = self.cashier_wallet.confirm_refund(deposit_id, &tx_id).await {
+                    error!(target: "CASHIER DAEMON", "Failed recording refund: {}", e);
+                }
+                JsonResult::Resp(jsonresp(json!({ "tx": tx_id }), id))
+            }
+            Err(e) => {
+                error!(
+                    target: "CASHIER DAEMON",
+                    "Refund of deposit {} failed: {}", deposit_id, e
+                );
+                if let Err(e) = self.cashier_wallet.abort_refund(deposit_id, &e.to_string()).await {
+                    error!(target: "CASHIER DAEMON", "Failed recording refund: {}", e);
+                }
+                JsonResult::Err(jsonerr(InternalError, Some(e.to_string()), id))
+            }
+        }
+    }
+
+    // RPCAPI:
+    // Resolves the refund of a deposit that was cut short by a restart,
+    // leaving it in the refunding state. Given the ID of the transaction the
+    // refund was sent in, the deposit is marked refunded. Without one, the
+    // refund is taken as never sent and the deposit goes back to the failed
+    // state, so it can be refunded again. Check the network for the refund
+    // first, or the deposit may be refunded twice.
+    // Operator only, needs the `operator_auth_token` in the `auth` member.
+    // --> {"jsonrpc": "2.0", "method": "resolve_refund", "params": [3, "5VER..."], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn resolve_refund(&self, id: Value, params: Value) -> JsonResult {
+        let args = params.as_array().unwrap();
+
+        let (deposit_id, tx_id) = match (args.get(0).and_then(|a| a.as_u64()), args.get(1)) {
+            (Some(d), None) if args.len() == 1 => (d, None),
+            (Some(d), Some(Value::String(t))) if args.len() == 2 && !t.is_empty() => {
+                (d, Some(t.as_str()))
+            }
+            _ => return JsonResult::Err(jsonerr(InvalidParams, None, id)),
+        };
+
+        let mut stale_refunds = self.stale_refunds.lock().await;
+        if !stale_refunds.contains(&deposit_id) {
+            return JsonResult::Err(jsonerr(
+                InvalidParams,
+                Some(format!("Deposit {} has no interrupted refund", deposit_id)),
+                id,
+            ))
+        }
+
+        let result = match tx_id {
+            Some(tx_id) => self.cashier_wallet.confirm_refund(deposit_id, tx_id).await,
+            None => self.cashier_wallet.abort_refund(deposit_id, "Refund interrupted").await,
+        };
+
+        if let Err(e) = result {
+            return JsonResult::Err(jsonerr(InternalError, Some(e.to_string()), id))
+        }
+
+        stale_refunds.remove(&deposit_id);
+        info!(target: "CASHIER DAEMON", "Resolved interrupted refund of deposit {}", deposit_id);
+        JsonResult::Resp(jsonresp(json!(true), id))
+    }
+
+    /// Send `amount` of a token from the network's main wallet to `address`
+    /// through the bridge, returning the transaction ID.
+    async fn send_refund(
+        &self,
+        network: &NetworkName,
+        drk_pub_key: PublicKey,
+        mint_address: String,
+        address: &str,
+        amount: u64,
+        executor: Arc<Executor<'_>>,
+    ) -> Result<String> {
+        let bridge_subscribtion =
+            self.bridge.subscribe(drk_pub_key, Some(mint_address), executor).await;
+
+        bridge_subscribtion
+            .sender
+            .send(bridge::BridgeRequests {
+                network: network.clone(),
+                payload: bridge::BridgeRequestsPayload::Send(
+                    serialize(&address.to_string()),
+                    amount,
+                ),
+            })
+            .await?;
+
+        let res = bridge_subscribtion.receiver.recv().await?;
+
+        let error_code = res.error as u32;
+        if error_code != 0 {
+            if let bridge::BridgeResponsePayload::Error(reason) = res.payload {
+                return Err(Error::CashierError(reason))
+            }
+            handle_bridge_error(error_code)?;
+        }
+
+        match res.payload {
+            bridge::BridgeResponsePayload::Send(tx_id) => Ok(tx_id),
+            _ => Err(Error::CashierError("Receive unknown value from Subscription".into())),
+        }
+    }
+}
+
+/// Whether minting failed in a way trying again won't fix, rather than on
+/// the node or the wallet being unavailable for now
+fn is_permanent(err: &ClientFailed) -> bool {
+    matches!(
+        err,
+        ClientFailed::InvalidAddress(_)
+            | ClientFailed::InvalidAmount(_)
+            | ClientFailed::VerifyError(_)
+    )
 }
 
 async fn start(
@@ -730,6 +1598,75 @@ async fn start(
     Ok(())
 }
 
+/// Check what cashierd needs to start, without starting it or writing to
+/// its wallets and database, and print the report.
+async fn self_check(config: Result<CashierdConfig>, check_upstreams: bool) -> Result<()> {
+    let mut report = CheckReport::new();
+
+    let config = match config {
+        Ok(v) => {
+            report.add("config", Ok(format!("{} networks", v.networks.len())));
+            v
+        }
+        Err(e) => {
+            report.add("config", Err(e));
+            return report.finish()
+        }
+    };
+
+    let pid_path = expand_path(&config.database_path)?.with_extension("pid");
+    let running = running_pid(&pid_path)?.is_some();
+
+    for network in &config.networks {
+        let name = NetworkName::from_str(&network.name);
+        report.add("network", name.map(|n| format!("{} on {}", n, network.blockchain)));
+    }
+
+    let mut gateway = vec![];
+    for (name, url) in [
+        ("gateway_protocol_url", &config.gateway_protocol_url),
+        ("gateway_publisher_url", &config.gateway_publisher_url),
+    ] {
+        match Url::parse(url) {
+            Ok(v) => {
+                report.add(name, Ok(url.clone()));
+                gateway.push((name, v));
+            }
+            Err(e) => report.fail(name, format!("{}: {}", url, e)),
+        }
+    }
+
+    let client_wallet_path = expand_path(&config.client_wallet_path)?;
+    report.check_wallet("client_wallet", &client_wallet_path, &config.client_wallet_password).await;
+    let cashier_wallet_path = expand_path(&config.cashier_wallet_path)?;
+    report
+        .check_wallet("cashier_wallet", &cashier_wallet_path, &config.cashier_wallet_password)
+        .await;
+
+    let database_path = expand_path(&config.database_path)?;
+    if !report.skip_locked("database", &database_path) {
+        if database_path.exists() {
+            report.check_path("database", &database_path);
+        } else {
+            report.add("database", Ok(format!("Not created yet: {}", database_path.display())));
+        }
+    }
+
+    if config.serve_tls {
+        report.check_path("tls_identity_path", &expand_path(&config.tls_identity_path)?);
+    }
+
+    let rpc_listen = Url::parse(&format!("tcp://{}", config.rpc_listen_address))?;
+    report.check_listen("rpc_listen_address", &rpc_listen, running);
+
+    if check_upstreams {
+        let gateway: Vec<(&str, &Url)> = gateway.iter().map(|(n, u)| (*n, u)).collect();
+        report.check_upstreams(&gateway, None);
+    }
+
+    report.finish()
+}
+
 #[async_std::main]
 async fn main() -> Result<()> {
     let args = CliCashierd::parse();
@@ -750,7 +1687,28 @@ async fn main() -> Result<()> {
 
     TermLogger::init(lvl, conf, TerminalMode::Mixed, ColorChoice::Auto)?;
 
-    let config: CashierdConfig = Config::<CashierdConfig>::load(config_path)?;
+    let config = Config::<CashierdConfig>::load(config_path);
+    if args.check {
+        return self_check(config, args.check_upstreams).await
+    }
+    let config: CashierdConfig = config?;
+
+    // Locked while an instance runs on the database
+    let pid_path = expand_path(&config.database_path)?.with_extension("pid");
+    if let Some(command) = args.service {
+        return run_command(command, &pid_path)
+    }
+
+    // Nothing else may touch the database while it's refreshed either. Only
+    // the main thread runs so far, so it's safe to fork.
+    let daemon = args.daemon && !args.refresh && !args.address;
+    let _pid_file = start_service(&pid_path, daemon)?;
+
+    // Taken after forking, so the locks record the PID that holds them
+    let mut db_locks = vec![];
+    for path in [&config.client_wallet_path, &config.cashier_wallet_path, &config.database_path] {
+        db_locks.push(DbLock::acquire(&expand_path(path)?, args.force_unlock)?);
+    }
 
     if args.refresh {
         info!(target: "CASHIER DAEMON", "Refresh the wallet and the database");
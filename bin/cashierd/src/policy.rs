@@ -0,0 +1,195 @@
+use darkfi::{
+    crypto::{
+        keypair::PublicKey,
+        schnorr::{SchnorrPublic, Signature},
+    },
+    util::{
+        serial::{deserialize, serialize},
+        NetworkName, Timestamp,
+    },
+};
+
+/// Shortest whitelist delay, so a newly registered address is never usable
+/// right away
+pub const MIN_WHITELIST_DELAY: u64 = 3600;
+
+/// Domain of the signatures registering a withdraw address
+pub const REGISTER_WITHDRAW_DOMAIN: &[u8] = b"DarkFi cashier withdraw address";
+/// Domain of the signatures requesting a withdrawal
+pub const WITHDRAW_DOMAIN: &[u8] = b"DarkFi cashier withdraw";
+/// Domain of the signatures reading the deposit events of a user
+pub const DEPOSIT_EVENTS_DOMAIN: &[u8] = b"DarkFi cashier deposit events";
+
+/// Seconds a signed timestamp is accepted for, either way from ours, so
+/// a request that was seen can't be replayed for long
+pub const SIGNED_TIMESTAMP_WINDOW: i64 = 300;
+
+/// Reasons for the cashier to refuse a withdrawal
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PolicyViolation {
+    #[error("Withdraw address is not whitelisted")]
+    NotWhitelisted,
+    #[error("Withdraw address can't be used before {0}")]
+    NotYetActive(Timestamp),
+}
+
+/// Rules a withdrawal has to satisfy before the cashier accepts it
+#[derive(Debug, Clone, Default)]
+pub struct WithdrawPolicy {
+    /// Seconds between registering a withdraw address and being able to
+    /// use it, at least [`MIN_WHITELIST_DELAY`]. `None` disables the
+    /// whitelist.
+    pub whitelist_delay: Option<u64>,
+}
+
+impl WithdrawPolicy {
+    pub fn new(whitelist_delay: Option<u64>) -> Self {
+        Self { whitelist_delay: whitelist_delay.map(|d| d.max(MIN_WHITELIST_DELAY)) }
+    }
+
+    /// Time from which an address registered at `registered_at` is usable.
+    pub fn active_at(&self, registered_at: Timestamp) -> Timestamp {
+        let delay = self.whitelist_delay.unwrap_or(0).min(i64::MAX as u64) as i64;
+        Timestamp(registered_at.0.saturating_add(delay))
+    }
+
+    /// Check that a withdraw address registered at `registered_at` can
+    /// be used at `now`.
+    pub fn check_address(
+        &self,
+        registered_at: Option<Timestamp>,
+        now: Timestamp,
+    ) -> Result<(), PolicyViolation> {
+        if self.whitelist_delay.is_none() {
+            return Ok(())
+        }
+
+        let registered_at = match registered_at {
+            Some(v) => v,
+            None => return Err(PolicyViolation::NotWhitelisted),
+        };
+
+        let active_at = self.active_at(registered_at);
+        if now < active_at {
+            return Err(PolicyViolation::NotYetActive(active_at))
+        }
+
+        Ok(())
+    }
+}
+
+/// Message a user signs with their DarkFi key to add `address` on
+/// `network` to their withdraw whitelist
+pub fn register_withdraw_message(network: &NetworkName, address: &str) -> Vec<u8> {
+    let mut message = REGISTER_WITHDRAW_DOMAIN.to_vec();
+    message.extend(serialize(network));
+    message.extend(serialize(&address.to_string()));
+    message
+}
+
+/// Message a user signs with their DarkFi key to withdraw `amount` of
+/// `token` to `address` on `network`
+pub fn withdraw_message(
+    network: &NetworkName,
+    token: &str,
+    address: &str,
+    amount: &str,
+) -> Vec<u8> {
+    let mut message = WITHDRAW_DOMAIN.to_vec();
+    message.extend(serialize(network));
+    message.extend(serialize(&token.to_string()));
+    message.extend(serialize(&address.to_string()));
+    message.extend(serialize(&amount.to_string()));
+    message
+}
+
+/// Message a user signs at `timestamp` with the key of `address` to read
+/// the events of its deposits after `after` from the cashier whose
+/// DarkFi address is `cashier`
+pub fn deposit_events_message(cashier: &str, address: &str, after: u64, timestamp: i64) -> Vec<u8> {
+    let mut message = DEPOSIT_EVENTS_DOMAIN.to_vec();
+    message.extend(serialize(&cashier.to_string()));
+    message.extend(serialize(&address.to_string()));
+    message.extend(serialize(&after));
+    message.extend(serialize(&timestamp));
+    message
+}
+
+/// Whether a signed `timestamp` is close enough to `now` to be accepted
+pub fn check_signed_timestamp(timestamp: i64, now: Timestamp) -> bool {
+    (timestamp - now.0).abs() <= SIGNED_TIMESTAMP_WINDOW
+}
+
+/// Check a base58 encoded signature of `message` by `public`
+pub fn verify_signature(public: &PublicKey, message: &[u8], signature: &str) -> bool {
+    let signature =
+        bs58::decode(signature).into_vec().ok().and_then(|s| deserialize::<Signature>(&s).ok());
+
+    match signature {
+        Some(v) => public.verify(message, &v),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use darkfi::crypto::{keypair::Keypair, schnorr::SchnorrSecret};
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_withdraw_whitelist() {
+        let disabled = WithdrawPolicy::new(None);
+        assert_eq!(disabled.check_address(None, Timestamp(0)), Ok(()));
+
+        let policy = WithdrawPolicy::new(Some(3600));
+        assert_eq!(policy.check_address(None, Timestamp(0)), Err(PolicyViolation::NotWhitelisted));
+        assert_eq!(
+            policy.check_address(Some(Timestamp(100)), Timestamp(3699)),
+            Err(PolicyViolation::NotYetActive(Timestamp(3700)))
+        );
+        assert_eq!(policy.check_address(Some(Timestamp(100)), Timestamp(3700)), Ok(()));
+
+        // New addresses are never usable right away
+        let policy = WithdrawPolicy::new(Some(0));
+        assert_eq!(policy.active_at(Timestamp(100)), Timestamp(100 + MIN_WHITELIST_DELAY as i64));
+    }
+
+    #[test]
+    fn test_withdraw_signatures() {
+        let keypair = Keypair::random(&mut OsRng);
+        let message = register_withdraw_message(&NetworkName::Solana, "Ht5G");
+        let signature = bs58::encode(serialize(&keypair.secret.sign(&message))).into_string();
+        assert!(verify_signature(&keypair.public, &message, &signature));
+
+        // Signatures are bound to the address, the action and the signer
+        let other = register_withdraw_message(&NetworkName::Solana, "Ht5H");
+        assert!(!verify_signature(&keypair.public, &other, &signature));
+        let other = withdraw_message(&NetworkName::Solana, "So11", "Ht5G", "1");
+        assert!(!verify_signature(&keypair.public, &other, &signature));
+        let other = deposit_events_message("1Cash", "1User", 0, 1656000000);
+        assert!(!verify_signature(&keypair.public, &other, &signature));
+        let stranger = Keypair::random(&mut OsRng);
+        assert!(!verify_signature(&stranger.public, &message, &signature));
+        assert!(!verify_signature(&keypair.public, &message, "not a signature"));
+
+        // Reading deposit events is bound to the cashier and the request
+        let message = deposit_events_message("1Cash", "1User", 7, 1656000000);
+        let signature = bs58::encode(serialize(&keypair.secret.sign(&message))).into_string();
+        assert!(verify_signature(&keypair.public, &message, &signature));
+        let other = deposit_events_message("1Else", "1User", 7, 1656000000);
+        assert!(!verify_signature(&keypair.public, &other, &signature));
+        let other = deposit_events_message("1Cash", "1User", 0, 1656000000);
+        assert!(!verify_signature(&keypair.public, &other, &signature));
+    }
+
+    #[test]
+    fn test_signed_timestamps() {
+        let now = Timestamp(1656000000);
+        assert!(check_signed_timestamp(now.0, now));
+        assert!(check_signed_timestamp(now.0 - SIGNED_TIMESTAMP_WINDOW, now));
+        assert!(check_signed_timestamp(now.0 + SIGNED_TIMESTAMP_WINDOW, now));
+        assert!(!check_signed_timestamp(now.0 - SIGNED_TIMESTAMP_WINDOW - 1, now));
+        assert!(!check_signed_timestamp(now.0 + SIGNED_TIMESTAMP_WINDOW + 1, now));
+    }
+}
@@ -0,0 +1,287 @@
+//! Proof of reserves of the bridged assets. The cashier regularly signs
+//! what its main wallets hold of every bridged token, next to the supply
+//! of the darkfi token minted for it, so anyone can check that the minted
+//! tokens are fully backed. The signature is over the canonical JSON of
+//! the attestation as served, so it can be checked from the RPC reply.
+use async_std::sync::{Arc, Mutex};
+use log::error;
+use serde_json::{json, Value};
+
+use darkfi::{
+    crypto::{
+        address::Address,
+        keypair::{PublicKey, SecretKey},
+        schnorr::{SchnorrPublic, SchnorrSecret, Signature},
+        types::DrkTokenId,
+    },
+    util::{
+        canonical_json::canonical_json,
+        serial::{deserialize, serialize, SerialDecodable, SerialEncodable},
+        sleep, NetworkName, Timestamp,
+    },
+    wallet::cashierdb::CashierDb,
+    Result,
+};
+
+use crate::service::bridge::Bridge;
+
+/// Default time, in seconds, between two attestations
+pub const RESERVES_INTERVAL: u64 = 60 * 60;
+
+/// Prefix of the signed message, so the signature can't be passed off as
+/// one over anything else
+const ATTESTATION_DOMAIN: &[u8] = b"DarkFi_CashierReserves";
+
+/// Holdings of one bridged token
+#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct ReserveEntry {
+    pub network: NetworkName,
+    /// Mint address of the token, empty for the network's native token
+    pub token: String,
+    /// The darkfi token minted for it
+    pub token_id: DrkTokenId,
+    /// Main wallet holding the reserves
+    pub address: String,
+    /// Balance of the main wallet, with 8 decimals
+    pub reserves: u64,
+    /// Minted and not yet redeemed, with 8 decimals
+    pub supply: u64,
+    /// The supply leaves out what was minted and redeemed before it was
+    /// tracked, so it isn't the real circulating supply
+    pub partial: bool,
+}
+
+impl ReserveEntry {
+    /// Whether the reserves cover the supply. A partial supply doesn't
+    /// tell.
+    pub fn is_backed(&self) -> bool {
+        !self.partial && self.reserves >= self.supply
+    }
+}
+
+/// Reserves of all the bridged tokens at some time, signed by the cashier
+#[derive(Debug, Clone)]
+pub struct ReservesAttestation {
+    pub timestamp: Timestamp,
+    pub entries: Vec<ReserveEntry>,
+    /// The cashier's darkfi public key
+    pub public_key: PublicKey,
+    pub signature: Signature,
+}
+
+impl ReservesAttestation {
+    pub fn new(secret: &SecretKey, timestamp: Timestamp, entries: Vec<ReserveEntry>) -> Self {
+        let public_key = PublicKey::from_secret(*secret);
+        let body = Self::body(timestamp, &entries, &public_key);
+        let signature = secret.sign(&Self::message(&body));
+        Self { timestamp, entries, public_key, signature }
+    }
+
+    /// The signed message: the domain, then the canonical JSON of the
+    /// attestation without its signature
+    fn message(body: &Value) -> Vec<u8> {
+        [ATTESTATION_DOMAIN, canonical_json(body).as_bytes()].concat()
+    }
+
+    /// Check the signature of the cashier
+    pub fn verify(&self) -> bool {
+        let body = Self::body(self.timestamp, &self.entries, &self.public_key);
+        self.public_key.verify(&Self::message(&body), &self.signature)
+    }
+
+    /// Check the signature of an attestation as returned by [`Self::to_json`],
+    /// whatever the key order and formatting it was passed on with
+    pub fn verify_json(attestation: &Value) -> bool {
+        let mut body = attestation.clone();
+        let signature = match body.as_object_mut().and_then(|b| b.remove("signature")) {
+            Some(v) => v,
+            None => return false,
+        };
+
+        let signature = signature
+            .as_str()
+            .and_then(|s| bs58::decode(s).into_vec().ok())
+            .and_then(|s| deserialize::<Signature>(&s).ok());
+        let public_key = body["public_key"]
+            .as_str()
+            .and_then(|a| a.parse::<Address>().ok())
+            .and_then(|a| PublicKey::try_from(a).ok());
+
+        match (public_key, signature) {
+            (Some(public_key), Some(signature)) => {
+                public_key.verify(&Self::message(&body), &signature)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether every bridged token is fully backed
+    pub fn is_backed(&self) -> bool {
+        self.entries.iter().all(|e| e.is_backed())
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut json = Self::body(self.timestamp, &self.entries, &self.public_key);
+        json["signature"] = json!(bs58::encode(serialize(&self.signature)).into_string());
+        json
+    }
+
+    fn body(timestamp: Timestamp, entries: &[ReserveEntry], public_key: &PublicKey) -> Value {
+        let backed = entries.iter().all(|e| e.is_backed());
+        let entries: Vec<Value> = entries
+            .iter()
+            .map(|e| {
+                json!({
+                    "network": e.network.to_string().to_lowercase(),
+                    "token": e.token,
+                    "token_id": bs58::encode(serialize(&e.token_id)).into_string(),
+                    "address": e.address,
+                    "reserves": e.reserves,
+                    "supply": e.supply,
+                    "partial": e.partial,
+                    "backed": e.is_backed(),
+                })
+            })
+            .collect();
+
+        json!({
+            "timestamp": timestamp.0,
+            "entries": entries,
+            "backed": backed,
+            "public_key": Address::from(*public_key).to_string(),
+        })
+    }
+}
+
+/// Gather the reserves and supply of every token the cashier bridged, and
+/// sign them
+pub async fn attest_reserves(
+    bridge: &Bridge,
+    cashier_wallet: &CashierDb,
+    secret: &SecretKey,
+) -> Result<ReservesAttestation> {
+    let mut entries = vec![];
+
+    for supply in cashier_wallet.get_token_supplies().await? {
+        let balance = bridge.main_wallet_balance(&supply.network, &supply.mint_address).await?;
+
+        entries.push(ReserveEntry {
+            network: supply.network.clone(),
+            token: supply.mint_address.clone(),
+            token_id: supply.token_id,
+            address: balance.address,
+            reserves: balance.balance,
+            supply: supply.circulating(),
+            partial: supply.partial,
+        });
+    }
+
+    Ok(ReservesAttestation::new(secret, Timestamp::current_time(), entries))
+}
+
+pub type ReservesPtr = Arc<Reserves>;
+
+/// The latest attestation, served over RPC
+#[derive(Default)]
+pub struct Reserves {
+    latest: Mutex<Option<ReservesAttestation>>,
+}
+
+impl Reserves {
+    pub fn new() -> ReservesPtr {
+        Arc::new(Self::default())
+    }
+
+    pub async fn set(&self, attestation: ReservesAttestation) {
+        *self.latest.lock().await = Some(attestation);
+    }
+
+    pub async fn latest(&self) -> Option<ReservesAttestation> {
+        self.latest.lock().await.clone()
+    }
+
+    /// Attest the reserves every `interval` seconds
+    pub async fn run(
+        self: Arc<Self>,
+        bridge: Arc<Bridge>,
+        cashier_wallet: Arc<CashierDb>,
+        secret: SecretKey,
+        interval: u64,
+    ) -> Result<()> {
+        loop {
+            match attest_reserves(&bridge, &cashier_wallet, &secret).await {
+                Ok(attestation) => {
+                    if !attestation.is_backed() {
+                        error!(target: "RESERVES", "Bridged tokens are not fully backed");
+                    }
+                    self.set(attestation).await;
+                }
+                // Keep serving the last one, its timestamp shows it's stale
+                Err(e) => error!(target: "RESERVES", "Failed attesting reserves: {}", e),
+            }
+
+            sleep(interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn sign_and_verify() {
+        let secret = SecretKey::random(&mut OsRng);
+        let entry = ReserveEntry {
+            network: NetworkName::Solana,
+            token: String::new(),
+            token_id: DrkTokenId::from(42),
+            address: "Ht5G1RhkcKnpLVLMhqJc5aqZ4wYUEbxbtZwGCVbgU7DL".to_string(),
+            reserves: 150,
+            supply: 120,
+            partial: false,
+        };
+
+        let attestation = ReservesAttestation::new(&secret, Timestamp(1656000000), vec![entry]);
+        assert!(attestation.verify());
+        assert!(attestation.is_backed());
+
+        // Any change to the entries breaks the signature
+        let mut forged = attestation.clone();
+        forged.entries[0].reserves = 1000;
+        assert!(!forged.verify());
+
+        let mut forged = attestation.clone();
+        forged.timestamp = Timestamp(1656000060);
+        assert!(!forged.verify());
+
+        // Signed by someone else
+        let mut forged = attestation.clone();
+        forged.public_key = PublicKey::random(&mut OsRng);
+        assert!(!forged.verify());
+
+        // The signature can be checked from the JSON served over RPC
+        let json = attestation.to_json();
+        let served = serde_json::to_string_pretty(&json).unwrap();
+        assert!(ReservesAttestation::verify_json(&serde_json::from_str(&served).unwrap()));
+
+        let mut forged = json.clone();
+        forged["entries"][0]["supply"] = json!(1);
+        assert!(!ReservesAttestation::verify_json(&forged));
+        let mut unsigned = json;
+        unsigned.as_object_mut().unwrap().remove("signature");
+        assert!(!ReservesAttestation::verify_json(&unsigned));
+
+        let mut short = attestation.clone();
+        short.entries[0].supply = 151;
+        assert!(!short.entries[0].is_backed());
+
+        // A supply that isn't known in full isn't claimed to be backed
+        let mut partial = attestation;
+        partial.entries[0].partial = true;
+        assert!(!partial.entries[0].is_backed());
+        assert!(!partial.is_backed());
+        assert!(!short.is_backed());
+    }
+}
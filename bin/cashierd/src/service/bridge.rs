@@ -7,8 +7,11 @@ use fxhash::FxHashMap;
 use log::{debug, error};
 
 use darkfi::{
-    crypto::{keypair::PublicKey, types::*},
-    util::NetworkName,
+    crypto::{address::Address, keypair::PublicKey, types::*},
+    util::{
+        supervisor::{RestartPolicy, SupervisorPtr},
+        NetworkName,
+    },
     wallet::cashierdb::TokenKey,
     Error, Result,
 };
@@ -26,12 +29,14 @@ pub struct BridgeResponse {
 pub enum BridgeRequestsPayload {
     Send(Vec<u8>, u64),      // send (address, amount)
     Watch(Option<TokenKey>), // if already has a keypair
+    WatchTag(String),        // derive the keypair from a user tag
 }
 
 pub enum BridgeResponsePayload {
     Watch(TokenSubscribtion),
     Address(String),
-    Send,
+    Send(String), // transaction id/hash
+    Error(String),
     Empty,
 }
 
@@ -54,34 +59,97 @@ pub struct TokenSubscribtion {
     pub public_key: String,
 }
 
+/// Cost of a withdrawal, paid in the network's native token
+#[derive(Debug, Clone)]
+pub struct FeeEstimate {
+    pub network: NetworkName,
+    /// Fee in the native token's base units (wei, lamports, satoshi)
+    pub fee: u64,
+    /// Decimals of the native token
+    pub decimals: u16,
+}
+
+/// Holdings of a network's main wallet, where deposits are swept to and
+/// withdrawals are paid from
+#[derive(Debug, Clone)]
+pub struct MainWalletBalance {
+    /// Address of the main wallet on the network
+    pub address: String,
+    /// Balance with the same precision as minted tokens (8 decimals)
+    pub balance: u64,
+}
+
+/// Memo a depositor attaches to the external transaction, binding the
+/// deposit to the darkfi address that should be credited.
+pub fn deposit_memo(drk_pub_key: &PublicKey) -> String {
+    format!("drk:{}", Address::from(*drk_pub_key))
+}
+
+/// Destination binding read from a deposit on the external network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DepositMemo {
+    /// The network has no way to carry a memo with the deposit
+    Unsupported,
+    /// Memo found in the deposit transaction, if there was one
+    Found(Option<String>),
+}
+
+impl DepositMemo {
+    /// Check that the deposit was made for `drk_pub_key`, so a mixed-up
+    /// deposit address never gets credited to the wrong darkfi address.
+    pub fn verify(&self, drk_pub_key: &PublicKey) -> Result<()> {
+        let expected = deposit_memo(drk_pub_key);
+
+        match self {
+            DepositMemo::Unsupported => Ok(()),
+            DepositMemo::Found(Some(memo)) if memo.trim() == expected => Ok(()),
+            DepositMemo::Found(Some(memo)) => Err(Error::CashierError(format!(
+                "Deposit memo `{}` does not match `{}`",
+                memo, expected
+            ))),
+            DepositMemo::Found(None) => {
+                Err(Error::CashierError(format!("Deposit is missing the `{}` memo", expected)))
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct TokenNotification {
     pub network: NetworkName,
+    /// Deposit address the deposit arrived on
+    pub address: String,
     pub token_id: DrkTokenId,
     pub drk_pub_key: PublicKey,
     pub received_balance: u64,
     pub decimals: u16,
+    pub memo: DepositMemo,
+    /// Address the deposit was sent from, where it can be refunded if
+    /// minting fails, when the deposit transaction shows it
+    pub sender: Option<String>,
 }
 
 pub struct Bridge {
     clients: Mutex<FxHashMap<NetworkName, Arc<dyn NetworkClient + Send + Sync>>>,
     notifiers: FuturesUnordered<async_channel::Receiver<TokenNotification>>,
+    supervisor: SupervisorPtr,
 }
 
 impl Bridge {
-    pub fn new() -> Arc<Self> {
+    pub fn new(supervisor: SupervisorPtr) -> Arc<Self> {
         Arc::new(Self {
             clients: Mutex::new(FxHashMap::default()),
             notifiers: FuturesUnordered::new(),
+            supervisor,
         })
     }
 
     pub async fn add_clients(
         self: Arc<Self>,
-        network: NetworkName,
         client: Arc<dyn NetworkClient + Send + Sync>,
     ) -> Result<()> {
-        debug!(target: "BRIDGE", "Adding new client");
+        let network = client.network();
+        debug!(target: "BRIDGE", "Adding new client for {}", network);
 
         let client2 = client.clone();
         let notifier = client2.get_notifier().await?;
@@ -95,6 +163,51 @@ impl Bridge {
         Ok(())
     }
 
+    /// Resolve the mint address for a token on the given network.
+    /// Returns `None` for the network's native token.
+    pub async fn mint_address(
+        &self,
+        network: &NetworkName,
+        token_id: &str,
+    ) -> Result<Option<String>> {
+        match self.clients.lock().await.get(network) {
+            Some(client) => Ok(client.mint_address(token_id)),
+            None => Err(Error::NotSupportedNetwork),
+        }
+    }
+
+    /// Estimate what sending `amount` of the given token on `network` would
+    /// currently cost, so withdrawals can be quoted before they are built.
+    pub async fn estimated_fee(
+        &self,
+        network: &NetworkName,
+        token_id: &str,
+        amount: u64,
+    ) -> Result<FeeEstimate> {
+        let client = match self.clients.lock().await.get(network) {
+            Some(client) => client.clone(),
+            None => return Err(Error::NotSupportedNetwork),
+        };
+
+        let mint = client.mint_address(token_id);
+        client.estimated_fee(mint, amount).await
+    }
+
+    /// Fetch what the main wallet of `network` holds of the given token
+    pub async fn main_wallet_balance(
+        &self,
+        network: &NetworkName,
+        token_id: &str,
+    ) -> Result<MainWalletBalance> {
+        let client = match self.clients.lock().await.get(network) {
+            Some(client) => client.clone(),
+            None => return Err(Error::NotSupportedNetwork),
+        };
+
+        let mint = client.mint_address(token_id);
+        client.main_wallet_balance(mint).await
+    }
+
     pub async fn listen(self: Arc<Self>) -> Option<Result<TokenNotification>> {
         if !self.notifiers.is_empty() {
             debug!(target: "BRIDGE", "Start listening for new notifications");
@@ -125,9 +238,18 @@ impl Bridge {
         let (sender, req) = async_channel::unbounded();
         let (rep, receiver) = async_channel::unbounded();
 
-        executor
-            .spawn(self.listen_for_new_subscription(req, rep, drk_pub_key, mint, executor.clone()))
-            .detach();
+        let name = format!("bridge request {}", Address::from(drk_pub_key));
+        let supervisor = self.supervisor.clone();
+        let ex = executor.clone();
+        supervisor.spawn(&executor, &name, RestartPolicy::Never, move || {
+            self.clone().listen_for_new_subscription(
+                req.clone(),
+                rep.clone(),
+                drk_pub_key,
+                mint.clone(),
+                ex.clone(),
+            )
+        });
 
         BridgeSubscribtion { sender, receiver }
     }
@@ -170,64 +292,70 @@ impl Bridge {
         let res: BridgeResponse;
 
         match req.payload {
-            BridgeRequestsPayload::Watch(val) => match val {
-                Some(token_key) => {
-                    let pub_key = client
-                        .subscribe_with_keypair(
-                            token_key.secret_key,
-                            token_key.public_key,
-                            drk_pub_key,
-                            mint_address,
-                            executor,
-                        )
-                        .await;
-
-                    if pub_key.is_err() {
-                        error!(target: "BRIDGE", "{}", pub_key.unwrap_err().to_string());
-                        res = BridgeResponse {
-                            error: BridgeResponseError::BridgeWatchSubscribtionError,
-                            payload: BridgeResponsePayload::Empty,
-                        };
-                    } else {
-                        res = BridgeResponse {
-                            error: BridgeResponseError::NoError,
-                            payload: BridgeResponsePayload::Address(pub_key?),
-                        };
-                    }
-                }
-                None => {
-                    let sub = client.subscribe(drk_pub_key, mint_address, executor).await;
-                    if sub.is_err() {
-                        error!(target: "BRIDGE", "{}", sub.unwrap_err().to_string());
-                        res = BridgeResponse {
-                            error: BridgeResponseError::BridgeWatchSubscribtionError,
-                            payload: BridgeResponsePayload::Empty,
-                        };
-                    } else {
-                        let sub = sub?;
-                        res = BridgeResponse {
-                            error: BridgeResponseError::NoError,
-                            payload: BridgeResponsePayload::Watch(sub),
-                        };
-                    }
+            BridgeRequestsPayload::Watch(Some(token_key)) => {
+                let pub_key = client
+                    .subscribe_with_keypair(
+                        token_key.secret_key,
+                        token_key.public_key,
+                        drk_pub_key,
+                        mint_address,
+                        executor,
+                        self.supervisor.clone(),
+                    )
+                    .await;
+
+                if pub_key.is_err() {
+                    error!(target: "BRIDGE", "{}", pub_key.unwrap_err().to_string());
+                    res = BridgeResponse {
+                        error: BridgeResponseError::BridgeWatchSubscribtionError,
+                        payload: BridgeResponsePayload::Empty,
+                    };
+                } else {
+                    res = BridgeResponse {
+                        error: BridgeResponseError::NoError,
+                        payload: BridgeResponsePayload::Address(pub_key?),
+                    };
                 }
-            },
-            BridgeRequestsPayload::Send(addr, amount) => {
-                let result = client.send(addr, mint_address, amount).await;
-
-                if result.is_err() {
-                    error!(target: "BRIDGE", "{}", result.unwrap_err().to_string());
+            }
+            payload @ (BridgeRequestsPayload::Watch(None) | BridgeRequestsPayload::WatchTag(_)) => {
+                let tag = match payload {
+                    BridgeRequestsPayload::WatchTag(tag) => Some(tag),
+                    _ => None,
+                };
+                let sub = client
+                    .subscribe(drk_pub_key, mint_address, tag, executor, self.supervisor.clone())
+                    .await;
+                if sub.is_err() {
+                    error!(target: "BRIDGE", "{}", sub.unwrap_err().to_string());
                     res = BridgeResponse {
-                        error: BridgeResponseError::BridgeSendSubscribtionError,
+                        error: BridgeResponseError::BridgeWatchSubscribtionError,
                         payload: BridgeResponsePayload::Empty,
                     };
                 } else {
+                    let sub = sub?;
                     res = BridgeResponse {
                         error: BridgeResponseError::NoError,
-                        payload: BridgeResponsePayload::Send,
+                        payload: BridgeResponsePayload::Watch(sub),
                     };
                 }
             }
+            BridgeRequestsPayload::Send(addr, amount) => {
+                match client.send(addr, mint_address, amount).await {
+                    Ok(tx_id) => {
+                        res = BridgeResponse {
+                            error: BridgeResponseError::NoError,
+                            payload: BridgeResponsePayload::Send(tx_id),
+                        };
+                    }
+                    Err(e) => {
+                        error!(target: "BRIDGE", "{}", e.to_string());
+                        res = BridgeResponse {
+                            error: BridgeResponseError::BridgeSendSubscribtionError,
+                            payload: BridgeResponsePayload::Error(e.to_string()),
+                        };
+                    }
+                }
+            }
         }
 
         rep.send(res).await?;
@@ -236,13 +364,43 @@ impl Bridge {
     }
 }
 
+/// Mint address of a token on a network, or `None` for the network's
+/// native token. On networks without tokens every ID is the native token.
+pub fn mint_address(
+    network: &NetworkName,
+    native_token_id: &str,
+    token_id: &str,
+) -> Option<String> {
+    if !network.has_tokens() || token_id.is_empty() || token_id == native_token_id {
+        return None
+    }
+    Some(token_id.to_string())
+}
+
+/// Interface every bridged network implements. The cashier only talks to
+/// networks through this trait, so adding one shouldn't need changes there.
 #[async_trait]
 pub trait NetworkClient {
+    /// The network this client bridges
+    fn network(&self) -> NetworkName;
+
+    /// Address used to derive the token ID of the network's native token
+    fn native_token_id(&self) -> &'static str;
+
+    /// Mint address for the given token, or `None` for the native token
+    fn mint_address(&self, token_id: &str) -> Option<String> {
+        mint_address(&self.network(), self.native_token_id(), token_id)
+    }
+
+    /// Watch a new deposit address. Its keypair is random, or derived from
+    /// the client's main keypair when a user `tag` is given.
     async fn subscribe(
         self: Arc<Self>,
         drk_pub_key: PublicKey,
         mint: Option<String>,
+        tag: Option<String>,
         executor: Arc<Executor<'_>>,
+        supervisor: SupervisorPtr,
     ) -> Result<TokenSubscribtion>;
 
     // should check if the keypair in not already subscribed
@@ -253,14 +411,32 @@ pub trait NetworkClient {
         drk_pub_key: PublicKey,
         mint: Option<String>,
         executor: Arc<Executor<'_>>,
+        supervisor: SupervisorPtr,
     ) -> Result<String>;
 
     async fn get_notifier(self: Arc<Self>) -> Result<async_channel::Receiver<TokenNotification>>;
 
+    /// Estimate the fee for sending `amount` of the given token, with the
+    /// current network conditions. `amount` has the same precision as in
+    /// `send`.
+    async fn estimated_fee(
+        self: Arc<Self>,
+        mint: Option<String>,
+        amount: u64,
+    ) -> Result<FeeEstimate>;
+
+    /// Balance of the given token in the client's main wallet, with the
+    /// same precision as in `send`
+    async fn main_wallet_balance(
+        self: Arc<Self>,
+        mint: Option<String>,
+    ) -> Result<MainWalletBalance>;
+
+    // returns the id/hash of the broadcasted transaction
     async fn send(
         self: Arc<Self>,
         address: Vec<u8>,
         mint: Option<String>,
         amount: u64,
-    ) -> Result<()>;
+    ) -> Result<String>;
 }
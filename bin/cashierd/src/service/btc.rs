@@ -40,12 +40,19 @@ use secp256k1::{
     All, Message as BtcMessage, Secp256k1,
 };
 
-use super::bridge::{NetworkClient, TokenNotification, TokenSubscribtion};
+use super::{
+    bridge::{
+        DepositMemo, FeeEstimate, MainWalletBalance, NetworkClient, TokenNotification,
+        TokenSubscribtion,
+    },
+    deposit::{deposit_key_seed, DepositSubscriptionsPtr},
+};
 use darkfi::{
     crypto::{keypair::PublicKey as DrkPublicKey, token_id::generate_id2},
     util::{
         expand_path, load_keypair_to_str,
         serial::{deserialize, serialize, Decodable, Encodable},
+        supervisor::{RestartPolicy, SupervisorPtr},
         NetworkName,
     },
     wallet::cashierdb::{CashierDb, TokenKey},
@@ -59,6 +66,12 @@ pub type PrivKey = BtcPrivKey;
 
 const KEYPAIR_LENGTH: usize = SECRET_KEY_SIZE + PUBLIC_KEY_SIZE;
 
+/// Bitcoin has no token addresses, the genesis address stands in for BTC
+pub const BTC_NATIVE_TOKEN_ID: &str = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
+
+/// Size in bytes of a signed withdrawal: one P2PKH input and one output
+const WITHDRAW_TX_SIZE: usize = 10 + 148 + 34;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub struct BlockHeight(u32);
 
@@ -124,6 +137,16 @@ impl Keypair {
 
         Ok(Keypair { secret, public, context: secp })
     }
+
+    /// Keypair whose secret key is the given seed
+    pub fn from_seed(seed: &[u8; 32]) -> BtcResult<Keypair> {
+        let secp = Secp256k1::new();
+
+        let secret = SecretKey::from_slice(seed)?;
+        let public = PublicKey::from_secret_key(&secp, &secret);
+
+        Ok(Keypair { secret, public, context: secp })
+    }
     fn secret(&self) -> SecretKey {
         self.secret
     }
@@ -323,12 +346,14 @@ pub struct BtcClient {
     notify_channel:
         (async_channel::Sender<TokenNotification>, async_channel::Receiver<TokenNotification>),
     network: Network,
+    deposits: DepositSubscriptionsPtr,
 }
 impl BtcClient {
     pub async fn new(
         cashier_wallet: Arc<CashierDb>,
         network: &str,
         keypair_path: &str,
+        deposits: DepositSubscriptionsPtr,
     ) -> Result<Arc<Self>> {
         let main_keypair: Keypair;
 
@@ -373,9 +398,39 @@ impl BtcClient {
             client: Arc::new(Mutex::new(Client::new(url)?)),
             notify_channel,
             network,
+            deposits,
         }))
     }
 
+    /// Watch the deposit address of `btc_keys` in a supervised task.
+    async fn spawn_subscription(
+        self: Arc<Self>,
+        executor: &Executor<'_>,
+        supervisor: &SupervisorPtr,
+        btc_keys: Account,
+        drk_pub_key: DrkPublicKey,
+    ) {
+        let address = btc_keys.address.to_string();
+        let network = NetworkName::Bitcoin;
+        if !self.deposits.register(network, &address, BTC_NATIVE_TOKEN_ID, drk_pub_key).await {
+            return
+        }
+
+        let name = format!("btc deposit {}", address);
+        supervisor.spawn(executor, &name, RestartPolicy::Never, move || {
+            let request = self.clone().handle_subscribe_request(btc_keys.clone(), drk_pub_key);
+            let (deposits, address) = (self.deposits.clone(), address.clone());
+            async move {
+                let result = request.await;
+                if let Err(e) = &result {
+                    deposits.fail(&address, &e.to_string()).await;
+                }
+                deposits.remove(&address).await;
+                Ok(result?)
+            }
+        });
+    }
+
     async fn handle_subscribe_request(
         self: Arc<Self>,
         btc_keys: Account,
@@ -394,9 +449,16 @@ impl BtcClient {
         //Fetch any current balance
         let prev_balance = client.lock().await.electrum.script_get_balance(&script)?;
         let mut last_status = None;
+        let address = btc_keys.address.to_string();
 
         loop {
             async_std::task::sleep(Duration::from_secs(5)).await;
+
+            if let Err(e) = self.deposits.check(&address).await {
+                client.lock().await.subscriptions.retain(|s| s != &script);
+                return Err(e.into())
+            }
+
             let new_status = match client.lock().await.status_of_script(script.clone()) {
                 Ok(new_status) => new_status,
                 Err(error) => {
@@ -438,27 +500,70 @@ impl BtcClient {
         //Just check unconfirmed for now
         let amnt = cur_balance.confirmed - prev_balance.confirmed;
         let ui_amnt = amnt;
+
+        // Not knowing the sender only rules out a refund, so the deposit is
+        // minted all the same.
+        let sender = match self.fetch_deposit_sender(&script).await {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(target: "BTC BRIDGE", "Failed fetching deposit sender: {}", e);
+                None
+            }
+        };
+
+        self.deposits.received(&address).await;
         send_notification
             .send(TokenNotification {
                 network: NetworkName::Bitcoin,
-                // is btc an acceptable token name?
-                token_id: generate_id2(
-                    "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa",
-                    &NetworkName::Bitcoin,
-                )?,
+                address: address.clone(),
+                token_id: generate_id2(BTC_NATIVE_TOKEN_ID, &NetworkName::Bitcoin)?,
                 drk_pub_key,
                 received_balance: amnt as u64,
                 decimals: 8,
+                memo: DepositMemo::Unsupported,
+                sender,
             })
             .await
             .map_err(Error::from)?;
 
         info!(target: "BTC BRIDGE", "Received {} btc", ui_amnt);
-        let _ = self.send_btc_to_main_wallet(amnt as u64, btc_keys).await;
+        match self.clone().send_btc_to_main_wallet(amnt as u64, btc_keys).await {
+            Ok(()) => self.deposits.forwarded(&address).await,
+            Err(e) => {
+                warn!(target: "BTC BRIDGE", "Failed sending deposit to main wallet: {}", e);
+                self.deposits.fail(&address, &e.to_string()).await;
+            }
+        }
 
         Ok(())
     }
 
+    /// Address spent by the first input of the latest transaction paying
+    /// to `script`, which sent the deposit.
+    async fn fetch_deposit_sender(&self, script: &Script) -> BtcResult<Option<String>> {
+        let client = self.client.lock().await;
+        let history = client.electrum.script_get_history(script)?;
+        let latest = match history.last() {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let tx = client.electrum.transaction_get(&latest.tx_hash)?;
+        let input = match tx.input.first() {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let prev_tx = client.electrum.transaction_get(&input.previous_output.txid)?;
+        let sender = prev_tx
+            .output
+            .get(input.previous_output.vout as usize)
+            .and_then(|out| Address::from_script(&out.script_pubkey, self.network))
+            .map(|addr| addr.to_string());
+
+        Ok(sender)
+    }
+
     async fn send_btc_to_main_wallet(
         self: Arc<Self>,
         amount: u64,
@@ -535,14 +640,30 @@ impl BtcClient {
 
 #[async_trait]
 impl NetworkClient for BtcClient {
+    fn network(&self) -> NetworkName {
+        NetworkName::Bitcoin
+    }
+
+    fn native_token_id(&self) -> &'static str {
+        BTC_NATIVE_TOKEN_ID
+    }
+
     async fn subscribe(
         self: Arc<Self>,
         drk_pub_key: DrkPublicKey,
         _mint: Option<String>,
+        tag: Option<String>,
         executor: Arc<Executor<'_>>,
+        supervisor: SupervisorPtr,
     ) -> Result<TokenSubscribtion> {
         // Generate bitcoin keys
-        let keypair = Keypair::new();
+        let keypair = match tag {
+            Some(tag) => {
+                let main_secret = self.main_account.keypair.secret.secret_bytes();
+                Keypair::from_seed(&deposit_key_seed(&main_secret, &tag))?
+            }
+            None => Keypair::new(),
+        };
         let btc_keys = Account::new(&keypair, self.network);
         let private_key = serialize(&keypair);
         let public_key = btc_keys.address.to_string();
@@ -550,14 +671,7 @@ impl NetworkClient for BtcClient {
         // start scheduler for checking balance
         trace!(target: "BRIDGE BITCOIN", "Subscribing for deposit");
 
-        executor
-            .spawn(async move {
-                let result = self.handle_subscribe_request(btc_keys, drk_pub_key).await;
-                if let Err(e) = result {
-                    error!(target: "BTC BRIDGE SUBSCRIPTION","{}", e.to_string());
-                }
-            })
-            .detach();
+        self.spawn_subscription(&executor, &supervisor, btc_keys, drk_pub_key).await;
 
         Ok(TokenSubscribtion { private_key, public_key })
     }
@@ -569,19 +683,13 @@ impl NetworkClient for BtcClient {
         drk_pub_key: DrkPublicKey,
         _mint: Option<String>,
         executor: Arc<Executor<'_>>,
+        supervisor: SupervisorPtr,
     ) -> Result<String> {
         let keypair: Keypair = deserialize(&private_key)?;
         let btc_keys = Account::new(&keypair, self.network);
         let public_key = btc_keys.address.to_string();
 
-        executor
-            .spawn(async move {
-                let result = self.handle_subscribe_request(btc_keys, drk_pub_key).await;
-                if let Err(e) = result {
-                    error!(target: "BTC BRIDGE SUBSCRIPTION","{}", e.to_string());
-                }
-            })
-            .detach();
+        self.spawn_subscription(&executor, &supervisor, btc_keys, drk_pub_key).await;
 
         Ok(public_key)
     }
@@ -590,12 +698,43 @@ impl NetworkClient for BtcClient {
         Ok(self.notify_channel.1.clone())
     }
 
+    async fn estimated_fee(
+        self: Arc<Self>,
+        _mint: Option<String>,
+        _amount: u64,
+    ) -> Result<FeeEstimate> {
+        let electrum = &self.client.lock().await.electrum;
+
+        // BTC per kilobyte, for confirmation in the next block
+        let fee_per_kb = electrum.estimate_fee(1).map_err(|e| Error::from(BtcFailed::from(e)))?;
+        let fee = (WITHDRAW_TX_SIZE as f64 * fee_per_kb * 100000_f64).ceil() as u64;
+
+        Ok(FeeEstimate { network: NetworkName::Bitcoin, fee, decimals: 8 })
+    }
+
+    async fn main_wallet_balance(
+        self: Arc<Self>,
+        _mint: Option<String>,
+    ) -> Result<MainWalletBalance> {
+        let electrum = &self.client.lock().await.electrum;
+
+        // Satoshi already have the precision of minted tokens
+        let balance = electrum
+            .script_get_balance(&self.main_account.script_pubkey)
+            .map_err(|e| Error::from(BtcFailed::from(e)))?;
+
+        Ok(MainWalletBalance {
+            address: self.main_account.address.to_string(),
+            balance: balance.confirmed,
+        })
+    }
+
     async fn send(
         self: Arc<Self>,
         address: Vec<u8>,
         _mint: Option<String>,
         amount: u64,
-    ) -> Result<()> {
+    ) -> Result<String> {
         // address is not a btc address, so derive the btc address
         let electrum = &self.client.lock().await.electrum;
         let public_key = deserialize::<SecPublicKey>(&address)?.0;
@@ -639,7 +778,7 @@ impl NetworkClient for BtcClient {
             .map_err(|e| Error::from(BtcFailed::from(e)))?;
 
         info!(target: "BTC BRIDGE", "Sent {} satoshi to external wallet, txid: {}", amount, txid);
-        Ok(())
+        Ok(txid.to_string())
     }
 }
 
@@ -913,7 +1052,7 @@ pub type BtcResult<T> = std::result::Result<T, BtcFailed>;
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use super::{super::bridge::mint_address, *};
     use darkfi::util::serial::{deserialize, serialize};
     use secp256k1::constants::{PUBLIC_KEY_SIZE, SECRET_KEY_SIZE};
     use std::str::FromStr;
@@ -935,6 +1074,20 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    pub fn test_mint_address() {
+        let usdt = "0xdAC17F958D2ee523a2206206994597C13D831ec7";
+        let btc = &NetworkName::Bitcoin;
+        assert_eq!(mint_address(btc, BTC_NATIVE_TOKEN_ID, BTC_NATIVE_TOKEN_ID), None);
+        assert_eq!(mint_address(btc, BTC_NATIVE_TOKEN_ID, ""), None);
+        // Bitcoin has no tokens, so other IDs stand for BTC as well
+        assert_eq!(mint_address(btc, BTC_NATIVE_TOKEN_ID, usdt), None);
+        assert_eq!(
+            mint_address(&NetworkName::Ethereum, BTC_NATIVE_TOKEN_ID, usdt),
+            Some(usdt.to_string())
+        );
+    }
+
     #[test]
     pub fn test_serialize_and_deserialize_keypair() -> super::BtcResult<()> {
         let keypair = Keypair::new();
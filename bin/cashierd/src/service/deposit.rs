@@ -0,0 +1,379 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use async_std::{
+    future::timeout,
+    sync::{Arc, Mutex},
+};
+use fxhash::FxHashMap;
+
+use darkfi::{
+    crypto::keypair::PublicKey,
+    system::{Subscriber, SubscriberPtr},
+    util::{NetworkName, Timestamp},
+    Error, Result,
+};
+
+/// Time, in seconds, a deposit subscription waits for a deposit
+pub const DEPOSIT_TIMEOUT: i64 = 60 * 10;
+
+/// Deposit events kept for depositors to catch up on
+pub const MAX_DEPOSIT_EVENTS: usize = 10_000;
+
+/// Context string for deriving tagged deposit keys
+const DEPOSIT_KEY_CONTEXT: &str = "darkfi cashierd 2022-07 tagged deposit key";
+
+/// Derive the seed of the deposit keypair for `tag` from the secret of a
+/// network's main keypair. The same main secret and tag always give the
+/// same seed, so tagged deposit keys can be recovered from a backup of
+/// the main keypair.
+pub fn deposit_key_seed(main_secret: &[u8], tag: &str) -> [u8; 32] {
+    let key = blake3::derive_key(DEPOSIT_KEY_CONTEXT, main_secret);
+    *blake3::keyed_hash(&key, tag.as_bytes()).as_bytes()
+}
+
+/// Where a deposit is at, as reported to the depositor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepositState {
+    /// The deposit address is watched for a deposit
+    Pending,
+    /// A deposit arrived on the deposit address
+    Received,
+    /// The deposit was swept to the network's main wallet
+    Forwarded,
+    /// No deposit arrived before the subscription expired
+    Expired,
+    /// Watching, forwarding or minting the deposit failed
+    Failed,
+}
+
+impl DepositState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Received => "received",
+            Self::Forwarded => "forwarded",
+            Self::Expired => "expired",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// A deposit changing state
+#[derive(Clone, Debug)]
+pub struct DepositEvent {
+    /// Position in the event log, counting from 1
+    pub seq: u64,
+    pub network: NetworkName,
+    pub address: String,
+    pub drk_pub_key: PublicKey,
+    pub state: DepositState,
+    /// Why the deposit failed
+    pub reason: Option<String>,
+    pub timestamp: Timestamp,
+}
+
+/// A deposit address being watched for a deposit
+#[derive(Clone, Debug)]
+pub struct DepositSubscription {
+    pub network: NetworkName,
+    pub address: String,
+    /// Mint address of the expected token, or the network's native token ID
+    pub token: String,
+    pub drk_pub_key: PublicKey,
+    pub started: Timestamp,
+    /// The subscription stops waiting for a deposit past this time
+    pub expires: Timestamp,
+    pub state: DepositState,
+}
+
+pub type DepositSubscriptionsPtr = Arc<DepositSubscriptions>;
+
+/// Deposit subscriptions shared by the network clients, which check them
+/// while waiting for deposits, and the RPC methods support staff use to
+/// cancel or extend them. Every change of state of a deposit is logged,
+/// for depositors to follow their deposits with.
+pub struct DepositSubscriptions {
+    subs: Mutex<FxHashMap<String, DepositSubscription>>,
+    events: Mutex<VecDeque<DepositEvent>>,
+    /// Notified with the sequence number of every new event
+    notifier: SubscriberPtr<u64>,
+}
+
+impl DepositSubscriptions {
+    pub fn new() -> DepositSubscriptionsPtr {
+        Arc::new(Self {
+            subs: Mutex::new(FxHashMap::default()),
+            events: Mutex::new(VecDeque::new()),
+            notifier: Subscriber::new(),
+        })
+    }
+
+    /// Start tracking a deposit address. Returns `false` if the address is
+    /// already watched.
+    pub async fn register(
+        &self,
+        network: NetworkName,
+        address: &str,
+        token: &str,
+        drk_pub_key: PublicKey,
+    ) -> bool {
+        let mut subs = self.subs.lock().await;
+        if subs.contains_key(address) {
+            return false
+        }
+
+        let started = Timestamp::current_time();
+        let sub = DepositSubscription {
+            network: network.clone(),
+            address: address.to_string(),
+            token: token.to_string(),
+            drk_pub_key,
+            started,
+            expires: Timestamp(started.0 + DEPOSIT_TIMEOUT),
+            state: DepositState::Pending,
+        };
+
+        subs.insert(address.to_string(), sub);
+        drop(subs);
+
+        self.publish(network, address, drk_pub_key, DepositState::Pending, None).await;
+        true
+    }
+
+    /// Check whether the subscription watching `address` should keep
+    /// waiting for a deposit. Fails once it expired or was cancelled.
+    pub async fn check(&self, address: &str) -> Result<()> {
+        let expired = match self.subs.lock().await.get_mut(address) {
+            Some(sub) if sub.expires.0 < Timestamp::current_time().0 => {
+                let first = sub.state != DepositState::Expired;
+                sub.state = DepositState::Expired;
+                first.then(|| (sub.network.clone(), sub.drk_pub_key))
+            }
+            Some(_) => return Ok(()),
+            None => {
+                return Err(Error::CashierError(format!(
+                    "Deposit subscription for {} was cancelled",
+                    address
+                )))
+            }
+        };
+
+        if let Some((network, drk_pub_key)) = expired {
+            self.publish(network, address, drk_pub_key, DepositState::Expired, None).await;
+        }
+
+        Err(Error::CashierError(format!("Deposit subscription for {} expired", address)))
+    }
+
+    /// Record that a deposit arrived on `address`.
+    pub async fn received(&self, address: &str) {
+        self.set_state(address, DepositState::Received, None).await
+    }
+
+    /// Record that the deposit on `address` was swept to the main wallet.
+    pub async fn forwarded(&self, address: &str) {
+        self.set_state(address, DepositState::Forwarded, None).await
+    }
+
+    /// Record that the watcher of `address` failed, unless the subscription
+    /// expired or was cancelled, which is reported already.
+    pub async fn fail(&self, address: &str, reason: &str) {
+        let expired = match self.subs.lock().await.get(address) {
+            Some(sub) => sub.state == DepositState::Expired,
+            None => true,
+        };
+
+        if !expired {
+            self.set_state(address, DepositState::Failed, Some(reason.to_string())).await
+        }
+    }
+
+    /// Stop tracking a deposit address, once its watcher is done.
+    pub async fn remove(&self, address: &str) {
+        self.subs.lock().await.remove(address);
+    }
+
+    /// Cancel the subscription watching `address`. Its watcher stops on
+    /// its next check. Returns `false` if there was no such subscription.
+    pub async fn cancel(&self, address: &str) -> bool {
+        let sub = match self.subs.lock().await.remove(address) {
+            Some(v) => v,
+            None => return false,
+        };
+
+        let reason = Some("Cancelled by the cashier".to_string());
+        self.publish(sub.network, address, sub.drk_pub_key, DepositState::Failed, reason).await;
+        true
+    }
+
+    /// Push back the expiry of the subscription watching `address` by
+    /// `secs` seconds, counted from now if it already expired. Returns the
+    /// new expiry, or `None` if there was no such subscription.
+    pub async fn extend(&self, address: &str, secs: i64) -> Option<Timestamp> {
+        let mut subs = self.subs.lock().await;
+        let sub = subs.get_mut(address)?;
+        let from = sub.expires.0.max(Timestamp::current_time().0);
+        sub.expires = Timestamp(from.saturating_add(secs));
+        Some(sub.expires)
+    }
+
+    /// The tracked subscriptions, oldest first.
+    pub async fn list(&self) -> Vec<DepositSubscription> {
+        let mut ret: Vec<DepositSubscription> = self.subs.lock().await.values().cloned().collect();
+        ret.sort_by_key(|sub| sub.started.0);
+        ret
+    }
+
+    /// Log a deposit changing state, and wake up whoever waits for events.
+    pub async fn publish(
+        &self,
+        network: NetworkName,
+        address: &str,
+        drk_pub_key: PublicKey,
+        state: DepositState,
+        reason: Option<String>,
+    ) {
+        let mut events = self.events.lock().await;
+        let seq = events.back().map_or(1, |e| e.seq + 1);
+        events.push_back(DepositEvent {
+            seq,
+            network,
+            address: address.to_string(),
+            drk_pub_key,
+            state,
+            reason,
+            timestamp: Timestamp::current_time(),
+        });
+
+        if events.len() > MAX_DEPOSIT_EVENTS {
+            events.pop_front();
+        }
+        drop(events);
+
+        self.notifier.notify(seq).await;
+    }
+
+    /// Events of the deposits for `drk_pub_key` logged after `after`,
+    /// oldest first.
+    pub async fn events(&self, drk_pub_key: &PublicKey, after: u64) -> Vec<DepositEvent> {
+        let events = self.events.lock().await;
+        events.iter().filter(|e| e.seq > after && e.drk_pub_key == *drk_pub_key).cloned().collect()
+    }
+
+    /// Like [`Self::events`], but when there are none yet, wait up to
+    /// `secs` seconds for one to come in.
+    pub async fn wait_events(
+        &self,
+        drk_pub_key: &PublicKey,
+        after: u64,
+        secs: u64,
+    ) -> Vec<DepositEvent> {
+        let deadline = Instant::now() + Duration::from_secs(secs);
+        let subscription = self.notifier.clone().subscribe().await;
+
+        let events = loop {
+            let events = self.events(drk_pub_key, after).await;
+            if !events.is_empty() {
+                break events
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            if timeout(left, subscription.receive()).await.is_err() {
+                break vec![]
+            }
+        };
+
+        subscription.unsubscribe().await;
+        events
+    }
+
+    async fn set_state(&self, address: &str, state: DepositState, reason: Option<String>) {
+        let sub = match self.subs.lock().await.get_mut(address) {
+            Some(sub) => {
+                sub.state = state;
+                sub.clone()
+            }
+            None => return,
+        };
+
+        self.publish(sub.network, address, sub.drk_pub_key, state, reason).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use darkfi::crypto::keypair::Keypair;
+
+    #[test]
+    fn test_deposit_subscriptions() {
+        async_std::task::block_on(async {
+            let deposits = DepositSubscriptions::new();
+            let drk_pub_key = Keypair::random(&mut rand::rngs::OsRng).public;
+
+            assert!(deposits.register(NetworkName::Bitcoin, "addr", "btc", drk_pub_key).await);
+            assert!(!deposits.register(NetworkName::Bitcoin, "addr", "btc", drk_pub_key).await);
+            assert!(deposits.check("addr").await.is_ok());
+
+            // Pushing the expiry into the past expires the subscription
+            let expires = deposits.list().await[0].expires;
+            assert!(deposits.extend("addr", -DEPOSIT_TIMEOUT - 1).await.unwrap() < expires);
+            assert!(deposits.check("addr").await.is_err());
+
+            assert!(deposits.cancel("addr").await);
+            assert!(!deposits.cancel("addr").await);
+            assert!(deposits.check("addr").await.is_err());
+            assert!(deposits.extend("addr", 60).await.is_none());
+        });
+    }
+
+    #[test]
+    fn test_deposit_events() {
+        async_std::task::block_on(async {
+            let deposits = DepositSubscriptions::new();
+            let drk_pub_key = Keypair::random(&mut rand::rngs::OsRng).public;
+            let other = Keypair::random(&mut rand::rngs::OsRng).public;
+
+            deposits.register(NetworkName::Ethereum, "a", "eth", drk_pub_key).await;
+            deposits.register(NetworkName::Ethereum, "b", "eth", other).await;
+            deposits.received("a").await;
+            deposits.forwarded("a").await;
+            deposits.remove("a").await;
+
+            // Expiry is reported once, and not as a failure of the watcher
+            deposits.register(NetworkName::Ethereum, "c", "eth", drk_pub_key).await;
+            deposits.extend("c", -DEPOSIT_TIMEOUT - 1).await;
+            assert!(deposits.check("c").await.is_err());
+            assert!(deposits.check("c").await.is_err());
+            deposits.fail("c", "expired").await;
+
+            let states: Vec<_> =
+                deposits.events(&drk_pub_key, 0).await.iter().map(|e| e.state).collect();
+            assert_eq!(
+                states,
+                vec![
+                    DepositState::Pending,
+                    DepositState::Received,
+                    DepositState::Forwarded,
+                    DepositState::Pending,
+                    DepositState::Expired,
+                ]
+            );
+
+            let events = deposits.wait_events(&other, 0, 1).await;
+            assert_eq!(events.len(), 1);
+            assert!(deposits.wait_events(&other, events[0].seq, 1).await.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_deposit_key_seed() {
+        let seed = deposit_key_seed(b"main secret", "user-1");
+        assert_eq!(seed, deposit_key_seed(b"main secret", "user-1"));
+        assert_ne!(seed, deposit_key_seed(b"main secret", "user-2"));
+        assert_ne!(seed, deposit_key_seed(b"other secret", "user-1"));
+    }
+}
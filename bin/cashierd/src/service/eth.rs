@@ -6,13 +6,19 @@ use async_trait::async_trait;
 use hash_db::Hasher;
 use keccak_hasher::KeccakHasher;
 use lazy_static::lazy_static;
-use log::{debug, error, info, trace};
+use log::{debug, info, trace, warn};
 use num_bigint::{BigUint, RandBigInt};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use url::Url;
 
-use super::bridge::{NetworkClient, TokenNotification, TokenSubscribtion};
+use super::{
+    bridge::{
+        DepositMemo, FeeEstimate, MainWalletBalance, NetworkClient, TokenNotification,
+        TokenSubscribtion,
+    },
+    deposit::{deposit_key_seed, DepositSubscriptionsPtr},
+};
 
 use darkfi::{
     crypto::{keypair::PublicKey, token_id::generate_id2},
@@ -20,7 +26,9 @@ use darkfi::{
     util::{
         parse::truncate,
         serial::{deserialize, serialize, Decodable, Encodable},
-        sleep, NetworkName,
+        sleep,
+        supervisor::{RestartPolicy, SupervisorPtr},
+        NetworkName,
     },
     wallet::cashierdb::{CashierDb, TokenKey},
     Error, Result,
@@ -28,6 +36,10 @@ use darkfi::{
 
 pub const ETH_NATIVE_TOKEN_ID: &str = "0x0000000000000000000000000000000000000000";
 
+/// Seconds to wait for the ETH paying the gas of an ERC-20 sweep to
+/// reach the deposit address
+const GAS_FUNDING_TIMEOUT: u64 = 600;
+
 #[derive(Clone, Debug)]
 pub struct Keypair {
     pub private_key: String,
@@ -121,6 +133,59 @@ pub fn erc20_balanceof_data(account: &str) -> String {
     format!("0x{}{}", hex::encode(*ERC20_BALANCEOF_METHOD), acc_padded)
 }
 
+pub fn erc20_decimals_data() -> String {
+    format!("0x{}", hex::encode(*ERC20_DECIMALS_METHOD))
+}
+
+/// Decode calldata as a UTF-8 memo.
+pub fn calldata_memo(input: &str) -> Option<String> {
+    let bytes = hex::decode(input.trim_start_matches("0x")).ok()?;
+    let memo = String::from_utf8(bytes).ok()?;
+
+    if memo.is_empty() {
+        return None
+    }
+
+    Some(memo)
+}
+
+/// Whether the calldata is an ERC-20 `transfer` to `recipient`
+pub fn is_erc20_transfer_to(input: &str, recipient: &str) -> bool {
+    let input = input.trim_start_matches("0x").to_lowercase();
+    let rec = recipient.trim_start_matches("0x").to_lowercase();
+
+    // 4 bytes method, 32 bytes recipient, 32 bytes amount
+    input.len() >= 136 &&
+        input.starts_with(&hex::encode(*ERC20_TRANSFER_METHOD)) &&
+        input[8..72] == format!("{:0>64}", rec)
+}
+
+/// Extract the memo appended to the calldata of an ERC-20 `transfer` to
+/// `recipient`. The token contract ignores the trailing bytes.
+pub fn erc20_transfer_memo(input: &str, recipient: &str) -> Option<String> {
+    if !is_erc20_transfer_to(input, recipient) {
+        return None
+    }
+
+    calldata_memo(&input.trim_start_matches("0x")[136..])
+}
+
+fn from_eth_hex(val: &Value) -> EthResult<BigUint> {
+    let hex = match val.as_str() {
+        Some(v) => v.trim_start_matches("0x"),
+        None => return Err(EthFailed::ParseError(format!("Expected hex string, got {}", val))),
+    };
+
+    if hex.is_empty() {
+        return Ok(BigUint::from(0_u64))
+    }
+
+    match BigUint::parse_bytes(hex.as_bytes(), 16) {
+        Some(v) => Ok(v),
+        None => Err(EthFailed::ParseError(format!("Invalid hex value: {}", hex))),
+    }
+}
+
 fn to_eth_hex(val: BigUint) -> String {
     let bytes = val.to_bytes_be();
     let h = hex::encode(bytes);
@@ -200,10 +265,16 @@ pub struct EthClient {
     subscriptions: Arc<Mutex<Vec<String>>>,
     notify_channel:
         (async_channel::Sender<TokenNotification>, async_channel::Receiver<TokenNotification>),
+    deposits: DepositSubscriptionsPtr,
 }
 
 impl EthClient {
-    pub fn new(_network: &str, socket_path: &str, passphrase: &str) -> Self {
+    pub fn new(
+        _network: &str,
+        socket_path: &str,
+        passphrase: &str,
+        deposits: DepositSubscriptionsPtr,
+    ) -> Self {
         let notify_channel = async_channel::unbounded();
 
         let subscriptions = Arc::new(Mutex::new(Vec::new()));
@@ -216,6 +287,7 @@ impl EthClient {
             socket_path: socket_path.into(),
             subscriptions,
             notify_channel,
+            deposits,
         }
     }
 
@@ -269,35 +341,122 @@ impl EthClient {
         Ok(())
     }
 
+    async fn send_erc20_to_main_wallet(
+        &self,
+        acc: &str,
+        mint: &str,
+        amount: BigUint,
+    ) -> Result<()> {
+        info!(target: "ETH BRIDGE", "Sending erc20 token {} to main wallet", mint);
+
+        let data = erc20_transfer_data(&self.main_keypair.public_key, amount);
+        let mut tx = EthTx::new(acc, mint, None, None, None, Some(data), None);
+
+        let gas = self.estimate_gas(&tx).await?;
+        let gas_price = self.gas_price().await?;
+        self.fund_gas(acc, gas.clone() * gas_price.clone()).await?;
+
+        tx.gas = Some(to_eth_hex(gas));
+        tx.gasPrice = Some(to_eth_hex(gas_price));
+        self.send_transaction(&tx, &self.passphrase).await?;
+
+        Ok(())
+    }
+
+    /// The transfer of a token is a call to its contract, paid for in ETH
+    /// by the deposit address. Send it what it lacks of `fee` from the main
+    /// wallet, and wait until it has it.
+    async fn fund_gas(&self, acc: &str, fee: BigUint) -> Result<()> {
+        let balance = self.get_current_balance(acc, None).await?;
+        if balance >= fee {
+            return Ok(())
+        }
+
+        // The main wallet pays for the funding transaction as well
+        let missing = fee.clone() - balance;
+        let main_pubkey = &self.main_keypair.public_key;
+        let mut tx = EthTx::new(main_pubkey, acc, None, None, Some(missing.clone()), None, None);
+        let gas = self.estimate_gas(&tx).await?;
+        let gas_price = self.gas_price().await?;
+        let required = missing.clone() + gas.clone() * gas_price.clone();
+        if self.get_current_balance(main_pubkey, None).await? < required {
+            return Err(EthFailed::MainAccountNotEnoughValue.into())
+        }
+
+        info!(target: "ETH BRIDGE", "Funding {} with {} wei for gas", acc, missing);
+        tx.gas = Some(to_eth_hex(gas));
+        tx.gasPrice = Some(to_eth_hex(gas_price));
+        self.send_transaction(&tx, &self.passphrase).await?;
+
+        for _ in 0..GAS_FUNDING_TIMEOUT {
+            sleep(1).await;
+            if self.get_current_balance(acc, None).await? >= fee {
+                return Ok(())
+            }
+        }
+
+        Err(EthFailed::Custom(format!("Gas funding of {} did not arrive", acc)).into())
+    }
+
+    /// Watch the deposit address `addr` in a supervised task.
+    async fn spawn_subscription(
+        self: Arc<Self>,
+        executor: &Executor<'_>,
+        supervisor: &SupervisorPtr,
+        addr: String,
+        drk_pub_key: PublicKey,
+        mint: Option<String>,
+    ) {
+        let token = mint.as_deref().unwrap_or(ETH_NATIVE_TOKEN_ID);
+        if !self.deposits.register(NetworkName::Ethereum, &addr, token, drk_pub_key).await {
+            return
+        }
+
+        let name = format!("eth deposit {}", addr);
+        supervisor.spawn(executor, &name, RestartPolicy::Never, move || {
+            let request =
+                self.clone().handle_subscribe_request(addr.clone(), drk_pub_key, mint.clone());
+            let (deposits, addr) = (self.deposits.clone(), addr.clone());
+            async move {
+                let result = request.await;
+                if let Err(e) = &result {
+                    deposits.fail(&addr, &e.to_string()).await;
+                }
+                deposits.remove(&addr).await;
+                result
+            }
+        });
+    }
+
     async fn handle_subscribe_request(
         self: Arc<Self>,
         addr: String,
         drk_pub_key: PublicKey,
+        mint: Option<String>,
     ) -> Result<()> {
         if self.subscriptions.lock().await.contains(&addr) {
             return Ok(())
         }
 
-        let decimals = 18;
+        let decimals = match &mint {
+            Some(m) => self.get_erc20_decimals(m).await?,
+            None => 18,
+        };
 
-        let prev_balance = self.get_current_balance(&addr, None).await?;
+        let prev_balance = self.get_current_balance(&addr, mint.as_deref()).await?;
+        let start_block = from_eth_hex(&self.block_number().await?)?;
 
         let mut current_balance;
 
-        let iter_interval = 1;
-        let mut sub_iter = 0;
-
         loop {
-            if sub_iter > 60 * 10 {
-                // 10 minutes
+            if let Err(e) = self.deposits.check(&addr).await {
                 self.unsubscribe(&addr).await;
-                return Err(EthFailed::Custom("Deposit for expired".to_string()).into())
+                return Err(e)
             }
 
-            sub_iter += iter_interval;
-            sleep(iter_interval).await;
+            sleep(1).await;
 
-            current_balance = self.get_current_balance(&addr, None).await?;
+            current_balance = self.get_current_balance(&addr, mint.as_deref()).await?;
 
             if current_balance != prev_balance {
                 break
@@ -316,27 +475,128 @@ impl EthClient {
 
         let received_balance = current_balance - prev_balance;
 
+        // The depositor binds the deposit to their darkfi address in the
+        // transaction calldata, which the cashier checks before minting. A
+        // deposit that isn't bound to it won't be minted, so it's left on
+        // the deposit address rather than swept into the main wallet.
+        let (memo, sender) = self.find_deposit(&addr, mint.as_deref(), start_block).await?;
+        let memo = DepositMemo::Found(memo);
+        let sweep = match memo.verify(&drk_pub_key) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(target: "ETH BRIDGE", "Not sweeping deposit on {}: {}", addr, e);
+                false
+            }
+        };
+
         let received_balance_ui = received_balance.clone() / u64::pow(10, decimals as u32);
+        // Amounts are passed on in the token's smallest unit, which for
+        // large deposits doesn't fit in a u64
+        let amount = match u64::try_from(&received_balance) {
+            Ok(b) => b,
+            Err(_) => {
+                return Err(EthFailed::Custom(format!(
+                    "Deposit on {} out of range: {}",
+                    addr, received_balance
+                ))
+                .into())
+            }
+        };
 
+        self.deposits.received(&addr).await;
         send_notification
             .send(TokenNotification {
                 network: NetworkName::Ethereum,
-                token_id: generate_id2(ETH_NATIVE_TOKEN_ID, &NetworkName::Ethereum)?,
+                address: addr.clone(),
+                token_id: generate_id2(
+                    mint.as_deref().unwrap_or(ETH_NATIVE_TOKEN_ID),
+                    &NetworkName::Ethereum,
+                )?,
                 drk_pub_key,
-                // TODO FIX
-                received_balance: received_balance.to_u64_digits()[0],
+                received_balance: amount,
                 decimals: decimals as u16,
+                memo,
+                sender,
             })
             .await
             .map_err(Error::from)?;
 
-        self.send_eth_to_main_wallet(&addr, received_balance).await?;
+        match &mint {
+            Some(m) => {
+                info!(target: "ETH BRIDGE", "Received {} of erc20 token {}", received_balance_ui, m);
+                if sweep {
+                    self.send_erc20_to_main_wallet(&addr, m, received_balance).await?;
+                }
+            }
+            None => {
+                info!(target: "ETH BRIDGE", "Received {} eth", received_balance_ui);
+                if sweep {
+                    self.send_eth_to_main_wallet(&addr, received_balance).await?;
+                }
+            }
+        }
 
-        info!(target: "ETH BRIDGE", "Received {} eth", received_balance_ui );
+        if sweep {
+            self.deposits.forwarded(&addr).await;
+        }
 
         Ok(())
     }
 
+    /// Scan the blocks mined since `from_block` for a deposit to `addr` and
+    /// return the memo carried in its calldata, and the address it was sent
+    /// from. For native ETH the memo is the whole calldata, for ERC-20
+    /// tokens it's appended to the arguments of the `transfer` call.
+    async fn find_deposit(
+        &self,
+        addr: &str,
+        mint: Option<&str>,
+        from_block: BigUint,
+    ) -> EthResult<(Option<String>, Option<String>)> {
+        let addr = addr.to_lowercase();
+        let to = mint.map(|m| m.to_lowercase()).unwrap_or_else(|| addr.clone());
+        let latest = from_eth_hex(&self.block_number().await?)?;
+
+        let mut memo = None;
+        let mut sender = None;
+        let mut block = from_block;
+
+        while block <= latest {
+            let req = jsonrpc::request(
+                json!("eth_getBlockByNumber"),
+                json!([to_eth_hex(block.clone()), true]),
+            );
+            let reply = self.request(req).await?;
+
+            for tx in reply["transactions"].as_array().cloned().unwrap_or_default() {
+                if tx["to"].as_str().map(|t| t.to_lowercase()) != Some(to.clone()) {
+                    continue
+                }
+
+                // Calls to the token contract are only deposits when they
+                // transfer to the deposit address
+                let input = tx["input"].as_str().unwrap_or("0x");
+                if mint.is_some() && !is_erc20_transfer_to(input, &addr) {
+                    continue
+                }
+
+                let found = match mint {
+                    Some(_) => erc20_transfer_memo(input, &addr),
+                    None => calldata_memo(input),
+                };
+
+                if found.is_some() {
+                    memo = found;
+                }
+                sender = tx["from"].as_str().map(String::from);
+            }
+
+            block += 1_u64;
+        }
+
+        Ok((memo, sender))
+    }
+
     async fn unsubscribe(&self, pubkey: &str) {
         let mut subscriptions = self.subscriptions.lock().await;
         let index = subscriptions.iter().position(|p| p == pubkey);
@@ -378,12 +638,15 @@ impl EthClient {
         Ok(self.request(req).await?)
     }
 
-    /*
-    pub async fn estimate_gas(&self, tx: &EthTx) -> Result<Value> {
-    let req = jsonrpc::request(json!("eth_estimateGas"), json!([tx]));
-    Ok(self.request(req).await?)
+    pub async fn estimate_gas(&self, tx: &EthTx) -> EthResult<BigUint> {
+        let req = jsonrpc::request(json!("eth_estimateGas"), json!([tx]));
+        from_eth_hex(&self.request(req).await?)
+    }
+
+    pub async fn gas_price(&self) -> EthResult<BigUint> {
+        let req = jsonrpc::request(json!("eth_gasPrice"), json!([]));
+        from_eth_hex(&self.request(req).await?)
     }
-    */
 
     pub async fn block_number(&self) -> EthResult<Value> {
         let req = jsonrpc::request(json!("eth_blockNumber"), json!([]));
@@ -401,17 +664,32 @@ impl EthClient {
         Ok(self.request(req).await?)
     }
 
-    pub async fn get_current_balance(&self, acc: &str, _mint: Option<&str>) -> EthResult<BigUint> {
+    pub async fn get_erc20_decimals(&self, mint: &str) -> EthResult<u16> {
+        let tx = EthTx::new(mint, mint, None, None, None, Some(erc20_decimals_data()), None);
+        let req = jsonrpc::request(json!("eth_call"), json!([tx, "latest"]));
+        let decimals = from_eth_hex(&self.request(req).await?)?;
+
+        match decimals.to_u64_digits().first() {
+            Some(d) if *d <= u16::MAX as u64 => Ok(*d as u16),
+            Some(_) => Err(EthFailed::MintIsNotValid(mint.to_string())),
+            None => Ok(0),
+        }
+    }
+
+    pub async fn get_current_balance(&self, acc: &str, mint: Option<&str>) -> EthResult<BigUint> {
+        // ERC-20 token balance
+        if let Some(mint) = mint {
+            return from_eth_hex(&self.get_erc20_balance(acc, mint).await?)
+        }
+
         // Latest known block, used to calculate present balance.
         let block = self.block_number().await?;
-        let block = block.as_str().unwrap();
+        let block = block
+            .as_str()
+            .ok_or_else(|| EthFailed::RpcError("Block number is not a string".into()))?;
 
         // Native ETH balance
-        let hexbalance = self.get_eth_balance(acc, block).await?;
-        let hexbalance = hexbalance.as_str().unwrap().trim_start_matches("0x");
-        let balance = BigUint::parse_bytes(hexbalance.as_bytes(), 16).unwrap();
-
-        Ok(balance)
+        from_eth_hex(&self.get_eth_balance(acc, block).await?)
     }
 
     pub async fn send_transaction(&self, tx: &EthTx, passphrase: &str) -> EthResult<Value> {
@@ -422,13 +700,28 @@ impl EthClient {
 
 #[async_trait]
 impl NetworkClient for EthClient {
+    fn network(&self) -> NetworkName {
+        NetworkName::Ethereum
+    }
+
+    fn native_token_id(&self) -> &'static str {
+        ETH_NATIVE_TOKEN_ID
+    }
+
     async fn subscribe(
         self: Arc<Self>,
         drk_pub_key: PublicKey,
-        _mint_address: Option<String>,
+        mint_address: Option<String>,
+        tag: Option<String>,
         executor: Arc<Executor<'_>>,
+        supervisor: SupervisorPtr,
     ) -> Result<TokenSubscribtion> {
-        let private_key = generate_privkey();
+        let private_key = match tag {
+            Some(tag) => {
+                hex::encode(deposit_key_seed(self.main_keypair.private_key.as_bytes(), &tag))
+            }
+            None => generate_privkey(),
+        };
 
         let addr = self.import_privkey(&private_key).await?;
 
@@ -438,15 +731,8 @@ impl NetworkClient for EthClient {
             return Err(Error::from(EthFailed::ImportPrivateError))
         };
 
-        let addr_cloned = address.clone();
-        executor
-            .spawn(async move {
-                let result = self.handle_subscribe_request(addr_cloned, drk_pub_key).await;
-                if let Err(e) = result {
-                    error!(target: "ETH BRIDGE SUBSCRIPTION","{}", e.to_string());
-                }
-            })
-            .detach();
+        self.spawn_subscription(&executor, &supervisor, address.clone(), drk_pub_key, mint_address)
+            .await;
 
         let private_key: Vec<u8> = serialize(&private_key);
 
@@ -458,20 +744,20 @@ impl NetworkClient for EthClient {
         _private_key: Vec<u8>,
         public_key: Vec<u8>,
         drk_pub_key: PublicKey,
-        _mint_address: Option<String>,
+        mint_address: Option<String>,
         executor: Arc<Executor<'_>>,
+        supervisor: SupervisorPtr,
     ) -> Result<String> {
         let public_key: String = deserialize(&public_key)?;
 
-        let address = public_key.clone();
-        executor
-            .spawn(async move {
-                let result = self.handle_subscribe_request(address, drk_pub_key).await;
-                if let Err(e) = result {
-                    error!(target: "ETH BRIDGE SUBSCRIPTION","{}", e.to_string());
-                }
-            })
-            .detach();
+        self.spawn_subscription(
+            &executor,
+            &supervisor,
+            public_key.clone(),
+            drk_pub_key,
+            mint_address,
+        )
+        .await;
 
         Ok(public_key)
     }
@@ -480,33 +766,140 @@ impl NetworkClient for EthClient {
         Ok(self.notify_channel.1.clone())
     }
 
+    async fn estimated_fee(
+        self: Arc<Self>,
+        mint: Option<String>,
+        amount: u64,
+    ) -> Result<FeeEstimate> {
+        let main_pubkey = &self.main_keypair.public_key;
+
+        let decimals = match &mint {
+            Some(m) => self.get_erc20_decimals(m).await?,
+            None => 18,
+        };
+        let amount = BigUint::from(truncate(amount, decimals, 8)?);
+
+        // The recipient doesn't change the gas used, so estimate a
+        // transfer back to the main wallet.
+        let tx = match &mint {
+            Some(m) => EthTx::new(
+                main_pubkey,
+                m,
+                None,
+                None,
+                None,
+                Some(erc20_transfer_data(main_pubkey, amount)),
+                None,
+            ),
+            None => EthTx::new(main_pubkey, main_pubkey, None, None, Some(amount), None, None),
+        };
+
+        let fee = self.estimate_gas(&tx).await? * self.gas_price().await?;
+        let fee = match u64::try_from(&fee) {
+            Ok(f) => f,
+            Err(_) => return Err(EthFailed::Custom(format!("Fee out of range: {}", fee)).into()),
+        };
+
+        Ok(FeeEstimate { network: NetworkName::Ethereum, fee, decimals: 18 })
+    }
+
+    async fn main_wallet_balance(
+        self: Arc<Self>,
+        mint: Option<String>,
+    ) -> Result<MainWalletBalance> {
+        let main_pubkey = &self.main_keypair.public_key;
+
+        let decimals = match &mint {
+            Some(m) => self.get_erc20_decimals(m).await?,
+            None => 18,
+        };
+
+        // Balances easily overflow u64 in wei, so bring them down to
+        // 8 decimals before converting
+        let balance = self.get_current_balance(main_pubkey, mint.as_deref()).await?;
+        let balance = if decimals > 8 {
+            balance / BigUint::from(10_u32).pow(decimals as u32 - 8)
+        } else {
+            balance * BigUint::from(10_u32).pow(8 - decimals as u32)
+        };
+        let balance = match u64::try_from(&balance) {
+            Ok(b) => b,
+            Err(_) => {
+                return Err(EthFailed::Custom(format!("Balance out of range: {}", balance)).into())
+            }
+        };
+
+        Ok(MainWalletBalance { address: main_pubkey.clone(), balance })
+    }
+
     async fn send(
         self: Arc<Self>,
         address: Vec<u8>,
-        _mint: Option<String>,
+        mint: Option<String>,
         amount: u64,
-    ) -> Result<()> {
+    ) -> Result<String> {
         // Recipient address
         let dest: String = deserialize(&address)?;
+        let dest_hex = dest.trim_start_matches("0x");
+        if dest_hex.len() != 40 || hex::decode(dest_hex).is_err() {
+            return Err(EthFailed::BadEthAddress(dest).into())
+        }
+
+        let main_pubkey = &self.main_keypair.public_key;
 
-        let decimals = 18;
+        let decimals = match &mint {
+            Some(m) => self.get_erc20_decimals(m).await?,
+            None => 18,
+        };
 
         // reverse truncate
-        let amount = truncate(amount, decimals as u16, 8)?;
-
-        let tx = EthTx::new(
-            &self.main_keypair.public_key,
-            &dest,
-            None,
-            None,
-            Some(BigUint::from(amount)),
-            None,
-            None,
-        );
+        let amount = BigUint::from(truncate(amount, decimals, 8)?);
 
-        self.send_transaction(&tx, &self.passphrase).await?;
+        let main_balance = self.get_current_balance(main_pubkey, mint.as_deref()).await?;
+        if main_balance < amount {
+            return Err(EthFailed::MainAccountNotEnoughValue.into())
+        }
 
-        Ok(())
+        // ERC-20 withdrawals are a transfer call on the token contract
+        let mut tx = match &mint {
+            Some(m) => EthTx::new(
+                main_pubkey,
+                m,
+                None,
+                None,
+                None,
+                Some(erc20_transfer_data(&dest, amount.clone())),
+                None,
+            ),
+            None => EthTx::new(main_pubkey, &dest, None, None, Some(amount.clone()), None, None),
+        };
+
+        let gas = self.estimate_gas(&tx).await?;
+        let gas_price = self.gas_price().await?;
+
+        // The main wallet pays the fees in native ETH
+        let mut required_eth = gas.clone() * gas_price.clone();
+        if mint.is_none() {
+            required_eth += amount.clone();
+        }
+        if self.get_current_balance(main_pubkey, None).await? < required_eth {
+            return Err(EthFailed::MainAccountNotEnoughValue.into())
+        }
+
+        tx.gas = Some(to_eth_hex(gas));
+        tx.gasPrice = Some(to_eth_hex(gas_price));
+
+        let tx_hash = self.send_transaction(&tx, &self.passphrase).await?;
+        let tx_hash = match tx_hash.as_str() {
+            Some(h) => h.to_string(),
+            None => {
+                return Err(EthFailed::RpcError(format!("Unexpected tx hash: {}", tx_hash)).into())
+            }
+        };
+
+        info!(target: "ETH BRIDGE", "Sent {} to {}, tx: {}", amount, dest, tx_hash);
+
+        Ok(tx_hash)
     }
 }
 
@@ -568,4 +961,28 @@ mod tests {
 
         assert_eq!(erc20_transfer_data(recipient, amnt), "0xa9059cbb0000000000000000000000005b7b3b499fb69c40c365343cb0dc842fe8c23887000000000000000000000000000000000000000000000001e27786570c272000");
     }
+
+    #[test]
+    fn test_erc20_transfer_memo() {
+        let recipient = "0x5b7b3b499fb69c40c365343cb0dc842fe8c23887";
+        let amnt = BigUint::from_str("34765403556934000640").unwrap();
+        let data = erc20_transfer_data(recipient, amnt);
+
+        assert_eq!(erc20_transfer_memo(&data, recipient), None);
+
+        let with_memo = format!("{}{}", data, hex::encode("drk:1DarkFi"));
+        assert_eq!(erc20_transfer_memo(&with_memo, recipient), Some("drk:1DarkFi".to_string()));
+        assert_eq!(erc20_transfer_memo(&with_memo, ETH_NATIVE_TOKEN_ID), None);
+
+        assert!(is_erc20_transfer_to(&data, recipient));
+        assert!(is_erc20_transfer_to(&with_memo, &format!("0x{}", recipient[2..].to_uppercase())));
+        assert!(!is_erc20_transfer_to(&data, ETH_NATIVE_TOKEN_ID));
+        assert!(!is_erc20_transfer_to(&erc20_balanceof_data(recipient), recipient));
+
+        assert_eq!(calldata_memo("0x"), None);
+        assert_eq!(
+            calldata_memo(&format!("0x{}", hex::encode("drk:1DarkFi"))).unwrap(),
+            "drk:1DarkFi"
+        );
+    }
 }
@@ -1,4 +1,5 @@
 pub mod bridge;
+pub mod deposit;
 
 #[cfg(feature = "btc")]
 pub mod btc;
@@ -8,7 +9,7 @@ pub use btc::{Account, BtcFailed, BtcResult, Keypair, PubAddress};
 #[cfg(feature = "sol")]
 pub mod sol;
 #[cfg(feature = "sol")]
-pub use sol::{SolClient, SolFailed, SolResult};
+pub use sol::{SolClient, SolEndpoints, SolFailed, SolResult};
 
 #[cfg(feature = "eth")]
 pub mod eth;
@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{cmp::min, str::FromStr};
 
 use async_executor::Executor;
 use async_native_tls::TlsConnector;
@@ -8,20 +8,33 @@ use futures::{SinkExt, StreamExt};
 use log::{debug, error, info, trace, warn};
 use serde::Serialize;
 use serde_json::{json, Value};
-use solana_client::{blockhash_query::BlockhashQuery, rpc_client::RpcClient};
+use solana_client::{
+    blockhash_query::BlockhashQuery, rpc_client::RpcClient, rpc_request::RpcRequest,
+    rpc_response::RpcConfirmedTransactionStatusWithSignature,
+};
 use solana_sdk::{
+    message::Message as SolMessage,
     native_token::{lamports_to_sol, sol_to_lamports},
     program_pack::Pack,
     pubkey::Pubkey,
     signature::{Signature, Signer},
-    signer::keypair::Keypair,
+    signer::keypair::{keypair_from_seed, Keypair},
     system_instruction,
     transaction::Transaction,
 };
 use spl_associated_token_account::{create_associated_token_account, get_associated_token_address};
 use tungstenite::Message;
+use url::Url;
+
+use super::{
+    bridge::{
+        deposit_memo, DepositMemo, FeeEstimate, MainWalletBalance, NetworkClient,
+        TokenNotification, TokenSubscribtion,
+    },
+    deposit::{deposit_key_seed, DepositSubscriptionsPtr},
+};
 
-use super::bridge::{NetworkClient, TokenNotification, TokenSubscribtion};
+use fxhash::FxHashMap;
 
 use darkfi::{
     crypto::{keypair::PublicKey, token_id::generate_id2},
@@ -30,7 +43,9 @@ use darkfi::{
         expand_path, load_keypair_to_str,
         parse::truncate,
         serial::{deserialize, serialize, Decodable, Encodable},
-        sleep, NetworkName,
+        sleep,
+        supervisor::{RestartPolicy, SupervisorPtr},
+        NetworkName,
     },
     wallet::cashierdb::{CashierDb, TokenKey},
     Error, Result,
@@ -38,6 +53,11 @@ use darkfi::{
 
 pub const SOL_NATIVE_TOKEN_ID: &str = "So11111111111111111111111111111111111111112";
 
+/// How many times a dropped subscription is retried before giving up
+pub const SUBSCRIPTION_MAX_RETRIES: u32 = 8;
+/// Upper bound, in seconds, for the delay between two retries
+pub const SUBSCRIPTION_MAX_BACKOFF: u64 = 60;
+
 struct SolKeypair(Keypair);
 struct SolPubkey(Pubkey);
 
@@ -47,14 +67,110 @@ struct SubscribeParams {
     commitment: Value,
 }
 
+/// State of a deposit address subscription
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubscriptionState {
+    /// Connecting to the cluster and subscribing to the account
+    Connecting,
+    /// Subscribed, waiting for a deposit
+    Watching,
+    /// The subscription dropped and will be retried
+    Retrying { attempt: u32, error: String },
+    /// The deposit was received and moved to the main wallet
+    Completed,
+    /// The subscription was given up on
+    Failed(String),
+}
+
+/// Sent on the error channel when a subscription is given up on, so the
+/// deposit can be looked into by hand.
+#[derive(Clone, Debug)]
+pub struct SubscriptionFailure {
+    pub deposit_address: String,
+    pub drk_pub_key: PublicKey,
+    pub error: String,
+}
+
+/// What a subscription has done so far, kept across retries.
+#[derive(Default)]
+struct SubscriptionProgress {
+    /// Account balance and decimals from before the first subscription
+    prev_balance: Option<(u64, u64)>,
+    /// Set once the deposit notification was sent. Retrying after this
+    /// point could credit the same deposit twice.
+    notified: bool,
+}
+
+/// Custom endpoints for a Solana cluster, overriding the public ones.
+#[derive(Clone, Debug, Default)]
+pub struct SolEndpoints {
+    pub rpc_server: Option<String>,
+    pub wss_server: Option<String>,
+    /// Appended to both URLs as the last path segment, which is how
+    /// most paid RPC providers expect it.
+    pub auth_token: Option<String>,
+}
+
+impl SolEndpoints {
+    /// Resolve the endpoints for the given cluster, falling back to the
+    /// public ones for anything that isn't configured.
+    pub fn resolve(&self, network: &str) -> SolResult<(String, String)> {
+        let (rpc_default, wss_default) = match network {
+            "mainnet" => {
+                ("https://api.mainnet-beta.solana.com", "wss://api.mainnet-beta.solana.com")
+            }
+            "devnet" => ("https://api.devnet.solana.com", "wss://api.devnet.solana.com"),
+            "testnet" => ("https://api.testnet.solana.com", "wss://api.testnet.solana.com"),
+            "localhost" => ("http://localhost:8899", "ws://localhost:8900"),
+            _ => return Err(SolFailed::Darkfi(Error::UnsupportedCoinNetwork)),
+        };
+
+        let rpc_server = self.rpc_server.as_deref().unwrap_or(rpc_default);
+        let wss_server = self.wss_server.as_deref().unwrap_or(wss_default);
+
+        let rpc_server = self.validate(rpc_server, &["http", "https"])?;
+        let wss_server = self.validate(wss_server, &["ws", "wss"])?;
+
+        Ok((rpc_server, wss_server))
+    }
+
+    fn validate(&self, endpoint: &str, schemes: &[&str]) -> SolResult<String> {
+        let mut url = Url::parse(endpoint)
+            .map_err(|e| SolFailed::BadEndpoint(format!("{}: {}", endpoint, e)))?;
+
+        if !schemes.contains(&url.scheme()) {
+            return Err(SolFailed::BadEndpoint(format!(
+                "{}: scheme must be one of {:?}",
+                endpoint, schemes
+            )))
+        }
+
+        if let Some(token) = &self.auth_token {
+            match url.path_segments_mut() {
+                Ok(mut segments) => {
+                    segments.pop_if_empty().push(token);
+                }
+                Err(()) => return Err(SolFailed::BadEndpoint(endpoint.to_string())),
+            }
+        }
+
+        Ok(url.to_string())
+    }
+}
+
 pub struct SolClient {
     main_keypair: Keypair,
     // Subscriptions vector of pubkey
     subscriptions: Arc<Mutex<Vec<Pubkey>>>,
+    // Subscription states, keyed by the watched account
+    states: Arc<Mutex<FxHashMap<Pubkey, SubscriptionState>>>,
     notify_channel:
         (async_channel::Sender<TokenNotification>, async_channel::Receiver<TokenNotification>),
-    rpc_server: &'static str,
-    wss_server: &'static str,
+    error_channel:
+        (async_channel::Sender<SubscriptionFailure>, async_channel::Receiver<SubscriptionFailure>),
+    rpc_server: String,
+    wss_server: String,
+    deposits: DepositSubscriptionsPtr,
 }
 
 impl SolClient {
@@ -62,8 +178,14 @@ impl SolClient {
         cashier_wallet: Arc<CashierDb>,
         network: &str,
         keypair_path: &str,
+        endpoints: &SolEndpoints,
+        deposits: DepositSubscriptionsPtr,
     ) -> Result<Arc<Self>> {
         let notify_channel = async_channel::unbounded();
+        let error_channel = async_channel::unbounded();
+
+        // Validate the endpoints before touching the wallet
+        let (rpc_server, wss_server) = endpoints.resolve(network)?;
 
         let main_keypair: SolKeypair;
 
@@ -97,20 +219,18 @@ impl SolClient {
 
         info!(target: "SOL BRIDGE", "Main SOL wallet pubkey: {:?}", &main_keypair.0.pubkey());
 
-        let (rpc_server, wss_server) = match network {
-            "mainnet" => ("https://api.mainnet-beta.solana.com", "wss://api.devnet.solana.com"),
-            "devnet" => ("https://api.devnet.solana.com", "wss://api.devnet.solana.com"),
-            "testnet" => ("https://api.testnet.solana.com", "wss://api.testnet.solana.com"),
-            "localhost" => ("http://localhost:8899", "ws://localhost:8900"),
-            _ => return Err(Error::UnsupportedCoinNetwork),
-        };
+        // Don't log the URLs, they might contain an auth token
+        debug!(target: "SOL BRIDGE", "Using {} cluster", network);
 
         Ok(Arc::new(Self {
             main_keypair: main_keypair.0,
             subscriptions: Arc::new(Mutex::new(Vec::new())),
+            states: Arc::new(Mutex::new(FxHashMap::default())),
             notify_channel,
+            error_channel,
             rpc_server,
             wss_server,
+            deposits,
         }))
     }
 
@@ -125,20 +245,139 @@ impl SolClient {
         Ok(main_sol_balance > required_funds)
     }
 
-    async fn handle_subscribe_request(
+    /// Receiver for subscriptions that failed for good
+    pub fn get_error_notifier(&self) -> async_channel::Receiver<SubscriptionFailure> {
+        self.error_channel.1.clone()
+    }
+
+    /// Current state of the subscription watching the given account
+    pub async fn subscription_state(&self, pubkey: &Pubkey) -> Option<SubscriptionState> {
+        self.states.lock().await.get(pubkey).cloned()
+    }
+
+    /// States of all the subscriptions we know about
+    pub async fn subscription_states(&self) -> Vec<(Pubkey, SubscriptionState)> {
+        self.states.lock().await.iter().map(|(k, v)| (*k, v.clone())).collect()
+    }
+
+    async fn set_state(&self, pubkey: &Pubkey, state: SubscriptionState) {
+        self.states.lock().await.insert(*pubkey, state);
+    }
+
+    /// Watch the deposit account of `keypair` in a supervised task.
+    async fn spawn_subscription(
         self: Arc<Self>,
+        executor: &Executor<'_>,
+        supervisor: &SupervisorPtr,
         keypair: Keypair,
         drk_pub_key: PublicKey,
         mint: Option<Pubkey>,
+    ) {
+        let address = keypair.pubkey().to_string();
+        let token = mint.map_or(SOL_NATIVE_TOKEN_ID.to_string(), |m| m.to_string());
+        if !self.deposits.register(NetworkName::Solana, &address, &token, drk_pub_key).await {
+            return
+        }
+
+        let name = format!("sol deposit {}", address);
+        let keypair = Arc::new(keypair);
+        supervisor.spawn(executor, &name, RestartPolicy::Never, move || {
+            let request = self.clone().supervise_subscription(keypair.clone(), drk_pub_key, mint);
+            let (deposits, address) = (self.deposits.clone(), address.clone());
+            async move {
+                let result = request.await;
+                if let Err(e) = &result {
+                    deposits.fail(&address, &e.to_string()).await;
+                }
+                deposits.remove(&address).await;
+                result
+            }
+        });
+    }
+
+    /// Run a subscription until the deposit is handled, resubscribing with
+    /// exponential backoff when the connection to the cluster fails.
+    /// Failures that can't be retried are sent on the error channel.
+    async fn supervise_subscription(
+        self: Arc<Self>,
+        keypair: Arc<Keypair>,
+        drk_pub_key: PublicKey,
+        mint: Option<Pubkey>,
+    ) -> Result<()> {
+        let pubkey = watched_account(&keypair, mint.as_ref());
+
+        // Another task is already watching this account
+        if self.subscriptions.lock().await.contains(&pubkey) {
+            return Ok(())
+        }
+
+        let mut progress = SubscriptionProgress::default();
+        let mut attempt = 0;
+
+        let error = loop {
+            self.set_state(&pubkey, SubscriptionState::Connecting).await;
+
+            let result = self
+                .clone()
+                .handle_subscribe_request(&keypair, drk_pub_key, mint, &mut progress)
+                .await;
+
+            let e = match result {
+                Ok(()) => {
+                    self.set_state(&pubkey, SubscriptionState::Completed).await;
+                    return Ok(())
+                }
+                Err(e) => e,
+            };
+
+            if !e.is_transient() || progress.notified || attempt >= SUBSCRIPTION_MAX_RETRIES {
+                break e
+            }
+
+            attempt += 1;
+            let backoff = min(1 << (attempt - 1), SUBSCRIPTION_MAX_BACKOFF);
+            warn!(
+                target: "SOL BRIDGE SUBSCRIPTION",
+                "Subscription for {} dropped: {}. Retrying in {}s ({}/{})",
+                pubkey, e, backoff, attempt, SUBSCRIPTION_MAX_RETRIES
+            );
+
+            // Make sure the retry doesn't see a stale subscription
+            self.remove_subscription(&pubkey).await;
+
+            let state = SubscriptionState::Retrying { attempt, error: e.to_string() };
+            self.set_state(&pubkey, state).await;
+            sleep(backoff).await;
+        };
+
+        error!(target: "SOL BRIDGE SUBSCRIPTION", "Subscription for {} failed: {}", pubkey, error);
+        self.remove_subscription(&pubkey).await;
+        self.set_state(&pubkey, SubscriptionState::Failed(error.to_string())).await;
+
+        let failure = SubscriptionFailure {
+            deposit_address: keypair.pubkey().to_string(),
+            drk_pub_key,
+            error: error.to_string(),
+        };
+
+        if let Err(e) = self.error_channel.0.send(failure).await {
+            error!(target: "SOL BRIDGE SUBSCRIPTION", "Failed reporting failure: {}", e);
+        }
+
+        Err(error.into())
+    }
+
+    async fn handle_subscribe_request(
+        self: Arc<Self>,
+        keypair: &Keypair,
+        drk_pub_key: PublicKey,
+        mint: Option<Pubkey>,
+        progress: &mut SubscriptionProgress,
     ) -> SolResult<()> {
         trace!(target: "SOL BRIDGE", "handle_subscribe_request()");
 
         // Derive token pubkey if mint was provided.
-        let pubkey = if mint.is_some() {
-            get_associated_token_address(&keypair.pubkey(), &mint.unwrap())
-        } else {
-            keypair.pubkey()
-        };
+        let pubkey = watched_account(keypair, mint.as_ref());
 
         if mint.is_some() {
             debug!(target: "SOL BRIDGE", "Got subscribe request for SPL token");
@@ -156,28 +395,22 @@ impl SolClient {
 
         let rpc = RpcClient::new(self.rpc_server.to_string());
 
-        // Fetch the current balance.
-        let (prev_balance, decimals) = if mint.is_none() {
-            (rpc.get_balance(&pubkey).map_err(SolFailed::from)?, 9)
-        } else {
-            let mint = mint.unwrap();
-            match get_account_token_balance(&rpc, &pubkey, &mint) {
-                Ok(v) => v,
-                Err(_) => {
-                    let (exists, decimals) = account_is_initialized_mint(&rpc, &mint);
-                    if !exists {
-                        debug!("Could not figure out the number of decimals in SPL token");
-                        return Err(SolFailed::MintIsNotValid(mint.to_string()))
-                    }
-                    (0, decimals)
-                }
+        // Fetch the current balance. On retries, keep the one from the
+        // first attempt so a deposit made while we were away is noticed.
+        let resubscribing = progress.prev_balance.is_some();
+        let (prev_balance, decimals) = match progress.prev_balance {
+            Some(v) => v,
+            None => {
+                let v = self.fetch_balance(&rpc, &pubkey, mint.as_ref())?;
+                progress.prev_balance = Some(v);
+                v
             }
         };
 
         // WebSocket connection
         let builder = native_tls::TlsConnector::builder();
         let tls = TlsConnector::from(builder);
-        let (stream, _) = websockets::connect(self.wss_server, tls).await?;
+        let (stream, _) = websockets::connect(&self.wss_server, tls).await?;
         let (mut write, mut read) = stream.split();
 
         // Subscription request build
@@ -196,12 +429,10 @@ impl SolClient {
         let mut sub_id: i64 = 0;
 
         // The balance we are going to receive from the JSONRPC notification
-        let cur_balance: u64;
+        let mut cur_balance: u64 = prev_balance;
 
         let ping_payload: Vec<u8> = vec![42, 33, 31, 42];
 
-        let iter_interval = 1;
-        let mut sub_iter = 0;
 
         loop {
             let message = read
@@ -210,13 +441,11 @@ impl SolClient {
                 .ok_or_else(|| Error::TungsteniteError("No more messages".to_string()))??;
 
             if let Message::Pong(_) = message.clone() {
-                if sub_iter > 60 * 10 {
-                    // 10 minutes
+                if let Err(e) = self.deposits.check(&keypair.pubkey().to_string()).await {
                     self.unsubscribe(&mut write, &pubkey, &sub_id).await?;
-                    return Err(SolFailed::RpcError(format!("Deposit for {:?} expired", pubkey)))
+                    return Err(e.into())
                 }
-                sub_iter += iter_interval;
-                sleep(iter_interval).await;
+                sleep(1).await;
                 write.send(Message::Ping(ping_payload.clone())).await?;
                 continue
             };
@@ -226,7 +455,20 @@ impl SolClient {
                     // ACK
                     debug!(target: "SOLANA RPC", "<-- {}", serde_json::to_string(&r)?);
                     self.subscriptions.lock().await.push(pubkey);
-                    sub_id = r.result.as_i64().unwrap();
+                    self.set_state(&pubkey, SubscriptionState::Watching).await;
+                    sub_id = r.result.as_i64().ok_or_else(|| {
+                        SolFailed::RpcError("Subscription id is not a number".into())
+                    })?;
+
+                    // The account won't notify us about changes that
+                    // happened before we resubscribed.
+                    if resubscribing {
+                        let (balance, _) = self.fetch_balance(&rpc, &pubkey, mint.as_ref())?;
+                        if balance != prev_balance {
+                            cur_balance = balance;
+                            break
+                        }
+                    }
 
                     // Start sending pings
                     write.send(Message::Ping(ping_payload.clone())).await?;
@@ -242,15 +484,17 @@ impl SolClient {
                     debug!(target: "SOLANA RPC", "Got WebSocket notification");
                     let params = n.params["result"]["value"].clone();
 
-                    if mint.is_some() {
-                        cur_balance = params["data"]["parsed"]["info"]["tokenAmount"]["amount"]
+                    let balance = if mint.is_some() {
+                        params["data"]["parsed"]["info"]["tokenAmount"]["amount"]
                             .as_str()
-                            .unwrap()
-                            .parse()
-                            .map_err(Error::from)?;
+                            .and_then(|v| v.parse().ok())
                     } else {
-                        cur_balance = params["lamports"].as_u64().unwrap();
-                    }
+                        params["lamports"].as_u64()
+                    };
+
+                    cur_balance = balance.ok_or_else(|| {
+                        SolFailed::Notification("Account notification has no balance".into())
+                    })?;
                     break
                 }
             }
@@ -267,57 +511,157 @@ impl SolClient {
 
         let amnt = cur_balance - prev_balance;
 
+        // The depositor binds the deposit to their darkfi address with a
+        // memo instruction, which the cashier checks before minting. A
+        // deposit that isn't bound to it won't be minted, so it's left on
+        // the deposit account rather than swept into the main wallet.
+        let (memo, sender) = self.fetch_deposit(&rpc, &pubkey, &drk_pub_key)?;
+        let memo = DepositMemo::Found(memo);
+        let sweep = match memo.verify(&drk_pub_key) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(target: "SOL BRIDGE", "Not sweeping deposit on {}: {}", pubkey, e);
+                false
+            }
+        };
+        let address = keypair.pubkey().to_string();
+        progress.notified = true;
+        self.deposits.received(&address).await;
+
         if mint.is_some() {
             let ui_amnt = amnt / u64::pow(10, decimals as u32);
 
             send_notification
                 .send(TokenNotification {
                     network: NetworkName::Solana,
+                    address: address.clone(),
                     token_id: generate_id2(&mint.unwrap().to_string(), &NetworkName::Solana)?,
                     drk_pub_key,
                     received_balance: amnt,
                     decimals: decimals as u16,
+                    memo,
+                    sender,
                 })
                 .await
                 .map_err(Error::from)?;
 
             info!(target: "SOL BRIDGE", "Received {} {:?} tokens", ui_amnt, mint.unwrap());
-            let _ = self.send_tok_to_main_wallet(&rpc, &mint.unwrap(), amnt, decimals, &keypair)?;
+            if sweep {
+                self.send_tok_to_main_wallet(&rpc, &mint.unwrap(), amnt, decimals, keypair)?;
+            }
         } else {
             let ui_amnt = lamports_to_sol(amnt);
 
             send_notification
                 .send(TokenNotification {
                     network: NetworkName::Solana,
+                    address: address.clone(),
                     token_id: generate_id2(SOL_NATIVE_TOKEN_ID, &NetworkName::Solana)?,
                     drk_pub_key,
                     received_balance: amnt,
                     decimals: decimals as u16,
+                    memo,
+                    sender,
                 })
                 .await
                 .map_err(Error::from)?;
 
             info!(target: "SOL BRIDGE", "Received {} SOL", ui_amnt);
-            let _ = self.send_sol_to_main_wallet(&rpc, amnt, &keypair)?;
+            if sweep {
+                self.send_sol_to_main_wallet(&rpc, amnt, keypair)?;
+            }
+        }
+
+        if sweep {
+            self.deposits.forwarded(&address).await;
         }
 
         Ok(())
     }
 
+    /// Fetch the balance and decimals of the watched account.
+    fn fetch_balance(
+        &self,
+        rpc: &RpcClient,
+        pubkey: &Pubkey,
+        mint: Option<&Pubkey>,
+    ) -> SolResult<(u64, u64)> {
+        match mint {
+            None => Ok((rpc.get_balance(pubkey).map_err(SolFailed::from)?, 9)),
+            Some(mint) => match get_account_token_balance(rpc, pubkey, mint) {
+                Ok(v) => Ok(v),
+                Err(_) => {
+                    let (exists, decimals) = account_is_initialized_mint(rpc, mint);
+                    if !exists {
+                        debug!("Could not figure out the number of decimals in SPL token");
+                        return Err(SolFailed::MintIsNotValid(mint.to_string()))
+                    }
+                    Ok((0, decimals))
+                }
+            },
+        }
+    }
+
+    /// Fetch the memo binding the deposit to `drk_pub_key`, and the fee
+    /// payer of the transaction carrying it, which sent the deposit. A
+    /// deposit can arrive in several transactions, and others can touch
+    /// the account after it, so all of its successful transactions are
+    /// searched for the memo, not only the latest. Without a match, the
+    /// latest transaction is reported.
+    fn fetch_deposit(
+        &self,
+        rpc: &RpcClient,
+        pubkey: &Pubkey,
+        drk_pub_key: &PublicKey,
+    ) -> SolResult<(Option<String>, Option<String>)> {
+        let expected = deposit_memo(drk_pub_key);
+        let signatures = rpc.get_signatures_for_address(pubkey)?;
+        let mut successful = signatures.iter().filter(|s| s.err.is_none());
+
+        let memo_of =
+            |s: &RpcConfirmedTransactionStatusWithSignature| s.memo.as_deref().and_then(parse_memo);
+        let bound = |s: &&RpcConfirmedTransactionStatusWithSignature| {
+            memo_of(s).map_or(false, |memo| memo.trim() == expected)
+        };
+        let deposit = match successful.clone().find(bound) {
+            Some(v) => v,
+            None => match successful.next() {
+                Some(v) => v,
+                None => return Ok((None, None)),
+            },
+        };
+        let memo = memo_of(deposit);
+
+        // Not knowing the sender only rules out a refund, so the deposit
+        // is minted all the same.
+        let params = json!([deposit.signature, "json"]);
+        let sender = match rpc.send::<Value>(RpcRequest::GetTransaction, params) {
+            Ok(tx) => tx["transaction"]["message"]["accountKeys"][0].as_str().map(String::from),
+            Err(e) => {
+                warn!(target: "SOL BRIDGE", "Failed fetching deposit {}: {}", deposit.signature, e);
+                None
+            }
+        };
+
+        Ok((memo, sender))
+    }
+
+    async fn remove_subscription(&self, pubkey: &Pubkey) {
+        let mut subscriptions = self.subscriptions.lock().await;
+        let index = subscriptions.iter().position(|p| p == pubkey);
+        if let Some(ind) = index {
+            trace!(target: "SOL BRIDGE", "Removing subscription from list");
+            subscriptions.remove(ind);
+        }
+    }
+
     async fn unsubscribe(
         self: Arc<Self>,
         write: &mut futures::stream::SplitSink<WsStream, tungstenite::Message>,
         pubkey: &Pubkey,
         sub_id: &i64,
     ) -> Result<()> {
-        {
-            let mut subscriptions = self.subscriptions.lock().await;
-            let index = subscriptions.iter().position(|p| p == pubkey);
-            if let Some(ind) = index {
-                trace!(target: "SOL BRIDGE", "Removing subscription from list");
-                subscriptions.remove(ind);
-            }
-        }
+        self.remove_subscription(pubkey).await;
 
         let unsubscription = jsonrpc::request(json!("accountUnsubscribe"), json!([sub_id]));
 
@@ -436,13 +780,32 @@ impl SolClient {
 
 #[async_trait]
 impl NetworkClient for SolClient {
+    fn network(&self) -> NetworkName {
+        NetworkName::Solana
+    }
+
+    fn native_token_id(&self) -> &'static str {
+        SOL_NATIVE_TOKEN_ID
+    }
+
     async fn subscribe(
         self: Arc<Self>,
         drk_pub_key: PublicKey,
         mint_address: Option<String>,
+        tag: Option<String>,
         executor: Arc<Executor<'_>>,
+        supervisor: SupervisorPtr,
     ) -> Result<TokenSubscribtion> {
-        let keypair = SolKeypair(Keypair::new());
+        let keypair = match tag {
+            Some(tag) => {
+                // The first half of the keypair bytes is the secret key
+                let seed = deposit_key_seed(&self.main_keypair.to_bytes()[..32], &tag);
+                let keypair = keypair_from_seed(&seed)
+                    .map_err(|e| SolFailed::DecodeAndEncodeError(e.to_string()))?;
+                SolKeypair(keypair)
+            }
+            None => SolKeypair(Keypair::new()),
+        };
 
         let public_key = keypair.0.pubkey().to_string();
         let private_key = serialize(&keypair);
@@ -456,14 +819,7 @@ impl NetworkClient for SolClient {
             return Err(Error::from(SolFailed::MainAccountNotEnoughValue))
         }
 
-        executor
-            .spawn(async move {
-                let result = self.handle_subscribe_request(keypair.0, drk_pub_key, mint).await;
-                if let Err(e) = result {
-                    error!(target: "SOL BRIDGE SUBSCRIPTION","{}", e.to_string());
-                }
-            })
-            .detach();
+        self.spawn_subscription(&executor, &supervisor, keypair.0, drk_pub_key, mint).await;
 
         Ok(TokenSubscribtion { private_key, public_key })
     }
@@ -476,6 +832,7 @@ impl NetworkClient for SolClient {
         drk_pub_key: PublicKey,
         mint_address: Option<String>,
         executor: Arc<Executor<'_>>,
+        supervisor: SupervisorPtr,
     ) -> Result<String> {
         let keypair: Keypair = deserialize::<SolKeypair>(&private_key)?.0;
 
@@ -489,14 +846,7 @@ impl NetworkClient for SolClient {
             return Err(Error::from(SolFailed::MainAccountNotEnoughValue))
         }
 
-        executor
-            .spawn(async move {
-                let result = self.handle_subscribe_request(keypair, drk_pub_key, mint).await;
-                if let Err(e) = result {
-                    error!(target: "SOL BRIDGE SUBSCRIPTION","{}", e.to_string());
-                }
-            })
-            .detach();
+        self.spawn_subscription(&executor, &supervisor, keypair, drk_pub_key, mint).await;
 
         Ok(public_key)
     }
@@ -505,12 +855,47 @@ impl NetworkClient for SolClient {
         Ok(self.notify_channel.1.clone())
     }
 
+    async fn estimated_fee(
+        self: Arc<Self>,
+        _mint: Option<String>,
+        amount: u64,
+    ) -> Result<FeeEstimate> {
+        let rpc = RpcClient::new(self.rpc_server.to_string());
+        let main_pubkey = self.main_keypair.pubkey();
+
+        // Fees only depend on the signatures, so price the same
+        // message `send` builds, addressed back to the main wallet.
+        let ix = system_instruction::transfer(&main_pubkey, &main_pubkey, amount);
+        let mut message = SolMessage::new(&[ix], Some(&main_pubkey));
+        message.recent_blockhash = rpc.get_latest_blockhash().map_err(SolFailed::from)?;
+
+        let fee = rpc.get_fee_for_message(&message).map_err(SolFailed::from)?;
+
+        Ok(FeeEstimate { network: NetworkName::Solana, fee, decimals: 9 })
+    }
+
+    async fn main_wallet_balance(
+        self: Arc<Self>,
+        mint: Option<String>,
+    ) -> Result<MainWalletBalance> {
+        let rpc = RpcClient::new(self.rpc_server.to_string());
+        let mint = self.check_mint_address(mint)?;
+
+        let account = watched_account(&self.main_keypair, mint.as_ref());
+        let (amount, decimals) = self.fetch_balance(&rpc, &account, mint.as_ref())?;
+
+        Ok(MainWalletBalance {
+            address: self.main_keypair.pubkey().to_string(),
+            balance: truncate(amount, 8, decimals as u16)?,
+        })
+    }
+
     async fn send(
         self: Arc<Self>,
         address: Vec<u8>,
         mint: Option<String>,
         amount: u64,
-    ) -> Result<()> {
+    ) -> Result<String> {
         debug!(target: "SOL BRIDGE", "start sending {} sol", lamports_to_sol(amount) );
 
         let rpc = RpcClient::new(self.rpc_server.to_string());
@@ -535,13 +920,22 @@ impl NetworkClient for SolClient {
         let mut tx = Transaction::new_with_payer(&[instruction], Some(&self.main_keypair.pubkey()));
         let bhq = BlockhashQuery::default();
         match bhq.get_blockhash(&rpc, rpc.commitment()) {
-            Err(_) => panic!("Couldn't connect to RPC"),
+            Err(_) => return Err(SolFailed::RpcError("Couldn't connect to RPC".into())),
             Ok(v) => tx.sign(&[&self.main_keypair], v),
         }
 
-        let _signature = rpc.send_and_confirm_transaction(&tx).map_err(SolFailed::from)?;
+        let signature = rpc.send_and_confirm_transaction(&tx).map_err(SolFailed::from)?;
 
-        Ok(())
+        Ok(signature.to_string())
+    }
+}
+
+/// The account watched for deposits: the deposit wallet itself for native
+/// SOL, or its associated token account for SPL tokens.
+fn watched_account(keypair: &Keypair, mint: Option<&Pubkey>) -> Pubkey {
+    match mint {
+        Some(mint) => get_associated_token_address(&keypair.pubkey(), mint),
+        None => keypair.pubkey(),
     }
 }
 
@@ -560,6 +954,22 @@ pub fn get_account_token_balance(
     Ok((token_data.amount, mint_data.decimals as u64))
 }
 
+/// The RPC reports memos as `[len] text`, with multiple memos separated
+/// by `; `. Returns the text of the first one.
+fn parse_memo(raw: &str) -> Option<String> {
+    let first = raw.split("; ").next()?;
+    let text = match first.strip_prefix('[') {
+        Some(rest) => rest.split_once("] ")?.1,
+        None => first,
+    };
+
+    if text.is_empty() {
+        return None
+    }
+
+    Some(text.to_string())
+}
+
 /// Check if given account is a valid token mint
 pub fn account_is_initialized_mint(rpc: &RpcClient, mint: &Pubkey) -> (bool, u64) {
     match rpc.get_token_supply(mint) {
@@ -647,6 +1057,8 @@ pub enum SolFailed {
     ParseError(#[from] solana_sdk::pubkey::ParsePubkeyError),
     #[error("Signature Error: `{0}`")]
     Signature(String),
+    #[error("Invalid endpoint: `{0}`")]
+    BadEndpoint(String),
     #[error(transparent)]
     Darkfi(#[from] darkfi::error::Error),
 }
@@ -657,4 +1069,20 @@ impl From<SolFailed> for Error {
     }
 }
 
+impl SolFailed {
+    /// Whether the error came from the connection to the cluster, in
+    /// which case subscribing again may succeed.
+    fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            SolFailed::WebSocketError(_) |
+                SolFailed::SolClientError(_) |
+                SolFailed::RpcError(_) |
+                SolFailed::Darkfi(Error::TungsteniteError(_)) |
+                SolFailed::Darkfi(Error::Io(_)) |
+                SolFailed::Darkfi(Error::NoUrlFound)
+        )
+    }
+}
+
 pub type SolResult<T> = std::result::Result<T, SolFailed>;
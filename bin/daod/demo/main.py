@@ -2,6 +2,9 @@ import sys
 from classnamespace import ClassNamespace
 
 import crypto, money
+from tx import TransactionBuilder, encode
+
+DAO_CONTRACT_ID = b"0xdao_ruleset"
 
 class MoneyState:
 
@@ -53,6 +56,8 @@ class ProposerTxBuilder:
         self.inputs = []
         self.proposal = proposal
         self.all_dao_bullas = all_dao_bullas
+        # Set by build(), in the order of ProposerTx.signature_publics()
+        self.signature_secrets = []
 
         self.ec = ec
 
@@ -100,6 +105,8 @@ class ProposerTxBuilder:
             self.proposal.serial,
             self.proposal.token_id,
             self.proposal.blind,
+            self.proposal.start_slot,
+            self.proposal.end_slot,
             # Merkle witness
             self.all_dao_bullas,
             self.ec
@@ -112,10 +119,10 @@ class ProposerTxBuilder:
         tx.note.enc_bulla_blind = enc_bulla_blind
         tx.note.proposal = self.proposal
 
-        signature_secrets = []
+        self.signature_secrets = []
         for input, value_blind in zip(self.inputs, input_value_blinds):
             signature_secret = self.ec.random_scalar()
-            signature_secrets.append(signature_secret)
+            self.signature_secrets.append(signature_secret)
 
             tx_input = ClassNamespace()
             tx_input.__name__ = "TransactionInput"
@@ -127,11 +134,7 @@ class ProposerTxBuilder:
             tx_input.revealed = tx_input.proof.get_revealed()
             tx.inputs.append(tx_input)
 
-        unsigned_tx_data = tx.partial_encode()
-        for (input, signature_secret) in zip(tx.inputs, signature_secrets):
-            signature = crypto.sign(unsigned_tx_data, signature_secret, self.ec)
-            input.signature = signature
-
+        # Signed by the TransactionBuilder this call is added to
         return tx
 
 class ProposerTx:
@@ -144,8 +147,11 @@ class ProposerTx:
         self.ec = ec
 
     def partial_encode(self):
-        # There is no cake
-        return b"hello"
+        inputs = [input.revealed for input in self.inputs]
+        return encode(inputs, self.dao.revealed, self.note)
+
+    def signature_publics(self):
+        return [input.revealed.signature_public for input in self.inputs]
 
     def verify(self):
         if not self._check_value_commits():
@@ -157,13 +163,6 @@ class ProposerTx:
         if not self._verify_token_commitments():
             return False, "token ID mismatch"
 
-        unsigned_tx_data = self.partial_encode()
-        for input in self.inputs:
-            public = input.revealed.signature_public
-            if not crypto.verify(unsigned_tx_data, input.signature,
-                                 public, self.ec):
-                return False
-
         return True, None
 
     def _check_value_commits(self):
@@ -261,6 +260,7 @@ class ProposerTxDaoProof:
                  token_blind, enc_bulla_blind,
                  proposal_dest, proposal_amount, proposal_serial,
                  proposal_token_id, proposal_blind,
+                 proposal_start_slot, proposal_end_slot,
                  all_dao_bullas, ec):
         self.total_value = total_value
         self.total_value_blinds = total_value_blinds
@@ -277,6 +277,8 @@ class ProposerTxDaoProof:
         self.proposal_serial = proposal_serial
         self.proposal_token_id = proposal_token_id
         self.proposal_blind = proposal_blind
+        self.proposal_start_slot = proposal_start_slot
+        self.proposal_end_slot = proposal_end_slot
         self.all_dao_bullas = all_dao_bullas
         self.ec = ec
 
@@ -311,8 +313,13 @@ class ProposerTxDaoProof:
             self.proposal_serial,
             self.proposal_token_id,
             self.proposal_blind,
+            self.proposal_start_slot,
+            self.proposal_end_slot,
             bulla
         )
+        # The voting window is public so everyone can enforce it
+        revealed.start_slot = self.proposal_start_slot
+        revealed.end_slot = self.proposal_end_slot
         # The merkle root
         revealed.all_dao_bullas = self.all_dao_bullas
         return revealed
@@ -348,6 +355,8 @@ class ProposerTxDaoProof:
             revealed.token_commit == public.token_commit,
             revealed.enc_bulla == public.enc_bulla,
             revealed.proposal_bulla == public.proposal_bulla,
+            revealed.start_slot == public.start_slot,
+            revealed.end_slot == public.end_slot,
             revealed.all_dao_bullas == public.all_dao_bullas
         ])
 
@@ -356,6 +365,8 @@ class VoteTxBuilder:
     def __init__(self, ec):
         self.inputs = []
         self.vote_option = None
+        # Set by build(), in the order of VoteTx.signature_publics()
+        self.signature_secrets = []
 
         self.ec = ec
 
@@ -378,14 +389,14 @@ class VoteTxBuilder:
         vote_option_blind = self.ec.random_base()
 
         total_value, total_blind = 0, 0
-        signature_secrets = []
+        self.signature_secrets = []
         for input in self.inputs:
             value_blind = self.ec.random_scalar()
             total_blind = (total_blind + value_blind) % self.ec.order
             total_value = (total_value + input.note.value) % self.ec.order
 
             signature_secret = self.ec.random_scalar()
-            signature_secrets.append(signature_secret)
+            self.signature_secrets.append(signature_secret)
 
             tx_input = ClassNamespace()
             tx_input.__name__ = "TransactionInput"
@@ -425,11 +436,7 @@ class VoteTxBuilder:
         tx.note.vote_blind = vote_blind
         tx.note.vote_option_blind = vote_option_blind
 
-        unsigned_tx_data = tx.partial_encode()
-        for (input, signature_secret) in zip(tx.inputs, signature_secrets):
-            signature = crypto.sign(unsigned_tx_data, signature_secret, self.ec)
-            input.signature = signature
-
+        # Signed by the TransactionBuilder this call is added to
         return tx
 
 class VoteBurnProof:
@@ -550,8 +557,11 @@ class VoteTx:
         self.ec = ec
 
     def partial_encode(self):
-        # There is no cake
-        return b"hello"
+        inputs = [input.revealed for input in self.inputs]
+        return encode(inputs, self.vote.revealed, self.note)
+
+    def signature_publics(self):
+        return [input.revealed.signature_public for input in self.inputs]
 
     def verify(self):
         if not self._check_value_commits():
@@ -623,6 +633,12 @@ class Dao:
         self.mint_proof = mint_proof
         self.ec = ec
 
+    def partial_encode(self):
+        return encode(self.revealed)
+
+    def signature_publics(self):
+        return []
+
     def verify(self):
         if not self.mint_proof.verify(self.revealed):
             return False, "mint proof failed to verify"
@@ -665,12 +681,22 @@ class DaoMintProof:
 # Shared between DaoMint and DaoExec
 class DaoState:
 
-    def __init__(self):
+    def __init__(self, min_voting_period, exec_period):
         self.dao_bullas = set()
         self.proposals = set()
         # Closed proposals
         self.proposal_nullifiers = set()
 
+        # Proposals must leave at least this many slots for voting
+        self.min_voting_period = min_voting_period
+        # Passed proposals expire this many slots after voting ends
+        self.exec_period = exec_period
+        self.slot = 0
+
+    def set_slot(self, slot):
+        assert slot >= self.slot
+        self.slot = slot
+
     def is_valid_merkle(self, all_dao_bullas):
         return all_dao_bullas.issubset(self.dao_bullas)
 
@@ -760,6 +786,12 @@ class DaoExecBuilder:
 
 class DaoExecTx:
 
+    def partial_encode(self):
+        return encode(self.revealed)
+
+    def signature_publics(self):
+        return []
+
     def verify(self):
         if not self._check_proofs():
             return False, "proofs failed to verify"
@@ -828,10 +860,14 @@ class DaoExecProof:
             self.proposal.serial,
             self.proposal.token_id,
             self.proposal.blind,
+            self.proposal.start_slot,
+            self.proposal.end_slot,
             dao_bulla
         )
         revealed.proposal_nullifier = crypto.ff_hash(
             self.ec.p, self.proposal.serial)
+        # Used to check voting is over, and the proposal hasn't expired
+        revealed.end_slot = self.proposal.end_slot
 
         revealed.coin_0 = crypto.ff_hash(
             self.ec.p,
@@ -896,18 +932,29 @@ class DaoExecProof:
             self.proposal.serial,
             self.proposal.token_id,
             self.proposal.blind,
+            self.proposal.start_slot,
+            self.proposal.end_slot,
             dao_bulla
         )
         # This being true also implies the DAO is valid
         assert proposal_bulla in self.all_proposals
 
-        assert self.total_votes >= self.dao.quorum
+        #
+        #   total_votes >= quorum
+        #
+        if not self.total_votes >= self.dao.quorum:
+            return False
 
-        assert self.win_votes / self.total_votes >= self.dao.approval_ratio
+        #
+        #   win_votes / total_votes >= approval_ratio / 100
+        #
+        if not self.win_votes * 100 >= self.dao.approval_ratio * self.total_votes:
+            return False
 
         return all([
             revealed.all_proposals == public.all_proposals,
             revealed.proposal_nullifier == public.proposal_nullifier,
+            revealed.end_slot == public.end_slot,
             revealed.coin_0 == public.coin_0,
             revealed.coin_1 == public.coin_1,
             revealed.inputs_value_commit == public.inputs_value_commit,
@@ -915,7 +962,7 @@ class DaoExecProof:
             revealed.total_vote_commit == public.total_vote_commit,
         ])
 
-def dao_exec_state_transition(state, tx, pay_tx, ec):
+def dao_exec_state_transition(state, vote_state, tx, pay_tx, ec):
     is_verify, reason = tx.verify()
     if not is_verify:
         print(f"dao exec tx verify failed: {reason}", file=sys.stderr)
@@ -930,6 +977,23 @@ def dao_exec_state_transition(state, tx, pay_tx, ec):
         print(f"duplicate nullifier found", file=sys.stderr)
         return None
 
+    end_slot = tx.revealed.end_slot
+    if state.slot < end_slot:
+        print(f"voting has not ended", file=sys.stderr)
+        return None
+    if state.slot >= end_slot + state.exec_period:
+        print(f"proposal expired", file=sys.stderr)
+        return None
+
+    # The proof checks quorum and approval against these totals,
+    # so they must be the sums of the votes cast.
+    if tx.revealed.total_value_commit != vote_state.total_value_commit:
+        print(f"total votes don't match the votes cast", file=sys.stderr)
+        return None
+    if tx.revealed.total_vote_commit != vote_state.total_vote_commit:
+        print(f"winning votes don't match the votes cast", file=sys.stderr)
+        return None
+
     # Check the structure of the payment tx is correct
     if len(pay_tx.outputs) != 2:
         print(f"only 2 outputs allowed", file=sys.stderr)
@@ -970,15 +1034,37 @@ def proposal_state_transition(dao_state, gov_state, tx):
             print(f"invalid merkle root", file=sys.stderr)
             return None
 
+    start_slot = tx.dao.revealed.start_slot
+    end_slot = tx.dao.revealed.end_slot
+    if start_slot < dao_state.slot:
+        print(f"voting cannot start in the past", file=sys.stderr)
+        return None
+    if end_slot - start_slot < dao_state.min_voting_period:
+        print(f"voting period too short", file=sys.stderr)
+        return None
+
     update = ClassNamespace()
     update.proposal = tx.dao.revealed.proposal_bulla
+    update.start_slot = start_slot
+    update.end_slot = end_slot
     return update
 
 class VoteState:
 
-    def __init__(self):
+    def __init__(self, start_slot, end_slot, ec):
         self.votes = set()
         self.nullifiers = set()
+        # Votes are accepted in [start_slot, end_slot)
+        self.start_slot = start_slot
+        self.end_slot = end_slot
+        # Sums of the votes cast, checked when executing the proposal
+        self.total_value_commit = (0, 1, 0)
+        self.total_vote_commit = (0, 1, 0)
+
+        self.ec = ec
+
+    def is_open(self, slot):
+        return self.start_slot <= slot < self.end_slot
 
     def nullifier_exists(self, nullifier):
         return nullifier in self.nullifiers
@@ -986,8 +1072,16 @@ class VoteState:
     def apply(self, update):
         self.nullifiers = self.nullifiers.union(update.nullifiers)
         self.votes.add(update.vote)
+        self.total_value_commit = self.ec.add(self.total_value_commit,
+                                              update.vote)
+        self.total_vote_commit = self.ec.add(self.total_vote_commit,
+                                             update.vote_commit)
+
+def vote_state_transition(dao_state, vote_state, gov_state, tx):
+    if not vote_state.is_open(dao_state.slot):
+        print(f"voting is not open", file=sys.stderr)
+        return None
 
-def vote_state_transition(vote_state, gov_state, tx):
     for input in tx.inputs:
         if not gov_state.is_valid_merkle(input.revealed.all_coins):
             print(f"invalid merkle root", file=sys.stderr)
@@ -1010,14 +1104,34 @@ def vote_state_transition(vote_state, gov_state, tx):
     update = ClassNamespace()
     update.nullifiers = [input.revealed.nullifier for input in tx.inputs]
     update.vote = tx.vote.revealed.value_commit
+    update.vote_commit = tx.vote.revealed.vote_commit
     return update
 
+# Tx with a single call, built and signed by the call's builder
+def build_call_tx(contract_id, func_id, call_builder, ec):
+    call_data = call_builder.build()
+    builder = TransactionBuilder(ec)
+    builder.add_call(contract_id, func_id, call_data,
+                     call_builder.signature_secrets)
+    return builder.build()
+
+def tx_verify(tx):
+    is_verify, reason = tx.verify()
+    if not is_verify:
+        print(f"tx verify failed: {reason}", file=sys.stderr)
+    return is_verify
+
 def main(argv):
     ec = crypto.pallas_curve()
 
+    # Voting lasts at least 10 slots, and a passed proposal
+    # must be executed within 100 slots after that
+    dao_min_voting_period = 10
+    dao_exec_period = 100
+
     money_state = MoneyState()
     gov_state = MoneyState()
-    dao_state = DaoState()
+    dao_state = DaoState(dao_min_voting_period, dao_exec_period)
 
     # Money parameters
     money_initial_supply = 21000
@@ -1030,7 +1144,8 @@ def main(argv):
     # DAO parameters
     dao_proposer_limit = 110
     dao_quorum = 110
-    dao_approval_ratio = 2
+    # Percentage of the votes that must be yes
+    dao_approval_ratio = 50
 
     ################################################
     # Create the DAO bulla
@@ -1050,15 +1165,21 @@ def main(argv):
         dao_bulla_blind,
         ec
     )
+    dao_mint = builder.build()
+
+    builder = TransactionBuilder(ec)
+    builder.add_call(DAO_CONTRACT_ID, "mint", dao_mint, [])
     tx = builder.build()
+    if not tx_verify(tx):
+        return -1
 
     # Each deployment of a contract has a unique state
     # associated with it.
-    if (update := dao_state_transition(dao_state, tx)) is None:
+    if (update := dao_state_transition(dao_state, dao_mint)) is None:
         return -1
     dao_state.apply(update)
 
-    dao_bulla = tx.revealed.bulla
+    dao_bulla = dao_mint.revealed.bulla
 
     ################################################
     # Mint the initial supply of treasury token
@@ -1081,7 +1202,10 @@ def main(argv):
     user_data = dao_bulla
     builder.add_output(money_initial_supply, money_token_id, dao_public_key,
                        spend_hook, user_data)
-    tx = builder.build()
+    tx = build_call_tx(money.CONTRACT_ID, "send_payment", builder, ec)
+    if not tx_verify(tx):
+        return -1
+    tx = tx.func_calls[0].call_data
 
     # This state_transition function is the ruleset for anon payments
     if (update := money_state_transition(money_state, tx)) is None:
@@ -1142,7 +1266,10 @@ def main(argv):
                        b"0x0000", b"0x0000")
     builder.add_output(2000, gov_token_id, gov_public_3,
                        b"0x0000", b"0x0000")
-    tx = builder.build()
+    tx = build_call_tx(money.CONTRACT_ID, "send_payment", builder, ec)
+    if not tx_verify(tx):
+        return -1
+    tx = tx.func_calls[0].call_data
 
     # This state_transition function is the ruleset for anon payments
     if (update := money_state_transition(gov_state, tx)) is None:
@@ -1172,6 +1299,8 @@ def main(argv):
     # 5. structure of outputs
     #   output 0: value and address
     #   output 1: change address
+    # 6. votes are only cast between the proposal's start and end slots,
+    #    and it's executed within exec_period slots after the end
     ################################################
 
     ################################################
@@ -1194,6 +1323,9 @@ def main(argv):
     proposal.serial = ec.random_base()
     proposal.token_id = money_token_id
     proposal.blind = ec.random_base()
+    # Voting starts next slot
+    proposal.start_slot = dao_state.slot + 1
+    proposal.end_slot = proposal.start_slot + dao_min_voting_period
 
     # For vote to become valid, the proposer must prove
     # that they own more than proposer_limit number of gov tokens.
@@ -1210,7 +1342,10 @@ def main(argv):
     witness = gov_state.all_coins
     builder.add_input(witness, gov_secret_1, gov_user_1_note)
     builder.set_dao(dao)
-    tx = builder.build()
+    tx = build_call_tx(DAO_CONTRACT_ID, "propose", builder, ec)
+    if not tx_verify(tx):
+        return -1
+    tx = tx.func_calls[0].call_data
 
     # No state changes actually happen so ignore the update
     # We just verify the tx is correct basically.
@@ -1225,7 +1360,7 @@ def main(argv):
 
     # Lets the voting begin
     # Voters have access to the proposal and dao data
-    vote_state = VoteState()
+    vote_state = VoteState(update.start_slot, update.end_slot, ec)
     # We don't need to copy nullifier set because it is checked from gov_state
     # in vote_state_transition() anyway
 
@@ -1235,8 +1370,6 @@ def main(argv):
     #   2. both the MPC or users can unblind
 
     # TODO: bug if I vote then send money, then we can double vote
-    # TODO: all timestamps missing
-    #       - timelock (future voting starts in 2 days)
     # Fix: use nullifiers from money gov state only from
     # beginning of gov period
     # Cannot use nullifiers from before voting period
@@ -1245,9 +1378,17 @@ def main(argv):
     builder = VoteTxBuilder(ec)
     builder.add_input(witness, gov_secret_1, gov_user_1_note)
     builder.set_vote_option(1)
-    tx1 = builder.build()
+    tx1 = build_call_tx(DAO_CONTRACT_ID, "vote", builder, ec)
+    if not tx_verify(tx1):
+        return -1
+    tx1 = tx1.func_calls[0].call_data
+
+    # Voting hasn't started yet
+    assert vote_state_transition(dao_state, vote_state, gov_state, tx1) is None
+    dao_state.set_slot(proposal.start_slot)
 
-    if (update := vote_state_transition(vote_state, gov_state, tx1)) is None:
+    if (update := vote_state_transition(dao_state, vote_state, gov_state,
+                                        tx1)) is None:
         return -1
     vote_state.apply(update)
 
@@ -1257,9 +1398,13 @@ def main(argv):
     builder = VoteTxBuilder(ec)
     builder.add_input(witness, gov_secret_2, gov_user_2_note)
     builder.set_vote_option(0)
-    tx2 = builder.build()
+    tx2 = build_call_tx(DAO_CONTRACT_ID, "vote", builder, ec)
+    if not tx_verify(tx2):
+        return -1
+    tx2 = tx2.func_calls[0].call_data
 
-    if (update := vote_state_transition(vote_state, gov_state, tx2)) is None:
+    if (update := vote_state_transition(dao_state, vote_state, gov_state,
+                                        tx2)) is None:
         return -1
     vote_state.apply(update)
 
@@ -1269,9 +1414,13 @@ def main(argv):
     builder = VoteTxBuilder(ec)
     builder.add_input(witness, gov_secret_3, gov_user_3_note)
     builder.set_vote_option(1)
-    tx3 = builder.build()
+    tx3 = build_call_tx(DAO_CONTRACT_ID, "vote", builder, ec)
+    if not tx_verify(tx3):
+        return -1
+    tx3 = tx3.func_calls[0].call_data
 
-    if (update := vote_state_transition(vote_state, gov_state, tx3)) is None:
+    if (update := vote_state_transition(dao_state, vote_state, gov_state,
+                                        tx3)) is None:
         return -1
     vote_state.apply(update)
 
@@ -1353,28 +1502,25 @@ def main(argv):
     # by 0xdao_ruleset
     user_data_blind = ec.random_base()
 
-    builder = money.SendPaymentTxBuilder(ec)
+    pay_builder = money.SendPaymentTxBuilder(ec)
     witness = money_state.all_coins
-    builder.add_input(witness, dao_shared_secret, coin_note, user_data_blind)
+    pay_builder.add_input(witness, dao_shared_secret, coin_note,
+                          user_data_blind)
 
-    builder.add_output(1000, money_token_id, user_public,
-                       spend_hook=b"0x0000", user_data=b"0x0000")
+    pay_builder.add_output(1000, money_token_id, user_public,
+                           spend_hook=b"0x0000", user_data=b"0x0000")
     # Change
-    builder.add_output(coin_note.value - 1000, money_token_id, dao_public_key,
-                       spend_hook, user_data)
-
-    tx = builder.build()
+    pay_builder.add_output(coin_note.value - 1000, money_token_id,
+                           dao_public_key, spend_hook, user_data)
 
-    if (update := money_state_transition(money_state, tx)) is None:
-        return -1
-    money_state.apply(update)
+    pay_tx = pay_builder.build()
 
     # Now the spend_hook field specifies the function DaoExec
     # so the tx above must also be combined with a DaoExec tx
-    assert len(tx.inputs) == 1
+    assert len(pay_tx.inputs) == 1
     # At least one input has this field value which means the 0xdao_ruleset
     # is invoked.
-    input = tx.inputs[0]
+    input = pay_tx.inputs[0]
     assert input.revealed.spend_hook == b"0xdao_ruleset"
     assert (input.revealed.enc_user_data ==
         crypto.ff_hash(
@@ -1395,8 +1541,6 @@ def main(argv):
         dao_bulla_blind
     ) # DAO bulla
 
-    pay_tx = tx
-
     # execution proof
     # 1. total votes >= quorum
     # 2. win_votes / total_votes >= approval_ratio
@@ -1426,7 +1570,7 @@ def main(argv):
     pay_tx_coin_blind_0 = pay_tx.outputs[0].enc_note.coin_blind
     pay_tx_coin_blind_1 = pay_tx.outputs[1].enc_note.coin_blind
     pay_tx_input_value = coin_note.value
-    pay_tx_input_blinds = sum(builder.input_blinds) % ec.order
+    pay_tx_input_blinds = sum(pay_builder.input_blinds) % ec.order
 
     builder = DaoExecBuilder(
         proposal,
@@ -1444,14 +1588,35 @@ def main(argv):
         pay_tx_input_blinds,
         ec
     )
+    exec_tx = builder.build()
+
+    # The payment and the exec are signed together, in this order,
+    # so the payment can't be included in a tx without the exec.
+    builder = TransactionBuilder(ec)
+    builder.add_call(money.CONTRACT_ID, "send_payment", pay_tx,
+                     pay_builder.signature_secrets)
+    builder.add_call(DAO_CONTRACT_ID, "exec", exec_tx, [])
     tx = builder.build()
-    if (update := dao_exec_state_transition(dao_state, tx, pay_tx, ec)) is None:
+    if not tx_verify(tx):
+        return -1
+
+    # Voting is still open
+    assert dao_exec_state_transition(dao_state, vote_state,
+                                     exec_tx, pay_tx, ec) is None
+    dao_state.set_slot(proposal.end_slot)
+
+    # Both calls must succeed before either update is applied
+    if (money_update := money_state_transition(money_state, pay_tx)) is None:
+        return -1
+    if (dao_update := dao_exec_state_transition(dao_state, vote_state,
+                                                exec_tx, pay_tx, ec)) is None:
         return -1
-    dao_state.apply_exec_tx(update)
+    money_state.apply(money_update)
+    dao_state.apply_exec_tx(dao_update)
 
     # These checks are also run by the verifier
-    assert tx.revealed.total_value_commit == total_value_commit
-    assert tx.revealed.total_vote_commit == total_vote_commit
+    assert exec_tx.revealed.total_value_commit == total_value_commit
+    assert exec_tx.revealed.total_vote_commit == total_vote_commit
 
     return 0
 
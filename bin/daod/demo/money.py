@@ -1,5 +1,8 @@
 from classnamespace import ClassNamespace
-from crypto import ff_hash, pedersen_encrypt, sign, verify
+from crypto import ff_hash, pedersen_encrypt
+from tx import encode
+
+CONTRACT_ID = b"0xmoney"
 
 # Tx representing send_payment() contract call
 class SendPaymentTxBuilder:
@@ -10,6 +13,8 @@ class SendPaymentTxBuilder:
         self.outputs = []
         self.input_blinds = []
         self.output_blinds = []
+        # Set by build(), in the order of SendPaymentTx.signature_publics()
+        self.signature_secrets = []
 
         self.ec = ec
 
@@ -61,13 +66,14 @@ class SendPaymentTxBuilder:
             tx.clear_inputs.append(tx_clear_input)
 
         self.input_blinds = []
-        signature_secrets = []
+        self.signature_secrets = [input.signature_secret
+                                  for input in self.clear_inputs]
         for input in self.inputs:
             value_blind = self.ec.random_scalar()
             self.input_blinds.append(value_blind)
 
             signature_secret = self.ec.random_scalar()
-            signature_secrets.append(signature_secret)
+            self.signature_secrets.append(signature_secret)
 
             tx_input = ClassNamespace()
             tx_input.__name__ = "TransactionInput"
@@ -116,15 +122,7 @@ class SendPaymentTxBuilder:
 
             tx.outputs.append(tx_output)
 
-        unsigned_tx_data = tx.partial_encode()
-        for (input, info) in zip(tx.clear_inputs, self.clear_inputs):
-            secret = info.signature_secret
-            signature = sign(unsigned_tx_data, secret, self.ec)
-            input.signature = signature
-        for (input, signature_secret) in zip(tx.inputs, signature_secrets):
-            signature = sign(unsigned_tx_data, signature_secret, self.ec)
-            input.signature = signature
-
+        # Signed by the TransactionBuilder this call is added to
         return tx
 
 # Transaction representing Money::send_payment() function call
@@ -138,8 +136,19 @@ class SendPaymentTx:
         self.ec = ec
 
     def partial_encode(self):
-        # There is no cake
-        return b"hello"
+        clear_inputs = [
+            (input.value, input.token_id, input.value_blind,
+             input.token_blind, input.signature_public)
+            for input in self.clear_inputs
+        ]
+        inputs = [input.revealed for input in self.inputs]
+        outputs = [(output.revealed, output.enc_note)
+                   for output in self.outputs]
+        return encode(clear_inputs, inputs, outputs)
+
+    def signature_publics(self):
+        return ([input.signature_public for input in self.clear_inputs] +
+                [input.revealed.signature_public for input in self.inputs])
 
     def verify(self):
         if not self._check_value_commits():
@@ -151,16 +160,6 @@ class SendPaymentTx:
         if not self._verify_token_commitments():
             return False, "token ID mismatch"
 
-        unsigned_tx_data = self.partial_encode()
-        for input in self.clear_inputs:
-            public = input.signature_public
-            if not verify(unsigned_tx_data, input.signature, public, self.ec):
-                return False
-        for input in self.inputs:
-            public = input.revealed.signature_public
-            if not verify(unsigned_tx_data, input.signature, public, self.ec):
-                return False
-
         return True, None
 
     def _check_value_commits(self):
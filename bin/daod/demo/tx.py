@@ -0,0 +1,149 @@
+import hashlib
+
+from classnamespace import ClassNamespace
+from crypto import sign, verify
+
+# Domain tag of the payloads signed by the inputs of a call, so they
+# can't be passed off as signatures of anything else. The node signs the
+# same fields, see src/tx/signing.rs.
+SIGNATURE_DOMAIN = b"DarkFi_TxCallSignature"
+
+def encode(*values):
+    data = bytearray()
+    _encode_into(data, values)
+    return bytes(data)
+
+# Serializes public values the same way every time, so the signer and
+# verifier agree on the signed bytes.
+def _encode_into(data, value):
+    match value:
+        case None:
+            data += b"\x00"
+        case bool():
+            data += b"\x01" if value else b"\x00"
+        case int():
+            data += value.to_bytes(32, byteorder="little")
+        case bytes():
+            data += len(value).to_bytes(8, byteorder="little")
+            data += value
+        case str():
+            _encode_into(data, value.encode())
+        case list() | tuple():
+            data += len(value).to_bytes(8, byteorder="little")
+            for item in value:
+                _encode_into(data, item)
+        case set() | frozenset():
+            # Sets have no order, so sort the encoded items
+            items = sorted(encode(item) for item in value)
+            data += len(items).to_bytes(8, byteorder="little")
+            for item in items:
+                data += item
+        case ClassNamespace():
+            # __name__ is only a label for debugging
+            keys = sorted(key for key in value.__dict__
+                          if not key.startswith("__"))
+            data += len(keys).to_bytes(8, byteorder="little")
+            for key in keys:
+                _encode_into(data, key)
+                _encode_into(data, value.__dict__[key])
+        case _:
+            raise Exception(f"unknown encode arg '{value}' type: {type(value)}")
+
+# A call to one function of a contract.
+# call_data is the tx built for that function, e.g. SendPaymentTx.
+# It must have:
+#   partial_encode(): its public data, without signatures
+#   signature_publics(): the keys that must sign the tx, in input order
+# Its proofs are checked by the contract's state transition function.
+class FuncCall:
+
+    def __init__(self, contract_id, func_id, call_data):
+        self.contract_id = contract_id
+        self.func_id = func_id
+        self.call_data = call_data
+
+    def partial_encode(self):
+        return encode(self.contract_id, self.func_id, self.call_data_digest())
+
+    def call_data_digest(self):
+        return hashlib.sha256(self.call_data.partial_encode()).digest()
+
+class Transaction:
+
+    def __init__(self, ec):
+        self.func_calls = []
+        # One list per call, one signature per signature public of the call
+        self.signatures = []
+
+        self.ec = ec
+
+    def partial_encode(self):
+        return encode([call.partial_encode() for call in self.func_calls])
+
+    # What the inputs of call i sign, for every contract:
+    #   domain tag, contract ID, function ID, call index,
+    #   digest of the call data, digest of the whole tx
+    # The tx digest covers all the calls in order, so calls can't be
+    # taken out of a tx or reordered. The contract and function IDs keep
+    # a signature for one function from being valid for another one
+    # with the same call data.
+    def signing_payload(self, i):
+        call = self.func_calls[i]
+        tx_digest = hashlib.sha256(self.partial_encode()).digest()
+        return encode(SIGNATURE_DOMAIN, call.contract_id, call.func_id, i,
+                      call.call_data_digest(), tx_digest)
+
+    # Only checks the signatures. Each call is then passed to the
+    # state transition function of its contract.
+    def verify(self):
+        if len(self.signatures) != len(self.func_calls):
+            return False, "wrong number of signature lists"
+
+        for i, (call, signatures) in enumerate(
+            zip(self.func_calls, self.signatures)):
+
+            publics = call.call_data.signature_publics()
+            if len(signatures) != len(publics):
+                return False, f"call {i} has wrong number of signatures"
+
+            payload = self.signing_payload(i)
+            for public, signature in zip(publics, signatures):
+                if not verify(payload, signature, public, self.ec):
+                    return False, f"call {i} has an invalid signature"
+
+        return True, None
+
+class TransactionBuilder:
+
+    def __init__(self, ec):
+        self.calls = []
+
+        self.ec = ec
+
+    # signature_secrets must match call_data.signature_publics(),
+    # which is checked by build()
+    def add_call(self, contract_id, func_id, call_data, signature_secrets):
+        call = ClassNamespace()
+        call.func_call = FuncCall(contract_id, func_id, call_data)
+        call.signature_secrets = signature_secrets
+        self.calls.append(call)
+
+    def build(self):
+        tx = Transaction(self.ec)
+
+        for call in self.calls:
+            publics = call.func_call.call_data.signature_publics()
+            assert len(publics) == len(call.signature_secrets)
+            for public, secret in zip(publics, call.signature_secrets):
+                assert public == self.ec.multiply(secret, self.ec.G)
+
+            tx.func_calls.append(call.func_call)
+
+        # Only sign once all the calls are in
+        for i, call in enumerate(self.calls):
+            payload = tx.signing_payload(i)
+            signatures = [sign(payload, secret, self.ec)
+                          for secret in call.signature_secrets]
+            tx.signatures.append(signatures)
+
+        return tx
@@ -0,0 +1,385 @@
+//! State of the DAO prototype: the DAOs and their treasuries, the balances
+//! of the governance and treasury tokens, and the proposals voted on. It
+//! follows the rules of the contracts in `demo/main.py`, without the coins
+//! and proofs. Slots don't pass by themselves, they're set with
+//! [`DaoState::set_slot`].
+use std::collections::{BTreeMap, HashMap};
+
+use rand::rngs::OsRng;
+
+use darkfi::{
+    crypto::{address::Address, keypair::Keypair},
+    util::serial::{serialize, SerialEncodable},
+};
+
+use crate::error::DaoError;
+
+pub type DaoResult<T> = std::result::Result<T, DaoError>;
+
+/// Parameters a DAO is created with
+#[derive(Clone, Debug, SerialEncodable)]
+pub struct DaoParams {
+    /// Governance tokens a member needs to make a proposal
+    pub proposer_limit: u64,
+    /// Governance tokens that must vote for a proposal to pass
+    pub quorum: u64,
+    /// Percentage of the votes that must be yes for a proposal to pass
+    pub approval_ratio: u64,
+    /// Token the members vote with
+    pub gov_token_id: u64,
+}
+
+struct Dao {
+    params: DaoParams,
+    treasury_token_id: u64,
+    treasury: u64,
+}
+
+struct Proposal {
+    dao_id: String,
+    dest: Address,
+    amount: u64,
+    /// Votes are accepted in `[start_slot, end_slot)`
+    start_slot: u64,
+    end_slot: u64,
+    /// Voter and their governance tokens, for yes and for no
+    yes: HashMap<Address, u64>,
+    no: HashMap<Address, u64>,
+    executed: bool,
+}
+
+/// Outcome of an executed proposal
+#[derive(Debug, PartialEq, Eq)]
+pub struct VoteTally {
+    pub yes: u64,
+    pub total: u64,
+}
+
+pub struct DaoState {
+    /// Proposals must leave at least this many slots for voting
+    min_voting_period: u64,
+    /// Passed proposals expire this many slots after voting ends
+    exec_period: u64,
+    slot: u64,
+    /// Keys held by the daemon, spending their tokens
+    keys: HashMap<Address, Keypair>,
+    /// Balances by address and token ID
+    balances: HashMap<(Address, u64), u64>,
+    daos: HashMap<String, Dao>,
+    proposals: HashMap<String, Proposal>,
+}
+
+impl DaoState {
+    pub fn new(min_voting_period: u64, exec_period: u64) -> Self {
+        Self {
+            min_voting_period,
+            exec_period,
+            slot: 0,
+            keys: HashMap::new(),
+            balances: HashMap::new(),
+            daos: HashMap::new(),
+            proposals: HashMap::new(),
+        }
+    }
+
+    /// Make a key held by the daemon, returning its address
+    pub fn keygen(&mut self) -> Address {
+        let keypair = Keypair::random(&mut OsRng);
+        let address = Address::from(keypair.public);
+        self.keys.insert(address, keypair);
+        address
+    }
+
+    /// Balances of an address held by the daemon, by token ID
+    pub fn get_balances(&self, address: &Address) -> DaoResult<BTreeMap<u64, u64>> {
+        if !self.keys.contains_key(address) {
+            return Err(DaoError::UnknownAddress)
+        }
+
+        let balances = self
+            .balances
+            .iter()
+            .filter(|((owner, _), value)| owner == address && **value > 0)
+            .map(|((_, token_id), value)| (*token_id, *value))
+            .collect();
+
+        Ok(balances)
+    }
+
+    /// Create a DAO, and mint the treasury supply to it. Returns the DAO ID.
+    pub fn create(
+        &mut self,
+        params: DaoParams,
+        treasury_token_id: u64,
+        treasury_supply: u64,
+    ) -> DaoResult<String> {
+        if params.approval_ratio > 100 {
+            return Err(DaoError::InvalidApprovalRatio)
+        }
+        if treasury_supply == 0 {
+            return Err(DaoError::InvalidAmount)
+        }
+
+        // Two DAOs with the same parameters are still two DAOs
+        let blind = Keypair::random(&mut OsRng).public;
+        let mut data = serialize(&params);
+        data.extend(serialize(&treasury_token_id));
+        data.extend(serialize(&blind));
+        let dao_id = blake3::hash(&data).to_hex().to_string();
+
+        let dao = Dao { params, treasury_token_id, treasury: treasury_supply };
+        self.daos.insert(dao_id.clone(), dao);
+        Ok(dao_id)
+    }
+
+    /// Mint governance tokens to each of the given addresses
+    pub fn mint_gov(&mut self, gov_token_id: u64, outputs: &[(Address, u64)]) -> DaoResult<()> {
+        if outputs.is_empty() || outputs.iter().any(|(_, value)| *value == 0) {
+            return Err(DaoError::InvalidAmount)
+        }
+
+        // Nothing is minted unless all of it can be
+        let mut balances = self.balances.clone();
+        for (address, value) in outputs {
+            let balance = balances.entry((*address, gov_token_id)).or_default();
+            *balance = balance.checked_add(*value).ok_or(DaoError::InvalidAmount)?;
+        }
+
+        self.balances = balances;
+        Ok(())
+    }
+
+    /// Propose paying `amount` of the DAO's treasury token to `dest`.
+    /// Voting starts now, and lasts `voting_period` slots. Returns the
+    /// proposal ID.
+    pub fn propose(
+        &mut self,
+        dao_id: &str,
+        proposer: &Address,
+        dest: Address,
+        amount: u64,
+        voting_period: u64,
+    ) -> DaoResult<String> {
+        let dao = self.daos.get(dao_id).ok_or(DaoError::UnknownDao)?;
+        if amount == 0 {
+            return Err(DaoError::InvalidAmount)
+        }
+        // The DAO keeps some change
+        if amount >= dao.treasury {
+            return Err(DaoError::ExceedsTreasury)
+        }
+        if voting_period < self.min_voting_period {
+            return Err(DaoError::VotingPeriodTooShort)
+        }
+        let end_slot = self.slot.checked_add(voting_period).ok_or(DaoError::InvalidAmount)?;
+
+        let gov_tokens = self.gov_tokens(proposer, dao.params.gov_token_id)?;
+        if gov_tokens < dao.params.proposer_limit {
+            return Err(DaoError::BelowProposerLimit)
+        }
+
+        let blind = Keypair::random(&mut OsRng).public;
+        let mut data = serialize(&dao_id.to_string());
+        data.extend(serialize(&dest));
+        data.extend(serialize(&amount));
+        data.extend(serialize(&end_slot));
+        data.extend(serialize(&blind));
+        let proposal_id = blake3::hash(&data).to_hex().to_string();
+
+        let proposal = Proposal {
+            dao_id: dao_id.to_string(),
+            dest,
+            amount,
+            start_slot: self.slot,
+            end_slot,
+            yes: HashMap::new(),
+            no: HashMap::new(),
+            executed: false,
+        };
+        self.proposals.insert(proposal_id.clone(), proposal);
+        Ok(proposal_id)
+    }
+
+    /// Vote on a proposal with all the governance tokens of `voter`
+    pub fn vote(&mut self, proposal_id: &str, voter: &Address, yes: bool) -> DaoResult<()> {
+        let proposal = self.proposals.get(proposal_id).ok_or(DaoError::UnknownProposal)?;
+        if self.slot < proposal.start_slot || self.slot >= proposal.end_slot {
+            return Err(DaoError::VotingClosed)
+        }
+        if proposal.yes.contains_key(voter) || proposal.no.contains_key(voter) {
+            return Err(DaoError::AlreadyVoted)
+        }
+
+        let gov_token_id = self.daos[&proposal.dao_id].params.gov_token_id;
+        let gov_tokens = self.gov_tokens(voter, gov_token_id)?;
+
+        let proposal = self.proposals.get_mut(proposal_id).unwrap();
+        match yes {
+            true => proposal.yes.insert(*voter, gov_tokens),
+            false => proposal.no.insert(*voter, gov_tokens),
+        };
+
+        Ok(())
+    }
+
+    /// Pay out a passed proposal, once voting is over
+    pub fn exec(&mut self, proposal_id: &str) -> DaoResult<VoteTally> {
+        let proposal = self.proposals.get(proposal_id).ok_or(DaoError::UnknownProposal)?;
+        if proposal.executed {
+            return Err(DaoError::ProposalExecuted)
+        }
+        if self.slot < proposal.end_slot {
+            return Err(DaoError::VotingNotEnded)
+        }
+        if self.slot - proposal.end_slot >= self.exec_period {
+            return Err(DaoError::ProposalExpired)
+        }
+
+        let yes: u128 = proposal.yes.values().map(|x| *x as u128).sum();
+        let no: u128 = proposal.no.values().map(|x| *x as u128).sum();
+        let total = yes + no;
+
+        let dao = &self.daos[&proposal.dao_id];
+        if total < dao.params.quorum as u128 {
+            return Err(DaoError::QuorumNotReached)
+        }
+        if yes * 100 < dao.params.approval_ratio as u128 * total {
+            return Err(DaoError::NotApproved)
+        }
+        if proposal.amount >= dao.treasury {
+            return Err(DaoError::ExceedsTreasury)
+        }
+
+        let payee = (proposal.dest, dao.treasury_token_id);
+        let balance = self.balances.get(&payee).copied().unwrap_or_default();
+        let balance = balance.checked_add(proposal.amount).ok_or(DaoError::InvalidAmount)?;
+        // Votes are capped by the governance token supply, a u64
+        let tally = VoteTally {
+            yes: u64::try_from(yes).map_err(|_| DaoError::InvalidAmount)?,
+            total: u64::try_from(total).map_err(|_| DaoError::InvalidAmount)?,
+        };
+
+        let proposal = self.proposals.get_mut(proposal_id).unwrap();
+        proposal.executed = true;
+        self.daos.get_mut(&proposal.dao_id).unwrap().treasury -= proposal.amount;
+        self.balances.insert(payee, balance);
+
+        Ok(tally)
+    }
+
+    /// Move to `slot`
+    pub fn set_slot(&mut self, slot: u64) -> DaoResult<()> {
+        if slot < self.slot {
+            return Err(DaoError::SlotInPast)
+        }
+
+        self.slot = slot;
+        Ok(())
+    }
+
+    /// Governance tokens of a key held by the daemon
+    fn gov_tokens(&self, address: &Address, gov_token_id: u64) -> DaoResult<u64> {
+        if !self.keys.contains_key(address) {
+            return Err(DaoError::UnknownAddress)
+        }
+
+        match self.balances.get(&(*address, gov_token_id)) {
+            Some(v) if *v > 0 => Ok(*v),
+            _ => Err(DaoError::NoGovTokens),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOV_TOKEN_ID: u64 = 1;
+    const TREASURY_TOKEN_ID: u64 = 2;
+
+    #[test]
+    fn proposal_lifecycle() {
+        let mut state = DaoState::new(10, 100);
+        let params = DaoParams {
+            proposer_limit: 100,
+            quorum: 200,
+            approval_ratio: 60,
+            gov_token_id: GOV_TOKEN_ID,
+        };
+        let dao_id = state.create(params, TREASURY_TOKEN_ID, 1000).unwrap();
+
+        let alice = state.keygen();
+        let bob = state.keygen();
+        let charlie = state.keygen();
+        let dest = state.keygen();
+        state.mint_gov(GOV_TOKEN_ID, &[(alice, 150), (bob, 100), (charlie, 50)]).unwrap();
+
+        let propose = |state: &mut DaoState, proposer, amount, period| {
+            state.propose(&dao_id, proposer, dest, amount, period)
+        };
+        assert_eq!(propose(&mut state, &charlie, 10, 10), Err(DaoError::BelowProposerLimit));
+        assert_eq!(propose(&mut state, &dest, 10, 10), Err(DaoError::NoGovTokens));
+        assert_eq!(propose(&mut state, &alice, 1000, 10), Err(DaoError::ExceedsTreasury));
+        assert_eq!(propose(&mut state, &alice, 10, 9), Err(DaoError::VotingPeriodTooShort));
+        let proposal_id = propose(&mut state, &alice, 300, 10).unwrap();
+
+        state.vote(&proposal_id, &alice, true).unwrap();
+        assert_eq!(state.vote(&proposal_id, &alice, false), Err(DaoError::AlreadyVoted));
+        state.vote(&proposal_id, &bob, false).unwrap();
+        assert_eq!(state.exec(&proposal_id), Err(DaoError::VotingNotEnded));
+        state.vote(&proposal_id, &charlie, true).unwrap();
+
+        state.set_slot(10).unwrap();
+        assert_eq!(state.vote(&proposal_id, &dest, true), Err(DaoError::VotingClosed));
+        assert_eq!(state.set_slot(9), Err(DaoError::SlotInPast));
+
+        // 200 of the 300 votes are yes, over the 60% needed
+        assert_eq!(state.exec(&proposal_id), Ok(VoteTally { yes: 200, total: 300 }));
+        assert_eq!(state.exec(&proposal_id), Err(DaoError::ProposalExecuted));
+        assert_eq!(state.get_balances(&dest).unwrap()[&TREASURY_TOKEN_ID], 300);
+        assert_eq!(state.get_balances(&alice).unwrap()[&GOV_TOKEN_ID], 150);
+
+        // Only 700 are left in the treasury
+        assert_eq!(propose(&mut state, &alice, 700, 10), Err(DaoError::ExceedsTreasury));
+    }
+
+    #[test]
+    fn rejected_proposals() {
+        let mut state = DaoState::new(10, 100);
+        let params = DaoParams {
+            proposer_limit: 0,
+            quorum: 100,
+            approval_ratio: 50,
+            gov_token_id: GOV_TOKEN_ID,
+        };
+        let dao_id = state.create(params, TREASURY_TOKEN_ID, 1000).unwrap();
+        let alice = state.keygen();
+        let bob = state.keygen();
+        state.mint_gov(GOV_TOKEN_ID, &[(alice, 60), (bob, 70)]).unwrap();
+
+        // Below the quorum
+        let proposal_id = state.propose(&dao_id, &alice, alice, 1, 10).unwrap();
+        state.vote(&proposal_id, &alice, true).unwrap();
+        state.set_slot(10).unwrap();
+        assert_eq!(state.exec(&proposal_id), Err(DaoError::QuorumNotReached));
+
+        // Not approved
+        let proposal_id = state.propose(&dao_id, &alice, alice, 1, 10).unwrap();
+        state.vote(&proposal_id, &alice, true).unwrap();
+        state.vote(&proposal_id, &bob, false).unwrap();
+        state.set_slot(20).unwrap();
+        assert_eq!(state.exec(&proposal_id), Err(DaoError::NotApproved));
+
+        // Expired
+        let proposal_id = state.propose(&dao_id, &alice, alice, 1, 10).unwrap();
+        state.vote(&proposal_id, &alice, true).unwrap();
+        state.vote(&proposal_id, &bob, true).unwrap();
+        state.set_slot(130).unwrap();
+        assert_eq!(state.exec(&proposal_id), Err(DaoError::ProposalExpired));
+        assert!(state.get_balances(&alice).unwrap().get(&TREASURY_TOKEN_ID).is_none());
+
+        let overflow = state.mint_gov(GOV_TOKEN_ID, &[(alice, u64::MAX)]);
+        assert_eq!(overflow, Err(DaoError::InvalidAmount));
+        assert_eq!(state.get_balances(&alice).unwrap()[&GOV_TOKEN_ID], 60);
+    }
+}
@@ -0,0 +1,54 @@
+use serde_json::Value;
+
+use darkfi::rpc::jsonrpc::{ErrorCode::ServerError, JsonError, JsonResult};
+
+/// Requests the DAO state refuses
+#[derive(Debug, PartialEq, Eq)]
+pub enum DaoError {
+    UnknownAddress = -32101,
+    UnknownDao = -32102,
+    UnknownProposal = -32103,
+    InvalidAmount = -32104,
+    InvalidApprovalRatio = -32105,
+    NoGovTokens = -32106,
+    BelowProposerLimit = -32107,
+    ExceedsTreasury = -32108,
+    VotingPeriodTooShort = -32109,
+    VotingClosed = -32110,
+    AlreadyVoted = -32111,
+    VotingNotEnded = -32112,
+    ProposalExpired = -32113,
+    ProposalExecuted = -32114,
+    QuorumNotReached = -32115,
+    NotApproved = -32116,
+    SlotInPast = -32117,
+}
+
+fn to_tuple(e: DaoError) -> (i64, String) {
+    let msg = match e {
+        DaoError::UnknownAddress => "Address is not in the wallet",
+        DaoError::UnknownDao => "Unknown DAO",
+        DaoError::UnknownProposal => "Unknown proposal",
+        DaoError::InvalidAmount => "Invalid amount",
+        DaoError::InvalidApprovalRatio => "Approval ratio is a percentage",
+        DaoError::NoGovTokens => "No governance tokens",
+        DaoError::BelowProposerLimit => "Not enough governance tokens to propose",
+        DaoError::ExceedsTreasury => "Amount exceeds the treasury",
+        DaoError::VotingPeriodTooShort => "Voting period too short",
+        DaoError::VotingClosed => "Voting is not open",
+        DaoError::AlreadyVoted => "Already voted",
+        DaoError::VotingNotEnded => "Voting has not ended",
+        DaoError::ProposalExpired => "Proposal expired",
+        DaoError::ProposalExecuted => "Proposal was already executed",
+        DaoError::QuorumNotReached => "Quorum not reached",
+        DaoError::NotApproved => "Proposal was not approved",
+        DaoError::SlotInPast => "Slots can't go backwards",
+    };
+
+    (e as i64, msg.to_string())
+}
+
+pub fn server_error(e: DaoError, id: Value) -> JsonResult {
+    let (code, msg) = to_tuple(e);
+    JsonError::new(ServerError(code), Some(msg), id).into()
+}
@@ -1,62 +1,96 @@
-use std::sync::Arc;
-
-use async_trait::async_trait;
-use log::debug;
-use serde_json::{json, Value};
-use simplelog::{ColorChoice, LevelFilter, TermLogger, TerminalMode};
+use async_executor::Executor;
+use async_std::sync::Arc;
+use futures_lite::future;
+use log::info;
+use serde_derive::Deserialize;
+use structopt::StructOpt;
+use structopt_toml::StructOptToml;
 use url::Url;
 
 use darkfi::{
-    rpc::{
-        jsonrpc::{ErrorCode::*, JsonError, JsonRequest, JsonResponse, JsonResult},
-        server::{listen_and_serve, RequestHandler},
+    async_daemonize, cli_desc,
+    rpc::server::listen_and_serve,
+    util::{
+        check::CheckReport,
+        cli::{get_log_config, get_log_level, spawn_config},
+        path::get_config_path,
     },
     Result,
 };
 
-async fn start() -> Result<()> {
-    let rpc_addr = Url::parse("tcp://127.0.0.1:7777")?;
-    let rpc_interface = Arc::new(JsonRpcInterface {});
+mod dao;
+use dao::DaoState;
 
-    listen_and_serve(rpc_addr, rpc_interface).await?;
-    Ok(())
-}
+mod error;
 
-struct JsonRpcInterface {}
+mod rpc;
+use rpc::JsonRpcInterface;
 
-#[async_trait]
-impl RequestHandler for JsonRpcInterface {
-    async fn handle_request(&self, req: JsonRequest) -> JsonResult {
-        if req.params.as_array().is_none() {
-            return JsonError::new(InvalidParams, None, req.id).into()
-        }
+const CONFIG_FILE: &str = "daod_config.toml";
+const CONFIG_FILE_CONTENTS: &str = include_str!("../daod_config.toml");
 
-        debug!(target: "RPC", "--> {}", serde_json::to_string(&req).unwrap());
+#[derive(Clone, Debug, Deserialize, StructOpt, StructOptToml)]
+#[serde(default)]
+#[structopt(name = "daod", about = cli_desc!())]
+struct Args {
+    #[structopt(short, long)]
+    /// Configuration file to use
+    config: Option<String>,
 
-        match req.method.as_str() {
-            Some("say_hello") => return self.say_hello(req.id, req.params).await,
-            Some(_) | None => return JsonError::new(MethodNotFound, None, req.id).into(),
-        }
-    }
+    #[structopt(long, default_value = "tcp://127.0.0.1:7777")]
+    /// JSON-RPC listen URL
+    rpc_listen: Url,
+
+    #[structopt(long, default_value = "10")]
+    /// Minimum slots a proposal is open for voting
+    min_voting_period: u64,
+
+    #[structopt(long, default_value = "100")]
+    /// Slots after voting a passed proposal can be executed
+    exec_period: u64,
+
+    #[structopt(long)]
+    /// Check the configuration and ports, print a JSON report and exit
+    check: bool,
+
+    #[structopt(short, parse(from_occurrences))]
+    /// Increase verbosity (-vvv supported)
+    verbose: u8,
 }
 
-impl JsonRpcInterface {
-    // --> {"method": "say_hello", "params": []}
-    // <-- {"result": "hello world"}
-    async fn say_hello(&self, id: Value, _params: Value) -> JsonResult {
-        JsonResponse::new(json!("hello world"), id).into()
-    }
+/// Check what daod needs to start, without starting it, and print the
+/// report.
+fn self_check(args: &Args) -> Result<()> {
+    let mut report = CheckReport::new();
+    report.check_listen("rpc_listen", &args.rpc_listen, false);
+    report.finish()
 }
 
-#[async_std::main]
-async fn main() -> Result<()> {
-    TermLogger::init(
-        LevelFilter::Debug,
-        simplelog::Config::default(),
-        TerminalMode::Mixed,
-        ColorChoice::Auto,
-    )?;
+async_daemonize!(realmain);
+async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
+    if args.check {
+        return self_check(&args)
+    }
+
+    // We use this handler to block this function after detaching all
+    // tasks, and to catch a shutdown signal, where we can clean up and
+    // exit gracefully.
+    let (signal, shutdown) = async_channel::bounded::<()>(1);
+    ctrlc_async::set_async_handler(async move {
+        signal.send(()).await.unwrap();
+    })
+    .unwrap();
+
+    let state = DaoState::new(args.min_voting_period, args.exec_period);
+    let rpc_interface = Arc::new(JsonRpcInterface::new(state));
+
+    info!("Starting JSON-RPC server on {}", args.rpc_listen);
+    ex.spawn(listen_and_serve(args.rpc_listen, rpc_interface)).detach();
+
+    // Wait for SIGINT
+    shutdown.recv().await?;
+    print!("\r");
+    info!("Caught termination signal, exiting...");
 
-    start().await?;
     Ok(())
 }
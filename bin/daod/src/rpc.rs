@@ -0,0 +1,234 @@
+use std::str::FromStr;
+
+use async_std::sync::Mutex;
+use async_trait::async_trait;
+use log::debug;
+use serde_json::{json, Value};
+
+use darkfi::{
+    crypto::address::Address,
+    rpc::{
+        jsonrpc::{redact_auth, ErrorCode::*, JsonError, JsonRequest, JsonResponse, JsonResult},
+        server::RequestHandler,
+    },
+};
+
+use crate::{
+    dao::{DaoParams, DaoState},
+    error::server_error,
+};
+
+pub struct JsonRpcInterface {
+    state: Mutex<DaoState>,
+}
+
+#[async_trait]
+impl RequestHandler for JsonRpcInterface {
+    async fn handle_request(&self, req: JsonRequest) -> JsonResult {
+        if !req.params.is_array() {
+            return JsonError::new(InvalidParams, None, req.id).into()
+        }
+
+        let params = req.params.as_array().unwrap();
+
+        debug!(target: "RPC", "--> {}", redact_auth(&json!(req)));
+
+        match req.method.as_str() {
+            Some("say_hello") => return self.say_hello(req.id, params).await,
+            Some("wallet.keygen") => return self.keygen(req.id, params).await,
+            Some("wallet.get_balances") => return self.get_balances(req.id, params).await,
+            Some("dao.create") => return self.create(req.id, params).await,
+            Some("dao.mint_gov") => return self.mint_gov(req.id, params).await,
+            Some("dao.propose") => return self.propose(req.id, params).await,
+            Some("dao.vote") => return self.vote(req.id, params).await,
+            Some("dao.exec") => return self.exec(req.id, params).await,
+            Some("dao.set_slot") => return self.set_slot(req.id, params).await,
+            Some(_) | None => return JsonError::new(MethodNotFound, None, req.id).into(),
+        }
+    }
+}
+
+impl JsonRpcInterface {
+    pub fn new(state: DaoState) -> Self {
+        Self { state: Mutex::new(state) }
+    }
+
+    // --> {"method": "say_hello", "params": []}
+    // <-- {"result": "hello world"}
+    async fn say_hello(&self, id: Value, _params: &[Value]) -> JsonResult {
+        JsonResponse::new(json!("hello world"), id).into()
+    }
+
+    // RPCAPI:
+    // Make a key held by the daemon, which spends its tokens.
+    // Returns its address.
+    // --> {"jsonrpc": "2.0", "method": "wallet.keygen", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "1DarkFi...", "id": 1}
+    async fn keygen(&self, id: Value, params: &[Value]) -> JsonResult {
+        if !params.is_empty() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let address = self.state.lock().await.keygen();
+        JsonResponse::new(json!(address.to_string()), id).into()
+    }
+
+    // RPCAPI:
+    // Returns the balances of an address held by the daemon, by token ID.
+    // --> {"jsonrpc": "2.0", "method": "wallet.get_balances", "params": ["1DarkFi..."], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"1": 150, "2": 300}, "id": 1}
+    async fn get_balances(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let address = match parse_address(&params[0]) {
+            Some(v) => v,
+            None => return JsonError::new(InvalidParams, None, id).into(),
+        };
+
+        match self.state.lock().await.get_balances(&address) {
+            Ok(v) => JsonResponse::new(json!(v), id).into(),
+            Err(e) => server_error(e, id),
+        }
+    }
+
+    // RPCAPI:
+    // Create a DAO, and mint the treasury supply to it. `approval_ratio` is
+    // the percentage of the votes that must be yes for a proposal to pass.
+    // Returns the DAO ID.
+    // --> {"jsonrpc": "2.0", "method": "dao.create", "params": [proposer_limit, quorum, approval_ratio, gov_token_id, treasury_token_id, treasury_supply], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "dao_id", "id": 1}
+    async fn create(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 6 || !params.iter().all(|x| x.is_u64()) {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let values: Vec<u64> = params.iter().map(|x| x.as_u64().unwrap()).collect();
+        let dao_params = DaoParams {
+            proposer_limit: values[0],
+            quorum: values[1],
+            approval_ratio: values[2],
+            gov_token_id: values[3],
+        };
+
+        match self.state.lock().await.create(dao_params, values[4], values[5]) {
+            Ok(v) => JsonResponse::new(json!(v), id).into(),
+            Err(e) => server_error(e, id),
+        }
+    }
+
+    // RPCAPI:
+    // Mint governance tokens to each of the given addresses.
+    // --> {"jsonrpc": "2.0", "method": "dao.mint_gov", "params": [gov_token_id, [["1DarkFi...", value], ...]], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn mint_gov(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 2 || !params[0].is_u64() || !params[1].is_array() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let mut outputs = vec![];
+        for output in params[1].as_array().unwrap() {
+            let output = match output.as_array() {
+                Some(v) if v.len() == 2 && v[1].is_u64() => v,
+                _ => return JsonError::new(InvalidParams, None, id).into(),
+            };
+
+            match parse_address(&output[0]) {
+                Some(v) => outputs.push((v, output[1].as_u64().unwrap())),
+                None => return JsonError::new(InvalidParams, None, id).into(),
+            }
+        }
+
+        match self.state.lock().await.mint_gov(params[0].as_u64().unwrap(), &outputs) {
+            Ok(()) => JsonResponse::new(json!(true), id).into(),
+            Err(e) => server_error(e, id),
+        }
+    }
+
+    // RPCAPI:
+    // Propose paying `amount` of the DAO's treasury token to `dest`. The
+    // proposer needs `proposer_limit` governance tokens. Voting starts now,
+    // and lasts `voting_period` slots. Returns the proposal ID.
+    // --> {"jsonrpc": "2.0", "method": "dao.propose", "params": ["dao_id", "1DarkFi...", "1DarkFi...", amount, voting_period], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "proposal_id", "id": 1}
+    async fn propose(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 5 || !params[0].is_string() || !params[3].is_u64() || !params[4].is_u64()
+        {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let (proposer, dest) = match (parse_address(&params[1]), parse_address(&params[2])) {
+            (Some(proposer), Some(dest)) => (proposer, dest),
+            _ => return JsonError::new(InvalidParams, None, id).into(),
+        };
+
+        match self.state.lock().await.propose(
+            params[0].as_str().unwrap(),
+            &proposer,
+            dest,
+            params[3].as_u64().unwrap(),
+            params[4].as_u64().unwrap(),
+        ) {
+            Ok(v) => JsonResponse::new(json!(v), id).into(),
+            Err(e) => server_error(e, id),
+        }
+    }
+
+    // RPCAPI:
+    // Vote yes or no on a proposal, with all the governance tokens of the
+    // voter.
+    // --> {"jsonrpc": "2.0", "method": "dao.vote", "params": ["proposal_id", "1DarkFi...", true], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn vote(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 3 || !params[0].is_string() || !params[2].is_boolean() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let voter = match parse_address(&params[1]) {
+            Some(v) => v,
+            None => return JsonError::new(InvalidParams, None, id).into(),
+        };
+
+        let proposal_id = params[0].as_str().unwrap();
+        match self.state.lock().await.vote(proposal_id, &voter, params[2].as_bool().unwrap()) {
+            Ok(()) => JsonResponse::new(json!(true), id).into(),
+            Err(e) => server_error(e, id),
+        }
+    }
+
+    // RPCAPI:
+    // Pay out a passed proposal, once voting is over. It must be executed
+    // within `exec_period` slots after that. Returns the votes it got.
+    // --> {"jsonrpc": "2.0", "method": "dao.exec", "params": ["proposal_id"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"yes": 200, "total": 300}, "id": 1}
+    async fn exec(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        match self.state.lock().await.exec(params[0].as_str().unwrap()) {
+            Ok(v) => JsonResponse::new(json!({"yes": v.yes, "total": v.total}), id).into(),
+            Err(e) => server_error(e, id),
+        }
+    }
+
+    // RPCAPI:
+    // Move to the given slot. Slots don't pass by themselves.
+    // --> {"jsonrpc": "2.0", "method": "dao.set_slot", "params": [slot], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn set_slot(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_u64() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        match self.state.lock().await.set_slot(params[0].as_u64().unwrap()) {
+            Ok(()) => JsonResponse::new(json!(true), id).into(),
+            Err(e) => server_error(e, id),
+        }
+    }
+}
+
+fn parse_address(value: &Value) -> Option<Address> {
+    Address::from_str(value.as_str()?).ok()
+}
@@ -0,0 +1,120 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::exit,
+    str::FromStr,
+};
+
+use clap::Parser;
+
+use darkfi::{
+    cli_desc,
+    consensus::{ChainParams, Genesis, KeyStore},
+    crypto::{address::Address, keypair::Keypair, mnemonic::Mnemonic},
+    util::time::Timestamp,
+    Result,
+};
+
+#[derive(Parser)]
+#[clap(name = "darkfi-genesis", about = cli_desc!(), version)]
+struct Args {
+    /// Directory to write the genesis file and validator keys to
+    output: PathBuf,
+
+    /// Chain the genesis is for (testnet, localnet)
+    #[clap(long, default_value = "testnet")]
+    network: String,
+
+    /// Genesis timestamp (defaults to now)
+    #[clap(long)]
+    timestamp: Option<i64>,
+
+    /// Number of validators to create keys for
+    #[clap(long, default_value = "1")]
+    validators: usize,
+
+    /// Whitelisted faucet address (repeatable flag)
+    #[clap(long)]
+    faucet_pub: Vec<String>,
+
+    /// Whitelisted cashier address (repeatable flag)
+    #[clap(long)]
+    cashier_pub: Vec<String>,
+}
+
+/// Write a file only its owner can read
+fn write_secret(path: &Path, contents: &str) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+/// Create the keys of a validator in `dir`: its identity and consensus keys
+/// in `keys`, for darkfid's `keys_dir`, and the mnemonic of its wallet in
+/// `mnemonic`, for the `wallet.restore_mnemonic` RPC method. Returns the
+/// wallet address, that of the first key derived from the mnemonic, which
+/// restoring it makes the default key.
+fn create_validator(dir: &Path) -> Result<Address> {
+    fs::create_dir_all(dir)?;
+
+    let keystore = KeyStore::new(&dir.join("keys"))?;
+    keystore.identity_key()?;
+    keystore.consensus_key()?;
+
+    let mnemonic = Mnemonic::generate(24)?;
+    write_secret(&dir.join("mnemonic"), &mnemonic.phrase())?;
+
+    let wallet = Keypair::derive(&mnemonic.to_seed(""), 0, 0);
+    Ok(Address::from(wallet.public))
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let params = match ChainParams::from_network(&args.network) {
+        Ok(v) if v.network != "mainnet" => v,
+        _ => {
+            eprintln!("Error: Can only make a genesis for testnet or localnet.");
+            exit(1);
+        }
+    };
+
+    if args.output.exists() {
+        eprintln!("Error: {:?} already exists.", args.output);
+        exit(1);
+    }
+
+    for i in args.faucet_pub.iter().chain(args.cashier_pub.iter()) {
+        if Address::from_str(i).is_err() {
+            eprintln!("Error: Invalid address {}", i);
+            exit(1);
+        }
+    }
+
+    let timestamp = args.timestamp.map_or_else(Timestamp::current_time, Timestamp);
+    let mut genesis = Genesis::new(&params.network, timestamp);
+    genesis.faucet_pubs = args.faucet_pub;
+    genesis.cashier_pubs = args.cashier_pub;
+
+    fs::create_dir_all(&args.output)?;
+    let genesis_path = args.output.join("genesis.toml");
+    genesis.save(&genesis_path)?;
+    println!("Genesis for {} at {}: {:?}", genesis.network, timestamp.0, genesis_path);
+
+    for i in 0..args.validators {
+        let dir = args.output.join(format!("validator{}", i));
+        let address = create_validator(&dir)?;
+        println!("Validator {}: {} {:?}", i, address, dir);
+    }
+
+    Ok(())
+}
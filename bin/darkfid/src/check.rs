@@ -0,0 +1,177 @@
+use std::{fs, str::FromStr};
+
+use url::Url;
+
+use darkfi::{
+    consensus::{Block, KeyStore},
+    crypto::{address::Address, keypair::PublicKey},
+    node::{token_registry::TokenListSource, vk_registry::VerifyingKeyRegistry},
+    tx::coin_selection::CoinSelection,
+    util::{check::CheckReport, expand_path, service::running_pid},
+    Result,
+};
+
+use super::{chain_params, check_clock_rpc, data_dir, parse_rate_limits, pid_file, Args, Roles};
+
+/// Check what darkfid needs to start, without starting it or writing to
+/// its data, and print the report.
+pub async fn self_check(args: &Args) -> Result<()> {
+    let mut report = CheckReport::new();
+    let running = running_pid(&pid_file(args)?)?.is_some();
+
+    // Configuration
+    let roles = match Roles::parse(&args.role, args.consensus) {
+        Ok(v) => {
+            report.add("roles", Ok(format!("{:?}", v)));
+            Some(v)
+        }
+        Err(e) => {
+            report.add("roles", Err(e));
+            None
+        }
+    };
+    // Without valid roles, check everything any role would use
+    let (sync, validator) = roles.map_or((true, true), |r| (r.sync, r.validator));
+
+    let genesis = match chain_params(args) {
+        Ok((params, _)) => {
+            let genesis = Block::genesis_block(params.genesis_ts, params.genesis_data).header;
+            let detail = format!("{}, genesis block {}", params.network, genesis);
+            report.add("chain_params", Ok(detail));
+            Some(genesis)
+        }
+        Err(e) => {
+            report.add("chain_params", Err(e));
+            None
+        }
+    };
+
+    if validator && args.clock_sync {
+        report.add("clock_sync", check_clock_rpc(args).map(|_| "Peer RPC URLs are set".into()));
+    }
+
+    let limits = parse_rate_limits(&args.sync_rate_limit);
+    report.add("sync_rate_limit", limits.map(|l| format!("{} limits", l.len())));
+
+    let coin_selection = CoinSelection::from_str(&args.coin_selection);
+    report.add("coin_selection", coin_selection.map(|_| args.coin_selection.clone()));
+
+    report.add("cashier_pub", parse_pubkeys(&args.cashier_pub));
+    report.add("faucet_pub", parse_pubkeys(&args.faucet_pub));
+
+    for source in &args.token_list {
+        let source = TokenListSource::from_str(source);
+        report.add("token_list", source.map(|s| format!("{} list from {}", s.network, s.url)));
+    }
+
+    // Databases and keys, without creating or moving any
+    let data_dir = data_dir(args)?;
+
+    let wallet_path = data_dir.find(
+        args.wallet_path.as_deref(),
+        "darkfid_wallet.db",
+        "~/.config/darkfi/darkfid_wallet.db",
+    )?;
+    report.check_wallet("wallet", &wallet_path, &args.wallet_pass).await;
+
+    let wallets_dir = data_dir.find(
+        args.wallets_dir.as_deref(),
+        "darkfid_wallets",
+        "~/.config/darkfi/darkfid_wallets",
+    )?;
+    if wallets_dir.exists() {
+        for entry in fs::read_dir(&wallets_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("db") {
+                report.check_wallet("named_wallet", &path, &args.wallet_pass).await;
+            }
+        }
+    }
+
+    let db_path = match &args.database {
+        Some(v) => expand_path(v)?.join(&args.chain),
+        None => {
+            let legacy = format!("~/.config/darkfi/darkfid_blockchain/{}", args.chain);
+            data_dir.find(None, "darkfid_blockchain", &legacy)?
+        }
+    };
+    report.check_chain_db("chain_database", &db_path, genesis);
+
+    let keys_dir =
+        data_dir.find(args.keys_dir.as_deref(), "darkfid_keys", "~/.config/darkfi/darkfid_keys")?;
+    let keys = KeyStore::read_only(&keys_dir).map(|(identity, consensus)| {
+        let identity = identity.map_or("not created yet".into(), |k| k.node_id());
+        let consensus =
+            consensus.map_or("not created yet".into(), |k| Address::from(k.public()).to_string());
+        format!("Node ID {}, consensus key {}", identity, consensus)
+    });
+    report.add("keys", keys);
+
+    // Verifying keys are built from the circuits, so they're checked by
+    // building them, and reported by the hash of the circuit
+    if let Some(dir) = &args.circuits_dir {
+        let dir = expand_path(dir)?;
+        let registry = VerifyingKeyRegistry::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.to_string_lossy().ends_with(".zk.bin") {
+                let circuit = registry.load_file(&path).and_then(|id| {
+                    let hash = blake3::hash(&fs::read(&path)?);
+                    Ok(format!("{} from {}, blake3 {}", id, path.display(), hash))
+                });
+                report.add("circuit", circuit);
+            }
+        }
+    }
+
+    match (&args.rpc_tls_cert, &args.rpc_tls_key) {
+        (Some(cert), Some(key)) => {
+            report.check_path("rpc_tls_cert", &expand_path(cert)?);
+            report.check_path("rpc_tls_key", &expand_path(key)?);
+        }
+        (None, None) => {}
+        _ => report.fail("rpc_tls", "Both rpc_tls_cert and rpc_tls_key have to be set".into()),
+    }
+
+    // Ports
+    for url in [&[args.rpc_listen.clone()], &args.rpc_extra_listen[..]].concat() {
+        report.check_listen("rpc_listen", &url, running);
+    }
+    if let Some(url) = &args.rpc_ws_listen {
+        report.check_listen("rpc_ws_listen", url, running);
+    }
+    if let Some(url) = &args.metrics_listen {
+        report.check_listen("metrics_listen", url, running);
+    }
+    if let (true, Some(url)) = (sync, &args.sync_p2p_accept) {
+        report.check_listen("sync_p2p_accept", url, running);
+    }
+    if let (true, Some(url)) = (validator, &args.consensus_p2p_accept) {
+        report.check_listen("consensus_p2p_accept", url, running);
+    }
+
+    if args.check_upstreams {
+        let mut upstreams: Vec<(&str, &Url)> = vec![];
+        if sync {
+            upstreams.extend(args.sync_p2p_seed.iter().map(|u| ("sync_p2p_seed", u)));
+            upstreams.extend(args.sync_p2p_peer.iter().map(|u| ("sync_p2p_peer", u)));
+        }
+        if validator {
+            upstreams.extend(args.consensus_p2p_seed.iter().map(|u| ("consensus_p2p_seed", u)));
+            upstreams.extend(args.consensus_p2p_peer.iter().map(|u| ("consensus_p2p_peer", u)));
+            upstreams.extend(args.consensus_seed_rpc.iter().map(|u| ("consensus_seed_rpc", u)));
+            upstreams.extend(args.consensus_peer_rpc.iter().map(|u| ("consensus_peer_rpc", u)));
+        }
+
+        report.check_upstreams(&upstreams, args.socks5_proxy.as_ref());
+    }
+
+    report.finish()
+}
+
+fn parse_pubkeys(addrs: &[String]) -> Result<String> {
+    for addr in addrs {
+        PublicKey::try_from(Address::from_str(addr)?)?;
+    }
+    Ok(format!("{} addresses", addrs.len()))
+}
@@ -17,6 +17,22 @@ pub enum RpcError {
     NotYetSynced = -32112,
     InvalidAddressParam = -32113,
     InvalidAmountParam = -32114,
+    InvalidWalletName = -32115,
+    WalletExists = -32116,
+    WalletNotFound = -32117,
+    InvalidMnemonic = -32118,
+    SeedExists = -32119,
+    SeedNotFound = -32120,
+    InvalidMemoParam = -32121,
+    MembershipProofFail = -32122,
+    InvalidMembershipProof = -32123,
+    CircuitLoadFail = -32124,
+    InvalidWalletExport = -32125,
+    WalletExportStale = -32126,
+    WalletWitnessConflict = -32127,
+    InvalidTx = -32128,
+    UnknownCircuit = -32129,
+    WalletExportUnwitnessed = -32130,
 }
 
 fn to_tuple(e: RpcError) -> (i64, String) {
@@ -35,6 +51,22 @@ fn to_tuple(e: RpcError) -> (i64, String) {
         RpcError::NotYetSynced => "Blockchain not yet synced",
         RpcError::InvalidAddressParam => "Invalid address parameter",
         RpcError::InvalidAmountParam => "invalid amount parameter",
+        RpcError::InvalidWalletName => "Invalid wallet name",
+        RpcError::WalletExists => "Wallet already exists",
+        RpcError::WalletNotFound => "Wallet not found",
+        RpcError::InvalidMnemonic => "Invalid mnemonic",
+        RpcError::SeedExists => "Wallet already has a seed",
+        RpcError::SeedNotFound => "Wallet has no seed",
+        RpcError::InvalidMemoParam => "Invalid memo parameter",
+        RpcError::MembershipProofFail => "Failed creating membership proof",
+        RpcError::InvalidMembershipProof => "Invalid membership proof",
+        RpcError::CircuitLoadFail => "Failed loading circuits",
+        RpcError::InvalidWalletExport => "Invalid wallet export or wrong passphrase",
+        RpcError::WalletExportStale => "Wallet export predates the last block and needs a rescan",
+        RpcError::WalletWitnessConflict => "Other wallets hold coins witnessed in the current tree",
+        RpcError::InvalidTx => "Transaction failed verification",
+        RpcError::UnknownCircuit => "No verifying key for circuit",
+        RpcError::WalletExportUnwitnessed => "Wallet export holds coins the node can't witness",
     };
 
     (e as i64, msg.to_string())
@@ -1,4 +1,8 @@
-use std::str::FromStr;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use async_executor::Executor;
 use async_std::sync::{Arc, Mutex};
@@ -6,39 +10,52 @@ use async_trait::async_trait;
 use futures_lite::future;
 use log::{debug, error, info};
 use serde_derive::Deserialize;
+use serde_json::Value;
 use structopt::StructOpt;
 use structopt_toml::StructOptToml;
 use url::Url;
 
 use darkfi::{
-    async_daemonize, cli_desc,
+    async_daemonize,
+    blockchain::wipe_chain,
+    cli_desc,
     consensus::{
         proto::{
-            ProtocolParticipant, ProtocolProposal, ProtocolSync, ProtocolSyncConsensus, ProtocolTx,
-            ProtocolVote,
+            ProtocolParticipant, ProtocolProposal, ProtocolRemoval, ProtocolSync,
+            ProtocolSyncBlocks, ProtocolSyncConsensus, ProtocolTx, ProtocolVote,
         },
         state::ValidatorStatePtr,
-        task::{block_sync_task, proposal_task},
-        ValidatorState, MAINNET_GENESIS_HASH_BYTES, MAINNET_GENESIS_TIMESTAMP,
-        TESTNET_GENESIS_HASH_BYTES, TESTNET_GENESIS_TIMESTAMP,
+        task::{block_sync_task, proposal_task, slashing_task},
+        tx_filter, ChainParams, Genesis, KeyStore, ValidatorState,
     },
     crypto::{address::Address, keypair::PublicKey, token_list::DrkTokenList},
     net,
     net::P2pPtr,
-    node::Client,
+    node::{
+        token_registry::TokenListSource, vk_registry::VerifyingKeyRegistryPtr, Client,
+        TokenRegistry,
+    },
     rpc::{
+        acl::RpcAcl,
         jsonrpc::{
             ErrorCode::{InvalidParams, MethodNotFound},
             JsonError, JsonRequest, JsonResult,
         },
-        server::{listen_and_serve, RequestHandler},
+        server::{listen_and_serve_with_tls, RequestHandler},
+        ws_server::listen_and_serve_ws,
     },
     util::{
         cli::{get_log_config, get_log_level, spawn_config},
         expand_path,
+        lock::DbLock,
         path::get_config_path,
-        time::check_clock,
+        clock::{check_clock, drift_task, ClockDrift},
+        service::ServiceCommand,
+        supervisor::{RestartPolicy, Supervisor, SupervisorPtr},
+        time::Timestamp,
+        DataDir,
     },
+    tx::coin_selection::CoinSelection,
     wallet::walletdb::init_wallet,
     Error, Result,
 };
@@ -49,6 +66,9 @@ use error::{server_error, RpcError};
 const CONFIG_FILE: &str = "darkfid_config.toml";
 const CONFIG_FILE_CONTENTS: &str = include_str!("../darkfid_config.toml");
 
+/// Seconds between measurements of the clock's drift from the peers
+const CLOCK_DRIFT_INTERVAL: u64 = 300;
+
 #[derive(Clone, Debug, Deserialize, StructOpt, StructOptToml)]
 #[serde(default)]
 #[structopt(name = "darkfid", about = cli_desc!())]
@@ -58,29 +78,114 @@ struct Args {
     config: Option<String>,
 
     #[structopt(long, default_value = "testnet")]
-    /// Chain to use (testnet, mainnet)
+    /// Chain to use (testnet, mainnet, localnet)
     chain: String,
 
     #[structopt(long)]
-    /// Participate in consensus
+    /// Genesis timestamp override, only on localnet
+    localnet_genesis_ts: Option<i64>,
+
+    #[structopt(long)]
+    /// Half of the slot duration in seconds, only on localnet
+    localnet_delta: Option<u64>,
+
+    #[structopt(long)]
+    /// Genesis file of the chain, made with darkfi-genesis (not on mainnet)
+    genesis: Option<String>,
+
+    #[structopt(long)]
+    /// Participate in consensus (same as `--role validator`)
     consensus: bool,
 
-    #[structopt(long, default_value = "~/.config/darkfi/darkfid_wallet.db")]
-    /// Path to wallet database
-    wallet_path: String,
+    #[structopt(long)]
+    /// Subsystem to run: wallet, sync, validator, gateway (repeatable flag)
+    role: Vec<String>,
+
+    #[structopt(long)]
+    /// Data directory, holding a directory per chain (default: ~/.local/share/darkfi)
+    data_dir: Option<String>,
+
+    #[structopt(long)]
+    /// Path to wallet database (default: darkfid_wallet.db in the chain directory)
+    wallet_path: Option<String>,
 
     #[structopt(long, default_value = "changeme")]
     /// Password for the wallet database
     wallet_pass: String,
 
-    #[structopt(long, default_value = "~/.config/darkfi/darkfid_blockchain")]
-    /// Path to blockchain database
-    database: String,
+    #[structopt(long)]
+    /// Directory holding named wallets, opened with the same password
+    wallets_dir: Option<String>,
+
+    #[structopt(long)]
+    /// Named wallet to use on startup, instead of the default one
+    wallet: Option<String>,
+
+    #[structopt(long)]
+    /// Directory holding the node identity and consensus keys
+    keys_dir: Option<String>,
+
+    #[structopt(long)]
+    /// Replace the node identity key with a new one before starting
+    rotate_identity_key: bool,
+
+    #[structopt(long)]
+    /// Replace the consensus signing key with a new one before starting
+    rotate_consensus_key: bool,
+
+    #[structopt(long)]
+    /// Path to blockchain database, holding a database per chain
+    database: Option<String>,
+
+    #[structopt(long)]
+    /// Directory of compiled zkas circuits to verify proofs with
+    circuits_dir: Option<String>,
+
+    #[structopt(long)]
+    /// Token list to fetch at runtime, as `<network>,<url>,<pin>`, pinned to
+    /// `blake3:<hash>` or `signer:<address>` (repeatable flag)
+    token_list: Vec<String>,
+
+    #[structopt(long, default_value = "86400")]
+    /// Seconds between fetches of the token lists, 0 to only fetch on request
+    token_list_refresh: u64,
 
     #[structopt(long, default_value = "tcp://127.0.0.1:8340")]
     /// JSON-RPC listen URL
     rpc_listen: Url,
 
+    #[structopt(long)]
+    /// Additional JSON-RPC listen URLs, e.g. unix:// or tcp+tls:// (repeatable flag)
+    rpc_extra_listen: Vec<Url>,
+
+    #[structopt(long)]
+    /// PEM certificate chain for TLS JSON-RPC listeners (self-signed if unset)
+    rpc_tls_cert: Option<String>,
+
+    #[structopt(long)]
+    /// PEM PKCS#8 private key for TLS JSON-RPC listeners
+    rpc_tls_key: Option<String>,
+
+    #[structopt(long)]
+    /// Token JSON-RPC clients must pass to call methods that aren't open
+    rpc_auth_token: Option<String>,
+
+    #[structopt(long)]
+    /// JSON-RPC methods callable without the token, `prefix.*` allowed (repeatable flag)
+    rpc_open_methods: Vec<String>,
+
+    #[structopt(long)]
+    /// WebSocket JSON-RPC listen URL, serving event subscriptions (disabled if not set)
+    rpc_ws_listen: Option<Url>,
+
+    #[structopt(long)]
+    /// Prometheus metrics listen URL (disabled if not set)
+    metrics_listen: Option<Url>,
+
+    #[structopt(long)]
+    /// SOCKS5 proxy to dial peers and clock sync RPC through, e.g. Tor's
+    socks5_proxy: Option<Url>,
+
     #[structopt(long)]
     /// P2P accept address for the consensus protocol
     consensus_p2p_accept: Option<Url>,
@@ -89,10 +194,18 @@ struct Args {
     /// P2P external address for the consensus protocol
     consensus_p2p_external: Option<Url>,
 
+    #[structopt(long)]
+    /// Map the consensus protocol accept port on the gateway (NAT-PMP or UPnP)
+    consensus_port_mapping: bool,
+
     #[structopt(long, default_value = "8")]
     /// Connection slots for the consensus protocol
     consensus_slots: u32,
 
+    #[structopt(long, default_value = "0")]
+    /// Most bytes per second read from each consensus protocol peer (0 to disable)
+    consensus_recv_rate_limit: u64,
+
     #[structopt(long)]
     /// Connect to peer for the consensus protocol (repeatable flag)
     consensus_p2p_peer: Vec<Url>,
@@ -117,6 +230,10 @@ struct Args {
     /// P2P external address for the syncing protocol
     sync_p2p_external: Option<Url>,
 
+    #[structopt(long)]
+    /// Map the syncing protocol accept port on the gateway (NAT-PMP or UPnP)
+    sync_port_mapping: bool,
+
     #[structopt(long, default_value = "8")]
     /// Connection slots for the syncing protocol
     sync_slots: u32,
@@ -129,6 +246,23 @@ struct Args {
     /// Connect to seed for the syncing protocol (repeatable flag)
     sync_p2p_seed: Vec<Url>,
 
+    #[structopt(long)]
+    /// Pad syncing protocol messages to fixed sizes with peers doing the same
+    sync_channel_padding: bool,
+
+    #[structopt(long, default_value = "0")]
+    /// Mean seconds between syncing protocol cover messages (0 to disable)
+    sync_cover_traffic_seconds: u32,
+
+    #[structopt(long)]
+    /// Most bytes per second of a syncing protocol message type sent to
+    /// each peer, as `command:bytes` (repeatable flag)
+    sync_rate_limit: Vec<String>,
+
+    #[structopt(long, default_value = "0")]
+    /// Most bytes per second read from each syncing protocol peer (0 to disable)
+    sync_recv_rate_limit: u64,
+
     #[structopt(long)]
     /// Whitelisted cashier address (repeatable flag)
     cashier_pub: Vec<String>,
@@ -137,28 +271,112 @@ struct Args {
     /// Whitelisted faucet address (repeatable flag)
     faucet_pub: Vec<String>,
 
+    #[structopt(long)]
+    /// Drop network transactions larger than this many bytes
+    max_tx_size: Option<usize>,
+
+    #[structopt(long)]
+    /// Drop network transactions carrying a proof larger than this many bytes
+    max_proof_size: Option<usize>,
+
+    #[structopt(long)]
+    /// Drop network transactions with more inputs and outputs than this
+    max_tx_io: Option<usize>,
+
+    #[structopt(long, default_value = "0")]
+    /// Threads creating transaction proofs concurrently (0 for one per CPU)
+    prover_threads: usize,
+
+    #[structopt(long, default_value = "largest-first")]
+    /// How to pick the coins to spend (largest-first, smallest-first, privacy)
+    coin_selection: String,
+
+    #[structopt(long)]
+    /// Rebuild the block order, state indexes and wallet coins from the
+    /// stored blocks before starting
+    reindex: bool,
+
+    #[structopt(long)]
+    /// Delete the chain database and the wallet coins before starting,
+    /// keeping the wallet keys
+    wipe_chain: bool,
+
+    #[structopt(long)]
+    /// Take over the database locks left by an instance that crashed
+    force_unlock: bool,
+
     #[structopt(long)]
     /// Verify system clock is correct
     clock_sync: bool,
 
+    #[structopt(long, default_value = "2")]
+    /// Seconds of clock drift from the consensus peers to warn about
+    clock_drift_warn_seconds: u64,
+
+    #[structopt(long, default_value = "5")]
+    /// Seconds of clock drift from the consensus peers past which no
+    /// blocks are proposed
+    max_clock_drift_seconds: u64,
+
+    #[structopt(long)]
+    /// Check the configuration, databases, keys and ports, print a JSON
+    /// report and exit
+    check: bool,
+
+    #[structopt(long)]
+    /// With --check, also check the configured peers and seeds can be reached
+    check_upstreams: bool,
+
+    #[structopt(long)]
+    /// Detach from the terminal and run in the background
+    daemon: bool,
+
+    #[structopt(parse(try_from_str))]
+    /// Manage the instance running on the chain database instead: status or stop
+    service: Option<ServiceCommand>,
+
     #[structopt(short, parse(from_occurrences))]
     /// Increase verbosity (-vvv supported)
     verbose: u8,
 }
 
 pub struct Darkfid {
+    roles: Roles,
     synced: Mutex<bool>, // AtomicBool is weird in Arc
-    _consensus_p2p: Option<P2pPtr>,
+    consensus_p2p: Option<P2pPtr>,
     sync_p2p: Option<P2pPtr>,
     client: Arc<Client>,
     validator_state: ValidatorStatePtr,
+    wallets_dir: PathBuf,
+    wallet_pass: String,
+    metrics: Arc<Metrics>,
+    supervisor: SupervisorPtr,
+    acl: RpcAcl,
+    subscribers: Mutex<Vec<(EventFilter, async_channel::Sender<Value>)>>,
+    last_balances: Mutex<Option<Value>>,
+    /// Verifying keys of the state machine, shared with it
+    verifying_keys: VerifyingKeyRegistryPtr,
+    /// Circuits loaded by `circuits.load` when given no path
+    circuits_dir: Option<PathBuf>,
 }
 
 // JSON-RPC methods
 mod rpc_blockchain;
+mod rpc_circuits;
 mod rpc_misc;
+mod rpc_subscribe;
+mod rpc_tokens;
 mod rpc_tx;
 mod rpc_wallet;
+use rpc_subscribe::EventFilter;
+
+mod metrics;
+use metrics::{listen_and_serve_metrics, Metrics, MetricsSource};
+
+mod role;
+use role::Roles;
+
+mod check;
 
 #[async_trait]
 impl RequestHandler for Darkfid {
@@ -169,62 +387,221 @@ impl RequestHandler for Darkfid {
 
         let params = req.params.as_array().unwrap();
 
+        if let Some(method) = req.method.as_str() {
+            if !self.roles.serves(method) {
+                return JsonError::new(MethodNotFound, None, req.id).into()
+            }
+        }
+
         match req.method.as_str() {
             Some("ping") => return self.pong(req.id, params).await,
             Some("clock") => return self.clock(req.id, params).await,
+            Some("tasks") => return self.tasks(req.id, params).await,
+            Some("get_bandwidth_stats") => return self.get_bandwidth_stats(req.id, params).await,
             Some("blockchain.get_slot") => return self.get_slot(req.id, params).await,
             Some("blockchain.merkle_roots") => return self.merkle_roots(req.id, params).await,
+            Some("blockchain.verify_membership") => {
+                return self.verify_membership(req.id, params).await
+            }
+            Some("circuits.list") => return self.list_circuits(req.id, params).await,
+            Some("circuits.load") => return self.load_circuits(req.id, params).await,
+            Some("circuits.verify") => return self.verify_circuit_proof(req.id, params).await,
+            Some("tokens.refresh") => return self.refresh_tokens(req.id, params).await,
             Some("tx.transfer") => return self.transfer(req.id, params).await,
+            Some("tx.validate") => return self.validate_tx(req.id, params).await,
+            Some("tx.broadcast") => return self.broadcast_tx(req.id, params).await,
             Some("wallet.keygen") => return self.keygen(req.id, params).await,
             Some("wallet.get_key") => return self.get_key(req.id, params).await,
             Some("wallet.export_keypair") => return self.export_keypair(req.id, params).await,
             Some("wallet.import_keypair") => return self.import_keypair(req.id, params).await,
+            Some("wallet.create_mnemonic") => return self.create_mnemonic(req.id, params).await,
+            Some("wallet.restore_mnemonic") => return self.restore_mnemonic(req.id, params).await,
+            Some("wallet.derive_keypair") => return self.derive_keypair(req.id, params).await,
             Some("wallet.set_default_address") => {
                 return self.set_default_address(req.id, params).await
             }
             Some("wallet.get_balances") => return self.get_balances(req.id, params).await,
+            Some("wallet.get_tx_history") => return self.get_tx_history(req.id, params).await,
+            Some("wallet.prove_membership") => return self.prove_membership(req.id, params).await,
+            Some("wallet.create") => return self.create_wallet(req.id, params).await,
+            Some("wallet.list") => return self.list_wallets(req.id, params).await,
+            Some("wallet.switch") => return self.switch_wallet(req.id, params).await,
+            Some("wallet.export") => return self.export_wallet(req.id, params).await,
+            Some("wallet.import") => return self.import_wallet(req.id, params).await,
             Some(_) | None => return JsonError::new(MethodNotFound, None, req.id).into(),
         }
     }
+
+    fn acl(&self) -> Option<&RpcAcl> {
+        Some(&self.acl)
+    }
 }
 
 impl Darkfid {
     pub async fn new(
+        roles: Roles,
         validator_state: ValidatorStatePtr,
         consensus_p2p: Option<P2pPtr>,
         sync_p2p: Option<P2pPtr>,
+        wallets_dir: PathBuf,
+        wallet_pass: String,
+        metrics: Arc<Metrics>,
+        supervisor: SupervisorPtr,
+        acl: RpcAcl,
+        circuits_dir: Option<PathBuf>,
     ) -> Result<Self> {
         debug!("Waiting for validator state lock");
         let client = validator_state.read().await.client.clone();
+        let state_machine = validator_state.read().await.state_machine.clone();
+        let verifying_keys = state_machine.lock().await.verifying_keys.clone();
         debug!("Released validator state lock");
 
         Ok(Self {
+            roles,
             synced: Mutex::new(false),
-            _consensus_p2p: consensus_p2p,
+            consensus_p2p,
             sync_p2p,
             client,
             validator_state,
+            wallets_dir,
+            wallet_pass,
+            metrics,
+            supervisor,
+            acl,
+            subscribers: Mutex::new(vec![]),
+            last_balances: Mutex::new(None),
+            verifying_keys,
+            circuits_dir,
         })
     }
 }
 
-async_daemonize!(realmain);
-async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
-    if args.consensus && args.clock_sync {
-        // We verify that if peer/seed nodes are configured, their rpc config also exists
-        if ((!args.consensus_p2p_peer.is_empty() && args.consensus_peer_rpc.is_empty()) ||
-            (args.consensus_p2p_peer.is_empty() && !args.consensus_peer_rpc.is_empty())) ||
-            ((!args.consensus_p2p_seed.is_empty() && args.consensus_seed_rpc.is_empty()) ||
-                (args.consensus_p2p_seed.is_empty() && !args.consensus_seed_rpc.is_empty()))
-        {
-            error!(
-                "Consensus peer/seed nodes misconfigured: both p2p and rpc urls must be present"
-            );
+/// Open every named wallet found in `wallets_dir` alongside the default one.
+async fn open_named_wallets(client: &Client, wallets_dir: &Path, pass: &str) -> Result<()> {
+    if !wallets_dir.exists() {
+        return Ok(())
+    }
+
+    for entry in std::fs::read_dir(wallets_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("db") {
+            continue
+        }
+
+        let name = match path.file_stem().and_then(|n| n.to_str()) {
+            Some(v) => v.to_string(),
+            None => continue,
+        };
+
+        let wallet = init_wallet(path.to_str().unwrap(), pass).await?;
+        client.add_wallet(&name, wallet).await?;
+    }
+
+    Ok(())
+}
+
+/// Parse `command:bytes` rate limits of P2P message types.
+fn parse_rate_limits(limits: &[String]) -> Result<HashMap<String, u64>> {
+    let mut ret = HashMap::new();
+
+    for limit in limits {
+        match limit.split_once(':').map(|(c, b)| (c, b.parse::<u64>())) {
+            Some((command, Ok(bytes))) if !command.is_empty() => {
+                ret.insert(command.to_string(), bytes);
+            }
+            _ => {
+                error!("Invalid rate limit `{}`, expected `command:bytes`", limit);
+                return Err(Error::ConfigInvalid)
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+/// With clock sync, validators need the JSON-RPC URL of each kind of
+/// consensus node they're configured to connect to.
+fn check_clock_rpc(args: &Args) -> Result<()> {
+    // We verify that if peer/seed nodes are configured, their rpc config also exists
+    if ((!args.consensus_p2p_peer.is_empty() && args.consensus_peer_rpc.is_empty()) ||
+        (args.consensus_p2p_peer.is_empty() && !args.consensus_peer_rpc.is_empty())) ||
+        ((!args.consensus_p2p_seed.is_empty() && args.consensus_seed_rpc.is_empty()) ||
+            (args.consensus_p2p_seed.is_empty() && !args.consensus_seed_rpc.is_empty()))
+    {
+        error!("Consensus peer/seed nodes misconfigured: both p2p and rpc urls must be present");
+        return Err(Error::ConfigInvalid)
+    }
+
+    Ok(())
+}
+
+/// Parameters of the configured chain, with the localnet overrides and
+/// the genesis file applied
+fn chain_params(args: &Args) -> Result<(ChainParams, Option<Genesis>)> {
+    let mut params = match ChainParams::from_network(&args.chain) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Unsupported chain `{}`", args.chain);
+            return Err(e)
+        }
+    };
+
+    if args.localnet_genesis_ts.is_some() || args.localnet_delta.is_some() {
+        if args.chain != "localnet" {
+            error!("Chain parameters can only be overridden on localnet");
             return Err(Error::ConfigInvalid)
         }
+
+        if let Some(ts) = args.localnet_genesis_ts {
+            params.genesis_ts = Timestamp(ts);
+        }
+
+        if let Some(delta) = args.localnet_delta {
+            params.delta = delta;
+        }
+    }
+
+    let genesis = match &args.genesis {
+        Some(path) => Some(Genesis::load(&expand_path(path)?)?),
+        None => None,
+    };
+    if let Some(genesis) = &genesis {
+        if let Err(e) = genesis.apply(&mut params) {
+            error!("Genesis file is for `{}`, can't use it on `{}`", genesis.network, args.chain);
+            return Err(e)
+        }
+    }
+
+    Ok((params, genesis))
+}
+
+/// Directory of the chain's data
+fn data_dir(args: &Args) -> Result<DataDir> {
+    DataDir::new(args.data_dir.as_deref(), &args.chain)
+}
+
+/// Locked while an instance runs on the chain database
+fn pid_file(args: &Args) -> Result<PathBuf> {
+    match &args.database {
+        Some(v) => Ok(expand_path(v)?.join(format!("{}.pid", args.chain))),
+        None => Ok(data_dir(args)?.path().join("darkfid.pid")),
+    }
+}
+
+async_daemonize!(realmain, pid_file);
+async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
+    if args.check {
+        return check::self_check(&args).await
+    }
+
+    let roles = Roles::parse(&args.role, args.consensus)?;
+    info!("Running with roles: {:?}", roles);
+
+    if roles.validator && args.clock_sync {
+        check_clock_rpc(&args)?;
         // We verify that the system clock is valid before initializing
         let peers = [&args.consensus_peer_rpc[..], &args.consensus_seed_rpc[..]].concat();
-        if (check_clock(peers).await).is_err() {
+        if (check_clock(peers, args.socks5_proxy.clone()).await).is_err() {
             error!("System clock is invalid, terminating...");
             return Err(Error::InvalidClock)
         };
@@ -239,35 +616,77 @@ async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
     })
     .unwrap();
 
+    // Whatever isn't configured goes in the chain's data directory, where
+    // data left in the legacy locations is moved.
+    let data_dir = data_dir(&args)?;
+    let wallet_path = data_dir.locate(
+        args.wallet_path.as_deref(),
+        "darkfid_wallet.db",
+        "~/.config/darkfi/darkfid_wallet.db",
+    )?;
+    let db_path = match &args.database {
+        Some(v) => expand_path(v)?.join(&args.chain),
+        None => {
+            let legacy = format!("~/.config/darkfi/darkfid_blockchain/{}", args.chain);
+            data_dir.locate(None, "darkfid_blockchain", &legacy)?
+        }
+    };
+
+    // Held until we exit, so no other process opens the databases under us
+    let _wallet_lock = DbLock::acquire(&wallet_path, args.force_unlock)?;
+
     // Initialize or load wallet
-    let wallet = init_wallet(&args.wallet_path, &args.wallet_pass).await?;
+    let wallet = init_wallet(wallet_path.to_str().unwrap(), &args.wallet_pass).await?;
 
     // Initialize or open sled database
-    let db_path = format!("{}/{}", expand_path(&args.database)?.to_str().unwrap(), args.chain);
+    let _db_lock = DbLock::acquire(&db_path, args.force_unlock)?;
+    if args.wipe_chain {
+        wipe_chain(&db_path)?;
+    }
     let sled_db = sled::open(&db_path)?;
 
-    // Initialize validator state
-    let (genesis_ts, genesis_data) = match args.chain.as_str() {
-        "mainnet" => (*MAINNET_GENESIS_TIMESTAMP, *MAINNET_GENESIS_HASH_BYTES),
-        "testnet" => (*TESTNET_GENESIS_TIMESTAMP, *TESTNET_GENESIS_HASH_BYTES),
-        x => {
-            error!("Unsupported chain `{}`", x);
-            return Err(Error::UnsupportedChain)
-        }
-    };
+    // Select the chain parameters
+    let (params, genesis) = chain_params(&args)?;
 
     debug!("Parsing token lists...");
-    let tokenlist = Arc::new(DrkTokenList::new(&[
+    let tokenlist = DrkTokenList::new(&[
         ("drk", include_bytes!("../../../contrib/token/darkfi_token_list.min.json")),
         ("btc", include_bytes!("../../../contrib/token/bitcoin_token_list.min.json")),
         ("eth", include_bytes!("../../../contrib/token/erc20_token_list.min.json")),
         ("sol", include_bytes!("../../../contrib/token/solana_token_list.min.json")),
-    ])?);
+    ])?;
     debug!("Finished parsing token lists");
 
+    // Lists fetched at runtime replace the built-in ones of their network,
+    // starting with the ones cached by the last run
+    let mut tokens = TokenRegistry::new(tokenlist);
+    let token_sources =
+        args.token_list.iter().map(|s| TokenListSource::from_str(s)).collect::<Result<_>>()?;
+    tokens.set_sources(token_sources, data_dir.path().join("darkfid_token_lists"));
+    tokens.set_proxy(args.socks5_proxy.clone());
+    let loaded = tokens.load_cache()?;
+    if !loaded.is_empty() {
+        info!("Loaded cached token lists of {:?}", loaded);
+    }
+    let tokens = Arc::new(tokens);
+
     // TODO: sqldb init cleanup
     // Initialize Client
-    let client = Arc::new(Client::new(wallet, tokenlist).await?);
+    let mut client = Client::new(wallet, tokens.clone()).await?;
+    client.set_prover_threads(args.prover_threads);
+    client.set_coin_selection(CoinSelection::from_str(&args.coin_selection)?);
+    let client = Arc::new(client);
+
+    // Open the named wallets, and select the one to start with
+    let wallets_dir = data_dir.locate(
+        args.wallets_dir.as_deref(),
+        "darkfid_wallets",
+        "~/.config/darkfi/darkfid_wallets",
+    )?;
+    open_named_wallets(&client, &wallets_dir, &args.wallet_pass).await?;
+    if let Some(name) = &args.wallet {
+        client.switch_wallet(name).await?;
+    }
 
     // Parse cashier addresses
     let mut cashier_pubkeys = vec![];
@@ -285,66 +704,138 @@ async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
         faucet_pubkeys.push(pk);
     }
 
+    if let Some(genesis) = &genesis {
+        cashier_pubkeys.extend(genesis.cashier_pubkeys()?);
+        faucet_pubkeys.extend(genesis.faucet_pubkeys()?);
+    }
+
+    // Load the node keys, kept apart from the wallet
+    let keys_dir =
+        data_dir.locate(args.keys_dir.as_deref(), "darkfid_keys", "~/.config/darkfi/darkfid_keys")?;
+    let keystore = KeyStore::new(&keys_dir)?;
+    let identity_key = match args.rotate_identity_key {
+        true => keystore.rotate_identity_key()?,
+        false => keystore.identity_key()?,
+    };
+    let consensus_key = match args.rotate_consensus_key {
+        true => keystore.rotate_consensus_key()?,
+        false => keystore.consensus_key()?,
+    };
+
     // Initialize validator state
     let state = ValidatorState::new(
         &sled_db,
-        genesis_ts,
-        genesis_data,
+        params,
         client,
+        consensus_key,
         cashier_pubkeys,
         faucet_pubkeys,
     )
     .await?;
 
+    // Verifying keys of circuits added or upgraded since this release
+    let circuits_dir = match &args.circuits_dir {
+        Some(v) => Some(expand_path(v)?),
+        None => None,
+    };
+    if let Some(dir) = &circuits_dir {
+        let verifying_keys = state.read().await.state_machine.lock().await.verifying_keys.clone();
+        match verifying_keys.load_dir(dir) {
+            Ok(v) => info!("Loaded verifying keys of circuits {:?} from {:?}", v, dir),
+            Err(e) => error!("Failed loading circuits from {:?}: {}", dir, e),
+        }
+    }
+
+    // A wiped chain only has its genesis block, so reindexing it clears
+    // the coins of the old chain from the wallets.
+    if args.reindex || args.wipe_chain {
+        state.read().await.reindex().await?;
+    }
+
+    // Filters for transactions received from the network
+    {
+        let mut state = state.write().await;
+        if let Some(limit) = args.max_tx_size {
+            state.tx_filters.push(tx_filter::max_tx_size(limit));
+        }
+        if let Some(limit) = args.max_proof_size {
+            state.tx_filters.push(tx_filter::max_proof_size(limit));
+        }
+        if let Some(limit) = args.max_tx_io {
+            state.tx_filters.push(tx_filter::max_io(limit));
+        }
+    }
+
     let sync_p2p = {
-        info!("Registering block sync P2P protocols...");
-        let sync_network_settings = net::Settings {
-            inbound: args.sync_p2p_accept,
-            outbound_connections: args.sync_slots,
-            external_addr: args.sync_p2p_external,
-            peers: args.sync_p2p_peer.clone(),
-            seeds: args.sync_p2p_seed.clone(),
-            ..Default::default()
-        };
+        if !roles.sync {
+            None
+        } else {
+            info!("Registering block sync P2P protocols...");
+            let hosts_file = data_dir.path().join("darkfid_sync_hosts");
+            let sync_network_settings = net::Settings {
+                inbound: args.sync_p2p_accept,
+                outbound_connections: args.sync_slots,
+                external_addr: args.sync_p2p_external,
+                port_mapping: args.sync_port_mapping,
+                socks5_proxy: args.socks5_proxy.clone(),
+                hosts_file: Some(hosts_file.display().to_string()),
+                peers: args.sync_p2p_peer.clone(),
+                seeds: args.sync_p2p_seed.clone(),
+                channel_padding: args.sync_channel_padding,
+                cover_traffic_seconds: args.sync_cover_traffic_seconds,
+                // Transactions are resent to peers reconnecting shortly after
+                persistent_messages: vec!["tx".to_string()],
+                rate_limits: parse_rate_limits(&args.sync_rate_limit)?,
+                recv_rate_limit: args.sync_recv_rate_limit,
+                node_id: identity_key.node_id(),
+                ..Default::default()
+            };
 
-        let p2p = net::P2p::new(sync_network_settings).await;
-        let registry = p2p.protocol_registry();
+            let p2p = net::P2p::new(sync_network_settings).await;
+            let registry = p2p.protocol_registry();
 
-        let _state = state.clone();
-        registry
-            .register(net::SESSION_ALL, move |channel, p2p| {
-                let state = _state.clone();
-                async move {
-                    ProtocolSync::init(channel, state, p2p, args.consensus)
-                        .await
-                        .unwrap()
-                }
-            })
-            .await;
+            let _state = state.clone();
+            registry
+                .register(net::SESSION_ALL, move |channel, p2p| {
+                    let state = _state.clone();
+                    async move {
+                        ProtocolSync::init(channel, state, p2p, roles.validator).await.unwrap()
+                    }
+                })
+                .await;
 
-        let _state = state.clone();
-        registry
-            .register(net::SESSION_ALL, move |channel, p2p| {
-                let state = _state.clone();
-                async move { ProtocolTx::init(channel, state, p2p).await.unwrap() }
-            })
-            .await;
-
-        Some(p2p)
+            let _state = state.clone();
+            registry
+                .register(net::SESSION_ALL, move |channel, p2p| {
+                    let state = _state.clone();
+                    async move { ProtocolTx::init(channel, state, p2p).await.unwrap() }
+                })
+                .await;
+
+            Some(p2p)
+        }
     };
 
     // P2P network settings for the consensus protocol
     let consensus_p2p = {
-        if !args.consensus {
+        if !roles.validator {
             None
         } else {
             info!("Registering consensus P2P protocols...");
+            let hosts_file = data_dir.path().join("darkfid_consensus_hosts");
             let consensus_network_settings = net::Settings {
                 inbound: args.consensus_p2p_accept,
                 outbound_connections: args.consensus_slots,
                 external_addr: args.consensus_p2p_external,
+                port_mapping: args.consensus_port_mapping,
+                socks5_proxy: args.socks5_proxy.clone(),
+                hosts_file: Some(hosts_file.display().to_string()),
                 peers: args.consensus_p2p_peer.clone(),
                 seeds: args.consensus_p2p_seed.clone(),
+                // Votes are resent to peers reconnecting shortly after
+                persistent_messages: vec!["vote".to_string()],
+                recv_rate_limit: args.consensus_recv_rate_limit,
+                node_id: identity_key.node_id(),
                 ..Default::default()
             };
             let p2p = net::P2p::new(consensus_network_settings).await;
@@ -380,6 +871,14 @@ async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
                 })
                 .await;
 
+            let _state = state.clone();
+            registry
+                .register(net::SESSION_ALL, move |channel, p2p| {
+                    let state = _state.clone();
+                    async move { ProtocolRemoval::init(channel, state, p2p).await.unwrap() }
+                })
+                .await;
+
             let _state = state.clone();
             registry
                 .register(net::SESSION_ALL, move |channel, p2p| {
@@ -388,49 +887,170 @@ async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
                 })
                 .await;
 
+            let _state = state.clone();
+            registry
+                .register(net::SESSION_ALL, move |channel, p2p| {
+                    let state = _state.clone();
+                    async move { ProtocolSyncBlocks::init(channel, state, p2p).await.unwrap() }
+                })
+                .await;
+
+            // Pending transactions are gossiped among participants too
+            let _state = state.clone();
+            registry
+                .register(net::SESSION_ALL, move |channel, p2p| {
+                    let state = _state.clone();
+                    async move { ProtocolTx::init(channel, state, p2p).await.unwrap() }
+                })
+                .await;
+
             Some(p2p)
         }
     };
 
     // Initialize program state
-    let darkfid = Darkfid::new(state.clone(), consensus_p2p.clone(), sync_p2p.clone()).await?;
+    let metrics = Arc::new(Metrics::default());
+    let supervisor = Supervisor::new();
+    let darkfid = Darkfid::new(
+        roles,
+        state.clone(),
+        consensus_p2p.clone(),
+        sync_p2p.clone(),
+        wallets_dir,
+        args.wallet_pass.clone(),
+        metrics.clone(),
+        supervisor.clone(),
+        RpcAcl::new(args.rpc_auth_token.clone(), args.rpc_open_methods.clone())
+            .restrict(&["circuits.load"]),
+        circuits_dir,
+    )
+    .await?;
     let darkfid = Arc::new(darkfid);
 
     // JSON-RPC server
-    info!("Starting JSON-RPC server");
-    ex.spawn(listen_and_serve(args.rpc_listen, darkfid.clone())).detach();
-
-    info!("Starting sync P2P network");
-    sync_p2p.clone().unwrap().start(ex.clone()).await?;
-    let _ex = ex.clone();
-    let _sync_p2p = sync_p2p.clone();
-    ex.spawn(async move {
-        if let Err(e) = _sync_p2p.unwrap().run(_ex).await {
-            error!("Failed starting sync P2P network: {}", e);
+    let tls_files = match (&args.rpc_tls_cert, &args.rpc_tls_key) {
+        (Some(cert), Some(key)) => Some((expand_path(cert)?, expand_path(key)?)),
+        (None, None) => None,
+        _ => {
+            error!("Both rpc_tls_cert and rpc_tls_key have to be set");
+            return Err(Error::ConfigInvalid)
         }
-    })
-    .detach();
+    };
+
+    info!("Starting JSON-RPC server");
+    let rpc_listen = [&[args.rpc_listen.clone()], &args.rpc_extra_listen[..]].concat();
+    for url in rpc_listen {
+        let _darkfid = darkfid.clone();
+        let _tls_files = tls_files.clone();
+        let name = format!("rpc {}", url);
+        supervisor.spawn(&ex, &name, RestartPolicy::OnFailure, move || {
+            listen_and_serve_with_tls(url.clone(), _darkfid.clone(), _tls_files.clone())
+        });
+    }
+
+    // WebSocket JSON-RPC server, publishing state events to subscribers
+    if let Some(rpc_ws_listen) = args.rpc_ws_listen {
+        info!("Starting WebSocket JSON-RPC server");
+        let _darkfid = darkfid.clone();
+        supervisor.spawn(&ex, "state events", RestartPolicy::Always, move || {
+            _darkfid.clone().publish_state_events()
+        });
+
+        let _darkfid = darkfid.clone();
+        let name = format!("rpc {}", rpc_ws_listen);
+        supervisor.spawn(&ex, &name, RestartPolicy::OnFailure, move || {
+            listen_and_serve_ws(rpc_ws_listen.clone(), _darkfid.clone())
+        });
+    }
+
+    // Metrics endpoint
+    if let Some(metrics_listen) = args.metrics_listen {
+        info!("Starting metrics server");
+        let source = Arc::new(MetricsSource {
+            metrics,
+            supervisor: supervisor.clone(),
+            validator_state: state.clone(),
+            sync_p2p: sync_p2p.clone(),
+            consensus_p2p: consensus_p2p.clone(),
+            sled_db: sled_db.clone(),
+        });
+        supervisor.spawn(&ex, "metrics", RestartPolicy::OnFailure, move || {
+            listen_and_serve_metrics(metrics_listen.clone(), source.clone())
+        });
+    }
 
-    match block_sync_task(sync_p2p.clone().unwrap(), state.clone()).await {
-        Ok(()) => *darkfid.synced.lock().await = true,
-        Err(e) => error!("Failed syncing blockchain: {}", e),
+    // Token lists fetched at runtime
+    if args.token_list_refresh > 0 && !args.token_list.is_empty() {
+        let interval = args.token_list_refresh;
+        supervisor.spawn(&ex, "token lists", RestartPolicy::OnFailure, move || {
+            tokens.clone().refresh_loop(interval)
+        });
+    }
+
+    if let Some(p2p) = sync_p2p.clone() {
+        info!("Starting sync P2P network");
+        p2p.clone().start(ex.clone()).await?;
+        let _ex = ex.clone();
+        let _p2p = p2p.clone();
+        supervisor
+            .spawn(&ex, "sync p2p", RestartPolicy::Never, move || _p2p.clone().run(_ex.clone()));
+
+        match block_sync_task(p2p, state.clone()).await {
+            Ok(()) => *darkfid.synced.lock().await = true,
+            Err(e) => error!("Failed syncing blockchain: {}", e),
+        }
+    } else {
+        info!("Not starting sync P2P network");
     }
 
     // Consensus protocol
-    if args.consensus && *darkfid.synced.lock().await {
+    if roles.validator && *darkfid.synced.lock().await {
         info!("Starting consensus P2P network");
         consensus_p2p.clone().unwrap().start(ex.clone()).await?;
+        let (consensus_p2p, sync_p2p) = (consensus_p2p.clone().unwrap(), sync_p2p.clone().unwrap());
         let _ex = ex.clone();
         let _consensus_p2p = consensus_p2p.clone();
-        ex.spawn(async move {
-            if let Err(e) = _consensus_p2p.unwrap().run(_ex).await {
-                error!("Failed starting consensus P2P network: {}", e);
+        supervisor.spawn(&ex, "consensus p2p", RestartPolicy::Never, move || {
+            _consensus_p2p.clone().run(_ex.clone())
+        });
+
+        info!("Starting slashing task");
+        let _consensus_p2p = consensus_p2p.clone();
+        let _state = state.clone();
+        supervisor.spawn(&ex, "slashing", RestartPolicy::Always, move || {
+            let task = slashing_task(_consensus_p2p.clone(), _state.clone());
+            async move {
+                task.await;
+                Ok(())
             }
-        })
-        .detach();
+        });
+
+        // Track the clock's drift from the consensus peers
+        let clock_peers = [&args.consensus_peer_rpc[..], &args.consensus_seed_rpc[..]].concat();
+        if !clock_peers.is_empty() {
+            info!("Starting clock drift task");
+            let clock_drift =
+                ClockDrift::new(args.clock_drift_warn_seconds, args.max_clock_drift_seconds);
+            state.write().await.clock_drift = clock_drift.clone();
+            let proxy = args.socks5_proxy.clone();
+            supervisor.spawn(&ex, "clock drift", RestartPolicy::Always, move || {
+                drift_task(
+                    clock_peers.clone(),
+                    proxy.clone(),
+                    clock_drift.clone(),
+                    CLOCK_DRIFT_INTERVAL,
+                )
+            });
+        }
 
         info!("Starting consensus protocol task");
-        ex.spawn(proposal_task(consensus_p2p.unwrap(), sync_p2p.unwrap(), state)).detach();
+        supervisor.spawn(&ex, "consensus", RestartPolicy::Always, move || {
+            let task = proposal_task(consensus_p2p.clone(), sync_p2p.clone(), state.clone());
+            async move {
+                task.await;
+                Ok(())
+            }
+        });
     } else {
         info!("Not starting consensus P2P network");
     }
@@ -440,6 +1060,11 @@ async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
     print!("\r");
     info!("Caught termination signal, cleaning up and exiting...");
 
+    // Stopping deletes the port mappings on the gateway
+    for p2p in [&sync_p2p, &consensus_p2p].into_iter().flatten() {
+        p2p.stop().await;
+    }
+
     info!("Flushing database...");
     let flushed_bytes = sled_db.flush_async().await?;
     info!("Flushed {} bytes", flushed_bytes);
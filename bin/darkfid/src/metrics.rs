@@ -0,0 +1,153 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use async_std::{
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+};
+use futures_lite::{AsyncReadExt, AsyncWriteExt};
+use log::{debug, error, info};
+use url::Url;
+
+use darkfi::{
+    consensus::state::ValidatorStatePtr,
+    net::P2pPtr,
+    util::supervisor::{SupervisorPtr, TaskStatus},
+    Error, Result,
+};
+
+/// Counters kept while the node runs, exposed next to the gauges read
+/// from the node state whenever the metrics endpoint is scraped.
+#[derive(Default)]
+pub struct Metrics {
+    txs_published: AtomicU64,
+}
+
+impl Metrics {
+    /// Count a transaction broadcast to the sync network over RPC.
+    pub fn tx_published(&self) {
+        self.txs_published.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Everything the metrics endpoint reads from.
+pub struct MetricsSource {
+    pub metrics: Arc<Metrics>,
+    pub supervisor: SupervisorPtr,
+    pub validator_state: ValidatorStatePtr,
+    pub sync_p2p: Option<P2pPtr>,
+    pub consensus_p2p: Option<P2pPtr>,
+    pub sled_db: sled::Db,
+}
+
+impl MetricsSource {
+    /// Render the metrics in the Prometheus text exposition format.
+    async fn render(&self) -> Result<String> {
+        let (blocks, last_slot, unconfirmed_txs, clock_drift) = {
+            let state = self.validator_state.read().await;
+            let (last_slot, _) = state.blockchain.last()?;
+            let clock_drift = state.clock_drift.offset();
+            (state.blockchain.order.len(), last_slot, state.mempool.len(), clock_drift)
+        };
+
+        let sync_peers = match &self.sync_p2p {
+            Some(p2p) => p2p.channels().lock().await.len(),
+            None => 0,
+        };
+
+        let consensus_peers = match &self.consensus_p2p {
+            Some(p2p) => p2p.channels().lock().await.len(),
+            None => 0,
+        };
+
+        let db_size = self.sled_db.size_on_disk()?;
+        let txs_published = self.metrics.txs_published.load(Ordering::Relaxed);
+
+        let metrics = [
+            ("darkfid_blocks_stored", "gauge", "Blocks in the local chain", blocks as u64),
+            ("darkfid_last_slot", "gauge", "Slot of the last block", last_slot),
+            ("darkfid_unconfirmed_txs", "gauge", "Pending transactions", unconfirmed_txs as u64),
+            ("darkfid_sync_peers", "gauge", "Sync P2P peers", sync_peers as u64),
+            ("darkfid_consensus_peers", "gauge", "Consensus P2P peers", consensus_peers as u64),
+            ("darkfid_txs_published_total", "counter", "Transactions broadcast", txs_published),
+            ("darkfid_database_size_bytes", "gauge", "Size of the sled database", db_size),
+        ];
+
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        // Signed, so it doesn't fit with the others. Left out until measured.
+        if let Some(drift) = clock_drift {
+            let name = "darkfid_clock_drift_seconds";
+            let _ = writeln!(out, "# HELP {} System clock offset from the peers' median", name);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            let _ = writeln!(out, "{} {}", name, drift);
+        }
+
+        let tasks = self.supervisor.status().await;
+        let task_metrics: [(&str, &str, fn(&TaskStatus) -> u64); 2] = [
+            ("darkfid_task_crashes_total", "Supervised task failures and panics", |t| t.failures),
+            ("darkfid_task_restarts_total", "Supervised task restarts", |t| t.restarts),
+        ];
+        for (name, help, value) in task_metrics {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for task in &tasks {
+                let _ = writeln!(out, "{}{{task=\"{}\"}} {}", name, task.name, value(task));
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Serve the metrics over plain HTTP on `listen`. Every request gets the
+/// metrics back, whatever its path.
+pub async fn listen_and_serve_metrics(listen: Url, source: Arc<MetricsSource>) -> Result<()> {
+    let host = listen.host_str().ok_or(Error::ConfigInvalid)?;
+    let port = listen.port().ok_or(Error::ConfigInvalid)?;
+
+    let listener = TcpListener::bind((host, port)).await?;
+    info!("Metrics listening on {}", listen);
+
+    loop {
+        let (stream, peer_addr) = match listener.accept().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed accepting metrics connection: {}", e);
+                continue
+            }
+        };
+
+        debug!("Serving metrics to {}", peer_addr);
+        let source = source.clone();
+        async_std::task::spawn(async move {
+            if let Err(e) = serve_metrics(stream, &source).await {
+                error!("Failed serving metrics to {}: {}", peer_addr, e);
+            }
+        });
+    }
+}
+
+async fn serve_metrics(mut stream: TcpStream, source: &MetricsSource) -> Result<()> {
+    // We don't care about the request, just wait for it to show up.
+    let mut buf = [0u8; 1024];
+    let _ = stream.read(&mut buf).await?;
+
+    let body = source.render().await?;
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use log::error;
+
+use darkfi::{Error, Result};
+
+/// Subsystems a darkfid instance runs, selected with the `role` option.
+/// Everything enabled shares the same executor, databases, JSON-RPC
+/// server and P2P stack. The bridge isn't one of them yet: it lives in
+/// cashierd, and becomes a role once it's moved into the library.
+#[derive(Clone, Copy, Debug)]
+pub struct Roles {
+    /// Wallet and transaction building JSON-RPC methods
+    pub wallet: bool,
+    /// Block sync and transaction relay P2P network
+    pub sync: bool,
+    /// Consensus participation
+    pub validator: bool,
+    /// Relaying transactions built by light clients to the sync network
+    pub gateway: bool,
+}
+
+impl Roles {
+    /// Parse the configured roles. With none configured we keep the old
+    /// defaults of a wallet node following the chain. `consensus` is kept
+    /// as a shorthand for the validator role.
+    pub fn parse(roles: &[String], consensus: bool) -> Result<Self> {
+        let mut ret = Self {
+            wallet: roles.is_empty(),
+            sync: roles.is_empty(),
+            validator: false,
+            gateway: false,
+        };
+
+        for role in roles {
+            match role.as_str() {
+                "wallet" => ret.wallet = true,
+                "sync" => ret.sync = true,
+                "validator" => ret.validator = true,
+                "gateway" => ret.gateway = true,
+                x => {
+                    error!("Unknown role `{}`", x);
+                    return Err(Error::ConfigInvalid)
+                }
+            }
+        }
+
+        ret.validator |= consensus;
+
+        // Validators relay votes and finalized blocks over the sync network
+        if ret.validator && !ret.sync {
+            error!("The validator role requires the sync role");
+            return Err(Error::ConfigInvalid)
+        }
+
+        // Gateways relay what they're sent over the sync network
+        if ret.gateway && !ret.sync {
+            error!("The gateway role requires the sync role");
+            return Err(Error::ConfigInvalid)
+        }
+
+        Ok(ret)
+    }
+
+    /// Whether a JSON-RPC method is served with these roles
+    pub fn serves(&self, method: &str) -> bool {
+        if method.starts_with("wallet.") || method == "tx.transfer" {
+            return self.wallet
+        }
+
+        if method == "tx.broadcast" {
+            return self.gateway
+        }
+
+        true
+    }
+}
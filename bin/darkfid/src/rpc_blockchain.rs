@@ -1,12 +1,20 @@
 use log::{debug, error};
+use pasta_curves::group::ff::PrimeField;
 use serde_json::{json, Value};
 
 use darkfi::{
-    crypto::merkle_node::MerkleNode,
+    crypto::{
+        membership_proof::{
+            membership_challenge, membership_scope, MembershipProof, MEMBERSHIP_ROOT_WINDOW,
+        },
+        merkle_node::MerkleNode,
+    },
+    node::vk_registry::MEMBERSHIP_CIRCUIT_ID,
     rpc::jsonrpc::{
         ErrorCode::{InternalError, InvalidParams},
         JsonError, JsonResponse, JsonResult,
     },
+    util::serial::deserialize,
 };
 
 use super::Darkfid;
@@ -51,15 +59,85 @@ impl Darkfid {
     // --> {"jsonrpc": "2.0", "method": "blockchain.merkle_roots", "params": [], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": [..., ..., ...], "id": 1}
     pub async fn merkle_roots(&self, id: Value, _params: &[Value]) -> JsonResult {
-        let roots: Vec<MerkleNode> =
-            match self.validator_state.read().await.blockchain.merkle_roots.get_all() {
-                Ok(v) => v,
-                Err(e) => {
-                    error!("Failed getting merkle roots from rootstore: {}", e);
-                    return JsonError::new(InternalError, None, id).into()
-                }
-            };
+        let blockchain = self.validator_state.read().await.async_blockchain.clone();
+        let roots: Vec<MerkleNode> = match blockchain.run(|b| b.merkle_roots.get_all()).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed getting merkle roots from rootstore: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
 
         JsonResponse::new(json!(roots), id).into()
     }
+
+    // RPCAPI:
+    // Check a base58-encoded proof from `wallet.prove_membership` for the
+    // given scope and challenge, and that it was made against the current
+    // Merkle root of the chain, or one of the last 10 slots. Returns the token
+    // the prover owns a coin of, and the coin's nullifier for the scope, which
+    // is the same for every proof of that coin for the scope. The coin is
+    // only known to be in the tree with that root, it may have been spent in
+    // the slots since.
+    // --> {"jsonrpc": "2.0", "method": "blockchain.verify_membership", "params": ["proof...", "dao.example", "challenge"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"token_id": "Ay1...", "nullifier": "5Hn..."}, "id": 1}
+    pub async fn verify_membership(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 3 || !params.iter().all(|x| x.is_string()) {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let proof: MembershipProof = match bs58::decode(params[0].as_str().unwrap())
+            .into_vec()
+            .map_err(|e| e.to_string())
+            .and_then(|x| deserialize(&x).map_err(|e| e.to_string()))
+        {
+            Ok(v) => v,
+            Err(e) => {
+                error!("verify_membership(): Failed decoding proof: {}", e);
+                return server_error(RpcError::ParseError, id)
+            }
+        };
+
+        let revealed = &proof.revealed;
+        if revealed.scope != membership_scope(params[1].as_str().unwrap()) ||
+            revealed.challenge != membership_challenge(params[2].as_str().unwrap().as_bytes())
+        {
+            error!("verify_membership(): Proof is for another scope or challenge");
+            return server_error(RpcError::InvalidMembershipProof, id)
+        }
+
+        match self
+            .validator_state
+            .read()
+            .await
+            .is_recent_root(&revealed.merkle_root, MEMBERSHIP_ROOT_WINDOW)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                error!("verify_membership(): Unknown or outdated Merkle root");
+                return server_error(RpcError::InvalidMembershipProof, id)
+            }
+            Err(e) => {
+                error!("verify_membership(): Failed querying rootstore: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        }
+
+        let vk = match self.verifying_keys.get(MEMBERSHIP_CIRCUIT_ID) {
+            Some(v) => v,
+            None => {
+                error!("verify_membership(): No verifying key for membership proofs");
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+        if let Err(e) = proof.verify(&vk) {
+            error!("verify_membership(): Failed verifying proof: {}", e);
+            return server_error(RpcError::InvalidMembershipProof, id)
+        }
+
+        let token_id = bs58::encode(revealed.token_id.to_repr()).into_string();
+        let nullifier = bs58::encode(revealed.nullifier.to_bytes()).into_string();
+        JsonResponse::new(json!({"token_id": token_id, "nullifier": nullifier}), id).into()
+    }
 }
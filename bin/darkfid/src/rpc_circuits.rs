@@ -0,0 +1,136 @@
+use std::path::PathBuf;
+
+use log::{error, info};
+use pasta_curves::pallas;
+use serde_json::{json, Value};
+
+use darkfi::{
+    crypto::proof::Proof,
+    rpc::jsonrpc::{ErrorCode::InvalidParams, JsonError, JsonResponse, JsonResult},
+    util::{
+        expand_path,
+        serial::{deserialize, Decodable},
+    },
+};
+
+use super::Darkfid;
+use crate::{server_error, RpcError};
+
+impl Darkfid {
+    // RPCAPI:
+    // Returns the IDs of the circuits the node has verifying keys for.
+    // The built-in circuits are only listed once a key was built for them.
+    // --> {"jsonrpc": "2.0", "method": "circuits.list", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": ["burn", "mint"], "id": 1}
+    pub async fn list_circuits(&self, id: Value, _params: &[Value]) -> JsonResult {
+        JsonResponse::new(json!(self.verifying_keys.circuit_ids()), id).into()
+    }
+
+    // RPCAPI:
+    // Build the verifying keys of the compiled zkas circuits in a directory,
+    // or of a single one, without restarting the node. Each is registered
+    // under its file name up to the first dot, replacing the key a circuit
+    // loaded earlier had, and `circuits.verify` checks proofs against it from
+    // then on. The built-in mint, burn and membership circuits can't be
+    // replaced. Files of a directory that fail to load are logged and
+    // skipped. Without a path, the configured `circuits_dir` is loaded again.
+    // Needs the `rpc_auth_token`, and is refused if none is set.
+    // Returns the IDs of the circuits loaded.
+    // --> {"jsonrpc": "2.0", "method": "circuits.load", "params": ["~/circuits/mint.zk.bin"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": ["mint"], "id": 1}
+    pub async fn load_circuits(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() > 1 || (params.len() == 1 && !params[0].is_string()) {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let path: PathBuf = match params.first() {
+            Some(v) => match expand_path(v.as_str().unwrap()) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("load_circuits(): Failed expanding path: {}", e);
+                    return JsonError::new(InvalidParams, None, id).into()
+                }
+            },
+            None => match &self.circuits_dir {
+                Some(v) => v.clone(),
+                None => {
+                    error!("load_circuits(): No path given and no circuits_dir configured");
+                    return JsonError::new(InvalidParams, None, id).into()
+                }
+            },
+        };
+
+        // Building keys takes a while, keep it off the executor
+        let verifying_keys = self.verifying_keys.clone();
+        let loaded = async_std::task::spawn_blocking(move || match path.is_dir() {
+            true => verifying_keys.load_dir(&path),
+            false => verifying_keys.load_file(&path).map(|x| vec![x]),
+        })
+        .await;
+
+        match loaded {
+            Ok(v) => {
+                info!("Loaded verifying keys of circuits {:?}", v);
+                JsonResponse::new(json!(v), id).into()
+            }
+            Err(e) => {
+                error!("load_circuits(): Failed loading circuits: {}", e);
+                server_error(RpcError::CircuitLoadFail, id)
+            }
+        }
+    }
+
+    // RPCAPI:
+    // Check a base58-encoded proof of a circuit the node has a verifying key
+    // for, with its public inputs given as base58-encoded field elements.
+    // Returns `true` if the proof is valid, and `false` otherwise.
+    // --> {"jsonrpc": "2.0", "method": "circuits.verify", "params": ["arithmetic", "proof...", ["2Jq...", "8Wk..."]], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    pub async fn verify_circuit_proof(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 3 || !params[0].is_string() || !params[1].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let circuit_id = params[0].as_str().unwrap();
+        let vk = match self.verifying_keys.get(circuit_id) {
+            Some(v) => v,
+            None => {
+                error!("verify_circuit_proof(): No verifying key for circuit {}", circuit_id);
+                return server_error(RpcError::UnknownCircuit, id)
+            }
+        };
+
+        let proof: Proof = match decode_base58(&params[1]) {
+            Some(v) => v,
+            None => {
+                error!("verify_circuit_proof(): Failed decoding proof");
+                return server_error(RpcError::ParseError, id)
+            }
+        };
+
+        let public_inputs: Option<Vec<pallas::Base>> = match params[2].as_array() {
+            Some(v) => v.iter().map(decode_base58).collect(),
+            None => return JsonError::new(InvalidParams, None, id).into(),
+        };
+        let public_inputs = match public_inputs {
+            Some(v) => v,
+            None => {
+                error!("verify_circuit_proof(): Failed decoding public inputs");
+                return server_error(RpcError::ParseError, id)
+            }
+        };
+
+        // Verifying takes a while, keep it off the executor
+        let valid =
+            async_std::task::spawn_blocking(move || proof.verify(&vk, &public_inputs).is_ok())
+                .await;
+
+        JsonResponse::new(json!(valid), id).into()
+    }
+}
+
+/// Decode a base58-encoded serialized value
+fn decode_base58<T: Decodable>(value: &Value) -> Option<T> {
+    let bytes = bs58::decode(value.as_str()?).into_vec().ok()?;
+    deserialize(&bytes).ok()
+}
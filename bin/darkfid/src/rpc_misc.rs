@@ -23,4 +23,33 @@ impl Darkfid {
     pub async fn clock(&self, id: Value, _params: &[Value]) -> JsonResult {
         JsonResponse::new(json!(Timestamp::current_time()), id).into()
     }
+
+    // RPCAPI:
+    // Returns the status of the node's supervised tasks, ordered by name.
+    // `state` is one of `running`, `restarting` or `failed`.
+    // --> {"jsonrpc": "2.0", "method": "tasks", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"name": "consensus", "policy": "always", "state": "running", "restarts": 0, "failures": 0, "last_error": null}, ...], "id": 1}
+    pub async fn tasks(&self, id: Value, _params: &[Value]) -> JsonResult {
+        JsonResponse::new(json!(self.supervisor.status().await), id).into()
+    }
+
+    // RPCAPI:
+    // Returns the bytes sent and received on each P2P channel by message type,
+    // and the bytes per second going through each channel lately, for the
+    // syncing and consensus networks this node runs.
+    // --> {"jsonrpc": "2.0", "method": "get_bandwidth_stats", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"sync": {"channels": [...], "messages": {...}, "total": {...}, "throughput": {...}}, "consensus": null}, "id": 1}
+    pub async fn get_bandwidth_stats(&self, id: Value, _params: &[Value]) -> JsonResult {
+        let sync = match &self.sync_p2p {
+            Some(p2p) => p2p.get_bandwidth_stats().await,
+            None => Value::Null,
+        };
+
+        let consensus = match &self.consensus_p2p {
+            Some(p2p) => p2p.get_bandwidth_stats().await,
+            None => Value::Null,
+        };
+
+        JsonResponse::new(json!({ "sync": sync, "consensus": consensus }), id).into()
+    }
 }
@@ -0,0 +1,224 @@
+use std::str::FromStr;
+
+use async_std::sync::Arc;
+use async_trait::async_trait;
+use log::{debug, error};
+use pasta_curves::group::ff::PrimeField;
+use serde_json::{json, Value};
+
+use darkfi::{
+    crypto::{address::Address, keypair::PublicKey},
+    node::state::StateEvent,
+    rpc::{
+        jsonrpc::{
+            ErrorCode::{InvalidParams, MethodNotFound},
+            JsonError, JsonRequest,
+        },
+        ws_server::{SubscriptionHandler, WsSubscription},
+    },
+    Result,
+};
+
+use super::Darkfid;
+
+/// What a WebSocket subscriber asked to be notified about
+pub enum EventFilter {
+    /// Coins received by any of our keys, or only by the given one
+    NewCoins(Option<PublicKey>),
+    /// Confirmation of the given transaction
+    TxStatus(blake3::Hash),
+    /// Changes of the wallet's balances
+    Balances,
+    /// Blocks appended to and rolled back from the canonical chain
+    Blocks,
+}
+
+#[async_trait]
+impl SubscriptionHandler for Darkfid {
+    async fn subscribe(
+        &self,
+        req: &JsonRequest,
+    ) -> Option<std::result::Result<WsSubscription, JsonError>> {
+        let method = req.method.as_str()?;
+        let params = match req.params.as_array() {
+            Some(v) => v,
+            None => return Some(Err(JsonError::new(InvalidParams, None, req.id.clone()))),
+        };
+
+        let filter = match method {
+            // RPCAPI:
+            // Subscribes to coins received by the wallet, optionally only
+            // those sent to the given address. Returns the subscription ID,
+            // and then pushes notifications for every new coin.
+            // --> {"jsonrpc": "2.0", "method": "subscribe_new_coins", "params": ["1DarkFi..."], "id": 1}
+            // <-- {"jsonrpc": "2.0", "result": 4242, "id": 1}
+            // <-- {"jsonrpc": "2.0", "method": "new_coin", "params": {"subscription": 4242, "result": {"address": "1DarkFi...", "coin": "...", "value": 50000000, "token_id": "Ay1...", "tx_hash": "a5b6..."}}}
+            "subscribe_new_coins" => {
+                let public = match params.first() {
+                    None => None,
+                    Some(v) => match v.as_str().and_then(parse_address) {
+                        Some(v) => Some(v),
+                        None => {
+                            return Some(Err(JsonError::new(InvalidParams, None, req.id.clone())))
+                        }
+                    },
+                };
+                EventFilter::NewCoins(public)
+            }
+
+            // RPCAPI:
+            // Subscribes to the confirmation of the given transaction hash.
+            // A single notification is pushed once the transaction is applied
+            // to the canonical state.
+            // --> {"jsonrpc": "2.0", "method": "subscribe_tx_status", "params": ["a5b6..."], "id": 1}
+            // <-- {"jsonrpc": "2.0", "result": 4242, "id": 1}
+            // <-- {"jsonrpc": "2.0", "method": "tx_status", "params": {"subscription": 4242, "result": {"tx_hash": "a5b6...", "status": "confirmed", "slot": 42}}}
+            "subscribe_tx_status" => {
+                let tx_hash = params.first().and_then(|v| v.as_str());
+                match tx_hash.and_then(|v| blake3::Hash::from_hex(v).ok()) {
+                    Some(v) => EventFilter::TxStatus(v),
+                    None => return Some(Err(JsonError::new(InvalidParams, None, req.id.clone()))),
+                }
+            }
+
+            // RPCAPI:
+            // Subscribes to changes of the wallet's balances. Notifications
+            // carry the same map as `wallet.get_balances`.
+            // --> {"jsonrpc": "2.0", "method": "subscribe_balance_changes", "params": [], "id": 1}
+            // <-- {"jsonrpc": "2.0", "result": 4242, "id": 1}
+            // <-- {"jsonrpc": "2.0", "method": "balance_changes", "params": {"subscription": 4242, "result": {"Ay1...": {"ticker": "BTC", ...}}}}
+            "subscribe_balance_changes" => EventFilter::Balances,
+
+            // RPCAPI:
+            // Subscribes to changes of the canonical chain. A notification
+            // is pushed for every block appended to it, and for every
+            // rollback, after which the blocks of the new chain follow.
+            // --> {"jsonrpc": "2.0", "method": "subscribe_blocks", "params": [], "id": 1}
+            // <-- {"jsonrpc": "2.0", "result": 4242, "id": 1}
+            // <-- {"jsonrpc": "2.0", "method": "blocks", "params": {"subscription": 4242, "result": {"event": "applied", "slot": 42, "hash": "a5b6..."}}}
+            // <-- {"jsonrpc": "2.0", "method": "blocks", "params": {"subscription": 4242, "result": {"event": "rolled_back", "slot": 40}}}
+            "subscribe_blocks" => EventFilter::Blocks,
+
+            _ => return None,
+        };
+
+        let method = match filter {
+            EventFilter::NewCoins(_) if self.roles.wallet => "new_coin",
+            EventFilter::TxStatus(_) => "tx_status",
+            EventFilter::Balances if self.roles.wallet => "balance_changes",
+            EventFilter::Blocks => "blocks",
+            _ => return Some(Err(JsonError::new(MethodNotFound, None, req.id.clone()))),
+        };
+
+        let (send, recv) = async_channel::unbounded();
+        self.subscribers.lock().await.push((filter, send));
+        Some(Ok(WsSubscription { method: method.to_string(), recv }))
+    }
+}
+
+impl Darkfid {
+    /// Forward the validator state events to the matching WebSocket
+    /// subscribers, dropping the subscribers that went away.
+    pub async fn publish_state_events(self: Arc<Self>) -> Result<()> {
+        let events = self.validator_state.read().await.events.clone();
+        let subscription = events.subscribe().await;
+
+        loop {
+            let event = subscription.receive().await;
+            debug!("Publishing state event: {:?}", event);
+
+            let mut subscribers = self.subscribers.lock().await;
+            subscribers.retain(|(_, send)| !send.is_closed());
+
+            let balances = if subscribers.iter().any(|(f, _)| matches!(f, EventFilter::Balances)) {
+                self.balances_changed().await
+            } else {
+                None
+            };
+
+            let mut done = vec![];
+            for (i, (filter, send)) in subscribers.iter().enumerate() {
+                let notif = match (filter, &event) {
+                    (
+                        EventFilter::NewCoins(pubkey),
+                        StateEvent::NewCoin { public, coin, value, token_id, tx_hash },
+                    ) => {
+                        if pubkey.map_or(false, |p| p != *public) {
+                            continue
+                        }
+                        json!({
+                            "address": Address::from(*public).to_string(),
+                            "coin": bs58::encode(coin.to_bytes()).into_string(),
+                            "value": value,
+                            "token_id": bs58::encode(token_id.to_repr()).into_string(),
+                            "tx_hash": tx_hash.to_hex().to_string(),
+                        })
+                    }
+
+                    (EventFilter::TxStatus(hash), StateEvent::TxConfirmed { tx_hash, slot }) => {
+                        if hash != tx_hash {
+                            continue
+                        }
+                        // The transaction won't be confirmed again
+                        done.push(i);
+                        json!({
+                            "tx_hash": tx_hash.to_hex().to_string(),
+                            "status": "confirmed",
+                            "slot": slot,
+                        })
+                    }
+
+                    (EventFilter::Blocks, StateEvent::BlockApplied { slot, hash }) => json!({
+                        "event": "applied",
+                        "slot": slot,
+                        "hash": hash.to_hex().to_string(),
+                    }),
+
+                    (EventFilter::Blocks, StateEvent::RolledBack { slot }) => json!({
+                        "event": "rolled_back",
+                        "slot": slot,
+                    }),
+
+                    (EventFilter::Balances, _) => match &balances {
+                        Some(v) => v.clone(),
+                        None => continue,
+                    },
+
+                    _ => continue,
+                };
+
+                // The receiving end is unbounded, so this only fails once
+                // the subscriber went away, which the next event cleans up.
+                let _ = send.send(notif).await;
+            }
+
+            for i in done.into_iter().rev() {
+                subscribers.remove(i);
+            }
+        }
+    }
+
+    /// Return the wallet's balances if they changed since last call.
+    async fn balances_changed(&self) -> Option<Value> {
+        let balances = match self.balances_json().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed fetching balances from wallet: {}", e);
+                return None
+            }
+        };
+
+        let mut last = self.last_balances.lock().await;
+        if last.as_ref() == Some(&balances) {
+            return None
+        }
+
+        *last = Some(balances.clone());
+        Some(balances)
+    }
+}
+
+fn parse_address(addr: &str) -> Option<PublicKey> {
+    let addr = Address::from_str(addr).ok()?;
+    PublicKey::try_from(addr).ok()
+}
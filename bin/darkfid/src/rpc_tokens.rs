@@ -0,0 +1,31 @@
+use serde_json::{json, Value};
+
+use darkfi::rpc::jsonrpc::{JsonResponse, JsonResult};
+
+use super::Darkfid;
+
+impl Darkfid {
+    // RPCAPI:
+    // Fetch the token lists of the configured `token_list` sources again,
+    // without waiting for the next refresh. Each list matching its pin
+    // replaces the one its network had, and is cached for the next start.
+    // Returns the number of tokens in each list, or why it wasn't used.
+    // --> {"jsonrpc": "2.0", "method": "tokens.refresh", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"network": "Solana", "url": "https://...", "tokens": 731}], "id": 1}
+    pub async fn refresh_tokens(&self, id: Value, _params: &[Value]) -> JsonResult {
+        let mut results = vec![];
+        for (source, result) in self.client.tokenlist.refresh().await {
+            let mut entry = json!({
+                "network": source.network.to_string(),
+                "url": source.url.to_string(),
+            });
+            match result {
+                Ok(n) => entry["tokens"] = json!(n),
+                Err(e) => entry["error"] = json!(e.to_string()),
+            }
+            results.push(entry);
+        }
+
+        JsonResponse::new(json!(results), id).into()
+    }
+}
@@ -4,12 +4,22 @@ use log::{error, warn};
 use serde_json::{json, Value};
 
 use darkfi::{
-    crypto::{address::Address, keypair::PublicKey, token_id::generate_id},
+    crypto::{address::Address, keypair::PublicKey, note::Memo, token_id::generate_id},
+    node::{
+        contract::money::{check_transfer, state_transition_report},
+        MemoryState,
+    },
     rpc::jsonrpc::{
         ErrorCode::{InternalError, InvalidParams},
         JsonError, JsonResponse, JsonResult,
     },
-    util::{decode_base10, serial::serialize, NetworkName},
+    tx::{builder::TransactionBuilderPayment, Transaction},
+    util::{
+        decode_base10,
+        serial::{deserialize, serialize},
+        NetworkName,
+    },
+    VerifyFailed, VerifyResult,
 };
 
 use super::Darkfid;
@@ -18,60 +28,58 @@ use crate::{server_error, RpcError};
 impl Darkfid {
     // RPCAPI:
     // Transfer a given amount of some token to the given address.
+    // Takes an optional memo, only readable by the recipient, of up to 127 bytes.
+    // Several addresses can be paid in a single transaction by passing a list of
+    // [address, amount, memo] payments instead, where the memo is optional.
+    // All the payments of a transaction are in the same token. The coins it
+    // spends are pending until it's on the chain, and spendable again if it
+    // is rejected, or hasn't reached the chain within an hour.
     // Returns a transaction ID upon success.
-    // --> {"jsonrpc": "2.0", "method": "tx.transfer", "params": ["darkfi" "gdrk", "1DarkFi...", 12.0], "id": 1}
+    // --> {"jsonrpc": "2.0", "method": "tx.transfer", "params": ["darkfi" "gdrk", "1DarkFi...", 12.0, "Invoice #1337"], "id": 1}
+    // --> {"jsonrpc": "2.0", "method": "tx.transfer", "params": ["darkfi", "gdrk", [["1DarkFi...", 12.0, "Invoice #1337"], ["1DarkFi...", 3.5]]], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": "txID...", "id": 1}
     pub async fn transfer(&self, id: Value, params: &[Value]) -> JsonResult {
-        if params.len() != 4 ||
-            !params[0].is_string() ||
-            !params[1].is_string() ||
-            !params[2].is_string() ||
-            !params[3].is_f64()
-        {
+        if params.len() < 3 || !params[0].is_string() || !params[1].is_string() {
             return JsonError::new(InvalidParams, None, id).into()
         }
 
+        // Either a single payment, or a list of them
+        let payments: Vec<&[Value]> = match &params[2] {
+            Value::String(_) => vec![&params[2..]],
+            Value::Array(v) if params.len() == 3 && !v.is_empty() => {
+                match v.iter().map(|x| x.as_array().map(|x| x.as_slice())).collect::<Option<_>>() {
+                    Some(v) => v,
+                    None => return JsonError::new(InvalidParams, None, id).into(),
+                }
+            }
+            _ => return JsonError::new(InvalidParams, None, id).into(),
+        };
+
+        for payment in &payments {
+            if !(2..=3).contains(&payment.len()) ||
+                !payment[0].is_string() ||
+                !payment[1].is_f64() ||
+                (payment.len() == 3 && !payment[2].is_string())
+            {
+                return JsonError::new(InvalidParams, None, id).into()
+            }
+        }
+
         let network = params[0].as_str().unwrap();
         let token = params[1].as_str().unwrap();
-        let address = params[2].as_str().unwrap();
-        let amount = params[3].as_f64().unwrap();
 
         if !(*self.synced.lock().await) {
             error!("transfer(): Blockchain is not yet synced");
             return server_error(RpcError::NotYetSynced, id)
         }
 
-        let address = match Address::from_str(address) {
-            Ok(v) => v,
-            Err(e) => {
-                error!("transfer(): Failed parsing address from string: {}", e);
-                return server_error(RpcError::InvalidAddressParam, id)
-            }
-        };
-
-        let pubkey = match PublicKey::try_from(address) {
-            Ok(v) => v,
-            Err(e) => {
-                error!("transfer(): Failed parsing PublicKey from Address: {}", e);
-                return server_error(RpcError::ParseError, id)
-            }
-        };
-
-        let amount = amount.to_string();
-        let amount = match decode_base10(&amount, 8, true) {
-            Ok(v) => v,
-            Err(e) => {
-                error!("transfer(): Failed parsing amount from string: {}", e);
-                return server_error(RpcError::InvalidAmountParam, id)
-            }
-        };
-        let amount: u64 = match amount.try_into() {
-            Ok(v) => v,
-            Err(e) => {
-                error!("transfer(): Failed converting biguint to u64: {}", e);
-                return JsonError::new(InternalError, None, id).into()
+        let mut parsed = Vec::with_capacity(payments.len());
+        for payment in payments {
+            match parse_payment(payment) {
+                Ok(v) => parsed.push(v),
+                Err(e) => return server_error(e, id),
             }
-        };
+        }
 
         let network = match NetworkName::from_str(network) {
             Ok(v) => v,
@@ -81,24 +89,23 @@ impl Darkfid {
             }
         };
 
-        let token_id =
-            if let Some(tok) = self.client.tokenlist.by_net[&network].get(token.to_uppercase()) {
-                tok.drk_address
-            } else {
-                match generate_id(&network, token) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!("transfer(): Failed generate_id(): {}", e);
-                        return JsonError::new(InternalError, None, id).into()
-                    }
+        let tokenlist = self.client.tokenlist.current();
+        let token_id = if let Some(tok) = tokenlist.by_net[&network].get(token.to_uppercase()) {
+            tok.drk_address
+        } else {
+            match generate_id(&network, token) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("transfer(): Failed generate_id(): {}", e);
+                    return JsonError::new(InternalError, None, id).into()
                 }
-            };
+            }
+        };
 
         let tx = match self
             .client
-            .build_transaction(
-                pubkey,
-                amount,
+            .build_multi_transaction(
+                parsed,
                 token_id,
                 false,
                 self.validator_state.read().await.state_machine.clone(),
@@ -112,11 +119,23 @@ impl Darkfid {
             }
         };
 
+        // Coins spent by a transaction the network would reject stay ours
+        if let Err(e) = self.verify_tx(&tx).await {
+            error!("transfer(): Built transaction was rejected: {}", e);
+            if let Err(e) = self.client.release_pending(&tx).await {
+                error!("transfer(): Failed releasing pending coins: {}", e);
+            }
+            return server_error(RpcError::InvalidTx, id)
+        }
+
         if let Some(sync_p2p) = &self.sync_p2p {
             match sync_p2p.broadcast(tx.clone()).await {
-                Ok(()) => {}
+                Ok(()) => self.metrics.tx_published(),
                 Err(e) => {
                     error!("transfer(): Failed broadcasting transaction: {}", e);
+                    if let Err(e) = self.client.release_pending(&tx).await {
+                        error!("transfer(): Failed releasing pending coins: {}", e);
+                    }
                     return server_error(RpcError::TxBroadcastFail, id)
                 }
             }
@@ -127,4 +146,174 @@ impl Darkfid {
         let tx_hash = blake3::hash(&serialize(&tx)).to_hex().as_str().to_string();
         JsonResponse::new(json!(tx_hash), id).into()
     }
+
+    // RPCAPI:
+    // Run the configured transaction filters and all the state transition
+    // checks on a base58-encoded serialized transaction, without broadcasting
+    // it. Returns every failed check, with the index of the offending input
+    // or output where relevant.
+    // --> {"jsonrpc": "2.0", "method": "tx.validate", "params": ["base58tx..."], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"valid": false, "errors": [{"check": "merkle_root", "index": 0, "message": "..."}]}, "id": 1}
+    pub async fn validate_tx(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let tx = match parse_tx(params[0].as_str().unwrap()) {
+            Ok(v) => v,
+            Err(e) => return server_error(e, id),
+        };
+
+        let validator_state = self.validator_state.read().await;
+        let mut failed = vec![];
+        if let Err(e) = validator_state.filter_tx(&tx) {
+            failed.push(e);
+        }
+
+        let state = validator_state.state_machine.clone();
+        failed.extend(state_transition_report(&*state.lock().await, &tx));
+
+        let errors: Vec<Value> = failed.iter().map(verify_failed_to_json).collect();
+        JsonResponse::new(json!({"valid": errors.is_empty(), "errors": errors}), id).into()
+    }
+
+    // RPCAPI:
+    // Relay a base58-encoded serialized transaction built by a light client
+    // to the sync network, after it passed the configured transaction
+    // filters and the state transition checks. Only served with the
+    // gateway role. Returns the transaction ID upon success.
+    // --> {"jsonrpc": "2.0", "method": "tx.broadcast", "params": ["base58tx..."], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "txID...", "id": 1}
+    pub async fn broadcast_tx(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let tx = match parse_tx(params[0].as_str().unwrap()) {
+            Ok(v) => v,
+            Err(e) => return server_error(e, id),
+        };
+
+        if !(*self.synced.lock().await) {
+            error!("broadcast_tx(): Blockchain is not yet synced");
+            return server_error(RpcError::NotYetSynced, id)
+        }
+
+        if let Err(e) = self.verify_tx(&tx).await {
+            warn!("broadcast_tx(): Rejected transaction: {}", e);
+            return server_error(RpcError::InvalidTx, id)
+        }
+
+        // The gateway role requires the sync role, so there's a network
+        let sync_p2p = self.sync_p2p.as_ref().unwrap();
+        if let Err(e) = sync_p2p.broadcast(tx.clone()).await {
+            error!("broadcast_tx(): Failed broadcasting transaction: {}", e);
+            return server_error(RpcError::TxBroadcastFail, id)
+        }
+        self.metrics.tx_published();
+
+        let tx_hash = blake3::hash(&serialize(&tx)).to_hex().as_str().to_string();
+        JsonResponse::new(json!(tx_hash), id).into()
+    }
+
+    /// Run the configured transaction filters and the state transition
+    /// checks on a transaction against the canonical state.
+    async fn verify_tx(&self, tx: &Transaction) -> VerifyResult<()> {
+        let validator_state = self.validator_state.read().await;
+        let state = validator_state.state_machine.clone();
+        let mem_state = MemoryState::new(state.lock().await.clone());
+        validator_state.filter_tx(tx).and_then(|()| check_transfer(mem_state, tx))
+    }
+}
+
+/// Decode a base58-encoded serialized transaction
+fn parse_tx(tx: &str) -> Result<Transaction, RpcError> {
+    let tx_bytes = match bs58::decode(tx).into_vec() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed decoding base58 transaction: {}", e);
+            return Err(RpcError::ParseError)
+        }
+    };
+
+    match deserialize(&tx_bytes) {
+        Ok(v) => Ok(v),
+        Err(e) => {
+            error!("Failed deserializing transaction: {}", e);
+            Err(RpcError::ParseError)
+        }
+    }
+}
+
+/// Parse an [address, amount, memo] payment of `tx.transfer`, whose
+/// types were already checked.
+fn parse_payment(payment: &[Value]) -> Result<TransactionBuilderPayment, RpcError> {
+    let address = payment[0].as_str().unwrap();
+    let amount = payment[1].as_f64().unwrap();
+
+    let memo = payment.get(2).map_or("", |x| x.as_str().unwrap());
+    let memo = match Memo::new(memo.as_bytes()) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("transfer(): {}", e);
+            return Err(RpcError::InvalidMemoParam)
+        }
+    };
+
+    let address = match Address::from_str(address) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("transfer(): Failed parsing address from string: {}", e);
+            return Err(RpcError::InvalidAddressParam)
+        }
+    };
+
+    let public = match PublicKey::try_from(address) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("transfer(): Failed parsing PublicKey from Address: {}", e);
+            return Err(RpcError::ParseError)
+        }
+    };
+
+    let amount = amount.to_string();
+    let amount = match decode_base10(&amount, 8, true) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("transfer(): Failed parsing amount from string: {}", e);
+            return Err(RpcError::InvalidAmountParam)
+        }
+    };
+    let value: u64 = match amount.try_into() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("transfer(): Failed converting biguint to u64: {}", e);
+            return Err(RpcError::InvalidAmountParam)
+        }
+    };
+
+    Ok(TransactionBuilderPayment { public, value, memo })
+}
+
+fn verify_failed_to_json(e: &VerifyFailed) -> Value {
+    let (check, index) = match e {
+        VerifyFailed::InvalidCashierOrFaucetKey(i) => ("clear_input_pubkey", Some(*i)),
+        VerifyFailed::InvalidMerkle(i) => ("merkle_root", Some(*i)),
+        VerifyFailed::NullifierExists(i) => ("nullifier", Some(*i)),
+        VerifyFailed::InputSignature(i) => ("input_signature", Some(*i)),
+        VerifyFailed::ClearInputSignature(i) => ("clear_input_signature", Some(*i)),
+        VerifyFailed::InvalidUniqueAsset(i) => ("unique_asset", Some(*i)),
+        VerifyFailed::UniqueAssetIssued(i) => ("unique_asset_issued", Some(*i)),
+        VerifyFailed::TokenMismatch => ("token_commitment", None),
+        VerifyFailed::MissingFunds => ("value_commitment", None),
+        VerifyFailed::MintProof(i) => ("mint_proof", Some(*i)),
+        VerifyFailed::BurnProof(i) => ("burn_proof", Some(*i)),
+        VerifyFailed::ProofVerifyFailed(_) => ("proof", None),
+        VerifyFailed::UnknownCircuit(_) => ("circuit", None),
+        VerifyFailed::UnknownContract(_) => ("contract", None),
+        VerifyFailed::Filtered(_) => ("filter", None),
+        VerifyFailed::InternalError(_) => ("internal", None),
+    };
+
+    json!({"check": check, "index": index, "message": e.to_string()})
 }
@@ -1,4 +1,6 @@
-use fxhash::FxHashMap;
+use std::str::FromStr;
+
+use incrementalmerkletree::Tree;
 use log::{error, warn};
 use num_bigint::BigUint;
 use pasta_curves::group::ff::PrimeField;
@@ -8,17 +10,31 @@ use darkfi::{
     crypto::{
         address::Address,
         keypair::{Keypair, PublicKey, SecretKey},
+        membership_proof::{membership_challenge, membership_scope},
+        mnemonic::Mnemonic,
+        token_id::generate_id,
     },
     rpc::jsonrpc::{
         ErrorCode::{InternalError, InvalidParams},
         JsonError, JsonResponse, JsonResult,
     },
-    util::{decode_base10, encode_base10, NetworkName},
+    util::{encode_base10, serial::serialize, NetworkName},
+    wallet::{
+        export::WalletExport,
+        walletdb::{init_wallet, CoinState},
+    },
+    Error, Result,
 };
 
 use super::Darkfid;
 use crate::{server_error, RpcError};
 
+/// Precision of the values stored in the wallet's coins
+const WALLET_DECIMALS: usize = 8;
+
+/// Most keys `wallet.restore_mnemonic` derives in one call
+const MAX_RESTORE_KEYS: u64 = 1000;
+
 impl Darkfid {
     // RPCAPI:
     // Attempts to generate a new keypair and returns its address upon success.
@@ -154,6 +170,119 @@ impl Darkfid {
         JsonResponse::new(json!(address), id).into()
     }
 
+    // RPCAPI:
+    // Creates a random mnemonic of 12 to 24 words (24 by default) and keeps the
+    // seed made from it, along with an optional passphrase, in the wallet.
+    // Returns the words, which are the backup of every key later derived from
+    // the seed, and the address of the first one, which is derived right away
+    // and becomes the default key.
+    // Fails if the wallet already has a seed.
+    // --> {"jsonrpc": "2.0", "method": "wallet.create_mnemonic", "params": [24, "passphrase"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"mnemonic": "word word ...", "address": "1DarkFi..."}, "id": 1}
+    pub async fn create_mnemonic(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() > 2 ||
+            (!params.is_empty() && !params[0].is_u64()) ||
+            (params.len() == 2 && !params[1].is_string())
+        {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let word_count = params.get(0).and_then(|x| x.as_u64()).unwrap_or(24);
+        let passphrase = params.get(1).and_then(|x| x.as_str()).unwrap_or_default();
+
+        let mnemonic = match Mnemonic::generate(word_count as usize) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed generating mnemonic: {}", e);
+                return server_error(RpcError::InvalidMnemonic, id)
+            }
+        };
+
+        match self.client.restore_seed(&mnemonic, passphrase, 1).await {
+            Ok(addresses) => {
+                let ret = json!({
+                    "mnemonic": mnemonic.phrase(),
+                    "address": addresses[0].to_string(),
+                });
+                JsonResponse::new(ret, id).into()
+            }
+            Err(e) => self.seed_error(e, id),
+        }
+    }
+
+    // RPCAPI:
+    // Restores the seed of a wallet from its mnemonic words and optional
+    // passphrase, and derives the given number of keys of the first account,
+    // at least 1 and at most 1000. The first key becomes the default one.
+    // Returns their addresses. Fails if the wallet already has a seed.
+    // --> {"jsonrpc": "2.0", "method": "wallet.restore_mnemonic", "params": ["word word ...", 5, "passphrase"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": ["1DarkFi...", ...], "id": 1}
+    pub async fn restore_mnemonic(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() < 2 ||
+            params.len() > 3 ||
+            !params[0].is_string() ||
+            !params[1].is_u64() ||
+            params[1].as_u64().unwrap() > MAX_RESTORE_KEYS ||
+            (params.len() == 3 && !params[2].is_string())
+        {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let mnemonic = match Mnemonic::from_phrase(params[0].as_str().unwrap()) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed parsing mnemonic: {}", e);
+                return server_error(RpcError::InvalidMnemonic, id)
+            }
+        };
+        let count = params[1].as_u64().unwrap() as u32;
+        let passphrase = params.get(2).and_then(|x| x.as_str()).unwrap_or_default();
+
+        match self.client.restore_seed(&mnemonic, passphrase, count).await {
+            Ok(addresses) => {
+                let ret: Vec<String> = addresses.iter().map(|x| x.to_string()).collect();
+                JsonResponse::new(json!(ret), id).into()
+            }
+            Err(e) => self.seed_error(e, id),
+        }
+    }
+
+    // RPCAPI:
+    // Derives the keypair at the given account and index from the wallet seed,
+    // and puts it in the wallet. Returns its address.
+    // --> {"jsonrpc": "2.0", "method": "wallet.derive_keypair", "params": [0, 3], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "1DarkFi...", "id": 1}
+    pub async fn derive_keypair(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 2 || !params[0].is_u64() || !params[1].is_u64() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let account = match u32::try_from(params[0].as_u64().unwrap()) {
+            Ok(v) => v,
+            Err(_) => return JsonError::new(InvalidParams, None, id).into(),
+        };
+        let index = match u32::try_from(params[1].as_u64().unwrap()) {
+            Ok(v) => v,
+            Err(_) => return JsonError::new(InvalidParams, None, id).into(),
+        };
+
+        match self.client.derive_keypair(account, index).await {
+            Ok(address) => JsonResponse::new(json!(address.to_string()), id).into(),
+            Err(e) => self.seed_error(e, id),
+        }
+    }
+
+    fn seed_error(&self, e: Error, id: Value) -> JsonResult {
+        match e {
+            Error::WalletSeedExists => server_error(RpcError::SeedExists, id),
+            Error::WalletSeedNotFound => server_error(RpcError::SeedNotFound, id),
+            e => {
+                error!("Failed deriving keypair from wallet seed: {}", e);
+                JsonError::new(InternalError, None, id).into()
+            }
+        }
+    }
+
     // RPCAPI:
     // Sets the default wallet address to the given index.
     // Returns `true` upon success.
@@ -192,64 +321,423 @@ impl Darkfid {
 
     // RPCAPI:
     // Queries the wallet for known balances.
-    // Returns a map of balances, indexed by `network`, and token ID.
+    // Returns a map of balances indexed by token ID, with the unspent coins of
+    // each token summed up. `amount` has the wallet's 8 decimals applied, while
+    // `value` is the raw integer sum, as a string since it can exceed 64 bits.
+    // `ticker` falls back to the token ID and `network` to `darkfi` for
+    // tokens missing from the token lists.
     // --> {"jsonrpc": "2.0", "method": "wallet.get_balances", "params": [], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": [{"btc": [100, "Bitcoin"]}, {...}], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"Ay1...": {"ticker": "BTC", "network": "bitcoin", "net_address": "btc", "amount": "0.5", "value": "50000000", "decimals": 8, "coins": 2}, ...}, "id": 1}
     pub async fn get_balances(&self, id: Value, _params: &[Value]) -> JsonResult {
-        let balances = match self.client.get_balances().await {
-            Ok(v) => v,
+        match self.balances_json().await {
+            Ok(v) => JsonResponse::new(v, id).into(),
             Err(e) => {
                 error!("Failed fetching balances from wallet: {}", e);
+                JsonError::new(InternalError, None, id).into()
+            }
+        }
+    }
+
+    /// Build the `wallet.get_balances` reply, which balance change
+    /// subscriptions also publish.
+    pub async fn balances_json(&self) -> Result<Value> {
+        let balances = self.client.get_balances().await?;
+
+        let mut ret = serde_json::Map::new();
+
+        for balance in balances.by_token() {
+            let drk_addr = bs58::encode(balance.token_id.to_repr()).into_string();
+
+            let (net_name, net_addr, ticker) = self.token_info(&drk_addr);
+
+            // Coins are minted with a fixed precision, regardless of the
+            // decimals the token has on its native network.
+            let amount = encode_base10(BigUint::from(balance.value), WALLET_DECIMALS);
+
+            ret.insert(
+                drk_addr,
+                json!({
+                    "ticker": ticker,
+                    "network": net_name,
+                    "net_address": net_addr,
+                    "amount": amount,
+                    "value": balance.value.to_string(),
+                    "decimals": WALLET_DECIMALS,
+                    "coins": balance.coins,
+                }),
+            );
+        }
+
+        Ok(Value::Object(ret))
+    }
+
+    // RPCAPI:
+    // Queries the wallet's transaction log, newest entries first.
+    // Takes the number of entries to skip, and the maximum number of entries
+    // to return. `amount` has the wallet's 8 decimals applied, and `ticker`
+    // falls back to the token ID for tokens missing from the token lists.
+    // `memo` is the memo the payment was made with, or an empty string. It is
+    // whatever the sender wrote, and no proof checks it.
+    // --> {"jsonrpc": "2.0", "method": "wallet.get_tx_history", "params": [0, 10], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"timestamp": 1656000000, "direction": "received", "amount": "0.5", "value": 50000000, "token_id": "Ay1...", "ticker": "BTC", "tx_hash": "a5b6...", "memo": "Invoice #1337"}, ...], "id": 1}
+    pub async fn get_tx_history(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 2 || !params[0].is_u64() || !params[1].is_u64() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let offset = params[0].as_u64().unwrap().min(u32::MAX as u64) as u32;
+        let limit = params[1].as_u64().unwrap().min(u32::MAX as u64) as u32;
+
+        let history = match self.client.get_tx_history(offset, limit).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed fetching transaction history from wallet: {}", e);
                 return JsonError::new(InternalError, None, id).into()
             }
         };
 
-        // k: ticker/drk_addr, v: (amount, network, net_addr, drk_addr)
-        let mut ret: FxHashMap<String, (String, String, String, String)> = FxHashMap::default();
+        let ret: Vec<Value> = history
+            .iter()
+            .map(|entry| {
+                let drk_addr = bs58::encode(entry.token_id.to_repr()).into_string();
+                let (_, _, ticker) = self.token_info(&drk_addr);
+                json!({
+                    "timestamp": entry.timestamp.0,
+                    "direction": entry.direction.as_str(),
+                    "amount": encode_base10(BigUint::from(entry.value), WALLET_DECIMALS),
+                    "value": entry.value,
+                    "token_id": drk_addr,
+                    "ticker": ticker,
+                    "tx_hash": entry.tx_hash.to_hex().to_string(),
+                    "memo": String::from_utf8_lossy(entry.memo.as_bytes()),
+                })
+            })
+            .collect();
 
-        for balance in balances.list {
-            let drk_addr = bs58::encode(balance.token_id.to_repr()).into_string();
-            let mut amount = BigUint::from(balance.value);
+        JsonResponse::new(json!(ret), id).into()
+    }
 
-            let (net_name, net_addr) =
-                if let Some((net, tok)) = self.client.tokenlist.by_addr.get(&drk_addr) {
-                    (net, tok.net_address.clone())
-                } else {
-                    warn!("Could not find network name and token info for {}", drk_addr);
-                    (&NetworkName::DarkFi, "unknown".to_string())
-                };
+    // RPCAPI:
+    // Creates a new named wallet, with its own keypairs and coins, stored in
+    // the configured wallets directory. Names may contain ASCII letters,
+    // digits, `-` and `_`. Returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "wallet.create", "params": ["savings"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    pub async fn create_wallet(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
 
-            let mut ticker = None;
-            for (k, v) in self.client.tokenlist.by_net[net_name].0.iter() {
-                if v.net_address == net_addr {
-                    ticker = Some(k.clone());
-                    break
-                }
+        let name = params[0].as_str().unwrap();
+        if name.is_empty() ||
+            !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return server_error(RpcError::InvalidWalletName, id)
+        }
+
+        let path = self.wallets_dir.join(format!("{}.db", name));
+        if path.exists() || self.client.list_wallets().await.iter().any(|(n, _)| n == name) {
+            return server_error(RpcError::WalletExists, id)
+        }
+
+        // Hold the state machine so no update is applied before the new
+        // wallet has a copy of the current Merkle tree.
+        let state_machine = self.validator_state.read().await.state_machine.clone();
+        let state = state_machine.lock().await;
+
+        let wallet = match init_wallet(path.to_str().unwrap(), &self.wallet_pass).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed creating wallet {}: {}", name, e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        if let Err(e) = self.client.add_wallet(name, wallet.clone()).await {
+            error!("Failed opening wallet {}: {}", name, e);
+            return JsonError::new(InternalError, None, id).into()
+        }
+
+        if let Err(e) = wallet.put_tree(&state.tree).await {
+            error!("Failed writing Merkle tree to wallet {}: {}", name, e);
+            return JsonError::new(InternalError, None, id).into()
+        }
+
+        JsonResponse::new(json!(true), id).into()
+    }
+
+    // RPCAPI:
+    // Lists the open wallets, and marks the one currently in use.
+    // --> {"jsonrpc": "2.0", "method": "wallet.list", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"name": "default", "active": true}, ...], "id": 1}
+    pub async fn list_wallets(&self, id: Value, _params: &[Value]) -> JsonResult {
+        let ret: Vec<Value> = self
+            .client
+            .list_wallets()
+            .await
+            .iter()
+            .map(|(name, active)| json!({"name": name, "active": active}))
+            .collect();
+
+        JsonResponse::new(json!(ret), id).into()
+    }
+
+    // RPCAPI:
+    // Switches to the given named wallet. Keys, balances and transactions
+    // are taken from it until switching again. Returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "wallet.switch", "params": ["savings"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    pub async fn switch_wallet(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        match self.client.switch_wallet(params[0].as_str().unwrap()).await {
+            Ok(()) => JsonResponse::new(json!(true), id).into(),
+            Err(Error::WalletNotFound(_)) => server_error(RpcError::WalletNotFound, id),
+            Err(e) => {
+                error!("Failed switching wallet: {}", e);
+                JsonError::new(InternalError, None, id).into()
+            }
+        }
+    }
+
+    // RPCAPI:
+    // Exports the wallet in use, its keys, seed, coins, Merkle tree and
+    // transaction log, encrypted under the given passphrase. Returns the
+    // base58-encoded export, which `wallet.import` takes.
+    // --> {"jsonrpc": "2.0", "method": "wallet.export", "params": ["passphrase"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "export...", "id": 1}
+    pub async fn export_wallet(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        // Hold the state machine so the wallet's tree matches the last block
+        let state_machine = self.validator_state.read().await.state_machine.clone();
+        let _state = state_machine.lock().await;
+
+        let blockchain = self.validator_state.read().await.async_blockchain.clone();
+        let (slot, block) = match blockchain.last().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed fetching last block: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        let export = match self.client.wallet().await.export(slot, block).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed exporting wallet: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        match export.encrypt(params[0].as_str().unwrap()) {
+            Ok(v) => JsonResponse::new(json!(bs58::encode(v).into_string()), id).into(),
+            Err(e) => {
+                error!("Failed encrypting wallet export: {}", e);
+                JsonError::new(InternalError, None, id).into()
             }
+        }
+    }
+
+    // RPCAPI:
+    // Imports a wallet export made by `wallet.export` into a new named
+    // wallet, given its name, the export and its passphrase. Exports made at
+    // a block the node hasn't reached yet get their coins' witnesses while
+    // syncing, as long as none of their coins are in blocks the node already
+    // applied, which always holds at genesis. Exports made at the node's last
+    // block bring their Merkle tree, which the node adopts if no other wallet
+    // holds unspent coins. Older exports can't be witnessed and are refused.
+    // Returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "wallet.import", "params": ["restored", "export...", "passphrase"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    pub async fn import_wallet(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 3 || !params.iter().all(|x| x.is_string()) {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let name = params[0].as_str().unwrap();
+        if name.is_empty() ||
+            !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return server_error(RpcError::InvalidWalletName, id)
+        }
+
+        let path = self.wallets_dir.join(format!("{}.db", name));
+        if path.exists() || self.client.list_wallets().await.iter().any(|(n, _)| n == name) {
+            return server_error(RpcError::WalletExists, id)
+        }
+
+        let export = match bs58::decode(params[1].as_str().unwrap()).into_vec() {
+            Ok(v) => WalletExport::decrypt(&v, params[2].as_str().unwrap()),
+            Err(e) => Err(Error::WalletExportInvalid(e.to_string())),
+        };
+
+        let export = match export {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed reading wallet export: {}", e);
+                return server_error(RpcError::InvalidWalletExport, id)
+            }
+        };
+
+        // Hold the state machine, so no update lands between choosing the
+        // tree and the new wallet getting it.
+        let state_machine = self.validator_state.read().await.state_machine.clone();
+        let mut state = state_machine.lock().await;
+
+        let blockchain = self.validator_state.read().await.async_blockchain.clone();
+        let last = match blockchain.last().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed fetching last block: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
 
-            if ticker.is_none() {
-                ticker = Some(drk_addr.clone())
+        let exported_tree = match export.merkle_tree() {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed decoding exported Merkle tree: {}", e);
+                return server_error(RpcError::InvalidWalletExport, id)
             }
+        };
 
-            let ticker = ticker.unwrap();
+        if export.slot == last.0 && export.block == *last.1.as_bytes() {
+            if exported_tree.root(0) != state.tree.root(0) {
+                return server_error(RpcError::InvalidWalletExport, id)
+            }
 
-            if let Some(prev) = ret.get(&ticker) {
-                // TODO: We shouldn't be hardcoding everything to 8 decimals.
-                let prev_amnt = match decode_base10(&prev.0, 8, false) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!("Failed to decode_base10(): {}", e);
+            // Every wallet shares the node's tree, so adopting the exported
+            // one drops the witnesses of the other wallets' coins.
+            for wallet in self.client.wallets().await {
+                let unspent = wallet.get_own_coins_in_state(CoinState::Unspent).await;
+                let pending = wallet.get_own_coins_in_state(CoinState::Pending).await;
+                match (unspent, pending) {
+                    (Ok(u), Ok(p)) if u.is_empty() && p.is_empty() => {}
+                    (Ok(_), Ok(_)) => return server_error(RpcError::WalletWitnessConflict, id),
+                    (Err(e), _) | (_, Err(e)) => {
+                        error!("Failed fetching coins: {}", e);
                         return JsonError::new(InternalError, None, id).into()
                     }
-                };
+                }
+            }
+
+            state.tree = exported_tree;
+        } else if export.slot <= last.0 {
+            return server_error(RpcError::WalletExportStale, id)
+        } else {
+            // Coins landing in blocks this node already applied were never
+            // witnessed, so only coins past the current tree can be tracked.
+            // At genesis the tree is empty, so this holds for any export.
+            let size = state.tree.current_position().map_or(0, |p| usize::from(p) + 1);
+            if export.coins.iter().any(|c| usize::from(c.leaf_position) < size) {
+                return server_error(RpcError::WalletExportUnwitnessed, id)
+            }
+        }
 
-                amount += prev_amnt;
+        let wallet = match init_wallet(path.to_str().unwrap(), &self.wallet_pass).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed creating wallet {}: {}", name, e);
+                return JsonError::new(InternalError, None, id).into()
             }
+        };
 
-            let amount = encode_base10(amount, 8);
-            ret.insert(ticker, (amount, net_name.to_string(), net_addr, drk_addr));
+        if let Err(e) = wallet.import(&export, &self.client.tokenlist.current()).await {
+            error!("Failed importing into wallet {}: {}", name, e);
+            return JsonError::new(InternalError, None, id).into()
         }
 
-        JsonResponse::new(json!(ret), id).into()
+        if let Err(e) = self.client.add_wallet(name, wallet).await {
+            error!("Failed opening wallet {}: {}", name, e);
+            return JsonError::new(InternalError, None, id).into()
+        }
+
+        for wallet in self.client.wallets().await {
+            if let Err(e) = wallet.put_tree(&state.tree).await {
+                error!("Failed writing Merkle tree to wallets: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        }
+
+        JsonResponse::new(json!(true), id).into()
+    }
+
+    // RPCAPI:
+    // Prove the wallet owns an unspent coin of the given token, without
+    // revealing which. Takes the network and token as `tx.transfer` does, the
+    // scope of the service asking for the proof, e.g. its domain name, and the
+    // challenge it handed out. Returns the base58-encoded proof, which the
+    // service checks with `blockchain.verify_membership`.
+    // --> {"jsonrpc": "2.0", "method": "wallet.prove_membership", "params": ["darkfi", "gdrk", "dao.example", "challenge"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "proof...", "id": 1}
+    pub async fn prove_membership(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 4 || !params.iter().all(|x| x.is_string()) {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let network = params[0].as_str().unwrap();
+        let token = params[1].as_str().unwrap();
+        let scope = membership_scope(params[2].as_str().unwrap());
+        let challenge = membership_challenge(params[3].as_str().unwrap().as_bytes());
+
+        let network = match NetworkName::from_str(network) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("prove_membership(): Failed parsing NetworkName: {}", e);
+                return server_error(RpcError::NetworkNameError, id)
+            }
+        };
+
+        let tokenlist = self.client.tokenlist.current();
+        let token_id = if let Some(tok) = tokenlist.by_net[&network].get(token.to_uppercase()) {
+            tok.drk_address
+        } else {
+            match generate_id(&network, token) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("prove_membership(): Failed generate_id(): {}", e);
+                    return JsonError::new(InternalError, None, id).into()
+                }
+            }
+        };
+
+        let state = self.validator_state.read().await.state_machine.clone();
+        match self.client.prove_membership(token_id, scope, challenge, state).await {
+            Ok(v) => JsonResponse::new(json!(bs58::encode(serialize(&v)).into_string()), id).into(),
+            Err(e) => {
+                error!("prove_membership(): Failed creating proof: {}", e);
+                server_error(RpcError::MembershipProofFail, id)
+            }
+        }
+    }
+
+    /// Look up the network name, native address and ticker of a token in
+    /// the token lists. Unknown tokens map to `darkfi`, `unknown` and the
+    /// token ID itself.
+    fn token_info(&self, drk_addr: &str) -> (String, String, String) {
+        let tokenlist = self.client.tokenlist.current();
+        let (net_name, net_addr) = if let Some((net, tok)) = tokenlist.by_addr.get(drk_addr) {
+            (net, tok.net_address.clone())
+        } else {
+            warn!("Could not find network name and token info for {}", drk_addr);
+            (&NetworkName::DarkFi, "unknown".to_string())
+        };
+
+        let mut ticker = drk_addr.to_string();
+        if let Some(tokens) = tokenlist.by_net.get(net_name) {
+            for (k, v) in tokens.0.iter() {
+                if v.net_address == net_addr {
+                    ticker = k.clone();
+                    break
+                }
+            }
+        }
+
+        (net_name.to_string(), net_addr, ticker)
     }
 }
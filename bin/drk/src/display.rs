@@ -0,0 +1,118 @@
+use std::str::FromStr;
+
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use darkfi::{Error, Result};
+
+/// How token amounts are shown
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Denomination {
+    /// Raw token units, as stored in the wallet
+    Token,
+    /// Estimated value in the configured reference currency
+    Reference,
+    /// Both of the above
+    Both,
+}
+
+impl Default for Denomination {
+    fn default() -> Self {
+        Self::Token
+    }
+}
+
+impl FromStr for Denomination {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "token" => Ok(Self::Token),
+            "reference" => Ok(Self::Reference),
+            "both" => Ok(Self::Both),
+            _ => Err(Error::ParseFailed("Denomination must be one of token, reference, both")),
+        }
+    }
+}
+
+/// Display settings, read from `drk_config.toml`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Denomination used when `--denomination` is not given
+    pub denomination: Denomination,
+    /// Name of the reference currency, e.g. "USD"
+    pub reference: String,
+    /// Price of one token in the reference currency, indexed by ticker.
+    /// There is no price feed, so these are only as fresh as the config.
+    pub rates: FxHashMap<String, f64>,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            denomination: Denomination::default(),
+            reference: "USD".to_string(),
+            rates: FxHashMap::default(),
+        }
+    }
+}
+
+impl DisplayConfig {
+    /// Estimated value of `amount` tokens of `ticker` in the reference
+    /// currency, if a rate is configured for it.
+    pub fn estimate(&self, ticker: &str, amount: &str) -> Option<f64> {
+        let rate = self.rates.get(ticker)?;
+        let amount = f64::from_str(amount).ok()?;
+        Some(amount * rate)
+    }
+
+    /// Format a single amount according to `denomination`.
+    pub fn format_amount(&self, denomination: Denomination, ticker: &str, amount: &str) -> String {
+        let token = format!("{} {}", amount, ticker);
+        let reference = match self.estimate(ticker, amount) {
+            Some(v) => format!("~{:.2} {} (estimate)", v, self.reference),
+            None => format!("no {} rate for {}", self.reference, ticker),
+        };
+
+        match denomination {
+            Denomination::Token => token,
+            Denomination::Reference => reference,
+            Denomination::Both => format!("{} ({})", token, reference),
+        }
+    }
+
+    /// Format the reply of `wallet.get_balances`, one line per token.
+    pub fn format_balances(&self, denomination: Denomination, balances: &Value) -> Vec<String> {
+        let balances = match balances.as_object() {
+            Some(v) => v,
+            None => return vec![],
+        };
+
+        let mut lines = vec![];
+        let mut total = 0.0;
+        let mut estimated_all = true;
+
+        for (token_id, info) in balances {
+            let ticker = info["ticker"].as_str().unwrap_or(token_id);
+            let amount = info["amount"].as_str().unwrap_or("0");
+            let network = info["network"].as_str().unwrap_or("unknown");
+            let shown = self.format_amount(denomination, ticker, amount);
+            lines.push(format!("{:>12} [{}]: {}", ticker, network, shown));
+
+            match self.estimate(ticker, amount) {
+                Some(v) => total += v,
+                None => estimated_all = false,
+            }
+        }
+
+        if denomination != Denomination::Token && !balances.is_empty() {
+            let partial = if estimated_all { "" } else { ", excluding tokens without a rate" };
+            lines.push(format!("Total: ~{:.2} {} (estimate{})", total, self.reference, partial));
+        }
+
+        lines
+    }
+}
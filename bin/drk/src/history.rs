@@ -0,0 +1,139 @@
+use std::{collections::HashMap, str::FromStr};
+
+use serde_json::{json, Value};
+
+use darkfi::{util::encode_base10, Error, Result};
+
+/// Precision of the values stored in the wallet's coins
+const WALLET_DECIMALS: usize = 8;
+
+/// Columns of the CSV export, in order
+const CSV_HEADER: &str = "timestamp,direction,token,amount,fee,txid,memo,balance";
+
+/// Formats `drk history` can export to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(Error::ParseFailed("Export format must be one of csv, json")),
+        }
+    }
+}
+
+/// One line of the accounting export.
+pub struct HistoryRecord {
+    pub timestamp: i64,
+    pub direction: String,
+    pub token: String,
+    /// Signed amount, negative for sent transactions
+    pub amount: String,
+    /// Transactions carry no fees yet, so this is always zero
+    pub fee: String,
+    pub txid: String,
+    /// Memo the payment was made with, if any
+    pub memo: String,
+    /// Balance of `token` after this transaction
+    pub balance: String,
+}
+
+/// Turn the entries returned by `wallet.get_tx_history`, newest first, into
+/// records ordered oldest first with a running balance per token.
+pub fn history_records(entries: &[Value]) -> Vec<HistoryRecord> {
+    let mut balances: HashMap<String, i128> = HashMap::new();
+    let mut records = vec![];
+
+    for entry in entries.iter().rev() {
+        let token_id = entry["token_id"].as_str().unwrap_or_default();
+        let token = entry["ticker"].as_str().unwrap_or(token_id).to_string();
+        let direction = entry["direction"].as_str().unwrap_or_default().to_string();
+        let value = entry["value"].as_u64().unwrap_or_default() as i128;
+
+        let value = if direction == "sent" { -value } else { value };
+        let balance = balances.entry(token_id.to_string()).or_default();
+        *balance += value;
+
+        records.push(HistoryRecord {
+            timestamp: entry["timestamp"].as_i64().unwrap_or_default(),
+            direction,
+            token,
+            amount: format_signed(value),
+            fee: format_signed(0),
+            txid: entry["tx_hash"].as_str().unwrap_or_default().to_string(),
+            memo: entry["memo"].as_str().unwrap_or_default().to_string(),
+            balance: format_signed(*balance),
+        });
+    }
+
+    records
+}
+
+/// Render the records in the given export format.
+pub fn export_records(records: &[HistoryRecord], format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Csv => {
+            let mut lines = vec![CSV_HEADER.to_string()];
+            for r in records {
+                let fields = [
+                    r.timestamp.to_string(),
+                    r.direction.clone(),
+                    r.token.clone(),
+                    r.amount.clone(),
+                    r.fee.clone(),
+                    r.txid.clone(),
+                    r.memo.clone(),
+                    r.balance.clone(),
+                ];
+                let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+                lines.push(fields.join(","));
+            }
+            lines.join("\n") + "\n"
+        }
+
+        ExportFormat::Json => {
+            let records: Vec<Value> = records
+                .iter()
+                .map(|r| {
+                    json!({
+                        "timestamp": r.timestamp,
+                        "direction": r.direction,
+                        "token": r.token,
+                        "amount": r.amount,
+                        "fee": r.fee,
+                        "txid": r.txid,
+                        "memo": r.memo,
+                        "balance": r.balance,
+                    })
+                })
+                .collect();
+            serde_json::to_string_pretty(&records).unwrap() + "\n"
+        }
+    }
+}
+
+/// Format a signed raw value with the wallet's decimals applied.
+fn format_signed(value: i128) -> String {
+    let amount = encode_base10(value.unsigned_abs().into(), WALLET_DECIMALS);
+    if value < 0 {
+        format!("-{}", amount)
+    } else {
+        amount
+    }
+}
+
+/// Quote a CSV field if it contains a separator, quote or newline.
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -1,22 +1,49 @@
-use std::{process::exit, str::FromStr, time::Instant};
+use std::{collections::HashSet, fs, path::Path, process::exit, str::FromStr, time::Instant};
 
 use clap::{Parser, Subcommand};
 
-use serde_json::json;
+use serde_json::{json, Value};
 use simplelog::{ColorChoice, TermLogger, TerminalMode};
 use url::Url;
 
 use darkfi::{
     cli_desc,
-    crypto::address::Address,
+    crypto::{
+        address::Address,
+        keypair::{PublicKey, SecretKey},
+        schnorr::SchnorrSecret,
+    },
     rpc::{client::RpcClient, jsonrpc::JsonRequest},
     util::{
-        cli::{get_log_config, get_log_level},
-        NetworkName,
+        cli::{
+            confirm, confirm_overwrite, get_log_config, get_log_level, print_completions, Config,
+            Shell,
+        },
+        path::get_config_path,
+        serial::serialize,
+        NetworkName, Timestamp,
     },
-    Result,
+    Error, Result,
 };
 
+mod display;
+use display::{Denomination, DisplayConfig};
+
+mod history;
+use history::{export_records, history_records, ExportFormat};
+
+const CONFIG_FILE: &str = "drk_config.toml";
+
+/// Domain of the signatures reading deposit events, as in cashierd's
+/// `policy::deposit_events_message`
+const DEPOSIT_EVENTS_DOMAIN: &[u8] = b"DarkFi cashier deposit events";
+
+/// Number of history entries requested from darkfid at once
+const HISTORY_PAGE_SIZE: u64 = 100;
+
+/// Transaction history pages requested in a single batch
+const HISTORY_BATCH_PAGES: u64 = 4;
+
 #[derive(Parser)]
 #[clap(name = "drk", about = cli_desc!(), version)]
 #[clap(arg_required_else_help(true))]
@@ -29,6 +56,22 @@ struct Args {
     /// darkfid JSON-RPC endpoint
     endpoint: Url,
 
+    #[clap(long)]
+    /// Token to authenticate to darkfid with
+    rpc_token: Option<String>,
+
+    #[clap(short, long)]
+    /// Configuration file to use
+    config: Option<String>,
+
+    #[clap(short, long, parse(try_from_str))]
+    /// Show amounts as "token", "reference" (estimated value) or "both"
+    denomination: Option<Denomination>,
+
+    #[clap(short, long, global = true)]
+    /// Don't ask for confirmation, e.g. before sending funds
+    yes: bool,
+
     #[clap(subcommand)]
     command: DrkSubcommand,
 }
@@ -53,6 +96,18 @@ enum DrkSubcommand {
         amount: f64,
     },
 
+    /// Follow deposits made through a cashier, until they're done
+    Deposits {
+        #[clap(long, parse(try_from_str))]
+        /// Address the deposits are credited to
+        /// (default is darkfid's wallet default)
+        address: Option<Address>,
+
+        #[clap(long)]
+        /// JSON-RPC endpoint of the cashier
+        cashier_endpoint: Url,
+    },
+
     /// Wallet operations
     Wallet {
         #[clap(long)]
@@ -88,11 +143,42 @@ enum DrkSubcommand {
         /// Token ID
         #[clap(short, long)]
         token_id: String,
+
+        /// Memo only the recipient can read, e.g. an invoice reference
+        #[clap(long)]
+        memo: Option<String>,
+    },
+
+    /// Token list operations
+    Tokens {
+        #[clap(long)]
+        /// Fetch the token lists configured in darkfid again
+        refresh: bool,
+    },
+
+    /// Show the wallet's transaction history
+    History {
+        #[clap(long, parse(try_from_str))]
+        /// Export the history with running balances as "csv" or "json"
+        export: Option<ExportFormat>,
+
+        #[clap(short, long)]
+        /// Write the export to this file instead of stdout
+        output: Option<String>,
+    },
+
+    /// Print a completion script for bash, zsh, fish, elvish or powershell
+    Completions {
+        #[clap(parse(try_from_str))]
+        /// Shell to complete in
+        shell: Shell,
     },
 }
 
 struct Drk {
     pub rpc_client: RpcClient,
+    pub display: DisplayConfig,
+    pub denomination: Denomination,
 }
 
 impl Drk {
@@ -129,6 +215,108 @@ impl Drk {
         Ok(())
     }
 
+    /// Print the events of the deposits for `address`, and keep waiting for
+    /// more while any of them is still pending.
+    async fn deposits(&self, address: Option<Address>, endpoint: Url) -> Result<()> {
+        let addr = match address {
+            Some(v) => v,
+            None => {
+                let req = JsonRequest::new("wallet.get_key", json!([0_i64]));
+                let rep = self.rpc_client.request(req).await?;
+                Address::from_str(rep.as_array().unwrap()[0].as_str().unwrap())?
+            }
+        };
+
+        // The cashier only gives out the events of an address to its owner
+        let secret = self.wallet_secret(&addr).await?;
+
+        println!("Deposits for {}:", addr);
+        let rpc_client = RpcClient::new(endpoint).await?;
+
+        // Signatures are bound to the cashier, so they can't be used on another
+        let req = JsonRequest::new("features", json!([]));
+        let cashier = match rpc_client.request(req).await?["public_key"].as_str() {
+            Some(v) => v.to_string(),
+            None => return Err(Error::ParseFailed("Invalid cashier features reply")),
+        };
+
+        // Deposit addresses that haven't reached a final state yet
+        let mut pending = HashSet::new();
+        let mut seq = 0;
+        let mut seen = false;
+
+        loop {
+            let timestamp = Timestamp::current_time().0;
+            let mut message = DEPOSIT_EVENTS_DOMAIN.to_vec();
+            message.extend(serialize(&cashier));
+            message.extend(serialize(&addr.to_string()));
+            message.extend(serialize(&seq));
+            message.extend(serialize(&timestamp));
+            let signature = bs58::encode(serialize(&secret.sign(&message))).into_string();
+
+            let params = json!([addr.to_string(), seq, timestamp, signature]);
+            let req = JsonRequest::new("deposit_events", params);
+            let events = match rpc_client.request(req).await? {
+                Value::Array(v) => v,
+                _ => return Err(Error::ParseFailed("Invalid deposit events reply")),
+            };
+
+            for e in &events {
+                seq = e["seq"].as_u64().unwrap_or(seq);
+                let network = e["network"].as_str().unwrap_or_default();
+                let address = e["address"].as_str().unwrap_or_default().to_string();
+                let state = e["state"].as_str().unwrap_or_default();
+                let time = Timestamp(e["timestamp"].as_i64().unwrap_or_default());
+
+                match e["reason"].as_str() {
+                    Some(reason) => {
+                        println!("{} {} {}: {} ({})", time, network, address, state, reason)
+                    }
+                    None => println!("{} {} {}: {}", time, network, address, state),
+                }
+
+                match state {
+                    "pending" | "received" => pending.insert(address),
+                    _ => pending.remove(&address),
+                };
+            }
+
+            seen |= !events.is_empty();
+            if pending.is_empty() {
+                break
+            }
+        }
+
+        if !seen {
+            println!("No deposits");
+        }
+
+        rpc_client.close().await
+    }
+
+    /// Secret key of `address`, exported from darkfid's wallet
+    async fn wallet_secret(&self, address: &Address) -> Result<SecretKey> {
+        let req = JsonRequest::new("wallet.get_key", json!([-1]));
+        let rep = self.rpc_client.request(req).await?;
+        let address_str = address.to_string();
+        let index = rep
+            .as_array()
+            .and_then(|keys| keys.iter().position(|x| x.as_str() == Some(address_str.as_str())))
+            .ok_or(Error::ParseFailed("Address is not in the wallet"))?;
+
+        let req = JsonRequest::new("wallet.export_keypair", json!([index]));
+        let rep = self.rpc_client.request(req).await?;
+        let bytes: [u8; 32] = serde_json::from_value(rep)
+            .map_err(|_| Error::ParseFailed("Invalid exported secret key"))?;
+        let secret = SecretKey::from_bytes(bytes)?;
+
+        if PublicKey::from_secret(secret) != PublicKey::try_from(*address)? {
+            return Err(Error::ParseFailed("Exported key doesn't match the address"))
+        }
+
+        Ok(secret)
+    }
+
     async fn wallet_keygen(&self) -> Result<()> {
         let req = JsonRequest::new("wallet.keygen", json!([]));
         let rep = self.rpc_client.request(req).await?;
@@ -139,8 +327,17 @@ impl Drk {
     async fn wallet_balance(&self) -> Result<()> {
         let req = JsonRequest::new("wallet.get_balances", json!([]));
         let rep = self.rpc_client.request(req).await?;
-        // TODO: Better representation
-        println!("Balances:\n{:#?}", rep);
+
+        let lines = self.display.format_balances(self.denomination, &rep);
+        if lines.is_empty() {
+            println!("No balances");
+            return Ok(())
+        }
+
+        println!("Balances:");
+        for line in lines {
+            println!("{}", line);
+        }
         Ok(())
     }
 
@@ -164,31 +361,151 @@ impl Drk {
         token_id: String,
         recipient: Address,
         amount: f64,
+        memo: Option<String>,
     ) -> Result<()> {
         println!("Attempting to transfer {} tokens to {}", amount, recipient);
 
-        let req = JsonRequest::new(
-            "tx.transfer",
-            json!([network.to_string(), token_id, recipient.to_string(), amount]),
-        );
+        let mut params = json!([network.to_string(), token_id, recipient.to_string(), amount]);
+        if let Some(memo) = memo {
+            params.as_array_mut().unwrap().push(json!(memo));
+        }
+
+        let req = JsonRequest::new("tx.transfer", params);
 
         let rep = self.rpc_client.request(req).await?;
 
         println!("Success! Transaction ID: {}", rep);
         Ok(())
     }
+
+    async fn tokens_refresh(&self) -> Result<()> {
+        let req = JsonRequest::new("tokens.refresh", json!([]));
+        let rep = self.rpc_client.request(req).await?;
+
+        let results = match rep {
+            Value::Array(v) => v,
+            _ => return Err(Error::ParseFailed("Invalid token list refresh reply")),
+        };
+        if results.is_empty() {
+            println!("No token lists configured");
+            return Ok(())
+        }
+
+        for r in results {
+            let network = r["network"].as_str().unwrap_or_default();
+            let url = r["url"].as_str().unwrap_or_default();
+            let error = r["error"].as_str().unwrap_or_default();
+            match r["tokens"].as_u64() {
+                Some(n) => println!("{}: {} tokens from {}", network, n, url),
+                None => println!("{}: failed fetching {}: {}", network, url, error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch the whole transaction log from the wallet, newest entries first.
+    /// Pages are requested [`HISTORY_BATCH_PAGES`] at a time.
+    async fn get_tx_history(&self) -> Result<Vec<Value>> {
+        let mut entries = vec![];
+
+        loop {
+            let offset = entries.len() as u64;
+            let reqs = (0..HISTORY_BATCH_PAGES)
+                .map(|i| {
+                    let params = json!([offset + i * HISTORY_PAGE_SIZE, HISTORY_PAGE_SIZE]);
+                    JsonRequest::new("wallet.get_tx_history", params)
+                })
+                .collect();
+
+            for rep in self.rpc_client.send_batch(reqs).await? {
+                let page = match rep? {
+                    Value::Array(v) => v,
+                    _ => return Err(Error::ParseFailed("Invalid transaction history reply")),
+                };
+
+                let done = (page.len() as u64) < HISTORY_PAGE_SIZE;
+                entries.extend(page);
+                if done {
+                    return Ok(entries)
+                }
+            }
+        }
+    }
+
+    async fn history(
+        &self,
+        export: Option<ExportFormat>,
+        output: Option<String>,
+        yes: bool,
+    ) -> Result<()> {
+        let entries = self.get_tx_history().await?;
+        let records = history_records(&entries);
+
+        let format = match export {
+            Some(v) => v,
+            None => {
+                if records.is_empty() {
+                    println!("No transactions");
+                    return Ok(())
+                }
+
+                for r in records.iter().rev() {
+                    print!(
+                        "{} {:>8} {:>16} {} (balance {})",
+                        r.timestamp, r.direction, r.amount, r.token, r.balance
+                    );
+                    match r.memo.is_empty() {
+                        true => println!(),
+                        false => println!(" {:?}", r.memo),
+                    }
+                }
+                return Ok(())
+            }
+        };
+
+        let exported = export_records(&records, format);
+        match output {
+            Some(path) => {
+                if !confirm_overwrite(Path::new(&path), yes)? {
+                    println!("Export cancelled");
+                    return Ok(())
+                }
+                fs::write(&path, exported)?;
+                println!("Exported {} transactions to {}", records.len(), path);
+            }
+            None => print!("{}", exported),
+        }
+
+        Ok(())
+    }
 }
 
 #[async_std::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
 
+    // Needs no darkfid
+    if let DrkSubcommand::Completions { shell } = args.command {
+        print_completions::<Args>(shell);
+        return Ok(())
+    }
+
     let log_level = get_log_level(args.verbose.into());
     let log_config = get_log_config();
     TermLogger::init(log_level, log_config, TerminalMode::Mixed, ColorChoice::Auto)?;
 
-    let rpc_client = RpcClient::new(args.endpoint).await?;
-    let drk = Drk { rpc_client };
+    // The config file is optional, it only holds display settings
+    let config_path = get_config_path(args.config, CONFIG_FILE)?;
+    let display = if config_path.exists() {
+        Config::<DisplayConfig>::load(config_path)?
+    } else {
+        DisplayConfig::default()
+    };
+    let denomination = args.denomination.unwrap_or(display.denomination);
+
+    let mut rpc_client = RpcClient::new(args.endpoint).await?;
+    rpc_client.set_auth_token(args.rpc_token);
+    let drk = Drk { rpc_client, display, denomination };
 
     match args.command {
         DrkSubcommand::Ping => drk.ping().await,
@@ -197,6 +514,10 @@ async fn main() -> Result<()> {
             drk.airdrop(address, faucet_endpoint, amount).await
         }
 
+        DrkSubcommand::Deposits { address, cashier_endpoint } => {
+            drk.deposits(address, cashier_endpoint).await
+        }
+
         DrkSubcommand::Wallet { keygen, balance, address, all_addresses } => {
             if keygen {
                 return drk.wallet_keygen().await
@@ -218,9 +539,28 @@ async fn main() -> Result<()> {
             exit(2);
         }
 
-        DrkSubcommand::Transfer { recipient, amount, network, token_id } => {
-            drk.tx_transfer(network, token_id, recipient, amount).await
+        DrkSubcommand::Transfer { recipient, amount, network, token_id, memo } => {
+            let question = format!("Transfer {} of token {} to {}?", amount, token_id, recipient);
+            if !confirm(&question, args.yes)? {
+                println!("Transfer cancelled");
+                return drk.close_connection().await
+            }
+
+            drk.tx_transfer(network, token_id, recipient, amount, memo).await
+        }
+
+        DrkSubcommand::Tokens { refresh } => {
+            if refresh {
+                return drk.tokens_refresh().await
+            }
+
+            eprintln!("Run 'drk tokens -h' to see the subcommand usage.");
+            exit(2);
         }
+
+        DrkSubcommand::History { export, output } => drk.history(export, output, args.yes).await,
+
+        DrkSubcommand::Completions { .. } => unreachable!(),
     }?;
 
     drk.close_connection().await
@@ -14,17 +14,18 @@ use structopt_toml::StructOptToml;
 use url::Url;
 
 use darkfi::{
-    async_daemonize, cli_desc,
+    async_daemonize,
+    blockchain::wipe_chain,
+    cli_desc,
     consensus::{
         proto::{ProtocolSync, ProtocolTx},
         task::block_sync_task,
-        ValidatorState, ValidatorStatePtr, MAINNET_GENESIS_HASH_BYTES, MAINNET_GENESIS_TIMESTAMP,
-        TESTNET_GENESIS_HASH_BYTES, TESTNET_GENESIS_TIMESTAMP,
+        Block, ChainParams, ConsensusKey, Genesis, ValidatorState, ValidatorStatePtr,
     },
-    crypto::{address::Address, keypair::PublicKey, token_list::DrkTokenList},
+    crypto::{address::Address, keypair::PublicKey, note::Memo, token_list::DrkTokenList},
     net,
     net::P2pPtr,
-    node::Client,
+    node::{Client, TokenRegistry},
     rpc::{
         jsonrpc::{
             ErrorCode::{InternalError, InvalidParams, MethodNotFound},
@@ -33,14 +34,16 @@ use darkfi::{
         server::{listen_and_serve, RequestHandler},
     },
     util::{
+        check::CheckReport,
         cli::{get_log_config, get_log_level, spawn_config},
         decode_base10, expand_path,
+        lock::{lock_owner, DbLock},
         path::get_config_path,
         serial::serialize,
-        sleep, NetworkName,
+        sleep, DataDir, NetworkName,
     },
     wallet::walletdb::init_wallet,
-    Error, Result,
+    Result,
 };
 
 mod error;
@@ -61,17 +64,34 @@ struct Args {
     /// Chain to use (testnet, mainnet)
     chain: String,
 
-    #[structopt(long, default_value = "~/.config/darkfi/faucetd_wallet.db")]
-    /// Path to wallet database
-    wallet_path: String,
+    #[structopt(long)]
+    /// Genesis file of the chain, made with darkfi-genesis (not on mainnet)
+    genesis: Option<String>,
+
+    #[structopt(long)]
+    /// Delete the chain database and the wallet coins before starting,
+    /// keeping the wallet keys
+    wipe_chain: bool,
+
+    #[structopt(long)]
+    /// Take over the database locks left by an instance that crashed
+    force_unlock: bool,
+
+    #[structopt(long)]
+    /// Data directory, holding a directory per chain (default: ~/.local/share/darkfi)
+    data_dir: Option<String>,
+
+    #[structopt(long)]
+    /// Path to wallet database (default: faucetd_wallet.db in the chain directory)
+    wallet_path: Option<String>,
 
     #[structopt(long, default_value = "changeme")]
     /// Password for the wallet database
     wallet_pass: String,
 
-    #[structopt(long, default_value = "~/.config/darkfi/faucetd_blockchain")]
-    /// Path to blockchain database
-    database: String,
+    #[structopt(long)]
+    /// Path to blockchain database, holding a database per chain
+    database: Option<String>,
 
     #[structopt(long, default_value = "tcp://127.0.0.1:9340")]
     /// JSON-RPC listen URL
@@ -85,6 +105,14 @@ struct Args {
     /// P2P external address for the syncing protocol
     sync_p2p_external: Option<Url>,
 
+    #[structopt(long)]
+    /// Map the syncing protocol accept port on the gateway (NAT-PMP or UPnP)
+    sync_port_mapping: bool,
+
+    #[structopt(long)]
+    /// SOCKS5 proxy to dial peers through, e.g. Tor's
+    socks5_proxy: Option<Url>,
+
     #[structopt(long, default_value = "8")]
     /// Connection slots for the syncing protocol
     sync_slots: u32,
@@ -113,6 +141,15 @@ struct Args {
     /// Airdrop amount limit
     airdrop_limit: String, // We convert this to biguint with decode_base10
 
+    #[structopt(long)]
+    /// Check the configuration, databases and ports, print a JSON report
+    /// and exit
+    check: bool,
+
+    #[structopt(long)]
+    /// With --check, also check the configured peers and seeds can be reached
+    check_upstreams: bool,
+
     #[structopt(short, parse(from_occurrences))]
     /// Increase verbosity (-vvv supported)
     verbose: u8,
@@ -218,7 +255,7 @@ impl Faucetd {
         };
         drop(map);
 
-        let token_id = self.client.tokenlist.by_net[&NetworkName::DarkFi]
+        let token_id = self.client.tokenlist.current().by_net[&NetworkName::DarkFi]
             .get("DRK".to_string())
             .unwrap()
             .drk_address;
@@ -237,6 +274,7 @@ impl Faucetd {
                 pubkey,
                 amnt,
                 token_id,
+                Memo::default(),
                 true,
                 self.validator_state.read().await.state_machine.clone(),
             )
@@ -293,8 +331,97 @@ async fn prune_airdrop_map(map: Arc<Mutex<HashMap<Address, i64>>>, timeout: i64)
     }
 }
 
+/// Parameters of the configured chain, with the genesis file applied
+fn chain_params(args: &Args) -> Result<(ChainParams, Option<Genesis>)> {
+    let mut params = match ChainParams::from_network(&args.chain) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Unsupported chain `{}`", args.chain);
+            return Err(e)
+        }
+    };
+
+    let genesis = match &args.genesis {
+        Some(path) => Some(Genesis::load(&expand_path(path)?)?),
+        None => None,
+    };
+    if let Some(genesis) = &genesis {
+        if let Err(e) = genesis.apply(&mut params) {
+            error!("Genesis file is for `{}`, can't use it on `{}`", genesis.network, args.chain);
+            return Err(e)
+        }
+    }
+
+    Ok((params, genesis))
+}
+
+/// Check what faucetd needs to start, without starting it or writing to
+/// its data, and print the report.
+async fn self_check(args: &Args) -> Result<()> {
+    let mut report = CheckReport::new();
+
+    let genesis = match chain_params(args) {
+        Ok((params, _)) => {
+            let genesis = Block::genesis_block(params.genesis_ts, params.genesis_data).header;
+            let detail = format!("{}, genesis block {}", params.network, genesis);
+            report.add("chain_params", Ok(detail));
+            Some(genesis)
+        }
+        Err(e) => {
+            report.add("chain_params", Err(e));
+            None
+        }
+    };
+
+    let limit = decode_base10(&args.airdrop_limit, 8, true);
+    report.add("airdrop_limit", limit.map(|l| l.to_string()));
+
+    for (name, addrs) in [("cashier_pub", &args.cashier_pub), ("faucet_pub", &args.faucet_pub)] {
+        let parsed: Result<Vec<PublicKey>> =
+            addrs.iter().map(|a| PublicKey::try_from(Address::from_str(a)?)).collect();
+        report.add(name, parsed.map(|p| format!("{} addresses", p.len())));
+    }
+
+    let data_dir = DataDir::new(args.data_dir.as_deref(), &args.chain)?;
+    let wallet_path = data_dir.find(
+        args.wallet_path.as_deref(),
+        "faucetd_wallet.db",
+        "~/.config/darkfi/faucetd_wallet.db",
+    )?;
+    report.check_wallet("wallet", &wallet_path, &args.wallet_pass).await;
+
+    let db_path = match &args.database {
+        Some(v) => expand_path(v)?.join(&args.chain),
+        None => {
+            let legacy = format!("~/.config/darkfi/faucetd_blockchain/{}", args.chain);
+            data_dir.find(None, "faucetd_blockchain", &legacy)?
+        }
+    };
+    report.check_chain_db("chain_database", &db_path, genesis);
+
+    // A running instance holds the wallet, and its ports
+    let running = lock_owner(&wallet_path)?.is_some();
+    report.check_listen("rpc_listen", &args.rpc_listen, running);
+    if let Some(url) = &args.sync_p2p_accept {
+        report.check_listen("sync_p2p_accept", url, running);
+    }
+
+    if args.check_upstreams {
+        let mut upstreams: Vec<(&str, &Url)> = vec![];
+        upstreams.extend(args.sync_p2p_seed.iter().map(|u| ("sync_p2p_seed", u)));
+        upstreams.extend(args.sync_p2p_peer.iter().map(|u| ("sync_p2p_peer", u)));
+        report.check_upstreams(&upstreams, args.socks5_proxy.as_ref());
+    }
+
+    report.finish()
+}
+
 async_daemonize!(realmain);
 async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
+    if args.check {
+        return self_check(&args).await
+    }
+
     // We use this handler to block this function after detaching all
     // tasks, and to catch a shutdown signal, where we can clean up and
     // exit gracefully.
@@ -304,29 +431,44 @@ async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
     })
     .unwrap();
 
+    // Whatever isn't configured goes in the chain's data directory, where
+    // data left in the legacy locations is moved.
+    let data_dir = DataDir::new(args.data_dir.as_deref(), &args.chain)?;
+    let wallet_path = data_dir.locate(
+        args.wallet_path.as_deref(),
+        "faucetd_wallet.db",
+        "~/.config/darkfi/faucetd_wallet.db",
+    )?;
+    let db_path = match &args.database {
+        Some(v) => expand_path(v)?.join(&args.chain),
+        None => {
+            let legacy = format!("~/.config/darkfi/faucetd_blockchain/{}", args.chain);
+            data_dir.locate(None, "faucetd_blockchain", &legacy)?
+        }
+    };
+
+    // Held until we exit, so no other process opens the databases under us
+    let _wallet_lock = DbLock::acquire(&wallet_path, args.force_unlock)?;
+
     // Initialize or load wallet
-    let wallet = init_wallet(&args.wallet_path, &args.wallet_pass).await?;
+    let wallet = init_wallet(wallet_path.to_str().unwrap(), &args.wallet_pass).await?;
 
     // Initialize or open sled database
-    let db_path = format!("{}/{}", expand_path(&args.database)?.to_str().unwrap(), args.chain);
+    let _db_lock = DbLock::acquire(&db_path, args.force_unlock)?;
+    if args.wipe_chain {
+        wipe_chain(&db_path)?;
+    }
     let sled_db = sled::open(&db_path)?;
 
     // Initialize validator state
-    let (genesis_ts, genesis_data) = match args.chain.as_str() {
-        "mainnet" => (*MAINNET_GENESIS_TIMESTAMP, *MAINNET_GENESIS_HASH_BYTES),
-        "testnet" => (*TESTNET_GENESIS_TIMESTAMP, *TESTNET_GENESIS_HASH_BYTES),
-        x => {
-            error!("Unsupported chain `{}`", x);
-            return Err(Error::UnsupportedChain)
-        }
-    };
+    let (params, genesis) = chain_params(&args)?;
 
-    let tokenlist = Arc::new(DrkTokenList::new(&[
+    let tokenlist = Arc::new(TokenRegistry::new(DrkTokenList::new(&[
         ("drk", include_bytes!("../../../contrib/token/darkfi_token_list.min.json")),
         ("btc", include_bytes!("../../../contrib/token/bitcoin_token_list.min.json")),
         ("eth", include_bytes!("../../../contrib/token/erc20_token_list.min.json")),
         ("sol", include_bytes!("../../../contrib/token/solana_token_list.min.json")),
-    ])?);
+    ])?));
 
     // TODO: sqldb init cleanup
     // Initialize client
@@ -348,23 +490,38 @@ async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
         faucet_pubkeys.push(pk);
     }
 
-    // Initialize validator state
+    if let Some(genesis) = &genesis {
+        cashier_pubkeys.extend(genesis.cashier_pubkeys()?);
+        faucet_pubkeys.extend(genesis.faucet_pubkeys()?);
+    }
+
+    // Initialize validator state. The faucet never signs proposals or
+    // votes, so its consensus key is thrown away on exit.
     let state = ValidatorState::new(
         &sled_db,
-        genesis_ts,
-        genesis_data,
+        params,
         client,
+        ConsensusKey::random(),
         cashier_pubkeys,
         faucet_pubkeys,
     )
     .await?;
 
+    // A wiped chain only has its genesis block, so reindexing it clears
+    // the coins of the old chain from the wallet.
+    if args.wipe_chain {
+        state.read().await.reindex().await?;
+    }
+
     // P2P network. The faucet doesn't participate in consensus, so we only
     // build the sync protocol.
     let network_settings = net::Settings {
         inbound: args.sync_p2p_accept,
         outbound_connections: args.sync_slots,
         external_addr: args.sync_p2p_external,
+        port_mapping: args.sync_port_mapping,
+        socks5_proxy: args.socks5_proxy,
+        hosts_file: Some(data_dir.path().join("faucetd_sync_hosts").display().to_string()),
         peers: args.sync_p2p_peer.clone(),
         seeds: args.sync_p2p_seed.clone(),
         ..Default::default()
@@ -18,6 +18,7 @@ use darkfi::{
     raft::{NetMsg, ProtocolRaft, Raft},
     rpc::server::listen_and_serve,
     util::{
+        check::CheckReport,
         cli::{get_log_config, get_log_level, spawn_config},
         path::{expand_path, get_config_path},
     },
@@ -147,8 +148,36 @@ async fn process(
     }
 }
 
+/// Check what irc-raft needs to start, without starting it, and print the
+/// report.
+fn self_check(settings: &Args) -> Result<()> {
+    let mut report = CheckReport::new();
+
+    let cfg_path = get_config_path(settings.config.clone(), CONFIG_FILE)?;
+    let channels = parse_configured_channels(&cfg_path);
+    report.add("channels", channels.map(|c| format!("{} configured channels", c.len())));
+
+    // A missing datastore is created on start
+    let datastore_path = expand_path(&settings.datastore)?;
+    if datastore_path.exists() {
+        report.check_path("datastore", &datastore_path);
+    } else {
+        report.add("datastore", Ok(format!("Not created yet: {}", datastore_path.display())));
+    }
+
+    report.check_listen("rpc_listen", &settings.rpc_listen, false);
+    report.check_listen("irc_listen", &settings.irc_listen, false);
+    report.check_net(&settings.net, false, settings.check_upstreams);
+
+    report.finish()
+}
+
 async_daemonize!(realmain);
 async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
+    if settings.check {
+        return self_check(&settings)
+    }
+
     if settings.gen_secret {
         let secret_key = crypto_box::SecretKey::generate(&mut OsRng);
         let encoded = bs58::encode(secret_key.as_bytes());
@@ -19,6 +19,7 @@ use darkfi::{
     rpc::server::listen_and_serve,
     system::{Subscriber, SubscriberPtr},
     util::{
+        check::CheckReport,
         cli::{get_log_config, get_log_level, spawn_config},
         expand_path,
         path::get_config_path,
@@ -146,8 +147,38 @@ impl Ircd {
     }
 }
 
+/// Check what ircd needs to start, without starting it, and print the
+/// report.
+fn self_check(settings: &Args) -> Result<()> {
+    let mut report = CheckReport::new();
+
+    let cfg_path = get_config_path(settings.config.clone(), CONFIG_FILE)?;
+    let channels = parse_configured_channels(&cfg_path);
+    report.add("channels", channels.map(|c| format!("{} configured channels", c.len())));
+
+    if settings.irc_listen.scheme() == "tls" {
+        match (&settings.irc_tls_cert, &settings.irc_tls_secret) {
+            (Some(cert), Some(secret)) => {
+                report.check_path("irc_tls_cert", &expand_path(cert)?);
+                report.check_path("irc_tls_secret", &expand_path(secret)?);
+            }
+            _ => report.fail("irc_tls", "Set irc_tls_secret and irc_tls_cert to use TLS".into()),
+        }
+    }
+
+    report.check_listen("rpc_listen", &settings.rpc_listen, false);
+    report.check_listen("irc_listen", &settings.irc_listen, false);
+    report.check_net(&settings.net, false, settings.check_upstreams);
+
+    report.finish()
+}
+
 async_daemonize!(realmain);
 async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
+    if settings.check {
+        return self_check(&settings)
+    }
+
     let seen_msg_ids =
         Arc::new(Mutex::new(ringbuffer::AllocRingBuffer::with_capacity(SIZE_OF_MSG_IDSS_BUFFER)));
     let privmsgs_buffer: PrivmsgsBuffer =
@@ -6,7 +6,7 @@ use url::Url;
 use darkfi::{
     net,
     rpc::{
-        jsonrpc::{ErrorCode, JsonError, JsonRequest, JsonResponse, JsonResult},
+        jsonrpc::{redact_auth, ErrorCode, JsonError, JsonRequest, JsonResponse, JsonResult},
         server::RequestHandler,
     },
 };
@@ -23,11 +23,12 @@ impl RequestHandler for JsonRpcInterface {
             return JsonError::new(ErrorCode::InvalidRequest, None, req.id).into()
         }
 
-        debug!(target: "RPC", "--> {}", serde_json::to_string(&req).unwrap());
+        debug!(target: "RPC", "--> {}", redact_auth(&json!(req)));
 
         match req.method.as_str() {
             Some("ping") => self.pong(req.id, req.params).await,
             Some("get_info") => self.get_info(req.id, req.params).await,
+            Some("get_bandwidth_stats") => self.get_bandwidth_stats(req.id, req.params).await,
             Some(_) | None => JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
         }
     }
@@ -50,4 +51,14 @@ impl JsonRpcInterface {
         let resp = self.p2p.get_info().await;
         JsonResponse::new(resp, id).into()
     }
+
+    // RPCAPI:
+    // Retrieves the bytes sent and received on each P2P channel by message type,
+    // and the bytes per second going through each channel lately.
+    // --> {"jsonrpc": "2.0", "method": "get_bandwidth_stats", "params": [], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": {"channels": [...], "messages": {...}, "total": {...}, "throughput": {...}}, "id": 42}
+    async fn get_bandwidth_stats(&self, id: Value, _params: Value) -> JsonResult {
+        let resp = self.p2p.get_bandwidth_stats().await;
+        JsonResponse::new(resp, id).into()
+    }
 }
@@ -44,6 +44,14 @@ pub struct Args {
     #[structopt(long)]
     pub autojoin: Vec<String>,
 
+    /// Check the configuration and ports, print a JSON report and exit
+    #[structopt(long)]
+    pub check: bool,
+
+    /// With --check, also check the configured peers and seeds can be reached
+    #[structopt(long)]
+    pub check_upstreams: bool,
+
     #[structopt(flatten)]
     pub net: SettingsOpt,
 
@@ -0,0 +1,308 @@
+//! Authenticated and encrypted channel between the two ends of the proxy.
+//!
+//! Both ends hold a long-term keypair. The client knows the public key of
+//! the server it dials, and the server only accepts the client public keys
+//! it was configured with. The handshake agrees on a secret with ephemeral
+//! keys, and either side signs the transcript with its long-term key:
+//!
+//! ```text
+//! client -> server: client public key, client ephemeral public key
+//! server -> client: server public key, server ephemeral public key,
+//!                   server signature over the transcript
+//! client -> server: client signature over the transcript
+//! ```
+//!
+//! The keys for either direction are derived from the ephemeral shared
+//! secret and the transcript. Data is then sent in frames sealed with
+//! ChaCha20-Poly1305, prefixed with their length, and using a counter as
+//! nonce, so frames can't be replayed or reordered.
+use std::io;
+
+use blake2b_simd::{Hash as Blake2bHash, Params as Blake2bParams};
+use crypto_api_chachapoly::ChachaPolyIetf;
+use futures::{
+    io::{ReadHalf, WriteHalf},
+    AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+};
+use rand::rngs::OsRng;
+
+use darkfi::{
+    crypto::{
+        diffie_hellman::sapling_ka_agree,
+        keypair::{Keypair, PublicKey},
+        schnorr::{SchnorrPublic, SchnorrSecret, Signature},
+    },
+    util::serial::{deserialize, serialize},
+    Error, Result,
+};
+
+pub const TRANSCRIPT_PERSONALIZATION: &[u8; 16] = b"DarkFiRpcPrxTrns";
+pub const KEY_PERSONALIZATION: &[u8; 16] = b"DarkFiRpcPrxKeys";
+
+/// Largest payload of a single frame
+pub const MAX_FRAME_SIZE: usize = 1024 * 1024;
+pub const AEAD_TAG_SIZE: usize = 16;
+
+/// Encoded size of a Schnorr signature
+const SIGNATURE_SIZE: usize = 64;
+
+/// Hash of the public keys exchanged during the handshake.
+fn transcript(
+    client: &PublicKey,
+    client_ephem: &PublicKey,
+    server: &PublicKey,
+    server_ephem: &PublicKey,
+) -> Blake2bHash {
+    Blake2bParams::new()
+        .hash_length(32)
+        .personal(TRANSCRIPT_PERSONALIZATION)
+        .to_state()
+        .update(&client.to_bytes())
+        .update(&client_ephem.to_bytes())
+        .update(&server.to_bytes())
+        .update(&server_ephem.to_bytes())
+        .finalize()
+}
+
+/// Message signed by either side, labelled with the signer's role so a
+/// signature can't be reflected back.
+fn signed_message(transcript: &Blake2bHash, role: &[u8]) -> Vec<u8> {
+    [transcript.as_bytes(), role].concat()
+}
+
+/// Derive the key for one direction of the channel.
+fn derive_key(shared: &PublicKey, transcript: &Blake2bHash, direction: &[u8]) -> [u8; 32] {
+    let hash = Blake2bParams::new()
+        .hash_length(32)
+        .personal(KEY_PERSONALIZATION)
+        .to_state()
+        .update(&shared.to_bytes())
+        .update(transcript.as_bytes())
+        .update(direction)
+        .finalize();
+
+    hash.as_bytes().try_into().unwrap()
+}
+
+fn nonce(counter: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..8].copy_from_slice(&counter.to_le_bytes());
+    nonce
+}
+
+async fn read_public<R: AsyncRead + Unpin>(reader: &mut R) -> Result<PublicKey> {
+    let mut buf = [0u8; 32];
+    reader.read_exact(&mut buf).await?;
+    PublicKey::from_bytes(&buf)
+}
+
+async fn read_signature<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Signature> {
+    let mut buf = [0u8; SIGNATURE_SIZE];
+    reader.read_exact(&mut buf).await?;
+    deserialize(&buf)
+}
+
+/// Receiving half of an encrypted channel.
+pub struct FrameReader<R> {
+    reader: R,
+    key: [u8; 32],
+    counter: u64,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    /// Read and decrypt the next frame. Returns `None` once the other end
+    /// closed the channel, and fails if the frame was tampered with.
+    pub async fn recv(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut len = [0u8; 4];
+        match self.reader.read_exact(&mut len).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let size = u32::from_le_bytes(len) as usize;
+        if size > MAX_FRAME_SIZE {
+            return Err(Error::RpcProxyError(format!("Frame of {} bytes is too large", size)))
+        }
+
+        let mut ciphertext = vec![0u8; size + AEAD_TAG_SIZE];
+        self.reader.read_exact(&mut ciphertext).await?;
+
+        let mut plaintext = vec![0u8; size];
+        ChachaPolyIetf::aead_cipher()
+            .open_to(&mut plaintext, &ciphertext, &len, &self.key, &nonce(self.counter))
+            .map_err(|_| Error::RpcProxyError("Failed decrypting frame".into()))?;
+        self.counter += 1;
+
+        Ok(Some(plaintext))
+    }
+}
+
+/// Sending half of an encrypted channel.
+pub struct FrameWriter<W> {
+    writer: W,
+    key: [u8; 32],
+    counter: u64,
+}
+
+impl<W: AsyncWrite + Unpin> FrameWriter<W> {
+    /// Encrypt `data` and send it, split into as many frames as needed.
+    pub async fn send(&mut self, data: &[u8]) -> Result<()> {
+        for chunk in data.chunks(MAX_FRAME_SIZE) {
+            let len = (chunk.len() as u32).to_le_bytes();
+
+            let mut ciphertext = vec![0u8; chunk.len() + AEAD_TAG_SIZE];
+            ChachaPolyIetf::aead_cipher()
+                .seal_to(&mut ciphertext, chunk, &len, &self.key, &nonce(self.counter))
+                .unwrap();
+            self.counter += 1;
+
+            self.writer.write_all(&len).await?;
+            self.writer.write_all(&ciphertext).await?;
+        }
+
+        self.writer.flush().await?;
+        Ok(())
+    }
+}
+
+pub type SecureChannel<S> = (FrameReader<ReadHalf<S>>, FrameWriter<WriteHalf<S>>);
+
+/// Run the handshake as the dialing end, and check the other end holds
+/// the secret key of `server_public`.
+pub async fn client_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    keypair: &Keypair,
+    server_public: &PublicKey,
+) -> Result<SecureChannel<S>> {
+    let (mut reader, mut writer) = stream.split();
+
+    let ephem = Keypair::random(&mut OsRng);
+    writer.write_all(&keypair.public.to_bytes()).await?;
+    writer.write_all(&ephem.public.to_bytes()).await?;
+    writer.flush().await?;
+
+    let public = read_public(&mut reader).await?;
+    let public_ephem = read_public(&mut reader).await?;
+    let signature = read_signature(&mut reader).await?;
+
+    if public != *server_public {
+        return Err(Error::RpcProxyError("Server presented an unexpected public key".into()))
+    }
+
+    let transcript = transcript(&keypair.public, &ephem.public, &public, &public_ephem);
+    if !public.verify(&signed_message(&transcript, b"server"), &signature) {
+        return Err(Error::RpcProxyError("Invalid server handshake signature".into()))
+    }
+
+    let signature = keypair.secret.sign(&signed_message(&transcript, b"client"));
+    writer.write_all(&serialize(&signature)).await?;
+    writer.flush().await?;
+
+    let shared = sapling_ka_agree(&ephem.secret, &public_ephem);
+    let reader = FrameReader {
+        reader,
+        key: derive_key(&shared, &transcript, b"server to client"),
+        counter: 0,
+    };
+    let writer = FrameWriter {
+        writer,
+        key: derive_key(&shared, &transcript, b"client to server"),
+        counter: 0,
+    };
+
+    Ok((reader, writer))
+}
+
+/// Run the handshake as the accepting end. Clients whose public key isn't
+/// in `authorized` are turned away before the server reveals its own.
+/// Returns the public key of the client along with the channel.
+pub async fn server_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    keypair: &Keypair,
+    authorized: &[PublicKey],
+) -> Result<(PublicKey, SecureChannel<S>)> {
+    let (mut reader, mut writer) = stream.split();
+
+    let public = read_public(&mut reader).await?;
+    let public_ephem = read_public(&mut reader).await?;
+
+    if !authorized.contains(&public) {
+        return Err(Error::RpcProxyError("Client public key is not authorized".into()))
+    }
+
+    let ephem = Keypair::random(&mut OsRng);
+    let transcript = transcript(&public, &public_ephem, &keypair.public, &ephem.public);
+    let signature = keypair.secret.sign(&signed_message(&transcript, b"server"));
+
+    writer.write_all(&keypair.public.to_bytes()).await?;
+    writer.write_all(&ephem.public.to_bytes()).await?;
+    writer.write_all(&serialize(&signature)).await?;
+    writer.flush().await?;
+
+    let signature = read_signature(&mut reader).await?;
+    if !public.verify(&signed_message(&transcript, b"client"), &signature) {
+        return Err(Error::RpcProxyError("Invalid client handshake signature".into()))
+    }
+
+    let shared = sapling_ka_agree(&ephem.secret, &public_ephem);
+    let reader = FrameReader {
+        reader,
+        key: derive_key(&shared, &transcript, b"client to server"),
+        counter: 0,
+    };
+    let writer = FrameWriter {
+        writer,
+        key: derive_key(&shared, &transcript, b"server to client"),
+        counter: 0,
+    };
+
+    Ok((public, (reader, writer)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::os::unix::net::UnixStream;
+
+    #[test]
+    fn test_handshake() {
+        async_std::task::block_on(async {
+            let server = Keypair::random(&mut OsRng);
+            let client = Keypair::random(&mut OsRng);
+            let stranger = Keypair::random(&mut OsRng);
+
+            // Authorized clients get a working channel both ways
+            let (a, b) = UnixStream::pair().unwrap();
+            let (c, s) = futures::join!(
+                client_handshake(a, &client, &server.public),
+                server_handshake(b, &server, &[client.public]),
+            );
+            let (mut c_reader, mut c_writer) = c.unwrap();
+            let (public, (mut s_reader, mut s_writer)) = s.unwrap();
+            assert_eq!(public, client.public);
+
+            c_writer.send(b"ping").await.unwrap();
+            assert_eq!(s_reader.recv().await.unwrap().unwrap(), b"ping");
+            s_writer.send(b"pong").await.unwrap();
+            assert_eq!(c_reader.recv().await.unwrap().unwrap(), b"pong");
+
+            // Unknown clients are refused
+            let (a, b) = UnixStream::pair().unwrap();
+            let (c, s) = futures::join!(
+                client_handshake(a, &stranger, &server.public),
+                server_handshake(b, &server, &[client.public]),
+            );
+            assert!(c.is_err());
+            assert!(s.is_err());
+
+            // And so are servers with another key than expected
+            let (a, b) = UnixStream::pair().unwrap();
+            let (c, _) = futures::join!(
+                client_handshake(a, &client, &stranger.public),
+                server_handshake(b, &server, &[client.public]),
+            );
+            assert!(c.is_err());
+        });
+    }
+}
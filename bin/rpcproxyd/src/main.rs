@@ -0,0 +1,382 @@
+use std::{fs, io::Write, path::Path, str::FromStr};
+
+use async_executor::Executor;
+use async_std::sync::Arc;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use futures_lite::future;
+use log::{debug, error, info, warn};
+use rand::rngs::OsRng;
+use serde_derive::Deserialize;
+use structopt::StructOpt;
+use structopt_toml::StructOptToml;
+use url::Url;
+
+use darkfi::{
+    async_daemonize, cli_desc,
+    crypto::keypair::{Keypair, PublicKey, SecretKey},
+    net::{
+        transport::Transport, TcpTransport, TorTransport, TransportListener, TransportName,
+        TransportStream, UnixTransport,
+    },
+    util::{
+        check::CheckReport,
+        cli::{get_log_config, get_log_level, spawn_config},
+        expand_path,
+        path::get_config_path,
+    },
+    Error, Result,
+};
+
+mod channel;
+use channel::{client_handshake, server_handshake, FrameReader, FrameWriter};
+
+const CONFIG_FILE: &str = "rpcproxyd_config.toml";
+const CONFIG_FILE_CONTENTS: &str = include_str!("../rpcproxyd_config.toml");
+
+#[derive(Clone, Debug, Deserialize, StructOpt, StructOptToml)]
+#[serde(default)]
+#[structopt(name = "rpcproxyd", about = cli_desc!())]
+struct Args {
+    #[structopt(short, long)]
+    /// Configuration file to use
+    config: Option<String>,
+
+    #[structopt(long)]
+    /// Run the end next to darkfid, rather than the remote end
+    server: bool,
+
+    #[structopt(long, default_value = "tcp://127.0.0.1:8350")]
+    /// URL to accept connections on
+    listen: Url,
+
+    #[structopt(long, default_value = "tcp://127.0.0.1:8340")]
+    /// URL to forward accepted connections to
+    forward: Url,
+
+    #[structopt(long, default_value = "~/.config/darkfi/rpcproxyd_secret.key")]
+    /// Path to the secret key of this end, created if missing
+    secret_path: String,
+
+    #[structopt(long)]
+    /// Public key of the server end (remote end only)
+    server_key: Option<String>,
+
+    #[structopt(long)]
+    /// Public key of a client allowed to connect (server only, repeatable flag)
+    authorized_key: Vec<String>,
+
+    #[structopt(long)]
+    /// Check the configuration, keys and listen address, print a JSON
+    /// report and exit
+    check: bool,
+
+    #[structopt(long)]
+    /// With --check, also check the forward URL can be reached
+    check_upstreams: bool,
+
+    #[structopt(short, parse(from_occurrences))]
+    /// Increase verbosity (-vvv supported)
+    verbose: u8,
+}
+
+/// Which end of the proxy we are.
+enum Mode {
+    /// Next to darkfid, accepting encrypted connections from the given
+    /// client keys and forwarding them in the clear.
+    Server(Vec<PublicKey>),
+    /// Next to drk or tau, accepting connections in the clear and
+    /// forwarding them encrypted to the server with the given key.
+    Client(PublicKey),
+}
+
+struct RpcProxy {
+    keypair: Keypair,
+    mode: Mode,
+    forward: Url,
+}
+
+impl RpcProxy {
+    async fn run(
+        self: Arc<Self>,
+        listener: Box<dyn TransportListener>,
+        ex: Arc<Executor<'_>>,
+    ) -> Result<()> {
+        while let Ok((stream, peer_addr)) = listener.next().await {
+            debug!("Accepted connection from {}", peer_addr);
+            ex.spawn(self.clone().handle(stream, peer_addr)).detach();
+        }
+
+        Ok(())
+    }
+
+    async fn handle(self: Arc<Self>, stream: Box<dyn TransportStream>, peer_addr: Url) {
+        match self.proxy(stream, &peer_addr).await {
+            Ok(()) => debug!("Closed connection from {}", peer_addr),
+            Err(e) => warn!("Proxying connection from {} failed: {}", peer_addr, e),
+        }
+    }
+
+    async fn proxy(&self, stream: Box<dyn TransportStream>, peer_addr: &Url) -> Result<()> {
+        match &self.mode {
+            Mode::Server(authorized) => {
+                // Only reach out to darkfid once the client is authenticated
+                let (public, (reader, writer)) =
+                    server_handshake(stream, &self.keypair, authorized).await?;
+                info!("Client {} connected from {}", encode_public(&public), peer_addr);
+
+                let upstream = dial(&self.forward).await?;
+                relay(upstream, reader, writer).await
+            }
+            Mode::Client(server_public) => {
+                let upstream = dial(&self.forward).await?;
+                let (reader, writer) =
+                    client_handshake(upstream, &self.keypair, server_public).await?;
+                relay(stream, reader, writer).await
+            }
+        }
+    }
+}
+
+/// Pass data between a plain stream and an encrypted channel, until either
+/// side closes.
+async fn relay<S, R, W>(
+    stream: S,
+    mut reader: FrameReader<R>,
+    mut writer: FrameWriter<W>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let (mut plain_reader, mut plain_writer) = stream.split();
+
+    let outgoing = async {
+        // Nasty size
+        let mut buf = vec![0; 2048 * 10];
+        loop {
+            let n = plain_reader.read(&mut buf).await?;
+            if n == 0 {
+                return Ok::<(), Error>(())
+            }
+            writer.send(&buf[..n]).await?;
+        }
+    };
+
+    let incoming = async {
+        while let Some(data) = reader.recv().await? {
+            plain_writer.write_all(&data).await?;
+            plain_writer.flush().await?;
+        }
+        Ok::<(), Error>(())
+    };
+
+    future::race(outgoing, incoming).await
+}
+
+/// Bind a listener on the given URL.
+async fn listen(url: &Url) -> Result<Box<dyn TransportListener>> {
+    macro_rules! listen {
+        ($listener:expr, $transport:expr, $upgrade:expr) => {{
+            let listener = $listener?.await?;
+            match $upgrade {
+                None => Ok(Box::new(listener) as Box<dyn TransportListener>),
+                Some(u) if u == "tls" => {
+                    let tls_listener = $transport.upgrade_listener(listener)?.await?;
+                    Ok(Box::new(tls_listener) as Box<dyn TransportListener>)
+                }
+                Some(u) => Err(Error::UnsupportedTransportUpgrade(u)),
+            }
+        }};
+    }
+
+    match TransportName::try_from(url.clone())? {
+        TransportName::Tcp(upgrade) => {
+            let transport = TcpTransport::new(None, 1024);
+            let listener = transport.listen_on(url.clone());
+            listen!(listener, transport, upgrade)
+        }
+        TransportName::Tor(upgrade) => {
+            let (socks5_url, torc_url, auth_cookie) = TorTransport::get_listener_env()?;
+            let auth_cookie = hex::encode(&fs::read(auth_cookie)?);
+            let transport = TorTransport::new(socks5_url, Some((torc_url, auth_cookie)))?;
+
+            // Generate EHS pointing to local address
+            let hurl = transport.create_ehs(url.clone())?;
+            info!("Created ephemeral hidden service: {}", hurl);
+
+            let listener = transport.clone().listen_on(url.clone());
+            listen!(listener, transport, upgrade)
+        }
+        TransportName::Unix => Ok(Box::new(UnixTransport::new().listen(url.clone()).await?)),
+        TransportName::Nym(_) => Err(Error::UnsupportedTransport(url.scheme().into())),
+    }
+}
+
+/// Open a connection to the given URL.
+async fn dial(url: &Url) -> Result<Box<dyn TransportStream>> {
+    macro_rules! dial {
+        ($stream:expr, $transport:expr, $upgrade:expr) => {{
+            let stream = $stream?.await?;
+            match $upgrade {
+                None => Ok(Box::new(stream) as Box<dyn TransportStream>),
+                Some(u) if u == "tls" => {
+                    let stream = $transport.upgrade_dialer(stream)?.await?;
+                    Ok(Box::new(stream) as Box<dyn TransportStream>)
+                }
+                Some(u) => Err(Error::UnsupportedTransportUpgrade(u)),
+            }
+        }};
+    }
+
+    match TransportName::try_from(url.clone())? {
+        TransportName::Tcp(upgrade) => {
+            let transport = TcpTransport::new(None, 1024);
+            let stream = transport.dial(url.clone(), None);
+            dial!(stream, transport, upgrade)
+        }
+        TransportName::Tor(upgrade) => {
+            let socks5_url = TorTransport::get_dialer_env()?;
+            let transport = TorTransport::new(socks5_url, None)?;
+            let stream = transport.clone().dial(url.clone(), None);
+            dial!(stream, transport, upgrade)
+        }
+        TransportName::Unix => Ok(Box::new(UnixTransport::new().dial(url.clone()).await?)),
+        TransportName::Nym(_) => Err(Error::UnsupportedTransport(url.scheme().into())),
+    }
+}
+
+/// Load the keypair of this end, or create one if there's none yet.
+fn read_keypair(path: &Path) -> Result<Keypair> {
+    let encoded = fs::read_to_string(path)?;
+    let bytes = bs58::decode(encoded.trim()).into_vec()?;
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| Error::SecretKeyFromStr)?;
+    Ok(Keypair::new(SecretKey::from_bytes(bytes)?))
+}
+
+fn load_keypair(path: &Path) -> Result<Keypair> {
+    if path.exists() {
+        return read_keypair(path)
+    }
+
+    let keypair = Keypair::random(&mut OsRng);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Only readable by us
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(bs58::encode(keypair.secret.to_bytes()).into_string().as_bytes())?;
+    info!("Created new secret key in {:?}", path);
+
+    Ok(keypair)
+}
+
+fn encode_public(public: &PublicKey) -> String {
+    bs58::encode(public.to_bytes()).into_string()
+}
+
+/// Check what rpcproxyd needs to start, without starting it or creating
+/// its key, and print the report.
+fn self_check(args: &Args) -> Result<()> {
+    let mut report = CheckReport::new();
+
+    let secret_path = expand_path(&args.secret_path)?;
+    if secret_path.exists() {
+        let keypair = read_keypair(&secret_path);
+        report.add("secret_key", keypair.map(|k| format!("Public key {}", encode_public(&k.public))));
+    } else {
+        report.add("secret_key", Ok(format!("Not created yet: {}", secret_path.display())));
+    }
+
+    if args.server {
+        let keys: Result<Vec<PublicKey>> =
+            args.authorized_key.iter().map(|k| PublicKey::from_str(k)).collect();
+        report.add("authorized_key", keys.map(|k| format!("{} client keys", k.len())));
+    } else {
+        match &args.server_key {
+            Some(key) => report.add("server_key", PublicKey::from_str(key).map(|_| key.clone())),
+            None => report.fail("server_key", "Needed to connect to the server end".into()),
+        }
+    }
+
+    // Only TCP addresses are bound and dialed directly
+    match args.listen.scheme() {
+        "tcp" | "tls" => report.check_listen("listen", &args.listen, false),
+        _ => report.skip("listen", format!("{} isn't a TCP address", args.listen)),
+    }
+
+    if args.check_upstreams {
+        match args.forward.scheme() {
+            "tcp" | "tls" => report.check_upstream("forward", &args.forward),
+            _ => report.skip("forward", format!("{} isn't a TCP address", args.forward)),
+        }
+    }
+
+    report.finish()
+}
+
+async_daemonize!(realmain);
+async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
+    if args.check {
+        return self_check(&args)
+    }
+
+    // We use this handler to block this function after detaching all
+    // tasks, and to catch a shutdown signal, where we can clean up and
+    // exit gracefully.
+    let (signal, shutdown) = async_channel::bounded::<()>(1);
+    ctrlc_async::set_async_handler(async move {
+        signal.send(()).await.unwrap();
+    })
+    .unwrap();
+
+    let keypair = load_keypair(&expand_path(&args.secret_path)?)?;
+    info!("Our public key: {}", encode_public(&keypair.public));
+
+    let mode = if args.server {
+        let mut authorized = vec![];
+        for key in &args.authorized_key {
+            authorized.push(PublicKey::from_str(key)?);
+        }
+        if authorized.is_empty() {
+            warn!("No authorized client keys configured, all clients will be turned away");
+        }
+        Mode::Server(authorized)
+    } else {
+        match &args.server_key {
+            Some(key) => Mode::Client(PublicKey::from_str(key)?),
+            None => {
+                error!("The public key of the server end is needed to connect to it");
+                return Err(Error::ConfigInvalid)
+            }
+        }
+    };
+
+    let listener = match listen(&args.listen).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed binding listener on {}: {}", args.listen, e);
+            return Err(Error::BindFailed(args.listen.to_string()))
+        }
+    };
+    info!("Proxying connections from {} to {}", args.listen, args.forward);
+
+    let proxy = Arc::new(RpcProxy { keypair, mode, forward: args.forward });
+    ex.spawn(proxy.run(listener, ex.clone())).detach();
+
+    // Wait for SIGINT
+    shutdown.recv().await?;
+    print!("\r");
+    info!("Caught termination signal, cleaning up and exiting...");
+
+    Ok(())
+}
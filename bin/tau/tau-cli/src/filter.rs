@@ -1,18 +1,29 @@
+use std::str::FromStr;
+
 use chrono::{Datelike, NaiveDateTime, Utc};
 use serde_json::Value;
 
-use crate::{primitives::TaskInfo, TaskEvent};
+use crate::{primitives::TaskInfo, task_state::TaskState, TaskEvent};
 
 /// Helper function to check task's state
-fn check_task_state(task: &TaskInfo, state: &str) -> bool {
-    let last_state = task.events.last().unwrap_or(&TaskEvent::default()).action.clone();
+fn check_task_state(task: &TaskInfo, state: &TaskState) -> bool {
+    let last_state = &task.events.last().unwrap_or(&TaskEvent::default()).action;
     state == last_state
 }
 
 pub fn apply_filter(tasks: &mut Vec<TaskInfo>, filter: &str) {
     match filter {
-        "open" => tasks.retain(|task| check_task_state(task, "open")),
-        "pause" => tasks.retain(|task| check_task_state(task, "pause")),
+        "open" => tasks.retain(|task| check_task_state(task, &TaskState::Open)),
+        "pause" => tasks.retain(|task| check_task_state(task, &TaskState::Pause)),
+
+        _ if filter.contains("state:") => {
+            let kv: Vec<&str> = filter.split(':').collect();
+            if kv.len() == 2 {
+                if let Ok(state) = TaskState::from_str(kv[1]) {
+                    tasks.retain(|task| check_task_state(task, &state))
+                }
+            }
+        }
 
         _ if filter.len() == 4 && filter.parse::<u32>().is_ok() => {
             let (month, year) =
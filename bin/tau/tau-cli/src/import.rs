@@ -0,0 +1,205 @@
+use std::{collections::HashMap, fs};
+
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::Value;
+
+use darkfi::{Error, Result};
+
+use crate::{primitives::BaseTask, task_state::TaskState, Tau};
+
+/// Maps GitHub/GitLab users and labels onto tau nicknames and projects.
+/// Users and labels that aren't listed are imported as they are.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ImportMap {
+    /// GitHub login or GitLab username -> tau nickname
+    pub users: HashMap<String, String>,
+    /// Issue label -> tau project
+    pub labels: HashMap<String, String>,
+}
+
+impl ImportMap {
+    /// Load the map from a TOML file.
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|_| Error::ParseFailed("invalid import map file"))
+    }
+
+    fn user(&self, name: &str) -> String {
+        self.users.get(name).cloned().unwrap_or_else(|| name.to_string())
+    }
+
+    fn label(&self, name: &str) -> String {
+        self.labels.get(name).cloned().unwrap_or_else(|| name.to_string())
+    }
+}
+
+/// An issue from a GitHub or GitLab export.
+#[derive(Debug, PartialEq)]
+pub struct Issue {
+    pub title: String,
+    pub desc: String,
+    pub assignees: Vec<String>,
+    pub labels: Vec<String>,
+    pub closed: bool,
+    /// Comments as (author, body)
+    pub comments: Vec<(String, String)>,
+}
+
+/// Read a JSON array of issues, as returned by the GitHub or GitLab issue
+/// APIs. Issues may carry their comments in an extra `comments` array.
+/// GitHub pull requests are skipped.
+pub fn issues_from_export(path: &str) -> Result<Vec<Issue>> {
+    let contents = fs::read_to_string(path)?;
+    let values: Vec<Value> = serde_json::from_str(&contents)?;
+
+    let mut issues = vec![];
+    for value in &values {
+        if value.get("pull_request").is_some() {
+            continue
+        }
+
+        match parse_issue(value) {
+            Some(issue) => issues.push(issue),
+            None => warn!("Skipping issue without a title: {}", value),
+        }
+    }
+
+    Ok(issues)
+}
+
+/// GitHub names users by `login`, GitLab by `username`.
+fn user_name(user: &Value) -> Option<String> {
+    user.get("login").or_else(|| user.get("username"))?.as_str().map(String::from)
+}
+
+fn parse_issue(value: &Value) -> Option<Issue> {
+    let title = value.get("title")?.as_str()?.to_string();
+    if title.is_empty() {
+        return None
+    }
+
+    let desc = value
+        .get("body")
+        .or_else(|| value.get("description"))
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+
+    let assignees = match value.get("assignees").and_then(|v| v.as_array()) {
+        Some(users) => users.iter().filter_map(user_name).collect(),
+        None => vec![],
+    };
+
+    // GitHub labels are objects, GitLab labels are plain strings.
+    let labels = match value.get("labels").and_then(|v| v.as_array()) {
+        Some(labels) => labels
+            .iter()
+            .filter_map(|l| l.get("name").unwrap_or(l).as_str().map(String::from))
+            .collect(),
+        None => vec![],
+    };
+
+    let closed = value.get("state").and_then(|v| v.as_str()) == Some("closed");
+
+    let mut comments = vec![];
+    if let Some(values) = value.get("comments").and_then(|v| v.as_array()) {
+        for comment in values {
+            let body = match comment.get("body").and_then(|v| v.as_str()) {
+                Some(v) => v.to_string(),
+                None => continue,
+            };
+            let author = comment
+                .get("user")
+                .or_else(|| comment.get("author"))
+                .and_then(user_name)
+                .unwrap_or_else(|| "unknown".to_string());
+            comments.push((author, body));
+        }
+    }
+
+    Some(Issue { title, desc, assignees, labels, closed, comments })
+}
+
+/// Create a task for every issue, along with its comments. Closed issues
+/// are skipped unless `closed` is set, in which case they're imported
+/// with the `stop` state.
+pub async fn import_issues(
+    tau: &Tau,
+    issues: Vec<Issue>,
+    map: &ImportMap,
+    closed: bool,
+) -> Result<()> {
+    let mut imported = 0;
+
+    for issue in issues {
+        if issue.closed && !closed {
+            continue
+        }
+
+        let task = BaseTask {
+            title: issue.title,
+            desc: Some(issue.desc),
+            assign: issue.assignees.iter().map(|u| map.user(u)).collect(),
+            project: issue.labels.iter().map(|l| map.label(l)).collect(),
+            due: None,
+            rank: None,
+        };
+
+        let task_id = tau.add(task).await?;
+
+        for (author, body) in issue.comments {
+            tau.set_comment(task_id, &format!("{}: {}", map.user(&author), body)).await?;
+        }
+
+        if issue.closed {
+            tau.set_state(task_id, &TaskState::Stop).await?;
+        }
+
+        imported += 1;
+    }
+
+    info!("Imported {} issues", imported);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_github_and_gitlab_issues() {
+        let github = serde_json::json!({
+            "title": "Fix sync",
+            "body": "It hangs",
+            "state": "open",
+            "assignees": [{"login": "alice"}],
+            "labels": [{"name": "bug"}],
+            "comments": [{"body": "Confirmed", "user": {"login": "bob"}}],
+        });
+
+        let gitlab = serde_json::json!({
+            "title": "Fix sync",
+            "description": "It hangs",
+            "state": "closed",
+            "assignees": [{"username": "alice"}],
+            "labels": ["bug"],
+        });
+
+        let issue = parse_issue(&github).unwrap();
+        assert_eq!(issue.desc, "It hangs");
+        assert_eq!(issue.assignees, vec!["alice"]);
+        assert_eq!(issue.labels, vec!["bug"]);
+        assert!(!issue.closed);
+        assert_eq!(issue.comments, vec![("bob".to_string(), "Confirmed".to_string())]);
+
+        let issue = parse_issue(&gitlab).unwrap();
+        assert_eq!(issue.desc, "It hangs");
+        assert_eq!(issue.assignees, vec!["alice"]);
+        assert_eq!(issue.labels, vec!["bug"]);
+        assert!(issue.closed);
+
+        assert!(parse_issue(&serde_json::json!({"body": "no title"})).is_none());
+    }
+}
@@ -7,19 +7,24 @@ use url::Url;
 
 use darkfi::{
     rpc::client::RpcClient,
-    util::cli::{get_log_config, get_log_level},
+    util::cli::{confirm, get_log_config, get_log_level, print_completions, Shell},
     Result,
 };
 
 mod filter;
+mod import;
 mod primitives;
 mod rpc;
+#[path = "../../taud/src/task_state.rs"]
+mod task_state;
 mod util;
 mod view;
 
-use primitives::{task_from_cli, State, TaskEvent};
-use util::{desc_in_editor, due_as_timestamp};
-use view::{comments_as_string, print_task_info, print_task_list};
+use import::{import_issues, issues_from_export, ImportMap};
+use primitives::{task_from_cli, TaskEvent};
+use task_state::{TaskState, BUILTIN_STATES};
+use util::{desc_in_editor, due_as_timestamp, patches_from_input};
+use view::{comments_as_string, print_task_info, print_task_json, print_task_list};
 
 #[derive(Parser)]
 #[clap(name = "tau", version)]
@@ -32,6 +37,14 @@ struct Args {
     /// taud JSON-RPC endpoint
     endpoint: Url,
 
+    #[clap(long)]
+    /// Token to authenticate to taud with
+    rpc_token: Option<String>,
+
+    #[clap(short, long, global = true)]
+    /// Don't ask for confirmation, e.g. before stopping a task
+    yes: bool,
+
     /// Search filters (zero or more)
     filters: Vec<String>,
 
@@ -70,6 +83,53 @@ enum TauSubcommand {
 
     /// Get task info by ID
     Info { task_id: u64 },
+
+    /// Print a task as JSON, or the JSON schema of tasks
+    Show {
+        #[clap(required_unless_present = "json-schema")]
+        /// Task ID
+        task_id: Option<u64>,
+        #[clap(long)]
+        /// Print the JSON schema tasks follow instead
+        json_schema: bool,
+    },
+
+    /// List tasks
+    List {
+        #[clap(short, long, default_value = "table")]
+        /// Output format (table or json)
+        output: String,
+        #[clap(short, long)]
+        /// Sort order (rank, due, created or id), defaults to taud's setting
+        sort: Option<String>,
+        /// Search filters (zero or more)
+        filters: Vec<String>,
+    },
+
+    /// Apply a JSON array of task patches (ex: from `tau list --output json`)
+    Apply {
+        /// File to read the patches from, or - for stdin
+        path: String,
+    },
+
+    /// Import issues from a GitHub or GitLab JSON export
+    ImportIssues {
+        /// JSON file with an array of issues
+        path: String,
+        #[clap(short, long)]
+        /// TOML file mapping users to nicknames and labels to projects
+        map: Option<String>,
+        #[clap(long)]
+        /// Also import closed issues, as stopped tasks
+        closed: bool,
+    },
+
+    /// Print a completion script for bash, zsh, fish, elvish or powershell
+    Completions {
+        #[clap(parse(try_from_str))]
+        /// Shell to complete in
+        shell: Shell,
+    },
 }
 
 pub struct Tau {
@@ -80,11 +140,18 @@ pub struct Tau {
 async fn main() -> Result<()> {
     let args = Args::parse();
 
+    // Needs no taud
+    if let Some(TauSubcommand::Completions { shell }) = args.command {
+        print_completions::<Args>(shell);
+        return Ok(())
+    }
+
     let log_level = get_log_level(args.verbose.into());
     let log_config = get_log_config();
     TermLogger::init(log_level, log_config, TerminalMode::Mixed, ColorChoice::Auto)?;
 
-    let rpc_client = RpcClient::new(args.endpoint).await?;
+    let mut rpc_client = RpcClient::new(args.endpoint).await?;
+    rpc_client.set_auth_token(args.rpc_token);
     let tau = Tau { rpc_client };
 
     // Parse subcommands
@@ -101,7 +168,8 @@ async fn main() -> Result<()> {
                     task.desc = desc_in_editor()?;
                 };
 
-                return tau.add(task).await
+                tau.add(task).await?;
+                Ok(())
             }
 
             TauSubcommand::Update { task_id, values } => {
@@ -111,17 +179,26 @@ async fn main() -> Result<()> {
 
             TauSubcommand::State { task_id, state } => match state {
                 Some(state) => {
-                    let state = state.trim().to_lowercase();
-                    if let Ok(st) = State::from_str(&state) {
+                    if let Ok(st) = TaskState::from_str(&state) {
+                        // Stopped tasks are dropped from the active tasks
+                        let question = format!("Stop task {}?", task_id);
+                        if st.is_closed() && !confirm(&question, args.yes)? {
+                            println!("Task {} left as it was", task_id);
+                            return tau.close_connection().await
+                        }
                         tau.set_state(task_id, &st).await
                     } else {
-                        error!("State can only be one of the following: open start stop pause",);
+                        error!(
+                            "State can be one of the following: {}, or a custom state \
+                             configured in taud",
+                            BUILTIN_STATES.join(" ")
+                        );
                         Ok(())
                     }
                 }
                 None => {
                     let task = tau.get_task_by_id(task_id).await?;
-                    let state = &task.events.last().unwrap_or(&TaskEvent::default()).action.clone();
+                    let state = &task.events.last().unwrap_or(&TaskEvent::default()).action;
                     println!("Task {}: {}", task_id, state);
                     Ok(())
                 }
@@ -142,13 +219,54 @@ async fn main() -> Result<()> {
                 let task = tau.get_task_by_id(task_id).await?;
                 print_task_info(task)
             }
+
+            TauSubcommand::Show { task_id, json_schema } => {
+                let json = match task_id {
+                    Some(task_id) if !json_schema => tau.get_task_json(task_id).await?,
+                    _ => tau.get_schema().await?,
+                };
+                println!("{}", serde_json::to_string_pretty(&json)?);
+                Ok(())
+            }
+
+            TauSubcommand::List { output, sort, filters } => {
+                let tasks = tau.get_tasks(sort.as_deref()).await?;
+                match output.as_str() {
+                    "table" => print_task_list(tasks, filters),
+                    "json" => print_task_json(tasks, filters),
+                    _ => {
+                        error!("Output format can only be one of the following: table json");
+                        exit(1);
+                    }
+                }
+            }
+
+            TauSubcommand::Apply { path } => {
+                let patches = match patches_from_input(&path) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("Failed reading task patches: {}", e);
+                        exit(1);
+                    }
+                };
+
+                tau.update_batch(patches).await
+            }
+
+            TauSubcommand::ImportIssues { path, map, closed } => {
+                let map = match map {
+                    Some(map) => ImportMap::load(&map)?,
+                    None => ImportMap::default(),
+                };
+
+                let issues = issues_from_export(&path)?;
+                import_issues(&tau, issues, &map, closed).await
+            }
+
+            TauSubcommand::Completions { .. } => unreachable!(),
         },
         None => {
-            let task_ids = tau.get_ids().await?;
-            let mut tasks = vec![];
-            for id in task_ids {
-                tasks.push(tau.get_task_by_id(id).await?);
-            }
+            let tasks = tau.get_tasks(None).await?;
             print_task_list(tasks, args.filters)?;
             Ok(())
         }
@@ -1,31 +1,54 @@
 use log::debug;
-use serde_json::json;
+use serde_json::{json, Value};
 
-use darkfi::{rpc::jsonrpc::JsonRequest, Result};
+use darkfi::{rpc::jsonrpc::JsonRequest, Error, Result};
 
 use crate::{
-    primitives::{BaseTask, State, TaskInfo},
+    primitives::{BaseTask, TaskInfo},
+    task_state::TaskState,
     Tau,
 };
 
+/// Version of taud's JSON tau understands
+const SCHEMA_VERSION: u64 = 2;
+
+/// Request pinned to the schema version tau understands
+fn versioned_request(method: &str, args: Value) -> JsonRequest {
+    JsonRequest::new(method, json!({"schema_version": SCHEMA_VERSION, "args": args}))
+}
+
+/// Unwrap the result of a versioned request
+fn versioned_result(rep: Value) -> Result<Value> {
+    match rep.get("schema_version").and_then(|v| v.as_u64()) {
+        Some(SCHEMA_VERSION) => Ok(rep["result"].clone()),
+        Some(v) => Err(Error::JsonRpcError(format!("unexpected schema version {}", v))),
+        None => Err(Error::JsonRpcError("taud is too old, update it".into())),
+    }
+}
+
 impl Tau {
     pub async fn close_connection(&self) -> Result<()> {
         self.rpc_client.close().await
     }
 
-    /// Add a new task.
-    pub async fn add(&self, task: BaseTask) -> Result<()> {
-        let req = JsonRequest::new("add", json!([task]));
-        let rep = self.rpc_client.request(req).await?;
+    /// Add a new task and return its ID.
+    pub async fn add(&self, task: BaseTask) -> Result<u64> {
+        let req = versioned_request("add", json!([task]));
+        let rep = versioned_result(self.rpc_client.request(req).await?)?;
 
         debug!("Got reply: {:?}", rep);
-        Ok(())
+        Ok(serde_json::from_value(rep)?)
     }
 
-    /// Get all task ids.
-    pub async fn get_ids(&self) -> Result<Vec<u64>> {
-        let req = JsonRequest::new("get_ids", json!([]));
-        let rep = self.rpc_client.request(req).await?;
+    /// Get all task ids, in the given order or the one taud is configured
+    /// with.
+    pub async fn get_ids(&self, sort: Option<&str>) -> Result<Vec<u64>> {
+        let params = match sort {
+            Some(sort) => json!([sort]),
+            None => json!([]),
+        };
+        let req = versioned_request("get_ids", params);
+        let rep = versioned_result(self.rpc_client.request(req).await?)?;
 
         let mut ret = vec![];
         for i in rep.as_array().unwrap() {
@@ -37,17 +60,26 @@ impl Tau {
 
     /// Update existing task given it's ID and some params.
     pub async fn update(&self, id: u64, task: BaseTask) -> Result<()> {
-        let req = JsonRequest::new("update", json!([id, task]));
-        let rep = self.rpc_client.request(req).await?;
+        let req = versioned_request("update", json!([id, task]));
+        let rep = versioned_result(self.rpc_client.request(req).await?)?;
+
+        debug!("Got reply: {:?}", rep);
+        Ok(())
+    }
+
+    /// Apply a list of task patches in a single request.
+    pub async fn update_batch(&self, patches: Vec<Value>) -> Result<()> {
+        let req = versioned_request("update_batch", json!(patches));
+        let rep = versioned_result(self.rpc_client.request(req).await?)?;
 
         debug!("Got reply: {:?}", rep);
         Ok(())
     }
 
     /// Set the state for a task.
-    pub async fn set_state(&self, id: u64, state: &State) -> Result<()> {
-        let req = JsonRequest::new("set_state", json!([id, state.to_string()]));
-        let rep = self.rpc_client.request(req).await?;
+    pub async fn set_state(&self, id: u64, state: &TaskState) -> Result<()> {
+        let req = versioned_request("set_state", json!([id, state.to_string()]));
+        let rep = versioned_result(self.rpc_client.request(req).await?)?;
 
         debug!("Got reply: {:?}", rep);
         Ok(())
@@ -55,18 +87,42 @@ impl Tau {
 
     /// Set a comment for a task.
     pub async fn set_comment(&self, id: u64, content: &str) -> Result<()> {
-        let req = JsonRequest::new("set_comment", json!([id, content]));
-        let rep = self.rpc_client.request(req).await?;
+        let req = versioned_request("set_comment", json!([id, content]));
+        let rep = versioned_result(self.rpc_client.request(req).await?)?;
 
         debug!("Got reply: {:?}", rep);
         Ok(())
     }
 
+    /// Get all tasks, in the given order or the one taud is configured
+    /// with.
+    pub async fn get_tasks(&self, sort: Option<&str>) -> Result<Vec<TaskInfo>> {
+        let task_ids = self.get_ids(sort).await?;
+        let reqs =
+            task_ids.iter().map(|id| versioned_request("get_task_by_id", json!([id]))).collect();
+
+        let mut tasks = vec![];
+        for rep in self.rpc_client.send_batch(reqs).await? {
+            tasks.push(serde_json::from_value(versioned_result(rep?)?)?);
+        }
+
+        Ok(tasks)
+    }
+
     /// Get task data by its ID.
     pub async fn get_task_by_id(&self, id: u64) -> Result<TaskInfo> {
-        let req = JsonRequest::new("get_task_by_id", json!([id]));
-        let rep = self.rpc_client.request(req).await?;
+        Ok(serde_json::from_value(self.get_task_json(id).await?)?)
+    }
 
-        Ok(serde_json::from_value(rep)?)
+    /// Get a task by its ID, as the JSON taud sent.
+    pub async fn get_task_json(&self, id: u64) -> Result<Value> {
+        let req = versioned_request("get_task_by_id", json!([id]));
+        versioned_result(self.rpc_client.request(req).await?)
+    }
+
+    /// Get the JSON schema of tasks.
+    pub async fn get_schema(&self) -> Result<Value> {
+        let req = versioned_request("get_schema", json!([]));
+        versioned_result(self.rpc_client.request(req).await?)
     }
 }
@@ -1,14 +1,15 @@
 use std::{
     env,
     fs::{self, File},
-    io::Write,
+    io::{self, Read, Write},
     process::Command,
 };
 
 use chrono::{Datelike, Local, NaiveDate};
 use log::error;
+use serde_json::Value;
 
-use darkfi::{util::Timestamp, Result};
+use darkfi::{util::Timestamp, Error, Result};
 
 /// Parse due date (e.g. "1503" for 15 March) as i64 timestamp.
 pub fn due_as_timestamp(due: &str) -> Option<i64> {
@@ -69,3 +70,22 @@ pub fn desc_in_editor() -> Result<Option<String>> {
     }
     Ok(Some(lines.join("\n")))
 }
+
+/// Read a JSON array of task patches from the given file, or from stdin
+/// if the path is "-". Every patch must carry the id of the task it edits.
+pub fn patches_from_input(path: &str) -> Result<Vec<Value>> {
+    let content = if path == "-" {
+        let mut buf = String::new();
+        io::stdin().read_to_string(&mut buf)?;
+        buf
+    } else {
+        fs::read_to_string(path)?
+    };
+
+    let patches: Vec<Value> = serde_json::from_str(&content)?;
+    if patches.iter().any(|p| !p.get("id").map_or(false, Value::is_u64)) {
+        return Err(Error::ParseFailed("every task patch must be an object with an id"))
+    }
+
+    Ok(patches)
+}
@@ -14,6 +14,7 @@ use darkfi::{
 use crate::{
     filter::apply_filter,
     primitives::{Comment, TaskInfo},
+    task_state::TaskState,
     TaskEvent,
 };
 
@@ -33,28 +34,17 @@ pub fn print_task_list(tasks: Vec<TaskInfo>, filters: Vec<String>) -> Result<()>
         apply_filter(&mut tasks, &filter);
     }
 
-    tasks.sort_by(|a, b| b.rank.partial_cmp(&a.rank).unwrap());
-
-    let mut min_rank = 0.0;
-    let mut max_rank = 0.0;
-
-    if let Some(first) = tasks.first() {
-        max_rank = first.rank;
-    }
-
-    if let Some(last) = tasks.last() {
-        min_rank = last.rank;
-    }
+    // Tasks come sorted from taud, only look for the extreme ranks.
+    let max_rank = tasks.iter().map(|t| t.rank).fold(f32::NEG_INFINITY, f32::max);
+    let min_rank = tasks.iter().map(|t| t.rank).fold(f32::INFINITY, f32::min);
 
     for task in tasks {
         let state = task.events.last().unwrap_or(&TaskEvent::default()).action.clone();
 
-        let (max_style, min_style, mid_style, gen_style) = if state == "start" {
-            ("bFg", "Fc", "Fg", "Fg")
-        } else if state == "pause" {
-            ("iFYBd", "iFYBd", "iFYBd", "iFYBd")
-        } else {
-            ("", "", "", "")
+        let (max_style, min_style, mid_style, gen_style) = match state {
+            TaskState::Start => ("bFg", "Fc", "Fg", "Fg"),
+            TaskState::Pause => ("iFYBd", "iFYBd", "iFYBd", "iFYBd"),
+            _ => ("", "", "", ""),
         };
 
         let rank = task.rank.to_string();
@@ -80,8 +70,19 @@ pub fn print_task_list(tasks: Vec<TaskInfo>, filters: Vec<String>) -> Result<()>
     Ok(())
 }
 
+pub fn print_task_json(tasks: Vec<TaskInfo>, filters: Vec<String>) -> Result<()> {
+    let mut tasks = tasks;
+
+    for filter in filters {
+        apply_filter(&mut tasks, &filter);
+    }
+
+    println!("{}", serde_json::to_string_pretty(&tasks)?);
+    Ok(())
+}
+
 pub fn print_task_info(taskinfo: TaskInfo) -> Result<()> {
-    let current_state = &taskinfo.events.last().unwrap_or(&TaskEvent::default()).action.clone();
+    let current_state = taskinfo.events.last().unwrap_or(&TaskEvent::default()).action.to_string();
     let due = timestamp_to_date(taskinfo.due.unwrap_or(0), DateFormat::Date);
     let created_at = timestamp_to_date(taskinfo.created_at, DateFormat::DateTime);
 
@@ -8,6 +8,8 @@ pub enum TaudError {
     InvalidDueTime,
     #[error("Invalid Id")]
     InvalidId,
+    #[error("Invalid task state: `{0}`")]
+    InvalidState(String),
     #[error("Invalid Data/Params: `{0}` ")]
     InvalidData(String),
     #[error("InternalError")]
@@ -16,6 +18,10 @@ pub enum TaudError {
     SerdeJsonError(String),
     #[error("Encryption error: `{0}`")]
     EncryptionError(String),
+    #[error("Hook error: `{0}`")]
+    HookError(String),
+    #[error("Unsupported schema version: {0}")]
+    UnsupportedSchema(u64),
 }
 
 pub type TaudResult<T> = std::result::Result<T, TaudError>;
@@ -39,13 +45,25 @@ pub fn to_json_result(res: TaudResult<Value>, id: Value) -> JsonResult {
             TaudError::InvalidId => {
                 JsonError::new(ErrorCode::InvalidParams, Some("invalid task id".into()), id).into()
             }
+            TaudError::InvalidState(s) => JsonError::new(
+                ErrorCode::InvalidParams,
+                Some(format!("invalid task state: {}", s)),
+                id,
+            )
+            .into(),
             TaudError::InvalidData(e) | TaudError::SerdeJsonError(e) => {
                 JsonError::new(ErrorCode::InvalidParams, Some(e), id).into()
             }
+            TaudError::UnsupportedSchema(v) => JsonError::new(
+                ErrorCode::InvalidParams,
+                Some(format!("unsupported schema version: {}", v)),
+                id,
+            )
+            .into(),
             TaudError::InvalidDueTime => {
                 JsonError::new(ErrorCode::InvalidParams, Some("invalid due time".into()), id).into()
             }
-            TaudError::EncryptionError(e) => {
+            TaudError::EncryptionError(e) | TaudError::HookError(e) => {
                 JsonError::new(ErrorCode::InternalError, Some(e), id).into()
             }
             TaudError::Darkfi(e) => {
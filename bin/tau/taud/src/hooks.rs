@@ -0,0 +1,280 @@
+use std::path::Path;
+
+use async_std::net::TcpStream;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use log::{debug, error, info};
+use serde::Deserialize;
+use serde_json::Value;
+use url::Url;
+
+use crate::{
+    error::{TaudError, TaudResult},
+    task_info::TaskInfo,
+};
+
+/// Task events hooks can be attached to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    TaskCreated,
+    StateChanged,
+    CommentAdded,
+}
+
+impl HookEvent {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::TaskCreated => "task_created",
+            Self::StateChanged => "state_changed",
+            Self::CommentAdded => "comment_added",
+        }
+    }
+}
+
+/// A `[[hook]]` entry from the config file
+#[derive(Clone, Debug, Deserialize)]
+pub struct Hook {
+    /// Event this hook runs on
+    pub event: HookEvent,
+    /// Only run `state_changed` hooks when the task enters this state
+    pub state: Option<String>,
+    /// Shell command to run, with the task available in `TAU_*` env vars
+    pub command: Option<String>,
+    /// URL to POST `payload` to
+    pub url: Option<Url>,
+    /// JSON payload template, where `{title}`, `{id}`, ... get replaced
+    pub payload: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct HooksConfig {
+    #[serde(default)]
+    hook: Vec<Hook>,
+}
+
+/// Parse the `[[hook]]` entries of the configuration file.
+pub fn parse_hooks(config_file: &Path) -> TaudResult<Vec<Hook>> {
+    let toml_contents = std::fs::read_to_string(config_file).map_err(darkfi::Error::from)?;
+    let config: HooksConfig =
+        toml::from_str(&toml_contents).map_err(|e| TaudError::HookError(e.to_string()))?;
+
+    for hook in &config.hook {
+        if hook.command.is_none() && hook.url.is_none() {
+            return Err(TaudError::HookError(format!(
+                "{} hook has neither a command nor a url",
+                hook.event.as_str()
+            )))
+        }
+        info!(target: "tau", "Found {} hook", hook.event.as_str());
+    }
+
+    Ok(config.hook)
+}
+
+/// Values describing one event, handed to the hooks.
+struct EventVars {
+    event: HookEvent,
+    vars: Vec<(&'static str, String)>,
+}
+
+impl EventVars {
+    fn new(event: HookEvent, task: &Value, state: &str) -> Self {
+        let field = |name: &str| match &task[name] {
+            Value::String(s) => s.clone(),
+            Value::Null => String::new(),
+            v => v.to_string(),
+        };
+
+        let vars = vec![
+            ("event", event.as_str().to_string()),
+            ("id", field("id")),
+            ("ref_id", field("ref_id")),
+            ("title", field("title")),
+            ("owner", field("owner")),
+            ("state", state.to_string()),
+            ("author", String::new()),
+            ("comment", String::new()),
+        ];
+
+        Self { event, vars }
+    }
+
+    fn set(&mut self, name: &str, value: String) {
+        if let Some(var) = self.vars.iter_mut().find(|(n, _)| *n == name) {
+            var.1 = value;
+        }
+    }
+
+    /// Replace every `{name}` in the template with the JSON-escaped value.
+    fn render(&self, template: &str) -> String {
+        let mut ret = template.to_string();
+        for (name, value) in &self.vars {
+            let escaped = Value::String(value.clone()).to_string();
+            ret = ret.replace(&format!("{{{}}}", name), &escaped[1..escaped.len() - 1]);
+        }
+        ret
+    }
+}
+
+/// Save the task and run the hooks for whatever changed since the copy
+/// stored on disk. Hooks run in the background and only log failures.
+pub fn save_with_hooks(task: &TaskInfo, dataset_path: &Path, hooks: &[Hook]) -> TaudResult<()> {
+    let old = TaskInfo::load(&task.ref_id, dataset_path).ok();
+    task.save(dataset_path)?;
+
+    if hooks.is_empty() {
+        return Ok(())
+    }
+
+    let state = task.get_state().to_string();
+    let new_value = serde_json::to_value(task)?;
+    let mut events = vec![];
+
+    match &old {
+        None => events.push(EventVars::new(HookEvent::TaskCreated, &new_value, &state)),
+        Some(old) => {
+            if old.get_state().to_string() != state {
+                events.push(EventVars::new(HookEvent::StateChanged, &new_value, &state));
+            }
+
+            let old_value = serde_json::to_value(old)?;
+            let seen = old_value["comments"].as_array().map_or(0, |c| c.len());
+            if let Some(comments) = new_value["comments"].as_array() {
+                for comment in comments.iter().skip(seen) {
+                    let mut vars = EventVars::new(HookEvent::CommentAdded, &new_value, &state);
+                    vars.set("author", comment["author"].as_str().unwrap_or_default().into());
+                    vars.set("comment", comment["content"].as_str().unwrap_or_default().into());
+                    events.push(vars);
+                }
+            }
+        }
+    }
+
+    for vars in events {
+        let hooks: Vec<Hook> = hooks
+            .iter()
+            .filter(|h| h.event == vars.event)
+            .filter(|h| h.state.as_ref().map_or(true, |s| *s == state))
+            .cloned()
+            .collect();
+
+        if hooks.is_empty() {
+            continue
+        }
+
+        async_std::task::spawn(async move {
+            for hook in hooks {
+                if let Err(e) = run_hook(&hook, &vars).await {
+                    error!(target: "tau", "Failed running {} hook: {}", vars.event.as_str(), e);
+                }
+            }
+        });
+    }
+
+    Ok(())
+}
+
+async fn run_hook(hook: &Hook, vars: &EventVars) -> TaudResult<()> {
+    if let Some(command) = &hook.command {
+        debug!(target: "tau", "Running hook command: {}", command);
+        let envs = vars.vars.iter().map(|(n, v)| (format!("TAU_{}", n.to_uppercase()), v));
+        let status = smol::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .envs(envs)
+            .status()
+            .await
+            .map_err(darkfi::Error::from)?;
+
+        if !status.success() {
+            return Err(TaudError::HookError(format!("`{}` exited with {}", command, status)))
+        }
+    }
+
+    if let Some(url) = &hook.url {
+        let payload = match &hook.payload {
+            Some(template) => vars.render(template),
+            None => {
+                let map: serde_json::Map<String, Value> = vars
+                    .vars
+                    .iter()
+                    .map(|(n, v)| (n.to_string(), Value::String(v.clone())))
+                    .collect();
+                Value::Object(map).to_string()
+            }
+        };
+
+        debug!(target: "tau", "Posting hook payload to {}", url);
+        http_post(url, &payload).await?;
+    }
+
+    Ok(())
+}
+
+/// Minimal HTTP/1.1 POST of a JSON body, over TLS for https URLs.
+async fn http_post(url: &Url, body: &str) -> TaudResult<()> {
+    let hook_err = |e: &dyn std::fmt::Display| TaudError::HookError(format!("{}: {}", url, e));
+
+    let host = url.host_str().ok_or_else(|| hook_err(&"missing host"))?;
+    let port = url.port_or_known_default().ok_or_else(|| hook_err(&"missing port"))?;
+
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+        path = format!("{}?{}", path, query);
+    }
+
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        body.len(),
+        body
+    );
+
+    let stream = TcpStream::connect((host, port)).await.map_err(|e| hook_err(&e))?;
+    let response = match url.scheme() {
+        "http" => send_request(stream, &request).await,
+        "https" => {
+            let stream = async_native_tls::connect(host, stream).await.map_err(|e| hook_err(&e))?;
+            send_request(stream, &request).await
+        }
+        scheme => return Err(hook_err(&format!("unsupported scheme {}", scheme))),
+    }
+    .map_err(|e| hook_err(&e))?;
+
+    // Status line looks like "HTTP/1.1 200 OK"
+    let status = response.lines().next().unwrap_or_default();
+    if status.split(' ').nth(1).map_or(true, |code| !code.starts_with('2')) {
+        return Err(hook_err(&status))
+    }
+
+    Ok(())
+}
+
+async fn send_request<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    request: &str,
+) -> std::io::Result<String> {
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).await?;
+    Ok(String::from_utf8_lossy(&response).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_payload() {
+        let task = serde_json::json!({"id": 3, "title": "Say \"hi\"", "owner": "dark"});
+        let mut vars = EventVars::new(HookEvent::CommentAdded, &task, "open");
+        vars.set("comment", "done".into());
+
+        let payload = vars.render(r#"{"text": "{owner} on {id} ({title}): {comment}"}"#);
+        assert_eq!(payload, r#"{"text": "dark on 3 (Say \"hi\"): done"}"#);
+        assert!(serde_json::from_str::<Value>(&payload).is_ok());
+    }
+}
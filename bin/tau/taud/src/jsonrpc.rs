@@ -7,6 +7,7 @@ use serde_json::{json, Value};
 
 use darkfi::{
     rpc::{
+        acl::RpcAcl,
         jsonrpc::{ErrorCode, JsonError, JsonRequest, JsonResult},
         server::RequestHandler,
     },
@@ -15,13 +16,20 @@ use darkfi::{
 
 use crate::{
     error::{to_json_result, TaudError, TaudResult},
+    hooks::{save_with_hooks, Hook},
     month_tasks::MonthTasks,
-    task_info::{Comment, TaskInfo},
+    schema::{check_version, task_schema, task_to_json, versioned_params, versioned_result},
+    task_info::{Comment, TaskInfo, TaskOrder},
+    task_state::TaskState,
 };
 
 pub struct JsonRpcInterface {
     dataset_path: PathBuf,
     nickname: String,
+    custom_states: Vec<TaskState>,
+    order: TaskOrder,
+    hooks: Vec<Hook>,
+    acl: RpcAcl,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -37,33 +45,46 @@ struct BaseTaskInfo {
 #[async_trait]
 impl RequestHandler for JsonRpcInterface {
     async fn handle_request(&self, req: JsonRequest) -> JsonResult {
-        if !req.params.is_array() {
-            return JsonError::new(ErrorCode::InvalidParams, None, req.id).into()
-        }
-
-        let params = req.params.as_array().unwrap();
+        let (version, params) = match versioned_params(&req.params) {
+            Ok(v) => v,
+            Err(e) => return to_json_result(Err(e), req.id),
+        };
+        let params = &params;
 
         let rep = match req.method.as_str() {
             Some("add") => self.add(params).await,
             Some("get_ids") => self.get_ids(params).await,
             Some("update") => self.update(params).await,
+            Some("update_batch") => self.update_batch(params).await,
             Some("set_state") => self.set_state(params).await,
             Some("set_comment") => self.set_comment(params).await,
-            Some("get_task_by_id") => self.get_task_by_id(params).await,
+            Some("get_task_by_id") => self.get_task_by_id(params, version).await,
+            Some("get_schema") => self.get_schema(params, version).await,
             Some(_) | None => return JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
         };
 
-        to_json_result(rep, req.id)
+        to_json_result(rep.map(|v| versioned_result(version, v)), req.id)
+    }
+
+    fn acl(&self) -> Option<&RpcAcl> {
+        Some(&self.acl)
     }
 }
 
 impl JsonRpcInterface {
-    pub fn new(dataset_path: PathBuf, nickname: String) -> Self {
-        Self { dataset_path, nickname }
+    pub fn new(
+        dataset_path: PathBuf,
+        nickname: String,
+        custom_states: Vec<TaskState>,
+        order: TaskOrder,
+        hooks: Vec<Hook>,
+        acl: RpcAcl,
+    ) -> Self {
+        Self { dataset_path, nickname, custom_states, order, hooks, acl }
     }
 
     // RPCAPI:
-    // Add new task and returns `true` upon success.
+    // Add new task and returns its id upon success.
     // --> {"jsonrpc": "2.0", "method": "add",
     //      "params":
     //          [{
@@ -76,7 +97,7 @@ impl JsonRpcInterface {
     //          }],
     //      "id": 1
     //      }
-    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": task_id, "id": 1}
     async fn add(&self, params: &[Value]) -> TaudResult<Value> {
         debug!(target: "tau", "JsonRpc::add() params {:?}", params);
 
@@ -92,17 +113,25 @@ impl JsonRpcInterface {
         new_task.set_project(&task.project);
         new_task.set_assign(&task.assign);
 
-        new_task.save(&self.dataset_path)?;
-        Ok(json!(true))
+        save_with_hooks(&new_task, &self.dataset_path, &self.hooks)?;
+        Ok(json!(new_task.get_id()))
     }
 
     // RPCAPI:
-    // List tasks
-    // --> {"jsonrpc": "2.0", "method": "get_ids", "params": [], "id": 1}
+    // List tasks, in the configured order unless one of rank, due, created
+    // or id is given.
+    // --> {"jsonrpc": "2.0", "method": "get_ids", "params": ["due"], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": [task_id, ...], "id": 1}
     async fn get_ids(&self, params: &[Value]) -> TaudResult<Value> {
         debug!(target: "tau", "JsonRpc::get_ids() params {:?}", params);
-        let tasks = MonthTasks::load_current_open_tasks(&self.dataset_path)?;
+
+        let order = match params.first() {
+            Some(order) => serde_json::from_value::<String>(order.clone())?.parse()?,
+            None => self.order,
+        };
+
+        let mut tasks = MonthTasks::load_current_open_tasks(&self.dataset_path)?;
+        tasks.sort_by(|a, b| a.compare(b, order));
         let task_ids: Vec<u32> = tasks.iter().map(|task| task.get_id()).collect();
         Ok(json!(task_ids))
     }
@@ -119,7 +148,7 @@ impl JsonRpcInterface {
         }
 
         let task = self.check_params_for_update(&params[0], &params[1])?;
-        task.save(&self.dataset_path)?;
+        save_with_hooks(&task, &self.dataset_path, &self.hooks)?;
         Ok(json!(true))
     }
 
@@ -128,24 +157,58 @@ impl JsonRpcInterface {
     // --> {"jsonrpc": "2.0", "method": "set_state", "params": [task_id, state], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
     async fn set_state(&self, params: &[Value]) -> TaudResult<Value> {
-        // Allowed states for a task
-        let states = ["stop", "start", "open", "pause"];
-
         debug!(target: "tau", "JsonRpc::set_state() params {:?}", params);
 
         if params.len() != 2 {
             return Err(TaudError::InvalidData("len of params should be 2".into()))
         }
 
-        let state: String = serde_json::from_value(params[1].clone())?;
+        let state = self.check_state(&params[1])?;
 
         let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
+        task.set_state(&state);
+        save_with_hooks(&task, &self.dataset_path, &self.hooks)?;
+
+        Ok(json!(true))
+    }
+
+    // RPCAPI:
+    // Update several tasks at once and returns `true` upon success.
+    // Every patch holds the task id along with the fields to change, as in
+    // `update`, plus an optional state. All patches are checked before any
+    // task is saved.
+    // --> {"jsonrpc": "2.0", "method": "update_batch",
+    //      "params": [{"id": task_id, "rank": 2.5}, {"id": task_id, "state": "pause"}],
+    //      "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn update_batch(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::update_batch() params {:?}", params);
 
-        if states.contains(&state.as_str()) {
-            task.set_state(&state);
+        let mut ids = Vec::with_capacity(params.len());
+        let mut tasks = Vec::with_capacity(params.len());
+
+        for patch in params {
+            let task_id = match patch.get("id") {
+                Some(id) => id,
+                None => return Err(TaudError::InvalidData("patch is missing the task id".into())),
+            };
+
+            if ids.contains(task_id) {
+                return Err(TaudError::InvalidData(format!("task {} patched twice", task_id)))
+            }
+            ids.push(task_id.clone());
+
+            let mut task = self.check_params_for_update(task_id, patch)?;
+            if let Some(state) = patch.get("state") {
+                task.set_state(&self.check_state(state)?);
+            }
+
+            tasks.push(task);
         }
 
-        task.save(&self.dataset_path)?;
+        for task in tasks {
+            save_with_hooks(&task, &self.dataset_path, &self.hooks)?;
+        }
 
         Ok(json!(true))
     }
@@ -166,16 +229,17 @@ impl JsonRpcInterface {
         let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
         task.set_comment(Comment::new(&comment_content, &self.nickname));
 
-        task.save(&self.dataset_path)?;
+        save_with_hooks(&task, &self.dataset_path, &self.hooks)?;
 
         Ok(json!(true))
     }
 
     // RPCAPI:
-    // Get a task by id.
-    // --> {"jsonrpc": "2.0", "method": "get_task_by_id", "params": [task_id], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": "task", "id": 1}
-    async fn get_task_by_id(&self, params: &[Value]) -> TaudResult<Value> {
+    // Get a task by id, in the shape of the requested schema version.
+    // --> {"jsonrpc": "2.0", "method": "get_task_by_id",
+    //      "params": {"schema_version": 2, "args": [task_id]}, "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"schema_version": 2, "result": task}, "id": 1}
+    async fn get_task_by_id(&self, params: &[Value], version: u64) -> TaudResult<Value> {
         debug!(target: "tau", "JsonRpc::get_task_by_id() params {:?}", params);
 
         if params.len() != 1 {
@@ -184,7 +248,24 @@ impl JsonRpcInterface {
 
         let task: TaskInfo = self.load_task_by_id(&params[0])?;
 
-        Ok(json!(task))
+        Ok(task_to_json(&task, version))
+    }
+
+    // RPCAPI:
+    // Get the JSON schema of a task, in the requested schema version or
+    // the one given as param.
+    // --> {"jsonrpc": "2.0", "method": "get_schema",
+    //      "params": {"schema_version": 2, "args": []}, "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"schema_version": 2, "result": schema}, "id": 1}
+    async fn get_schema(&self, params: &[Value], version: u64) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::get_schema() params {:?}", params);
+
+        let version = match params.first() {
+            Some(v) => check_version(serde_json::from_value(v.clone())?)?,
+            None => version,
+        };
+
+        Ok(task_schema(version))
     }
 
     fn load_task_by_id(&self, task_id: &Value) -> TaudResult<TaskInfo> {
@@ -196,6 +277,17 @@ impl JsonRpcInterface {
         task.ok_or(TaudError::InvalidId)
     }
 
+    fn check_state(&self, state: &Value) -> TaudResult<TaskState> {
+        let state: String = serde_json::from_value(state.clone())?;
+        let state: TaskState = state.parse().map_err(|_| TaudError::InvalidState(state))?;
+
+        if state.is_custom() && !self.custom_states.contains(&state) {
+            return Err(TaudError::InvalidState(state.to_string()))
+        }
+
+        Ok(state)
+    }
+
     fn check_params_for_update(&self, task_id: &Value, fields: &Value) -> TaudResult<TaskInfo> {
         let mut task: TaskInfo = self.load_task_by_id(task_id)?;
 
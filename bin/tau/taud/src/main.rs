@@ -1,5 +1,5 @@
 use async_std::sync::{Arc, Mutex};
-use std::{env, fs::create_dir_all, sync::mpsc, time::Duration};
+use std::{env, fs::create_dir_all, path::PathBuf, sync::mpsc, time::Duration};
 
 use async_executor::Executor;
 use crypto_box::{aead::Aead, Box, SecretKey, KEY_SIZE};
@@ -12,29 +12,38 @@ use structopt_toml::StructOptToml;
 use darkfi::{
     async_daemonize, net,
     raft::{NetMsg, ProtocolRaft, Raft},
-    rpc::server::listen_and_serve,
+    rpc::{acl::RpcAcl, server::listen_and_serve},
     util::{
+        check::CheckReport,
         cli::{get_log_config, get_log_level, spawn_config},
         expand_path,
         path::get_config_path,
-        serial::{deserialize, serialize, SerialDecodable, SerialEncodable},
+        serial::{serialize, SerialDecodable, SerialEncodable},
+        service::running_pid,
     },
     Error, Result,
 };
 
 mod error;
+mod hooks;
 mod jsonrpc;
 mod month_tasks;
+mod schema;
 mod settings;
 mod task_info;
+mod task_state;
+mod task_sync;
 mod util;
 
 use crate::{
     error::TaudResult,
+    hooks::{parse_hooks, save_with_hooks, Hook},
     jsonrpc::JsonRpcInterface,
     settings::{Args, CONFIG_FILE, CONFIG_FILE_CONTENTS},
-    task_info::TaskInfo,
-    util::{load, save},
+    task_info::{TaskInfo, TaskOrder},
+    task_state::TaskState,
+    task_sync::{decode_update, Applied, TaskSync, TaskUpdate},
+    util::{load, random_ref_id, save},
 };
 
 #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
@@ -44,7 +53,7 @@ pub struct EncryptedTask {
 }
 
 fn encrypt_task(
-    task: &TaskInfo,
+    update: &TaskUpdate,
     secret_key: &SecretKey,
     rng: &mut crypto_box::rand_core::OsRng,
 ) -> TaudResult<EncryptedTask> {
@@ -53,14 +62,14 @@ fn encrypt_task(
     let msg_box = Box::new(&public_key, secret_key);
 
     let nonce = crypto_box::generate_nonce(rng);
-    let payload = &serialize(task)[..];
+    let payload = &serialize(update)[..];
     let payload = msg_box.encrypt(&nonce, payload)?;
 
     let nonce = nonce.to_vec();
     Ok(EncryptedTask { nonce, payload })
 }
 
-fn decrypt_task(encrypt_task: &EncryptedTask, secret_key: &SecretKey) -> TaudResult<TaskInfo> {
+fn decrypt_task(encrypt_task: &EncryptedTask, secret_key: &SecretKey) -> TaudResult<TaskUpdate> {
     debug!("start decrypting task");
     let public_key = secret_key.public_key();
     let msg_box = Box::new(&public_key, secret_key);
@@ -68,9 +77,9 @@ fn decrypt_task(encrypt_task: &EncryptedTask, secret_key: &SecretKey) -> TaudRes
     let nonce = encrypt_task.nonce.as_slice();
     let decrypted_task = msg_box.decrypt(nonce.into(), &encrypt_task.payload[..])?;
 
-    let task = deserialize(&decrypted_task)?;
+    let update = decode_update(&decrypted_task)?;
 
-    Ok(task)
+    Ok(update)
 }
 
 fn load_task_path_from_osstr(task_path: std::path::PathBuf) -> Option<String> {
@@ -93,30 +102,42 @@ async fn start_sync_loop(
     datastore_path: std::path::PathBuf,
     secret_key: SecretKey,
     mut rng: crypto_box::rand_core::OsRng,
+    hooks: Vec<Hook>,
+    mut sync: TaskSync,
 ) -> TaudResult<()> {
     loop {
         select! {
             task = broadcast_rcv.recv().fuse() => {
                 let tk = task.map_err(Error::from)?;
                 info!(target: "tau", "Save the received task {:?}", tk);
-                let encrypted_task = encrypt_task(&tk, &secret_key,&mut rng)?;
+                let update = sync.outgoing(tk);
+                let encrypted_task = encrypt_task(&update, &secret_key, &mut rng)?;
                 raft_msgs_sender.send(encrypted_task).await.map_err(Error::from)?;
             }
             task = commits_recv.recv().fuse() => {
                 let recv = task.map_err(Error::from)?;
-                let task = decrypt_task(&recv, &secret_key);
+                let update = decrypt_task(&recv, &secret_key);
 
-                if let Err(e) = task {
+                if let Err(e) = update {
                     warn!("unable to decrypt the task: {}", e);
                     continue
                 }
 
-                let task = task.unwrap();
+                let task = match sync.incoming(update.unwrap()) {
+                    Applied::Save(task) => task,
+                    Applied::Resend(update) => {
+                        let encrypted_task = encrypt_task(&update, &secret_key, &mut rng)?;
+                        raft_msgs_sender.send(encrypted_task).await.map_err(Error::from)?;
+                        continue
+                    }
+                    Applied::Ignore => continue,
+                };
+
                 if !commits_received.lock().await.contains(&task.ref_id) {
                     commits_received.lock().await.push(task.ref_id.clone());
                 }
                 info!(target: "tau", "Receive update from the commits {:?}", task);
-                task.save(&datastore_path)?;
+                save_with_hooks(&task, &datastore_path, &hooks)?;
             }
         }
     }
@@ -180,8 +201,72 @@ async fn watch_files(
     Ok(())
 }
 
-async_daemonize!(realmain);
+/// Locked while an instance runs on the datastore
+fn pid_file(settings: &Args) -> Result<PathBuf> {
+    Ok(expand_path(&settings.datastore)?.join("taud.pid"))
+}
+
+/// Check what taud needs to start, without starting it or writing to the
+/// datastore, and print the report.
+async fn self_check(settings: &Args) -> Result<()> {
+    let mut report = CheckReport::new();
+    let running = running_pid(&pid_file(settings)?)?.is_some();
+
+    match settings.nickname.clone().or_else(|| env::var("USER").ok()) {
+        Some(v) => report.add("nickname", Ok(v)),
+        None => report.fail("nickname", "Provide a nickname in config file".into()),
+    }
+
+    for name in &settings.custom_states {
+        match name.parse::<TaskState>() {
+            Ok(state) if state.is_custom() => report.add("custom_states", Ok(name.clone())),
+            _ => report.fail("custom_states", format!("Invalid custom task state: {}", name)),
+        }
+    }
+
+    match settings.sort.parse::<TaskOrder>() {
+        Ok(_) => report.add("sort", Ok(settings.sort.clone())),
+        Err(e) => report.fail("sort", e.to_string()),
+    }
+
+    let cfg_path = get_config_path(settings.config.clone(), CONFIG_FILE)?;
+    match parse_hooks(&cfg_path) {
+        Ok(v) => report.add("hooks", Ok(format!("{} hooks", v.len()))),
+        Err(e) => report.fail("hooks", format!("Invalid hooks in config file: {}", e)),
+    }
+
+    // A missing datastore is created on start, but the key has to be
+    // generated with --key-gen first
+    let datastore_path = expand_path(&settings.datastore)?;
+    if datastore_path.exists() {
+        report.check_path("datastore", &datastore_path);
+    } else {
+        report.add("datastore", Ok(format!("Not created yet: {}", datastore_path.display())));
+    }
+
+    if settings.key_gen {
+        report.skip("secret_key", "A new key is generated on start".into());
+    } else {
+        let secret_key = load::<String>(&datastore_path.join("secret_key")).and_then(|key| {
+            let sk_bytes: [u8; KEY_SIZE] = hex::decode(key)?.as_slice().try_into()?;
+            SecretKey::try_from(sk_bytes)?;
+            Ok("Secret key is valid".to_string())
+        });
+        report.add("secret_key", secret_key);
+    }
+
+    report.check_listen("rpc_listen", &settings.rpc_listen, running);
+    report.check_net(&settings.net, running, settings.check_upstreams);
+
+    report.finish()
+}
+
+async_daemonize!(realmain, pid_file);
 async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
+    if settings.check {
+        return self_check(&settings).await
+    }
+
     let datastore_path = expand_path(&settings.datastore)?;
 
     let nickname =
@@ -192,6 +277,34 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
         return Ok(())
     }
 
+    let mut custom_states = vec![];
+    for name in &settings.custom_states {
+        match name.parse::<TaskState>() {
+            Ok(state) if state.is_custom() => custom_states.push(state),
+            _ => {
+                error!("Invalid custom task state: {}", name);
+                return Ok(())
+            }
+        }
+    }
+
+    let order = match settings.sort.parse::<TaskOrder>() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Invalid sort setting: {}", e);
+            return Ok(())
+        }
+    };
+
+    let cfg_path = get_config_path(settings.config.clone(), CONFIG_FILE)?;
+    let hooks = match parse_hooks(&cfg_path) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Invalid hooks in config file: {}", e);
+            return Ok(())
+        }
+    };
+
     // mkdir datastore_path if not exists
     create_dir_all(datastore_path.join("month"))?;
     create_dir_all(datastore_path.join("task"))?;
@@ -220,12 +333,29 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
         SecretKey::try_from(sk_bytes)?
     };
 
+    // Tells apart the updates of this node in task versions
+    let node_id = match load::<String>(&datastore_path.join("node_id")) {
+        Ok(v) => v,
+        Err(_) => {
+            let node_id = random_ref_id();
+            save::<String>(&datastore_path.join("node_id"), &node_id)?;
+            node_id
+        }
+    };
+
     let (broadcast_snd, broadcast_rcv) = async_channel::unbounded::<TaskInfo>();
 
     //
     // RPC
     //
-    let rpc_interface = Arc::new(JsonRpcInterface::new(datastore_path.clone(), nickname.unwrap()));
+    let rpc_interface = Arc::new(JsonRpcInterface::new(
+        datastore_path.clone(),
+        nickname.unwrap(),
+        custom_states,
+        order,
+        hooks.clone(),
+        RpcAcl::new(settings.rpc_auth_token.clone(), settings.rpc_open_methods.clone()),
+    ));
     executor.spawn(listen_and_serve(settings.rpc_listen.clone(), rpc_interface)).detach();
 
     //
@@ -252,6 +382,8 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
             datastore_path.clone(),
             secret_key,
             rng,
+            hooks.clone(),
+            TaskSync::new(node_id, &datastore_path),
         ))
         .detach();
 
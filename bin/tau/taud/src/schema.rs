@@ -0,0 +1,188 @@
+//! Versions of the JSON taud answers RPC requests with, so scripts keep
+//! working across taud upgrades.
+//!
+//! Requests pin a version by passing their params as an object,
+//! `{"schema_version": 2, "args": [...]}`, and get the result back as
+//! `{"schema_version": 2, "result": ...}`. Requests with plain array params
+//! predate versioning and get schema 1, the unwrapped results they always
+//! got.
+use serde_json::{json, Map, Value};
+
+use crate::{
+    error::{TaudError, TaudResult},
+    task_info::TaskInfo,
+};
+
+/// Schema taud answers in unless asked for an older one
+pub const SCHEMA_VERSION: u64 = 2;
+
+/// Schema of requests that don't ask for one
+pub const LEGACY_SCHEMA_VERSION: u64 = 1;
+
+/// Schema version and params of a request
+pub fn versioned_params(params: &Value) -> TaudResult<(u64, Vec<Value>)> {
+    if let Some(params) = params.as_array() {
+        return Ok((LEGACY_SCHEMA_VERSION, params.clone()))
+    }
+
+    let params = match params.as_object() {
+        Some(v) => v,
+        None => return Err(TaudError::InvalidData("params should be an array or object".into())),
+    };
+
+    let version = match params.get("schema_version").and_then(|v| v.as_u64()) {
+        Some(v) => check_version(v)?,
+        None => return Err(TaudError::InvalidData("schema_version is missing".into())),
+    };
+
+    let args = match params.get("args") {
+        Some(args) => serde_json::from_value(args.clone())?,
+        None => vec![],
+    };
+
+    Ok((version, args))
+}
+
+/// Wrap a result in the shape of the given schema version
+pub fn versioned_result(version: u64, result: Value) -> Value {
+    match version {
+        LEGACY_SCHEMA_VERSION => result,
+        _ => json!({"schema_version": version, "result": result}),
+    }
+}
+
+pub fn check_version(version: u64) -> TaudResult<u64> {
+    if !(LEGACY_SCHEMA_VERSION..=SCHEMA_VERSION).contains(&version) {
+        return Err(TaudError::UnsupportedSchema(version))
+    }
+
+    Ok(version)
+}
+
+/// A task as JSON, in the given schema version.
+///
+/// Schema 2 adds the current `state` and the task's `version` vector.
+pub fn task_to_json(task: &TaskInfo, version: u64) -> Value {
+    let mut value = json!(task);
+    let fields = value.as_object_mut().unwrap();
+
+    match version {
+        LEGACY_SCHEMA_VERSION => {
+            fields.remove("version");
+        }
+        _ => {
+            fields.insert("state".into(), json!(task.get_state().to_string()));
+        }
+    }
+
+    value
+}
+
+/// JSON schema of a task in the given schema version
+pub fn task_schema(version: u64) -> Value {
+    let timestamp = json!({"type": "integer", "description": "Unix timestamp, in seconds"});
+    let strings = json!({"type": "array", "items": {"type": "string"}});
+
+    let mut properties = Map::new();
+    properties.insert("ref_id".into(), json!({"type": "string"}));
+    properties.insert("id".into(), json!({"type": "integer", "minimum": 0}));
+    properties.insert("title".into(), json!({"type": "string"}));
+    properties.insert("desc".into(), json!({"type": "string"}));
+    properties.insert("owner".into(), json!({"type": "string"}));
+    properties.insert("assign".into(), strings.clone());
+    properties.insert("project".into(), strings);
+    properties.insert("due".into(), json!({"oneOf": [timestamp.clone(), {"type": "null"}]}));
+    properties.insert("rank".into(), json!({"type": ["number", "null"]}));
+    properties.insert("created_at".into(), timestamp.clone());
+    properties.insert(
+        "events".into(),
+        json!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {"action": {"type": "string"}, "timestamp": timestamp.clone()},
+                "required": ["action", "timestamp"],
+            },
+        }),
+    );
+    properties.insert(
+        "comments".into(),
+        json!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "content": {"type": "string"},
+                    "author": {"type": "string"},
+                    "timestamp": timestamp,
+                },
+                "required": ["content", "author", "timestamp"],
+            },
+        }),
+    );
+
+    if version >= 2 {
+        properties.insert(
+            "state".into(),
+            json!({"type": "string", "description": "State of the last event, or open"}),
+        );
+        properties.insert(
+            "version".into(),
+            json!({
+                "type": "object",
+                "description": "Number of updates each node sent for the task",
+                "additionalProperties": {"type": "integer", "minimum": 0},
+            }),
+        );
+    }
+
+    let required: Vec<String> = properties.keys().cloned().collect();
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "taud task",
+        "type": "object",
+        "schema_version": version,
+        "properties": properties,
+        "required": required,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_versions() {
+        let (version, args) = versioned_params(&json!([1, "pause"])).unwrap();
+        assert_eq!((version, args), (LEGACY_SCHEMA_VERSION, vec![json!(1), json!("pause")]));
+
+        let params = json!({"schema_version": 2, "args": [1]});
+        assert_eq!(versioned_params(&params).unwrap(), (2, vec![json!(1)]));
+        assert!(versioned_params(&json!({"schema_version": 3})).is_err());
+        assert!(versioned_params(&json!({"args": [1]})).is_err());
+
+        assert_eq!(versioned_result(1, json!(true)), json!(true));
+        let result = versioned_result(2, json!(true));
+        assert_eq!(result, json!({"schema_version": 2, "result": true}));
+
+        // Every field of a task is in the schema of its version
+        let task = serde_json::from_value::<TaskInfo>(json!({
+            "ref_id": "ref", "id": 1, "title": "", "desc": "", "owner": "",
+            "assign": [], "project": [], "due": null, "rank": 0.0, "created_at": 0,
+            "events": [], "comments": [],
+        }))
+        .unwrap();
+
+        for version in LEGACY_SCHEMA_VERSION..=SCHEMA_VERSION {
+            let task = task_to_json(&task, version);
+            let mut fields: Vec<&String> = task.as_object().unwrap().keys().collect();
+            let schema = task_schema(version);
+            let required = schema["required"].as_array().unwrap();
+            let mut required: Vec<&str> = required.iter().map(|v| v.as_str().unwrap()).collect();
+            fields.sort();
+            required.sort();
+            assert_eq!(fields, required);
+        }
+    }
+}
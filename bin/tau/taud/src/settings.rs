@@ -3,7 +3,7 @@ use structopt::StructOpt;
 use structopt_toml::StructOptToml;
 use url::Url;
 
-use darkfi::net::settings::SettingsOpt;
+use darkfi::{net::settings::SettingsOpt, util::service::ServiceCommand};
 
 pub const CONFIG_FILE: &str = "taud_config.toml";
 pub const CONFIG_FILE_CONTENTS: &str = include_str!("../../taud_config.toml");
@@ -19,6 +19,12 @@ pub struct Args {
     /// JSON-RPC listen URL
     #[structopt(long = "rpc", default_value = "tcp://127.0.0.1:12055")]
     pub rpc_listen: Url,
+    /// Token JSON-RPC clients must pass to call methods that aren't open
+    #[structopt(long)]
+    pub rpc_auth_token: Option<String>,
+    /// JSON-RPC methods callable without the token, `prefix*` allowed
+    #[structopt(long)]
+    pub rpc_open_methods: Vec<String>,
     /// Sets Datastore Path
     #[structopt(long, default_value = "~/.config/darkfi/tau")]
     pub datastore: String,
@@ -33,4 +39,22 @@ pub struct Args {
     /// Current display name    
     #[structopt(long)]
     pub nickname: Option<String>,
+    /// Extra task states accepted besides open, start, pause and stop
+    #[structopt(long)]
+    pub custom_states: Vec<String>,
+    /// Order tasks are listed in: rank, due, created or id
+    #[structopt(long, default_value = "rank")]
+    pub sort: String,
+    /// Check the configuration, datastore and ports, print a JSON report and exit
+    #[structopt(long)]
+    pub check: bool,
+    /// With --check, also check the configured peers and seeds can be reached
+    #[structopt(long)]
+    pub check_upstreams: bool,
+    /// Detach from the terminal and run in the background
+    #[structopt(long)]
+    pub daemon: bool,
+    /// Manage the instance running on the datastore instead: status or stop
+    #[structopt(parse(try_from_str))]
+    pub service: Option<ServiceCommand>,
 }
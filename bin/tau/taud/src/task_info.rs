@@ -1,6 +1,9 @@
 use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
     io,
     path::{Path, PathBuf},
+    str::FromStr,
 };
 
 use log::debug;
@@ -14,6 +17,7 @@ use darkfi::util::{
 use crate::{
     error::{TaudError, TaudResult},
     month_tasks::MonthTasks,
+    task_state::TaskState,
     util::{find_free_id, load, random_ref_id, save},
 };
 
@@ -24,8 +28,8 @@ struct TaskEvent {
 }
 
 impl TaskEvent {
-    fn new(action: String) -> Self {
-        Self { action, timestamp: Timestamp::current_time() }
+    fn new(state: &TaskState) -> Self {
+        Self { action: state.to_string(), timestamp: Timestamp::current_time() }
     }
 }
 
@@ -55,6 +59,88 @@ pub struct TaskProjects(Vec<String>);
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct TaskAssigns(Vec<String>);
 
+/// Version of a task, as the number of updates each node sent for it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VersionVector(BTreeMap<String, u64>);
+
+impl VersionVector {
+    /// Number of updates the given node sent
+    pub fn get(&self, node_id: &str) -> u64 {
+        self.0.get(node_id).copied().unwrap_or(0)
+    }
+
+    /// Count one more update from the given node
+    pub fn increment(&mut self, node_id: &str) {
+        *self.0.entry(node_id.to_string()).or_insert(0) += 1;
+    }
+
+    /// Include all the updates counted in another version
+    pub fn merge(&mut self, other: &Self) {
+        for (node_id, count) in &other.0 {
+            let entry = self.0.entry(node_id.clone()).or_insert(0);
+            *entry = (*entry).max(*count);
+        }
+    }
+
+    /// Whether this version includes all the updates of another
+    pub fn descends(&self, other: &Self) -> bool {
+        other.0.iter().all(|(node_id, count)| self.get(node_id) >= *count)
+    }
+}
+
+/// Changes made to a task on top of a version of it, so edits can be synced
+/// without sending the whole task. Fields left as `None` are unchanged, and
+/// events and comments only hold the ones added since `base`.
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable, PartialEq)]
+pub struct TaskPatch {
+    pub(crate) ref_id: String,
+    /// Version the changes were made on
+    pub(crate) base: VersionVector,
+    /// Version of the task with the changes
+    pub(crate) version: VersionVector,
+    title: Option<String>,
+    desc: Option<String>,
+    owner: Option<String>,
+    assign: Option<TaskAssigns>,
+    project: Option<TaskProjects>,
+    due: Option<Option<Timestamp>>,
+    rank: Option<f32>,
+    events: TaskEvents,
+    comments: TaskComments,
+}
+
+/// Orders tasks can be listed in. Each order falls back on the remaining
+/// keys, in the order rank, due date, creation time and id, so that tasks
+/// always come out in the same order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskOrder {
+    /// Highest rank first
+    Rank,
+    /// Earliest due date first, tasks without one last
+    Due,
+    /// Oldest first
+    Created,
+    /// Lowest id first
+    Id,
+}
+
+impl FromStr for TaskOrder {
+    type Err = TaudError;
+
+    fn from_str(s: &str) -> TaudResult<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "rank" => Ok(Self::Rank),
+            "due" => Ok(Self::Due),
+            "created" => Ok(Self::Created),
+            "id" => Ok(Self::Id),
+            _ => Err(TaudError::InvalidData(format!(
+                "unknown sort order `{}`, must be one of rank, due, created, id",
+                s
+            ))),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, SerialEncodable, SerialDecodable, PartialEq)]
 pub struct TaskInfo {
     pub(crate) ref_id: String,
@@ -69,6 +155,8 @@ pub struct TaskInfo {
     created_at: Timestamp,
     events: TaskEvents,
     comments: TaskComments,
+    #[serde(default)]
+    pub(crate) version: VersionVector,
 }
 
 impl TaskInfo {
@@ -109,6 +197,7 @@ impl TaskInfo {
             created_at,
             comments: TaskComments(vec![]),
             events: TaskEvents(vec![]),
+            version: VersionVector::default(),
         })
     }
 
@@ -123,7 +212,7 @@ impl TaskInfo {
         save::<Self>(&Self::get_path(&self.ref_id, dataset_path), self)
             .map_err(TaudError::Darkfi)?;
 
-        if self.get_state() == "stop" {
+        if self.get_state().is_closed() {
             self.deactivate(dataset_path)?;
         } else {
             self.activate(dataset_path)?;
@@ -146,13 +235,11 @@ impl TaskInfo {
         mt.save(path)
     }
 
-    pub fn get_state(&self) -> String {
+    pub fn get_state(&self) -> TaskState {
         debug!(target: "tau", "TaskInfo::get_state()");
-        if let Some(ev) = self.events.0.last() {
-            ev.action.clone()
-        } else {
-            "open".into()
-        }
+        // Events that don't parse can only come from a broken peer,
+        // so treat them like a task without any events.
+        self.events.0.last().and_then(|ev| ev.action.parse().ok()).unwrap_or_default()
     }
 
     fn get_path(ref_id: &str, dataset_path: &Path) -> PathBuf {
@@ -200,12 +287,129 @@ impl TaskInfo {
         self.due = d;
     }
 
-    pub fn set_state(&mut self, action: &str) {
+    pub fn set_state(&mut self, state: &TaskState) {
         debug!(target: "tau", "TaskInfo::set_state()");
-        if self.get_state() == action {
+        if self.get_state() == *state {
             return
         }
-        self.events.0.push(TaskEvent::new(action.into()));
+        self.events.0.push(TaskEvent::new(state));
+    }
+
+    /// Decode a task as it was encoded before tasks had a version.
+    pub fn decode_legacy<D: io::Read>(mut d: D) -> darkfi::Result<Self> {
+        Ok(Self {
+            ref_id: Decodable::decode(&mut d)?,
+            id: Decodable::decode(&mut d)?,
+            title: Decodable::decode(&mut d)?,
+            desc: Decodable::decode(&mut d)?,
+            owner: Decodable::decode(&mut d)?,
+            assign: Decodable::decode(&mut d)?,
+            project: Decodable::decode(&mut d)?,
+            due: Decodable::decode(&mut d)?,
+            rank: Decodable::decode(&mut d)?,
+            created_at: Decodable::decode(&mut d)?,
+            events: Decodable::decode(&mut d)?,
+            comments: Decodable::decode(&mut d)?,
+            version: VersionVector::default(),
+        })
+    }
+
+    /// Changes from `base` to this task, or `None` when they can't be told
+    /// as a patch, like for another task or removed events or comments.
+    pub fn diff(&self, base: &Self) -> Option<TaskPatch> {
+        if self.ref_id != base.ref_id || self.id != base.id || self.created_at != base.created_at {
+            return None
+        }
+
+        if !self.events.0.starts_with(&base.events.0) ||
+            !self.comments.0.starts_with(&base.comments.0)
+        {
+            return None
+        }
+
+        fn changed<T: Clone + PartialEq>(new: &T, old: &T) -> Option<T> {
+            (new != old).then(|| new.clone())
+        }
+
+        Some(TaskPatch {
+            ref_id: self.ref_id.clone(),
+            base: base.version.clone(),
+            version: self.version.clone(),
+            title: changed(&self.title, &base.title),
+            desc: changed(&self.desc, &base.desc),
+            owner: changed(&self.owner, &base.owner),
+            assign: changed(&self.assign, &base.assign),
+            project: changed(&self.project, &base.project),
+            due: changed(&self.due, &base.due),
+            // Compare the bits, so a NaN rank isn't always a change
+            rank: (self.rank.to_bits() != base.rank.to_bits()).then(|| self.rank),
+            events: TaskEvents(self.events.0[base.events.0.len()..].to_vec()),
+            comments: TaskComments(self.comments.0[base.comments.0.len()..].to_vec()),
+        })
+    }
+
+    /// Apply the changes of a patch, and take its version.
+    pub fn apply(&mut self, patch: &TaskPatch) {
+        debug!(target: "tau", "TaskInfo::apply()");
+        if let Some(title) = &patch.title {
+            self.title = title.clone();
+        }
+        if let Some(desc) = &patch.desc {
+            self.desc = desc.clone();
+        }
+        if let Some(owner) = &patch.owner {
+            self.owner = owner.clone();
+        }
+        if let Some(assign) = &patch.assign {
+            self.assign = assign.clone();
+        }
+        if let Some(project) = &patch.project {
+            self.project = project.clone();
+        }
+        if let Some(due) = patch.due {
+            self.due = due;
+        }
+        if let Some(rank) = patch.rank {
+            self.rank = rank;
+        }
+        self.events.0.extend(patch.events.0.iter().cloned());
+        self.comments.0.extend(patch.comments.0.iter().cloned());
+        self.version = patch.version.clone();
+    }
+
+    /// Compare two tasks for listing in the given order.
+    pub fn compare(&self, other: &Self, order: TaskOrder) -> Ordering {
+        let rank = || cmp_rank(other.rank, self.rank);
+        let due = || cmp_due(&self.due, &other.due);
+        let created = || self.created_at.0.cmp(&other.created_at.0);
+        let id = || self.id.cmp(&other.id);
+
+        match order {
+            TaskOrder::Rank => rank().then_with(due).then_with(created).then_with(id),
+            TaskOrder::Due => due().then_with(rank).then_with(created).then_with(id),
+            TaskOrder::Created => created().then_with(rank).then_with(due).then_with(id),
+            TaskOrder::Id => id(),
+        }
+    }
+}
+
+/// Total order on ranks, where NaN sorts below every other rank.
+fn cmp_rank(a: f32, b: f32) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.partial_cmp(&b).unwrap(),
+    }
+}
+
+/// Order on due dates, where tasks without one sort last.
+fn cmp_due(a: &Option<Timestamp>, b: &Option<Timestamp>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.0.cmp(&b.0),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
     }
 }
 
@@ -255,6 +459,20 @@ impl Decodable for TaskAssigns {
     }
 }
 
+impl Encodable for VersionVector {
+    fn encode<S: io::Write>(&self, s: S) -> darkfi::Result<usize> {
+        let entries: Vec<(String, u64)> = self.0.clone().into_iter().collect();
+        encode_vec(&entries, s)
+    }
+}
+
+impl Decodable for VersionVector {
+    fn decode<D: io::Read>(d: D) -> darkfi::Result<Self> {
+        let entries: Vec<(String, u64)> = decode_vec(d)?;
+        Ok(Self(entries.into_iter().collect()))
+    }
+}
+
 fn encode_vec<T: Encodable, S: io::Write>(vec: &[T], mut s: S) -> darkfi::Result<usize> {
     let mut len = 0;
     len += VarInt(vec.len() as u64).encode(&mut s)?;
@@ -272,3 +490,100 @@ fn decode_vec<T: Decodable, D: io::Read>(mut d: D) -> darkfi::Result<Vec<T>> {
     }
     Ok(ret)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_sync::{decode_update, TaskUpdate};
+    use darkfi::util::serial::serialize;
+
+    fn task(id: u32, rank: f32, due: Option<i64>, created_at: i64) -> TaskInfo {
+        TaskInfo {
+            ref_id: id.to_string(),
+            id,
+            title: String::new(),
+            desc: String::new(),
+            owner: String::new(),
+            assign: TaskAssigns(vec![]),
+            project: TaskProjects(vec![]),
+            due: due.map(Timestamp),
+            rank,
+            created_at: Timestamp(created_at),
+            events: TaskEvents(vec![]),
+            comments: TaskComments(vec![]),
+            version: VersionVector::default(),
+        }
+    }
+
+    fn sorted_ids(tasks: &mut [TaskInfo], order: TaskOrder) -> Vec<u32> {
+        tasks.sort_by(|a, b| a.compare(b, order));
+        tasks.iter().map(|t| t.id).collect()
+    }
+
+    #[test]
+    fn task_order() {
+        let mut tasks = vec![
+            task(1, 1.0, None, 10),
+            task(2, f32::NAN, Some(5), 10),
+            task(3, 1.0, Some(20), 10),
+            task(4, 2.0, None, 30),
+            task(5, 1.0, None, 5),
+            task(6, 1.0, Some(20), 10),
+        ];
+
+        assert_eq!(sorted_ids(&mut tasks, TaskOrder::Rank), vec![4, 3, 6, 5, 1, 2]);
+        assert_eq!(sorted_ids(&mut tasks, TaskOrder::Due), vec![2, 3, 6, 4, 5, 1]);
+        assert_eq!(sorted_ids(&mut tasks, TaskOrder::Created), vec![5, 3, 6, 1, 2, 4]);
+        assert_eq!(sorted_ids(&mut tasks, TaskOrder::Id), vec![1, 2, 3, 4, 5, 6]);
+        assert!("bogus".parse::<TaskOrder>().is_err());
+    }
+
+    #[test]
+    fn task_patch() {
+        let mut base = task(1, 1.0, None, 10);
+        base.version.increment("a");
+
+        let mut edited = base.clone();
+        edited.set_title("new title");
+        edited.set_rank(f32::NAN);
+        edited.set_comment(Comment::new("comment", "author"));
+        edited.version.increment("b");
+
+        let patch = edited.diff(&base).unwrap();
+        assert_eq!((patch.title.as_deref(), patch.desc.as_ref()), (Some("new title"), None));
+        assert_eq!(patch.comments.0.len(), 1);
+
+        let mut synced = base.clone();
+        synced.apply(&patch);
+        assert_eq!(synced.title, edited.title);
+        assert_eq!(synced.comments, edited.comments);
+        assert_eq!(synced.version, edited.version);
+        assert!(synced.rank.is_nan());
+
+        // A NaN rank that stays NaN isn't a change
+        assert!(edited.diff(&synced).unwrap().rank.is_none());
+        // Removed comments can't be patched
+        assert!(base.diff(&edited).is_none());
+
+        assert!(edited.version.descends(&base.version));
+        assert!(!base.version.descends(&edited.version));
+    }
+
+    #[test]
+    fn legacy_update() {
+        let mut task = task(1, 1.0, None, 10);
+        task.ref_id = random_ref_id();
+
+        // Raft log entries used to hold the bare task, without a version
+        let mut legacy = serialize(&task);
+        assert_eq!(legacy.pop(), Some(0));
+
+        match decode_update(&legacy).unwrap() {
+            TaskUpdate::Full(decoded) => assert_eq!(decoded, task),
+            TaskUpdate::Patch(_) => panic!("legacy entry decoded as a patch"),
+        }
+
+        let update = TaskUpdate::Full(task.clone());
+        assert!(matches!(decode_update(&serialize(&update)).unwrap(), TaskUpdate::Full(_)));
+    }
+}
@@ -0,0 +1,119 @@
+//! Task states shared between taud and tau-cli.
+//!
+//! tau-cli pulls this file in with a `#[path]` attribute, so it should only
+//! depend on std, serde and darkfi.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use darkfi::{Error, Result};
+
+/// Names of the states every taud node understands
+pub const BUILTIN_STATES: [&str; 4] = ["open", "start", "pause", "stop"];
+
+/// The state of a task. States outside the built-in set are carried in
+/// `Custom`, and taud only accepts the ones listed in its config.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum TaskState {
+    Open,
+    Start,
+    Pause,
+    Stop,
+    Custom(String),
+}
+
+impl TaskState {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Open => "open",
+            Self::Start => "start",
+            Self::Pause => "pause",
+            Self::Stop => "stop",
+            Self::Custom(s) => s,
+        }
+    }
+
+    pub fn is_custom(&self) -> bool {
+        matches!(self, Self::Custom(_))
+    }
+
+    /// Closed tasks are dropped from the set of active tasks
+    pub fn is_closed(&self) -> bool {
+        *self == Self::Stop
+    }
+}
+
+impl Default for TaskState {
+    fn default() -> Self {
+        Self::Open
+    }
+}
+
+impl fmt::Display for TaskState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for TaskState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().to_lowercase();
+
+        let state = match s.as_str() {
+            "open" => Self::Open,
+            "start" => Self::Start,
+            "pause" => Self::Pause,
+            "stop" => Self::Stop,
+            _ => {
+                let valid = s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+                if s.is_empty() || !valid {
+                    return Err(Error::ParseFailed("unable to parse state"))
+                }
+                Self::Custom(s)
+            }
+        };
+
+        Ok(state)
+    }
+}
+
+impl From<TaskState> for String {
+    fn from(state: TaskState) -> Self {
+        state.to_string()
+    }
+}
+
+impl TryFrom<String> for TaskState {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        Self::from_str(&s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_task_state() {
+        for name in BUILTIN_STATES {
+            let state = TaskState::from_str(name).unwrap();
+            assert!(!state.is_custom());
+            assert_eq!(state.to_string(), name);
+        }
+
+        assert_eq!(TaskState::from_str(" Pause ").unwrap(), TaskState::Pause);
+        assert_eq!(TaskState::from_str("blocked").unwrap(), TaskState::Custom("blocked".into()));
+        assert!(TaskState::from_str("").is_err());
+        assert!(TaskState::from_str("not valid").is_err());
+
+        let json = serde_json::to_string(&TaskState::Stop).unwrap();
+        assert_eq!(json, "\"stop\"");
+        assert_eq!(serde_json::from_str::<TaskState>(&json).unwrap(), TaskState::Stop);
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+
+use darkfi::{
+    util::serial::{deserialize, Decodable, Encodable},
+    Error,
+};
+
+use crate::task_info::{TaskInfo, TaskPatch, VersionVector};
+
+/// Update of a task committed through raft.
+#[derive(Clone, Debug)]
+pub enum TaskUpdate {
+    /// The whole task
+    Full(TaskInfo),
+    /// Only the fields that changed
+    Patch(TaskPatch),
+}
+
+impl Encodable for TaskUpdate {
+    fn encode<S: io::Write>(&self, mut s: S) -> darkfi::Result<usize> {
+        let len = match self {
+            Self::Full(task) => 0u8.encode(&mut s)? + task.encode(&mut s)?,
+            Self::Patch(patch) => 1u8.encode(&mut s)? + patch.encode(&mut s)?,
+        };
+        Ok(len)
+    }
+}
+
+impl Decodable for TaskUpdate {
+    fn decode<D: io::Read>(mut d: D) -> darkfi::Result<Self> {
+        let kind: u8 = Decodable::decode(&mut d)?;
+        match kind {
+            0 => Ok(Self::Full(Decodable::decode(&mut d)?)),
+            1 => Ok(Self::Patch(Decodable::decode(&mut d)?)),
+            _ => Err(Error::ParseFailed("unknown task update")),
+        }
+    }
+}
+
+/// Decode an update committed through raft. Entries written before
+/// updates existed hold the bare task, which starts with the length of its
+/// 30 character ref ID rather than an update kind, and are read as the
+/// whole task with no version.
+pub fn decode_update(bytes: &[u8]) -> darkfi::Result<TaskUpdate> {
+    let err = match deserialize(bytes) {
+        Ok(update) => return Ok(update),
+        Err(e) => e,
+    };
+
+    let mut cursor = io::Cursor::new(bytes);
+    match TaskInfo::decode_legacy(&mut cursor) {
+        Ok(task) if cursor.position() == bytes.len() as u64 => {
+            debug!(target: "tau", "Decoded legacy task {}", task.ref_id);
+            Ok(TaskUpdate::Full(task))
+        }
+        _ => Err(err),
+    }
+}
+
+/// What to do with a committed update
+pub enum Applied {
+    /// Save the task
+    Save(TaskInfo),
+    /// Our patch was made on a version the others no longer have, so send
+    /// this update with the whole task instead
+    Resend(TaskUpdate),
+    /// Nothing, the update is stale or for a task we don't have
+    Ignore,
+}
+
+/// Turns local task edits into updates, and applies the updates committed
+/// through raft.
+///
+/// Edits are sent as patches on top of the last committed copy of the task,
+/// or as the whole task when there's no such copy yet. Raft commits updates
+/// in the same order on every node, so a patch either applies everywhere or
+/// misses its base version everywhere, which happens when another edit of
+/// the task got committed first. The node that made the patch then falls
+/// back to sending the whole task, with the edits of both.
+pub struct TaskSync {
+    node_id: String,
+    datastore_path: PathBuf,
+    /// Last committed copy of the tasks seen since startup
+    committed: HashMap<String, TaskInfo>,
+    /// Version of the last update we sent for each task
+    sent: HashMap<String, VersionVector>,
+}
+
+impl TaskSync {
+    pub fn new(node_id: String, datastore_path: &Path) -> Self {
+        Self {
+            node_id,
+            datastore_path: datastore_path.to_path_buf(),
+            committed: HashMap::new(),
+            sent: HashMap::new(),
+        }
+    }
+
+    /// Update to send for a task that was edited locally.
+    pub fn outgoing(&mut self, mut task: TaskInfo) -> TaskUpdate {
+        debug!(target: "tau", "TaskSync::outgoing()");
+        self.bump_version(&mut task);
+
+        match self.committed.get(&task.ref_id).and_then(|base| task.diff(base)) {
+            Some(patch) => TaskUpdate::Patch(patch),
+            None => TaskUpdate::Full(task),
+        }
+    }
+
+    /// Apply an update committed through raft.
+    pub fn incoming(&mut self, update: TaskUpdate) -> Applied {
+        debug!(target: "tau", "TaskSync::incoming()");
+        match update {
+            TaskUpdate::Full(task) => {
+                // Skip replays of older updates
+                if let Some(current) = self.current(&task.ref_id) {
+                    let newer = current.version.descends(&task.version);
+                    if newer && current.version != task.version {
+                        return Applied::Ignore
+                    }
+                }
+
+                self.committed.insert(task.ref_id.clone(), task.clone());
+                Applied::Save(task)
+            }
+            TaskUpdate::Patch(patch) => {
+                let current = match self.current(&patch.ref_id) {
+                    Some(v) => v,
+                    None => {
+                        warn!(target: "tau", "Received a patch for unknown task {}", patch.ref_id);
+                        return Applied::Ignore
+                    }
+                };
+
+                if current.version == patch.base {
+                    let mut committed = current.clone();
+                    committed.apply(&patch);
+                    self.committed.insert(patch.ref_id.clone(), committed);
+
+                    // Keep the local edits that weren't sent yet
+                    let mut task =
+                        TaskInfo::load(&patch.ref_id, &self.datastore_path).unwrap_or(current);
+                    task.apply(&patch);
+                    return Applied::Save(task)
+                }
+
+                if current.version.descends(&patch.version) {
+                    return Applied::Ignore
+                }
+
+                // The versions diverged. Our own patch gets resent in full,
+                // with the edits committed before it.
+                if patch.version.get(&self.node_id) <= patch.base.get(&self.node_id) {
+                    return Applied::Ignore
+                }
+
+                debug!(target: "tau", "Patch for task {} missed its base, resending", patch.ref_id);
+                let mut task = match TaskInfo::load(&patch.ref_id, &self.datastore_path) {
+                    Ok(v) => v,
+                    Err(_) => current,
+                };
+                task.version.merge(&patch.version);
+                self.bump_version(&mut task);
+                Applied::Resend(TaskUpdate::Full(task))
+            }
+        }
+    }
+
+    /// Give a task a version after all the ones committed or sent for it
+    fn bump_version(&mut self, task: &mut TaskInfo) {
+        if let Some(committed) = self.committed.get(&task.ref_id) {
+            task.version.merge(&committed.version);
+        }
+        if let Some(sent) = self.sent.get(&task.ref_id) {
+            task.version.merge(sent);
+        }
+        task.version.increment(&self.node_id);
+        self.sent.insert(task.ref_id.clone(), task.version.clone());
+    }
+
+    /// Last committed copy of a task, or the one on disk if there's none yet
+    fn current(&mut self, ref_id: &str) -> Option<TaskInfo> {
+        if let Some(task) = self.committed.get(ref_id) {
+            return Some(task.clone())
+        }
+
+        let task = TaskInfo::load(ref_id, &self.datastore_path).ok()?;
+        self.committed.insert(ref_id.to_string(), task.clone());
+        Some(task)
+    }
+}
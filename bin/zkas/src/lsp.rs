@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Read, Write},
+};
+
+use serde_json::{json, Value};
+
+use darkfi::zkas::lsp::Document;
+
+/// Minimal language server speaking the LSP over stdin and stdout, for
+/// editors to check .zk sources as they're written. Documents are synced
+/// in full on every change, and get diagnostics, go-to-definition of
+/// names, and hover with their types and function signatures.
+#[derive(Default)]
+pub struct LanguageServer {
+    /// Open documents by URI, with their text
+    documents: HashMap<String, String>,
+}
+
+impl LanguageServer {
+    pub fn run(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+
+        while let Some(msg) = read_message(&mut stdin)? {
+            let method = msg["method"].as_str().unwrap_or_default();
+            let params = &msg["params"];
+
+            let result = match method {
+                "initialize" => json!({
+                    "capabilities": {
+                        // Full document sync
+                        "textDocumentSync": 1,
+                        "definitionProvider": true,
+                        "hoverProvider": true,
+                    },
+                    "serverInfo": { "name": "zkas", "version": env!("CARGO_PKG_VERSION") },
+                }),
+                "shutdown" => Value::Null,
+                "exit" => return Ok(()),
+                "textDocument/didOpen" => {
+                    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                    let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                    self.documents.insert(uri.to_string(), text.to_string());
+                    self.publish_diagnostics(uri)?;
+                    continue
+                }
+                "textDocument/didChange" => {
+                    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                    if let Some(text) = params["contentChanges"]
+                        .as_array()
+                        .and_then(|x| x.last())
+                        .and_then(|x| x["text"].as_str())
+                    {
+                        self.documents.insert(uri.to_string(), text.to_string());
+                        self.publish_diagnostics(uri)?;
+                    }
+                    continue
+                }
+                "textDocument/didClose" => {
+                    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                    self.documents.remove(uri);
+                    self.notify(
+                        "textDocument/publishDiagnostics",
+                        json!({ "uri": uri, "diagnostics": [] }),
+                    )?;
+                    continue
+                }
+                "textDocument/definition" => {
+                    let (uri, line, column) = position(params);
+                    match self.document(uri).and_then(|x| x.definition(line, column).cloned()) {
+                        Some(symbol) => json!({
+                            "uri": uri,
+                            "range": range(symbol.line, symbol.column, symbol.name.len()),
+                        }),
+                        None => Value::Null,
+                    }
+                }
+                "textDocument/hover" => {
+                    let (uri, line, column) = position(params);
+                    match self.document(uri).and_then(|x| x.hover(line, column)) {
+                        Some(text) => {
+                            let value = format!("```\n{}\n```", text);
+                            json!({ "contents": { "kind": "markdown", "value": value } })
+                        }
+                        None => Value::Null,
+                    }
+                }
+                // Notifications we don't care about
+                _ if msg.get("id").is_none() => continue,
+                _ => {
+                    let message = format!("Unsupported method {}", method);
+                    self.send(json!({
+                        "jsonrpc": "2.0",
+                        "id": msg["id"],
+                        "error": { "code": -32601, "message": message },
+                    }))?;
+                    continue
+                }
+            };
+
+            self.send(json!({ "jsonrpc": "2.0", "id": msg["id"], "result": result }))?;
+        }
+
+        Ok(())
+    }
+
+    fn document(&self, uri: &str) -> Option<Document> {
+        let text = self.documents.get(uri)?;
+        Some(Document::analyze(filename(uri), text))
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> io::Result<()> {
+        let mut diagnostics = vec![];
+        if let Some(diagnostic) = self.document(uri).and_then(|x| x.diagnostic) {
+            let mut message = format!("{} error: {}", diagnostic.namespace, diagnostic.msg);
+            let mut span = range(diagnostic.line, diagnostic.column, 1);
+
+            // Errors in included files are shown at the top of the document
+            if !diagnostic.file.is_empty() && diagnostic.file != filename(uri) {
+                message = format!(
+                    "{}:{}:{}: {}",
+                    diagnostic.file, diagnostic.line, diagnostic.column, message
+                );
+                span = range(1, 1, 1);
+            }
+
+            diagnostics.push(json!({
+                "range": span,
+                // Error
+                "severity": 1,
+                "source": "zkas",
+                "message": message,
+            }));
+        }
+
+        self.notify(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    fn notify(&self, method: &str, params: Value) -> io::Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn send(&self, msg: Value) -> io::Result<()> {
+        let body = msg.to_string();
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+        write!(handle, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        handle.flush()
+    }
+}
+
+/// Read a message, or return `None` once stdin is closed
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None)
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let content_length = match content_length {
+        Some(v) => v,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length")),
+    };
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// Document URI and position of a request, as 1-based zkas line and column
+fn position(params: &Value) -> (&str, usize, usize) {
+    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+    let line = params["position"]["line"].as_u64().unwrap_or_default() as usize;
+    let column = params["position"]["character"].as_u64().unwrap_or_default() as usize;
+    (uri, line + 1, column + 1)
+}
+
+/// LSP range for `len` characters from a 1-based zkas line and column
+fn range(line: usize, column: usize, len: usize) -> Value {
+    let line = line.saturating_sub(1);
+    let column = column.saturating_sub(1);
+    json!({
+        "start": { "line": line, "character": column },
+        "end": { "line": line, "character": column + len },
+    })
+}
+
+/// Path of a document URI, which included files are relative to
+fn filename(uri: &str) -> &str {
+    uri.strip_prefix("file://").unwrap_or(uri)
+}
@@ -1,6 +1,7 @@
 use std::{
-    fs::{read_to_string, File},
+    fs::{read, read_to_string, File},
     io::Write,
+    path::{Path, PathBuf},
     process::exit,
 };
 
@@ -8,13 +9,26 @@ use clap::Parser as ClapParser;
 
 use darkfi::{
     cli_desc,
+    util::cli::{confirm_overwrite, print_completions, Shell},
     zkas::{
-        analyzer::Analyzer, compiler::Compiler, decoder::ZkBinary, lexer::Lexer, parser::Parser,
+        analyzer::Analyzer,
+        codegen::witness_struct,
+        compiler::Compiler,
+        decoder::ZkBinary,
+        diff::diff,
+        disassembler::disassemble,
+        lexer::Lexer,
+        parser::Parser,
+        workspace::{compile_workspace, find_circuits},
     },
 };
 
+mod lsp;
+use lsp::LanguageServer;
+
 #[derive(clap::Parser)]
 #[clap(name = "zkas", about = cli_desc!(), version)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
     /// Place the output into <FILE>
     #[clap(short = 'o', value_name = "FILE")]
@@ -36,14 +50,98 @@ struct Args {
     #[clap(short = 'e')]
     examine: bool,
 
-    /// ZK script to compile
-    input: String,
+    /// Print the witnesses as a Rust struct for provers; do not compile
+    #[clap(short = 'w')]
+    witness_struct: bool,
+
+    /// Disassemble a compiled binary
+    #[clap(short = 'd')]
+    disassemble: bool,
+
+    /// Run a language server for editors on stdin and stdout
+    #[clap(long)]
+    lsp: bool,
+
+    /// Overwrite <FILE> without asking, even if it's not a compiled binary
+    #[clap(short = 'y')]
+    yes: bool,
+
+    /// ZK script to compile, or binary to disassemble
+    #[clap(required_unless_present = "lsp")]
+    input: Option<String>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Show what changed between two compiled binaries of a circuit
+    Diff {
+        /// Binary before the change
+        old: String,
+        /// Binary after the change
+        new: String,
+    },
+
+    /// Compile all the circuits of a workspace in parallel, each to <FILE>.bin
+    Workspace {
+        /// Strip debug symbols
+        #[clap(short = 's')]
+        strip: bool,
+
+        /// Number of threads to compile on, 0 for one per logical CPU
+        #[clap(short = 'j', default_value = "0")]
+        jobs: usize,
+
+        /// ZK scripts to compile, or directories to search for them
+        #[clap(required = true)]
+        paths: Vec<String>,
+    },
+
+    /// Print a completion script for bash, zsh, fish, elvish or powershell
+    Completions {
+        /// Shell to complete in
+        #[clap(parse(try_from_str))]
+        shell: Shell,
+    },
 }
 
 fn main() {
     let args = Args::parse();
 
-    let filename = args.input.as_str();
+    match &args.command {
+        Some(Command::Diff { old, new }) => {
+            diff_binaries(old, new);
+            exit(0);
+        }
+        Some(Command::Workspace { strip, jobs, paths }) => {
+            build_workspace(paths, *strip, *jobs);
+            exit(0);
+        }
+        Some(Command::Completions { shell }) => {
+            print_completions::<Args>(*shell);
+            exit(0);
+        }
+        None => {}
+    }
+
+    if args.lsp {
+        if let Err(e) = LanguageServer::default().run() {
+            eprintln!("Error: Language server failed. {}", e);
+            exit(1);
+        }
+        exit(0);
+    }
+
+    let input = args.input.unwrap();
+    let filename = input.as_str();
+
+    if args.disassemble {
+        disassemble_binary(filename);
+        exit(0);
+    }
+
     let source = match read_to_string(filename) {
         Ok(v) => v,
         Err(e) => {
@@ -73,6 +171,14 @@ fn main() {
         exit(0);
     }
 
+    if args.witness_struct {
+        // Name the struct after the file, e.g. MintWitnesses for mint.zk
+        let file_name = Path::new(filename).file_name().unwrap_or_default().to_string_lossy();
+        let name = file_name.split('.').next().unwrap_or_default();
+        print!("{}", witness_struct(name, &analyzer.witnesses));
+        exit(0);
+    }
+
     let compiler = Compiler::new(
         filename,
         source.chars(),
@@ -86,9 +192,26 @@ fn main() {
 
     let output = match args.output {
         Some(o) => o,
-        None => format!("{}.bin", args.input),
+        None => format!("{}.bin", input),
     };
 
+    // Rebuilding a binary is routine, writing over anything else, like
+    // the source after a typo in -o, likely isn't
+    let is_zkbin = read(&output).map_or(false, |x| ZkBinary::decode(&x).is_ok());
+    if !is_zkbin {
+        match confirm_overwrite(Path::new(&output), args.yes) {
+            Ok(true) => {}
+            Ok(false) => {
+                eprintln!("Error: Not overwriting \"{}\"", output);
+                exit(1);
+            }
+            Err(e) => {
+                eprintln!("Error: Failed asking to overwrite \"{}\". {}", output, e);
+                exit(1);
+            }
+        }
+    }
+
     let mut file = match File::create(&output) {
         Ok(v) => v,
         Err(e) => {
@@ -112,3 +235,82 @@ fn main() {
         println!("{:#?}", zkbin);
     }
 }
+
+fn read_binary(filename: &str) -> ZkBinary {
+    let bincode = match read(filename) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: Failed reading from \"{}\". {}", filename, e);
+            exit(1);
+        }
+    };
+
+    match ZkBinary::decode(&bincode) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: Failed decoding \"{}\". {}", filename, e);
+            exit(1);
+        }
+    }
+}
+
+fn diff_binaries(old: &str, new: &str) {
+    match diff(&read_binary(old), &read_binary(new)) {
+        Ok(Some(v)) => print!("{}", v),
+        Ok(None) => println!("No changes"),
+        Err(e) => {
+            eprintln!("Error: Failed comparing \"{}\" and \"{}\". {}", old, new, e);
+            exit(1);
+        }
+    }
+}
+
+fn build_workspace(paths: &[String], strip: bool, jobs: usize) {
+    let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+    let files = match find_circuits(&paths) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: Failed searching for circuits. {}", e);
+            exit(1);
+        }
+    };
+
+    let mut failed = 0;
+    for circuit in compile_workspace(&files, strip, jobs) {
+        let bincode = match circuit.result {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("{}:{}:{}: {} error: {}", e.file, e.line, e.column, e.namespace, e.msg);
+                failed += 1;
+                continue
+            }
+        };
+
+        let output = format!("{}.bin", circuit.file.display());
+        if let Err(e) = File::create(&output).and_then(|mut x| x.write_all(&bincode)) {
+            eprintln!("Error: Failed to write bincode to \"{}\". {}", output, e);
+            failed += 1;
+        }
+    }
+
+    println!("Compiled {} of {} circuits", files.len() - failed, files.len());
+    if failed > 0 {
+        exit(1);
+    }
+}
+
+fn disassemble_binary(filename: &str) {
+    let zkbin = read_binary(filename);
+
+    // Name the sections after the file, e.g. "mint" for mint.zk.bin
+    let file_name = Path::new(filename).file_name().unwrap_or_default().to_string_lossy();
+    let namespace = file_name.split('.').next().unwrap_or_default();
+
+    match disassemble(&zkbin, namespace) {
+        Ok(v) => print!("{}", v),
+        Err(e) => {
+            eprintln!("Error: Failed disassembling \"{}\". {}", filename, e);
+            exit(1);
+        }
+    }
+}
@@ -1,4 +1,6 @@
 // Example transaction flow
+use std::sync::Arc;
+
 use incrementalmerkletree::{bridgetree::BridgeTree, Tree};
 use rand::rngs::OsRng;
 
@@ -7,16 +9,22 @@ use darkfi::{
         constants::MERKLE_DEPTH,
         keypair::{Keypair, PublicKey, SecretKey},
         merkle_node::MerkleNode,
-        note::{EncryptedNote, Note},
+        note::{EncryptedNote, Memo, Note},
         nullifier::Nullifier,
         proof::{ProvingKey, VerifyingKey},
         token_id::generate_id,
         OwnCoin, OwnCoins,
     },
-    node::state::{state_transition, ProgramState, StateUpdate},
-    tx::builder::{
-        TransactionBuilder, TransactionBuilderClearInputInfo, TransactionBuilderInputInfo,
-        TransactionBuilderOutputInfo,
+    node::{
+        state::{state_transition, ProgramState, StateUpdate},
+        vk_registry::{BURN_CIRCUIT_ID, MINT_CIRCUIT_ID},
+    },
+    tx::{
+        builder::{
+            TransactionBuilder, TransactionBuilderClearInputInfo, TransactionBuilderOutputInfo,
+            TransactionBuilderPayment,
+        },
+        coin_selection::CoinSelection,
     },
     util::NetworkName,
     zk::circuit::{BurnContract, MintContract},
@@ -39,9 +47,9 @@ struct MemoryState {
     // this coin was received.
     own_coins: OwnCoins,
     /// Verifying key for the mint zk circuit.
-    mint_vk: VerifyingKey,
+    mint_vk: Arc<VerifyingKey>,
     /// Verifying key for the burn zk circuit.
-    burn_vk: VerifyingKey,
+    burn_vk: Arc<VerifyingKey>,
 
     /// Public key of the cashier
     cashier_signature_public: PublicKey,
@@ -70,12 +78,12 @@ impl ProgramState for MemoryState {
         self.nullifiers.iter().any(|n| n == nullifier)
     }
 
-    fn mint_vk(&self) -> &VerifyingKey {
-        &self.mint_vk
-    }
-
-    fn burn_vk(&self) -> &VerifyingKey {
-        &self.burn_vk
+    fn verifying_key(&self, circuit_id: &str) -> Option<Arc<VerifyingKey>> {
+        match circuit_id {
+            MINT_CIRCUIT_ID => Some(self.mint_vk.clone()),
+            BURN_CIRCUIT_ID => Some(self.burn_vk.clone()),
+            _ => None,
+        }
     }
 }
 
@@ -127,8 +135,8 @@ fn main() -> Result<()> {
 
     let keypair = Keypair::random(&mut OsRng);
 
-    let mint_vk = VerifyingKey::build(8, &MintContract::default());
-    let burn_vk = VerifyingKey::build(11, &BurnContract::default());
+    let mint_vk = Arc::new(VerifyingKey::build(8, &MintContract::default()));
+    let burn_vk = Arc::new(VerifyingKey::build(11, &BurnContract::default()));
 
     let mut state = MemoryState {
         tree: BridgeTree::<MerkleNode, MERKLE_DEPTH>::new(100),
@@ -150,12 +158,15 @@ fn main() -> Result<()> {
             value: 110,
             token_id,
             signature_secret: cashier_signature_secret,
+            metadata: None,
         }],
         inputs: vec![],
         outputs: vec![TransactionBuilderOutputInfo {
             value: 110,
             token_id,
             public: keypair.public,
+            memo: Memo::default(),
+            metadata: None,
         }],
     };
 
@@ -171,26 +182,16 @@ fn main() -> Result<()> {
     state.apply(update);
 
     // Now spend
-    let owncoin = &state.own_coins[0];
-    let note = owncoin.note;
-    let leaf_position = owncoin.leaf_position;
-    let root = state.tree.root(0).unwrap();
-    let merkle_path = state.tree.authentication_path(leaf_position, &root).unwrap();
-
-    let builder = TransactionBuilder {
-        clear_inputs: vec![],
-        inputs: vec![TransactionBuilderInputInfo {
-            leaf_position,
-            merkle_path,
-            secret: keypair.secret,
-            note,
-        }],
-        outputs: vec![TransactionBuilderOutputInfo {
-            value: 110,
-            token_id,
-            public: keypair.public,
-        }],
-    };
+    let payment =
+        TransactionBuilderPayment { public: keypair.public, value: 110, memo: Memo::default() };
+    let builder = TransactionBuilder::transfer(
+        token_id,
+        vec![payment],
+        &state.own_coins,
+        CoinSelection::default(),
+        keypair.public,
+        &state.tree,
+    )?;
 
     let tx = builder.build(&mint_pk, &burn_pk)?;
 
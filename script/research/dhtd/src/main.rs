@@ -1,20 +1,18 @@
 use async_executor::Executor;
 use async_std::sync::Arc;
 use async_trait::async_trait;
-use chrono::Utc;
-use futures::{select, FutureExt};
 use futures_lite::future;
-use log::{debug, error, info, warn};
+use log::{error, info, warn};
 use serde_derive::Deserialize;
 use serde_json::{json, Value};
-use std::time::Duration;
 use structopt::StructOpt;
 use structopt_toml::StructOptToml;
 use url::Url;
 
 use darkfi::{
-    async_daemonize, cli_desc, net,
-    net::P2pPtr,
+    async_daemonize, cli_desc,
+    dht::{self, Dht, DhtPtr, Key},
+    net,
     rpc::{
         jsonrpc::{
             ErrorCode::{InvalidParams, MethodNotFound},
@@ -23,9 +21,9 @@ use darkfi::{
         server::{listen_and_serve, RequestHandler},
     },
     util::{
+        check::CheckReport,
         cli::{get_log_config, get_log_level, spawn_config},
         path::get_config_path,
-        sleep,
     },
     Result,
 };
@@ -33,16 +31,8 @@ use darkfi::{
 mod error;
 use error::{server_error, RpcError};
 
-mod structures;
-use structures::{KeyRequest, KeyResponse, State, StatePtr};
-
-mod protocol;
-use protocol::Protocol;
-
 const CONFIG_FILE: &str = "dhtd_config.toml";
 const CONFIG_FILE_CONTENTS: &str = include_str!("../dhtd_config.toml");
-const REQUEST_TIMEOUT: u64 = 2400;
-const SEEN_DURATION: i64 = 120;
 
 #[derive(Clone, Debug, Deserialize, StructOpt, StructOptToml)]
 #[serde(default)]
@@ -76,6 +66,27 @@ struct Args {
     /// Connect to peer (repeatable flag)
     p2p_peer: Vec<Url>,
 
+    #[structopt(long, default_value = "86400")]
+    /// Seconds records are kept for when inserted without a TTL, and the
+    /// most other nodes' records are kept for
+    record_ttl: u64,
+
+    #[structopt(long, default_value = "3600")]
+    /// Seconds between republishing the records held
+    republish_interval: u64,
+
+    #[structopt(long, default_value = "60")]
+    /// Seconds between dropping the expired records
+    purge_interval: u64,
+
+    #[structopt(long)]
+    /// Check the configuration and ports, print a JSON report and exit
+    check: bool,
+
+    #[structopt(long)]
+    /// With --check, also check the configured peers and seeds can be reached
+    check_upstreams: bool,
+
     #[structopt(short, parse(from_occurrences))]
     /// Increase verbosity (-vvv supported)
     verbose: u8,
@@ -83,28 +94,20 @@ struct Args {
 
 /// Struct representing DHT daemon state
 pub struct Dhtd {
-    /// Daemon state
-    state: StatePtr,
+    /// DHT node
+    dht: DhtPtr,
     /// P2P network pointer
-    p2p: P2pPtr,
-    /// Channel to receive responses from P2P
-    p2p_recv_channel: async_channel::Receiver<KeyResponse>,
-    /// Stop signal channel to terminate background processes
-    stop_signal: async_channel::Receiver<()>,
+    p2p: net::P2pPtr,
 }
 
 impl Dhtd {
-    pub async fn new(
-        state: StatePtr,
-        p2p: P2pPtr,
-        p2p_recv_channel: async_channel::Receiver<KeyResponse>,
-        stop_signal: async_channel::Receiver<()>,
-    ) -> Result<Self> {
-        Ok(Self { state, p2p, p2p_recv_channel, stop_signal })
+    pub fn new(dht: DhtPtr, p2p: net::P2pPtr) -> Self {
+        Self { dht, p2p }
     }
 
     // RPCAPI:
-    // Checks if provided key exist in local map, otherwise queries the network.
+    // Checks if provided key exist in local records, otherwise looks it up
+    // on the network.
     // Returns key value or not found message.
     // --> {"jsonrpc": "2.0", "method": "get", "params": ["key"], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": "value", "id": 1}
@@ -113,101 +116,72 @@ impl Dhtd {
             return JsonError::new(InvalidParams, None, id).into()
         }
 
-        // Each node holds a local map, acting as its cache.
-        // When the node receives a request for a key it doesn't hold,
-        // it will query the P2P network and saves the response in its local cache.
-        let key = params[0].to_string();
-        match self.state.read().await.map.get(&key) {
-            Some(v) => return JsonResponse::new(json!(v), id).into(),
-            None => info!("Requested key doesn't exist, querying the network..."),
-        };
+        let key = params[0].as_str().unwrap();
 
         // We retrieve p2p network connected channels, to verify if we
         // are connected to a network.
-        // Using len here because is_empty() uses unstable library feature
-        // called 'exact_size_is_empty'.
-        if self.p2p.channels().lock().await.values().len() == 0 {
+        if self.p2p.channels().lock().await.is_empty() {
             warn!("Node is not connected to other nodes");
-            return server_error(RpcError::UnknownKey, id).into()
-        }
-
-        // We create a key request, and broadcast it to the network
-        // TODO: this should be based on the lookup table, and ask peers directly
-        let daemon = self.state.read().await.id.to_string();
-        let request = KeyRequest::new(daemon, key.clone());
-        if let Err(e) = self.p2p.broadcast(request).await {
-            error!("Failed broadcasting request: {}", e);
-            return server_error(RpcError::RequestBroadcastFail, id)
         }
 
-        // Waiting network response
-        match self.waiting_for_response().await {
-            Ok(resp) => match resp {
-                Some(response) => {
-                    info!("Key found!");
-                    self.state.write().await.map.insert(response.key, response.value.clone());
-                    JsonResponse::new(json!(response.value), id).into()
-                }
-                None => {
-                    info!("Did not find key: {}", key);
-                    server_error(RpcError::UnknownKey, id).into()
-                }
-            },
+        match self.dht.get(&Key::hash(key.as_bytes())).await {
+            Ok(Some(value)) => JsonResponse::new(json!(String::from_utf8_lossy(&value)), id).into(),
+            Ok(None) => {
+                info!("Did not find key: {}", key);
+                server_error(RpcError::UnknownKey, id)
+            }
             Err(e) => {
                 error!("Failed to query key: {}", e);
-                server_error(RpcError::QueryFailed, id).into()
-            }
-        }
-    }
-
-    // Auxilary function to wait for a key response from the P2P network.
-    // TODO: if no node holds the key, we shouldn't wait until the request timeout.
-    async fn waiting_for_response(&self) -> Result<Option<KeyResponse>> {
-        let ex = Arc::new(async_executor::Executor::new());
-        let (timeout_s, timeout_r) = async_channel::unbounded::<()>();
-        ex.spawn(async move {
-            sleep(Duration::from_millis(REQUEST_TIMEOUT).as_secs()).await;
-            timeout_s.send(()).await.unwrap_or(());
-        })
-        .detach();
-
-        loop {
-            select! {
-                msg =  self.p2p_recv_channel.recv().fuse() => {
-                    let response = msg?;
-                    return Ok(Some(response))
-                },
-                _ = self.stop_signal.recv().fuse() => break,
-                _ = timeout_r.recv().fuse() => break,
+                server_error(RpcError::QueryFailed, id)
             }
         }
-        Ok(None)
     }
 
     // RPCAPI:
-    // Insert key value pair in local map.
-    // --> {"jsonrpc": "2.0", "method": "insert", "params": ["key", "value"], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": "(key, value)", "id": 1}
+    // Stores key value pair on the nodes closest to the key, for the given
+    // number of seconds, or the configured record TTL if omitted.
+    // Returns the number of nodes it was stored on.
+    // --> {"jsonrpc": "2.0", "method": "insert", "params": ["key", "value", 3600], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": 20, "id": 1}
     async fn insert(&self, id: Value, params: &[Value]) -> JsonResult {
-        if params.len() != 2 || !params[0].is_string() || !params[1].is_string() {
+        if params.len() < 2 ||
+            params.len() > 3 ||
+            !params[0].is_string() ||
+            !params[1].is_string() ||
+            (params.len() == 3 && !params[2].is_u64())
+        {
             return JsonError::new(InvalidParams, None, id).into()
         }
 
-        let key = params[0].to_string();
-        let value = params[1].to_string();
-
-        self.state.write().await.map.insert(key.clone(), value.clone());
-        // TODO: inform network for the insert/update
+        let key = params[0].as_str().unwrap();
+        let value = params[1].as_str().unwrap().as_bytes().to_vec();
+        let ttl = params.get(2).and_then(|v| v.as_u64());
 
-        JsonResponse::new(json!((key, value)), id).into()
+        match self.dht.put(Key::hash(key.as_bytes()), value, ttl).await {
+            Ok(stored) => JsonResponse::new(json!(stored), id).into(),
+            Err(e) => {
+                error!("Failed to store key: {}", e);
+                server_error(RpcError::StoreFailed, id)
+            }
+        }
     }
 
     // RPCAPI:
-    // Returns current local map.
+    // Returns the records held by this node, by the hash of their key,
+    // with the UNIX timestamp they expire at.
     // --> {"jsonrpc": "2.0", "method": "map", "params": [], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": "map", "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"af13...": {"value": "value", "expires": 1658320000}}, "id": 1}
     pub async fn map(&self, id: Value, _params: &[Value]) -> JsonResult {
-        let map = self.state.read().await.map.clone();
+        let map: serde_json::Map<String, Value> = self
+            .dht
+            .records()
+            .await
+            .into_iter()
+            .map(|(k, v, expires)| {
+                let record = json!({"value": String::from_utf8_lossy(&v), "expires": expires.0});
+                (k.to_string(), record)
+            })
+            .collect();
         JsonResponse::new(json!(map), id).into()
     }
 }
@@ -230,34 +204,43 @@ impl RequestHandler for Dhtd {
     }
 }
 
-// Auxilary function to periodically prun seen messages, based on when they were received.
-// This helps us to prevent broadcasting loops.
-async fn prune_seen_messages(state: StatePtr) {
-    loop {
-        sleep(SEEN_DURATION as u64).await;
-        debug!("Pruning seen messages");
-
-        let now = Utc::now().timestamp();
-
-        let mut prune = vec![];
-        let map = state.read().await.seen.clone();
-        for (k, v) in map.iter() {
-            if now - v > SEEN_DURATION {
-                prune.push(k);
-            }
+/// Check what dhtd needs to start, without starting it, and print the
+/// report.
+fn self_check(args: &Args) -> Result<()> {
+    let mut report = CheckReport::new();
+
+    for (name, secs) in [
+        ("record_ttl", args.record_ttl),
+        ("republish_interval", args.republish_interval),
+        ("purge_interval", args.purge_interval),
+    ] {
+        match secs {
+            0 => report.fail(name, "Has to be at least a second".into()),
+            _ => report.add(name, Ok(format!("{} seconds", secs))),
         }
+    }
 
-        let mut map = map.clone();
-        for i in prune {
-            map.remove(i);
-        }
+    report.check_listen("rpc_listen", &args.rpc_listen, false);
+    if let Some(url) = &args.p2p_accept {
+        report.check_listen("p2p_accept", url, false);
+    }
 
-        state.write().await.seen = map;
+    if args.check_upstreams {
+        let mut upstreams: Vec<(&str, &Url)> = vec![];
+        upstreams.extend(args.p2p_seed.iter().map(|u| ("p2p_seed", u)));
+        upstreams.extend(args.p2p_peer.iter().map(|u| ("p2p_peer", u)));
+        report.check_upstreams(&upstreams, None);
     }
+
+    report.finish()
 }
 
 async_daemonize!(realmain);
 async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
+    if args.check {
+        return self_check(&args)
+    }
+
     // We use this handler to block this function after detaching all
     // tasks, and to catch a shutdown signal, where we can clean up and
     // exit gracefully.
@@ -267,9 +250,6 @@ async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
     })
     .unwrap();
 
-    // Initialize daemon state
-    let state = State::new().await?;
-
     // P2P network
     let network_settings = net::Settings {
         inbound: args.p2p_accept,
@@ -280,26 +260,19 @@ async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
         ..Default::default()
     };
 
-    let (p2p_send_channel, p2p_recv_channel) = async_channel::unbounded::<KeyResponse>();
     let p2p = net::P2p::new(network_settings).await;
-    let registry = p2p.protocol_registry();
-
-    info!("Registering P2P protocols...");
-    let _state = state.clone();
-    registry
-        .register(net::SESSION_ALL, move |channel, p2p| {
-            let sender = p2p_send_channel.clone();
-            let state = _state.clone();
-            async move { Protocol::init(channel, sender, state, p2p).await.unwrap() }
-        })
-        .await;
 
-    // Initialize program state
-    let dhtd = Dhtd::new(state.clone(), p2p.clone(), p2p_recv_channel, shutdown.clone()).await?;
-    let dhtd = Arc::new(dhtd);
+    info!("Registering DHT protocol...");
+    let dht_settings = dht::Settings {
+        record_ttl: args.record_ttl,
+        republish_interval: args.republish_interval,
+        purge_interval: args.purge_interval,
+        ..Default::default()
+    };
+    let dht = Dht::new(p2p.clone(), dht_settings).await;
 
-    // Task to periodically clean up daemon seen messages
-    ex.spawn(prune_seen_messages(state.clone())).detach();
+    // Initialize program state
+    let dhtd = Arc::new(Dhtd::new(dht.clone(), p2p.clone()));
 
     // JSON-RPC server
     info!("Starting JSON-RPC server");
@@ -316,6 +289,16 @@ async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
     })
     .detach();
 
+    // Dials the nodes lookups need, republishes the records, and drops
+    // the expired ones
+    let _ex = ex.clone();
+    ex.spawn(async move {
+        if let Err(e) = dht.run(_ex).await {
+            error!("DHT node failed: {}", e);
+        }
+    })
+    .detach();
+
     // Wait for SIGINT
     shutdown.recv().await?;
     print!("\r");
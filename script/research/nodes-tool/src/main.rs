@@ -14,10 +14,10 @@ use darkfi::{
         participant::Participant,
         state::{ConsensusState, ValidatorState},
         vote::Vote,
-        TESTNET_GENESIS_HASH_BYTES,
+        ChainParams, ConsensusKey,
     },
     crypto::{merkle_node::MerkleNode, token_list::DrkTokenList},
-    node::Client,
+    node::{Client, TokenRegistry},
     tx::Transaction,
     util::{expand_path, serial::serialize, time::Timestamp},
     wallet::walletdb::init_wallet,
@@ -85,6 +85,7 @@ struct MetadataInfo {
     _proof: String,
     _rand_seed: String,
     _signature: String,
+    _state: blake3::Hash,
 }
 
 impl MetadataInfo {
@@ -92,7 +93,8 @@ impl MetadataInfo {
         let _proof = metadata.proof.clone();
         let _rand_seed = metadata.rand_seed.clone();
         let _signature = metadata.signature.clone();
-        MetadataInfo { _proof, _rand_seed, _signature }
+        let _state = metadata.state;
+        MetadataInfo { _proof, _rand_seed, _signature, _state }
     }
 }
 
@@ -374,19 +376,18 @@ impl StateInfo {
 }
 
 async fn generate(name: &str, folder: &str) -> Result<()> {
-    let genesis_ts = Timestamp(1648383795);
-    let genesis_data = *TESTNET_GENESIS_HASH_BYTES;
+    let params = ChainParams { genesis_ts: Timestamp(1648383795), ..ChainParams::testnet() };
     let pass = "changeme";
     // Initialize or load wallet
     let path = folder.to_owned() + "/wallet.db";
     let wallet = init_wallet(&path, &pass).await?;
     let address = wallet.get_default_address().await?;
-    let tokenlist = Arc::new(DrkTokenList::new(&[
+    let tokenlist = Arc::new(TokenRegistry::new(DrkTokenList::new(&[
         ("drk", include_bytes!("../../../../contrib/token/darkfi_token_list.min.json")),
         ("btc", include_bytes!("../../../../contrib/token/bitcoin_token_list.min.json")),
         ("eth", include_bytes!("../../../../contrib/token/erc20_token_list.min.json")),
         ("sol", include_bytes!("../../../../contrib/token/solana_token_list.min.json")),
-    ])?);
+    ])?));
     let client = Arc::new(Client::new(wallet, tokenlist).await?);
 
     // Initialize or load sled database
@@ -397,7 +398,8 @@ async fn generate(name: &str, folder: &str) -> Result<()> {
     // Data export
     println!("Exporting data for {:?} - {:?}", name, address.to_string());
     let state =
-        ValidatorState::new(&sled_db, genesis_ts, genesis_data, client, vec![], vec![]).await?;
+        ValidatorState::new(&sled_db, params, client, ConsensusKey::random(), vec![], vec![])
+            .await?;
     let info = StateInfo::new(&*state.read().await);
     let info_string = format!("{:#?}", info);
     let path = name.to_owned() + "_testnet_db";
@@ -6,5 +6,6 @@ CREATE TABLE IF NOT EXISTS deposit_keypairs(
 	network BLOB NOT NULL,
 	token_id BLOB NOT NULL,
 	mint_address BLOB NOT NULL,
-	confirm BLOB NOT NULL
+	confirm BLOB NOT NULL,
+	tag TEXT
 );
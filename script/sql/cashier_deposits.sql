@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS deposits(
+	deposit_id INTEGER PRIMARY KEY NOT NULL,
+	network BLOB NOT NULL,
+	token_id BLOB NOT NULL,
+	d_key_public BLOB NOT NULL,
+	amount INTEGER NOT NULL,
+	sender TEXT,
+	status INTEGER NOT NULL,
+	reason TEXT,
+	refund_amount INTEGER,
+	refund_tx TEXT,
+	received_at INTEGER NOT NULL,
+	updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS token_supply(
+	network BLOB NOT NULL,
+	token_id BLOB NOT NULL,
+	mint_address BLOB NOT NULL,
+	minted INTEGER NOT NULL DEFAULT 0,
+	redeemed INTEGER NOT NULL DEFAULT 0,
+	partial INTEGER NOT NULL DEFAULT 0,
+	PRIMARY KEY (network, token_id)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS withdraw_whitelist(
+	drk_public_key BLOB NOT NULL,
+	token_key_public BLOB NOT NULL,
+	network BLOB NOT NULL,
+	registered_at INTEGER NOT NULL,
+	PRIMARY KEY (drk_public_key, token_key_public, network)
+);
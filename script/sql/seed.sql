@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS seed(
+	seed_id INTEGER PRIMARY KEY NOT NULL,
+	seed BLOB NOT NULL
+);
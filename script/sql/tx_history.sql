@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS tx_history(
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	timestamp INTEGER NOT NULL,
+	direction INTEGER NOT NULL,
+	value BLOB NOT NULL,
+	token_id BLOB NOT NULL,
+	tx_hash BLOB NOT NULL,
+	memo BLOB
+);
@@ -180,6 +180,7 @@ impl BlockStore {
 /// The `BlockOrderStore` is a `sled` tree storing the order of the
 /// blockchain's slots, where the key is the slot uid, and the value is
 /// the block's headers' hash. [`BlockStore`] can be queried with this hash.
+#[derive(Clone)]
 pub struct BlockOrderStore(sled::Tree);
 
 impl BlockOrderStore {
@@ -197,6 +198,16 @@ impl BlockOrderStore {
         Ok(store)
     }
 
+    /// Hash of the genesis block header in `db`, read without opening the
+    /// store, which would add one. `None` if the chain wasn't created yet.
+    pub fn stored_genesis(db: &sled::Db) -> Result<Option<blake3::Hash>> {
+        if !db.tree_names().iter().any(|n| n.as_ref() == SLED_BLOCK_ORDER_TREE) {
+            return Ok(None)
+        }
+
+        Ok(Self(db.open_tree(SLED_BLOCK_ORDER_TREE)?).get(&[0], false)?[0])
+    }
+
     /// Insert a slice of slots and headerhashes into the store. With sled, the
     /// operation is done as a batch.
     /// The block slot is used as the key, and the headerhash is used as value.
@@ -282,6 +293,47 @@ impl BlockOrderStore {
         Ok(ret)
     }
 
+    /// Fetch the last slot before the given one, with its headerhash.
+    pub fn get_before(&self, slot: u64) -> Result<Option<(u64, blake3::Hash)>> {
+        let found = match self.0.get_lt(slot.to_be_bytes())? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let slot_bytes: [u8; 8] = found.0.as_ref().try_into().unwrap();
+        let hash_bytes: [u8; 32] = found.1.as_ref().try_into().unwrap();
+        Ok(Some((u64::from_be_bytes(slot_bytes), blake3::Hash::from(hash_bytes))))
+    }
+
+    /// Remove all slots after the given one from the store.
+    pub fn remove_after(&self, slot: u64) -> Result<()> {
+        let mut batch = sled::Batch::default();
+
+        for entry in self.0.range((slot + 1).to_be_bytes()..) {
+            let (key, _) = entry?;
+            batch.remove(key);
+        }
+
+        self.0.apply_batch(batch)?;
+        Ok(())
+    }
+
+    /// Remove all slots from the store.
+    pub fn clear(&self) -> Result<()> {
+        self.0.clear()?;
+        Ok(())
+    }
+
+    /// Returns the number of slots in the store.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the store holds no slots.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     /// Fetch the last block headerhash in the tree, based on the `Ord`
     /// implementation for `Vec<u8>`. This should not be able to
     /// fail because we initialize the store with the genesis block.
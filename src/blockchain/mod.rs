@@ -1,15 +1,20 @@
-use std::io;
+use std::{collections::HashMap, fs, io, path::Path};
 
-use log::debug;
+use log::{debug, info};
+use sled::{
+    transaction::{TransactionError, TransactionResult},
+    Transactional,
+};
 
 use crate::{
-    consensus::{Block, BlockInfo},
+    consensus::{Block, BlockInfo, Header},
+    crypto::{merkle_node::MerkleNode, nullifier::Nullifier},
     impl_vec,
     util::{
         serial::{Decodable, Encodable, ReadExt, VarInt, WriteExt},
         time::Timestamp,
     },
-    Result,
+    Error, Result,
 };
 
 pub mod blockstore;
@@ -21,13 +26,92 @@ pub use metadatastore::StreamletMetadataStore;
 pub mod nfstore;
 pub use nfstore::NullifierStore;
 
+pub mod pool;
+pub use pool::{AsyncBlockchain, DbPool, DbPoolPtr, DB_POOL_THREADS};
+
 pub mod rootstore;
 pub use rootstore::RootStore;
 
+pub mod treestore;
+pub use treestore::MerkleTreeStore;
+
 pub mod txstore;
 pub use txstore::TxStore;
 
+pub mod undostore;
+pub use undostore::UndoStore;
+
+/// Delete the database of a chain, so that the node starts over from the
+/// genesis block. Anything that doesn't look like a sled database is left
+/// alone, in case the path is wrong.
+pub fn wipe_chain(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(())
+    }
+
+    if !path.join("conf").is_file() || !path.join("db").is_file() {
+        return Err(Error::NotChainDatabase(path.display().to_string()))
+    }
+
+    info!("Wiping chain database {:?}", path);
+    fs::remove_dir_all(path)?;
+    Ok(())
+}
+
+/// Insert nullifiers and Merkle roots in a single atomic write, so a crash
+/// can't leave one of the stores, or the nullifier set digest, ahead of
+/// the others.
+pub fn insert_nullifiers_and_roots(
+    nullifier_store: &NullifierStore,
+    nullifiers: &[Nullifier],
+    root_store: &RootStore,
+    roots: &[MerkleNode],
+) -> Result<()> {
+    let root_batch = RootStore::batch(roots);
+    let result: TransactionResult<()> =
+        (&nullifier_store.tree, &nullifier_store.digest, &root_store.0).transaction(
+            |(nf_tree, digest_tree, root_tree)| {
+                NullifierStore::insert_in(nf_tree, digest_tree, nullifiers)?;
+                root_tree.apply_batch(&root_batch)?;
+                Ok(())
+            },
+        );
+
+    storage_result(result)
+}
+
+/// Remove nullifiers and Merkle roots in a single atomic write. Used to
+/// undo [`insert_nullifiers_and_roots`] on a rollback.
+pub fn remove_nullifiers_and_roots(
+    nullifier_store: &NullifierStore,
+    nullifiers: &[Nullifier],
+    root_store: &RootStore,
+    roots: &[MerkleNode],
+) -> Result<()> {
+    let root_batch = RootStore::remove_batch(roots);
+    let result: TransactionResult<()> =
+        (&nullifier_store.tree, &nullifier_store.digest, &root_store.0).transaction(
+            |(nf_tree, digest_tree, root_tree)| {
+                NullifierStore::remove_in(nf_tree, digest_tree, nullifiers)?;
+                root_tree.apply_batch(&root_batch)?;
+                Ok(())
+            },
+        );
+
+    storage_result(result)
+}
+
+fn storage_result(result: TransactionResult<()>) -> Result<()> {
+    // We never abort the transaction ourselves
+    if let Err(TransactionError::Storage(e)) = result {
+        return Err(e.into())
+    }
+
+    Ok(())
+}
+
 /// Structure holding all sled trees that comprise the concept of Blockchain.
+#[derive(Clone)]
 pub struct Blockchain {
     /// Headers sled tree
     pub headers: HeaderStore,
@@ -43,6 +127,10 @@ pub struct Blockchain {
     pub nullifiers: NullifierStore,
     /// Merkle roots sled tree
     pub merkle_roots: RootStore,
+    /// Merkle tree checkpoint sled tree
+    pub merkle_tree: MerkleTreeStore,
+    /// State undo log sled tree
+    pub undo: UndoStore,
 }
 
 impl Blockchain {
@@ -55,6 +143,8 @@ impl Blockchain {
         let transactions = TxStore::new(db)?;
         let nullifiers = NullifierStore::new(db)?;
         let merkle_roots = RootStore::new(db)?;
+        let merkle_tree = MerkleTreeStore::new(db)?;
+        let undo = UndoStore::new(db)?;
 
         Ok(Self {
             headers,
@@ -64,6 +154,8 @@ impl Blockchain {
             streamlet_metadata,
             nullifiers,
             merkle_roots,
+            merkle_tree,
+            undo,
         })
     }
 
@@ -160,6 +252,64 @@ impl Blockchain {
     pub fn last(&self) -> Result<(u64, blake3::Hash)> {
         self.order.get_last()
     }
+
+    /// Retrieve the slot and hash of the last block before the given slot.
+    pub fn last_before(&self, slot: u64) -> Result<Option<(u64, blake3::Hash)>> {
+        self.order.get_before(slot)
+    }
+
+    /// Drop all blocks after the given slot from the canonical order.
+    /// The block data itself is kept, as it's only reachable by hash.
+    pub fn truncate_after(&self, slot: u64) -> Result<()> {
+        self.order.remove_after(slot)
+    }
+
+    /// Rebuild the canonical block order from the stored headers, by
+    /// following the previous block hashes from the tip back to genesis.
+    /// The tip is the last block in the order store if its header is
+    /// known, otherwise the header with the highest slot.
+    /// Returns the rebuilt chain as slots and hashes, oldest first.
+    pub fn reindex_order(&self) -> Result<Vec<(u64, blake3::Hash)>> {
+        let headers: HashMap<blake3::Hash, Header> = self.headers.get_all()?.into_iter().collect();
+
+        let mut tip = None;
+        if !self.order.is_empty() {
+            let (_, hash) = self.order.get_last()?;
+            if headers.contains_key(&hash) {
+                tip = Some(hash);
+            }
+        }
+
+        let mut hash = match tip {
+            Some(v) => v,
+            None => match headers.iter().max_by_key(|(_, h)| h.slot) {
+                Some((hash, _)) => *hash,
+                None => return Err(Error::HeaderNotFound("tip".to_string())),
+            },
+        };
+
+        let mut chain = vec![];
+        loop {
+            let header = match headers.get(&hash) {
+                Some(v) => v,
+                None => return Err(Error::HeaderNotFound(hash.to_hex().to_string())),
+            };
+
+            chain.push((header.slot, hash));
+            if header.slot == 0 {
+                break
+            }
+
+            hash = header.state;
+        }
+        chain.reverse();
+
+        let (slots, hashes): (Vec<u64>, Vec<blake3::Hash>) = chain.iter().cloned().unzip();
+        self.order.clear()?;
+        self.order.insert(&slots, &hashes)?;
+
+        Ok(chain)
+    }
 }
 
 impl Encodable for blake3::Hash {
@@ -1,3 +1,10 @@
+use sled::{
+    transaction::{
+        TransactionError, TransactionResult, TransactionalTree, UnabortableTransactionError,
+    },
+    Transactional,
+};
+
 use crate::{
     crypto::nullifier::Nullifier,
     util::serial::{deserialize, serialize},
@@ -5,38 +12,141 @@ use crate::{
 };
 
 const SLED_NULLIFIER_TREE: &[u8] = b"_nullifiers";
+const SLED_NULLIFIER_DIGEST_TREE: &[u8] = b"_nullifiers_digest";
+const DIGEST_KEY: &[u8] = b"digest";
+
+/// Add a nullifier to a digest built by [`NullifierStore::digest`].
+pub fn add_to_digest(digest: &mut [u8; 32], nullifier: &Nullifier) {
+    let hash = blake3::hash(&nullifier.to_bytes());
+    for (d, h) in digest.iter_mut().zip(hash.as_bytes()) {
+        *d ^= h;
+    }
+}
 
 /// The `NullifierStore` is a `sled` tree storing all the nullifiers seen
 /// in existing blocks. The key is the nullifier itself, while the value
 /// is an empty vector that's not used. As a sidenote, perhaps we could
 /// hold the transaction hash where the nullifier was seen in the value.
+/// The digest of the set is kept in a second tree, and updated in the
+/// same transaction as the nullifiers.
 #[derive(Clone)]
-pub struct NullifierStore(sled::Tree);
+pub struct NullifierStore {
+    pub(super) tree: sled::Tree,
+    pub(super) digest: sled::Tree,
+}
 
 impl NullifierStore {
     /// Opens a new or existing `NullifierStore` on the given sled database.
+    /// The digest of stores written before it was kept is computed once.
     pub fn new(db: &sled::Db) -> Result<Self> {
         let tree = db.open_tree(SLED_NULLIFIER_TREE)?;
-        Ok(Self(tree))
+        let digest = db.open_tree(SLED_NULLIFIER_DIGEST_TREE)?;
+        let store = Self { tree, digest };
+
+        if !store.digest.contains_key(DIGEST_KEY)? {
+            let digest = store.compute_digest()?;
+            store.digest.insert(DIGEST_KEY, &digest[..])?;
+        }
+
+        Ok(store)
     }
 
-    /// Insert a slice of [`Nullifier`] into the store. With sled, the
-    /// operation is done as a batch. The nullifier is used as a key,
-    /// while the value is an empty vector.
+    /// Insert a slice of [`Nullifier`] into the store. The nullifier is
+    /// used as a key, while the value is an empty vector.
     pub fn insert(&self, nfs: &[Nullifier]) -> Result<()> {
-        let mut batch = sled::Batch::default();
+        let result: TransactionResult<()> =
+            (&self.tree, &self.digest).transaction(|(tree, digest)| {
+                Self::insert_in(tree, digest, nfs)?;
+                Ok(())
+            });
+
+        // We never abort the transaction ourselves
+        if let Err(TransactionError::Storage(e)) = result {
+            return Err(e.into())
+        }
 
+        Ok(())
+    }
+
+    /// Insert the given [`Nullifier`] slice and update the digest within a
+    /// transaction, so it can be done along with writes to other trees.
+    /// Nullifiers already in the store leave the digest as it is.
+    pub(super) fn insert_in(
+        tree: &TransactionalTree,
+        digest: &TransactionalTree,
+        nfs: &[Nullifier],
+    ) -> std::result::Result<(), UnabortableTransactionError> {
+        let mut value = Self::read_digest(digest)?;
         for nf in nfs {
-            batch.insert(serialize(nf), vec![] as Vec<u8>);
+            if tree.insert(serialize(nf), vec![] as Vec<u8>)?.is_none() {
+                add_to_digest(&mut value, nf);
+            }
         }
+        digest.insert(DIGEST_KEY, &value[..])?;
+        Ok(())
+    }
 
-        self.0.apply_batch(batch)?;
+    /// Remove the given [`Nullifier`] slice and update the digest within a
+    /// transaction. Nullifiers not in the store leave the digest as it is.
+    pub(super) fn remove_in(
+        tree: &TransactionalTree,
+        digest: &TransactionalTree,
+        nfs: &[Nullifier],
+    ) -> std::result::Result<(), UnabortableTransactionError> {
+        let mut value = Self::read_digest(digest)?;
+        for nf in nfs {
+            if tree.remove(serialize(nf))?.is_some() {
+                add_to_digest(&mut value, nf);
+            }
+        }
+        digest.insert(DIGEST_KEY, &value[..])?;
         Ok(())
     }
 
+    fn read_digest(
+        digest: &TransactionalTree,
+    ) -> std::result::Result<[u8; 32], UnabortableTransactionError> {
+        let mut value = [0u8; 32];
+        if let Some(v) = digest.get(DIGEST_KEY)? {
+            value.copy_from_slice(&v);
+        }
+        Ok(value)
+    }
+
     /// Check if the nullifierstore contains a given nullifier.
     pub fn contains(&self, nullifier: &Nullifier) -> Result<bool> {
-        Ok(self.0.contains_key(serialize(nullifier))?)
+        Ok(self.tree.contains_key(serialize(nullifier))?)
+    }
+
+    /// Order-independent digest of the whole nullifier set, the XOR of the
+    /// hashes of every nullifier. Nodes with the same set get the same
+    /// digest, regardless of the order nullifiers were inserted in.
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        let mut value = [0u8; 32];
+        if let Some(v) = self.digest.get(DIGEST_KEY)? {
+            value.copy_from_slice(&v);
+        }
+        Ok(value)
+    }
+
+    /// Compute the digest from every nullifier in the store.
+    fn compute_digest(&self) -> Result<[u8; 32]> {
+        let mut digest = [0u8; 32];
+
+        for nullifier in self.tree.iter() {
+            let (key, _) = nullifier?;
+            let nullifier: Nullifier = deserialize(&key)?;
+            add_to_digest(&mut digest, &nullifier);
+        }
+
+        Ok(digest)
+    }
+
+    /// Remove all nullifiers from the store.
+    pub fn clear(&self) -> Result<()> {
+        self.tree.clear()?;
+        self.digest.insert(DIGEST_KEY, &[0u8; 32][..])?;
+        Ok(())
     }
 
     /// Retrieve all nullifiers from the store.
@@ -44,7 +154,7 @@ impl NullifierStore {
     pub fn get_all(&self) -> Result<Vec<Nullifier>> {
         let mut nullifiers = vec![];
 
-        for nullifier in self.0.iter() {
+        for nullifier in self.tree.iter() {
             let (key, _) = nullifier.unwrap();
             let nullifier = deserialize(&key)?;
             nullifiers.push(nullifier);
@@ -53,3 +163,43 @@ impl NullifierStore {
         Ok(nullifiers)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockchain::{insert_nullifiers_and_roots, remove_nullifiers_and_roots, RootStore},
+        crypto::keypair::SecretKey,
+    };
+    use pasta_curves::{group::ff::Field, pallas};
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn incremental_digest() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let store = NullifierStore::new(&db)?;
+        let roots = RootStore::new(&db)?;
+        assert_eq!(store.digest()?, [0u8; 32]);
+
+        let nullifier =
+            |_| Nullifier::new(SecretKey::random(&mut OsRng), pallas::Base::random(&mut OsRng));
+        let nfs: Vec<Nullifier> = (0..4).map(nullifier).collect();
+
+        // Nullifiers already in the set leave the digest as it is
+        insert_nullifiers_and_roots(&store, &nfs[..3], &roots, &[])?;
+        insert_nullifiers_and_roots(&store, &nfs[2..], &roots, &[])?;
+        assert_eq!(store.digest()?, store.compute_digest()?);
+
+        remove_nullifiers_and_roots(&store, &nfs[1..], &roots, &[])?;
+        let mut expected = [0u8; 32];
+        add_to_digest(&mut expected, &nfs[0]);
+        assert_eq!(store.digest()?, expected);
+        assert_eq!(store.compute_digest()?, expected);
+
+        // Stores written before the digest was kept get it when opened
+        store.digest.remove(DIGEST_KEY)?;
+        assert_eq!(NullifierStore::new(&db)?.digest()?, expected);
+
+        Ok(())
+    }
+}
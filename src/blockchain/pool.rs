@@ -0,0 +1,146 @@
+use async_std::sync::Arc;
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    thread,
+};
+
+use log::error;
+
+use crate::{consensus::BlockInfo, Error, Result};
+
+use super::Blockchain;
+
+/// Threads of the pool the node's database work runs on
+pub const DB_POOL_THREADS: usize = 4;
+
+/// Atomic pointer to a database thread pool.
+pub type DbPoolPtr = Arc<DbPool>;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Threads set apart for blocking database work. sled can stall a write
+/// for a long while when it flushes or compacts, which on an executor
+/// thread would hold up every task queued behind it, like the P2P and
+/// RPC ones. Keeping the work on its own threads keeps them responsive.
+pub struct DbPool {
+    sender: async_channel::Sender<Job>,
+}
+
+impl DbPool {
+    /// Start a pool of `threads` threads. They exit once the pool is
+    /// dropped and the queued work is done.
+    pub fn new(threads: usize) -> Result<DbPoolPtr> {
+        let (sender, receiver) = async_channel::unbounded::<Job>();
+
+        for i in 0..threads.max(1) {
+            let receiver = receiver.clone();
+            thread::Builder::new().name(format!("db-pool-{}", i)).spawn(move || {
+                while let Ok(job) = smol::block_on(receiver.recv()) {
+                    job();
+                }
+            })?;
+        }
+
+        Ok(Arc::new(Self { sender }))
+    }
+
+    /// Run `f` on the pool and wait for its result. A panic in `f` is
+    /// returned as an error, and doesn't take the thread down with it.
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        let (sender, receiver) = async_channel::bounded(1);
+        let job = Box::new(move || {
+            let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+                error!("DbPool::run(): Database operation panicked");
+                Err(Error::DatabaseJobPanicked)
+            });
+            // There's always room for the one result
+            let _ = sender.try_send(result);
+        });
+
+        self.sender.send(job).await?;
+        receiver.recv().await?
+    }
+}
+
+/// Async access to a [`Blockchain`], running its operations on a
+/// [`DbPool`] instead of the calling executor thread. Cloning it is
+/// cheap, so readers can take one out of the validator state and let
+/// go of the state lock before touching the disk.
+#[derive(Clone)]
+pub struct AsyncBlockchain {
+    blockchain: Blockchain,
+    pool: DbPoolPtr,
+}
+
+impl AsyncBlockchain {
+    pub fn new(blockchain: Blockchain, pool: DbPoolPtr) -> Self {
+        Self { blockchain, pool }
+    }
+
+    /// Run `f` on the database pool. Batching reads into one `f` takes a
+    /// single trip to the pool for all of them.
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Blockchain) -> Result<T> + Send + 'static,
+    {
+        let blockchain = self.blockchain.clone();
+        self.pool.run(move || f(&blockchain)).await
+    }
+
+    /// Insert blocks, see [`Blockchain::add`].
+    pub async fn add(&self, blocks: Vec<BlockInfo>) -> Result<Vec<blake3::Hash>> {
+        self.run(move |b| b.add(&blocks)).await
+    }
+
+    /// Retrieve the last block slot and hash.
+    pub async fn last(&self) -> Result<(u64, blake3::Hash)> {
+        self.run(|b| b.last()).await
+    }
+
+    /// Retrieve n blocks after given start slot.
+    pub async fn get_blocks_after(&self, slot: u64, n: u64) -> Result<Vec<BlockInfo>> {
+        self.run(move |b| b.get_blocks_after(slot, n)).await
+    }
+
+    /// Retrieve blocks by given hashes. Fails if any of them are not found.
+    pub async fn get_blocks_by_hash(&self, hashes: Vec<blake3::Hash>) -> Result<Vec<BlockInfo>> {
+        self.run(move |b| b.get_blocks_by_hash(&hashes)).await
+    }
+
+    /// Retrieve blocks by given slots, skipping the ones not found.
+    pub async fn get_blocks_by_slot(&self, slots: Vec<u64>) -> Result<Vec<BlockInfo>> {
+        self.run(move |b| b.get_blocks_by_slot(&slots)).await
+    }
+
+    /// Check which of the given transactions are in the ledger, in the
+    /// same order.
+    pub async fn contains_txs(&self, hashes: Vec<blake3::Hash>) -> Result<Vec<bool>> {
+        self.run(move |b| hashes.iter().map(|h| b.transactions.contains(h)).collect()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn db_pool() -> Result<()> {
+        let pool = DbPool::new(2)?;
+        let name = pool.run(|| Ok(thread::current().name().map(String::from))).await?;
+        assert!(name.unwrap().starts_with("db-pool-"));
+
+        // A panicking job doesn't take the pool down
+        assert!(pool.run::<(), _>(|| panic!("boom")).await.is_err());
+
+        let doubled = futures::future::join_all((0..8u64).map(|i| pool.run(move || Ok(i * 2))));
+        let doubled: Result<Vec<u64>> = doubled.await.into_iter().collect();
+        assert_eq!(doubled?, vec![0, 2, 4, 6, 8, 10, 12, 14]);
+
+        Ok(())
+    }
+}
@@ -10,7 +10,7 @@ const SLED_ROOTS_TREE: &[u8] = b"_merkleroots";
 /// in existing blocks. The key is the Merkle root itself, while the value
 /// is an empty vector that's not used.
 #[derive(Clone)]
-pub struct RootStore(sled::Tree);
+pub struct RootStore(pub(super) sled::Tree);
 
 impl RootStore {
     /// Opens a new or existing `RootStore` on the given sled database.
@@ -23,14 +23,31 @@ impl RootStore {
     /// operation is done as a batch. The Merkle root is used as a key,
     /// while the value is an empty vector.
     pub fn insert(&self, roots: &[MerkleNode]) -> Result<()> {
+        self.0.apply_batch(Self::batch(roots))?;
+        Ok(())
+    }
+
+    /// Build the [`sled::Batch`] inserting the given [`MerkleNode`] slice, so
+    /// it can be applied along with writes to other trees.
+    pub fn batch(roots: &[MerkleNode]) -> sled::Batch {
         let mut batch = sled::Batch::default();
 
         for root in roots {
             batch.insert(serialize(root), vec![] as Vec<u8>);
         }
 
-        self.0.apply_batch(batch)?;
-        Ok(())
+        batch
+    }
+
+    /// Build the [`sled::Batch`] removing the given [`MerkleNode`] slice.
+    pub fn remove_batch(roots: &[MerkleNode]) -> sled::Batch {
+        let mut batch = sled::Batch::default();
+
+        for root in roots {
+            batch.remove(serialize(root));
+        }
+
+        batch
     }
 
     /// Check if the rootstore contains a given Merkle root.
@@ -38,6 +55,12 @@ impl RootStore {
         Ok(self.0.contains_key(serialize(root))?)
     }
 
+    /// Remove all Merkle roots from the store.
+    pub fn clear(&self) -> Result<()> {
+        self.0.clear()?;
+        Ok(())
+    }
+
     /// Retrieve all Merkle roots from the store.
     /// Be careful as this will try to load everything in memory.
     pub fn get_all(&self) -> Result<Vec<MerkleNode>> {
@@ -0,0 +1,79 @@
+use std::io::Cursor;
+
+use incrementalmerkletree::bridgetree::BridgeTree;
+
+use crate::{
+    crypto::{constants::MERKLE_DEPTH, merkle_node::MerkleNode},
+    util::serial::{Decodable, Encodable},
+    Result,
+};
+
+const SLED_MERKLE_TREE_TREE: &[u8] = b"_merkletree";
+const SLED_CHECKPOINT_KEY: &[u8] = b"checkpoint";
+
+/// Version of the checkpoint encoding. Checkpoints written with a different
+/// version are ignored, and the tree has to be recovered some other way.
+pub const MERKLE_TREE_CHECKPOINT_VERSION: u8 = 1;
+
+/// The `MerkleTreeStore` is a `sled` tree holding a checkpoint of the
+/// commitment Merkle tree, along with the slot and hash of the last block
+/// applied to it. The tree also carries the witnesses of our own coins.
+#[derive(Clone)]
+pub struct MerkleTreeStore(sled::Tree);
+
+impl MerkleTreeStore {
+    /// Opens a new or existing `MerkleTreeStore` on the given sled database.
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        let tree = db.open_tree(SLED_MERKLE_TREE_TREE)?;
+        Ok(Self(tree))
+    }
+
+    /// Replace the stored checkpoint with the given tree, which must include
+    /// every block up to and including the one at `slot` with hash `hash`.
+    pub fn insert(
+        &self,
+        slot: u64,
+        hash: &blake3::Hash,
+        tree: &BridgeTree<MerkleNode, MERKLE_DEPTH>,
+    ) -> Result<()> {
+        let mut bytes = vec![];
+        MERKLE_TREE_CHECKPOINT_VERSION.encode(&mut bytes)?;
+        slot.encode(&mut bytes)?;
+        hash.encode(&mut bytes)?;
+        bytes.extend(bincode::serde::encode_to_vec(tree, bincode::config::legacy())?);
+
+        self.0.insert(SLED_CHECKPOINT_KEY, bytes)?;
+        Ok(())
+    }
+
+    /// Retrieve the stored checkpoint as the slot and hash of the last
+    /// applied block, and the tree itself. Returns `None` if there is no
+    /// checkpoint, or if it was written with another encoding version.
+    #[allow(clippy::type_complexity)]
+    pub fn get(&self) -> Result<Option<(u64, blake3::Hash, BridgeTree<MerkleNode, MERKLE_DEPTH>)>> {
+        let bytes = match self.0.get(SLED_CHECKPOINT_KEY)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let mut cursor = Cursor::new(&bytes[..]);
+        if u8::decode(&mut cursor)? != MERKLE_TREE_CHECKPOINT_VERSION {
+            return Ok(None)
+        }
+
+        let slot = u64::decode(&mut cursor)?;
+        let hash = blake3::Hash::decode(&mut cursor)?;
+
+        let pos = cursor.position() as usize;
+        let (tree, _read) =
+            bincode::serde::decode_from_slice(&bytes[pos..], bincode::config::legacy())?;
+
+        Ok(Some((slot, hash, tree)))
+    }
+
+    /// Remove the stored checkpoint.
+    pub fn clear(&self) -> Result<()> {
+        self.0.remove(SLED_CHECKPOINT_KEY)?;
+        Ok(())
+    }
+}
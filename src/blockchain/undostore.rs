@@ -0,0 +1,74 @@
+use crate::{
+    node::state::StateDiff,
+    util::serial::{deserialize, serialize},
+    Result,
+};
+
+const SLED_UNDO_TREE: &[u8] = b"_undo";
+
+/// Number of blocks kept in the undo log. This matches the number of
+/// checkpoints the wallet's Merkle tree keeps, since a rollback also has
+/// to rewind the tree.
+pub const UNDO_LOG_DEPTH: usize = 100;
+
+/// The `UndoStore` is a `sled` tree storing the [`StateDiff`] each block
+/// made to the canonical state, so it can be undone on a reorg.
+/// The block slot is used as the key, and the diff is used as value.
+#[derive(Clone)]
+pub struct UndoStore(sled::Tree);
+
+impl UndoStore {
+    /// Opens a new or existing `UndoStore` on the given sled database.
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        let tree = db.open_tree(SLED_UNDO_TREE)?;
+        Ok(Self(tree))
+    }
+
+    /// Insert the diff for the block in the given slot, dropping the oldest
+    /// entries past [`UNDO_LOG_DEPTH`].
+    pub fn insert(&self, slot: u64, diff: &StateDiff) -> Result<()> {
+        self.0.insert(slot.to_be_bytes(), serialize(diff))?;
+
+        while self.0.len() > UNDO_LOG_DEPTH {
+            self.0.pop_min()?;
+        }
+
+        Ok(())
+    }
+
+    /// Retrieve the oldest slot we still have a diff for.
+    pub fn first_slot(&self) -> Result<Option<u64>> {
+        match self.0.first()? {
+            Some((key, _)) => {
+                let slot_bytes: [u8; 8] = key.as_ref().try_into().unwrap();
+                Ok(Some(u64::from_be_bytes(slot_bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Fetch all diffs for slots after the given one, newest first.
+    pub fn get_after(&self, slot: u64) -> Result<Vec<(u64, StateDiff)>> {
+        let mut ret = vec![];
+
+        for entry in self.0.range((slot + 1).to_be_bytes()..).rev() {
+            let (key, value) = entry?;
+            let slot_bytes: [u8; 8] = key.as_ref().try_into().unwrap();
+            ret.push((u64::from_be_bytes(slot_bytes), deserialize(&value)?));
+        }
+
+        Ok(ret)
+    }
+
+    /// Remove the diff for the given slot.
+    pub fn remove(&self, slot: u64) -> Result<()> {
+        self.0.remove(slot.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Remove all diffs from the store.
+    pub fn clear(&self) -> Result<()> {
+        self.0.clear()?;
+        Ok(())
+    }
+}
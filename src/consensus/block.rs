@@ -16,7 +16,7 @@ use crate::{
         serial::{serialize, Decodable, Encodable, SerialDecodable, SerialEncodable, VarInt},
         time::Timestamp,
     },
-    Result,
+    Error, Result,
 };
 
 /// This struct represents a tuple of the form (version, state, epoch, slot, timestamp, merkle_root).
@@ -32,7 +32,8 @@ pub struct Header {
     pub slot: u64,
     /// Block creation timestamp
     pub timestamp: Timestamp,
-    /// Root of the transaction hashes merkle tree
+    /// Merkle root of the coins output by the block's transactions, see
+    /// [`Header::txs_root`]
     pub root: MerkleNode,
 }
 
@@ -60,6 +61,20 @@ impl Header {
     pub fn headerhash(&self) -> blake3::Hash {
         blake3::hash(&serialize(self))
     }
+
+    /// Merkle root of the coins output by the transactions, in order,
+    /// which the header's `root` commits to.
+    pub fn txs_root(txs: &[Transaction]) -> Result<MerkleNode> {
+        let mut tree = BridgeTree::<MerkleNode, MERKLE_DEPTH>::new(100);
+        for tx in txs {
+            for output in &tx.outputs {
+                tree.append(&MerkleNode::from_coin(&output.revealed.coin));
+                tree.witness();
+            }
+        }
+
+        tree.root(0).ok_or(Error::MerkleTreeNoRoot)
+    }
 }
 
 /// This struct represents a tuple of the form (`magic`, `header`, `counter`, `txs`, `metadata`).
@@ -86,7 +101,10 @@ impl Block {
     /// Generate the genesis block.
     pub fn genesis_block(genesis_ts: Timestamp, genesis_data: blake3::Hash) -> Self {
         let header = Header::genesis_header(genesis_ts, genesis_data);
-        let metadata = Metadata::new(String::from("proof"), String::from("r"), String::from("s"));
+        // Nothing is applied before the genesis block, so there's no state
+        // to commit to.
+        let state = blake3::Hash::from([0u8; 32]);
+        let metadata = Metadata::genesis(genesis_data, state);
 
         Self::new(header.headerhash(), vec![], metadata)
     }
@@ -149,6 +167,54 @@ pub struct BlockResponse {
     pub blocks: Vec<BlockInfo>,
 }
 
+impl BlockResponse {
+    /// Verify that the response blocks form a contiguous hash chain that
+    /// extends the block with the given hash and slot, and that each
+    /// header commits to the transactions it came with. Responses come
+    /// from untrusted peers, so this must pass before applying anything.
+    pub fn verify_chain(&self, prev_hash: blake3::Hash, prev_slot: u64) -> Result<()> {
+        let mut prev_hash = prev_hash;
+        let mut prev_slot = prev_slot;
+
+        for block in &self.blocks {
+            let hash = block.header.headerhash();
+
+            if block.header.state != prev_hash {
+                return Err(Error::BrokenBlockChain(format!(
+                    "block {} links to {}, expected {}",
+                    hash, block.header.state, prev_hash
+                )))
+            }
+
+            if block.header.slot <= prev_slot {
+                return Err(Error::BrokenBlockChain(format!(
+                    "block {} has slot {}, expected one after {}",
+                    hash, block.header.slot, prev_slot
+                )))
+            }
+
+            let mut tree = BridgeTree::<MerkleNode, MERKLE_DEPTH>::new(100);
+            for tx in &block.txs {
+                for output in &tx.outputs {
+                    tree.append(&MerkleNode::from_coin(&output.revealed.coin));
+                }
+            }
+
+            if tree.root(0).ok_or(Error::MerkleTreeNoRoot)? != block.header.root {
+                return Err(Error::BrokenBlockChain(format!(
+                    "block {} transactions don't match the header root",
+                    hash
+                )))
+            }
+
+            prev_hash = hash;
+            prev_slot = block.header.slot;
+        }
+
+        Ok(())
+    }
+}
+
 impl net::Message for BlockResponse {
     fn name() -> &'static str {
         "blockresponse"
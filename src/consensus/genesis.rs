@@ -0,0 +1,123 @@
+use std::{fs, path::Path, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::ChainParams;
+use crate::{
+    crypto::{address::Address, keypair::PublicKey},
+    util::time::Timestamp,
+    Error, Result,
+};
+
+/// Genesis of a test chain, shared by its nodes as a file so that the
+/// chain can be reset by handing out a new one, without a new release.
+/// It overrides the genesis timestamp and data of the chain preset, and
+/// adds to the whitelisted faucet and cashier addresses.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Genesis {
+    /// Network the genesis is for
+    pub network: String,
+    /// Genesis block creation timestamp
+    pub timestamp: Timestamp,
+    /// Genesis block data is the hash of this
+    pub seed: String,
+    /// Faucet addresses allowed to mint coins from the start
+    #[serde(default)]
+    pub faucet_pubs: Vec<String>,
+    /// Cashier addresses allowed to mint coins from the start
+    #[serde(default)]
+    pub cashier_pubs: Vec<String>,
+}
+
+impl Genesis {
+    /// Genesis of a new chain starting at the given time
+    pub fn new(network: &str, timestamp: Timestamp) -> Self {
+        Self {
+            network: network.to_string(),
+            timestamp,
+            seed: format!("darkfi_{}_{}", network, timestamp.0),
+            faucet_pubs: vec![],
+            cashier_pubs: vec![],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let genesis: Self = toml::from_str(&fs::read_to_string(path)?)?;
+        genesis.faucet_pubkeys()?;
+        genesis.cashier_pubkeys()?;
+        Ok(genesis)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = match toml::to_string(self) {
+            Ok(v) => v,
+            Err(_) => return Err(Error::ParseFailed("genesis")),
+        };
+
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    pub fn genesis_data(&self) -> blake3::Hash {
+        blake3::hash(self.seed.as_bytes())
+    }
+
+    /// Start the chain of `params` from this genesis. Mainnet's genesis
+    /// can't be replaced.
+    pub fn apply(&self, params: &mut ChainParams) -> Result<()> {
+        if self.network != params.network || params.network == "mainnet" {
+            return Err(Error::UnsupportedChain)
+        }
+
+        params.genesis_ts = self.timestamp;
+        params.genesis_data = self.genesis_data();
+        Ok(())
+    }
+
+    pub fn faucet_pubkeys(&self) -> Result<Vec<PublicKey>> {
+        parse_pubkeys(&self.faucet_pubs)
+    }
+
+    pub fn cashier_pubkeys(&self) -> Result<Vec<PublicKey>> {
+        parse_pubkeys(&self.cashier_pubs)
+    }
+}
+
+fn parse_pubkeys(addresses: &[String]) -> Result<Vec<PublicKey>> {
+    let mut ret = vec![];
+    for i in addresses {
+        ret.push(PublicKey::try_from(Address::from_str(i)?)?);
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::Keypair;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn genesis_file() {
+        let faucet = Keypair::random(&mut OsRng);
+        let mut genesis = Genesis::new("testnet", Timestamp(1660000000));
+        genesis.faucet_pubs.push(Address::from(faucet.public).to_string());
+
+        let path = std::env::temp_dir().join("darkfi_genesis.toml");
+        genesis.save(&path).unwrap();
+        let loaded = Genesis::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, genesis);
+        assert_eq!(loaded.faucet_pubkeys().unwrap(), vec![faucet.public]);
+
+        let mut params = ChainParams::testnet();
+        loaded.apply(&mut params).unwrap();
+        assert_eq!(params.genesis_ts, Timestamp(1660000000));
+        assert_ne!(params.genesis_data, ChainParams::testnet().genesis_data);
+
+        // Only for the network it was made for, and never mainnet
+        assert!(loaded.apply(&mut ChainParams::localnet()).is_err());
+        let mainnet = Genesis::new("mainnet", Timestamp(1660000000));
+        assert!(mainnet.apply(&mut ChainParams::mainnet()).is_err());
+    }
+}
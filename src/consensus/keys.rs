@@ -0,0 +1,237 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use rand::rngs::OsRng;
+
+use crate::{
+    crypto::{
+        address::Address,
+        keypair::{Keypair, PublicKey, SecretKey},
+        schnorr::{SchnorrPublic, SchnorrSecret, Signature},
+    },
+    util::{
+        serial::{SerialDecodable, SerialEncodable},
+        time::Timestamp,
+    },
+    Error, Result,
+};
+
+/// Prefix of the message a wallet key signs to vouch for a consensus key,
+/// so that the signature can't be passed off as one over anything else.
+const CONSENSUS_KEY_DOMAIN: &[u8] = b"DarkFi:ConsensusKeyCertificate";
+
+const IDENTITY_KEY_FILE: &str = "identity.key";
+const CONSENSUS_KEY_FILE: &str = "consensus.key";
+
+/// Key a node is known by on the P2P network. It doesn't sign anything
+/// consensus related, and doesn't hold funds.
+#[derive(Copy, Clone, Debug)]
+pub struct IdentityKey(pub Keypair);
+
+impl IdentityKey {
+    /// Node ID to announce to peers
+    pub fn node_id(&self) -> String {
+        bs58::encode(self.0.public.to_bytes()).into_string()
+    }
+}
+
+/// Key a validator signs its proposals and votes with. It doesn't hold funds,
+/// and is tied to the wallet address of the validator by a
+/// [`ConsensusKeyCertificate`].
+#[derive(Copy, Clone, Debug)]
+pub struct ConsensusKey(pub Keypair);
+
+impl ConsensusKey {
+    pub fn random() -> Self {
+        Self(Keypair::random(&mut OsRng))
+    }
+
+    pub fn public(&self) -> PublicKey {
+        self.0.public
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        self.0.secret.sign(message)
+    }
+}
+
+/// Signature of a wallet key over a consensus key, proving that whoever
+/// signs with the consensus key acts for the wallet address. The wallet
+/// key only has to be around to make the certificate, so a leaked
+/// consensus key can't spend anything, and a leaked wallet key can't
+/// sign for the node.
+#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct ConsensusKeyCertificate {
+    pub consensus_public: PublicKey,
+    pub wallet_public: PublicKey,
+    pub signature: Signature,
+}
+
+impl ConsensusKeyCertificate {
+    pub fn new(consensus_public: PublicKey, wallet: &Keypair) -> Self {
+        let signature = wallet.secret.sign(&Self::message(&consensus_public));
+        Self { consensus_public, wallet_public: wallet.public, signature }
+    }
+
+    /// Wallet address the consensus key acts for
+    pub fn address(&self) -> Address {
+        Address::from(self.wallet_public)
+    }
+
+    pub fn verify(&self) -> bool {
+        self.wallet_public.verify(&Self::message(&self.consensus_public), &self.signature)
+    }
+
+    fn message(consensus_public: &PublicKey) -> Vec<u8> {
+        [CONSENSUS_KEY_DOMAIN, &consensus_public.to_bytes()].concat()
+    }
+}
+
+/// Directory holding the identity and consensus keys of a node, apart from
+/// the wallet. Each key is in its own file, readable only by its owner,
+/// and gets created the first time it's asked for.
+///
+/// Rotating a key replaces it with a new one, and keeps the old one in a
+/// file suffixed with the time it was replaced, so it can still be looked
+/// at. A rotated consensus key needs a new certificate, which the node
+/// makes on startup with its wallet key.
+pub struct KeyStore {
+    dir: PathBuf,
+}
+
+impl KeyStore {
+    pub fn new(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self { dir: dir.to_path_buf() })
+    }
+
+    pub fn identity_key(&self) -> Result<IdentityKey> {
+        Ok(IdentityKey(self.load_or_create(IDENTITY_KEY_FILE)?))
+    }
+
+    pub fn consensus_key(&self) -> Result<ConsensusKey> {
+        Ok(ConsensusKey(self.load_or_create(CONSENSUS_KEY_FILE)?))
+    }
+
+    pub fn rotate_identity_key(&self) -> Result<IdentityKey> {
+        Ok(IdentityKey(self.rotate(IDENTITY_KEY_FILE)?))
+    }
+
+    pub fn rotate_consensus_key(&self) -> Result<ConsensusKey> {
+        Ok(ConsensusKey(self.rotate(CONSENSUS_KEY_FILE)?))
+    }
+
+    /// Read the identity and consensus keys in `dir`, without creating the
+    /// directory or the keys. `None` for the keys not created yet.
+    pub fn read_only(dir: &Path) -> Result<(Option<IdentityKey>, Option<ConsensusKey>)> {
+        let load = |name| {
+            let path = dir.join(name);
+            match path.exists() {
+                true => read_key(&path).map(Some),
+                false => Ok(None),
+            }
+        };
+
+        let identity = load(IDENTITY_KEY_FILE)?.map(IdentityKey);
+        let consensus = load(CONSENSUS_KEY_FILE)?.map(ConsensusKey);
+        Ok((identity, consensus))
+    }
+
+    fn load_or_create(&self, name: &str) -> Result<Keypair> {
+        let path = self.dir.join(name);
+        if !path.exists() {
+            info!("Creating new key {:?}", path);
+            let keypair = Keypair::random(&mut OsRng);
+            write_key(&path, &keypair.secret)?;
+            return Ok(keypair)
+        }
+
+        read_key(&path)
+    }
+
+    fn rotate(&self, name: &str) -> Result<Keypair> {
+        let path = self.dir.join(name);
+        if path.exists() {
+            let archived = self.dir.join(format!("{}.{}", name, Timestamp::current_time().0));
+            info!("Moving old key {:?} to {:?}", path, archived);
+            fs::rename(&path, archived)?;
+        }
+
+        self.load_or_create(name)
+    }
+}
+
+fn read_key(path: &Path) -> Result<Keypair> {
+    let encoded = fs::read_to_string(path)?;
+    let bytes = match bs58::decode(encoded.trim()).into_vec() {
+        Ok(v) if v.len() == 32 => v,
+        _ => return Err(Error::ParseFailed("invalid key file")),
+    };
+
+    Ok(Keypair::new(SecretKey::from_bytes(bytes.try_into().unwrap())?))
+}
+
+fn write_key(path: &Path, secret: &SecretKey) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(bs58::encode(secret.to_bytes()).into_string().as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_store() {
+        let dir = std::env::temp_dir().join("darkfi_key_store");
+        let _ = fs::remove_dir_all(&dir);
+        let store = KeyStore::new(&dir).unwrap();
+
+        // Keys are created once, and are different from each other
+        let identity = store.identity_key().unwrap();
+        let consensus = store.consensus_key().unwrap();
+        assert_eq!(store.identity_key().unwrap().0, identity.0);
+        assert_eq!(store.consensus_key().unwrap().0, consensus.0);
+        assert_ne!(identity.0, consensus.0);
+
+        // Rotating replaces only the one key, and keeps the old one around
+        let rotated = store.rotate_consensus_key().unwrap();
+        assert_ne!(rotated.0, consensus.0);
+        assert_eq!(store.consensus_key().unwrap().0, rotated.0);
+        assert_eq!(store.identity_key().unwrap().0, identity.0);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn consensus_key_certificate() {
+        let wallet = Keypair::random(&mut OsRng);
+        let consensus = ConsensusKey::random();
+
+        let cert = ConsensusKeyCertificate::new(consensus.public(), &wallet);
+        assert!(cert.verify());
+        assert_eq!(cert.address(), Address::from(wallet.public));
+
+        // The certificate doesn't vouch for any other key or address
+        let other = ConsensusKey::random();
+        let forged = ConsensusKeyCertificate { consensus_public: other.public(), ..cert.clone() };
+        assert!(!forged.verify());
+
+        let forged = ConsensusKeyCertificate { wallet_public: other.public(), ..cert };
+        assert!(!forged.verify());
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+
+use log::debug;
+
+use super::Seen;
+use crate::{tx::Transaction, util::serial::serialize};
+
+/// Maximum number of transactions waiting in the mempool
+pub const MAX_MEMPOOL_TXS: usize = 10_000;
+
+/// Transactions waiting to be included in a block, in the order they
+/// were received. The slot leader proposes them in that order.
+#[derive(Debug, Default)]
+pub struct Mempool {
+    txs: Vec<Transaction>,
+    hashes: HashSet<blake3::Hash>,
+    /// Valid transactions received from the network
+    seen: Seen,
+}
+
+/// Hash identifying a transaction on the network
+pub fn tx_hash(tx: &Transaction) -> blake3::Hash {
+    blake3::hash(&serialize(tx))
+}
+
+impl Mempool {
+    /// Check if a transaction with the given hash was seen already, so it
+    /// needs neither validating nor relaying again.
+    pub fn has_seen(&self, hash: &blake3::Hash) -> bool {
+        self.seen.contains(hash)
+    }
+
+    /// Record the hash of a transaction received from the network, once it
+    /// validated. Invalid ones aren't recorded, as a transaction spending
+    /// coins from a block we haven't applied yet becomes valid later.
+    /// Returns false if it was already seen.
+    pub fn see(&mut self, hash: blake3::Hash) -> bool {
+        self.seen.insert(hash)
+    }
+
+    /// Append a transaction. Returns false if it's already in the mempool,
+    /// or if the mempool is full.
+    pub fn insert(&mut self, tx: Transaction) -> bool {
+        let hash = tx_hash(&tx);
+        if self.hashes.contains(&hash) {
+            debug!("Mempool::insert(): We already have tx {}", hash);
+            return false
+        }
+
+        if self.txs.len() >= MAX_MEMPOOL_TXS {
+            debug!("Mempool::insert(): Mempool is full, dropping tx {}", hash);
+            return false
+        }
+
+        self.seen.insert(hash);
+        self.hashes.insert(hash);
+        self.txs.push(tx);
+        true
+    }
+
+    pub fn contains(&self, tx: &Transaction) -> bool {
+        self.hashes.contains(&tx_hash(tx))
+    }
+
+    /// Remove the given transactions, if they're in the mempool.
+    pub fn remove(&mut self, txs: &[Transaction]) {
+        let hashes: HashSet<blake3::Hash> = txs.iter().map(tx_hash).collect();
+        if hashes.is_disjoint(&self.hashes) {
+            return
+        }
+
+        self.txs.retain(|tx| !hashes.contains(&tx_hash(tx)));
+        self.hashes.retain(|hash| !hashes.contains(hash));
+    }
+
+    /// Pending transactions, oldest first
+    pub fn txs(&self) -> &[Transaction] {
+        &self.txs
+    }
+
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+}
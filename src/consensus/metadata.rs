@@ -1,21 +1,117 @@
 use super::{Participant, Vote};
-use crate::util::serial::{SerialDecodable, SerialEncodable};
+use crate::{
+    crypto::{
+        keypair::{PublicKey, SecretKey},
+        VrfProof,
+    },
+    util::serial::{serialize, SerialDecodable, SerialEncodable},
+};
+
+/// Fractional bits of the fixed point numbers the leader election is
+/// computed with. Floats round differently across platforms, and all
+/// nodes must agree on who leads a slot.
+const FIXED_POINT_BITS: u32 = 60;
+
+/// `-ln(1 - f)` in fixed point, where the active slot coefficient `f` of
+/// [`Metadata::is_elected`], the chance that a slot has at least one
+/// leader, is 0.9
+const NEG_LN_INACTIVE_SLOT: u128 = 2654699869899991813;
 
 /// This struct represents [`Block`](super::Block) information used by the Ouroboros
 /// Praos consensus protocol.
 #[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
 pub struct Metadata {
-    /// Proof that the stakeholder is the block owner
-    pub proof: String,
-    /// Random seed for VRF
-    pub rand_seed: String,
+    /// Proof that the block owner is the slot leader, `None` for the
+    /// genesis block
+    pub proof: Option<VrfProof>,
+    /// Random seed, the output of the VRF
+    pub rand_seed: blake3::Hash,
     /// Block owner signature
     pub signature: String,
+    /// Commitment to the state after applying the block, see
+    /// [`state_commitment`](super::state::state_commitment)
+    pub state: blake3::Hash,
 }
 
 impl Metadata {
-    pub fn new(proof: String, rand_seed: String, signature: String) -> Self {
-        Self { proof, rand_seed, signature }
+    /// Metadata of a block proposed by the slot leader, which proves it
+    /// with the VRF over [`Self::leader_input`].
+    pub fn new(proof: VrfProof, signature: String, state: blake3::Hash) -> Self {
+        Self { rand_seed: proof.output(), proof: Some(proof), signature, state }
+    }
+
+    /// Metadata of the genesis block, which has no leader
+    pub fn genesis(genesis_data: blake3::Hash, state: blake3::Hash) -> Self {
+        Self { proof: None, rand_seed: genesis_data, signature: String::from("s"), state }
+    }
+
+    /// VRF input the leaders of a slot evaluate. The epoch seed commits to
+    /// the chain, so proofs can't be replayed on another one or in another
+    /// slot, see [`ValidatorState::epoch_snapshot`](super::ValidatorState::epoch_snapshot).
+    pub fn leader_input(epoch_seed: &blake3::Hash, slot: u64) -> Vec<u8> {
+        let mut input = epoch_seed.as_bytes().to_vec();
+        input.extend(serialize(&slot));
+        input
+    }
+
+    /// Evaluate the VRF for the slot with the consensus secret key. The
+    /// owner leads the slot if [`Self::is_elected`] holds for the output.
+    pub fn prove_leader(secret: &SecretKey, epoch_seed: &blake3::Hash, slot: u64) -> VrfProof {
+        VrfProof::prove(secret, &Self::leader_input(epoch_seed, slot))
+    }
+
+    /// Check that the block was made by the owner of `leader` for the slot,
+    /// that the random seed is the VRF output, and that it elected the
+    /// owner, holding `stake` out of `total_stake`.
+    pub fn verify_leader(
+        &self,
+        leader: &PublicKey,
+        epoch_seed: &blake3::Hash,
+        slot: u64,
+        stake: u64,
+        total_stake: u64,
+    ) -> bool {
+        match &self.proof {
+            Some(proof) => {
+                proof.output() == self.rand_seed &&
+                    Self::is_elected(&self.rand_seed, stake, total_stake) &&
+                    proof.verify(leader, &Self::leader_input(epoch_seed, slot))
+            }
+            None => false,
+        }
+    }
+
+    /// Check if a VRF output elects its owner, holding `stake` out of
+    /// `total_stake`, as a slot leader. Following Praos, the output is
+    /// compared with the threshold `phi(a) = 1 - (1 - f)^a`, where `a` is
+    /// the relative stake and `f` is the active slot coefficient. A slot
+    /// can have no leader or several, and splitting stake across keys
+    /// doesn't change the odds of leading it.
+    pub fn is_elected(output: &blake3::Hash, stake: u64, total_stake: u64) -> bool {
+        if stake == 0 || total_stake == 0 || stake > total_stake {
+            return false
+        }
+
+        let value = u64::from_le_bytes(output.as_bytes()[..8].try_into().unwrap());
+        (value as u128) < Self::threshold(stake, total_stake) << (64 - FIXED_POINT_BITS)
+    }
+
+    /// `phi(a)` in fixed point, as `1 - e^(-x)` with `x = -a ln(1 - f)`.
+    /// `x` is at most `-ln(1 - f)`, so the terms of the series shrink fast
+    /// and stay far from overflowing.
+    fn threshold(stake: u64, total_stake: u64) -> u128 {
+        let one = 1i128 << FIXED_POINT_BITS;
+        let x = (NEG_LN_INACTIVE_SLOT * stake as u128 / total_stake as u128) as i128;
+
+        // 1 - e^(-x) = x - x^2/2! + x^3/3! - ...
+        let (mut phi, mut term, mut k) = (0, x, 1);
+        while term != 0 {
+            phi += term;
+            k += 1;
+            term = -term * x / one / k;
+        }
+
+        phi as u128
     }
 }
 
@@ -38,3 +134,64 @@ impl StreamletMetadata {
         Self { votes: vec![], notarized: false, finalized: false, participants }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn leader_election() {
+        let secret = SecretKey::random(&mut OsRng);
+        let public = PublicKey::from_secret(secret);
+        let seed = blake3::hash(b"epoch seed");
+        let state = blake3::hash(b"state");
+
+        // Holding all the stake, a slot is led with probability f
+        let slots = 1000;
+        let led = (0..slots)
+            .filter(|slot| {
+                let proof = Metadata::prove_leader(&secret, &seed, *slot);
+                Metadata::is_elected(&proof.output(), 1, 1)
+            })
+            .count();
+        assert!(led > 850 && led < 950);
+
+        let slot = (0..).find(|slot| {
+            let proof = Metadata::prove_leader(&secret, &seed, *slot);
+            Metadata::is_elected(&proof.output(), 1, 1)
+        });
+        let slot = slot.unwrap();
+        let proof = Metadata::prove_leader(&secret, &seed, slot);
+        let metadata = Metadata::new(proof, String::from("s"), state);
+        assert!(metadata.verify_leader(&public, &seed, slot, 1, 1));
+
+        // Proofs are bound to the seed, the slot and the key, and no stake
+        // elects nobody
+        assert!(!metadata.verify_leader(&public, &blake3::hash(b"other"), slot, 1, 1));
+        assert!(!metadata.verify_leader(&public, &seed, slot + 1, 1, 1));
+        assert!(!metadata.verify_leader(&PublicKey::random(&mut OsRng), &seed, slot, 1, 1));
+        assert!(!metadata.verify_leader(&public, &seed, slot, 0, 1));
+        assert!(!Metadata::genesis(seed, state).verify_leader(&public, &seed, slot, 1, 1));
+    }
+
+    #[test]
+    fn election_threshold() {
+        let one = (1u128 << FIXED_POINT_BITS) as f64;
+        let phi = |stake, total_stake| Metadata::threshold(stake, total_stake) as f64 / one;
+
+        // phi(a) = 1 - (1 - f)^a
+        assert!((phi(1, 1) - 0.9).abs() < 1e-12);
+        assert!((phi(1, 2) - (1.0 - 0.1f64.sqrt())).abs() < 1e-12);
+        assert!((phi(1, 3) - (1.0 - 0.1f64.powf(1.0 / 3.0))).abs() < 1e-12);
+        assert!(phi(1, 1000) > phi(1, 1001));
+        assert_eq!(Metadata::threshold(u64::MAX, u64::MAX), Metadata::threshold(1, 1));
+
+        // An output of zero wins with any stake, and no stake wins nothing
+        let zero = blake3::Hash::from([0u8; 32]);
+        assert!(Metadata::is_elected(&zero, 1, u64::MAX));
+        assert!(!Metadata::is_elected(&zero, 0, 1));
+        assert!(!Metadata::is_elected(&zero, 2, 1));
+        assert!(!Metadata::is_elected(&blake3::Hash::from([0xff; 32]), 1, 1));
+    }
+}
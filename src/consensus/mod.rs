@@ -6,6 +6,10 @@ pub use block::{Block, BlockInfo, BlockProposal, Header, ProposalChain};
 pub mod metadata;
 pub use metadata::{Metadata, StreamletMetadata};
 
+/// Node identity and consensus keys
+pub mod keys;
+pub use keys::{ConsensusKey, ConsensusKeyCertificate, IdentityKey, KeyStore};
+
 /// Consensus participant
 pub mod participant;
 pub use participant::Participant;
@@ -14,10 +18,37 @@ pub use participant::Participant;
 pub mod vote;
 pub use vote::Vote;
 
+/// Removal of misbehaving participants
+pub mod slashing;
+pub use slashing::{Misbehavior, Removal};
+
+/// Fork choice and competing chains
+pub mod fork;
+
+/// Seen message cache of the gossip protocols
+pub mod seen;
+pub use seen::Seen;
+
+/// Pending transactions
+pub mod mempool;
+pub use mempool::Mempool;
+
+/// Chain parameters
+pub mod params;
+pub use params::ChainParams;
+
+/// Genesis files of test chains
+pub mod genesis;
+pub use genesis::Genesis;
+
 /// Consensus state
 pub mod state;
 pub use state::{ValidatorState, ValidatorStatePtr};
 
+/// Transaction filters
+pub mod tx_filter;
+pub use tx_filter::TxFilter;
+
 /// Utility functions and types
 use crate::util::time::Timestamp;
 
@@ -0,0 +1,92 @@
+use super::{
+    MAINNET_GENESIS_HASH_BYTES, MAINNET_GENESIS_TIMESTAMP, TESTNET_GENESIS_HASH_BYTES,
+    TESTNET_GENESIS_TIMESTAMP,
+};
+use crate::{util::time::Timestamp, Error, Result};
+
+/// Parameters of a chain. Nodes on the same network must agree on all
+/// of them, so they are selected by network name rather than configured
+/// one by one, except on localnet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainParams {
+    /// Network name
+    pub network: String,
+    /// Genesis block creation timestamp
+    pub genesis_ts: Timestamp,
+    /// Genesis block data
+    pub genesis_data: blake3::Hash,
+    /// `2 * delta` is the slot duration, in seconds
+    pub delta: u64,
+    /// Slots in an epoch
+    pub epoch_slots: u64,
+    /// Quarantine duration, in slots
+    pub quarantine_duration: u64,
+    /// Slots a participant can go without voting before it's quarantined
+    pub missed_slots: u64,
+    /// Consecutive notarized proposals needed to finalize a fork chain
+    pub finality_window: usize,
+    /// Blocks a fork can replace before it's compared by density
+    /// instead of length
+    pub fork_depth: usize,
+    /// Slots after a fork point in which the density of chains is compared
+    pub density_window: u64,
+    /// Maximum number of transactions in a block
+    pub max_block_txs: usize,
+    /// Number of blocks sent per sync request
+    pub sync_batch: u64,
+}
+
+impl ChainParams {
+    pub fn mainnet() -> Self {
+        Self {
+            network: String::from("mainnet"),
+            genesis_ts: *MAINNET_GENESIS_TIMESTAMP,
+            genesis_data: *MAINNET_GENESIS_HASH_BYTES,
+            delta: 20,
+            epoch_slots: 10,
+            quarantine_duration: 5,
+            missed_slots: 3,
+            finality_window: 3,
+            fork_depth: 10,
+            density_window: 50,
+            max_block_txs: 1000,
+            sync_batch: 10,
+        }
+    }
+
+    pub fn testnet() -> Self {
+        Self {
+            network: String::from("testnet"),
+            genesis_ts: *TESTNET_GENESIS_TIMESTAMP,
+            genesis_data: *TESTNET_GENESIS_HASH_BYTES,
+            ..Self::mainnet()
+        }
+    }
+
+    /// Parameters for a local development network. Start from these and
+    /// override whatever the setup needs.
+    pub fn localnet() -> Self {
+        Self {
+            network: String::from("localnet"),
+            genesis_ts: *TESTNET_GENESIS_TIMESTAMP,
+            genesis_data: blake3::hash(b"darkfi_localnet"),
+            delta: 5,
+            ..Self::mainnet()
+        }
+    }
+
+    /// Select the preset for the given network name.
+    pub fn from_network(network: &str) -> Result<Self> {
+        match network {
+            "mainnet" => Ok(Self::mainnet()),
+            "testnet" => Ok(Self::testnet()),
+            "localnet" => Ok(Self::localnet()),
+            _ => Err(Error::UnsupportedChain),
+        }
+    }
+
+    /// Slot duration, in seconds
+    pub fn slot_time(&self) -> u64 {
+        2 * self.delta
+    }
+}
@@ -1,5 +1,6 @@
 use std::{collections::BTreeMap, io};
 
+use super::keys::ConsensusKeyCertificate;
 use crate::{
     crypto::{address::Address, keypair::PublicKey},
     impl_vec, net,
@@ -11,10 +12,12 @@ use crate::{
 /// (`node_address`, `slot_joined`, `last_slot_voted`, `slot_quarantined`)
 #[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
 pub struct Participant {
-    /// Node public key
+    /// Node consensus public key
     pub public_key: PublicKey,
     /// Node wallet address
     pub address: Address,
+    /// Proof that the consensus key acts for the wallet address
+    pub certificate: ConsensusKeyCertificate,
     /// Slot node joined the network
     pub joined: u64,
     /// Last slot node voted
@@ -24,8 +27,23 @@ pub struct Participant {
 }
 
 impl Participant {
-    pub fn new(public_key: PublicKey, address: Address, joined: u64) -> Self {
-        Self { public_key, address, joined, voted: None, quarantined: None }
+    pub fn new(certificate: ConsensusKeyCertificate, joined: u64) -> Self {
+        Self {
+            public_key: certificate.consensus_public,
+            address: certificate.address(),
+            certificate,
+            joined,
+            voted: None,
+            quarantined: None,
+        }
+    }
+
+    /// Check that the keys of the participant are the ones its certificate
+    /// ties together.
+    pub fn verify(&self) -> bool {
+        self.public_key == self.certificate.consensus_public &&
+            self.address == self.certificate.address() &&
+            self.certificate.verify()
     }
 }
 
@@ -2,6 +2,10 @@
 mod protocol_participant;
 pub use protocol_participant::ProtocolParticipant;
 
+/// Misbehaving participant removal protocol
+mod protocol_removal;
+pub use protocol_removal::ProtocolRemoval;
+
 /// Block proposal protocol
 mod protocol_proposal;
 pub use protocol_proposal::ProtocolProposal;
@@ -18,6 +22,10 @@ pub use protocol_vote::ProtocolVote;
 mod protocol_sync;
 pub use protocol_sync::ProtocolSync;
 
+/// Finalized block gossip among consensus participants
+mod protocol_sync_blocks;
+pub use protocol_sync_blocks::ProtocolSyncBlocks;
+
 /// Validator consensus sync protocol
 mod protocol_sync_consensus;
 pub use protocol_sync_consensus::ProtocolSyncConsensus;
@@ -6,15 +6,17 @@ use log::{debug, error, info, warn};
 use url::Url;
 
 use crate::{
-    consensus::{BlockProposal, ValidatorState, ValidatorStatePtr},
+    consensus::{BlockProposal, ValidatorStatePtr},
     net::{
         ChannelPtr, MessageSubscription, P2pPtr, ProtocolBase, ProtocolBasePtr,
         ProtocolJobsManager, ProtocolJobsManagerPtr,
     },
-    node::MemoryState,
     Result,
 };
 
+/// Gossip of block proposals among consensus participants, with a vote
+/// for the ones this node votes on. Valid proposals are relayed once,
+/// whether the node votes for them or not.
 pub struct ProtocolProposal {
     proposal_sub: MessageSubscription<BlockProposal>,
     jobsman: ProtocolJobsManagerPtr,
@@ -63,14 +65,27 @@ impl ProtocolProposal {
             debug!("ProtocolProposal::handle_receive_proposal(): Full proposal: {:?}", proposal);
 
             let proposal_copy = (*proposal).clone();
+            let hash = proposal_copy.block.header.headerhash();
+            if self.state.read().await.seen_proposals.contains(&hash) {
+                debug!("ProtocolProposal::handle_receive_proposal(): Already seen {}", hash);
+                continue
+            }
 
             debug!(
                 "ProtocolProposal::handle_receive_proposal(): Starting state transition validation"
             );
-            let canon_state_clone = self.state.read().await.state_machine.lock().await.clone();
-            let mem_state = MemoryState::new(canon_state_clone);
-
-            match ValidatorState::validate_state_transitions(mem_state, &proposal_copy.block.txs) {
+            let header = &proposal_copy.block.header;
+            let txs = &proposal_copy.block.txs;
+            let state = self.state.read().await.expected_state_commitment(&header.state, txs).await;
+            match state {
+                Ok(Some(state)) if state != proposal_copy.block.metadata.state => {
+                    warn!(
+                        "ProtocolProposal::handle_receive_proposal(): State commitment mismatch: proposal has {}, expected {}",
+                        proposal_copy.block.metadata.state, state
+                    );
+                    continue
+                }
+                // Proposals extending unknown blocks are dropped in receive_proposal()
                 Ok(_) => {
                     debug!("ProtocolProposal::handle_receive_proposal(): State transition valid")
                 }
@@ -83,22 +98,17 @@ impl ProtocolProposal {
                 }
             }
 
-            let vote = match self.state.write().await.receive_proposal(&proposal_copy) {
-                Ok(v) => {
-                    if v.is_none() {
-                        debug!("ProtocolProposal::handle_receive_proposal(): Node didn't vote for proposed block.");
-                        continue
-                    }
-                    v.unwrap()
-                }
+            let (valid, vote) = match self.state.write().await.receive_proposal(&proposal_copy) {
+                Ok(v) => v,
                 Err(e) => {
                     debug!("ProtocolProposal::handle_receive_proposal(): error processing proposal: {}", e);
                     continue
                 }
             };
 
-            if let Err(e) = self.state.write().await.receive_vote(&vote).await {
-                error!("ProtocolProposal::handle_receive_proposal(): receive_vote error: {}", e);
+            // Proposals are only marked seen once they check out, so an
+            // invalid copy can't get the genuine one dropped.
+            if !valid || !self.state.write().await.seen_proposals.insert(hash) {
                 continue
             }
 
@@ -110,6 +120,19 @@ impl ProtocolProposal {
                 );
             };
 
+            let vote = match vote {
+                Some(v) => v,
+                None => {
+                    debug!("ProtocolProposal::handle_receive_proposal(): Node didn't vote for proposed block.");
+                    continue
+                }
+            };
+
+            if let Err(e) = self.state.write().await.receive_vote(&vote).await {
+                error!("ProtocolProposal::handle_receive_proposal(): receive_vote error: {}", e);
+                continue
+            }
+
             // Broadcast vote
             if let Err(e) = self.p2p.broadcast(vote).await {
                 error!("ProtocolProposal::handle_receive_proposal(): vote broadcast fail: {}", e);
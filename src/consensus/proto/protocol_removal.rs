@@ -0,0 +1,89 @@
+use async_std::sync::Arc;
+
+use async_executor::Executor;
+use async_trait::async_trait;
+use log::{debug, error};
+use url::Url;
+
+use crate::{
+    consensus::{Removal, ValidatorStatePtr},
+    net::{
+        ChannelPtr, MessageSubscription, P2pPtr, ProtocolBase, ProtocolBasePtr,
+        ProtocolJobsManager, ProtocolJobsManagerPtr,
+    },
+    Result,
+};
+
+pub struct ProtocolRemoval {
+    removal_sub: MessageSubscription<Removal>,
+    jobsman: ProtocolJobsManagerPtr,
+    state: ValidatorStatePtr,
+    p2p: P2pPtr,
+    channel_address: Url,
+}
+
+impl ProtocolRemoval {
+    pub async fn init(
+        channel: ChannelPtr,
+        state: ValidatorStatePtr,
+        p2p: P2pPtr,
+    ) -> Result<ProtocolBasePtr> {
+        debug!("Adding ProtocolRemoval to the protocol registry");
+        let msg_subsystem = channel.get_message_subsystem();
+        msg_subsystem.add_dispatch::<Removal>().await;
+
+        let removal_sub = channel.subscribe_msg::<Removal>().await?;
+        let channel_address = channel.address();
+
+        Ok(Arc::new(Self {
+            removal_sub,
+            jobsman: ProtocolJobsManager::new("RemovalProtocol", channel),
+            state,
+            p2p,
+            channel_address,
+        }))
+    }
+
+    async fn handle_receive_removal(self: Arc<Self>) -> Result<()> {
+        debug!("ProtocolRemoval::handle_receive_removal() [START]");
+        let exclude_list = vec![self.channel_address.clone()];
+        loop {
+            let removal = match self.removal_sub.receive().await {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("ProtocolRemoval::handle_receive_removal(): recv error: {}", e);
+                    continue
+                }
+            };
+
+            debug!("ProtocolRemoval::handle_receive_removal() recv: {:?}", removal);
+
+            let removal_copy = (*removal).clone();
+
+            if self.state.write().await.receive_removal(&removal_copy) {
+                if let Err(e) = self.p2p.broadcast_with_exclude(removal_copy, &exclude_list).await {
+                    error!("ProtocolRemoval::handle_receive_removal(): broadcast failed: {}", e);
+                    continue
+                };
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ProtocolBase for ProtocolRemoval {
+    async fn start(self: Arc<Self>, executor: Arc<Executor<'_>>) -> Result<()> {
+        debug!("ProtocolRemoval::start() [START]");
+        self.jobsman.clone().start(executor.clone());
+        self.jobsman
+            .clone()
+            .spawn(self.clone().handle_receive_removal(), executor.clone())
+            .await;
+        debug!("ProtocolRemoval::start() [END]");
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "ProtocolRemoval"
+    }
+}
@@ -1,24 +1,20 @@
 use async_executor::Executor;
 use async_std::sync::{Arc, Mutex};
 use async_trait::async_trait;
-use log::{debug, error, info, warn};
+use log::{debug, error, info};
 
 use crate::{
     consensus::{
         block::{BlockInfo, BlockOrder, BlockResponse},
-        ValidatorState, ValidatorStatePtr,
+        ValidatorStatePtr,
     },
     net::{
         ChannelPtr, MessageSubscription, P2pPtr, ProtocolBase, ProtocolBasePtr,
         ProtocolJobsManager, ProtocolJobsManagerPtr,
     },
-    node::MemoryState,
     Result,
 };
 
-// Constant defining how many blocks we send during syncing.
-const BATCH: u64 = 10;
-
 pub struct ProtocolSync {
     channel: ChannelPtr,
     request_sub: MessageSubscription<BlockOrder>,
@@ -71,7 +67,9 @@ impl ProtocolSync {
 
             // Extra validations can be added here
             let key = order.slot;
-            let blocks = match self.state.read().await.blockchain.get_blocks_after(key, BATCH) {
+            let batch = self.state.read().await.params.sync_batch;
+            let blockchain = self.state.read().await.async_blockchain.clone();
+            let blocks = match blockchain.get_blocks_after(key, batch).await {
                 Ok(v) => v,
                 Err(e) => {
                     error!("ProtocolSync::handle_receive_request(): get_blocks_after fail: {}", e);
@@ -119,74 +117,23 @@ impl ProtocolSync {
             while *self.pending.lock().await {}
             debug!("ProtocolSync::handle_receive_block(): Pending lock released");
 
-            // Node stores finalized block, if it doesn't exist (checking by slot),
-            // and removes its transactions from the unconfirmed_txs vector.
-            // Extra validations can be added here.
+            // Node applies the finalized block if it's on top of its chain.
             *self.pending.lock().await = true;
             let info_copy = (*info).clone();
 
-            let has_block = match self.state.read().await.blockchain.has_block(&info_copy) {
+            let is_new = match self.state.write().await.receive_block(info_copy.clone()).await {
                 Ok(v) => v,
                 Err(e) => {
-                    error!(
-                        "ProtocolSync::handle_receive_block(): failed checking for has_block(): {}",
-                        e
-                    );
+                    error!("ProtocolSync::handle_receive_block(): receive_block() fail: {}", e);
                     *self.pending.lock().await = false;
                     continue
                 }
             };
 
-            if !has_block {
-                debug!(
-                    "ProtocolSync::handle_receive_block(): Starting state transition validation"
-                );
-                let canon_state_clone = self.state.read().await.state_machine.lock().await.clone();
-                let mem_state = MemoryState::new(canon_state_clone);
-                let state_updates =
-                    match ValidatorState::validate_state_transitions(mem_state, &info.txs) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            warn!(
-                                "ProtocolSync::handle_receive_block(): State transition fail: {}",
-                                e
-                            );
-                            *self.pending.lock().await = false;
-                            continue
-                        }
-                    };
-                debug!("ProtocolSync::handle_receive_block(): All state transitions passed");
-
-                debug!("ProtocolSync::handle_receive_block(): Updating canon state machine");
-                if let Err(e) =
-                    self.state.write().await.update_canon_state(state_updates, None).await
-                {
-                    error!(
-                        "ProtocolSync::handle_receive_block(): Canon statemachine update fail: {}",
-                        e
-                    );
-                    *self.pending.lock().await = false;
-                    continue
-                };
-
-                debug!("ProtocolSync::handle_receive_block(): Appending block to ledger");
-                if let Err(e) = self.state.write().await.blockchain.add(&[info_copy.clone()]) {
-                    error!("ProtocolSync::handle_receive_block(): blockchain.add() fail: {}", e);
-                    *self.pending.lock().await = false;
-                    continue
-                };
-
-                if let Err(e) = self.state.write().await.remove_txs(info_copy.txs.clone()) {
-                    error!("ProtocolSync::handle_receive_block(): remove_txs() fail: {}", e);
-                    *self.pending.lock().await = false;
-                    continue
-                };
-
+            if is_new {
                 if let Err(e) = self.p2p.broadcast_with_exclude(info_copy, &exclude_list).await {
                     error!("ProtocolSync::handle_receive_block(): p2p broadcast fail: {}", e);
-                    *self.pending.lock().await = false;
-                    continue
-                };
+                }
             }
 
             *self.pending.lock().await = false;
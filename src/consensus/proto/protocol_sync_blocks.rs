@@ -0,0 +1,115 @@
+use async_std::sync::Arc;
+
+use async_executor::Executor;
+use async_trait::async_trait;
+use log::{debug, error, info, warn};
+use url::Url;
+
+use crate::{
+    consensus::{BlockInfo, ValidatorStatePtr},
+    net::{
+        ChannelPtr, MessageSubscription, P2pPtr, ProtocolBase, ProtocolBasePtr,
+        ProtocolJobsManager, ProtocolJobsManagerPtr,
+    },
+    Result,
+};
+
+/// Gossip of finalized blocks among consensus participants. Every
+/// participant broadcasts the blocks it finalizes, so one that missed
+/// some votes still gets the blocks the others finalized. Proposed blocks
+/// are gossiped by [`ProtocolProposal`](super::ProtocolProposal).
+pub struct ProtocolSyncBlocks {
+    block_sub: MessageSubscription<BlockInfo>,
+    jobsman: ProtocolJobsManagerPtr,
+    state: ValidatorStatePtr,
+    p2p: P2pPtr,
+    channel_address: Url,
+}
+
+impl ProtocolSyncBlocks {
+    pub async fn init(
+        channel: ChannelPtr,
+        state: ValidatorStatePtr,
+        p2p: P2pPtr,
+    ) -> Result<ProtocolBasePtr> {
+        debug!("Adding ProtocolSyncBlocks to the protocol registry");
+        let msg_subsystem = channel.get_message_subsystem();
+        msg_subsystem.add_dispatch::<BlockInfo>().await;
+
+        let block_sub = channel.subscribe_msg::<BlockInfo>().await?;
+        let channel_address = channel.address();
+
+        Ok(Arc::new(Self {
+            block_sub,
+            jobsman: ProtocolJobsManager::new("SyncBlocksProtocol", channel),
+            state,
+            p2p,
+            channel_address,
+        }))
+    }
+
+    async fn handle_receive_block(self: Arc<Self>) -> Result<()> {
+        debug!("ProtocolSyncBlocks::handle_receive_block() [START]");
+        let exclude_list = vec![self.channel_address.clone()];
+        loop {
+            let info = match self.block_sub.receive().await {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("ProtocolSyncBlocks::handle_receive_block(): recv fail: {}", e);
+                    continue
+                }
+            };
+
+            let info_copy = (*info).clone();
+            let hash = info_copy.header.headerhash();
+            if self.state.read().await.seen_blocks.contains(&hash) {
+                debug!("ProtocolSyncBlocks::handle_receive_block(): Already seen block {}", hash);
+                continue
+            }
+
+            info!("ProtocolSyncBlocks::handle_receive_block(): Received block: {}", hash);
+
+            // Verification covers the transactions too, and blocks are only
+            // marked seen once they pass it, so a tampered copy can't get
+            // the genuine block dropped.
+            if !self.state.read().await.verify_block(&info_copy) {
+                warn!("ProtocolSyncBlocks::handle_receive_block(): Dropping block {}", hash);
+                continue
+            }
+
+            if !self.state.write().await.seen_blocks.insert(hash) {
+                continue
+            }
+
+            // Blocks we finalized ourselves were seen already, so this is
+            // one we missed.
+            match self.state.write().await.receive_block(info_copy.clone()).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    error!("ProtocolSyncBlocks::handle_receive_block(): receive_block(): {}", e);
+                    continue
+                }
+            }
+
+            if let Err(e) = self.p2p.broadcast_with_exclude(info_copy, &exclude_list).await {
+                error!("ProtocolSyncBlocks::handle_receive_block(): p2p broadcast fail: {}", e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ProtocolBase for ProtocolSyncBlocks {
+    async fn start(self: Arc<Self>, executor: Arc<Executor<'_>>) -> Result<()> {
+        debug!("ProtocolSyncBlocks::start() [START]");
+        self.jobsman.clone().start(executor.clone());
+        self.jobsman.clone().spawn(self.clone().handle_receive_block(), executor.clone()).await;
+        debug!("ProtocolSyncBlocks::start() [END]");
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "ProtocolSyncBlocks"
+    }
+}
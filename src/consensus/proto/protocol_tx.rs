@@ -6,7 +6,7 @@ use log::{debug, error, warn};
 use url::Url;
 
 use crate::{
-    consensus::{ValidatorState, ValidatorStatePtr},
+    consensus::{mempool::tx_hash, ValidatorState, ValidatorStatePtr},
     net,
     net::{
         ChannelPtr, MessageSubscription, P2pPtr, ProtocolBase, ProtocolBasePtr,
@@ -14,7 +14,6 @@ use crate::{
     },
     node::MemoryState,
     tx::Transaction,
-    util::serial::serialize,
     Result,
 };
 
@@ -67,22 +66,32 @@ impl ProtocolTx {
             };
 
             let tx_copy = (*tx).clone();
-            let tx_hash = blake3::hash(&serialize(&tx_copy));
-
-            let tx_in_txstore =
-                match self.state.read().await.blockchain.transactions.contains(&tx_hash) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!("handle_receive_tx(): Failed querying txstore: {}", e);
-                        continue
-                    }
-                };
+            let tx_hash = tx_hash(&tx_copy);
 
-            if self.state.read().await.unconfirmed_txs.contains(&tx_copy) || tx_in_txstore {
+            if self.state.read().await.mempool.has_seen(&tx_hash) {
                 debug!("ProtocolTx::handle_receive_tx(): We have already seen this tx.");
                 continue
             }
 
+            let blockchain = self.state.read().await.async_blockchain.clone();
+            let tx_in_txstore = match blockchain.contains_txs(vec![tx_hash]).await {
+                Ok(v) => v[0],
+                Err(e) => {
+                    error!("handle_receive_tx(): Failed querying txstore: {}", e);
+                    continue
+                }
+            };
+
+            if tx_in_txstore {
+                debug!("ProtocolTx::handle_receive_tx(): Tx is already confirmed.");
+                continue
+            }
+
+            if let Err(e) = self.state.read().await.filter_tx(&tx_copy) {
+                warn!("ProtocolTx::handle_receive_tx(): Dropping tx: {}", e);
+                continue
+            }
+
             debug!("ProtocolTx::handle_receive_tx(): Starting state transition validation");
             let canon_state_clone = self.state.read().await.state_machine.lock().await.clone();
             let mem_state = MemoryState::new(canon_state_clone);
@@ -94,7 +103,12 @@ impl ProtocolTx {
                 }
             }
 
-            // Nodes use unconfirmed_txs vector as seen_txs pool.
+            // Another peer may have relayed it while we were validating
+            if !self.state.write().await.mempool.see(tx_hash) {
+                debug!("ProtocolTx::handle_receive_tx(): We have already seen this tx.");
+                continue
+            }
+
             if self.state.write().await.append_tx(tx_copy.clone()) {
                 if let Err(e) = self.p2p.broadcast_with_exclude(tx_copy, &exclude_list).await {
                     error!("handle_receive_tx(): p2p broadcast fail: {}", e);
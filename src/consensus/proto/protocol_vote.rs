@@ -84,6 +84,10 @@ impl ProtocolVote {
                 if let Some(blocks) = to_broadcast {
                     debug!("handle_receive_vote(): Broadcasting finalized blocks");
                     for info in blocks {
+                        if let Err(e) = self.consensus_p2p.broadcast(info.clone()).await {
+                            error!("handle_receive_vote(): consensus p2p broadcast fail: {}", e);
+                        }
+
                         if let Err(e) = self.sync_p2p.broadcast(info).await {
                             error!("handle_receive_vote(): sync p2p broadcast fail: {}", e);
                             // TODO: Should we quit broadcasting if one fails?
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use crate::util::time::Timestamp;
+
+/// Seconds a message is remembered after it was first seen
+pub const SEEN_TTL: i64 = 600;
+
+/// Hashes of the messages a gossip protocol already handled, so they are
+/// neither processed nor relayed twice. Entries expire after [`SEEN_TTL`]
+/// seconds, by then the message stopped going around the network.
+#[derive(Debug, Default)]
+pub struct Seen {
+    hashes: HashMap<blake3::Hash, i64>,
+    pruned: i64,
+}
+
+impl Seen {
+    /// Record the hash as seen now. Returns false if it already was.
+    pub fn insert(&mut self, hash: blake3::Hash) -> bool {
+        let now = Timestamp::current_time().0;
+        if now - self.pruned >= SEEN_TTL {
+            self.prune(now);
+        }
+
+        if self.hashes.contains_key(&hash) {
+            return false
+        }
+
+        self.hashes.insert(hash, now);
+        true
+    }
+
+    pub fn contains(&self, hash: &blake3::Hash) -> bool {
+        self.hashes.contains_key(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Forget the hashes seen more than [`SEEN_TTL`] seconds before `now`.
+    pub fn prune(&mut self, now: i64) {
+        self.hashes.retain(|_, seen| now - *seen < SEEN_TTL);
+        self.pruned = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seen_messages() {
+        let mut seen = Seen::default();
+        let (first, second) = (blake3::hash(b"first"), blake3::hash(b"second"));
+
+        assert!(seen.insert(first));
+        assert!(!seen.insert(first));
+        assert!(seen.contains(&first));
+        assert!(!seen.contains(&second));
+
+        let now = Timestamp::current_time().0;
+        seen.hashes.insert(second, now - SEEN_TTL);
+        seen.prune(now);
+        assert!(seen.contains(&first));
+        assert!(!seen.contains(&second));
+        assert_eq!(seen.len(), 1);
+    }
+}
@@ -0,0 +1,193 @@
+use std::io;
+
+use super::{ConsensusKey, Participant, Vote};
+use crate::{
+    crypto::{
+        address::Address,
+        keypair::PublicKey,
+        schnorr::{SchnorrPublic, Signature},
+    },
+    net,
+    util::serial::{serialize, Decodable, Encodable, SerialDecodable, SerialEncodable},
+    Error, Result,
+};
+
+/// Prefix of the message a participant signs to report another one,
+/// so that the signature can't be passed off as one over anything else.
+const REMOVAL_DOMAIN: &[u8] = b"DarkFi:ParticipantRemoval";
+
+/// Why a participant gets removed from the consensus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Misbehavior {
+    /// Stayed in quarantine longer than the quarantine duration.
+    /// Each node judges this on its own, so it comes without evidence.
+    Inactivity,
+    /// Voted on two different proposals of the same slot. Both votes
+    /// are signed by the participant, so anyone can check it.
+    DoubleVote(Vote, Vote),
+}
+
+impl Misbehavior {
+    /// Check that the evidence, if any, incriminates the given participant
+    pub fn verify(&self, offender: &Participant) -> bool {
+        match self {
+            Self::Inactivity => true,
+            Self::DoubleVote(first, second) => {
+                first.address == offender.address &&
+                    second.address == offender.address &&
+                    first.slot == second.slot &&
+                    first.proposal != second.proposal &&
+                    first.verify(&offender.public_key) &&
+                    second.verify(&offender.public_key)
+            }
+        }
+    }
+}
+
+impl Encodable for Misbehavior {
+    fn encode<S: io::Write>(&self, mut s: S) -> Result<usize> {
+        match self {
+            Self::Inactivity => 0u8.encode(s),
+            Self::DoubleVote(first, second) => {
+                let mut len = 1u8.encode(&mut s)?;
+                len += first.encode(&mut s)?;
+                len += second.encode(&mut s)?;
+                Ok(len)
+            }
+        }
+    }
+}
+
+impl Decodable for Misbehavior {
+    fn decode<D: io::Read>(mut d: D) -> Result<Self> {
+        let kind: u8 = Decodable::decode(&mut d)?;
+        match kind {
+            0 => Ok(Self::Inactivity),
+            1 => Ok(Self::DoubleVote(Decodable::decode(&mut d)?, Decodable::decode(&mut d)?)),
+            _ => Err(Error::DecodeError("Unknown misbehavior kind")),
+        }
+    }
+}
+
+/// Report of a misbehaving participant, signed by the consensus key of
+/// the participant reporting it. Nodes drop the offender from their
+/// participants list once they checked it, and relay it to their peers.
+#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct Removal {
+    /// Wallet address of the removed participant
+    pub address: Address,
+    /// What the participant did
+    pub misbehavior: Misbehavior,
+    /// Slot the misbehavior was detected
+    pub slot: u64,
+    /// Wallet address of the reporting participant
+    pub reporter: Address,
+    /// Signature of the reporter's consensus key
+    pub signature: Signature,
+}
+
+impl Removal {
+    pub fn new(
+        address: Address,
+        misbehavior: Misbehavior,
+        slot: u64,
+        reporter: Address,
+        consensus_key: &ConsensusKey,
+    ) -> Self {
+        let signature = consensus_key.sign(&Self::message(&address, &misbehavior, slot, &reporter));
+        Self { address, misbehavior, slot, reporter, signature }
+    }
+
+    fn message(
+        address: &Address,
+        misbehavior: &Misbehavior,
+        slot: u64,
+        reporter: &Address,
+    ) -> Vec<u8> {
+        let mut message = REMOVAL_DOMAIN.to_vec();
+        message.extend(serialize(address));
+        message.extend(serialize(misbehavior));
+        message.extend(serialize(&slot));
+        message.extend(serialize(reporter));
+        message
+    }
+
+    /// Check the signature against the reporter's consensus public key
+    pub fn verify(&self, reporter_public: &PublicKey) -> bool {
+        let message = Self::message(&self.address, &self.misbehavior, self.slot, &self.reporter);
+        reporter_public.verify(&message, &self.signature)
+    }
+}
+
+impl net::Message for Removal {
+    fn name() -> &'static str {
+        "removal"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        consensus::ConsensusKeyCertificate, crypto::keypair::Keypair, util::serial::deserialize,
+    };
+
+    fn participant() -> (Participant, ConsensusKey) {
+        let key = ConsensusKey::random();
+        let certificate = ConsensusKeyCertificate::new(key.public(), &Keypair::random(&mut OsRng));
+        (Participant::new(certificate, 0), key)
+    }
+
+    fn vote(participant: &Participant, key: &ConsensusKey, data: &[u8], slot: u64) -> Vote {
+        let proposal = blake3::hash(data);
+        Vote::new(key.sign(&Vote::message(&proposal, slot)), proposal, slot, participant.address)
+    }
+
+    #[test]
+    fn double_vote_evidence() {
+        let (offender, key) = participant();
+        let first = vote(&offender, &key, b"first", 5);
+        let second = vote(&offender, &key, b"second", 5);
+        assert!(Misbehavior::DoubleVote(first.clone(), second.clone()).verify(&offender));
+
+        // Voting twice on the same proposal, or in different slots, is fine
+        assert!(!Misbehavior::DoubleVote(first.clone(), first.clone()).verify(&offender));
+        let later = vote(&offender, &key, b"second", 6);
+        assert!(!Misbehavior::DoubleVote(first.clone(), later.clone()).verify(&offender));
+
+        // The slot is signed, so votes of other slots can't be relabeled
+        let relabeled = Vote { slot: 5, ..later };
+        assert!(!Misbehavior::DoubleVote(first.clone(), relabeled).verify(&offender));
+
+        // Votes must be signed by the offender
+        let (other, other_key) = participant();
+        let forged = Vote { address: offender.address, ..vote(&other, &other_key, b"third", 5) };
+        assert!(!Misbehavior::DoubleVote(first.clone(), forged).verify(&offender));
+        assert!(!Misbehavior::DoubleVote(first, second).verify(&other));
+    }
+
+    #[test]
+    fn signed_removal() {
+        let (offender, key) = participant();
+        let (reporter, reporter_key) = participant();
+        let misbehavior = Misbehavior::DoubleVote(
+            vote(&offender, &key, b"first", 5),
+            vote(&offender, &key, b"second", 5),
+        );
+
+        let removal =
+            Removal::new(offender.address, misbehavior, 6, reporter.address, &reporter_key);
+        assert!(removal.verify(&reporter.public_key));
+        assert!(!removal.verify(&offender.public_key));
+
+        let decoded: Removal = deserialize(&serialize(&removal)).unwrap();
+        assert_eq!(decoded, removal);
+
+        let forged = Removal { misbehavior: Misbehavior::Inactivity, ..removal.clone() };
+        assert!(!forged.verify(&reporter.public_key));
+        let forged = Removal { slot: 7, ..removal };
+        assert!(!forged.verify(&reporter.public_key));
+    }
+}
@@ -1,49 +1,90 @@
 // TODO: Use sets instead of vectors where possible.
-use std::{
-    collections::{hash_map::DefaultHasher, BTreeMap},
-    hash::{Hash, Hasher},
-    time::Duration,
-};
+use std::{collections::BTreeMap, time::Duration};
 
 use async_std::sync::{Arc, Mutex, RwLock};
 use chrono::{NaiveDateTime, Utc};
 use incrementalmerkletree::{bridgetree::BridgeTree, Tree};
-use lazy_init::Lazy;
 use log::{debug, error, info, warn};
-use rand::rngs::OsRng;
 
 use super::{
-    Block, BlockInfo, BlockProposal, Header, Metadata, Participant, ProposalChain,
-    StreamletMetadata, Vote,
+    fork::prefer_fork, Block, BlockInfo, BlockProposal, ChainParams, ConsensusKey,
+    ConsensusKeyCertificate, Header, Mempool, Metadata, Misbehavior, Participant, ProposalChain,
+    Removal, Seen, StreamletMetadata, TxFilter, Vote,
 };
 use crate::{
-    blockchain::Blockchain,
+    blockchain::{nfstore::add_to_digest, AsyncBlockchain, Blockchain, DbPool, DB_POOL_THREADS},
     crypto::{
         address::Address,
         constants::MERKLE_DEPTH,
         keypair::{PublicKey, SecretKey},
         merkle_node::MerkleNode,
-        schnorr::{SchnorrPublic, SchnorrSecret},
+        schnorr::SchnorrPublic,
+        VrfProof,
     },
     net,
     node::{
-        state::{state_transition, ProgramState, StateUpdate},
-        Client, MemoryState, State,
+        contract::{
+            self,
+            money::{transfer_call, MONEY_CONTRACT_ID},
+            StateRegistry,
+        },
+        state::{StateEvent, StateUpdate},
+        vk_registry::{BURN_CIRCUIT_ID, MINT_CIRCUIT_ID},
+        Client, MemoryState, State, VerifyingKeyRegistry,
     },
+    system::{Subscriber, SubscriberPtr},
     tx::Transaction,
     util::{
-        serial::{serialize, Encodable, SerialDecodable, SerialEncodable},
+        clock::{ClockDrift, ClockDriftPtr, DRIFT_MAX_SECONDS, DRIFT_WARN_SECONDS},
+        serial::{serialize, SerialDecodable, SerialEncodable},
         time::Timestamp,
     },
-    Result,
+    Error, Result, VerifyResult,
 };
 
-/// `2 * DELTA` represents slot time
-pub const DELTA: u64 = 20;
-/// Slots in an epoch
-pub const EPOCH_SLOTS: u64 = 10;
-/// Quarantine duration, in slots
-pub const QUARANTINE_DURATION: u64 = 5;
+/// Commitment to a ledger state, published in block [`Metadata`]: the hash
+/// of the Merkle tree root and the digest of the nullifier set, as returned
+/// by [`NullifierStore::digest`](crate::blockchain::nfstore::NullifierStore::digest).
+pub fn state_commitment(root: &MerkleNode, nullifier_digest: &[u8; 32]) -> blake3::Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"darkfi:state_commitment");
+    hasher.update(&root.to_bytes());
+    hasher.update(nullifier_digest);
+    hasher.finalize()
+}
+
+/// Leader election inputs of an epoch, read from the canonical chain so
+/// that all nodes agree on them
+pub struct EpochSnapshot {
+    /// Randomness the slot leaders are elected with
+    pub seed: blake3::Hash,
+    /// Participants holding stake, one unit each until staking exists
+    pub stakeholders: Vec<Address>,
+}
+
+impl EpochSnapshot {
+    /// Stake of the given participant, and the total stake. Until a
+    /// canonical block records its participants, everyone holds it all.
+    pub fn stake(&self, address: &Address) -> (u64, u64) {
+        if self.stakeholders.is_empty() {
+            return (1, 1)
+        }
+
+        (self.stakeholders.contains(address) as u64, self.stakeholders.len() as u64)
+    }
+}
+
+/// Compute the commitment to the canonical state with the changes of
+/// `mem_state` applied on top.
+fn memory_state_commitment(mem_state: &MemoryState) -> Result<blake3::Hash> {
+    let mut digest = mem_state.canon.nullifiers.digest()?;
+    for nullifier in &mem_state.nullifiers {
+        add_to_digest(&mut digest, nullifier);
+    }
+
+    let root = mem_state.tree.root(0).ok_or(Error::MerkleTreeNoRoot)?;
+    Ok(state_commitment(&root, &digest))
+}
 
 /// This struct represents the information required by the consensus algorithm
 #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
@@ -63,6 +104,8 @@ pub struct ConsensusState {
     pub pending_participants: Vec<Participant>,
     /// Last slot participants where refreshed
     pub refreshed: u64,
+    /// Validators removed for double voting, that can't participate again
+    pub slashed: Vec<Address>,
 }
 
 impl ConsensusState {
@@ -78,6 +121,7 @@ impl ConsensusState {
             participants: BTreeMap::new(),
             pending_participants: vec![],
             refreshed: 0,
+            slashed: vec![],
         })
     }
 }
@@ -113,67 +157,116 @@ pub type ValidatorStatePtr = Arc<RwLock<ValidatorState>>;
 
 /// This struct represents the state of a validator node.
 pub struct ValidatorState {
+    /// Chain parameters
+    pub params: ChainParams,
     /// Node wallet address
     pub address: Address,
-    /// Secret key, to sign messages
-    pub secret: SecretKey,
-    /// Node public key
-    pub public: PublicKey,
+    /// Key signing proposals and votes
+    pub consensus_key: ConsensusKey,
+    /// Wallet key signature over the consensus key
+    pub certificate: ConsensusKeyCertificate,
     /// Hot/Live data used by the consensus algorithm
     pub consensus: ConsensusState,
     /// Canonical (finalized) blockchain
     pub blockchain: Blockchain,
+    /// The same blockchain, with its reads and writes run on a database
+    /// thread pool. Use it from tasks that shouldn't block the executor.
+    pub async_blockchain: AsyncBlockchain,
     /// Canonical state machine
     pub state_machine: Arc<Mutex<State>>,
     /// Client providing wallet access
     pub client: Arc<Client>,
-    /// Pending transactions
-    pub unconfirmed_txs: Vec<Transaction>,
+    /// Pending transactions, proposed when the node leads a slot
+    pub mempool: Mempool,
+    /// Filters run on transactions received from the network
+    pub tx_filters: Vec<TxFilter>,
     /// Participating start slot
     pub participating: Option<u64>,
+    /// Signed removals of misbehaving participants, to be broadcasted
+    pub removals: Vec<Removal>,
+    /// Proposals gossiped on the consensus network
+    pub seen_proposals: Seen,
+    /// Finalized blocks gossiped on the consensus network
+    pub seen_blocks: Seen,
+    /// Events published while applying blocks to the canonical state
+    pub events: SubscriberPtr<StateEvent>,
+    /// Drift of the system clock from the peers', checked before proposing
+    pub clock_drift: ClockDriftPtr,
 }
 
 impl ValidatorState {
     pub async fn new(
         db: &sled::Db, // <-- TODO: Avoid this with some wrapping, sled should only be in blockchain
-        genesis_ts: Timestamp,
-        genesis_data: blake3::Hash,
+        params: ChainParams,
         client: Arc<Client>,
+        consensus_key: ConsensusKey,
         cashier_pubkeys: Vec<PublicKey>,
         faucet_pubkeys: Vec<PublicKey>,
     ) -> Result<ValidatorStatePtr> {
-        let secret = SecretKey::random(&mut OsRng);
-        let public = PublicKey::from_secret(secret);
-        let consensus = ConsensusState::new(genesis_ts, genesis_data)?;
-        let blockchain = Blockchain::new(db, genesis_ts, genesis_data)?;
-        let unconfirmed_txs = vec![];
+        let consensus = ConsensusState::new(params.genesis_ts, params.genesis_data)?;
+        let blockchain = Blockchain::new(db, params.genesis_ts, params.genesis_data)?;
         let participating = None;
 
-        let address = client.wallet.get_default_address().await?;
+        // The wallet key vouches for the consensus key, but is never used
+        // to sign proposals or votes itself.
+        let wallet_keypair = client.wallet().await.get_default_keypair().await?;
+        let certificate = ConsensusKeyCertificate::new(consensus_key.public(), &wallet_keypair);
+        let address = certificate.address();
+
+        // Restore the Merkle tree checkpoint if it matches our last block,
+        // otherwise fall back to the tree kept in the wallet.
+        let last = blockchain.last()?;
+        let tree = match blockchain.merkle_tree.get()? {
+            Some((slot, hash, tree)) if (slot, hash) == last => {
+                info!("Restored Merkle tree checkpoint at slot {}", slot);
+                tree
+            }
+            Some((slot, _, _)) => {
+                warn!(
+                    "Merkle tree checkpoint at slot {} doesn't match last block at slot {}, \
+                     loading tree from wallet",
+                    slot, last.0
+                );
+                client.get_tree().await?
+            }
+            None => client.get_tree().await?,
+        };
+
         let state_machine = Arc::new(Mutex::new(State {
-            tree: client.get_tree().await?,
+            tree,
             merkle_roots: blockchain.merkle_roots.clone(),
             nullifiers: blockchain.nullifiers.clone(),
             cashier_pubkeys,
             faucet_pubkeys,
-            mint_vk: Lazy::new(),
-            burn_vk: Lazy::new(),
+            verifying_keys: VerifyingKeyRegistry::new(),
         }));
 
         // Create zk proof verification keys
-        let _ = state_machine.lock().await.mint_vk();
-        let _ = state_machine.lock().await.burn_vk();
+        let verifying_keys = state_machine.lock().await.verifying_keys.clone();
+        let _ = verifying_keys.get(MINT_CIRCUIT_ID);
+        let _ = verifying_keys.get(BURN_CIRCUIT_ID);
+
+        let async_blockchain =
+            AsyncBlockchain::new(blockchain.clone(), DbPool::new(DB_POOL_THREADS)?);
 
         let state = Arc::new(RwLock::new(ValidatorState {
+            params,
             address,
-            secret,
-            public,
+            consensus_key,
+            certificate,
             consensus,
+            async_blockchain,
             blockchain,
             state_machine,
             client,
-            unconfirmed_txs,
+            mempool: Mempool::default(),
+            tx_filters: vec![],
             participating,
+            removals: vec![],
+            seen_proposals: Seen::default(),
+            seen_blocks: Seen::default(),
+            events: Subscriber::new(),
+            clock_drift: ClockDrift::new(DRIFT_WARN_SECONDS, DRIFT_MAX_SECONDS),
         }));
 
         Ok(state)
@@ -183,26 +276,33 @@ impl ValidatorState {
     /// transactions list. Additional validity rules must be defined by the
     /// protocol for transactions.
     pub fn append_tx(&mut self, tx: Transaction) -> bool {
-        if self.unconfirmed_txs.contains(&tx) {
-            debug!("append_tx(): We already have this tx");
+        if !self.mempool.insert(tx) {
             return false
         }
 
         debug!("append_tx(): Appended tx to mempool");
-        self.unconfirmed_txs.push(tx);
         true
     }
 
+    /// Run the configured filters on a transaction, returning the first
+    /// rejection.
+    pub fn filter_tx(&self, tx: &Transaction) -> VerifyResult<()> {
+        for filter in &self.tx_filters {
+            filter(tx)?;
+        }
+        Ok(())
+    }
+
     /// Calculates the epoch of the provided slot.
-    /// Epoch duration is configured using the `EPOCH_SLOTS` value.
+    /// Epoch duration is configured using the `epoch_slots` parameter.
     pub fn slot_epoch(&self, slot: u64) -> u64 {
-        slot / EPOCH_SLOTS
+        slot / self.params.epoch_slots
     }
 
     /// Calculates current slot, based on elapsed time from the genesis block.
-    /// Slot duration is configured using the `DELTA` value.
+    /// Slot duration is configured using the `delta` parameter.
     pub fn current_slot(&self) -> u64 {
-        self.consensus.genesis_ts.elapsed() / (2 * DELTA)
+        self.consensus.genesis_ts.elapsed() / self.params.slot_time()
     }
 
     /// Finds the last slot a proposal or block was generated.
@@ -231,7 +331,8 @@ impl ValidatorState {
     pub fn next_slot_start(&self) -> Duration {
         let start_time = NaiveDateTime::from_timestamp(self.consensus.genesis_ts.0, 0);
         let current_slot = self.current_slot() + 1;
-        let next_slot_start = (current_slot * (2 * DELTA)) + (start_time.timestamp() as u64);
+        let next_slot_start =
+            (current_slot * self.params.slot_time()) + (start_time.timestamp() as u64);
         let next_slot_start = NaiveDateTime::from_timestamp(next_slot_start as i64, 0);
         let current_time = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
         let diff = next_slot_start - current_time;
@@ -245,53 +346,132 @@ impl ValidatorState {
         Ok(())
     }
 
-    /// Find slot leader, using a simple hash method.
-    /// Leader calculation is based on how many nodes are participating
-    /// in the network.
-    pub fn slot_leader(&mut self) -> Participant {
-        let slot = self.current_slot();
-        // DefaultHasher is used to hash the slot number
-        // because it produces a number string which then can be modulated by the len.
-        // blake3 produces alphanumeric
-        let mut hasher = DefaultHasher::new();
-        slot.hash(&mut hasher);
-        let pos = hasher.finish() % (self.consensus.participants.len() as u64);
-        // Since BTreeMap orders by key in asceding order, each node will have
-        // the same key in calculated position.
-        self.consensus.participants.iter().nth(pos as usize).unwrap().1.clone()
+    /// Participants that can lead the given slot. Participants quarantined
+    /// before it are skipped, unless nobody else is left.
+    pub fn eligible_leaders(&self, slot: u64) -> Vec<&Participant> {
+        let eligible: Vec<&Participant> = self
+            .consensus
+            .participants
+            .values()
+            .filter(|p| p.quarantined.map_or(true, |quarantined| quarantined >= slot))
+            .collect();
+        if eligible.is_empty() {
+            return self.consensus.participants.values().collect()
+        }
+
+        eligible
+    }
+
+    /// Leader election inputs of an epoch, for a block extending `parent`.
+    /// The seed hashes the VRF outputs of the canonical blocks in the
+    /// first two thirds of the previous epoch, and the stakeholders are the
+    /// participants of the last canonical block before them. The last
+    /// third is left out so the window is finalized before the epoch
+    /// starts. `None` while blocks of the window can still be finalized,
+    /// unless `parent` is the canonical tip, which rules them out for the
+    /// block.
+    pub fn epoch_snapshot(
+        &self,
+        epoch: u64,
+        parent: &blake3::Hash,
+    ) -> Result<Option<EpochSnapshot>> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"darkfi:epoch_seed");
+        hasher.update(self.consensus.genesis_block.as_bytes());
+        hasher.update(&serialize(&epoch));
+
+        if epoch == 0 {
+            return Ok(Some(EpochSnapshot { seed: hasher.finalize(), stakeholders: vec![] }))
+        }
+
+        let start = (epoch - 1) * self.params.epoch_slots;
+        let end = start + 2 * self.params.epoch_slots / 3;
+        let (last_slot, last_hash) = self.blockchain.last()?;
+        if last_slot < end && *parent != last_hash {
+            return Ok(None)
+        }
+
+        let slots: Vec<u64> = (start..end).collect();
+        for block in self.blockchain.get_blocks_by_slot(&slots)? {
+            hasher.update(block.metadata.rand_seed.as_bytes());
+        }
+
+        // The genesis block is at slot 0, so there's always one before `end`
+        let stakeholders = match self.blockchain.last_before(end.max(1))? {
+            Some((_, hash)) => {
+                let block = &self.blockchain.get_blocks_by_hash(&[hash])?[0];
+                block.sm.participants.iter().map(|p| p.address).collect()
+            }
+            None => vec![],
+        };
+
+        Ok(Some(EpochSnapshot { seed: hasher.finalize(), stakeholders }))
     }
 
-    /// Check if we're the current slot leader
-    pub fn is_slot_leader(&mut self) -> bool {
-        let address = self.address;
-        address == self.slot_leader().address
+    /// Evaluate the VRF for the slot, returning the proof if it elects us
+    /// as one of the slot leaders of the chain we'd extend.
+    pub fn leader_proof(&self, slot: u64) -> Result<Option<VrfProof>> {
+        if !self.eligible_leaders(slot).iter().any(|p| p.address == self.address) {
+            return Ok(None)
+        }
+
+        let (parent, _) = self.longest_notarized_chain_last_hash()?;
+        let snapshot = match self.epoch_snapshot(self.slot_epoch(slot), &parent)? {
+            Some(v) => v,
+            None => {
+                debug!("leader_proof(): Previous epoch isn't finalized enough to lead {}", slot);
+                return Ok(None)
+            }
+        };
+
+        let proof = Metadata::prove_leader(&self.consensus_key.0.secret, &snapshot.seed, slot);
+        let (stake, total_stake) = snapshot.stake(&self.address);
+        if !Metadata::is_elected(&proof.output(), stake, total_stake) {
+            return Ok(None)
+        }
+
+        Ok(Some(proof))
+    }
+
+    /// Check if we're one of the current slot leaders
+    pub fn is_slot_leader(&self) -> bool {
+        match self.leader_proof(self.current_slot()) {
+            Ok(proof) => proof.is_some(),
+            Err(e) => {
+                error!("is_slot_leader(): Failed evaluating the leader VRF: {}", e);
+                false
+            }
+        }
     }
 
     /// Generate a block proposal for the current slot, containing all
     /// unconfirmed transactions. Proposal extends the longest notarized fork
     /// chain the node is holding.
-    pub fn propose(&self) -> Result<Option<BlockProposal>> {
+    pub async fn propose(&self) -> Result<Option<BlockProposal>> {
         let slot = self.current_slot();
-        let (prev_hash, index) = self.longest_notarized_chain_last_hash().unwrap();
+        let proof = match self.leader_proof(slot)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let (prev_hash, index) = self.longest_notarized_chain_last_hash()?;
         let unproposed_txs = self.unproposed_txs(index);
 
-        let mut tree = BridgeTree::<MerkleNode, MERKLE_DEPTH>::new(100);
-        for tx in &unproposed_txs {
-            for output in &tx.outputs {
-                tree.append(&MerkleNode::from_coin(&output.revealed.coin));
-                tree.witness();
-            }
-        }
-        let root = tree.root(0).unwrap();
+        let root = Header::txs_root(&unproposed_txs)?;
 
         let header =
             Header::new(prev_hash, self.slot_epoch(slot), slot, Timestamp::current_time(), root);
 
-        let metadata = Metadata::new(String::from("proof"), String::from("r"), String::from("s"));
+        let state = match self.expected_state_commitment(&prev_hash, &unproposed_txs).await? {
+            Some(v) => v,
+            None => return Err(Error::HeaderNotFound(prev_hash.to_hex().to_string())),
+        };
+
+        let metadata = Metadata::new(proof, String::from("s"), state);
 
         let sm = StreamletMetadata::new(self.consensus.participants.values().cloned().collect());
 
-        let signed_proposal = self.secret.sign(&header.headerhash().as_bytes()[..]);
+        let signed_proposal = self.consensus_key.sign(&header.headerhash().as_bytes()[..]);
 
         Ok(Some(BlockProposal::new(
             signed_proposal,
@@ -306,11 +486,12 @@ impl ValidatorState {
     /// Retrieve all unconfirmed transactions not proposed in previous blocks
     /// of provided index chain.
     pub fn unproposed_txs(&self, index: i64) -> Vec<Transaction> {
-        let mut unproposed_txs = self.unconfirmed_txs.clone();
+        let mut unproposed_txs = self.mempool.txs().to_vec();
 
         // If index is -1 (canonical blockchain) a new fork will be generated,
         // therefore all unproposed transactions can be included in the proposal.
         if index == -1 {
+            unproposed_txs.truncate(self.params.max_block_txs);
             return unproposed_txs
         }
 
@@ -325,23 +506,33 @@ impl ValidatorState {
             }
         }
 
+        unproposed_txs.truncate(self.params.max_block_txs);
         unproposed_txs
     }
 
-    /// Finds the longest fully notarized blockchain the node holds and
-    /// returns the last block hash and the chain index.
+    /// Finds the fully notarized fork chain the fork choice rule prefers
+    /// among the ones the node holds, and returns the last block hash and
+    /// the chain index. The index is -1 for the canonical blockchain.
     pub fn longest_notarized_chain_last_hash(&self) -> Result<(blake3::Hash, i64)> {
         let mut longest_notarized_chain: Option<ProposalChain> = None;
-        let mut length = 0;
+        let mut slots = vec![];
         let mut index = -1;
 
-        if !self.consensus.proposals.is_empty() {
-            for (i, chain) in self.consensus.proposals.iter().enumerate() {
-                if chain.notarized() && chain.proposals.len() > length {
-                    longest_notarized_chain = Some(chain.clone());
-                    length = chain.proposals.len();
-                    index = i as i64;
-                }
+        // All fork chains branch off the last canonical block, and are
+        // compared with the fork choice rule.
+        let (fork_slot, _) = self.blockchain.last()?;
+        let (depth, window) = (self.params.fork_depth, self.params.density_window);
+        for (i, chain) in self.consensus.proposals.iter().enumerate() {
+            if !chain.notarized() {
+                continue
+            }
+
+            let chain_slots: Vec<u64> =
+                chain.proposals.iter().map(|p| p.block.header.slot).collect();
+            if prefer_fork(fork_slot, &slots, &chain_slots, depth, window) {
+                longest_notarized_chain = Some(chain.clone());
+                slots = chain_slots;
+                index = i as i64;
             }
         }
 
@@ -353,41 +544,162 @@ impl ValidatorState {
         Ok((hash, index))
     }
 
-    /// Receive the proposed block, verify its sender (slot leader),
-    /// and proceed with voting on it.
-    pub fn receive_proposal(&mut self, proposal: &BlockProposal) -> Result<Option<Vote>> {
+    /// Compute the commitment to the state reached by applying `txs` on top
+    /// of the block with hash `prev_hash`. That block is either the last
+    /// canonical block, or a proposal in one of the fork chains, in which
+    /// case the transactions of the proposals leading to it are applied
+    /// first. Returns `None` if the block isn't known, and an error if the
+    /// transactions don't apply.
+    pub async fn expected_state_commitment(
+        &self,
+        prev_hash: &blake3::Hash,
+        txs: &[Transaction],
+    ) -> Result<Option<blake3::Hash>> {
+        let mut all_txs = match self.fork_txs(prev_hash)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        all_txs.extend_from_slice(txs);
+
+        let canon_state_clone = self.state_machine.lock().await.clone();
+        let mut mem_state = MemoryState::new(canon_state_clone);
+        let state_updates =
+            ValidatorState::validate_state_transitions(mem_state.clone(), &all_txs)?;
+        for update in state_updates {
+            mem_state.apply(update)?;
+        }
+
+        Ok(Some(memory_state_commitment(&mem_state)?))
+    }
+
+    /// Transactions of the fork chain proposals up to and including the one
+    /// with the given hash, or none if it is the last canonical block.
+    fn fork_txs(&self, hash: &blake3::Hash) -> Result<Option<Vec<Transaction>>> {
+        if *hash == self.blockchain.last()?.1 {
+            return Ok(Some(vec![]))
+        }
+
+        for chain in &self.consensus.proposals {
+            let pos = chain.proposals.iter().position(|p| p.block.header.headerhash() == *hash);
+            if let Some(pos) = pos {
+                let txs = chain.proposals[..=pos].iter().flat_map(|p| p.block.txs.clone());
+                return Ok(Some(txs.collect()))
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Check the state reached by applying `block`, held in `mem_state`,
+    /// against the commitment its proposer published, before anything is
+    /// written to the canonical state. A mismatch means the block would
+    /// take our state somewhere its proposer says it doesn't go, so the
+    /// block is rejected.
+    pub fn check_state_commitment(block: &BlockInfo, mem_state: &MemoryState) -> Result<()> {
+        // Nothing is applied before genesis
+        if block.header.slot == 0 {
+            return Ok(())
+        }
+
+        let state = memory_state_commitment(mem_state)?;
+        if state != block.metadata.state {
+            error!(
+                "State diverged at slot {}: block commits to {}, our state is {}",
+                block.header.slot, block.metadata.state, state
+            );
+            return Err(Error::StateCommitmentMismatch(block.header.slot))
+        }
+
+        Ok(())
+    }
+
+    /// Receive the proposed block, verify its sender (slot leader), and
+    /// proceed with voting on it once the node participates. Returns
+    /// whether the proposal is valid, so it can be relayed, and our vote.
+    pub fn receive_proposal(&mut self, proposal: &BlockProposal) -> Result<(bool, Option<Vote>)> {
+        // Node refreshes participants records
+        self.refresh_participants()?;
+
+        if !self.verify_proposal(proposal)? {
+            return Ok((false, None))
+        }
+
         // Node hasn't started participating
         match self.participating {
             Some(start) => {
                 if self.current_slot() < start {
-                    return Ok(None)
+                    return Ok((true, None))
                 }
             }
-            None => return Ok(None),
+            None => return Ok((true, None)),
         }
 
-        // Node refreshes participants records
-        self.refresh_participants()?;
+        Ok((true, self.vote(proposal)?))
+    }
 
-        let leader = self.slot_leader();
-        if leader.address != proposal.address {
-            warn!(
-                "Received proposal not from slot leader ({}), but from ({})",
-                leader.address.to_string(),
-                proposal.address.to_string()
-            );
-            return Ok(None)
-        }
+    /// Check that the proposal was signed by a participant that can lead
+    /// its slot, with a valid leader proof, and holds the transactions its
+    /// header commits to.
+    pub fn verify_proposal(&self, proposal: &BlockProposal) -> Result<bool> {
+        let slot = proposal.block.header.slot;
+        let eligible = self.eligible_leaders(slot);
+        let leader = match eligible.into_iter().find(|p| p.address == proposal.address) {
+            Some(v) => v,
+            None => {
+                warn!(
+                    "Received proposal from ({}), which can't lead slot {}",
+                    proposal.address.to_string(),
+                    slot
+                );
+                return Ok(false)
+            }
+        };
 
         if !leader
             .public_key
             .verify(proposal.block.header.headerhash().as_bytes(), &proposal.signature)
         {
             warn!("Proposer ({}) signature could not be verified", proposal.address.to_string());
-            return Ok(None)
+            return Ok(false)
         }
 
-        self.vote(proposal)
+        let parent = proposal.block.header.state;
+        let snapshot = match self.epoch_snapshot(self.slot_epoch(slot), &parent)? {
+            Some(v) => v,
+            None => {
+                debug!("Previous epoch isn't finalized enough to check leaders of slot {}", slot);
+                return Ok(false)
+            }
+        };
+
+        let (stake, total_stake) = snapshot.stake(&leader.address);
+        let metadata = &proposal.block.metadata;
+        if !metadata.verify_leader(&leader.public_key, &snapshot.seed, slot, stake, total_stake) {
+            warn!("Proposer ({}) is not elected as a slot leader", proposal.address.to_string());
+            return Ok(false)
+        }
+
+        if let Err(e) = self.check_block_txs(&proposal.block) {
+            warn!("Proposal from ({}) rejected: {}", proposal.address.to_string(), e);
+            return Ok(false)
+        }
+
+        Ok(true)
+    }
+
+    /// Check a block holds no more transactions than `max_block_txs`, and
+    /// that they're the ones its header commits to.
+    pub fn check_block_txs(&self, block: &BlockInfo) -> Result<()> {
+        let txs = &block.txs;
+        if txs.len() > self.params.max_block_txs {
+            return Err(Error::TooManyBlockTxs(txs.len(), self.params.max_block_txs))
+        }
+
+        if Header::txs_root(txs)? != block.header.root {
+            return Err(Error::TxsRootMismatch(block.header.slot))
+        }
+
+        Ok(())
     }
 
     /// Given a proposal, the node finds which blockchain it extends.
@@ -436,8 +748,17 @@ impl ValidatorState {
             return Ok(None)
         }
 
-        let signed_hash = self.secret.sign(&serialize(&proposal_hash));
-        Ok(Some(Vote::new(signed_hash, proposal_hash, proposal.block.header.slot, self.address)))
+        // A slot can have several leaders, but voting for more than one
+        // of their proposals is a double vote.
+        let slot = proposal.block.header.slot;
+        let signature = self.consensus_key.sign(&Vote::message(&proposal_hash, slot));
+        let vote = Vote::new(signature, proposal_hash, slot, self.address);
+        if self.conflicting_vote(&vote).is_some() {
+            debug!("vote(): Already voted for another proposal of the slot");
+            return Ok(None)
+        }
+
+        Ok(Some(vote))
     }
 
     /// Verify if the provided chain is notarized excluding the last block.
@@ -535,15 +856,14 @@ impl ValidatorState {
                     return Ok((false, None))
                 }
 
-                let mut encoded_proposal = vec![];
-
-                if let Err(e) = vote.proposal.encode(&mut encoded_proposal) {
-                    error!("consensus: Proposal encoding failed: {:?}", e);
+                if !vote.verify(&participant.public_key) {
+                    warn!("consensus: Voter ({}), signature couldn't be verified", va);
                     return Ok((false, None))
-                };
+                }
 
-                if !participant.public_key.verify(&encoded_proposal, &vote.vote) {
-                    warn!("consensus: Voter ({}), signature couldn't be verified", va);
+                if let Some(seen) = self.conflicting_vote(vote) {
+                    warn!("consensus: Voter ({}) voted twice in slot {}", va, vote.slot);
+                    self.report(vote.address, Misbehavior::DoubleVote(seen, vote.clone()));
                     return Ok((false, None))
                 }
 
@@ -611,6 +931,23 @@ impl ValidatorState {
         Ok((true, Some(to_broadcast)))
     }
 
+    /// Find a vote of the same voter for another proposal of the same slot.
+    fn conflicting_vote(&self, vote: &Vote) -> Option<Vote> {
+        let proposal_votes = self
+            .consensus
+            .proposals
+            .iter()
+            .flat_map(|chain| chain.proposals.iter())
+            .flat_map(|proposal| proposal.block.sm.votes.iter());
+
+        proposal_votes
+            .chain(self.consensus.orphan_votes.iter())
+            .find(|v| {
+                v.address == vote.address && v.slot == vote.slot && v.proposal != vote.proposal
+            })
+            .cloned()
+    }
+
     /// Search the chains we're holding for the given proposal.
     pub fn find_proposal(
         &mut self,
@@ -628,31 +965,27 @@ impl ValidatorState {
         Ok(None)
     }
 
-    /// Remove provided transactions vector from unconfirmed_txs if they exist.
+    /// Remove provided transactions vector from the mempool if they exist.
     pub fn remove_txs(&mut self, transactions: Vec<Transaction>) -> Result<()> {
-        for tx in transactions {
-            if let Some(pos) = self.unconfirmed_txs.iter().position(|txs| *txs == tx) {
-                self.unconfirmed_txs.remove(pos);
-            }
-        }
-
+        self.mempool.remove(&transactions);
         Ok(())
     }
 
     /// Provided an index, the node checks if the chain can be finalized.
     /// Consensus finalization logic:
-    /// - If the node has observed the notarization of 3 consecutive
-    ///   proposals in a fork chain, it finalizes (appends to canonical
-    ///   blockchain) all proposals up to the middle block.
+    /// - If the node has observed the notarization of `finality_window`
+    ///   consecutive proposals in a fork chain, it finalizes (appends to
+    ///   canonical blockchain) all of them but the last one.
     /// When fork chain proposals are finalized, the rest of fork chains not
     /// starting by those proposals are removed.
     pub async fn chain_finalization(&mut self, chain_index: i64) -> Result<Vec<BlockInfo>> {
+        let window = self.params.finality_window;
         let chain = &mut self.consensus.proposals[chain_index as usize];
 
-        if chain.proposals.len() < 3 {
+        if chain.proposals.len() < window {
             debug!(
-                "chain_finalization(): Less than 3 proposals in chain {}, nothing to finalize",
-                chain_index
+                "chain_finalization(): Less than {} proposals in chain {}, nothing to finalize",
+                window, chain_index
             );
             return Ok(vec![])
         }
@@ -667,10 +1000,10 @@ impl ValidatorState {
             break
         }
 
-        if consecutive < 3 {
+        if consecutive < window {
             debug!(
-                "chain_finalization(): Less than 3 notarized blocks in chain {}, nothing to finalize",
-                chain_index
+                "chain_finalization(): Less than {} notarized blocks in chain {}, nothing to finalize",
+                window, chain_index
             );
             return Ok(vec![])
         }
@@ -683,8 +1016,32 @@ impl ValidatorState {
 
         chain.proposals.drain(0..(consecutive - 1));
 
-        info!("consensus: Adding {} finalized block to canonical chain", finalized.len());
-        let blockhashes = match self.blockchain.add(&finalized) {
+        // Blocks gossiped by other participants may be applied already
+        let mut applied = vec![];
+        for block in &finalized {
+            self.seen_blocks.insert(block.header.headerhash());
+            if !self.blockchain.has_block(block)? {
+                applied.push(block.clone());
+            }
+        }
+
+        // Check every block against its state commitment before any of
+        // them is written.
+        let canon_state_clone = self.state_machine.lock().await.clone();
+        let mut mem_state = MemoryState::new(canon_state_clone);
+        let mut canon_updates = vec![];
+        for block in &applied {
+            let state_updates =
+                ValidatorState::validate_state_transitions(mem_state.clone(), &block.txs)?;
+            for update in &state_updates {
+                mem_state.apply(update.clone())?;
+            }
+            Self::check_state_commitment(block, &mem_state)?;
+            canon_updates.push(state_updates);
+        }
+
+        info!("consensus: Adding {} finalized block to canonical chain", applied.len());
+        let blockhashes = match self.blockchain.add(&applied) {
             Ok(v) => v,
             Err(e) => {
                 error!("consensus: Failed appending finalized blocks to canonical chain: {}", e);
@@ -692,18 +1049,19 @@ impl ValidatorState {
             }
         };
 
-        for proposal in &finalized {
-            // TODO: Is this the right place? We're already doing this in protocol_sync.
-            // TODO: These state transitions have already been checked.
+        for (proposal, state_updates) in applied.iter().zip(canon_updates) {
             debug!(target: "consensus", "Applying state transition for finalized block");
-            let canon_state_clone = self.state_machine.lock().await.clone();
-            let mem_st = MemoryState::new(canon_state_clone);
-            let state_updates = ValidatorState::validate_state_transitions(mem_st, &proposal.txs)?;
-            self.update_canon_state(state_updates, None).await?;
+            self.update_canon_state(proposal.header.slot, state_updates).await?;
             self.remove_txs(proposal.txs.clone())?;
         }
+        self.checkpoint_tree().await?;
 
-        let last_block = *blockhashes.last().unwrap();
+        for (block, hash) in applied.iter().zip(&blockhashes) {
+            let slot = block.header.slot;
+            self.events.notify(StateEvent::BlockApplied { slot, hash: *hash }).await;
+        }
+
+        let last_block = finalized.last().unwrap().header.headerhash();
         let last_slot = finalized.last().unwrap().header.slot;
 
         let mut dropped = vec![];
@@ -735,6 +1093,16 @@ impl ValidatorState {
 
     /// Append a new participant to the pending participants list.
     pub fn append_participant(&mut self, participant: Participant) -> bool {
+        if !participant.verify() {
+            warn!("append_participant(): Invalid certificate for {}", participant.address);
+            return false
+        }
+
+        if self.consensus.slashed.contains(&participant.address) {
+            warn!("append_participant(): {} was slashed", participant.address);
+            return false
+        }
+
         if self.consensus.pending_participants.contains(&participant) {
             return false
         }
@@ -750,6 +1118,7 @@ impl ValidatorState {
     /// the case of a node joining while the chosen slot leader is inactive.
     /// Inactive nodes are marked as quarantined, so they can be removed if
     /// they are in quarantine more than the predifined quarantine period.
+    /// Removed nodes are reported to the other participants.
     pub fn refresh_participants(&mut self) -> Result<()> {
         // Node checks if it should refresh its participants list
         let current = self.current_slot();
@@ -795,11 +1164,10 @@ impl ValidatorState {
             self.address.to_string(), previous_slot, last_slot, previous_from_last_slot
         );
 
-        let leader = self.slot_leader();
         for (index, participant) in self.consensus.participants.iter_mut() {
             match participant.quarantined {
                 Some(slot) => {
-                    if (current - slot) > QUARANTINE_DURATION {
+                    if (current - slot) > self.params.quarantine_duration {
                         warn!(
                             "refresh_participants(): Removing participant: {:?} (joined {:?}, voted {:?})",
                             participant.address.to_string(),
@@ -809,57 +1177,43 @@ impl ValidatorState {
                         inactive.push(*index);
                     }
                 }
-                None => {
-                    // Slot leader is always quarantined, to cover the case they become inactive the slot before
-                    // becoming the leader. This can be used for slashing in the future.
-                    if participant.address == leader.address {
-                        debug!(
-                            "refresh_participants(): Quaranteening leader: {:?} (joined {:?}, voted {:?})",
-                            participant.address.to_string(),
-                            participant.joined,
-                            participant.voted
-                        );
-                        participant.quarantined = Some(current);
-                        continue
-                    }
-                    match participant.voted {
-                        Some(slot) => {
-                            if slot < last_slot {
-                                warn!(
-                                    "refresh_participants(): Quaranteening participant: {:?} (joined {:?}, voted {:?})",
-                                    participant.address.to_string(),
-                                    participant.joined,
-                                    participant.voted
-                                );
-                                participant.quarantined = Some(current);
-                            }
+                None => match participant.voted {
+                    Some(slot) => {
+                        if last_slot.saturating_sub(slot) >= self.params.missed_slots {
+                            warn!(
+                                "refresh_participants(): Quaranteening participant: {:?} (joined {:?}, voted {:?})",
+                                participant.address.to_string(),
+                                participant.joined,
+                                participant.voted
+                            );
+                            participant.quarantined = Some(current);
                         }
-                        None => {
-                            if (previous_slot == last_slot && participant.joined < previous_slot) ||
-                                (previous_slot != last_slot &&
-                                    participant.joined < previous_from_last_slot)
-                            {
-                                warn!(
-                                    "refresh_participants(): Quaranteening participant: {:?} (joined {:?}, voted {:?})",
-                                    participant.address.to_string(),
-                                    participant.joined,
-                                    participant.voted
-                                );
-                                participant.quarantined = Some(current);
-                            }
+                    }
+                    None => {
+                        if (previous_slot == last_slot && participant.joined < previous_slot) ||
+                            (previous_slot != last_slot &&
+                                participant.joined < previous_from_last_slot)
+                        {
+                            warn!(
+                                "refresh_participants(): Quaranteening participant: {:?} (joined {:?}, voted {:?})",
+                                participant.address.to_string(),
+                                participant.joined,
+                                participant.voted
+                            );
+                            participant.quarantined = Some(current);
                         }
                     }
-                }
+                },
             }
         }
 
         for index in inactive {
-            self.consensus.participants.remove(&index);
+            self.report(index, Misbehavior::Inactivity);
         }
 
         if self.consensus.participants.is_empty() {
             // If no nodes are active, node becomes a single node network.
-            let participant = Participant::new(self.public, self.address, self.current_slot());
+            let participant = Participant::new(self.certificate.clone(), self.current_slot());
             self.consensus.participants.insert(participant.address, participant);
         }
 
@@ -868,6 +1222,80 @@ impl ValidatorState {
         Ok(())
     }
 
+    /// Remove a misbehaving participant, and sign its removal for the other
+    /// participants, if we're one of them.
+    fn report(&mut self, address: Address, misbehavior: Misbehavior) {
+        let slashed = matches!(misbehavior, Misbehavior::DoubleVote(..));
+        if self.consensus.participants.contains_key(&self.address) {
+            let slot = self.current_slot();
+            let removal =
+                Removal::new(address, misbehavior, slot, self.address, &self.consensus_key);
+            self.removals.push(removal);
+        }
+
+        self.remove_participant(&address, slashed);
+    }
+
+    fn remove_participant(&mut self, address: &Address, slashed: bool) {
+        self.consensus.participants.remove(address);
+        self.consensus.pending_participants.retain(|p| &p.address != address);
+        if slashed && !self.consensus.slashed.contains(address) {
+            self.consensus.slashed.push(*address);
+        }
+    }
+
+    /// Check a removal reported by another participant, and drop the
+    /// participant it concerns. Returns whether the removal should be
+    /// relayed to our peers.
+    pub fn receive_removal(&mut self, removal: &Removal) -> bool {
+        let reporter = match self.consensus.participants.get(&removal.reporter) {
+            Some(reporter) => reporter,
+            None => {
+                warn!("receive_removal(): Reporter ({}) is not a participant", removal.reporter);
+                return false
+            }
+        };
+
+        if !removal.verify(&reporter.public_key) {
+            warn!("receive_removal(): Invalid signature of reporter ({})", removal.reporter);
+            return false
+        }
+
+        let offender = match self.consensus.participants.get(&removal.address) {
+            Some(offender) => offender,
+            None => {
+                debug!("receive_removal(): {} is not a participant anymore", removal.address);
+                return false
+            }
+        };
+
+        if !removal.misbehavior.verify(offender) {
+            warn!("receive_removal(): Invalid evidence against {}", removal.address);
+            return false
+        }
+
+        let slashed = match removal.misbehavior {
+            // There's no evidence of inactivity, so we only go along if we saw
+            // the participant miss a slot too. Slot leaders are quarantined
+            // until they vote, so that doesn't cover the current slot.
+            Misbehavior::Inactivity => match offender.quarantined {
+                Some(slot) if slot < self.current_slot() => false,
+                _ => {
+                    debug!("receive_removal(): {} is active on our side", removal.address);
+                    return false
+                }
+            },
+            Misbehavior::DoubleVote(..) => true,
+        };
+
+        warn!(
+            "receive_removal(): Removing participant {} reported by {} (slashed: {})",
+            removal.address, removal.reporter, slashed
+        );
+        self.remove_participant(&removal.address, slashed);
+        true
+    }
+
     /// Utility function to reset the current consensus state.
     pub fn reset_consensus_state(&mut self) -> Result<()> {
         let genesis_ts = self.consensus.genesis_ts;
@@ -881,6 +1309,7 @@ impl ValidatorState {
             participants: BTreeMap::new(),
             pending_participants: vec![],
             refreshed: 0,
+            slashed: vec![],
         };
 
         self.consensus = consensus;
@@ -892,55 +1321,270 @@ impl ValidatorState {
     // ==========================
 
     /// Validate state transitions for given transactions and state and
-    /// return a vector of [`StateUpdate`]
+    /// return a vector of [`StateUpdate`]. Each transaction is a call to
+    /// the money contract, checked against `state` as its contract state.
     pub fn validate_state_transitions(
         state: MemoryState,
         txs: &[Transaction],
     ) -> Result<Vec<StateUpdate>> {
         let mut ret = vec![];
-        let mut st = state;
+        let mut states = StateRegistry::default();
+        states.register(MONEY_CONTRACT_ID, state);
 
         for (i, tx) in txs.iter().enumerate() {
-            let update = match state_transition(&st, tx.clone()) {
+            let call = transfer_call(tx.clone());
+            let update = match contract::state_transition(&states, &call) {
                 Ok(v) => v,
                 Err(e) => {
                     warn!("validate_state_transition(): Failed for tx {}: {}", i, e);
                     return Err(e.into())
                 }
             };
-            st.apply(update.clone());
-            ret.push(update);
+            if let Some(v) = update.as_any().downcast_ref::<StateUpdate>() {
+                ret.push(v.clone());
+            }
+            update.apply(&mut states)?;
         }
 
         Ok(ret)
     }
 
-    /// Apply a vector of [`StateUpdate`] to the canonical state.
-    pub async fn update_canon_state(
-        &self,
-        updates: Vec<StateUpdate>,
-        notify: Option<async_channel::Sender<(PublicKey, u64)>>,
-    ) -> Result<()> {
-        let secret_keys: Vec<SecretKey> =
-            self.client.get_keypairs().await?.iter().map(|x| x.secret).collect();
+    /// Apply a vector of [`StateUpdate`] from the block in the given slot to
+    /// the canonical state, record the changes in the undo log, and publish
+    /// the resulting [`StateEvent`]s.
+    pub async fn update_canon_state(&self, slot: u64, updates: Vec<StateUpdate>) -> Result<()> {
+        let mut wallets = vec![];
+        for wallet in self.client.wallets().await {
+            let secret_keys: Vec<SecretKey> =
+                wallet.get_keypairs().await?.iter().map(|x| x.secret).collect();
+            wallets.push((wallet, secret_keys));
+        }
 
         debug!("update_canon_state(): Acquiring state machine lock");
         let mut state = self.state_machine.lock().await;
-        for update in updates {
-            state
-                .apply(
-                    update,
-                    secret_keys.clone(),
-                    notify.clone(),
-                    self.client.wallet.clone(),
-                    self.client.tokenlist.clone(),
-                )
-                .await?;
-        }
+        // Mark the tree so the block can be rewound on rollback
+        state.tree.checkpoint();
+        let tx_hashes: Vec<_> = updates.iter().map(|x| x.tx_hash).collect();
+        let events = Some(self.events.clone());
+        let tokenlist = self.client.tokenlist.current();
+        let diff = state.apply(updates, wallets, events, tokenlist).await?;
         drop(state);
         debug!("update_canon_state(): Dropped state machine lock");
 
+        self.blockchain.undo.insert(slot, &diff)?;
+
+        for tx_hash in tx_hashes {
+            self.events.notify(StateEvent::TxConfirmed { tx_hash, slot }).await;
+        }
+
         debug!("update_canon_state(): Successfully applied state updates");
         Ok(())
     }
+
+    /// Undo the state changes of all blocks after the given slot and drop
+    /// those blocks from the canonical chain, so they can be synced again.
+    /// Only the last
+    /// [`UNDO_LOG_DEPTH`](crate::blockchain::undostore::UNDO_LOG_DEPTH)
+    /// blocks can be rolled back.
+    pub async fn rollback_to(&self, slot: u64) -> Result<()> {
+        let (last_slot, _) = self.blockchain.last()?;
+        if slot >= last_slot {
+            return Ok(())
+        }
+
+        // The undo log has to cover every block we're about to drop.
+        match self.blockchain.undo.first_slot()? {
+            Some(first) if first <= slot + 1 => {}
+            _ => return Err(Error::RollbackTooDeep(slot)),
+        }
+
+        let wallets = self.client.wallets().await;
+
+        debug!("rollback_to(): Acquiring state machine lock");
+        let mut state = self.state_machine.lock().await;
+        for (diff_slot, diff) in self.blockchain.undo.get_after(slot)? {
+            debug!("rollback_to(): Rolling back block in slot {}", diff_slot);
+            state.rollback(&diff, &wallets).await?;
+            self.blockchain.undo.remove(diff_slot)?;
+        }
+        drop(state);
+        debug!("rollback_to(): Dropped state machine lock");
+
+        self.blockchain.truncate_after(slot)?;
+        self.checkpoint_tree().await?;
+        self.events.notify(StateEvent::RolledBack { slot }).await;
+
+        info!("Rolled back canonical state to slot {}", slot);
+        Ok(())
+    }
+
+    // ==============
+    // Block handling
+    // ==============
+
+    /// Apply a finalized block on top of the canonical chain: run its state
+    /// transitions, append it to the ledger and drop its transactions from
+    /// the mempool.
+    pub async fn apply_block(&mut self, block: &BlockInfo) -> Result<()> {
+        let canon_state_clone = self.state_machine.lock().await.clone();
+        let mut mem_state = MemoryState::new(canon_state_clone);
+        let state_updates =
+            ValidatorState::validate_state_transitions(mem_state.clone(), &block.txs)?;
+        for update in &state_updates {
+            mem_state.apply(update.clone())?;
+        }
+        Self::check_state_commitment(block, &mem_state)?;
+        self.update_canon_state(block.header.slot, state_updates).await?;
+
+        let hash = self.blockchain.add(&[block.clone()])?[0];
+        self.checkpoint_tree().await?;
+        self.remove_txs(block.txs.clone())?;
+
+        self.events.notify(StateEvent::BlockApplied { slot: block.header.slot, hash }).await;
+        Ok(())
+    }
+
+    /// Handle a finalized block received from the network, and apply it if
+    /// it's on top of our last block. Finalized blocks never conflict
+    /// unless a third of the participants misbehave, so others are
+    /// dropped, as are blocks received ahead of their parent, which the
+    /// block sync fetches later. Forks are only tracked over unfinalized
+    /// proposals. Returns whether the block was new to us, so it can be
+    /// relayed.
+    pub async fn receive_block(&mut self, block: BlockInfo) -> Result<bool> {
+        let hash = block.header.headerhash();
+        if self.blockchain.has_block(&block)? {
+            debug!("receive_block(): Already have block {}", hash);
+            return Ok(false)
+        }
+
+        if !self.verify_block(&block) {
+            warn!("receive_block(): Dropping block {} without a valid leader or quorum", hash);
+            return Ok(false)
+        }
+
+        let (last_slot, last_hash) = self.blockchain.last()?;
+        if block.header.state != last_hash || block.header.slot <= last_slot {
+            warn!("receive_block(): Dropping block {} not on top of our last block", hash);
+            return Ok(false)
+        }
+
+        self.apply_block(&block).await?;
+        Ok(true)
+    }
+
+    /// Check that a block received from the network holds the transactions
+    /// its header commits to, was proposed by one of our participants with
+    /// a valid leader proof, and notarized by more than two thirds of them.
+    /// The participants the block lists are ignored, as anyone can make
+    /// them up. Votes from unknown or repeated voters, or for another
+    /// block, don't count.
+    pub fn verify_block(&self, block: &BlockInfo) -> bool {
+        let participants: Vec<&Participant> = self.consensus.participants.values().collect();
+        if participants.is_empty() {
+            return false
+        }
+
+        if let Err(e) = self.check_block_txs(block) {
+            debug!("verify_block(): {}", e);
+            return false
+        }
+
+        let slot = block.header.slot;
+        let snapshot = match self.epoch_snapshot(self.slot_epoch(slot), &block.header.state) {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                debug!("verify_block(): Previous epoch isn't finalized enough for slot {}", slot);
+                return false
+            }
+            Err(e) => {
+                error!("verify_block(): Failed reading the epoch snapshot: {}", e);
+                return false
+            }
+        };
+
+        let elected = participants.iter().any(|p| {
+            let (stake, total_stake) = snapshot.stake(&p.address);
+            block.metadata.verify_leader(&p.public_key, &snapshot.seed, slot, stake, total_stake)
+        });
+        if !elected {
+            return false
+        }
+
+        let hash = block.header.headerhash();
+        let mut voters = vec![];
+        for vote in &block.sm.votes {
+            if vote.proposal != hash || vote.slot != slot || voters.contains(&vote.address) {
+                continue
+            }
+
+            let voter = match participants.iter().find(|p| p.address == vote.address) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            if vote.verify(&voter.public_key) {
+                voters.push(vote.address);
+            }
+        }
+
+        voters.len() > (2 * participants.len() / 3)
+    }
+
+    /// Check if the Merkle root is the current root of the canonical tree,
+    /// or one the blocks of the last `window` slots produced.
+    pub async fn is_recent_root(&self, root: &MerkleNode, window: u64) -> Result<bool> {
+        if self.state_machine.lock().await.tree.root(0) == Some(*root) {
+            return Ok(true)
+        }
+
+        let (last_slot, _) = self.blockchain.last()?;
+        let diffs = self.blockchain.undo.get_after(last_slot.saturating_sub(window))?;
+        Ok(diffs.iter().any(|(_, diff)| diff.roots.contains(root)))
+    }
+
+    /// Write a checkpoint of the canonical Merkle tree, marked with the
+    /// last block in the ledger, so it can be restored on restart.
+    pub async fn checkpoint_tree(&self) -> Result<()> {
+        let (slot, hash) = self.blockchain.last()?;
+        let tree = self.state_machine.lock().await.tree.clone();
+        debug!("checkpoint_tree(): Writing Merkle tree checkpoint at slot {}", slot);
+        self.blockchain.merkle_tree.insert(slot, &hash, &tree)
+    }
+
+    /// Rebuild everything derived from the stored blocks: the block order,
+    /// the nullifier and Merkle root sets, the Merkle tree, the undo log and
+    /// the coins of every wallet. Blocks are replayed from genesis without
+    /// contacting the network.
+    pub async fn reindex(&self) -> Result<()> {
+        info!("Reindexing blockchain...");
+        let chain = self.blockchain.reindex_order()?;
+        info!("Rebuilt block order with {} blocks", chain.len());
+
+        self.blockchain.nullifiers.clear()?;
+        self.blockchain.merkle_roots.clear()?;
+        self.blockchain.merkle_tree.clear()?;
+        self.blockchain.undo.clear()?;
+
+        let tree = BridgeTree::<MerkleNode, MERKLE_DEPTH>::new(100);
+        for wallet in self.client.wallets().await {
+            wallet.remove_own_coins().await?;
+            wallet.init_db().await?;
+            wallet.put_tree(&tree).await?;
+        }
+        self.state_machine.lock().await.tree = tree;
+
+        for (slot, hash) in chain {
+            let block = &self.blockchain.get_blocks_by_hash(&[hash])?[0];
+            debug!("reindex(): Replaying block in slot {}", slot);
+            let canon_state_clone = self.state_machine.lock().await.clone();
+            let mem_state = MemoryState::new(canon_state_clone);
+            let state_updates = ValidatorState::validate_state_transitions(mem_state, &block.txs)?;
+            self.update_canon_state(slot, state_updates).await?;
+        }
+
+        self.checkpoint_tree().await?;
+        info!("Reindexing finished");
+        Ok(())
+    }
 }
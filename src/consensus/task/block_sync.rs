@@ -4,7 +4,7 @@ use crate::{
         ValidatorState, ValidatorStatePtr,
     },
     net,
-    node::MemoryState,
+    node::{state::StateEvent, MemoryState},
     Result,
 };
 use log::{debug, info, warn};
@@ -29,7 +29,8 @@ pub async fn block_sync_task(p2p: net::P2pPtr, state: ValidatorStatePtr) -> Resu
         // Node sends the last known block hash of the canonical blockchain
         // and loops until the response is the same block (used to utilize
         // batch requests).
-        let mut last = state.read().await.blockchain.last()?;
+        let blockchain = state.read().await.async_blockchain.clone();
+        let mut last = blockchain.last().await?;
         info!("Last known block: {:?} - {:?}", last.0, last.1);
 
         loop {
@@ -40,30 +41,50 @@ pub async fn block_sync_task(p2p: net::P2pPtr, state: ValidatorStatePtr) -> Resu
             // Node stores response data.
             let resp = response_sub.receive().await?;
 
+            // Make sure the peer sent us a contiguous, untampered sequence
+            // on top of our last block before touching any state.
+            resp.verify_chain(last.1, last.0)?;
+            for block in &resp.blocks {
+                state.read().await.check_block_txs(block)?;
+            }
+
             // Verify state transitions for all blocks and their respective transactions.
             debug!("block_sync_task(): Starting state transition validations");
             let mut canon_updates = vec![];
             let canon_state_clone = state.read().await.state_machine.lock().await.clone();
             let mut mem_state = MemoryState::new(canon_state_clone);
             for block in &resp.blocks {
-                let mut state_updates =
+                let state_updates =
                     ValidatorState::validate_state_transitions(mem_state.clone(), &block.txs)?;
 
                 for update in &state_updates {
-                    mem_state.apply(update.clone());
+                    mem_state.apply(update.clone())?;
                 }
+                ValidatorState::check_state_commitment(block, &mem_state)?;
 
-                canon_updates.append(&mut state_updates);
+                canon_updates.push((block.header.slot, state_updates));
             }
             debug!("block_sync_task(): All state transitions passed");
 
             debug!("block_sync_task(): Updating canon state");
-            state.write().await.update_canon_state(canon_updates, None).await?;
+            for (slot, state_updates) in canon_updates {
+                state.write().await.update_canon_state(slot, state_updates).await?;
+            }
 
             debug!("block_sync_task(): Appending blocks to ledger");
-            state.write().await.blockchain.add(&resp.blocks)?;
+            // Hold the write lock, so consensus doesn't append meanwhile
+            let hashes = {
+                let _state = state.write().await;
+                blockchain.add(resp.blocks.clone()).await?
+            };
+            state.read().await.checkpoint_tree().await?;
+
+            let events = state.read().await.events.clone();
+            for (block, hash) in resp.blocks.iter().zip(hashes) {
+                events.notify(StateEvent::BlockApplied { slot: block.header.slot, hash }).await;
+            }
 
-            let last_received = state.read().await.blockchain.last()?;
+            let last_received = blockchain.last().await?;
             info!("Last received block: {:?} - {:?}", last_received.0, last_received.1);
 
             if last == last_received {
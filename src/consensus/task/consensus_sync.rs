@@ -35,8 +35,12 @@ pub async fn consensus_sync_task(p2p: P2pPtr, state: ValidatorStatePtr) -> Resul
                 warn!("Retrieved consensus state from a new node, retrying...");
                 continue
             }
-            // Node stores response data.
-            state.write().await.consensus = response.consensus.clone();
+            // Node stores response data, without the participants whose
+            // consensus keys aren't vouched for by their wallet keys.
+            let mut consensus = response.consensus.clone();
+            consensus.participants.retain(|_, participant| participant.verify());
+            consensus.pending_participants.retain(|participant| participant.verify());
+            state.write().await.consensus = consensus;
         }
     } else {
         warn!("Node is not connected to other nodes, resetting consensus state.");
@@ -8,3 +8,6 @@ pub use consensus_sync::consensus_sync_task;
 
 mod proposal;
 pub use proposal::proposal_task;
+
+mod slashing;
+pub use slashing::slashing_task;
@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 
 use super::consensus_sync_task;
 use crate::{
@@ -39,10 +39,9 @@ pub async fn proposal_task(consensus_p2p: P2pPtr, sync_p2p: P2pPtr, state: Valid
     };
 
     // Node signals the network that it will start participating
-    let public = state.read().await.public;
-    let address = state.read().await.address;
+    let certificate = state.read().await.certificate.clone();
     let cur_slot = state.read().await.current_slot();
-    let participant = Participant::new(public, address, cur_slot);
+    let participant = Participant::new(certificate, cur_slot);
     state.write().await.append_participant(participant.clone());
 
     match consensus_p2p.broadcast(participant).await {
@@ -68,9 +67,20 @@ pub async fn proposal_task(consensus_p2p: P2pPtr, sync_p2p: P2pPtr, state: Valid
         }
 
         // Node checks if it's the slot leader to generate a new proposal
-        // for that slot.
-        let result = if state.write().await.is_slot_leader() {
-            state.read().await.propose()
+        // for that slot. A drifting clock would sign the wrong slot, so the
+        // node sits out its slots until the clock is back within bounds.
+        let result = if state.read().await.is_slot_leader() {
+            let clock_drift = state.read().await.clock_drift.clone();
+            if clock_drift.exceeded() {
+                warn!(
+                    "consensus: Node is the slot leader, but not proposing with the clock {} sec \
+                     off the peers",
+                    clock_drift.offset().unwrap()
+                );
+                continue
+            }
+
+            state.read().await.propose().await
         } else {
             Ok(None)
         };
@@ -91,9 +101,11 @@ pub async fn proposal_task(consensus_p2p: P2pPtr, sync_p2p: P2pPtr, state: Valid
 
         info!("consensus: Node is the slot leader: Proposed block: {}", proposal);
         debug!("consensus: Full proposal: {:?}", proposal);
+        let hash = proposal.block.header.headerhash();
+        state.write().await.seen_proposals.insert(hash);
         let vote = state.write().await.receive_proposal(&proposal);
         let vote = match vote {
-            Ok(v) => {
+            Ok((_, v)) => {
                 if v.is_none() {
                     debug!("proposal_task(): Node did not vote for the proposed block");
                     continue
@@ -114,6 +126,10 @@ pub async fn proposal_task(consensus_p2p: P2pPtr, sync_p2p: P2pPtr, state: Valid
                 if let Some(blocks) = to_broadcast {
                     info!("consensus: Broadcasting finalized blocks");
                     for info in blocks {
+                        if let Err(e) = consensus_p2p.broadcast(info.clone()).await {
+                            error!("consensus: Failed gossiping block: {}", e);
+                        }
+
                         match sync_p2p.broadcast(info).await {
                             Ok(()) => info!("consensus: Broadcasted block"),
                             Err(e) => error!("consensus: Failed broadcasting block: {}", e),
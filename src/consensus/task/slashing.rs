@@ -0,0 +1,40 @@
+use log::{debug, error, info};
+
+use crate::{consensus::ValidatorStatePtr, net::P2pPtr, util::sleep};
+
+/// async task reporting misbehaving participants. Double votes are caught
+/// as votes come in, and inactive participants when refreshing the
+/// participants, which this task makes sure happens every slot. The
+/// signed removals are then broadcasted to the other participants.
+pub async fn slashing_task(consensus_p2p: P2pPtr, state: ValidatorStatePtr) {
+    loop {
+        let seconds_next_slot = state.read().await.next_slot_start().as_secs();
+        sleep(seconds_next_slot).await;
+
+        // Node can't judge anyone before it participates
+        let participating = {
+            let state = state.read().await;
+            matches!(state.participating, Some(start) if state.current_slot() >= start)
+        };
+        if !participating {
+            continue
+        }
+
+        let removals = {
+            let mut state = state.write().await;
+            match state.refresh_participants() {
+                Ok(()) => debug!("slashing_task(): Participants refreshed successfully."),
+                Err(e) => error!("slashing_task(): Failed refreshing participants: {}", e),
+            }
+            std::mem::take(&mut state.removals)
+        };
+
+        for removal in removals {
+            info!("consensus: Reporting participant {} (slot {})", removal.address, removal.slot);
+            match consensus_p2p.broadcast(removal).await {
+                Ok(()) => info!("consensus: Removal broadcasted successfully"),
+                Err(e) => error!("consensus: Failed broadcasting removal: {}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use async_std::sync::Arc;
+
+use crate::{tx::Transaction, util::serial::serialize, VerifyFailed, VerifyResult};
+
+/// A check run on transactions received from the network, before they're
+/// added to the pending transactions and rebroadcast. Filters are meant
+/// for cheap, local policy, and run before the state transition is
+/// validated.
+pub type TxFilter = Arc<dyn Fn(&Transaction) -> VerifyResult<()> + Send + Sync>;
+
+/// Reject transactions whose serialized size exceeds `limit` bytes.
+pub fn max_tx_size(limit: usize) -> TxFilter {
+    Arc::new(move |tx| {
+        let size = serialize(tx).len();
+        if size > limit {
+            return Err(VerifyFailed::Filtered(format!(
+                "transaction is {} bytes, the limit is {}",
+                size, limit
+            )))
+        }
+        Ok(())
+    })
+}
+
+/// Reject transactions carrying a mint or burn proof larger than `limit`
+/// bytes.
+pub fn max_proof_size(limit: usize) -> TxFilter {
+    Arc::new(move |tx| {
+        for (i, input) in tx.inputs.iter().enumerate() {
+            let size = input.burn_proof.as_ref().len();
+            if size > limit {
+                return Err(VerifyFailed::Filtered(format!(
+                    "burn proof of input {} is {} bytes, the limit is {}",
+                    i, size, limit
+                )))
+            }
+        }
+
+        for (i, output) in tx.outputs.iter().enumerate() {
+            let size = output.mint_proof.as_ref().len();
+            if size > limit {
+                return Err(VerifyFailed::Filtered(format!(
+                    "mint proof of output {} is {} bytes, the limit is {}",
+                    i, size, limit
+                )))
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// Reject transactions without any outputs, or with more than `limit`
+/// inputs and outputs in total.
+pub fn max_io(limit: usize) -> TxFilter {
+    Arc::new(move |tx| {
+        if tx.outputs.is_empty() {
+            return Err(VerifyFailed::Filtered("transaction has no outputs".to_string()))
+        }
+
+        let count = tx.clear_inputs.len() + tx.inputs.len() + tx.outputs.len();
+        if count > limit {
+            return Err(VerifyFailed::Filtered(format!(
+                "transaction has {} inputs and outputs, the limit is {}",
+                count, limit
+            )))
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_empty_tx() {
+        let tx = Transaction { clear_inputs: vec![], inputs: vec![], outputs: vec![] };
+
+        assert!(max_tx_size(16)(&tx).is_ok());
+        assert!(max_tx_size(2)(&tx).is_err());
+        assert!(max_proof_size(0)(&tx).is_ok());
+        assert!(max_io(8)(&tx).is_err());
+    }
+}
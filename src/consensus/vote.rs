@@ -1,16 +1,24 @@
 use std::io;
 
 use crate::{
-    crypto::{address::Address, schnorr::Signature},
+    crypto::{
+        address::Address,
+        keypair::PublicKey,
+        schnorr::{SchnorrPublic, Signature},
+    },
     impl_vec, net,
-    util::serial::{Decodable, Encodable, SerialDecodable, SerialEncodable, VarInt},
+    util::serial::{serialize, Decodable, Encodable, SerialDecodable, SerialEncodable, VarInt},
     Result,
 };
 
+/// Prefix of the message a participant signs to vote, so that the
+/// signature can't be passed off as one over anything else.
+const VOTE_DOMAIN: &[u8] = b"DarkFi:Vote";
+
 /// This struct represents a `Vote` used by the Streamlet consensus
 #[derive(Debug, Clone, PartialEq, Eq, SerialDecodable, SerialEncodable)]
 pub struct Vote {
-    /// Block signature
+    /// Signature over the proposal hash and the slot
     pub vote: Signature,
     /// Block proposal hash to vote on
     pub proposal: blake3::Hash,
@@ -24,6 +32,20 @@ impl Vote {
     pub fn new(vote: Signature, proposal: blake3::Hash, slot: u64, address: Address) -> Self {
         Self { vote, proposal, slot, address }
     }
+
+    /// The message signed by a vote. It covers the slot too, so a vote
+    /// can't be relabeled to another slot.
+    pub fn message(proposal: &blake3::Hash, slot: u64) -> Vec<u8> {
+        let mut message = VOTE_DOMAIN.to_vec();
+        message.extend(serialize(proposal));
+        message.extend(serialize(&slot));
+        message
+    }
+
+    /// Check the signature against the voter's consensus public key
+    pub fn verify(&self, public_key: &PublicKey) -> bool {
+        public_key.verify(&Self::message(&self.proposal, self.slot), &self.vote)
+    }
 }
 
 impl net::Message for Vote {
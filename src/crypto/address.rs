@@ -4,7 +4,8 @@ use sha2::Digest;
 
 use crate::{
     crypto::keypair::PublicKey,
-    util::serial::{Decodable, Encodable, ReadExt, WriteExt},
+    impl_vec,
+    util::serial::{Decodable, Encodable, ReadExt, VarInt, WriteExt},
     Error, Result,
 };
 
@@ -96,6 +97,8 @@ impl Decodable for Address {
     }
 }
 
+impl_vec!(Address);
+
 #[cfg(test)]
 mod tests {
     use rand::rngs::OsRng;
@@ -5,7 +5,7 @@ use halo2_proofs::circuit::Value;
 use incrementalmerkletree::Hashable;
 use log::debug;
 use pasta_curves::{arithmetic::CurveAffine, group::Curve};
-use rand::rngs::OsRng;
+use rand::RngCore;
 
 use super::{
     nullifier::Nullifier,
@@ -121,6 +121,7 @@ pub fn create_burn_proof(
     leaf_position: incrementalmerkletree::Position,
     merkle_path: Vec<MerkleNode>,
     signature_secret: SecretKey,
+    mut rng: impl RngCore,
 ) -> Result<(Proof, BurnRevealedValues)> {
     let revealed = BurnRevealedValues::compute(
         value,
@@ -152,7 +153,7 @@ pub fn create_burn_proof(
 
     let start = Instant::now();
     let public_inputs = revealed.make_outputs();
-    let proof = Proof::create(pk, &[c], &public_inputs, &mut OsRng)?;
+    let proof = Proof::create(pk, &[c], &public_inputs, &mut rng)?;
     debug!("Prove burn: [{:?}]", start.elapsed());
 
     Ok((proof, revealed))
@@ -3,7 +3,8 @@ use std::io;
 use pasta_curves::{group::ff::PrimeField, pallas};
 
 use crate::{
-    util::serial::{Decodable, Encodable, ReadExt, WriteExt},
+    impl_vec,
+    util::serial::{Decodable, Encodable, ReadExt, VarInt, WriteExt},
     Result,
 };
 
@@ -34,3 +35,5 @@ impl Decodable for Coin {
         Ok(Self::from_bytes(bytes))
     }
 }
+
+impl_vec!(Coin);
@@ -6,6 +6,24 @@ pub use fixed_bases::{NullifierK, OrchardFixedBases, OrchardFixedBasesFull, Valu
 
 pub const DRK_SCHNORR_DOMAIN: &[u8] = b"DarkFi_Schnorr";
 
+pub const DRK_MUSIG_KEY_DOMAIN: &[u8] = b"DarkFi_MuSig_Key";
+
+pub const DRK_MUSIG_NONCE_DOMAIN: &[u8] = b"DarkFi_MuSig_Non";
+
+pub const DRK_MEMBERSHIP_SCOPE_DOMAIN: &[u8] = b"DarkFiMembership";
+
+pub const DRK_MEMBERSHIP_CHALLENGE_DOMAIN: &[u8] = b"DarkFiMemberChal";
+
+pub const DRK_UNIQUE_ASSET_DOMAIN: &[u8] = b"DarkFi_UniqAsset";
+
+pub const DRK_ISSUED_TOKEN_DOMAIN: &[u8] = b"DarkFi_IssuToken";
+
+pub const DRK_VRF_INPUT_DOMAIN: &str = "DarkFi:VrfInput";
+
+pub const DRK_VRF_CHALLENGE_DOMAIN: &[u8] = b"DarkFi_VRF_Chall";
+
+pub const DRK_VRF_OUTPUT_DOMAIN: &[u8] = b"DarkFi:VrfOutput";
+
 pub const MERKLE_DEPTH_ORCHARD: usize = 32;
 
 pub const MERKLE_DEPTH: u8 = MERKLE_DEPTH_ORCHARD as u8;
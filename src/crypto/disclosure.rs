@@ -0,0 +1,86 @@
+use crate::{
+    crypto::{keypair::PublicKey, note::Note, MintRevealedValues, OwnCoin},
+    util::serial::{SerialDecodable, SerialEncodable},
+};
+
+/// Opening of a transaction output, which can be handed to a third party
+/// to prove that a payment of `note.value` was made to `public_key`,
+/// without revealing anything else about the wallet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct PaymentDisclosure {
+    /// Recipient's public key
+    pub public_key: PublicKey,
+    /// Decrypted note of the disclosed output
+    pub note: Note,
+}
+
+impl PaymentDisclosure {
+    pub fn new(public_key: PublicKey, note: Note) -> Self {
+        Self { public_key, note }
+    }
+
+    /// Disclose a coin we received, given the public key it was sent to.
+    pub fn from_own_coin(coin: &OwnCoin) -> Self {
+        Self::new(PublicKey::from_secret(coin.secret), coin.note)
+    }
+
+    /// Check the disclosure against the public values of an output found
+    /// on chain. The coin and both commitments have to match.
+    pub fn verify(&self, revealed: &MintRevealedValues) -> bool {
+        let computed = MintRevealedValues::compute(
+            self.note.value,
+            self.note.token_id,
+            self.note.value_blind,
+            self.note.token_blind,
+            self.note.serial,
+            self.note.coin_blind,
+            self.public_key,
+        );
+
+        computed == *revealed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{
+        keypair::Keypair,
+        note::Memo,
+        types::{DrkCoinBlind, DrkSerial, DrkTokenId, DrkValueBlind},
+    };
+    use group::ff::Field;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_payment_disclosure() {
+        let note = Note {
+            serial: DrkSerial::random(&mut OsRng),
+            value: 110,
+            token_id: DrkTokenId::random(&mut OsRng),
+            coin_blind: DrkCoinBlind::random(&mut OsRng),
+            value_blind: DrkValueBlind::random(&mut OsRng),
+            token_blind: DrkValueBlind::random(&mut OsRng),
+            memo: Memo::default(),
+            metadata: None,
+        };
+
+        let keypair = Keypair::random(&mut OsRng);
+        let revealed = MintRevealedValues::compute(
+            note.value,
+            note.token_id,
+            note.value_blind,
+            note.token_blind,
+            note.serial,
+            note.coin_blind,
+            keypair.public,
+        );
+
+        let disclosure = PaymentDisclosure::new(keypair.public, note);
+        assert!(disclosure.verify(&revealed));
+
+        let mut forged = disclosure;
+        forged.note.value = 1000;
+        assert!(!forged.verify(&revealed));
+    }
+}
@@ -1,7 +1,9 @@
 use std::{convert::TryFrom, io, str::FromStr};
 
+use blake2b_simd::Params;
 use halo2_gadgets::ecc::chip::FixedPoint;
 use pasta_curves::{
+    arithmetic::FieldExt,
     group::{
         ff::{Field, PrimeField},
         Group, GroupEncoding,
@@ -34,6 +36,67 @@ impl Keypair {
         let secret = SecretKey::random(&mut rng);
         Self::new(secret)
     }
+
+    /// Derive the keypair of a wallet account at `account/index` under the
+    /// master key of a seed, such as the one of a mnemonic.
+    pub fn derive(seed: &[u8], account: u32, index: u32) -> Self {
+        ExtendedSecretKey::from_seed(seed).derive_path(&[account, index]).keypair()
+    }
+}
+
+/// Personalization of the master key made from a seed
+const MASTER_KEY_PERSONALIZATION: &[u8] = b"DarkFi_MasterKey";
+
+/// Personalization of child keys
+const CHILD_KEY_PERSONALIZATION: &[u8] = b"DarkFi_ChildKey_";
+
+/// Secret key along with a chain code, from which child keys are derived
+/// deterministically. Every child is hashed from the secret of its parent,
+/// so a leaked child key gives nothing away about its parent or siblings.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ExtendedSecretKey {
+    pub secret: SecretKey,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedSecretKey {
+    pub fn from_seed(seed: &[u8]) -> Self {
+        Self::hash(MASTER_KEY_PERSONALIZATION, &[], seed)
+    }
+
+    /// Child key at the given index
+    pub fn child(&self, index: u32) -> Self {
+        let data = [&self.secret.to_bytes()[..], &index.to_le_bytes()].concat();
+        Self::hash(CHILD_KEY_PERSONALIZATION, &self.chain_code, &data)
+    }
+
+    /// Key at the end of a path of child indexes, starting from this one
+    pub fn derive_path(&self, path: &[u32]) -> Self {
+        path.iter().fold(*self, |key, index| key.child(*index))
+    }
+
+    pub fn keypair(&self) -> Keypair {
+        Keypair::new(self.secret)
+    }
+
+    fn hash(persona: &[u8], key: &[u8], data: &[u8]) -> Self {
+        let hash = |tag: u8| {
+            Params::new()
+                .hash_length(64)
+                .personal(persona)
+                .key(key)
+                .to_state()
+                .update(data)
+                .update(&[tag])
+                .finalize()
+        };
+
+        let secret = SecretKey(pallas::Base::from_bytes_wide(hash(0).as_array()));
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&hash(1).as_bytes()[..32]);
+
+        Self { secret, chain_code }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, SerialDecodable, SerialEncodable)]
@@ -306,4 +369,22 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_key_derivation() {
+        let seed = [7u8; 64];
+
+        // Same seed and path, same keys
+        let keypair = Keypair::derive(&seed, 0, 0);
+        assert_eq!(Keypair::derive(&seed, 0, 0), keypair);
+        let master = ExtendedSecretKey::from_seed(&seed);
+        assert_eq!(master.derive_path(&[0, 0]).keypair(), keypair);
+        assert_eq!(master.child(0).child(0), master.derive_path(&[0, 0]));
+
+        // Any other seed, account or index gives another key
+        assert_ne!(Keypair::derive(&seed, 0, 1), keypair);
+        assert_ne!(Keypair::derive(&seed, 1, 0), keypair);
+        assert_ne!(Keypair::derive(&[8u8; 64], 0, 0), keypair);
+        assert_ne!(master.keypair(), keypair);
+    }
 }
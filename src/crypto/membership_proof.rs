@@ -0,0 +1,235 @@
+//! Proof of owning a coin of some token without spending it, for services
+//! gating access to holders of a token, e.g. DAO members. The service picks
+//! a scope, usually its name, and a fresh challenge for every login. The
+//! proof reveals the token, and a nullifier that is the same for all the
+//! proofs of a coin for one scope, so the service can tell returning
+//! members apart without learning which coin they own.
+//!
+//! A proof shows the coin was in the Merkle tree when it had the root the
+//! proof was made against, not that the coin is still unspent: the spend
+//! nullifier isn't revealed, so the service can't check it. Roots are
+//! therefore only accepted for [`MEMBERSHIP_ROOT_WINDOW`] slots, so a coin
+//! spent since keeps proving membership for that long at most.
+use std::time::Instant;
+
+use halo2_gadgets::poseidon::primitives as poseidon;
+use halo2_proofs::circuit::Value;
+use incrementalmerkletree::Hashable;
+use log::debug;
+use pasta_curves::pallas;
+use rand::RngCore;
+
+use super::{
+    constants::{DRK_MEMBERSHIP_CHALLENGE_DOMAIN, DRK_MEMBERSHIP_SCOPE_DOMAIN},
+    nullifier::Nullifier,
+    proof::{Proof, ProvingKey, VerifyingKey},
+    util::hash_to_base,
+    OwnCoin,
+};
+use crate::{
+    crypto::{merkle_node::MerkleNode, types::*},
+    util::serial::{SerialDecodable, SerialEncodable},
+    zk::circuit::MembershipContract,
+    Result,
+};
+
+/// Slots a Merkle root stays valid for membership proofs after the block
+/// that produced it, on top of the current root
+pub const MEMBERSHIP_ROOT_WINDOW: u64 = 10;
+
+/// Scope of a service accepting membership proofs, e.g. its domain name
+pub fn membership_scope(service: &str) -> pallas::Base {
+    hash_to_base(DRK_MEMBERSHIP_SCOPE_DOMAIN, service.as_bytes(), &[])
+}
+
+/// Challenge a service hands out for one membership proof
+pub fn membership_challenge(challenge: &[u8]) -> pallas::Base {
+    hash_to_base(DRK_MEMBERSHIP_CHALLENGE_DOMAIN, challenge, &[])
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct MembershipRevealedValues {
+    /// Nullifier of the coin for this scope, unrelated to its spend nullifier
+    pub nullifier: Nullifier,
+    pub merkle_root: MerkleNode,
+    pub token_id: DrkTokenId,
+    pub scope: pallas::Base,
+    pub challenge: pallas::Base,
+}
+
+impl MembershipRevealedValues {
+    pub fn compute(
+        coin: &OwnCoin,
+        merkle_path: &[MerkleNode],
+        scope: pallas::Base,
+        challenge: pallas::Base,
+    ) -> Self {
+        let nullifier = [coin.secret.0, coin.note.serial, scope];
+        let nullifier =
+            poseidon::Hash::<_, poseidon::P128Pow5T3, poseidon::ConstantLength<3>, 3, 2>::init()
+                .hash(nullifier);
+
+        let merkle_root = {
+            let position: u64 = coin.leaf_position.into();
+            let mut current = MerkleNode(coin.coin.0);
+            for (level, sibling) in merkle_path.iter().enumerate() {
+                let level = level as u8;
+                current = if position & (1 << level) == 0 {
+                    MerkleNode::combine(level.into(), &current, sibling)
+                } else {
+                    MerkleNode::combine(level.into(), sibling, &current)
+                };
+            }
+            current
+        };
+
+        MembershipRevealedValues {
+            nullifier: Nullifier(nullifier),
+            merkle_root,
+            token_id: coin.note.token_id,
+            scope,
+            challenge,
+        }
+    }
+
+    pub fn make_outputs(&self) -> [DrkCircuitField; 5] {
+        [self.nullifier.0, self.merkle_root.0, self.token_id, self.scope, self.challenge]
+    }
+}
+
+/// A membership proof as handed to the service
+#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct MembershipProof {
+    pub proof: Proof,
+    pub revealed: MembershipRevealedValues,
+}
+
+impl MembershipProof {
+    /// Check the proof itself. The service also has to check that the
+    /// Merkle root is a recent one of the chain, see
+    /// [`MEMBERSHIP_ROOT_WINDOW`], and that the token, scope and challenge
+    /// are the ones it expects.
+    pub fn verify(&self, vk: &VerifyingKey) -> Result<()> {
+        let start = Instant::now();
+        let public_inputs = self.revealed.make_outputs();
+        self.proof.verify(vk, &public_inputs)?;
+        debug!("Verify membership: [{:?}]", start.elapsed());
+        Ok(())
+    }
+}
+
+pub fn create_membership_proof(
+    pk: &ProvingKey,
+    coin: &OwnCoin,
+    merkle_path: Vec<MerkleNode>,
+    scope: pallas::Base,
+    challenge: pallas::Base,
+    mut rng: impl RngCore,
+) -> Result<MembershipProof> {
+    let revealed = MembershipRevealedValues::compute(coin, &merkle_path, scope, challenge);
+
+    let leaf_position: u64 = coin.leaf_position.into();
+
+    let c = MembershipContract {
+        secret_key: Value::known(coin.secret.0),
+        serial: Value::known(coin.note.serial),
+        value: Value::known(DrkValue::from(coin.note.value)),
+        token: Value::known(coin.note.token_id),
+        coin_blind: Value::known(coin.note.coin_blind),
+        leaf_pos: Value::known(leaf_position as u32),
+        merkle_path: Value::known(merkle_path.try_into().unwrap()),
+        scope: Value::known(scope),
+        challenge: Value::known(challenge),
+    };
+
+    let start = Instant::now();
+    let public_inputs = revealed.make_outputs();
+    let proof = Proof::create(pk, &[c], &public_inputs, &mut rng)?;
+    debug!("Prove membership: [{:?}]", start.elapsed());
+
+    Ok(MembershipProof { proof, revealed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{
+        coin::Coin,
+        keypair::{PublicKey, SecretKey},
+        note::{Memo, Note},
+    };
+    use group::{ff::Field, Curve};
+    use incrementalmerkletree::{bridgetree::BridgeTree, Tree};
+    use pasta_curves::arithmetic::CurveAffine;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn membership_proof() -> Result<()> {
+        let secret = SecretKey::random(&mut OsRng);
+        let note = Note {
+            serial: DrkSerial::random(&mut OsRng),
+            value: 42,
+            token_id: DrkTokenId::from(22),
+            coin_blind: DrkCoinBlind::random(&mut OsRng),
+            value_blind: DrkValueBlind::random(&mut OsRng),
+            token_blind: DrkValueBlind::random(&mut OsRng),
+            memo: Memo::default(),
+            metadata: None,
+        };
+
+        let coords = PublicKey::from_secret(secret).0.to_affine().coordinates().unwrap();
+        let msg = [
+            *coords.x(),
+            *coords.y(),
+            DrkValue::from(note.value),
+            note.token_id,
+            note.serial,
+            note.coin_blind,
+        ];
+        let coin =
+            poseidon::Hash::<_, poseidon::P128Pow5T3, poseidon::ConstantLength<6>, 3, 2>::init()
+                .hash(msg);
+
+        let mut tree = BridgeTree::<MerkleNode, 32>::new(100);
+        tree.append(&MerkleNode(pallas::Base::random(&mut OsRng)));
+        tree.append(&MerkleNode(coin));
+        let leaf_position = tree.witness().unwrap();
+        tree.append(&MerkleNode(pallas::Base::random(&mut OsRng)));
+        let root = tree.root(0).unwrap();
+        let merkle_path = tree.authentication_path(leaf_position, &root).unwrap();
+
+        let own_coin = OwnCoin {
+            coin: Coin(coin),
+            note,
+            secret,
+            nullifier: Nullifier::new(secret, note.serial),
+            leaf_position,
+        };
+
+        let pk = ProvingKey::build(11, &MembershipContract::default());
+        let vk = VerifyingKey::build(11, &MembershipContract::default());
+        let scope = membership_scope("example.com");
+        let challenge = membership_challenge(b"nonce");
+
+        let proof =
+            create_membership_proof(&pk, &own_coin, merkle_path.clone(), scope, challenge, OsRng)?;
+        assert_eq!(proof.revealed.merkle_root, root);
+        assert_eq!(proof.revealed.token_id, note.token_id);
+        assert_ne!(proof.revealed.nullifier, own_coin.nullifier);
+        proof.verify(&vk)?;
+
+        // Can't be passed off for another challenge
+        let mut replayed = proof.clone();
+        replayed.revealed.challenge = membership_challenge(b"other nonce");
+        assert!(replayed.verify(&vk).is_err());
+
+        // Same nullifier for the scope, another one for other scopes
+        let again = MembershipRevealedValues::compute(&own_coin, &merkle_path, scope, challenge);
+        assert_eq!(again.nullifier, proof.revealed.nullifier);
+        let other = membership_scope("example.org");
+        let other = MembershipRevealedValues::compute(&own_coin, &merkle_path, other, challenge);
+        assert_ne!(other.nullifier, proof.revealed.nullifier);
+
+        Ok(())
+    }
+}
@@ -22,7 +22,8 @@ use crate::{
             MERKLE_DEPTH_ORCHARD,
         },
     },
-    util::serial::{Decodable, Encodable},
+    impl_vec,
+    util::serial::{Decodable, Encodable, VarInt},
     Result,
 };
 
@@ -130,6 +131,8 @@ impl Decodable for MerkleNode {
     }
 }
 
+impl_vec!(MerkleNode);
+
 impl Encodable for incrementalmerkletree::Position {
     fn encode<S: io::Write>(&self, mut s: S) -> Result<usize> {
         u64::from(*self).encode(&mut s)
@@ -4,7 +4,7 @@ use halo2_gadgets::poseidon::primitives as poseidon;
 use halo2_proofs::circuit::Value;
 use log::debug;
 use pasta_curves::{arithmetic::CurveAffine, group::Curve, pallas};
-use rand::rngs::OsRng;
+use rand::RngCore;
 
 use crate::{
     crypto::{
@@ -76,6 +76,7 @@ pub fn create_mint_proof(
     serial: DrkSerial,
     coin_blind: DrkCoinBlind,
     public_key: PublicKey,
+    mut rng: impl RngCore,
 ) -> Result<(Proof, MintRevealedValues)> {
     let revealed = MintRevealedValues::compute(
         value,
@@ -102,7 +103,7 @@ pub fn create_mint_proof(
 
     let start = Instant::now();
     let public_inputs = revealed.make_outputs();
-    let proof = Proof::create(pk, &[c], &public_inputs, &mut OsRng)?;
+    let proof = Proof::create(pk, &[c], &public_inputs, &mut rng)?;
     debug!("Prove mint: [{:?}]", start.elapsed());
 
     Ok((proof, revealed))
@@ -0,0 +1,85 @@
+use rand::{rngs::OsRng, RngCore};
+
+use crate::{Error, Result};
+
+/// Number of words a mnemonic can have. Each word carries 11 bits, and the
+/// words together carry 32 bits of entropy for every 3 of them, plus a
+/// checksum.
+pub const MNEMONIC_WORD_COUNTS: [usize; 5] = [12, 15, 18, 21, 24];
+
+/// BIP-39 mnemonic, the English words a wallet seed is backed up as. The
+/// seed is made from the words and an optional passphrase, and the wallet
+/// keys are derived from the seed with [`Keypair::derive`].
+///
+/// [`Keypair::derive`]: super::keypair::Keypair::derive
+pub struct Mnemonic(bip39::Mnemonic);
+
+impl Mnemonic {
+    /// Generate a random mnemonic with the given number of words
+    pub fn generate(word_count: usize) -> Result<Self> {
+        if !MNEMONIC_WORD_COUNTS.contains(&word_count) {
+            return Err(Error::InvalidMnemonic(format!("can't have {} words", word_count)))
+        }
+
+        let mut entropy = vec![0u8; word_count / 3 * 4];
+        OsRng.fill_bytes(&mut entropy);
+        Self::from_entropy(&entropy)
+    }
+
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self> {
+        match bip39::Mnemonic::from_entropy(entropy) {
+            Ok(v) => Ok(Self(v)),
+            Err(e) => Err(Error::InvalidMnemonic(e.to_string())),
+        }
+    }
+
+    /// Parse the words of a mnemonic, checking its checksum
+    pub fn from_phrase(phrase: &str) -> Result<Self> {
+        match bip39::Mnemonic::parse(phrase) {
+            Ok(v) => Ok(Self(v)),
+            Err(e) => Err(Error::InvalidMnemonic(e.to_string())),
+        }
+    }
+
+    /// The words, separated by spaces
+    pub fn phrase(&self) -> String {
+        self.0.to_string()
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.0.word_count()
+    }
+
+    pub fn to_seed(&self, passphrase: &str) -> [u8; 64] {
+        self.0.to_seed(passphrase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mnemonic_seed() {
+        // Test vector from BIP-39
+        let mnemonic = Mnemonic::from_entropy(&[0; 16]).unwrap();
+        assert_eq!(mnemonic.phrase(), format!("{} about", ["abandon"; 11].join(" ")));
+        let seed: String =
+            mnemonic.to_seed("TREZOR").iter().map(|x| format!("{:02x}", x)).collect();
+        assert_eq!(
+            seed,
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f\
+             09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+
+        // Restoring from the words gives back the same seed
+        let mnemonic = Mnemonic::generate(24).unwrap();
+        let restored = Mnemonic::from_phrase(&mnemonic.phrase()).unwrap();
+        assert_eq!(restored.word_count(), 24);
+        assert_eq!(restored.to_seed(""), mnemonic.to_seed(""));
+
+        // Bad checksum
+        assert!(Mnemonic::from_phrase(&["abandon"; 12].join(" ")).is_err());
+        assert!(Mnemonic::generate(13).is_err());
+    }
+}
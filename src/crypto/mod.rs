@@ -2,10 +2,14 @@ pub mod address;
 pub mod coin;
 pub mod constants;
 pub mod diffie_hellman;
+pub mod disclosure;
 pub mod keypair;
 //pub mod loader;
 pub mod burn_proof;
+pub mod membership_proof;
 pub mod merkle_node;
+pub mod mnemonic;
+pub mod musig;
 //pub mod point_node;
 pub mod mint_proof;
 pub mod note;
@@ -16,10 +20,13 @@ pub mod token_id;
 pub mod token_list;
 pub mod types;
 pub mod util;
+pub mod vrf;
 
 pub use burn_proof::BurnRevealedValues;
+pub use membership_proof::{MembershipProof, MembershipRevealedValues};
 pub use mint_proof::MintRevealedValues;
 pub use proof::Proof;
+pub use vrf::VrfProof;
 
 //pub mod lead_proof;
 //pub mod leadcoin;
@@ -0,0 +1,224 @@
+//! Multi-signatures, where several parties jointly make a single Schnorr
+//! [`Signature`] that verifies against their aggregated [`PublicKey`],
+//! following the two-round MuSig2 protocol.
+//!
+//! 1. The signers aggregate their public keys with [`KeyAggregation`].
+//! 2. Each signer makes a [`SecretNonce`] for the session, and sends its
+//!    [`PublicNonce`] to the others.
+//! 3. Once all the public nonces are in, each signer starts a
+//!    [`SigningSession`] for the message, signs with its secret key and
+//!    nonce, and sends its [`PartialSignature`] to the others.
+//! 4. The partial signatures are summed up into the signature.
+//!
+//! A secret nonce must never be used for more than one session, which is
+//! why signing consumes it.
+use halo2_gadgets::ecc::chip::FixedPoint;
+use pasta_curves::{
+    group::{ff::Field, Group, GroupEncoding},
+    pallas,
+};
+use rand::RngCore;
+
+use crate::{
+    crypto::{
+        constants::{NullifierK, DRK_MUSIG_KEY_DOMAIN, DRK_MUSIG_NONCE_DOMAIN, DRK_SCHNORR_DOMAIN},
+        keypair::{PublicKey, SecretKey},
+        schnorr::Signature,
+        util::{hash_to_scalar, mod_r_p},
+    },
+    util::serial::{SerialDecodable, SerialEncodable},
+    Error, Result,
+};
+
+/// Public keys of the signers, and the key they sign for together. Each
+/// key is weighted by a coefficient hashed from all of them, so that no
+/// signer can pick its key to cancel out the others.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyAggregation {
+    pubkeys: Vec<PublicKey>,
+    coefficients: Vec<pallas::Scalar>,
+    aggregated: PublicKey,
+}
+
+impl KeyAggregation {
+    /// Aggregate the keys of the signers. All the signers have to pass
+    /// them in the same order.
+    pub fn new(pubkeys: &[PublicKey]) -> Result<Self> {
+        if pubkeys.is_empty() {
+            return Err(Error::MuSigError("no public keys to aggregate"))
+        }
+
+        for (i, public) in pubkeys.iter().enumerate() {
+            if pubkeys[..i].contains(public) {
+                return Err(Error::MuSigError("duplicate public key"))
+            }
+        }
+
+        let all: Vec<u8> = pubkeys.iter().flat_map(|x| x.to_bytes()).collect();
+        let coefficients: Vec<pallas::Scalar> = pubkeys
+            .iter()
+            .map(|x| hash_to_scalar(DRK_MUSIG_KEY_DOMAIN, &all, &x.to_bytes()))
+            .collect();
+
+        let aggregated = pubkeys
+            .iter()
+            .zip(coefficients.iter())
+            .fold(pallas::Point::identity(), |acc, (public, a)| acc + public.0 * a);
+
+        Ok(Self { pubkeys: pubkeys.to_vec(), coefficients, aggregated: PublicKey(aggregated) })
+    }
+
+    /// The key the signature verifies against
+    pub fn public_key(&self) -> PublicKey {
+        self.aggregated
+    }
+
+    fn coefficient(&self, public: &PublicKey) -> Option<pallas::Scalar> {
+        let index = self.pubkeys.iter().position(|x| x == public)?;
+        Some(self.coefficients[index])
+    }
+}
+
+/// Public half of a signer's nonces, sent to the other signers in the
+/// first round.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct PublicNonce {
+    r1: pallas::Point,
+    r2: pallas::Point,
+}
+
+/// A signer's nonces for one signing session
+pub struct SecretNonce {
+    r1: pallas::Scalar,
+    r2: pallas::Scalar,
+}
+
+impl SecretNonce {
+    pub fn random(mut rng: impl RngCore) -> Self {
+        Self { r1: pallas::Scalar::random(&mut rng), r2: pallas::Scalar::random(&mut rng) }
+    }
+
+    pub fn public(&self) -> PublicNonce {
+        let nfk = NullifierK;
+        PublicNonce { r1: nfk.generator() * self.r1, r2: nfk.generator() * self.r2 }
+    }
+}
+
+/// A signer's share of the signature, sent to the other signers in the
+/// second round.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct PartialSignature(pallas::Scalar);
+
+/// Signing of a message by all the signers of a [`KeyAggregation`], once
+/// their public nonces are known.
+pub struct SigningSession {
+    keys: KeyAggregation,
+    /// Weight of the second nonce of every signer
+    b: pallas::Scalar,
+    /// Aggregated nonce, the commitment of the signature
+    commit: pallas::Point,
+    challenge: pallas::Scalar,
+}
+
+impl SigningSession {
+    /// `nonces` are the public nonces of all the signers, in any order.
+    pub fn new(keys: &KeyAggregation, nonces: &[PublicNonce], message: &[u8]) -> Result<Self> {
+        if nonces.len() != keys.pubkeys.len() {
+            return Err(Error::MuSigError("need one nonce per signer"))
+        }
+
+        let r1 = nonces.iter().fold(pallas::Point::identity(), |acc, x| acc + x.r1);
+        let r2 = nonces.iter().fold(pallas::Point::identity(), |acc, x| acc + x.r2);
+
+        let points: Vec<u8> =
+            [keys.aggregated.0, r1, r2].iter().flat_map(|x| x.to_bytes()).collect();
+        let b = hash_to_scalar(DRK_MUSIG_NONCE_DOMAIN, &points, message);
+        let commit = r1 + r2 * b;
+
+        // The same challenge as a single-party signature, so that the
+        // result verifies like one
+        let challenge = hash_to_scalar(DRK_SCHNORR_DOMAIN, &commit.to_bytes(), message);
+
+        Ok(Self { keys: keys.clone(), b, commit, challenge })
+    }
+
+    /// Make our share of the signature, using up our nonce.
+    pub fn sign(&self, secret: &SecretKey, nonce: SecretNonce) -> Result<PartialSignature> {
+        let a = match self.keys.coefficient(&PublicKey::from_secret(*secret)) {
+            Some(v) => v,
+            None => return Err(Error::MuSigError("secret key isn't one of the signers")),
+        };
+
+        let response = nonce.r1 + nonce.r2 * self.b + self.challenge * a * mod_r_p(secret.0);
+        Ok(PartialSignature(response))
+    }
+
+    /// Check the share of the signer with the given key and public nonce,
+    /// to find out who to blame when the signature doesn't verify.
+    pub fn verify_partial(
+        &self,
+        public: &PublicKey,
+        nonce: &PublicNonce,
+        partial: &PartialSignature,
+    ) -> bool {
+        let a = match self.keys.coefficient(public) {
+            Some(v) => v,
+            None => return false,
+        };
+
+        let nfk = NullifierK;
+        let expected = nonce.r1 + nonce.r2 * self.b + public.0 * (self.challenge * a);
+        nfk.generator() * partial.0 == expected
+    }
+
+    /// Sum up the shares of all the signers into the signature
+    pub fn aggregate(&self, partials: &[PartialSignature]) -> Signature {
+        let response = partials.iter().fold(pallas::Scalar::zero(), |acc, x| acc + x.0);
+        Signature { commit: self.commit, response }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::schnorr::SchnorrPublic;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_musig() {
+        let secrets: Vec<SecretKey> = (0..3).map(|_| SecretKey::random(&mut OsRng)).collect();
+        let pubkeys: Vec<PublicKey> = secrets.iter().map(|x| PublicKey::from_secret(*x)).collect();
+        let keys = KeyAggregation::new(&pubkeys).unwrap();
+        let message = b"Foo bar";
+
+        // First round
+        let nonces: Vec<SecretNonce> = (0..3).map(|_| SecretNonce::random(&mut OsRng)).collect();
+        let public_nonces: Vec<PublicNonce> = nonces.iter().map(|x| x.public()).collect();
+
+        // Second round
+        let session = SigningSession::new(&keys, &public_nonces, message).unwrap();
+        let partials: Vec<PartialSignature> = secrets
+            .iter()
+            .zip(nonces)
+            .map(|(secret, nonce)| session.sign(secret, nonce).unwrap())
+            .collect();
+
+        for i in 0..3 {
+            assert!(session.verify_partial(&pubkeys[i], &public_nonces[i], &partials[i]));
+        }
+        assert!(!session.verify_partial(&pubkeys[0], &public_nonces[0], &partials[1]));
+
+        let signature = session.aggregate(&partials);
+        assert!(keys.public_key().verify(message, &signature));
+        assert!(!keys.public_key().verify(b"Foo baz", &signature));
+
+        // All the signers are needed
+        let signature = session.aggregate(&partials[..2]);
+        assert!(!keys.public_key().verify(message, &signature));
+
+        // Outsiders can't sign
+        let outsider = SecretKey::random(&mut OsRng);
+        assert!(session.sign(&outsider, SecretNonce::random(&mut OsRng)).is_err());
+        assert!(KeyAggregation::new(&[pubkeys[0], pubkeys[0]]).is_err());
+    }
+}
@@ -1,10 +1,13 @@
+use blake2b_simd::{Hash as Blake2bHash, Params as Blake2bParams};
 use crypto_api_chachapoly::ChachaPolyIetf;
-use rand::rngs::OsRng;
+use rand::{rngs::OsRng, RngCore};
+use subtle::ConstantTimeEq;
 
 use crate::{
     crypto::{
         diffie_hellman::{kdf_sapling, sapling_ka_agree},
         keypair::{PublicKey, SecretKey},
+        token_id::MetadataCommitment,
         types::{DrkCoinBlind, DrkSerial, DrkTokenId, DrkValueBlind},
     },
     util::serial::{Decodable, Encodable, SerialDecodable, SerialEncodable},
@@ -13,8 +16,106 @@ use crate::{
 
 /// Plaintext size is serial + value + token_id + coin_blind + value_blind
 pub const NOTE_PLAINTEXT_SIZE: usize = 32 + 8 + 32 + 32 + 32 + 32;
+/// Space a memo takes in the note, its length byte included
+pub const NOTE_MEMO_SIZE: usize = 128;
+/// Space the optional metadata commitment takes in the note
+pub const NOTE_METADATA_SIZE: usize = 1 + 32;
 pub const AEAD_TAG_SIZE: usize = 16;
+/// Ciphertext size of notes without a memo
 pub const ENC_CIPHERTEXT_SIZE: usize = NOTE_PLAINTEXT_SIZE + AEAD_TAG_SIZE;
+/// Ciphertext size of notes with a memo
+pub const ENC_MEMO_CIPHERTEXT_SIZE: usize = ENC_CIPHERTEXT_SIZE + NOTE_MEMO_SIZE;
+/// Ciphertext size of notes with a memo and room for a metadata commitment
+pub const ENC_METADATA_CIPHERTEXT_SIZE: usize = ENC_MEMO_CIPHERTEXT_SIZE + NOTE_METADATA_SIZE;
+
+/// Notes carrying a commitment to the encryption key. Notes from before
+/// versioning had no header and aren't decodable anymore.
+pub const NOTE_VERSION_KEY_COMMITTED: u8 = 1;
+/// Key-committed notes carrying a memo
+pub const NOTE_VERSION_MEMO: u8 = 2;
+/// Notes with a memo, and the metadata commitment of unique assets
+pub const NOTE_VERSION_METADATA: u8 = 3;
+
+pub const NOTE_ENC_KEY_PERSONALIZATION: &[u8; 16] = b"DarkFiNoteEncKey";
+pub const NOTE_KEY_COMMIT_PERSONALIZATION: &[u8; 16] = b"DarkFiNoteKeyCom";
+
+/// Poly1305 is not key-committing, so a ciphertext can be crafted to open
+/// under several keys. We derive the AEAD key and a commitment to it from
+/// the same KDF output, and check the commitment before opening the box.
+fn note_key_commitment(key: &Blake2bHash) -> (Blake2bHash, Blake2bHash) {
+    let derive = |personal: &[u8; 16]| {
+        Blake2bParams::new()
+            .hash_length(32)
+            .personal(personal)
+            .to_state()
+            .update(key.as_bytes())
+            .finalize()
+    };
+
+    (derive(NOTE_ENC_KEY_PERSONALIZATION), derive(NOTE_KEY_COMMIT_PERSONALIZATION))
+}
+
+/// Bytes the sender of a payment attaches to it for the recipient, e.g.
+/// an invoice reference. Memos are padded to the same size, so their
+/// length doesn't show in the encrypted note.
+///
+/// The memo isn't committed to by the coin or checked by any proof, so it
+/// is an unauthenticated hint: whoever made the note can put anything in
+/// it, and nothing should be trusted or credited based on it alone.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Memo([u8; NOTE_MEMO_SIZE]);
+
+impl Memo {
+    /// Longest memo that fits in a note
+    pub const MAX_LEN: usize = NOTE_MEMO_SIZE - 1;
+
+    pub fn new(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() > Self::MAX_LEN {
+            return Err(Error::MemoTooLong(bytes.len()))
+        }
+
+        let mut memo = [0u8; NOTE_MEMO_SIZE];
+        memo[0] = bytes.len() as u8;
+        memo[1..=bytes.len()].copy_from_slice(bytes);
+        Ok(Self(memo))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[1..=self.0[0] as usize]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0[0] == 0
+    }
+}
+
+impl Default for Memo {
+    fn default() -> Self {
+        Self([0u8; NOTE_MEMO_SIZE])
+    }
+}
+
+impl std::fmt::Debug for Memo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Memo({:?})", String::from_utf8_lossy(self.as_bytes()))
+    }
+}
+
+impl Encodable for Memo {
+    fn encode<S: std::io::Write>(&self, s: S) -> Result<usize> {
+        self.0.encode(s)
+    }
+}
+
+impl Decodable for Memo {
+    fn decode<D: std::io::Read>(d: D) -> Result<Self> {
+        let memo: [u8; NOTE_MEMO_SIZE] = Decodable::decode(d)?;
+        if memo[0] as usize > Self::MAX_LEN {
+            return Err(Error::MemoTooLong(memo[0] as usize))
+        }
+        Ok(Self(memo))
+    }
+}
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, SerialEncodable, SerialDecodable)]
 pub struct Note {
@@ -24,48 +125,119 @@ pub struct Note {
     pub coin_blind: DrkCoinBlind,
     pub value_blind: DrkValueBlind,
     pub token_blind: DrkValueBlind,
+    /// Only readable by the recipient. Not part of the coin, so it's an
+    /// unauthenticated hint, see [`Memo`].
+    pub memo: Memo,
+    /// Metadata of the unique asset `token_id` is derived from, if it is one
+    pub metadata: Option<MetadataCommitment>,
 }
 
 impl Note {
     pub fn encrypt(&self, public: &PublicKey) -> Result<EncryptedNote> {
-        let ephem_secret = SecretKey::random(&mut OsRng);
+        self.encrypt_with_rng(public, &mut OsRng)
+    }
+
+    pub fn encrypt_with_rng(
+        &self,
+        public: &PublicKey,
+        mut rng: impl RngCore,
+    ) -> Result<EncryptedNote> {
+        let ephem_secret = SecretKey::random(&mut rng);
         let ephem_public = PublicKey::from_secret(ephem_secret);
         let shared_secret = sapling_ka_agree(&ephem_secret, public);
         let key = kdf_sapling(&shared_secret, &ephem_public);
+        let (enc_key, key_commitment) = note_key_commitment(&key);
 
+        // Padded, so notes with and without metadata look the same
         let mut input = Vec::new();
         self.encode(&mut input)?;
+        input.resize(NOTE_PLAINTEXT_SIZE + NOTE_MEMO_SIZE + NOTE_METADATA_SIZE, 0);
 
-        let mut ciphertext = [0u8; ENC_CIPHERTEXT_SIZE];
+        let version = NOTE_VERSION_METADATA;
+        let mut ciphertext = vec![0u8; ENC_METADATA_CIPHERTEXT_SIZE];
         assert_eq!(
             ChachaPolyIetf::aead_cipher()
-                .seal_to(&mut ciphertext, &input, &[], key.as_ref(), &[0u8; 12])
+                .seal_to(&mut ciphertext, &input, &[version], enc_key.as_ref(), &[0u8; 12])
                 .unwrap(),
-            ENC_CIPHERTEXT_SIZE
+            ENC_METADATA_CIPHERTEXT_SIZE
         );
 
-        Ok(EncryptedNote { ciphertext, ephem_public })
+        let key_commitment = key_commitment.as_bytes().try_into().unwrap();
+        Ok(EncryptedNote { version, key_commitment, ciphertext, ephem_public })
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EncryptedNote {
-    ciphertext: [u8; ENC_CIPHERTEXT_SIZE],
+    version: u8,
+    key_commitment: [u8; 32],
+    ciphertext: Vec<u8>,
     ephem_public: PublicKey,
 }
 
+/// Size of the ciphertext of a note of the given version
+fn ciphertext_size(version: u8) -> Result<usize> {
+    match version {
+        NOTE_VERSION_KEY_COMMITTED => Ok(ENC_CIPHERTEXT_SIZE),
+        NOTE_VERSION_MEMO => Ok(ENC_MEMO_CIPHERTEXT_SIZE),
+        NOTE_VERSION_METADATA => Ok(ENC_METADATA_CIPHERTEXT_SIZE),
+        _ => Err(Error::ParseFailed("Unknown note version")),
+    }
+}
+
+// The ciphertext size follows from the version, so it isn't encoded
+impl Encodable for EncryptedNote {
+    fn encode<S: std::io::Write>(&self, mut s: S) -> Result<usize> {
+        let mut len = 0;
+        len += self.version.encode(&mut s)?;
+        len += self.key_commitment.encode(&mut s)?;
+        s.write_all(&self.ciphertext)?;
+        len += self.ciphertext.len();
+        len += self.ephem_public.encode(&mut s)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for EncryptedNote {
+    fn decode<D: std::io::Read>(mut d: D) -> Result<Self> {
+        let version: u8 = Decodable::decode(&mut d)?;
+        let key_commitment = Decodable::decode(&mut d)?;
+        let mut ciphertext = vec![0u8; ciphertext_size(version)?];
+        d.read_exact(&mut ciphertext)?;
+        let ephem_public = Decodable::decode(&mut d)?;
+        Ok(Self { version, key_commitment, ciphertext, ephem_public })
+    }
+}
+
 impl EncryptedNote {
     pub fn decrypt(&self, secret: &SecretKey) -> Result<Note> {
+        if self.ciphertext.len() != ciphertext_size(self.version)? {
+            return Err(Error::NoteDecryptionFailed)
+        }
+
         let shared_secret = sapling_ka_agree(secret, &self.ephem_public);
         let key = kdf_sapling(&shared_secret, &self.ephem_public);
 
-        let mut plaintext = [0; ENC_CIPHERTEXT_SIZE];
-        assert_eq!(
-            ChachaPolyIetf::aead_cipher()
-                .open_to(&mut plaintext, &self.ciphertext, &[], key.as_ref(), &[0u8; 12])
-                .map_err(|_| Error::NoteDecryptionFailed)?,
-            NOTE_PLAINTEXT_SIZE
-        );
+        let (enc_key, key_commitment) = note_key_commitment(&key);
+        if !bool::from(key_commitment.as_bytes().ct_eq(&self.key_commitment)) {
+            return Err(Error::NoteDecryptionFailed)
+        }
+
+        let aad = [self.version];
+        let mut plaintext = vec![0; self.ciphertext.len()];
+        let len = ChachaPolyIetf::aead_cipher()
+            .open_to(&mut plaintext, &self.ciphertext, &aad, enc_key.as_ref(), &[0u8; 12])
+            .map_err(|_| Error::NoteDecryptionFailed)?;
+        plaintext.truncate(len);
+
+        // Notes from before memos get an empty one, and notes from before
+        // metadata get none
+        if self.version < NOTE_VERSION_MEMO {
+            Memo::default().encode(&mut plaintext)?;
+        }
+        if self.version < NOTE_VERSION_METADATA {
+            None::<MetadataCommitment>.encode(&mut plaintext)?;
+        }
 
         Note::decode(&plaintext[..])
     }
@@ -74,8 +246,12 @@ impl EncryptedNote {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::crypto::keypair::Keypair;
+    use crate::{
+        crypto::keypair::Keypair,
+        util::serial::{deserialize, serialize},
+    };
     use group::ff::Field;
+    use rand::{rngs::StdRng, SeedableRng};
 
     #[test]
     fn test_note_encdec() {
@@ -86,6 +262,8 @@ mod tests {
             coin_blind: DrkCoinBlind::random(&mut OsRng),
             value_blind: DrkValueBlind::random(&mut OsRng),
             token_blind: DrkValueBlind::random(&mut OsRng),
+            memo: Memo::default(),
+            metadata: None,
         };
 
         let keypair = Keypair::random(&mut OsRng);
@@ -95,5 +273,71 @@ mod tests {
         assert_eq!(note.value, note2.value);
         assert_eq!(note.token_id, note2.token_id);
         assert_eq!(note.token_blind, note2.token_blind);
+
+        // A note opened under a different key must be rejected
+        let other = Keypair::random(&mut OsRng);
+        assert!(encrypted_note.decrypt(&other.secret).is_err());
+
+        // Unknown versions are refused
+        let mut unversioned = encrypted_note.clone();
+        unversioned.version = 0;
+        assert!(unversioned.decrypt(&keypair.secret).is_err());
+    }
+
+    #[test]
+    fn test_note_encrypt_deterministic() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let note = Note {
+            serial: DrkSerial::random(&mut rng),
+            value: 110,
+            token_id: DrkTokenId::random(&mut rng),
+            coin_blind: DrkCoinBlind::random(&mut rng),
+            value_blind: DrkValueBlind::random(&mut rng),
+            token_blind: DrkValueBlind::random(&mut rng),
+            memo: Memo::default(),
+            metadata: None,
+        };
+        let keypair = Keypair::random(&mut rng);
+
+        let enc1 = note.encrypt_with_rng(&keypair.public, StdRng::seed_from_u64(1)).unwrap();
+        let enc2 = note.encrypt_with_rng(&keypair.public, StdRng::seed_from_u64(1)).unwrap();
+        assert_eq!(enc1, enc2);
+        assert_eq!(enc1.decrypt(&keypair.secret).unwrap(), note);
+    }
+
+    #[test]
+    fn test_note_memo() {
+        let note = Note {
+            serial: DrkSerial::random(&mut OsRng),
+            value: 110,
+            token_id: DrkTokenId::random(&mut OsRng),
+            coin_blind: DrkCoinBlind::random(&mut OsRng),
+            value_blind: DrkValueBlind::random(&mut OsRng),
+            token_blind: DrkValueBlind::random(&mut OsRng),
+            memo: Memo::new(b"Invoice #1337").unwrap(),
+            metadata: None,
+        };
+        let keypair = Keypair::random(&mut OsRng);
+
+        let encrypted_note = note.encrypt(&keypair.public).unwrap();
+        let encoded = serialize(&encrypted_note);
+        let decoded: EncryptedNote = deserialize(&encoded).unwrap();
+        let note2 = decoded.decrypt(&keypair.secret).unwrap();
+        assert_eq!(note2.memo.as_bytes(), b"Invoice #1337");
+
+        // Short and long memos make notes of the same size
+        let mut empty = note;
+        empty.memo = Memo::default();
+        assert_eq!(serialize(&empty.encrypt(&keypair.public).unwrap()).len(), encoded.len());
+
+        assert!(Memo::new(&[0u8; Memo::MAX_LEN]).is_ok());
+        assert!(Memo::new(&[0u8; Memo::MAX_LEN + 1]).is_err());
+
+        // So do notes with metadata
+        let mut unique = note;
+        unique.metadata = Some(MetadataCommitment::new(b"Foo bar"));
+        let encrypted = unique.encrypt(&keypair.public).unwrap();
+        assert_eq!(serialize(&encrypted).len(), encoded.len());
+        assert_eq!(encrypted.decrypt(&keypair.secret).unwrap(), unique);
     }
 }
@@ -5,7 +5,8 @@ use pasta_curves::{group::ff::PrimeField, pallas};
 
 use crate::{
     crypto::keypair::SecretKey,
-    util::serial::{Decodable, Encodable, ReadExt, WriteExt},
+    impl_vec,
+    util::serial::{Decodable, Encodable, ReadExt, VarInt, WriteExt},
     Result,
 };
 
@@ -49,3 +50,5 @@ impl Decodable for Nullifier {
         Ok(result)
     }
 }
+
+impl_vec!(Nullifier);
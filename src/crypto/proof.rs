@@ -2,12 +2,14 @@ use std::io;
 
 use halo2_proofs::{
     plonk,
-    plonk::{Circuit, SingleVerifier},
+    plonk::{BatchVerifier, Circuit, SingleVerifier},
     poly::commitment::Params,
     transcript::{Blake2bRead, Blake2bWrite},
 };
+use log::warn;
 use pasta_curves::vesta;
 use rand::RngCore;
+use rayon::prelude::*;
 
 use crate::{
     crypto::types::*,
@@ -44,6 +46,75 @@ impl ProvingKey {
     }
 }
 
+/// Environment variable used to set the number of prover threads.
+/// Defaults to the number of logical CPUs when unset or invalid.
+#[cfg(feature = "accel-msm")]
+pub const PROVER_THREADS_ENV: &str = "DARKFI_PROVER_THREADS";
+
+/// halo2 runs its multi-scalar multiplications and FFTs on the global
+/// rayon pool, so we size it once before the first proof is created.
+/// If the pool was already configured elsewhere, we keep using it.
+#[cfg(feature = "accel-msm")]
+fn init_prover_pool() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+
+    INIT.call_once(|| {
+        let threads = match std::env::var(PROVER_THREADS_ENV) {
+            Ok(v) => v.parse::<usize>().unwrap_or(0),
+            Err(_) => 0,
+        };
+
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("prover-{}", i))
+            .build_global()
+        {
+            warn!("Unable to configure prover thread pool, using default: {}", e);
+        }
+    });
+}
+
+/// Thread pool creating several proofs at once, like the ones for all the
+/// inputs and outputs of a transaction.
+pub struct ParallelProver {
+    /// `None` when the pool couldn't be set up, proving one by one instead
+    pool: Option<rayon::ThreadPool>,
+}
+
+impl ParallelProver {
+    /// Set up a pool of `threads` threads, or of one per logical CPU with 0.
+    pub fn new(threads: usize) -> Self {
+        let pool = match rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("parallel-prover-{}", i))
+            .build()
+        {
+            Ok(v) => Some(v),
+            Err(e) => {
+                warn!("Unable to set up parallel prover thread pool, proving serially: {}", e);
+                None
+            }
+        };
+
+        Self { pool }
+    }
+
+    /// Run `prove` on every job concurrently, returning the results in the
+    /// same order as the jobs. Proofs made by `prove` also run their own
+    /// multi-scalar multiplications and FFTs on this pool.
+    pub fn prove<J, T, F>(&self, jobs: Vec<J>, prove: F) -> Vec<T>
+    where
+        J: Send,
+        T: Send,
+        F: Fn(J) -> T + Send + Sync,
+    {
+        match &self.pool {
+            Some(pool) => pool.install(|| jobs.into_par_iter().map(prove).collect()),
+            None => jobs.into_iter().map(prove).collect(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Proof(Vec<u8>);
 
@@ -60,6 +131,9 @@ impl Proof {
         instances: &[DrkCircuitField],
         mut rng: impl RngCore,
     ) -> std::result::Result<Self, plonk::Error> {
+        #[cfg(feature = "accel-msm")]
+        init_prover_pool();
+
         let mut transcript = Blake2bWrite::<_, vesta::Affine, _>::init(vec![]);
 
         plonk::create_proof(
@@ -85,6 +159,39 @@ impl Proof {
         plonk::verify_proof(&vk.params, &vk.vk, strategy, &[&[instances]], &mut transcript)
     }
 
+    /// Verify a batch of proofs with their verifying keys and public inputs.
+    /// The proofs sharing a verifying key are accumulated into a single
+    /// multi-scalar multiplication, which is a lot faster than verifying
+    /// them one by one. Keys are told apart by reference, so proofs for the
+    /// same circuit should be given the same `VerifyingKey`.
+    ///
+    /// This only tells whether all the proofs are valid. Use `verify` to
+    /// find the invalid ones when the batch fails.
+    pub fn verify_batch(
+        batch: &[(&VerifyingKey, &Proof, &[DrkCircuitField])],
+    ) -> std::result::Result<(), plonk::Error> {
+        let mut verifiers: Vec<(&VerifyingKey, BatchVerifier<vesta::Affine>)> = vec![];
+        for (vk, proof, instances) in batch {
+            let idx = match verifiers.iter().position(|(x, _)| std::ptr::eq(*x, *vk)) {
+                Some(v) => v,
+                None => {
+                    verifiers.push((vk, BatchVerifier::new()));
+                    verifiers.len() - 1
+                }
+            };
+
+            verifiers[idx].1.add_proof(vec![vec![instances.to_vec()]], proof.0.clone());
+        }
+
+        let valid = verifiers.into_par_iter().all(|(vk, v)| v.finalize(&vk.params, &vk.vk));
+
+        if !valid {
+            return Err(plonk::Error::ConstraintSystemFailure)
+        }
+
+        Ok(())
+    }
+
     pub fn new(bytes: Vec<u8>) -> Self {
         Proof(bytes)
     }
@@ -110,7 +217,7 @@ mod tests {
     use super::*;
     use crate::{
         crypto::{keypair::PublicKey, mint_proof::create_mint_proof},
-        zk::circuit::MintContract,
+        zk::circuit::{BurnContract, MintContract},
     };
     use group::ff::Field;
     use rand::rngs::OsRng;
@@ -135,6 +242,7 @@ mod tests {
             serial,
             coin_blind,
             public_key,
+            &mut OsRng,
         )?;
 
         let mut buf = vec![];
@@ -144,4 +252,50 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_proof_verify_batch() -> Result<()> {
+        let pk = ProvingKey::build(11, &MintContract::default());
+        let vk = VerifyingKey::build(11, &MintContract::default());
+        let other_vk = VerifyingKey::build(11, &BurnContract::default());
+
+        let mut proofs = vec![];
+        for value in [1_u64, 2, 3] {
+            let (proof, revealed) = create_mint_proof(
+                &pk,
+                value,
+                DrkTokenId::from(42),
+                DrkValueBlind::random(&mut OsRng),
+                DrkValueBlind::random(&mut OsRng),
+                DrkSerial::random(&mut OsRng),
+                DrkCoinBlind::random(&mut OsRng),
+                PublicKey::random(&mut OsRng),
+                &mut OsRng,
+            )?;
+            proofs.push((proof, revealed.make_outputs()));
+        }
+
+        let batch: Vec<_> = proofs.iter().map(|(p, i)| (&vk, p, &i[..])).collect();
+        assert!(Proof::verify_batch(&batch).is_ok());
+        assert!(Proof::verify_batch(&[]).is_ok());
+
+        // Public inputs that don't match their proof fail the whole batch
+        let mut batch = batch;
+        batch[1].2 = &proofs[0].1[..];
+        assert!(Proof::verify_batch(&batch).is_err());
+
+        // So does a proof checked against the wrong circuit
+        let batch = vec![(&vk, &proofs[0].0, &proofs[0].1[..]), (&other_vk, &proofs[1].0, &[][..])];
+        assert!(Proof::verify_batch(&batch).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_prover() {
+        // Results come back in the order of the jobs
+        let jobs: Vec<u64> = (0..32).collect();
+        let expected: Vec<u64> = jobs.iter().map(|x| x * 2).collect();
+        assert_eq!(ParallelProver::new(4).prove(jobs, |x| x * 2), expected);
+    }
 }
@@ -5,7 +5,7 @@ use pasta_curves::{
     group::{ff::Field, GroupEncoding},
     pallas,
 };
-use rand::rngs::OsRng;
+use rand::{rngs::OsRng, RngCore};
 
 use crate::{
     crypto::{
@@ -19,12 +19,16 @@ use crate::{
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Signature {
-    commit: pallas::Point,
-    response: pallas::Scalar,
+    pub(crate) commit: pallas::Point,
+    pub(crate) response: pallas::Scalar,
 }
 
 pub trait SchnorrSecret {
-    fn sign(&self, message: &[u8]) -> Signature;
+    fn sign(&self, message: &[u8]) -> Signature {
+        self.sign_with_rng(&mut OsRng, message)
+    }
+
+    fn sign_with_rng(&self, rng: impl RngCore, message: &[u8]) -> Signature;
 }
 
 pub trait SchnorrPublic {
@@ -32,8 +36,8 @@ pub trait SchnorrPublic {
 }
 
 impl SchnorrSecret for SecretKey {
-    fn sign(&self, message: &[u8]) -> Signature {
-        let mask = pallas::Scalar::random(&mut OsRng);
+    fn sign_with_rng(&self, mut rng: impl RngCore, message: &[u8]) -> Signature {
+        let mask = pallas::Scalar::random(&mut rng);
         let nfk = NullifierK;
         let commit = nfk.generator() * mask;
 
@@ -1,5 +1,30 @@
-use super::types::DrkTokenId;
-use crate::{util::NetworkName, Result};
+use pasta_curves::group::ff::PrimeField;
+
+use super::{
+    constants::{DRK_ISSUED_TOKEN_DOMAIN, DRK_UNIQUE_ASSET_DOMAIN},
+    nullifier::Nullifier,
+    types::DrkTokenId,
+    util::hash_to_base,
+};
+use crate::{
+    util::{
+        serial::{SerialDecodable, SerialEncodable},
+        NetworkName,
+    },
+    Result,
+};
+
+/// Commitment to the content of a unique asset, e.g. an image and its
+/// description, which is kept off-chain. Travels in the notes of the
+/// asset's coins, so each holder can check the token ID against it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct MetadataCommitment(pub [u8; 32]);
+
+impl MetadataCommitment {
+    pub fn new(content: &[u8]) -> Self {
+        Self(blake3::derive_key("DarkFi 2022-07 unique asset metadata", content))
+    }
+}
 
 pub fn generate_id(network: &NetworkName, token_str: &str) -> Result<DrkTokenId> {
     let mut net_bytes: Vec<u8> = network.to_string().as_bytes().to_vec();
@@ -7,7 +32,7 @@ pub fn generate_id(network: &NetworkName, token_str: &str) -> Result<DrkTokenId>
     let mut token_bytes = match network {
         NetworkName::DarkFi => bs58::decode(token_str).into_vec()?,
         NetworkName::Bitcoin => bs58::decode(token_str).into_vec()?,
-        NetworkName::Ethereum => hex::decode(token_str.strip_prefix("0x").unwrap())?,
+        NetworkName::Ethereum => hex::decode(token_str.strip_prefix("0x").unwrap_or(token_str))?,
         NetworkName::Solana => bs58::decode(token_str).into_vec()?,
     };
 
@@ -20,3 +45,24 @@ pub fn generate_id(network: &NetworkName, token_str: &str) -> Result<DrkTokenId>
 
     Ok(DrkTokenId::from(u64::from_le_bytes(data)))
 }
+
+/// Token ID of the unique asset with the given metadata. Like all token
+/// IDs it fits in 64 bits, which the circuits range check.
+pub fn generate_unique_id(metadata: &MetadataCommitment) -> DrkTokenId {
+    let hash = blake3::derive_key("DarkFi 2022-07 unique asset token ID", &metadata.0);
+    let data: [u8; 8] = hash[0..8].try_into().unwrap();
+    DrkTokenId::from(u64::from_le_bytes(data))
+}
+
+/// Nullifier added to the nullifier set when a unique asset is issued, so
+/// that it can only be issued once.
+pub fn unique_asset_nullifier(token_id: DrkTokenId) -> Nullifier {
+    Nullifier(hash_to_base(DRK_UNIQUE_ASSET_DOMAIN, &token_id.to_repr(), &[]))
+}
+
+/// Nullifier added to the nullifier set when a token is first issued
+/// without metadata. A unique asset can't be issued with the ID of a token
+/// that has one, so its supply can't be minted before the asset is.
+pub fn issued_token_nullifier(token_id: DrkTokenId) -> Nullifier {
+    Nullifier(hash_to_base(DRK_ISSUED_TOKEN_DOMAIN, &token_id.to_repr(), &[]))
+}
@@ -5,7 +5,7 @@ use group::ff::PrimeField;
 use serde_json::Value;
 
 use super::{token_id::generate_id, types::DrkTokenId};
-use crate::{util::NetworkName, Result};
+use crate::{util::NetworkName, Error, Result};
 
 #[derive(Clone, Debug)]
 pub struct TokenInfo {
@@ -19,19 +19,22 @@ pub struct TokenInfo {
 pub struct TokenList(pub FxHashMap<String, TokenInfo>);
 
 impl TokenList {
-    /// Create a new `TokenList` given a standard JSON object (as bytes)
+    /// Create a new `TokenList` given a standard JSON object (as bytes).
+    /// Lists may be fetched at runtime, so malformed ones are an error.
     pub fn new(network_name: &str, data: &[u8]) -> Result<Self> {
         let tokenlist: Value = serde_json::from_slice(data)?;
+        let network_name = NetworkName::from_str(network_name)?;
+        let malformed = || Error::ParseFailed("Malformed token list");
 
         let mut map = FxHashMap::default();
-        for i in tokenlist["tokens"].as_array().unwrap() {
-            let net_address = i["address"].as_str().unwrap().to_string();
-            let decimals = i["decimals"].as_u64().unwrap();
-            let name = i["name"].as_str().unwrap().to_string();
-            let drk_address = generate_id(&NetworkName::from_str(network_name)?, &net_address)?;
+        for i in tokenlist["tokens"].as_array().ok_or_else(malformed)? {
+            let net_address = i["address"].as_str().ok_or_else(malformed)?.to_string();
+            let decimals = i["decimals"].as_u64().ok_or_else(malformed)?;
+            let name = i["name"].as_str().ok_or_else(malformed)?.to_string();
+            let drk_address = generate_id(&network_name, &net_address)?;
 
             let info = TokenInfo { net_address, drk_address, decimals, name };
-            let ticker = i["symbol"].as_str().unwrap().to_uppercase().to_string();
+            let ticker = i["symbol"].as_str().ok_or_else(malformed)?.to_uppercase();
             map.insert(ticker, info);
         }
 
@@ -57,21 +60,24 @@ pub struct DrkTokenList {
 
 impl DrkTokenList {
     pub fn new(data: &[(&str, &[u8])]) -> Result<Self> {
-        let mut by_net = FxHashMap::default();
-        let mut by_addr = FxHashMap::default();
+        let mut lists = Self { by_net: FxHashMap::default(), by_addr: FxHashMap::default() };
 
         for (name, json) in data {
-            let net_name = NetworkName::from_str(name)?;
-            let tokenlist = TokenList::new(name, json)?;
-            for (_, token) in tokenlist.0.iter() {
-                by_addr.insert(
-                    bs58::encode(token.drk_address.to_repr()).into_string(),
-                    (net_name.clone(), token.clone()),
-                );
-            }
-            by_net.insert(net_name, tokenlist);
+            lists.insert(NetworkName::from_str(name)?, TokenList::new(name, json)?);
         }
 
-        Ok(Self { by_net, by_addr })
+        Ok(lists)
+    }
+
+    /// Set the token list of a network, replacing the one it had
+    pub fn insert(&mut self, net_name: NetworkName, tokenlist: TokenList) {
+        self.by_addr.retain(|_, (net, _)| *net != net_name);
+        for (_, token) in tokenlist.0.iter() {
+            self.by_addr.insert(
+                bs58::encode(token.drk_address.to_repr()).into_string(),
+                (net_name.clone(), token.clone()),
+            );
+        }
+        self.by_net.insert(net_name, tokenlist);
     }
 }
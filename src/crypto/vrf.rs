@@ -0,0 +1,116 @@
+//! Verifiable random function over pallas, following the ECVRF
+//! construction. Only the owner of a secret key can compute the output
+//! for a given input, the output looks random to anyone else, and the
+//! [`VrfProof`] lets anyone holding the public key check it.
+use halo2_gadgets::ecc::chip::FixedPoint;
+use pasta_curves::{
+    arithmetic::CurveExt,
+    group::{ff::Field, GroupEncoding},
+    pallas,
+};
+use rand::{rngs::OsRng, RngCore};
+
+use crate::{
+    crypto::{
+        constants::{
+            NullifierK, DRK_VRF_CHALLENGE_DOMAIN, DRK_VRF_INPUT_DOMAIN, DRK_VRF_OUTPUT_DOMAIN,
+        },
+        keypair::{PublicKey, SecretKey},
+        util::{hash_to_scalar, mod_r_p},
+    },
+    util::serial::{SerialDecodable, SerialEncodable},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct VrfProof {
+    /// The secret key times the input point, the output is hashed from it
+    gamma: pallas::Point,
+    challenge: pallas::Scalar,
+    response: pallas::Scalar,
+}
+
+impl VrfProof {
+    /// Evaluate the VRF on `input` with the secret key
+    pub fn prove(secret: &SecretKey, input: &[u8]) -> Self {
+        Self::prove_with_rng(&mut OsRng, secret, input)
+    }
+
+    pub fn prove_with_rng(mut rng: impl RngCore, secret: &SecretKey, input: &[u8]) -> Self {
+        let public = PublicKey::from_secret(*secret);
+        let x = mod_r_p(secret.0);
+        let h = input_point(&public, input);
+        let gamma = h * x;
+
+        let mask = pallas::Scalar::random(&mut rng);
+        let nfk = NullifierK;
+        let challenge = challenge(&public, &h, &gamma, &(nfk.generator() * mask), &(h * mask));
+        let response = mask + challenge * x;
+
+        Self { gamma, challenge, response }
+    }
+
+    /// Check that the proof was made by the owner of `public` for `input`
+    pub fn verify(&self, public: &PublicKey, input: &[u8]) -> bool {
+        let h = input_point(public, input);
+        let nfk = NullifierK;
+        let u = nfk.generator() * self.response - public.0 * self.challenge;
+        let v = h * self.response - self.gamma * self.challenge;
+        challenge(public, &h, &self.gamma, &u, &v) == self.challenge
+    }
+
+    /// The VRF output. It is only meaningful once the proof is verified.
+    pub fn output(&self) -> blake3::Hash {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(DRK_VRF_OUTPUT_DOMAIN);
+        hasher.update(&self.gamma.to_bytes());
+        hasher.finalize()
+    }
+}
+
+/// The input is mapped to a point bound to the public key, so the same
+/// input gives unrelated outputs for different keys.
+fn input_point(public: &PublicKey, input: &[u8]) -> pallas::Point {
+    let hasher = pallas::Point::hash_to_curve(DRK_VRF_INPUT_DOMAIN);
+    hasher(&[&public.to_bytes()[..], input].concat())
+}
+
+fn challenge(
+    public: &PublicKey,
+    h: &pallas::Point,
+    gamma: &pallas::Point,
+    u: &pallas::Point,
+    v: &pallas::Point,
+) -> pallas::Scalar {
+    let points = [public.to_bytes(), h.to_bytes(), gamma.to_bytes()].concat();
+    hash_to_scalar(DRK_VRF_CHALLENGE_DOMAIN, &points, &[u.to_bytes(), v.to_bytes()].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vrf() {
+        let secret = SecretKey::random(&mut OsRng);
+        let public = PublicKey::from_secret(secret);
+
+        let proof = VrfProof::prove(&secret, b"slot 42");
+        assert!(proof.verify(&public, b"slot 42"));
+
+        // The output only depends on the key and the input
+        let again = VrfProof::prove(&secret, b"slot 42");
+        assert_ne!(proof, again);
+        assert_eq!(proof.output(), again.output());
+        assert_ne!(proof.output(), VrfProof::prove(&secret, b"slot 43").output());
+
+        // Wrong input or key
+        assert!(!proof.verify(&public, b"slot 43"));
+        assert!(!proof.verify(&PublicKey::random(&mut OsRng), b"slot 42"));
+
+        // Another key can't claim the output
+        let other = SecretKey::random(&mut OsRng);
+        let forged = VrfProof { gamma: proof.gamma, ..VrfProof::prove(&other, b"slot 42") };
+        assert!(!forged.verify(&PublicKey::from_secret(other), b"slot 42"));
+        assert!(!forged.verify(&public, b"slot 42"));
+    }
+}
@@ -0,0 +1,67 @@
+use url::Url;
+
+use crate::{
+    net,
+    util::serial::{SerialDecodable, SerialEncodable},
+};
+
+use super::routing::{Contact, Key};
+
+/// Sent when a channel starts, telling the peer our node ID, and the
+/// address we can be reached on if we have one.
+#[derive(Debug, Clone, SerialDecodable, SerialEncodable)]
+pub struct DhtHello {
+    pub id: Key,
+    pub addr: Option<Url>,
+}
+
+impl net::Message for DhtHello {
+    fn name() -> &'static str {
+        "dhthello"
+    }
+}
+
+/// Asks a node for the contacts it knows closest to `target`, and for the
+/// value stored under it when `want_value` is set.
+#[derive(Debug, Clone, SerialDecodable, SerialEncodable)]
+pub struct DhtLookup {
+    pub request_id: u64,
+    pub target: Key,
+    pub want_value: bool,
+}
+
+impl net::Message for DhtLookup {
+    fn name() -> &'static str {
+        "dhtlookup"
+    }
+}
+
+/// Reply to a `DhtLookup`. When the node holds the value, it's returned
+/// instead of contacts.
+#[derive(Debug, Clone, SerialDecodable, SerialEncodable)]
+pub struct DhtLookupReply {
+    pub request_id: u64,
+    pub contacts: Vec<Contact>,
+    pub value: Option<Vec<u8>>,
+}
+
+impl net::Message for DhtLookupReply {
+    fn name() -> &'static str {
+        "dhtlookupreply"
+    }
+}
+
+/// Asks a node to keep a record. `ttl` is the seconds left until the
+/// record expires, so replicas expire at the same time everywhere.
+#[derive(Debug, Clone, SerialDecodable, SerialEncodable)]
+pub struct DhtStore {
+    pub key: Key,
+    pub value: Vec<u8>,
+    pub ttl: u64,
+}
+
+impl net::Message for DhtStore {
+    fn name() -> &'static str {
+        "dhtstore"
+    }
+}
@@ -0,0 +1,53 @@
+//! Kademlia distributed hash table over the P2P network.
+//!
+//! Every node takes an ID in the same 256-bit keyspace as the records,
+//! hashed from the address it's reached on, so it can't choose where it
+//! sits in the keyspace. Nodes without an address take a random ID, and
+//! are left out of routing tables. Every node keeps the nodes it learns
+//! of in a routing table of buckets, one per bit of XOR distance from its
+//! own ID. Records are stored on the `K` nodes closest to their key, found
+//! with iterative lookups that ask `ALPHA` nodes at a time for closer
+//! ones. Every record carries a TTL set by its owner. Holders republish
+//! their records every hour, so they survive nodes leaving, and drop them
+//! once the TTL runs out. Nodes keep a bounded number of records for
+//! others, and for the nodes of any one host, and only the host that
+//! stored a record can change its value.
+
+mod messages;
+mod node;
+mod protocol_dht;
+mod routing;
+mod settings;
+
+pub use messages::{DhtHello, DhtLookup, DhtLookupReply, DhtStore};
+pub use node::{Dht, DhtPtr};
+pub use protocol_dht::ProtocolDht;
+pub use routing::{Contact, Key, RoutingTable, KEY_BITS};
+pub use settings::Settings;
+
+/// Size of a routing table bucket, and how many nodes a record is kept on
+pub const K: usize = 20;
+
+/// Nodes queried at once in a lookup
+pub const ALPHA: usize = 3;
+
+/// Seconds to wait for a node to answer a query, dialing it included
+pub const REQUEST_TIMEOUT: u64 = 10;
+
+/// Default seconds between republishing the records we hold
+pub const REPUBLISH_INTERVAL: u64 = 3600;
+
+/// Default seconds a record is kept for after it's published
+pub const RECORD_TTL: u64 = 86400;
+
+/// Default seconds between dropping the expired records
+pub const PURGE_INTERVAL: u64 = 60;
+
+/// Largest value a record can hold, in bytes
+pub const MAX_VALUE_SIZE: usize = 64 * 1024;
+
+/// Default most records kept for other nodes
+pub const MAX_RECORDS: usize = 10_000;
+
+/// Default most records kept for the nodes of one host
+pub const MAX_HOST_RECORDS: usize = 500;
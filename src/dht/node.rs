@@ -0,0 +1,558 @@
+use async_std::{
+    future::timeout,
+    sync::{Arc, Mutex},
+};
+use std::time::Duration;
+
+use async_executor::Executor;
+use futures::future::{join3, join_all};
+use fxhash::{FxHashMap, FxHashSet};
+use log::{debug, info, warn};
+use rand::Rng;
+use url::Url;
+
+use crate::{
+    net,
+    util::{async_util, Timestamp},
+    Error, Result,
+};
+
+use super::{
+    messages::{DhtLookup, DhtLookupReply, DhtStore},
+    protocol_dht::ProtocolDht,
+    routing::{Contact, Key, RoutingTable},
+    settings::Settings,
+    ALPHA, K, MAX_VALUE_SIZE, REQUEST_TIMEOUT,
+};
+
+/// Atomic pointer to a DHT node.
+pub type DhtPtr = Arc<Dht>;
+
+/// Address to dial, and where to send the channel once it's set up
+type DialRequest = (Url, async_channel::Sender<Result<net::ChannelPtr>>);
+
+struct Record {
+    value: Vec<u8>,
+    /// When the record is dropped
+    expires: Timestamp,
+    /// Whether we're the owner, and so don't let other nodes replace it
+    local: bool,
+    /// Host of the node that stored the record with us, `None` for ours
+    source: Option<String>,
+}
+
+impl Record {
+    fn expired(&self, now: Timestamp) -> bool {
+        now.0 >= self.expires.0
+    }
+}
+
+/// A node of the DHT, reached through the protocol it registers on the
+/// P2P network. `run` has to be spawned for it to reach nodes it isn't
+/// connected to, to republish its records, and to drop the expired ones.
+pub struct Dht {
+    id: Key,
+    p2p: net::P2pPtr,
+    settings: Settings,
+    routing: Mutex<RoutingTable>,
+    records: Mutex<FxHashMap<Key, Record>>,
+    /// Address of the channel each connected node is on
+    peers: Mutex<FxHashMap<Key, Url>>,
+    /// Lookups waiting for a reply, by request ID
+    pending: Mutex<FxHashMap<u64, async_channel::Sender<DhtLookupReply>>>,
+    dial_sender: async_channel::Sender<DialRequest>,
+    dial_receiver: async_channel::Receiver<DialRequest>,
+}
+
+impl Dht {
+    /// Create a DHT node, and register its protocol on the P2P network.
+    /// Its ID is bound to our external address, or random if we have none.
+    pub async fn new(p2p: net::P2pPtr, settings: Settings) -> DhtPtr {
+        let id = match p2p.external_addr().await {
+            Some(addr) => Key::node_id(&addr),
+            None => Key::random(),
+        };
+        let (dial_sender, dial_receiver) = async_channel::unbounded();
+        info!(target: "dht", "DHT node ID: {}", id);
+
+        let dht = Arc::new(Self {
+            id,
+            p2p: p2p.clone(),
+            settings,
+            routing: Mutex::new(RoutingTable::new(id)),
+            records: Mutex::new(FxHashMap::default()),
+            peers: Mutex::new(FxHashMap::default()),
+            pending: Mutex::new(FxHashMap::default()),
+            dial_sender,
+            dial_receiver,
+        });
+
+        let dht_ = dht.clone();
+        p2p.protocol_registry()
+            .register(net::SESSION_ALL, move |channel, p2p| {
+                let dht = dht_.clone();
+                async move { ProtocolDht::init(channel, dht, p2p).await }
+            })
+            .await;
+
+        dht
+    }
+
+    /// Our node ID
+    pub fn id(&self) -> Key {
+        self.id
+    }
+
+    /// Dial the nodes lookups ask for, republish our records, and drop
+    /// the expired ones, until the P2P network stops.
+    pub async fn run(self: Arc<Self>, executor: Arc<Executor<'_>>) -> Result<()> {
+        let (dial, republish, purge) = join3(
+            self.clone().dial_loop(executor),
+            self.clone().republish_loop(),
+            self.clone().purge_loop(),
+        )
+        .await;
+        dial.and(republish).and(purge)
+    }
+
+    /// Store `value` under `key` on the nodes closest to it, for `ttl`
+    /// seconds or the configured record TTL. We republish it until it
+    /// expires. Returns the number of other nodes it was sent to.
+    pub async fn put(&self, key: Key, value: Vec<u8>, ttl: Option<u64>) -> Result<usize> {
+        if value.len() > MAX_VALUE_SIZE {
+            return Err(Error::DhtError(format!(
+                "Value of {} bytes is over the {} bytes limit",
+                value.len(),
+                MAX_VALUE_SIZE
+            )))
+        }
+
+        let ttl = ttl.unwrap_or(self.settings.record_ttl);
+        if ttl == 0 {
+            return Err(Error::DhtError("Record TTL must be over zero".to_string()))
+        }
+
+        let expires = Timestamp(Timestamp::current_time().0 + ttl as i64);
+        let record = Record { value: value.clone(), expires, local: true, source: None };
+        self.records.lock().await.insert(key, record);
+        Ok(self.replicate(key, value, ttl).await)
+    }
+
+    /// Get the value stored under `key`, from our records or else from the
+    /// network.
+    pub async fn get(&self, key: &Key) -> Result<Option<Vec<u8>>> {
+        let now = Timestamp::current_time();
+        match self.records.lock().await.get(key) {
+            Some(record) if !record.expired(now) => return Ok(Some(record.value.clone())),
+            _ => {}
+        }
+
+        let (_, value) = self.lookup(key, true).await;
+        Ok(value)
+    }
+
+    /// Find the `K` nodes closest to `target` that answer, closest first.
+    pub async fn closest_nodes(&self, target: &Key) -> Vec<Contact> {
+        self.lookup(target, false).await.0
+    }
+
+    /// The records we hold, ours and the ones we keep for others, with
+    /// when they expire
+    pub async fn records(&self) -> Vec<(Key, Vec<u8>, Timestamp)> {
+        let now = Timestamp::current_time();
+        let records = self.records.lock().await;
+        records
+            .iter()
+            .filter(|(_, r)| !r.expired(now))
+            .map(|(k, r)| (*k, r.value.clone(), r.expires))
+            .collect()
+    }
+
+    /// Number of nodes in the routing table
+    pub async fn contacts_count(&self) -> usize {
+        self.routing.lock().await.len()
+    }
+
+    /// A node told us its ID on `channel`. If it gave an address we can
+    /// reach it on, and its ID is the one bound to it, it goes in the
+    /// routing table, in place of the least recently seen node of its
+    /// bucket if that one is full and the node isn't connected anymore.
+    pub(super) async fn peer_connected(
+        &self,
+        id: Key,
+        addr: Option<Url>,
+        channel: &net::ChannelPtr,
+    ) {
+        if id == self.id {
+            return
+        }
+
+        let contact = match addr {
+            Some(addr) => Contact { id, addr },
+            None => return,
+        };
+
+        if !contact.is_valid() {
+            debug!(target: "dht", "Node {} isn't the one on {}", id, contact.addr);
+            return
+        }
+
+        // Anyone can claim an address, so only a channel to it is taken as
+        // the node's
+        if channel.address() == contact.addr {
+            self.peers.lock().await.insert(id, channel.address());
+        }
+
+        let oldest = self.routing.lock().await.insert(contact.clone());
+        if let Some(oldest) = oldest {
+            if self.connected_channel(&oldest).await.is_none() {
+                debug!(target: "dht", "Replacing {} with {} in the routing table", oldest.id, id);
+                self.routing.lock().await.replace(&oldest.id, contact);
+            }
+        }
+    }
+
+    /// Answer a lookup with the value if we hold it, or with the nodes we
+    /// know closest to the target.
+    pub(super) async fn handle_lookup(&self, lookup: &DhtLookup) -> DhtLookupReply {
+        if lookup.want_value {
+            let now = Timestamp::current_time();
+            match self.records.lock().await.get(&lookup.target) {
+                Some(record) if !record.expired(now) => {
+                    return DhtLookupReply {
+                        request_id: lookup.request_id,
+                        contacts: vec![],
+                        value: Some(record.value.clone()),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let contacts = self.routing.lock().await.closest(&lookup.target, K);
+        DhtLookupReply { request_id: lookup.request_id, contacts, value: None }
+    }
+
+    /// Pass a reply to the lookup waiting for it.
+    pub(super) async fn handle_reply(&self, reply: DhtLookupReply) {
+        let sender = self.pending.lock().await.get(&reply.request_id).cloned();
+        match sender {
+            // A node answering twice can't hold up the channel
+            Some(sender) => {
+                let _ = sender.try_send(reply);
+            }
+            None => debug!(target: "dht", "Dropping reply to unknown request {}", reply.request_id),
+        }
+    }
+
+    /// Keep a record a node on `from` asked us to, for at most the
+    /// configured record TTL. Our own records aren't replaced, and neither
+    /// are ones expiring later, which were published more recently. Only
+    /// the host that stored a record can change its value, others can just
+    /// extend it. New records are refused once we keep `max_records` for
+    /// others, or `max_host_records` for the host.
+    pub(super) async fn handle_store(&self, store: DhtStore, from: &Url) {
+        if store.value.len() > MAX_VALUE_SIZE || store.ttl == 0 {
+            return
+        }
+
+        let source = from.host_str().unwrap_or_default().to_string();
+        let now = Timestamp::current_time();
+        let ttl = store.ttl.min(self.settings.record_ttl);
+        let expires = Timestamp(now.0 + ttl as i64);
+        let mut records = self.records.lock().await;
+        match records.get_mut(&store.key) {
+            Some(record) if record.local => return,
+            Some(record) if record.expires.0 > expires.0 => return,
+            Some(record) if record.value == store.value => {
+                record.expires = expires;
+                return
+            }
+            Some(record) if !record.expired(now) => {
+                if record.source.as_ref() != Some(&source) {
+                    debug!(target: "dht", "Refusing change of record {} from {}", store.key, from);
+                    return
+                }
+            }
+            _ => {
+                let held = records.values().filter(|r| !r.local && !r.expired(now));
+                let (total, from_host) = held.fold((0, 0), |(total, from_host), r| {
+                    (total + 1, from_host + (r.source.as_ref() == Some(&source)) as usize)
+                });
+                if total >= self.settings.max_records ||
+                    from_host >= self.settings.max_host_records
+                {
+                    debug!(
+                        target: "dht",
+                        "Refusing record {} from {}, over the limit", store.key, from
+                    );
+                    return
+                }
+            }
+        }
+
+        debug!(target: "dht", "Storing record {} for {}s", store.key, ttl);
+        let record = Record { value: store.value, expires, local: false, source: Some(source) };
+        records.insert(store.key, record);
+    }
+
+    /// Iterative lookup of `target`. The closest nodes not queried yet are
+    /// asked `ALPHA` at a time for closer ones, until the `K` closest have
+    /// all answered or failed. When `want_value` is set, stops as soon as
+    /// a node returns the value. Returns the closest nodes that answered,
+    /// closest first, and the value if found.
+    async fn lookup(&self, target: &Key, want_value: bool) -> (Vec<Contact>, Option<Vec<u8>>) {
+        let mut shortlist = self.routing.lock().await.closest(target, K);
+        let mut queried = FxHashSet::default();
+        let mut answered = vec![];
+
+        loop {
+            let round: Vec<Contact> = shortlist
+                .iter()
+                .filter(|c| !queried.contains(&c.id))
+                .take(ALPHA)
+                .cloned()
+                .collect();
+            if round.is_empty() {
+                break
+            }
+
+            for contact in &round {
+                queried.insert(contact.id);
+            }
+
+            let replies = join_all(round.iter().map(|c| self.query(c, target, want_value))).await;
+
+            for (contact, reply) in round.into_iter().zip(replies) {
+                let reply = match reply {
+                    Ok(v) => v,
+                    Err(e) => {
+                        debug!(target: "dht", "Query to {} failed: {}", contact.addr, e);
+                        shortlist.retain(|c| c.id != contact.id);
+                        continue
+                    }
+                };
+
+                if want_value && reply.value.is_some() {
+                    answered.push(contact);
+                    return (answered, reply.value)
+                }
+
+                for found in reply.contacts {
+                    let known =
+                        queried.contains(&found.id) || shortlist.iter().any(|c| c.id == found.id);
+                    if found.id != self.id && !known && found.is_valid() {
+                        shortlist.push(found);
+                    }
+                }
+                answered.push(contact);
+            }
+
+            shortlist.sort_by_key(|c| c.id.distance(target));
+            shortlist.truncate(K);
+        }
+
+        answered.sort_by_key(|c| c.id.distance(target));
+        answered.truncate(K);
+        (answered, None)
+    }
+
+    /// Ask a node for the nodes it knows closest to `target`, or for the
+    /// value stored under it. Nodes we aren't connected to are dialed for
+    /// the query, and hung up on after it. Nodes that can't be reached are
+    /// dropped from the routing table.
+    async fn query(
+        &self,
+        contact: &Contact,
+        target: &Key,
+        want_value: bool,
+    ) -> Result<DhtLookupReply> {
+        let request_id = rand::thread_rng().gen();
+        let (sender, receiver) = async_channel::bounded(1);
+        self.pending.lock().await.insert(request_id, sender);
+
+        let lookup = DhtLookup { request_id, target: *target, want_value };
+        let mut dialed = None;
+        let query = async {
+            let channel = match self.connected_channel(contact).await {
+                Some(v) => v,
+                None => {
+                    let channel = self.dial(&contact.addr).await?;
+                    dialed = Some(channel.clone());
+                    channel
+                }
+            };
+
+            channel.send(lookup).await?;
+            Ok::<_, Error>(receiver.recv().await?)
+        };
+
+        let result = match timeout(Duration::from_secs(REQUEST_TIMEOUT), query).await {
+            Ok(v) => v,
+            Err(_) => Err(Error::DhtError(format!("No answer from {}", contact.addr))),
+        };
+
+        self.pending.lock().await.remove(&request_id);
+        if let Some(channel) = dialed {
+            channel.stop().await;
+        }
+        if result.is_err() {
+            self.routing.lock().await.remove(&contact.id);
+        }
+
+        result
+    }
+
+    /// Send a record to the nodes closest to its key. Returns how many
+    /// it was sent to.
+    async fn replicate(&self, key: Key, value: Vec<u8>, ttl: u64) -> usize {
+        let mut stored = 0;
+        for contact in self.closest_nodes(&key).await {
+            let store = DhtStore { key, value: value.clone(), ttl };
+            match self.send_store(&contact, store).await {
+                Ok(()) => stored += 1,
+                Err(e) => {
+                    debug!(target: "dht", "Failed storing {} on {}: {}", key, contact.addr, e)
+                }
+            }
+        }
+        stored
+    }
+
+    async fn send_store(&self, contact: &Contact, store: DhtStore) -> Result<()> {
+        if let Some(channel) = self.connected_channel(contact).await {
+            return channel.send(store).await
+        }
+
+        let dial = timeout(Duration::from_secs(REQUEST_TIMEOUT), self.dial(&contact.addr));
+        let channel = match dial.await {
+            Ok(v) => v?,
+            Err(_) => return Err(Error::DhtError(format!("Timed out dialing {}", contact.addr))),
+        };
+        let result = channel.send(store).await;
+        channel.stop().await;
+        result
+    }
+
+    /// The channel we're connected to a node on, if any
+    async fn connected_channel(&self, contact: &Contact) -> Option<net::ChannelPtr> {
+        let addr = self.peers.lock().await.get(&contact.id).cloned();
+        let channels = self.p2p.channels().lock().await;
+        addr.and_then(|a| channels.get(&a).cloned())
+            .or_else(|| channels.get(&contact.addr).cloned())
+    }
+
+    /// Have `run` connect to `addr`.
+    async fn dial(&self, addr: &Url) -> Result<net::ChannelPtr> {
+        let (sender, receiver) = async_channel::bounded(1);
+        self.dial_sender.send((addr.clone(), sender)).await?;
+        receiver.recv().await?
+    }
+
+    async fn dial_loop(self: Arc<Self>, executor: Arc<Executor<'_>>) -> Result<()> {
+        let stop_sub = self.p2p.subscribe_stop().await;
+
+        loop {
+            let request = smol::future::or(
+                async {
+                    stop_sub.receive().await;
+                    None
+                },
+                async { self.dial_receiver.recv().await.ok() },
+            )
+            .await;
+
+            let (addr, reply) = match request {
+                Some(v) => v,
+                None => return Ok(()),
+            };
+
+            let session = self.p2p.session_manual().await;
+            let ex = executor.clone();
+            executor
+                .spawn(async move {
+                    let _ = reply.send(session.connect_once(&addr, ex).await).await;
+                })
+                .detach();
+        }
+    }
+
+    /// Republish the records we hold that haven't expired, every
+    /// `republish_interval` seconds.
+    async fn republish_loop(self: Arc<Self>) -> Result<()> {
+        let stop_sub = self.p2p.subscribe_stop().await;
+
+        loop {
+            let stopped = smol::future::or(
+                async {
+                    stop_sub.receive().await;
+                    true
+                },
+                async {
+                    async_util::sleep(self.settings.republish_interval).await;
+                    false
+                },
+            )
+            .await;
+
+            if stopped {
+                return Ok(())
+            }
+
+            let now = Timestamp::current_time();
+            let republish: Vec<(Key, Vec<u8>, u64)> = self
+                .records
+                .lock()
+                .await
+                .iter()
+                .filter(|(_, r)| !r.expired(now))
+                .map(|(k, r)| (*k, r.value.clone(), (r.expires.0 - now.0) as u64))
+                .collect();
+
+            debug!(target: "dht", "Republishing {} records", republish.len());
+            for (key, value, ttl) in republish {
+                if self.replicate(key, value, ttl).await == 0 {
+                    warn!(target: "dht", "Found no node to republish {} on", key);
+                }
+            }
+        }
+    }
+
+    /// Drop the expired records every `purge_interval` seconds, and forget
+    /// the channels of nodes we aren't connected to anymore.
+    async fn purge_loop(self: Arc<Self>) -> Result<()> {
+        let stop_sub = self.p2p.subscribe_stop().await;
+
+        loop {
+            let stopped = smol::future::or(
+                async {
+                    stop_sub.receive().await;
+                    true
+                },
+                async {
+                    async_util::sleep(self.settings.purge_interval).await;
+                    false
+                },
+            )
+            .await;
+
+            if stopped {
+                return Ok(())
+            }
+
+            let now = Timestamp::current_time();
+            let purged = {
+                let mut records = self.records.lock().await;
+                let count = records.len();
+                records.retain(|_, r| !r.expired(now));
+                count - records.len()
+            };
+            if purged > 0 {
+                debug!(target: "dht", "Dropped {} expired records", purged);
+            }
+
+            let channels = self.p2p.channels().lock().await.keys().cloned().collect::<Vec<_>>();
+            self.peers.lock().await.retain(|_, addr| channels.contains(addr));
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use async_std::sync::Arc;
+
+use async_executor::Executor;
+use async_trait::async_trait;
+use log::debug;
+
+use crate::{net, Result};
+
+use super::{
+    messages::{DhtHello, DhtLookup, DhtLookupReply, DhtStore},
+    node::DhtPtr,
+};
+
+/// Exchanges DHT messages with a peer, answering its lookups and stores
+/// from our node, and passing replies to our own lookups back to it.
+pub struct ProtocolDht {
+    channel: net::ChannelPtr,
+    dht: DhtPtr,
+    p2p: net::P2pPtr,
+    hello_sub: net::MessageSubscription<DhtHello>,
+    lookup_sub: net::MessageSubscription<DhtLookup>,
+    reply_sub: net::MessageSubscription<DhtLookupReply>,
+    store_sub: net::MessageSubscription<DhtStore>,
+    jobsman: net::ProtocolJobsManagerPtr,
+}
+
+impl ProtocolDht {
+    pub async fn init(
+        channel: net::ChannelPtr,
+        dht: DhtPtr,
+        p2p: net::P2pPtr,
+    ) -> net::ProtocolBasePtr {
+        let message_subsytem = channel.get_message_subsystem();
+        message_subsytem.add_dispatch::<DhtHello>().await;
+        message_subsytem.add_dispatch::<DhtLookup>().await;
+        message_subsytem.add_dispatch::<DhtLookupReply>().await;
+        message_subsytem.add_dispatch::<DhtStore>().await;
+
+        let hello_sub =
+            channel.subscribe_msg::<DhtHello>().await.expect("Missing DhtHello dispatcher!");
+        let lookup_sub =
+            channel.subscribe_msg::<DhtLookup>().await.expect("Missing DhtLookup dispatcher!");
+        let reply_sub = channel
+            .subscribe_msg::<DhtLookupReply>()
+            .await
+            .expect("Missing DhtLookupReply dispatcher!");
+        let store_sub =
+            channel.subscribe_msg::<DhtStore>().await.expect("Missing DhtStore dispatcher!");
+
+        Arc::new(Self {
+            channel: channel.clone(),
+            dht,
+            p2p,
+            hello_sub,
+            lookup_sub,
+            reply_sub,
+            store_sub,
+            jobsman: net::ProtocolJobsManager::new("ProtocolDht", channel),
+        })
+    }
+
+    async fn handle_receive_hello(self: Arc<Self>) -> Result<()> {
+        debug!(target: "dht", "ProtocolDht::handle_receive_hello() [START]");
+        loop {
+            let hello = self.hello_sub.receive().await?;
+            debug!(
+                target: "dht",
+                "ProtocolDht::handle_receive_hello() node {} on {}",
+                hello.id, self.channel.address()
+            );
+
+            // A peer we dialed can be dialed again on the same address
+            let addr = match &hello.addr {
+                Some(v) => Some(v.clone()),
+                None if self.channel.session_type_id() != net::SESSION_INBOUND => {
+                    Some(self.channel.address())
+                }
+                None => None,
+            };
+            self.dht.peer_connected(hello.id, addr, &self.channel).await;
+        }
+    }
+
+    async fn handle_receive_lookup(self: Arc<Self>) -> Result<()> {
+        debug!(target: "dht", "ProtocolDht::handle_receive_lookup() [START]");
+        loop {
+            let lookup = self.lookup_sub.receive().await?;
+            let reply = self.dht.handle_lookup(&lookup).await;
+            self.channel.send(reply).await?;
+        }
+    }
+
+    async fn handle_receive_reply(self: Arc<Self>) -> Result<()> {
+        debug!(target: "dht", "ProtocolDht::handle_receive_reply() [START]");
+        loop {
+            let reply = self.reply_sub.receive().await?;
+            self.dht.handle_reply((*reply).clone()).await;
+        }
+    }
+
+    async fn handle_receive_store(self: Arc<Self>) -> Result<()> {
+        debug!(target: "dht", "ProtocolDht::handle_receive_store() [START]");
+        loop {
+            let store = self.store_sub.receive().await?;
+            self.dht.handle_store((*store).clone(), &self.channel.address()).await;
+        }
+    }
+}
+
+#[async_trait]
+impl net::ProtocolBase for ProtocolDht {
+    /// Starts handling the peer's DHT messages, then tells it our node ID,
+    /// and our external address if we have one so it can dial us later.
+    async fn start(self: Arc<Self>, executor: Arc<Executor<'_>>) -> Result<()> {
+        debug!(target: "dht", "ProtocolDht::start() [START]");
+        self.jobsman.clone().start(executor.clone());
+        self.jobsman.clone().spawn(self.clone().handle_receive_hello(), executor.clone()).await;
+        self.jobsman.clone().spawn(self.clone().handle_receive_lookup(), executor.clone()).await;
+        self.jobsman.clone().spawn(self.clone().handle_receive_reply(), executor.clone()).await;
+        self.jobsman.clone().spawn(self.clone().handle_receive_store(), executor).await;
+
+        let hello = DhtHello { id: self.dht.id(), addr: self.p2p.external_addr().await };
+        self.channel.send(hello).await?;
+        debug!(target: "dht", "ProtocolDht::start() [END]");
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "ProtocolDht"
+    }
+}
@@ -0,0 +1,209 @@
+use std::{collections::VecDeque, fmt, io};
+
+use rand::RngCore;
+use url::Url;
+
+use crate::{
+    impl_vec,
+    util::serial::{Decodable, Encodable, SerialDecodable, SerialEncodable, VarInt},
+    Result,
+};
+
+use super::K;
+
+/// Bits in a key, and so buckets in a routing table
+pub const KEY_BITS: usize = 256;
+
+/// A point in the DHT keyspace, naming either a node or a record.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, SerialEncodable, SerialDecodable,
+)]
+pub struct Key(pub [u8; 32]);
+
+impl Key {
+    /// The key a record with the given name is stored under
+    pub fn hash(data: &[u8]) -> Self {
+        Self(*blake3::hash(data).as_bytes())
+    }
+
+    /// The ID of the node reached on `addr`
+    pub fn node_id(addr: &Url) -> Self {
+        Self::hash(format!("DarkFi:DhtNode:{}", addr).as_bytes())
+    }
+
+    /// A random key, used as the ID of a node without an address
+    pub fn random() -> Self {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+
+    /// XOR distance to another key. Compares as a big-endian number.
+    pub fn distance(&self, other: &Key) -> [u8; 32] {
+        let mut distance = [0u8; 32];
+        for (i, byte) in distance.iter_mut().enumerate() {
+            *byte = self.0[i] ^ other.0[i];
+        }
+        distance
+    }
+
+    /// Index of the bucket `other` falls in, counted from the one holding
+    /// the furthest half of the keyspace. `None` if the keys are equal.
+    fn bucket_index(&self, other: &Key) -> Option<usize> {
+        let distance = self.distance(other);
+        let mut leading_zeros = 0;
+        for byte in distance {
+            if byte != 0 {
+                return Some(leading_zeros + byte.leading_zeros() as usize)
+            }
+            leading_zeros += 8;
+        }
+        None
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+/// A node we can reach, and the address it's reached on.
+#[derive(Clone, Debug, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct Contact {
+    pub id: Key,
+    pub addr: Url,
+}
+
+impl Contact {
+    /// Whether the ID is the one bound to the address
+    pub fn is_valid(&self) -> bool {
+        self.id == Key::node_id(&self.addr)
+    }
+}
+
+impl_vec!(Contact);
+
+/// Kademlia routing table. Contacts are kept in one bucket per bit of
+/// distance from our ID, each holding up to `K` of them, least recently
+/// seen first.
+pub struct RoutingTable {
+    local: Key,
+    buckets: Vec<VecDeque<Contact>>,
+}
+
+impl RoutingTable {
+    pub fn new(local: Key) -> Self {
+        Self { local, buckets: vec![VecDeque::new(); KEY_BITS] }
+    }
+
+    /// Add a contact, or mark it as seen if we know it. If its bucket is
+    /// full, returns the least recently seen contact in it, which the
+    /// caller can swap for the new one with `replace` if it's gone.
+    pub fn insert(&mut self, contact: Contact) -> Option<Contact> {
+        let index = self.bucket_index(&contact.id)?;
+        let bucket = &mut self.buckets[index];
+
+        if let Some(pos) = bucket.iter().position(|c| c.id == contact.id) {
+            bucket.remove(pos);
+            bucket.push_back(contact);
+            return None
+        }
+
+        if bucket.len() < K {
+            bucket.push_back(contact);
+            return None
+        }
+
+        bucket.front().cloned()
+    }
+
+    /// Swap the contact with ID `old` for `contact`, in the same bucket.
+    pub fn replace(&mut self, old: &Key, contact: Contact) {
+        if let Some(index) = self.bucket_index(&contact.id) {
+            let bucket = &mut self.buckets[index];
+            bucket.retain(|c| &c.id != old);
+            if bucket.len() < K {
+                bucket.push_back(contact);
+            }
+        }
+    }
+
+    /// Forget a contact.
+    pub fn remove(&mut self, id: &Key) {
+        if let Some(index) = self.bucket_index(id) {
+            self.buckets[index].retain(|c| &c.id != id);
+        }
+    }
+
+    /// Return up to `count` contacts closest to `target`, closest first.
+    pub fn closest(&self, target: &Key, count: usize) -> Vec<Contact> {
+        let mut contacts: Vec<Contact> = self.buckets.iter().flatten().cloned().collect();
+        contacts.sort_by_key(|c| c.id.distance(target));
+        contacts.truncate(count);
+        contacts
+    }
+
+    /// Number of contacts known
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|b| b.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn bucket_index(&self, id: &Key) -> Option<usize> {
+        self.local.bucket_index(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(first: u8, last: u8) -> Contact {
+        let mut id = [0u8; 32];
+        id[0] = first;
+        id[31] = last;
+        let addr = Url::parse(&format!("tcp://127.0.0.1:{}", 10000 + first as u16)).unwrap();
+        Contact { id: Key(id), addr }
+    }
+
+    #[test]
+    fn routing_table() {
+        let local = Key([0u8; 32]);
+        assert_eq!(local.bucket_index(&local), None);
+        assert_eq!(local.bucket_index(&contact(0x80, 0).id), Some(0));
+        assert_eq!(local.bucket_index(&contact(0, 1).id), Some(255));
+
+        let mut table = RoutingTable::new(local);
+        assert!(table.insert(contact(0, 0)).is_none());
+        assert!(table.is_empty());
+
+        // Fill the bucket of the furthest half
+        for i in 0..K as u8 {
+            assert!(table.insert(contact(0x80 + i, 0)).is_none());
+        }
+        let oldest = table.insert(contact(0xff, 0)).unwrap();
+        assert_eq!(oldest, contact(0x80, 0));
+
+        // Seeing the oldest again moves it to the back
+        assert!(table.insert(contact(0x80, 0)).is_none());
+        assert_eq!(table.insert(contact(0xff, 0)).unwrap(), contact(0x81, 0));
+        table.replace(&contact(0x81, 0).id, contact(0xff, 0));
+        assert_eq!(table.len(), K);
+
+        table.insert(contact(0x01, 0));
+        table.insert(contact(0x02, 0));
+        let closest = table.closest(&contact(0x03, 0).id, 3);
+        assert_eq!(closest, vec![contact(0x02, 0), contact(0x01, 0), contact(0x83, 0)]);
+
+        table.remove(&contact(0x02, 0).id);
+        assert_eq!(table.len(), K + 1);
+
+        let addr = Url::parse("tcp://127.0.0.1:11001").unwrap();
+        assert!(Contact { id: Key::node_id(&addr), addr: addr.clone() }.is_valid());
+        assert!(!Contact { id: contact(0x01, 0).id, addr }.is_valid());
+    }
+}
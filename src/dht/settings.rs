@@ -0,0 +1,29 @@
+use super::{MAX_HOST_RECORDS, MAX_RECORDS, PURGE_INTERVAL, RECORD_TTL, REPUBLISH_INTERVAL};
+
+/// Default settings for a DHT node. Can be manually configured.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Seconds a record is kept for when it's stored without a TTL. Also
+    /// the longest we keep a record other nodes ask us to store.
+    pub record_ttl: u64,
+    /// Seconds between republishing the records we hold
+    pub republish_interval: u64,
+    /// Seconds between dropping the expired records
+    pub purge_interval: u64,
+    /// Most records kept for other nodes
+    pub max_records: usize,
+    /// Most records kept for the nodes of one host
+    pub max_host_records: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            record_ttl: RECORD_TTL,
+            republish_interval: REPUBLISH_INTERVAL,
+            purge_interval: PURGE_INTERVAL,
+            max_records: MAX_RECORDS,
+            max_host_records: MAX_HOST_RECORDS,
+        }
+    }
+}
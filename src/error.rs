@@ -46,6 +46,9 @@ pub enum Error {
     #[error("Could not parse token parameter")]
     TokenParseError,
 
+    #[error("Token list error: {0}")]
+    TokenListError(String),
+
     #[error(transparent)]
     TryFromSliceError(#[from] std::array::TryFromSliceError),
 
@@ -136,9 +139,15 @@ pub enum Error {
     #[error("Malformed packet")]
     MalformedPacket,
 
+    #[error("Peer exceeded its rate limit")]
+    RateLimitExceeded,
+
     #[error("Socks proxy error: {0}")]
     SocksError(String),
 
+    #[error("Port mapping failed: {0}")]
+    PortMappingFailed(String),
+
     #[error("No Socks5 URL found")]
     NoSocks5UrlFound,
 
@@ -166,6 +175,9 @@ pub enum Error {
     #[error("Unable to decrypt mint note")]
     NoteDecryptionFailed,
 
+    #[error("Memo of {0} bytes doesn't fit in a note")]
+    MemoTooLong(usize),
+
     #[error("No keypair file detected")]
     KeypairPathNotFound,
 
@@ -181,6 +193,12 @@ pub enum Error {
     #[error("Failed converting bs58 string to SecretKey")]
     SecretKeyFromStr,
 
+    #[error("Invalid mnemonic: {0}")]
+    InvalidMnemonic(String),
+
+    #[error("MuSig error: {0}")]
+    MuSigError(&'static str),
+
     #[error("Invalid DarkFi address")]
     InvalidAddress,
 
@@ -203,6 +221,12 @@ pub enum Error {
     #[error("Raft error: {0}")]
     RaftError(String),
 
+    #[error("DHT error: {0}")]
+    DhtError(String),
+
+    #[error("RPC proxy error: {0}")]
+    RpcProxyError(String),
+
     #[error("JSON-RPC error: {0}")]
     JsonRpcError(String),
 
@@ -232,6 +256,36 @@ pub enum Error {
     #[error("Block {0} metadata not found in database")]
     BlockMetadataNotFound(String),
 
+    #[error("Received blocks don't extend the chain: {0}")]
+    BrokenBlockChain(String),
+
+    #[error("Block has {0} transactions, more than the maximum of {1}")]
+    TooManyBlockTxs(usize, usize),
+
+    #[error("Block in slot {0} doesn't match its state commitment")]
+    StateCommitmentMismatch(u64),
+
+    #[error("Transactions of the block in slot {0} don't match its header")]
+    TxsRootMismatch(u64),
+
+    #[error("Can't roll back to slot {0}, the undo log doesn't go back that far")]
+    RollbackTooDeep(u64),
+
+    #[error("Merkle tree has no checkpoint left to rewind to")]
+    MerkleTreeRewindFailed,
+
+    #[error("Merkle tree has no root")]
+    MerkleTreeNoRoot,
+
+    #[error("Merkle tree couldn't witness the last leaf")]
+    MerkleTreeWitnessFailed,
+
+    #[error("{0} isn't a blockchain database")]
+    NotChainDatabase(String),
+
+    #[error("Database operation panicked")]
+    DatabaseJobPanicked,
+
     // =============
     // Wallet errors
     // =============
@@ -241,6 +295,36 @@ pub enum Error {
     #[error("Merkle tree already exists in wallet")]
     WalletTreeExists,
 
+    #[error("Wallet `{0}` already exists")]
+    WalletExists(String),
+
+    #[error("Wallet `{0}` not found")]
+    WalletNotFound(String),
+
+    #[error("Wallet has no seed to derive keys from")]
+    WalletSeedNotFound,
+
+    #[error("Wallet already has a seed")]
+    WalletSeedExists,
+
+    #[error("Wallet doesn't track the supply of this token")]
+    WalletTokenSupplyNotFound,
+
+    #[error("Deposit {0} not found")]
+    WalletDepositNotFound(u64),
+
+    #[error("Deposit {0} can't be refunded")]
+    WalletDepositNotRefundable(u64),
+
+    #[error("Invalid wallet export: {0}")]
+    WalletExportInvalid(String),
+
+    #[error("Wallet export version {0} is unsupported")]
+    UnsupportedWalletExport(u8),
+
+    #[error("Wrong passphrase or corrupted wallet export")]
+    WalletExportDecryptFailed,
+
     // ===================
     // wasm runtime errors
     // ===================
@@ -296,6 +380,18 @@ pub enum Error {
     #[error("Failed decoding bincode: {0}")]
     ZkasDecoderError(&'static str),
 
+    #[error("Unsupported zkas binary version: {0}")]
+    ZkasUnsupportedVersion(u8),
+
+    #[error("Witness `{0}` was not set")]
+    MissingWitness(&'static str),
+
+    #[error("Witnesses don't match the circuit: {0}")]
+    WitnessMismatch(String),
+
+    #[error("Circuit {0} is built in, its verifying key can't be replaced")]
+    BuiltinCircuit(String),
+
     #[cfg(feature = "regex")]
     #[error(transparent)]
     RegexError(#[from] regex::Error),
@@ -307,9 +403,24 @@ pub enum Error {
     #[error("Unsupported OS")]
     UnsupportedOS,
 
+    #[error("Another instance is already running with PID {0}")]
+    AlreadyRunning(i32),
+
+    #[error("No instance is running")]
+    NotRunning,
+
+    #[error("Database {0} is in use by PID {1}")]
+    DatabaseLocked(String, i32),
+
+    #[error("Database {0} is locked by PID {1}, which isn't running (see --force-unlock)")]
+    StaleDatabaseLock(String, i32),
+
     #[error("System clock went backwards")]
     BackwardsTime(std::time::SystemTimeError),
 
+    #[error("{0} startup checks failed")]
+    SelfCheckFailed(usize),
+
     // ==============================================
     // Wrappers for other error types in this library
     // ==============================================
@@ -338,6 +449,12 @@ pub enum VerifyFailed {
     #[error("Invalid signature for clear input {0}")]
     ClearInputSignature(usize),
 
+    #[error("Invalid unique asset issuance in clear input {0}")]
+    InvalidUniqueAsset(usize),
+
+    #[error("Unique asset of clear input {0} was issued already")]
+    UniqueAssetIssued(usize),
+
     #[error("Token commitments in inputs or outputs to not match")]
     TokenMismatch,
 
@@ -353,6 +470,15 @@ pub enum VerifyFailed {
     #[error("Failed verifying zk proofs: {0}")]
     ProofVerifyFailed(String),
 
+    #[error("No verifying key for circuit {0}")]
+    UnknownCircuit(String),
+
+    #[error("Unknown contract or function {0}")]
+    UnknownContract(String),
+
+    #[error("Rejected by transaction filter: {0}")]
+    Filtered(String),
+
     #[error("Internal error: {0}")]
     InternalError(String),
 }
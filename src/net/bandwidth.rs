@@ -0,0 +1,315 @@
+use std::time::{Duration, Instant};
+
+use async_std::sync::Mutex;
+use fxhash::FxHashMap;
+use serde::Serialize;
+use serde_json::json;
+use smol::Timer;
+
+use crate::{util::serial::VarInt, Error, Result};
+
+use super::{message::Packet, Settings};
+
+/// Seconds the throughput of a channel is averaged over
+const METER_WINDOW: f64 = 10.0;
+
+/// Traffic of a message type.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ByteCounter {
+    pub sent_bytes: u64,
+    pub sent_msgs: u64,
+    pub recv_bytes: u64,
+    pub recv_msgs: u64,
+}
+
+impl ByteCounter {
+    /// Add up the traffic of another counter
+    pub fn add(&mut self, other: &Self) {
+        self.sent_bytes += other.sent_bytes;
+        self.sent_msgs += other.sent_msgs;
+        self.recv_bytes += other.recv_bytes;
+        self.recv_msgs += other.recv_msgs;
+    }
+}
+
+/// Bytes per second sent and received on a channel lately.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Throughput {
+    pub sent_bytes_per_second: u64,
+    pub recv_bytes_per_second: u64,
+}
+
+impl Throughput {
+    /// Add up the throughput of another channel
+    pub fn add(&mut self, other: &Self) {
+        self.sent_bytes_per_second += other.sent_bytes_per_second;
+        self.recv_bytes_per_second += other.recv_bytes_per_second;
+    }
+}
+
+/// Moving average of the bytes per second going through, weighing the
+/// last `METER_WINDOW` seconds the most.
+struct Meter {
+    rate: f64,
+    updated: Instant,
+}
+
+impl Meter {
+    fn new() -> Self {
+        Self { rate: 0.0, updated: Instant::now() }
+    }
+
+    fn decay(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.rate *= (-elapsed / METER_WINDOW).exp();
+        self.updated = now;
+    }
+
+    fn record(&mut self, bytes: usize) {
+        self.decay();
+        self.rate += bytes as f64 / METER_WINDOW;
+    }
+
+    fn rate(&mut self) -> u64 {
+        self.decay();
+        self.rate.round() as u64
+    }
+}
+
+/// Token bucket letting through `rate` bytes per second, with bursts of
+/// up to a second worth of them.
+struct RateLimiter {
+    rate: u64,
+    /// Bytes that can be sent right away. Goes negative when a message
+    /// bigger than what's left has to be let through.
+    available: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    fn new(rate: u64) -> Self {
+        Self { rate, available: rate as f64, updated: Instant::now() }
+    }
+
+    /// Take `bytes` out of the bucket, returning how long to wait before
+    /// sending them.
+    fn reserve(&mut self, bytes: usize) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.updated = now;
+
+        let rate = self.rate as f64;
+        self.available = (self.available + elapsed * rate).min(rate) - bytes as f64;
+
+        if self.available >= 0.0 {
+            return Duration::ZERO
+        }
+
+        Duration::from_secs_f64(-self.available / rate)
+    }
+}
+
+/// Rate cap on the bytes a peer sends us, noting since when the peer has
+/// been held back by it without a break.
+struct RecvLimiter {
+    limiter: RateLimiter,
+    grace: Duration,
+    throttled_since: Option<Instant>,
+}
+
+/// Bytes sent and received on a channel by message type, and the rate caps
+/// on sending and receiving them.
+pub struct Bandwidth {
+    counters: Mutex<FxHashMap<String, ByteCounter>>,
+    limiters: Mutex<FxHashMap<String, RateLimiter>>,
+    recv_limiter: Option<Mutex<RecvLimiter>>,
+    sent_meter: Mutex<Meter>,
+    recv_meter: Mutex<Meter>,
+}
+
+impl Bandwidth {
+    /// Create the counters of a channel, sending the message types in
+    /// `rate_limits` at most at the given bytes per second, and reading
+    /// at most `recv_rate_limit` bytes per second from the peer.
+    pub fn new(settings: &Settings) -> Self {
+        let limiters = settings
+            .rate_limits
+            .iter()
+            .filter(|(_, rate)| **rate > 0)
+            .map(|(command, rate)| (command.clone(), RateLimiter::new(*rate)))
+            .collect();
+
+        let recv_limiter = match settings.recv_rate_limit {
+            0 => None,
+            rate => Some(Mutex::new(RecvLimiter {
+                limiter: RateLimiter::new(rate),
+                grace: Duration::from_secs(settings.recv_rate_grace_seconds),
+                throttled_since: None,
+            })),
+        };
+
+        Self {
+            counters: Mutex::new(FxHashMap::default()),
+            limiters: Mutex::new(limiters),
+            recv_limiter,
+            sent_meter: Mutex::new(Meter::new()),
+            recv_meter: Mutex::new(Meter::new()),
+        }
+    }
+
+    /// Wait until a packet can be sent under the rate cap of its message
+    /// type, if it has one.
+    pub async fn throttle(&self, command: &str, bytes: usize) {
+        let delay = match self.limiters.lock().await.get_mut(command) {
+            Some(limiter) => limiter.reserve(bytes),
+            None => return,
+        };
+
+        if !delay.is_zero() {
+            Timer::after(delay).await;
+        }
+    }
+
+    /// Wait until a received packet fits under the peer's rate cap, which
+    /// holds off reading from it meanwhile. Fails once the peer has been
+    /// held back for longer than its grace period without a break, so it
+    /// can be dropped.
+    pub async fn throttle_received(&self, bytes: usize) -> Result<()> {
+        let recv_limiter = match &self.recv_limiter {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let delay = {
+            let mut recv_limiter = recv_limiter.lock().await;
+            let delay = recv_limiter.limiter.reserve(bytes);
+            if delay.is_zero() {
+                recv_limiter.throttled_since = None;
+                return Ok(())
+            }
+
+            match recv_limiter.throttled_since {
+                Some(since) if since.elapsed() > recv_limiter.grace => {
+                    return Err(Error::RateLimitExceeded)
+                }
+                Some(_) => {}
+                None => recv_limiter.throttled_since = Some(Instant::now()),
+            }
+            delay
+        };
+
+        Timer::after(delay).await;
+        Ok(())
+    }
+
+    pub async fn record_sent(&self, command: &str, bytes: usize) {
+        self.sent_meter.lock().await.record(bytes);
+        let mut counters = self.counters.lock().await;
+        let counter = counters.entry(command.to_string()).or_default();
+        counter.sent_bytes += bytes as u64;
+        counter.sent_msgs += 1;
+    }
+
+    pub async fn record_received(&self, command: &str, bytes: usize) {
+        self.recv_meter.lock().await.record(bytes);
+        let mut counters = self.counters.lock().await;
+        let counter = counters.entry(command.to_string()).or_default();
+        counter.recv_bytes += bytes as u64;
+        counter.recv_msgs += 1;
+    }
+
+    /// Traffic so far by message type
+    pub async fn counters(&self) -> FxHashMap<String, ByteCounter> {
+        self.counters.lock().await.clone()
+    }
+
+    /// Bytes per second sent and received lately
+    pub async fn throughput(&self) -> Throughput {
+        Throughput {
+            sent_bytes_per_second: self.sent_meter.lock().await.rate(),
+            recv_bytes_per_second: self.recv_meter.lock().await.rate(),
+        }
+    }
+
+    pub async fn get_info(&self) -> serde_json::Value {
+        let counters = self.counters().await;
+        let mut total = ByteCounter::default();
+        for counter in counters.values() {
+            total.add(counter);
+        }
+
+        json!({ "messages": counters, "total": total, "throughput": self.throughput().await })
+    }
+}
+
+/// Number of bytes a packet takes on the wire, with its header.
+pub fn packet_size(packet: &Packet) -> usize {
+    let command_len = packet.command.len();
+    let payload_len = packet.payload.len();
+
+    4 + VarInt(command_len as u64).length() +
+        command_len +
+        VarInt(payload_len as u64).length() +
+        payload_len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limiter() {
+        let mut limiter = RateLimiter::new(1000);
+
+        // A second worth of bytes goes through right away
+        assert_eq!(limiter.reserve(600), Duration::ZERO);
+        assert_eq!(limiter.reserve(400), Duration::ZERO);
+
+        // Then they have to wait for the bucket to fill up again
+        let delay = limiter.reserve(500);
+        assert!(delay > Duration::from_millis(400) && delay <= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn bandwidth_counters() {
+        let packet = Packet { command: String::from("ping"), payload: vec![0; 300] };
+        // Magic, command with its length, payload with its length
+        assert_eq!(packet_size(&packet), 4 + 1 + 4 + 3 + 300);
+
+        let bandwidth = Bandwidth::new(&Settings::default());
+        smol::block_on(async {
+            bandwidth.record_sent("ping", 10).await;
+            bandwidth.record_sent("ping", 20).await;
+            bandwidth.record_received("pong", 5).await;
+
+            let counters = bandwidth.counters().await;
+            assert_eq!(
+                counters["ping"],
+                ByteCounter { sent_bytes: 30, sent_msgs: 2, ..Default::default() }
+            );
+            assert_eq!(counters["pong"].recv_bytes, 5);
+            assert_eq!(bandwidth.get_info().await["total"]["sent_bytes"], 30);
+            assert_eq!(bandwidth.throughput().await.sent_bytes_per_second, 3);
+        });
+    }
+
+    #[test]
+    fn recv_rate_limit() {
+        let settings =
+            Settings { recv_rate_limit: 1000, recv_rate_grace_seconds: 0, ..Default::default() };
+        let bandwidth = Bandwidth::new(&settings);
+        smol::block_on(async {
+            // Under the cap nothing is held back
+            bandwidth.throttle_received(1000).await.unwrap();
+
+            // Held back once, then dropped when it's still over the cap
+            // past the grace period
+            bandwidth.throttle_received(100).await.unwrap();
+            assert!(matches!(
+                bandwidth.throttle_received(1000).await,
+                Err(Error::RateLimitExceeded)
+            ));
+        });
+    }
+}
@@ -4,7 +4,7 @@ use futures::{
     io::{ReadHalf, WriteHalf},
     AsyncReadExt,
 };
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use rand::Rng;
 use serde_json::json;
 use smol::Executor;
@@ -17,7 +17,8 @@ use crate::{
 };
 
 use super::{
-    message,
+    bandwidth::{self, Bandwidth},
+    message::{self, Message},
     message_subscriber::{MessageSubscription, MessageSubsystem},
     Session, SessionBitflag, SessionWeakPtr, TransportStream,
 };
@@ -28,6 +29,8 @@ pub type ChannelPtr = Arc<Channel>;
 struct ChannelInfo {
     random_id: u32,
     remote_node_id: String,
+    // Traffic features both ends agreed on in the version handshake
+    capabilities: message::CapabilityBitflag,
     last_msg: String,
     last_status: String,
     // Message log which is cleared on querying get_info
@@ -39,6 +42,7 @@ impl ChannelInfo {
         Self {
             random_id: rand::thread_rng().gen(),
             remote_node_id: String::new(),
+            capabilities: 0,
             last_msg: String::new(),
             last_status: String::new(),
             log: Mutex::new(Vec::new()),
@@ -49,6 +53,7 @@ impl ChannelInfo {
         let result = json!({
             "random_id": self.random_id,
             "remote_node_id": self.remote_node_id,
+            "capabilities": self.capabilities,
             "last_msg": self.last_msg,
             "last_status": self.last_status,
             "log": self.log.lock().await.clone(),
@@ -68,6 +73,7 @@ pub struct Channel {
     receive_task: StoppableTaskPtr,
     stopped: Mutex<bool>,
     info: Mutex<ChannelInfo>,
+    bandwidth: Bandwidth,
     session: SessionWeakPtr,
 }
 
@@ -87,6 +93,11 @@ impl Channel {
         let message_subsystem = MessageSubsystem::new();
         Self::setup_dispatchers(&message_subsystem).await;
 
+        let bandwidth = match session.upgrade() {
+            Some(session) => Bandwidth::new(&session.p2p().settings()),
+            None => Bandwidth::new(&Default::default()),
+        };
+
         Arc::new(Self {
             reader,
             writer,
@@ -96,6 +107,7 @@ impl Channel {
             receive_task: StoppableTask::new(),
             stopped: Mutex::new(false),
             info: Mutex::new(ChannelInfo::new()),
+            bandwidth,
             session,
         })
     }
@@ -104,6 +116,11 @@ impl Channel {
         self.info.lock().await.get_info().await
     }
 
+    /// Bytes sent and received on this channel by message type.
+    pub fn bandwidth(&self) -> &Bandwidth {
+        &self.bandwidth
+    }
+
     /// Starts the channel. Runs a receive loop to start receiving messages or
     /// handles a network failure.
     pub fn start(self: Arc<Self>, executor: Arc<Executor<'_>>) {
@@ -157,9 +174,9 @@ impl Channel {
         Ok(sub)
     }
 
-    /// Sends a message across a channel. Calls function 'send_message' that
-    /// creates a new payload and sends it over the TCP connection as a
-    /// packet. Returns an error if something goes wrong.
+    /// Sends a message across a channel. Calls function 'send_packet' that
+    /// sends the encoded message over the TCP connection as a packet.
+    /// Returns an error if something goes wrong.
     pub async fn send<M: message::Message>(&self, message: M) -> Result<()> {
         debug!(target: "net",
          "Channel::send() [START, command={:?}, address={}]",
@@ -167,55 +184,89 @@ impl Channel {
          self.address()
         );
 
+        let mut payload = Vec::new();
+        message.encode(&mut payload)?;
+        let packet = message::Packet { command: String::from(M::name()), payload };
+        let result = self.send_packet(packet).await;
+
+        debug!(target: "net",
+         "Channel::send() [END, command={:?}, address={}]",
+         M::name(),
+         self.address()
+        );
+
+        result
+    }
+
+    /// Sends an encoded message across a channel. If the channel is stopped
+    /// or fails, the message is queued for when the peer reconnects, in
+    /// case it's one of the persistent ones.
+    pub async fn send_packet(&self, packet: message::Packet) -> Result<()> {
         {
             let stopped = *self.stopped.lock().await;
             if stopped {
+                self.queue_packet(packet).await;
                 return Err(Error::ChannelStopped)
             }
         }
 
+        let command = packet.command.clone();
+
         // Catch failure and stop channel, return a net error
-        let result = match self.send_message(message).await {
+        let result = match self.send_message(packet.clone()).await {
             Ok(()) => Ok(()),
             Err(err) => {
                 error!("Channel send error for [{}]: {}", self.address(), err);
                 self.stop().await;
+                self.queue_packet(packet).await;
                 Err(Error::ChannelStopped)
             }
         };
 
-        debug!(target: "net",
-         "Channel::send() [END, command={:?}, address={}]",
-         M::name(),
-         self.address()
-        );
         {
             let info = &mut *self.info.lock().await;
-            info.last_msg = M::name().to_string();
+            info.last_msg = command;
             info.last_status = "sent".to_string();
         }
 
         result
     }
 
-    /// Implements send message functionality. Creates a new payload and encodes
-    /// it. Then creates a message packet- the base type of the network- and
-    /// copies the payload into it. Then we send the packet over the TCP
+    /// Implements send message functionality. Takes an encoded message
+    /// packet- the base type of the network- and sends it over the TCP
     /// stream.
-    async fn send_message<M: message::Message>(&self, message: M) -> Result<()> {
-        let mut payload = Vec::new();
-        message.encode(&mut payload)?;
-        let packet = message::Packet { command: String::from(M::name()), payload };
+    async fn send_message(&self, mut packet: message::Packet) -> Result<()> {
         let time = NanoTimestamp::current_time();
         //let time = time::unix_timestamp()?;
+        let command = packet.command.clone();
 
         {
             let info = &mut *self.info.lock().await;
-            info.log.lock().await.push((time, "send".to_string(), packet.command.clone()));
+            info.log.lock().await.push((time, "send".to_string(), command.clone()));
+            if info.capabilities & message::CAPABILITY_PADDING != 0 {
+                packet = message::pad_packet(packet)?;
+            }
         }
 
-        let stream = &mut *self.writer.lock().await;
-        message::send_packet(stream, packet).await
+        // Padding is accounted to the message it pads
+        let size = bandwidth::packet_size(&packet);
+        self.bandwidth.throttle(&command, size).await;
+
+        {
+            let stream = &mut *self.writer.lock().await;
+            message::send_packet(stream, packet).await?;
+        }
+
+        self.bandwidth.record_sent(&command, size).await;
+        Ok(())
+    }
+
+    /// Keep a packet the peer couldn't be sent for when it reconnects.
+    async fn queue_packet(&self, packet: message::Packet) {
+        if let Some(session) = self.session.upgrade() {
+            let p2p = session.p2p();
+            p2p.outbound_queue().push(self.peer_id().await, self.address(), packet).await;
+        }
     }
 
     /// Subscribe to a messages on the message subsystem.
@@ -242,10 +293,29 @@ impl Channel {
     pub async fn remote_node_id(&self) -> String {
         self.info.lock().await.remote_node_id.clone()
     }
+
+    /// Identifies the remote node across connections. This is its node ID,
+    /// or the channel address if it has none.
+    pub async fn peer_id(&self) -> String {
+        let remote_node_id = self.remote_node_id().await;
+        if remote_node_id.is_empty() {
+            return self.address().to_string()
+        }
+        remote_node_id
+    }
     pub async fn set_remote_node_id(&self, remote_node_id: String) {
         self.info.lock().await.remote_node_id = remote_node_id;
     }
 
+    /// Traffic features used on this channel, agreed on with the remote
+    /// node in the version handshake.
+    pub async fn capabilities(&self) -> message::CapabilityBitflag {
+        self.info.lock().await.capabilities
+    }
+    pub async fn set_capabilities(&self, capabilities: message::CapabilityBitflag) {
+        self.info.lock().await.capabilities = capabilities;
+    }
+
     /// End of file error. Triggered when unexpected end of file occurs.
     fn is_eof_error(err: Error) -> bool {
         match err {
@@ -280,7 +350,7 @@ impl Channel {
         let reader = &mut *self.reader.lock().await;
 
         loop {
-            let packet = match message::read_packet(reader).await {
+            let mut packet = match message::read_packet(reader).await {
                 Ok(packet) => packet,
                 Err(err) => {
                     if Self::is_eof_error(err.clone()) {
@@ -296,6 +366,29 @@ impl Channel {
                     return Err(Error::ChannelStopped)
                 }
             };
+
+            let size = bandwidth::packet_size(&packet);
+
+            if packet.command == message::PADDED_COMMAND {
+                packet = match message::unpad_packet(packet) {
+                    Ok(packet) => packet,
+                    Err(err) => {
+                        error!("Malformed padded packet on channel {}: {}", self.address(), err);
+                        self.stop().await;
+                        return Err(Error::ChannelStopped)
+                    }
+                };
+            }
+
+            self.bandwidth.record_received(&packet.command, size).await;
+
+            // Reading is held off while the peer is over its rate cap
+            if let Err(err) = self.bandwidth.throttle_received(size).await {
+                warn!("Dropping channel {}: {}", self.address(), err);
+                self.stop().await;
+                return Err(Error::ChannelStopped)
+            }
+
             {
                 let info = &mut *self.info.lock().await;
                 info.last_msg = packet.command.clone();
@@ -305,6 +398,11 @@ impl Channel {
                 info.log.lock().await.push((time, "recv".to_string(), packet.command.clone()));
             }
 
+            // Cover traffic only exists to be seen on the wire
+            if packet.command == message::CoverMessage::name() {
+                continue
+            }
+
             // Send result to our subscribers
             self.message_subsystem.notify(&packet.command, packet.payload).await;
         }
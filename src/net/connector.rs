@@ -1,5 +1,5 @@
 use async_std::sync::Arc;
-use std::{env, time::Duration};
+use std::time::Duration;
 
 use log::error;
 use url::Url;
@@ -7,8 +7,8 @@ use url::Url;
 use crate::{Error, Result};
 
 use super::{
-    Channel, ChannelPtr, SessionWeakPtr, SettingsPtr, TcpTransport, TorTransport, Transport,
-    TransportName,
+    transport::dial_proxy, Channel, ChannelPtr, SessionWeakPtr, SettingsPtr, Socks5Transport,
+    TcpTransport, Transport, TransportName,
 };
 
 /// Create outbound socket connections.
@@ -71,22 +71,17 @@ impl Connector {
             }};
         }
 
-        match transport_name {
-            TransportName::Tcp(upgrade) => {
-                let transport = TcpTransport::new(None, 1024);
-                let stream = transport.dial(connect_url.clone(), Some(timeout));
+        let proxy = dial_proxy(&connect_url, &self.settings.socks5_proxy)?;
+
+        match (transport_name, proxy) {
+            (TransportName::Tcp(upgrade) | TransportName::Tor(upgrade), Some(proxy)) => {
+                let transport = Socks5Transport::new(proxy)?;
+                let stream = transport.clone().dial(connect_url.clone(), Some(timeout));
                 connect!(stream, transport, upgrade)
             }
-            TransportName::Tor(upgrade) => {
-                let socks5_url = Url::parse(
-                    &env::var("DARKFI_TOR_SOCKS5_URL")
-                        .unwrap_or_else(|_| "socks5://127.0.0.1:9050".to_string()),
-                )?;
-
-                let transport = TorTransport::new(socks5_url, None)?;
-
-                let stream = transport.clone().dial(connect_url.clone(), None);
-
+            (TransportName::Tcp(upgrade), None) => {
+                let transport = TcpTransport::new(None, 1024);
+                let stream = transport.dial(connect_url.clone(), Some(timeout));
                 connect!(stream, transport, upgrade)
             }
             _ => unimplemented!(),
@@ -1,32 +1,120 @@
-use async_std::sync::{Arc, Mutex};
+use async_std::{
+    fs,
+    sync::{Arc, Mutex},
+};
+use std::path::PathBuf;
 
 use fxhash::FxHashSet;
+use log::{debug, warn};
+use rand::seq::{IteratorRandom, SliceRandom};
 use url::Url;
 
+use crate::Result;
+
 /// Pointer to hosts class.
 pub type HostsPtr = Arc<Hosts>;
 
+/// Most addresses kept. Past it, random ones we never connected to are
+/// forgotten, so a peer flooding us with addresses can't push out the rest.
+pub const MAX_HOSTS: usize = 1000;
+
+/// Most addresses kept because we connected to them. Past it, a random one
+/// is treated like any other address again.
+pub const MAX_CONNECTED_HOSTS: usize = MAX_HOSTS / 4;
+
 /// Manages a store of network addresses.
 pub struct Hosts {
     addrs: Mutex<Vec<Url>>,
+    /// Addresses we connected to, which are never evicted
+    connected: Mutex<FxHashSet<Url>>,
+    /// File the addresses are kept in across restarts, if any
+    path: Option<PathBuf>,
 }
 
 impl Hosts {
     /// Create a new host list.
     pub fn new() -> Arc<Self> {
-        Arc::new(Self { addrs: Mutex::new(Vec::new()) })
+        Arc::new(Self {
+            addrs: Mutex::new(Vec::new()),
+            connected: Mutex::new(FxHashSet::default()),
+            path: None,
+        })
     }
 
-    /// Checks if a host address is in the host list.
-    async fn contains(&self, addrs: &[Url]) -> bool {
-        let a_set: FxHashSet<_> = addrs.iter().cloned().collect();
-        self.addrs.lock().await.iter().any(|item| a_set.contains(item))
+    /// Create a host list kept in the file at `path`, starting with the
+    /// addresses saved there on the last run. The file holds one address
+    /// per line, with the ones we connected to marked by a leading `*`.
+    pub async fn load(path: PathBuf) -> Arc<Self> {
+        let mut addrs = vec![];
+        let mut connected = vec![];
+        match fs::read_to_string(&path).await {
+            Ok(contents) => {
+                for line in contents.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                    let (addr, was_connected) = match line.strip_prefix('*') {
+                        Some(addr) => (addr.trim_start(), true),
+                        None => (line, false),
+                    };
+
+                    match Url::parse(addr) {
+                        Ok(addr) if was_connected => connected.push(addr),
+                        Ok(addr) => addrs.push(addr),
+                        Err(e) => warn!(target: "net", "Skipping host {}: {}", line, e),
+                    }
+                }
+                debug!(target: "net", "Loaded {} hosts from {:?}", addrs.len(), path);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!(target: "net", "Failed reading hosts from {:?}: {}", path, e),
+        }
+
+        let hosts = Arc::new(Self {
+            addrs: Mutex::new(vec![]),
+            connected: Mutex::new(FxHashSet::default()),
+            path: Some(path),
+        });
+        for addr in connected {
+            hosts.mark_connected(addr).await;
+        }
+        hosts.store(addrs).await;
+        hosts
     }
 
-    /// Add a new host to the host list.
+    /// Add the addresses we don't know yet to the host list.
     pub async fn store(&self, addrs: Vec<Url>) {
-        if !self.contains(&addrs).await {
-            self.addrs.lock().await.extend(addrs)
+        let mut hosts = self.addrs.lock().await;
+        let mut known: FxHashSet<Url> = hosts.iter().cloned().collect();
+        for addr in addrs {
+            if known.insert(addr.clone()) {
+                hosts.push(addr);
+            }
+        }
+
+        if hosts.len() > MAX_HOSTS {
+            let excess = hosts.len() - MAX_HOSTS;
+            let connected = self.connected.lock().await;
+            let evictable: Vec<usize> =
+                (0..hosts.len()).filter(|&i| !connected.contains(&hosts[i])).collect();
+
+            let mut evicted: Vec<usize> =
+                evictable.choose_multiple(&mut rand::thread_rng(), excess).copied().collect();
+            evicted.sort_unstable();
+            for i in evicted.into_iter().rev() {
+                hosts.remove(i);
+            }
+        }
+    }
+
+    /// Keep `addr` in the host list for good, as we managed to connect to it.
+    pub async fn mark_connected(&self, addr: Url) {
+        let mut hosts = self.addrs.lock().await;
+        let mut connected = self.connected.lock().await;
+        if !hosts.contains(&addr) {
+            hosts.push(addr.clone());
+        }
+
+        if connected.insert(addr) && connected.len() > MAX_CONNECTED_HOSTS {
+            let dropped = connected.iter().choose(&mut rand::thread_rng()).cloned().unwrap();
+            connected.remove(&dropped);
         }
     }
 
@@ -35,8 +123,90 @@ impl Hosts {
         self.addrs.lock().await.clone()
     }
 
+    /// Return up to `count` hosts, picked at random.
+    pub async fn load_random(&self, count: usize) -> Vec<Url> {
+        let hosts = self.addrs.lock().await;
+        hosts.choose_multiple(&mut rand::thread_rng(), count).cloned().collect()
+    }
+
     /// Check if the host list is empty.
     pub async fn is_empty(&self) -> bool {
         self.addrs.lock().await.is_empty()
     }
+
+    /// Write the host list to its file, if it has one, replacing the file
+    /// at once so a crash can't leave it half written.
+    pub async fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let mut contents = String::new();
+        {
+            let hosts = self.addrs.lock().await;
+            let connected = self.connected.lock().await;
+            for addr in hosts.iter() {
+                if connected.contains(addr) {
+                    contents.push('*');
+                }
+                contents.push_str(addr.as_str());
+                contents.push('\n');
+            }
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, contents).await?;
+        fs::rename(&tmp, path).await?;
+        debug!(target: "net", "Saved hosts to {:?}", path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn hosts_persistence() {
+        let path = std::env::temp_dir().join(format!("darkfi_hosts_{}", std::process::id()));
+        let a = Url::parse("tcp://127.0.0.1:8342").unwrap();
+        let b = Url::parse("tor://abcdefghij234567.onion:8342").unwrap();
+
+        let hosts = Hosts::load(path.clone()).await;
+        assert!(hosts.is_empty().await);
+
+        // Known addresses don't keep the new ones out
+        hosts.store(vec![a.clone()]).await;
+        hosts.store(vec![a.clone(), b.clone()]).await;
+        assert_eq!(hosts.load_all().await, vec![a.clone(), b.clone()]);
+        hosts.save().await.unwrap();
+
+        let hosts = Hosts::load(path.clone()).await;
+        assert_eq!(hosts.load_all().await, vec![a, b]);
+        assert_eq!(hosts.load_random(1).await.len(), 1);
+
+        // Flooding evicts at random, but never the addresses we connected to
+        let c = Url::parse("tcp://127.0.0.1:8343").unwrap();
+        hosts.mark_connected(c.clone()).await;
+        let many: Vec<Url> = (0..MAX_HOSTS as u16 * 2)
+            .map(|port| Url::parse(&format!("tcp://10.0.0.1:{}", port)).unwrap())
+            .collect();
+        hosts.store(many.clone()).await;
+        let all = hosts.load_all().await;
+        assert_eq!(all.len(), MAX_HOSTS);
+        assert!(all.contains(&c));
+        assert!(all.iter().any(|a| many[..MAX_HOSTS].contains(a)));
+        hosts.save().await.unwrap();
+
+        let hosts = Hosts::load(path.clone()).await;
+        hosts.store(many).await;
+        assert!(hosts.load_all().await.contains(&c));
+
+        std::fs::remove_file(path).unwrap();
+    }
 }
@@ -1,4 +1,4 @@
-use std::io;
+use std::io::{self, Cursor};
 
 use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use log::debug;
@@ -11,6 +11,23 @@ use crate::{
 
 const MAGIC_BYTES: [u8; 4] = [0xd9, 0xef, 0xb6, 0x7d];
 
+/// Command of packets wrapping another packet, padded to a fixed size.
+pub const PADDED_COMMAND: &str = "padded";
+
+/// Sizes padded packets are rounded up to. Larger packets are rounded up to
+/// a multiple of the last bucket.
+const PADDING_BUCKETS: [usize; 5] = [256, 1024, 4096, 16384, 65536];
+
+/// Bitflags of the traffic features a node enables, advertised in its
+/// version message. A feature is used on a channel only when both ends
+/// advertise it.
+pub type CapabilityBitflag = u64;
+
+/// Pad packets to fixed size buckets, hiding the size of messages.
+pub const CAPABILITY_PADDING: CapabilityBitflag = 0b01;
+/// Send dummy messages at random intervals, hiding when messages are sent.
+pub const CAPABILITY_COVER_TRAFFIC: CapabilityBitflag = 0b10;
+
 /// Generic message template.
 pub trait Message: 'static + Encodable + Decodable + Send + Sync {
     fn name() -> &'static str;
@@ -38,11 +55,17 @@ pub struct AddrsMessage {
 /// Requests version information of outbound connection.
 pub struct VersionMessage {
     pub node_id: String,
+    pub capabilities: CapabilityBitflag,
 }
 
 /// Sends version information to inbound connection. Response to VersionMessage.
 pub struct VerackMessage {}
 
+/// Dummy message sent as cover traffic, and dropped on arrival.
+pub struct CoverMessage {
+    pub data: Vec<u8>,
+}
+
 impl Message for PingMessage {
     fn name() -> &'static str {
         "ping"
@@ -79,6 +102,12 @@ impl Message for VerackMessage {
     }
 }
 
+impl Message for CoverMessage {
+    fn name() -> &'static str {
+        "cover"
+    }
+}
+
 impl Encodable for PingMessage {
     fn encode<S: io::Write>(&self, mut s: S) -> Result<usize> {
         let mut len = 0;
@@ -138,13 +167,17 @@ impl Encodable for VersionMessage {
     fn encode<S: io::Write>(&self, mut s: S) -> Result<usize> {
         let mut len = 0;
         len += self.node_id.encode(&mut s)?;
+        len += self.capabilities.encode(&mut s)?;
         Ok(len)
     }
 }
 
 impl Decodable for VersionMessage {
     fn decode<D: io::Read>(mut d: D) -> Result<Self> {
-        Ok(Self { node_id: Decodable::decode(&mut d)? })
+        let node_id = Decodable::decode(&mut d)?;
+        // Nodes from before capabilities were added don't send them
+        let capabilities = Decodable::decode(&mut d).unwrap_or(0);
+        Ok(Self { node_id, capabilities })
     }
 }
 
@@ -160,13 +193,56 @@ impl Decodable for VerackMessage {
     }
 }
 
+impl Encodable for CoverMessage {
+    fn encode<S: io::Write>(&self, mut s: S) -> Result<usize> {
+        let mut len = 0;
+        len += self.data.encode(&mut s)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for CoverMessage {
+    fn decode<D: io::Read>(mut d: D) -> Result<Self> {
+        Ok(Self { data: Decodable::decode(&mut d)? })
+    }
+}
+
 /// Packets are the base type read from the network. Converted to messages and
 /// passed to event loop.
+#[derive(Clone)]
 pub struct Packet {
     pub command: String,
     pub payload: Vec<u8>,
 }
 
+/// Wraps a packet into a padded one, filled with zeros up to the next
+/// bucket size.
+pub fn pad_packet(packet: Packet) -> Result<Packet> {
+    let mut payload = vec![];
+    packet.command.encode(&mut payload)?;
+    packet.payload.encode(&mut payload)?;
+
+    let last_bucket = PADDING_BUCKETS[PADDING_BUCKETS.len() - 1];
+    let size = match PADDING_BUCKETS.iter().find(|x| **x >= payload.len()) {
+        Some(v) => *v,
+        None => (payload.len() + last_bucket - 1) / last_bucket * last_bucket,
+    };
+    payload.resize(size, 0);
+
+    Ok(Packet { command: String::from(PADDED_COMMAND), payload })
+}
+
+/// Unwraps the packet inside a padded packet.
+pub fn unpad_packet(packet: Packet) -> Result<Packet> {
+    let mut cursor = Cursor::new(packet.payload);
+    let command: String = Decodable::decode(&mut cursor)?;
+    if command.is_empty() || command == PADDED_COMMAND {
+        return Err(Error::MalformedPacket)
+    }
+    let payload = Decodable::decode(&mut cursor)?;
+    Ok(Packet { command, payload })
+}
+
 /// Reads and decodes an inbound payload.
 pub async fn read_packet<R: AsyncRead + Unpin + Sized>(stream: &mut R) -> Result<Packet> {
     // Packets have a 4 byte header of magic digits
@@ -226,3 +302,24 @@ pub async fn send_packet<W: AsyncWrite + Unpin + Sized>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padding_round_trip() -> Result<()> {
+        for (payload_len, padded_len) in [(0, 256), (300, 1024), (70000, 131072)] {
+            let packet = Packet { command: String::from("ping"), payload: vec![42; payload_len] };
+            let padded = pad_packet(packet)?;
+            assert_eq!(padded.command, PADDED_COMMAND);
+            assert_eq!(padded.payload.len(), padded_len);
+
+            let unpadded = unpad_packet(padded)?;
+            assert_eq!(unpadded.command, "ping");
+            assert_eq!(unpadded.payload, vec![42; payload_len]);
+        }
+
+        Ok(())
+    }
+}
@@ -3,6 +3,10 @@
 /// connections and to handle network errors.
 pub mod acceptor;
 
+/// Bytes sent and received on channels by message type, and per message
+/// type rate caps on sending, to keep some traffic from starving the rest.
+pub mod bandwidth;
+
 /// Async channel that handles the sending of messages across the network.
 /// Public interface is used to create new channels, to stop and start
 /// a channel, and to send messages.
@@ -48,6 +52,18 @@ pub mod message_subscriber;
 /// converted into messages and passed to an event loop.
 pub mod message;
 
+/// Port mapping on home gateways through NAT-PMP or UPnP, so nodes behind
+/// them can accept inbound connections. The mapping is a lease that has
+/// to be renewed before it runs out.
+pub mod nat;
+
+/// Sphinx-style onion routing. Senders wrap a message in a layer of
+/// encryption for every hop of a route through other darkfi nodes, and
+/// every hop strips its layer to learn where to forward the packet to,
+/// without learning the rest of the route or the message.
+#[cfg(feature = "crypto")]
+pub mod onion;
+
 /// P2P provides all core functionality to interact with the peer-to-peer
 /// network.
 ///
@@ -74,6 +90,11 @@ pub mod p2p;
 /// asynchronous execution of the protocols.
 pub mod protocol;
 
+/// Queues of the messages that couldn't be sent to peers that disconnected,
+/// sent once they reconnect. Only the message types set as persistent in
+/// the network settings are queued, and they expire after a while.
+pub mod outbound_queue;
+
 /// Defines the interaction between nodes during a connection. Consists of an
 /// inbound session, which describes how to set up an incoming connection, and
 /// an outbound session, which describes setting up an outbound connection. Also
@@ -85,7 +106,9 @@ pub mod session;
 /// Network configuration settings.
 pub mod settings;
 
-/// Network transport implementations.
+/// Network transport implementations. Outbound connections can be dialed
+/// through a SOCKS5 proxy such as Tor, set with `socks5_proxy` in the
+/// settings, which is also how `.onion` addresses are reached.
 pub mod transport;
 
 pub use acceptor::{Acceptor, AcceptorPtr};
@@ -94,6 +117,7 @@ pub use connector::Connector;
 pub use hosts::{Hosts, HostsPtr};
 pub use message::Message;
 pub use message_subscriber::MessageSubscription;
+pub use outbound_queue::OutboundQueue;
 pub use p2p::{P2p, P2pPtr};
 pub use protocol::{ProtocolBase, ProtocolBasePtr, ProtocolJobsManager, ProtocolJobsManagerPtr};
 pub use session::{
@@ -102,6 +126,6 @@ pub use session::{
 };
 pub use settings::{Settings, SettingsPtr};
 pub use transport::{
-    TcpTransport, TorTransport, Transport, TransportListener, TransportName, TransportStream,
-    UnixTransport,
+    Socks5Transport, TcpTransport, TorTransport, Transport, TransportListener, TransportName,
+    TransportStream, UnixTransport,
 };
@@ -0,0 +1,415 @@
+use std::{
+    fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use async_std::{
+    io::{timeout, ReadExt, WriteExt},
+    net::{TcpStream, UdpSocket},
+};
+use log::debug;
+use url::Url;
+
+use crate::{Error, Result};
+
+/// Port NAT-PMP gateways listen on
+const NATPMP_PORT: u16 = 5351;
+/// Multicast address UPnP devices answer discovery requests on
+const SSDP_ADDR: &str = "239.255.255.250:1900";
+/// Seconds to wait for gateways to answer discovery
+const SSDP_TIMEOUT_SECONDS: u64 = 3;
+/// Seconds to wait for a gateway to answer an HTTP request
+const HTTP_TIMEOUT_SECONDS: u64 = 10;
+/// UPnP error code of gateways that only map ports without a lease
+const UPNP_ONLY_PERMANENT_LEASES: &str = "725";
+/// Seconds a mapping is kept when the gateway grants no lifetime
+const DEFAULT_LIFETIME: u64 = 3600;
+
+/// Services of UPnP gateways that can map ports
+const UPNP_SERVICES: [&str; 3] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+
+/// How a port was mapped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MappingProtocol {
+    NatPmp,
+    Upnp,
+}
+
+/// TCP port mapped on the gateway, forwarding it to us
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PortMapping {
+    pub protocol: MappingProtocol,
+    pub internal_port: u16,
+    pub external_ip: IpAddr,
+    pub external_port: u16,
+    /// Seconds the gateway granted the mapping for
+    pub lifetime: u64,
+    /// Service and control URL of the UPnP gateway, to delete the mapping
+    upnp_control: Option<(&'static str, Url)>,
+}
+
+/// Ask the gateway to forward a TCP port to `internal_port` for
+/// `lease_seconds`, through NAT-PMP or else UPnP. Mapping again before
+/// the lease is over renews it.
+pub async fn map_port(internal_port: u16, lease_seconds: u64) -> Result<PortMapping> {
+    // A lease of 0 deletes NAT-PMP mappings, and makes UPnP ones permanent
+    let lease_seconds = lease_seconds.max(1);
+    match natpmp_map_port(internal_port, lease_seconds).await {
+        Ok(mapping) => return Ok(mapping),
+        Err(e) => debug!(target: "net", "NAT-PMP port mapping failed: {}", e),
+    }
+
+    upnp_map_port(internal_port, lease_seconds).await
+}
+
+/// Ask the gateway to stop forwarding the mapped port.
+pub async fn unmap_port(mapping: &PortMapping) -> Result<()> {
+    match &mapping.upnp_control {
+        Some((service, control_url)) => {
+            let args = format!(
+                "<NewRemoteHost></NewRemoteHost>\
+                 <NewExternalPort>{}</NewExternalPort>\
+                 <NewProtocol>TCP</NewProtocol>",
+                mapping.external_port
+            );
+            soap_request(control_url, service, "DeletePortMapping", &args).await?;
+        }
+        None => {
+            // A lifetime of 0 deletes the mapping
+            let request = natpmp_map_request(mapping.internal_port, 0);
+            let reply = natpmp_request(default_gateway()?, &request, 16).await?;
+            parse_natpmp_mapping(&reply)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn natpmp_map_port(internal_port: u16, lease_seconds: u64) -> Result<PortMapping> {
+    let gateway = default_gateway()?;
+
+    let reply = natpmp_request(gateway, &[0, 0], 12).await?;
+    let external_ip = parse_natpmp_external_addr(&reply)?;
+
+    let request = natpmp_map_request(internal_port, lease_seconds);
+    let reply = natpmp_request(gateway, &request, 16).await?;
+    let (external_port, lifetime) = parse_natpmp_mapping(&reply)?;
+
+    Ok(PortMapping {
+        protocol: MappingProtocol::NatPmp,
+        internal_port,
+        external_ip: IpAddr::V4(external_ip),
+        external_port,
+        lifetime: if lifetime == 0 { DEFAULT_LIFETIME } else { lifetime.into() },
+        upnp_control: None,
+    })
+}
+
+/// Send a NAT-PMP request, retrying with doubling timeouts as the RFC
+/// asks, and return the reply.
+async fn natpmp_request(gateway: Ipv4Addr, request: &[u8], reply_len: usize) -> Result<Vec<u8>> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect((gateway, NATPMP_PORT)).await?;
+
+    let mut reply = vec![0; reply_len];
+    for attempt in 0..4 {
+        socket.send(request).await?;
+        let wait = Duration::from_millis(250 << attempt);
+        match timeout(wait, socket.recv(&mut reply)).await {
+            Ok(n) if n == reply_len => return Ok(reply),
+            Ok(_) => return Err(Error::PortMappingFailed("short NAT-PMP reply".into())),
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Err(Error::PortMappingFailed(format!("no NAT-PMP reply from {}", gateway)))
+}
+
+fn natpmp_map_request(internal_port: u16, lease_seconds: u64) -> [u8; 12] {
+    let lifetime = lease_seconds.min(u32::MAX.into()) as u32;
+
+    // Version 0, map TCP, reserved, then the ports and lifetime
+    let mut request = [0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    request[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    request[6..8].copy_from_slice(&internal_port.to_be_bytes());
+    request[8..12].copy_from_slice(&lifetime.to_be_bytes());
+    request
+}
+
+/// Check the header of a NAT-PMP reply to the given request opcode
+fn check_natpmp_reply(reply: &[u8], opcode: u8) -> Result<()> {
+    if reply[0] != 0 || reply[1] != 128 + opcode {
+        return Err(Error::PortMappingFailed("unexpected NAT-PMP reply".into()))
+    }
+
+    let result = u16::from_be_bytes([reply[2], reply[3]]);
+    if result != 0 {
+        return Err(Error::PortMappingFailed(format!("NAT-PMP result code {}", result)))
+    }
+
+    Ok(())
+}
+
+fn parse_natpmp_external_addr(reply: &[u8]) -> Result<Ipv4Addr> {
+    check_natpmp_reply(reply, 0)?;
+    Ok(Ipv4Addr::new(reply[8], reply[9], reply[10], reply[11]))
+}
+
+fn parse_natpmp_mapping(reply: &[u8]) -> Result<(u16, u32)> {
+    check_natpmp_reply(reply, 2)?;
+    let external_port = u16::from_be_bytes([reply[10], reply[11]]);
+    let lifetime = u32::from_be_bytes([reply[12], reply[13], reply[14], reply[15]]);
+    Ok((external_port, lifetime))
+}
+
+/// Address of the default IPv4 gateway, from the kernel routing table
+fn default_gateway() -> Result<Ipv4Addr> {
+    let routes = match fs::read_to_string("/proc/net/route") {
+        Ok(v) => v,
+        Err(_) => return Err(Error::PortMappingFailed("can't find the default gateway".into())),
+    };
+
+    parse_default_gateway(&routes)
+        .ok_or_else(|| Error::PortMappingFailed("no default gateway".into()))
+}
+
+/// Gateway of the default route in `/proc/net/route`, where addresses are
+/// hex in host byte order
+fn parse_default_gateway(routes: &str) -> Option<Ipv4Addr> {
+    for line in routes.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 || fields[1] != "00000000" {
+            continue
+        }
+
+        let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+        return Some(Ipv4Addr::from(gateway.to_ne_bytes()))
+    }
+
+    None
+}
+
+async fn upnp_map_port(internal_port: u16, lease_seconds: u64) -> Result<PortMapping> {
+    let location = upnp_discover().await?;
+    let description = http_request(&location, "GET", None, "").await?;
+    let (service, control_url) = match upnp_control_url(&description, &location) {
+        Some(v) => v,
+        None => return Err(Error::PortMappingFailed("gateway can't map ports".into())),
+    };
+
+    let local_ip = local_ip_towards(&control_url).await?;
+    let add_mapping = format!(
+        "<NewRemoteHost></NewRemoteHost>\
+         <NewExternalPort>{port}</NewExternalPort>\
+         <NewProtocol>TCP</NewProtocol>\
+         <NewInternalPort>{port}</NewInternalPort>\
+         <NewInternalClient>{ip}</NewInternalClient>\
+         <NewEnabled>1</NewEnabled>\
+         <NewPortMappingDescription>darkfi</NewPortMappingDescription>\
+         <NewLeaseDuration>{lifetime}</NewLeaseDuration>",
+        port = internal_port,
+        ip = local_ip,
+        lifetime = lease_seconds,
+    );
+
+    if let Err(e) = soap_request(&control_url, service, "AddPortMapping", &add_mapping).await {
+        // Some gateways only keep mappings until they reboot. A forward
+        // left behind if we die would outlive us, so they're not used.
+        if e.to_string().contains(UPNP_ONLY_PERMANENT_LEASES) {
+            return Err(Error::PortMappingFailed("gateway only maps ports permanently".into()))
+        }
+        return Err(e)
+    }
+
+    let reply = soap_request(&control_url, service, "GetExternalIPAddress", "").await?;
+    let external_ip = match xml_value(&reply, "NewExternalIPAddress").map(|v| v.parse()) {
+        Some(Ok(v)) => v,
+        _ => return Err(Error::PortMappingFailed("gateway has no external address".into())),
+    };
+
+    Ok(PortMapping {
+        protocol: MappingProtocol::Upnp,
+        internal_port,
+        external_ip,
+        external_port: internal_port,
+        lifetime: lease_seconds,
+        upnp_control: Some((service, control_url)),
+    })
+}
+
+/// Find an internet gateway on the local network through SSDP, and return
+/// the location of its description.
+async fn upnp_discover() -> Result<Url> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    let request = "M-SEARCH * HTTP/1.1\r\n\
+                   HOST: 239.255.255.250:1900\r\n\
+                   MAN: \"ssdp:discover\"\r\n\
+                   MX: 2\r\n\
+                   ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\r\n";
+    socket.send_to(request.as_bytes(), SSDP_ADDR).await?;
+
+    let mut buf = [0; 2048];
+    let wait = Duration::from_secs(SSDP_TIMEOUT_SECONDS);
+    let n = match timeout(wait, socket.recv(&mut buf)).await {
+        Ok(n) => n,
+        Err(_) => return Err(Error::PortMappingFailed("no UPnP gateway found".into())),
+    };
+
+    let reply = String::from_utf8_lossy(&buf[..n]);
+    for line in reply.lines() {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("location") {
+                return Ok(Url::parse(value.trim())?)
+            }
+        }
+    }
+
+    Err(Error::PortMappingFailed("UPnP gateway gave no location".into()))
+}
+
+/// Type and control URL of the first service of the gateway description
+/// that can map ports
+fn upnp_control_url(description: &str, location: &Url) -> Option<(&'static str, Url)> {
+    let base = match xml_value(description, "URLBase") {
+        Some(v) => Url::parse(v).ok()?,
+        None => location.clone(),
+    };
+
+    for service in UPNP_SERVICES {
+        let tag = format!("<serviceType>{}</serviceType>", service);
+        if let Some(start) = description.find(&tag) {
+            let rest = &description[start..];
+            let rest = &rest[..rest.find("</service>").unwrap_or(rest.len())];
+            if let Some(control_url) = xml_value(rest, "controlURL") {
+                return Some((service, base.join(control_url).ok()?))
+            }
+        }
+    }
+
+    None
+}
+
+/// Text of the first `tag` element in `xml`
+fn xml_value<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(xml[start..end].trim())
+}
+
+/// Call an action of a UPnP service, returning the reply body
+async fn soap_request(url: &Url, service: &str, action: &str, args: &str) -> Result<String> {
+    let body = format!(
+        "<?xml version=\"1.0\"?>\
+         <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+         s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+         <s:Body><u:{action} xmlns:u=\"{service}\">{args}</u:{action}></s:Body>\
+         </s:Envelope>",
+        action = action,
+        service = service,
+        args = args,
+    );
+    let soap_action = format!("SOAPAction: \"{}#{}\"", service, action);
+    http_request(url, "POST", Some(&soap_action), &body).await
+}
+
+/// Minimal HTTP/1.0 client, enough to talk to gateways. HTTP/1.0 keeps
+/// replies from being chunked.
+async fn http_request(url: &Url, method: &str, header: Option<&str>, body: &str) -> Result<String> {
+    let addr = match url.socket_addrs(|| Some(80))?.first() {
+        Some(v) => *v,
+        None => return Err(Error::PortMappingFailed(format!("can't resolve {}", url))),
+    };
+
+    let target = request_target(url);
+    let mut request = format!("{} {} HTTP/1.0\r\nHost: {}\r\n", method, target, addr);
+    if let Some(header) = header {
+        request.push_str(&format!("{}\r\nContent-Type: text/xml; charset=\"utf-8\"\r\n", header));
+    }
+    request.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+
+    let wait = Duration::from_secs(HTTP_TIMEOUT_SECONDS);
+    let reply = timeout(wait, async {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut reply = vec![];
+        stream.read_to_end(&mut reply).await?;
+        Ok(reply)
+    })
+    .await?;
+
+    let reply = String::from_utf8_lossy(&reply).to_string();
+    let (head, body) = reply.split_once("\r\n\r\n").unwrap_or((reply.as_str(), ""));
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        let error = xml_value(body, "errorCode").unwrap_or(status);
+        return Err(Error::PortMappingFailed(format!("{} {} failed: {}", method, url, error)))
+    }
+
+    Ok(body.to_string())
+}
+
+/// Path of `url` with its query, as sent in the request line
+fn request_target(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    }
+}
+
+/// Our address on the interface packets to `url` go out from
+async fn local_ip_towards(url: &Url) -> Result<IpAddr> {
+    let addr: SocketAddr = match url.socket_addrs(|| Some(80))?.first() {
+        Some(v) => *v,
+        None => return Err(Error::PortMappingFailed(format!("can't resolve {}", url))),
+    };
+
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(addr).await?;
+    Ok(socket.local_addr()?.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn port_mapping_messages() {
+        let request = natpmp_map_request(26661, 3600);
+        assert_eq!(request, [0, 2, 0, 0, 0x68, 0x25, 0x68, 0x25, 0, 0, 0x0e, 0x10]);
+
+        let reply = [0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 7];
+        assert_eq!(parse_natpmp_external_addr(&reply).unwrap(), Ipv4Addr::new(203, 0, 113, 7));
+        let reply = [0, 130, 0, 2, 0, 0, 0, 1, 0x68, 0x25, 0x68, 0x26, 0, 0, 0x0e, 0x10];
+        assert!(parse_natpmp_mapping(&reply).is_err());
+        let reply = [0, 130, 0, 0, 0, 0, 0, 1, 0x68, 0x25, 0x68, 0x26, 0, 0, 0x0e, 0x10];
+        assert_eq!(parse_natpmp_mapping(&reply).unwrap(), (26662, 3600));
+
+        let routes = "Iface\tDestination\tGateway \tFlags\n\
+                      eth0\t0000A8C0\t00000000\t0001\n\
+                      eth0\t00000000\t0100A8C0\t0003\n";
+        assert_eq!(parse_default_gateway(routes), Some(Ipv4Addr::new(192, 168, 0, 1)));
+
+        let description = "<root><device><serviceList>\
+            <service><serviceType>urn:schemas-upnp-org:service:WANCommonInterfaceConfig:1\
+            </serviceType><controlURL>/common</controlURL></service>\
+            <service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+            <controlURL>/ctl/IPConn</controlURL></service>\
+            </serviceList></device></root>";
+        let location = Url::parse("http://192.168.0.1:5000/rootDesc.xml").unwrap();
+        let (service, url) = upnp_control_url(description, &location).unwrap();
+        assert_eq!(service, "urn:schemas-upnp-org:service:WANIPConnection:1");
+        assert_eq!(url.as_str(), "http://192.168.0.1:5000/ctl/IPConn");
+
+        let url = Url::parse("http://192.168.0.1:5000/rootDesc.xml?uuid=1234").unwrap();
+        assert_eq!(request_target(&url), "/rootDesc.xml?uuid=1234");
+        assert_eq!(request_target(&location), "/rootDesc.xml");
+    }
+}
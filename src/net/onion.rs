@@ -0,0 +1,496 @@
+use std::collections::BTreeMap;
+
+use async_std::sync::{Arc, Mutex};
+
+use fxhash::FxHashSet;
+use halo2_gadgets::ecc::chip::FixedPoint;
+use log::{debug, warn};
+use pasta_curves::{group::ff::Field, pallas};
+use rand::rngs::OsRng;
+use url::Url;
+
+use crate::{
+    crypto::{
+        constants::NullifierK,
+        keypair::{PublicKey, SecretKey},
+        util::{hash_to_scalar, mod_r_p},
+    },
+    system::{Subscriber, SubscriberPtr, Subscription},
+    util::{
+        serial::{SerialDecodable, SerialEncodable},
+        time::Timestamp,
+    },
+    Error, Result,
+};
+
+use super::{message::Message, P2pPtr};
+
+/// Most hops a packet can be routed through
+pub const MAX_HOPS: usize = 5;
+/// Size of the encoded address of the next hop
+pub const ADDR_SIZE: usize = 128;
+pub const MAC_SIZE: usize = 32;
+/// Size of the routing information of a single hop
+pub const HOP_SIZE: usize = ADDR_SIZE + MAC_SIZE;
+pub const HEADER_SIZE: usize = MAX_HOPS * HOP_SIZE;
+/// Size of the encrypted payload. Payloads are padded to it, so that all
+/// packets look the same.
+pub const PAYLOAD_SIZE: usize = 1024;
+/// Largest payload that fits in a packet, after its MAC and length
+pub const MAX_PAYLOAD_LEN: usize = PAYLOAD_SIZE - MAC_SIZE - 2;
+
+pub const ONION_BLINDING_PERSONALIZATION: &[u8; 16] = b"DarkFiOnionBlind";
+pub const ONION_EPOCH_PERSONALIZATION: &[u8; 16] = b"DarkFiOnionEpoch";
+
+/// Relays rotate their key every epoch, of this many seconds. Replay tags
+/// only have to be remembered for as long as the key they were made with.
+pub const KEY_EPOCH_SECS: i64 = 3600;
+/// Most replay tags remembered per epoch. Past it, packets are dropped
+/// rather than relayed without replay protection.
+pub const MAX_SEEN_PER_EPOCH: usize = 1 << 20;
+
+const HEADER_STREAM_CONTEXT: &str = "darkfi net onion 2022-08 header stream";
+const HEADER_MAC_CONTEXT: &str = "darkfi net onion 2022-08 header mac";
+const PAYLOAD_STREAM_CONTEXT: &str = "darkfi net onion 2022-08 payload stream";
+const PAYLOAD_MAC_CONTEXT: &str = "darkfi net onion 2022-08 payload mac";
+const REPLAY_TAG_CONTEXT: &str = "darkfi net onion 2022-08 replay tag";
+
+/// Sphinx-style onion packet. Every hop on the route strips a layer of
+/// encryption, learning only the address of the next hop, and blinds the
+/// ephemeral key so the packet can't be linked between hops. Headers and
+/// payloads have a fixed size, which hides the length of the route and
+/// the position of a hop on it.
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct OnionPacket {
+    /// Ephemeral key, blinded at every hop
+    pub ephem_public: PublicKey,
+    /// Routing information for the hops, `HEADER_SIZE` bytes
+    pub header: Vec<u8>,
+    /// MAC of the header for the current hop
+    pub mac: [u8; MAC_SIZE],
+    /// Payload, `PAYLOAD_SIZE` bytes
+    pub payload: Vec<u8>,
+}
+
+impl Message for OnionPacket {
+    fn name() -> &'static str {
+        "onion"
+    }
+}
+
+/// What a hop is left with after stripping its layer of a packet.
+#[derive(Debug)]
+pub enum Peeled {
+    /// Send the packet on to the given address
+    Forward(Url, OnionPacket),
+    /// We are the last hop, and this is the payload
+    Deliver(Vec<u8>),
+}
+
+/// Key material shared between the sender and a single hop.
+struct HopKeys {
+    shared: PublicKey,
+}
+
+impl HopKeys {
+    fn key(&self, context: &str) -> [u8; 32] {
+        blake3::derive_key(context, &self.shared.to_bytes())
+    }
+
+    /// `len` bytes of keystream for the given context.
+    fn stream(&self, context: &str, len: usize) -> Vec<u8> {
+        let mut stream = vec![0u8; len];
+        blake3::Hasher::new_keyed(&self.key(context)).finalize_xof().fill(&mut stream);
+        stream
+    }
+
+    fn mac(&self, context: &str, data: &[u8]) -> blake3::Hash {
+        blake3::keyed_hash(&self.key(context), data)
+    }
+}
+
+fn xor(data: &mut [u8], stream: &[u8]) {
+    for (d, s) in data.iter_mut().zip(stream) {
+        *d ^= s;
+    }
+}
+
+fn blinding_factor(ephem_public: &PublicKey, shared: &PublicKey) -> pallas::Scalar {
+    hash_to_scalar(ONION_BLINDING_PERSONALIZATION, &ephem_public.to_bytes(), &shared.to_bytes())
+}
+
+/// Encode a hop address, prefixed with its length. An empty address
+/// marks the last hop.
+fn encode_addr(addr: &Url) -> Result<[u8; ADDR_SIZE]> {
+    let addr = addr.as_str().as_bytes();
+    if addr.is_empty() || addr.len() >= ADDR_SIZE {
+        return Err(Error::EncodeError("Onion hop address doesn't fit in the header"))
+    }
+
+    let mut encoded = [0u8; ADDR_SIZE];
+    encoded[0] = addr.len() as u8;
+    encoded[1..addr.len() + 1].copy_from_slice(addr);
+    Ok(encoded)
+}
+
+/// Build a packet carrying `payload` along `route`, a list of the address
+/// and public key of every hop. Returns the address of the first hop, to
+/// send the packet to. The last hop delivers the payload.
+pub fn build_packet(route: &[(Url, PublicKey)], payload: &[u8]) -> Result<(Url, OnionPacket)> {
+    if route.is_empty() || route.len() > MAX_HOPS {
+        return Err(Error::EncodeError("Onion route needs between 1 and MAX_HOPS hops"))
+    }
+    if payload.len() > MAX_PAYLOAD_LEN {
+        return Err(Error::EncodeError("Onion payload is too large"))
+    }
+
+    // Shared secrets with every hop, along with the ephemeral key as the
+    // hop sees it.
+    let mut ephem_secret = pallas::Scalar::random(&mut OsRng);
+    let ephem_public = PublicKey(NullifierK.generator() * ephem_secret);
+    let mut alpha = ephem_public;
+    let mut hops = vec![];
+    for (_, public) in route {
+        let shared = PublicKey(public.0 * ephem_secret);
+        let blinding = blinding_factor(&alpha, &shared);
+        hops.push(HopKeys { shared });
+        ephem_secret *= blinding;
+        alpha = PublicKey(alpha.0 * blinding);
+    }
+
+    // The filler makes up for the routing information stripped by the
+    // hops, so the header the last hop sees still checks out.
+    let mut filler: Vec<u8> = vec![];
+    for (i, hop) in hops[..hops.len() - 1].iter().enumerate() {
+        filler.extend_from_slice(&[0u8; HOP_SIZE]);
+        let stream = hop.stream(HEADER_STREAM_CONTEXT, HEADER_SIZE + HOP_SIZE);
+        xor(&mut filler, &stream[HEADER_SIZE - i * HOP_SIZE..]);
+    }
+
+    // Header of the last hop, marked with an empty address
+    let last = hops.last().unwrap();
+    let mut header = vec![0u8; HEADER_SIZE - filler.len()];
+    xor(&mut header, &last.stream(HEADER_STREAM_CONTEXT, HEADER_SIZE));
+    header.extend_from_slice(&filler);
+    let mut mac = last.mac(HEADER_MAC_CONTEXT, &header);
+
+    // And wrap it in the layers of the previous hops
+    for i in (0..hops.len() - 1).rev() {
+        let mut wrapped = encode_addr(&route[i + 1].0)?.to_vec();
+        wrapped.extend_from_slice(mac.as_bytes());
+        wrapped.extend_from_slice(&header[..HEADER_SIZE - HOP_SIZE]);
+        xor(&mut wrapped, &hops[i].stream(HEADER_STREAM_CONTEXT, HEADER_SIZE));
+        mac = hops[i].mac(HEADER_MAC_CONTEXT, &wrapped);
+        header = wrapped;
+    }
+
+    // The payload carries a MAC for the last hop, which catches any hop
+    // tampering with it on the way.
+    let mut inner = (payload.len() as u16).to_le_bytes().to_vec();
+    inner.extend_from_slice(payload);
+    inner.resize(PAYLOAD_SIZE - MAC_SIZE, 0);
+    let mut padded = last.mac(PAYLOAD_MAC_CONTEXT, &inner).as_bytes().to_vec();
+    padded.extend_from_slice(&inner);
+
+    for hop in hops.iter().rev() {
+        xor(&mut padded, &hop.stream(PAYLOAD_STREAM_CONTEXT, PAYLOAD_SIZE));
+    }
+
+    let packet = OnionPacket { ephem_public, header, mac: *mac.as_bytes(), payload: padded };
+    Ok((route[0].0.clone(), packet))
+}
+
+/// The current key epoch, see [`KEY_EPOCH_SECS`]
+pub fn current_epoch() -> u64 {
+    (Timestamp::current_time().0 / KEY_EPOCH_SECS) as u64
+}
+
+fn epoch_factor(epoch: u64) -> pallas::Scalar {
+    hash_to_scalar(ONION_EPOCH_PERSONALIZATION, &epoch.to_le_bytes(), &[])
+}
+
+/// Key of a relay for the given epoch, to route packets through it. It's
+/// derived from the relay's long-term public key, so senders don't have
+/// to learn every new key.
+pub fn epoch_public(public: &PublicKey, epoch: u64) -> PublicKey {
+    PublicKey(public.0 * epoch_factor(epoch))
+}
+
+/// Strip our layer of `packet` with our `secret`. Also returns a tag that
+/// is the same every time this packet reaches us, for replay protection.
+pub fn peel(packet: &OnionPacket, secret: &SecretKey) -> Result<(Peeled, [u8; 32])> {
+    peel_with(packet, mod_r_p(secret.0))
+}
+
+/// [`peel`] with the key of the given epoch, see [`epoch_public`].
+pub fn peel_epoch(
+    packet: &OnionPacket,
+    secret: &SecretKey,
+    epoch: u64,
+) -> Result<(Peeled, [u8; 32])> {
+    peel_with(packet, mod_r_p(secret.0) * epoch_factor(epoch))
+}
+
+fn peel_with(packet: &OnionPacket, secret: pallas::Scalar) -> Result<(Peeled, [u8; 32])> {
+    if packet.header.len() != HEADER_SIZE || packet.payload.len() != PAYLOAD_SIZE {
+        return Err(Error::MalformedPacket)
+    }
+
+    let shared = PublicKey(packet.ephem_public.0 * secret);
+    let hop = HopKeys { shared };
+
+    // blake3::Hash compares in constant time
+    if hop.mac(HEADER_MAC_CONTEXT, &packet.header) != blake3::Hash::from(packet.mac) {
+        return Err(Error::MalformedPacket)
+    }
+    let replay_tag = hop.key(REPLAY_TAG_CONTEXT);
+
+    let mut header = packet.header.clone();
+    header.extend_from_slice(&[0u8; HOP_SIZE]);
+    xor(&mut header, &hop.stream(HEADER_STREAM_CONTEXT, HEADER_SIZE + HOP_SIZE));
+
+    let mut payload = packet.payload.clone();
+    xor(&mut payload, &hop.stream(PAYLOAD_STREAM_CONTEXT, PAYLOAD_SIZE));
+
+    let addr_len = header[0] as usize;
+    if addr_len == 0 {
+        let (mac, inner) = payload.split_at(MAC_SIZE);
+        let mac: [u8; MAC_SIZE] = mac.try_into()?;
+        if hop.mac(PAYLOAD_MAC_CONTEXT, inner) != blake3::Hash::from(mac) {
+            return Err(Error::MalformedPacket)
+        }
+
+        let len = u16::from_le_bytes([inner[0], inner[1]]) as usize;
+        if len > MAX_PAYLOAD_LEN {
+            return Err(Error::MalformedPacket)
+        }
+        return Ok((Peeled::Deliver(inner[2..len + 2].to_vec()), replay_tag))
+    }
+
+    if addr_len >= ADDR_SIZE {
+        return Err(Error::MalformedPacket)
+    }
+    let addr = std::str::from_utf8(&header[1..addr_len + 1])?;
+    let addr = Url::parse(addr)?;
+
+    let blinding = blinding_factor(&packet.ephem_public, &shared);
+    let packet = OnionPacket {
+        ephem_public: PublicKey(packet.ephem_public.0 * blinding),
+        mac: header[ADDR_SIZE..HOP_SIZE].try_into().unwrap(),
+        header: header[HOP_SIZE..].to_vec(),
+        payload,
+    };
+
+    Ok((Peeled::Forward(addr, packet), replay_tag))
+}
+
+/// Replay tags of the packets a relay handled, kept per key epoch. Only
+/// the tags of the epochs whose keys still peel packets are kept.
+#[derive(Default)]
+struct ReplayCache {
+    epochs: BTreeMap<u64, FxHashSet<[u8; 32]>>,
+}
+
+impl ReplayCache {
+    /// Record a tag seen in `epoch`, forgetting the epochs before
+    /// `oldest`. Returns `false` if the tag was already seen, or if the
+    /// epoch can't take more tags.
+    fn insert(&mut self, epoch: u64, oldest: u64, tag: [u8; 32]) -> bool {
+        self.epochs = self.epochs.split_off(&oldest);
+
+        let seen = self.epochs.entry(epoch).or_default();
+        if seen.len() >= MAX_SEEN_PER_EPOCH {
+            warn!(target: "net", "Onion replay cache is full for epoch {}", epoch);
+            return false
+        }
+
+        seen.insert(tag)
+    }
+}
+
+pub type OnionRelayPtr = Arc<OnionRelay>;
+
+/// Relays onion packets for the rest of the network, and hands the
+/// payloads of packets addressed to us to the protocols subscribed to
+/// them. Packets are only forwarded over channels that are already
+/// connected to the next hop, so routes should be picked among the peers
+/// of every hop.
+///
+/// The relay key rotates every epoch, see [`epoch_public`]. Packets made
+/// for the previous epoch's key are still accepted, for those in flight
+/// when it rotates.
+pub struct OnionRelay {
+    secret: SecretKey,
+    p2p: P2pPtr,
+    /// Replay tags of the packets we already handled
+    seen: Mutex<ReplayCache>,
+    delivered: SubscriberPtr<Vec<u8>>,
+}
+
+impl OnionRelay {
+    pub fn new(secret: SecretKey, p2p: P2pPtr) -> OnionRelayPtr {
+        Arc::new(Self {
+            secret,
+            p2p,
+            seen: Mutex::new(ReplayCache::default()),
+            delivered: Subscriber::new(),
+        })
+    }
+
+    /// Our long-term public key. Senders route packets through us with
+    /// the key [`epoch_public`] derives from it for the current epoch.
+    pub fn public(&self) -> PublicKey {
+        PublicKey::from_secret(self.secret)
+    }
+
+    /// Subscribe to the payloads of packets addressed to us.
+    pub async fn subscribe(&self) -> Subscription<Vec<u8>> {
+        self.delivered.clone().subscribe().await
+    }
+
+    /// Build a packet carrying `payload` along `route`, with the long-term
+    /// public keys of its hops, and send it to the first hop. The packet
+    /// is made for the keys of the current epoch.
+    pub async fn send(&self, route: &[(Url, PublicKey)], payload: &[u8]) -> Result<()> {
+        let epoch = current_epoch();
+        let route: Vec<_> =
+            route.iter().map(|(addr, key)| (addr.clone(), epoch_public(key, epoch))).collect();
+        let (addr, packet) = build_packet(&route, payload)?;
+        self.forward(&addr, packet).await
+    }
+
+    /// Handle a packet received from a peer. Packets that don't decrypt,
+    /// or that we already saw, are dropped.
+    pub async fn handle(&self, packet: &OnionPacket) -> Result<()> {
+        let current = current_epoch();
+        let (epoch, (peeled, replay_tag)) = match peel_epoch(packet, &self.secret, current) {
+            Ok(v) => (current, v),
+            Err(_) if current > 0 => (current - 1, peel_epoch(packet, &self.secret, current - 1)?),
+            Err(e) => return Err(e),
+        };
+
+        if !self.seen.lock().await.insert(epoch, current.saturating_sub(1), replay_tag) {
+            warn!(target: "net", "Dropping replayed onion packet");
+            return Ok(())
+        }
+
+        match peeled {
+            Peeled::Forward(addr, packet) => self.forward(&addr, packet).await,
+            Peeled::Deliver(payload) => {
+                debug!(target: "net", "Delivering onion payload of {} bytes", payload.len());
+                self.delivered.notify(payload).await;
+                Ok(())
+            }
+        }
+    }
+
+    async fn forward(&self, addr: &Url, packet: OnionPacket) -> Result<()> {
+        let channel = match self.p2p.channels().lock().await.get(addr) {
+            Some(v) => v.clone(),
+            None => {
+                warn!(target: "net", "Not connected to onion hop {}, dropping packet", addr);
+                return Err(Error::NetworkOperationFailed)
+            }
+        };
+
+        debug!(target: "net", "Forwarding onion packet to {}", addr);
+        channel.send(packet).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::Keypair;
+
+    fn route(n: usize) -> (Vec<Keypair>, Vec<(Url, PublicKey)>) {
+        let keypairs: Vec<Keypair> = (0..n).map(|_| Keypair::random(&mut OsRng)).collect();
+        let route = keypairs
+            .iter()
+            .enumerate()
+            .map(|(i, k)| {
+                (Url::parse(&format!("tcp://127.0.0.1:{}", 10000 + i)).unwrap(), k.public)
+            })
+            .collect();
+        (keypairs, route)
+    }
+
+    #[test]
+    fn onion_routing() {
+        for n in 1..=MAX_HOPS {
+            let (keypairs, route) = route(n);
+            let (mut addr, mut packet) = build_packet(&route, b"hello").unwrap();
+
+            for (i, keypair) in keypairs.iter().enumerate() {
+                assert_eq!(addr, route[i].0);
+
+                // Only the hop the packet is for can peel it
+                let other = &keypairs[(i + 1) % n];
+                assert!(n == 1 || peel(&packet, &other.secret).is_err());
+
+                match peel(&packet, &keypair.secret).unwrap().0 {
+                    Peeled::Forward(next, next_packet) => {
+                        assert!(i < n - 1);
+                        assert_ne!(next_packet.ephem_public, packet.ephem_public);
+                        addr = next;
+                        packet = next_packet;
+                    }
+                    Peeled::Deliver(payload) => {
+                        assert_eq!(i, n - 1);
+                        assert_eq!(payload, b"hello");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn onion_epochs() {
+        let (keypairs, route) = route(2);
+        let epoch = current_epoch();
+        let epoch_route: Vec<_> =
+            route.iter().map(|(addr, key)| (addr.clone(), epoch_public(key, epoch))).collect();
+        let (_, packet) = build_packet(&epoch_route, b"hello").unwrap();
+
+        // Only the key of the epoch the packet was made for peels it
+        assert!(peel(&packet, &keypairs[0].secret).is_err());
+        assert!(peel_epoch(&packet, &keypairs[0].secret, epoch + 1).is_err());
+        let (_, tag) = peel_epoch(&packet, &keypairs[0].secret, epoch).unwrap();
+
+        let mut seen = ReplayCache::default();
+        assert!(seen.insert(epoch, epoch, tag));
+        assert!(!seen.insert(epoch, epoch, tag));
+
+        // Tags are forgotten with the epoch of their key
+        assert!(seen.insert(epoch + 1, epoch, tag));
+        assert!(seen.insert(epoch + 2, epoch + 1, tag));
+        let epochs: Vec<u64> = seen.epochs.keys().copied().collect();
+        assert_eq!(epochs, vec![epoch + 1, epoch + 2]);
+    }
+
+    #[test]
+    fn onion_tampering() {
+        let (keypairs, route) = route(3);
+        let (_, packet) = build_packet(&route, b"hello").unwrap();
+
+        let mut tampered = packet.clone();
+        tampered.header[42] ^= 1;
+        assert!(peel(&tampered, &keypairs[0].secret).is_err());
+
+        // Payload tampering is only caught by the last hop
+        let mut tampered = packet;
+        tampered.payload[42] ^= 1;
+        let (peeled, _) = peel(&tampered, &keypairs[0].secret).unwrap();
+        let packet = match peeled {
+            Peeled::Forward(_, p) => p,
+            Peeled::Deliver(_) => unreachable!(),
+        };
+        let packet = match peel(&packet, &keypairs[1].secret).unwrap().0 {
+            Peeled::Forward(_, p) => p,
+            Peeled::Deliver(_) => unreachable!(),
+        };
+        assert!(peel(&packet, &keypairs[2].secret).is_err());
+
+        assert!(build_packet(&route, &[0u8; MAX_PAYLOAD_LEN + 1]).is_err());
+    }
+}
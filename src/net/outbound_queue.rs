@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+
+use async_std::sync::Mutex;
+use fxhash::FxHashMap;
+use log::debug;
+use url::Url;
+
+use crate::util::time::Timestamp;
+
+use super::{message::Packet, SettingsPtr};
+
+/// Message waiting for its peer to reconnect.
+struct QueuedPacket {
+    packet: Packet,
+    queued: Timestamp,
+}
+
+/// Messages waiting for a peer that went away.
+struct PeerQueue {
+    /// Last address the peer was connected on
+    address: Url,
+    packets: VecDeque<QueuedPacket>,
+    /// When the peer went away. Peers that don't come back before the
+    /// expiry are forgotten.
+    disconnected: Timestamp,
+}
+
+/// Per-peer queues of the messages that couldn't be sent to peers because
+/// they disconnected, to be sent once they reconnect. Only the message
+/// types listed in the `persistent_messages` setting are queued, and they
+/// are dropped once older than `outbound_queue_expiry_seconds`, so that a
+/// peer coming back doesn't get replayed stale data.
+///
+/// Peers are told apart by their node ID, or by their address for peers
+/// without one, which only finds outbound and manual peers again.
+pub struct OutboundQueue {
+    queues: Mutex<FxHashMap<String, PeerQueue>>,
+    settings: SettingsPtr,
+}
+
+impl OutboundQueue {
+    pub fn new(settings: SettingsPtr) -> Self {
+        Self { queues: Mutex::new(FxHashMap::default()), settings }
+    }
+
+    /// Whether messages with this command are queued for disconnected peers.
+    pub fn is_persistent(&self, command: &str) -> bool {
+        self.settings.persistent_messages.iter().any(|x| x == command)
+    }
+
+    /// Note that a peer went away, so that persistent messages broadcast
+    /// while it's gone are queued for it.
+    pub async fn disconnected(&self, peer: String, address: Url) {
+        if self.settings.persistent_messages.is_empty() {
+            return
+        }
+
+        let mut queues = self.queues.lock().await;
+        self.prune(&mut queues);
+        let queue = queues.entry(peer).or_insert_with(|| PeerQueue {
+            address: address.clone(),
+            packets: VecDeque::new(),
+            disconnected: Timestamp::current_time(),
+        });
+        queue.address = address;
+        queue.disconnected = Timestamp::current_time();
+    }
+
+    /// Queue a message for a peer, if it's persistent. When the queue is
+    /// full, the oldest message is dropped.
+    pub async fn push(&self, peer: String, address: Url, packet: Packet) {
+        if !self.is_persistent(&packet.command) {
+            return
+        }
+
+        let mut queues = self.queues.lock().await;
+        self.prune(&mut queues);
+        let queue = queues.entry(peer).or_insert_with(|| PeerQueue {
+            address,
+            packets: VecDeque::new(),
+            disconnected: Timestamp::current_time(),
+        });
+        self.push_packet(queue, packet);
+    }
+
+    /// Queue a broadcast message for all the peers that are away, except
+    /// the ones on the given addresses, if it's persistent.
+    pub async fn push_all(&self, packet: Packet, exclude_list: &[Url]) {
+        if !self.is_persistent(&packet.command) {
+            return
+        }
+
+        let mut queues = self.queues.lock().await;
+        self.prune(&mut queues);
+        for queue in queues.values_mut() {
+            if exclude_list.contains(&queue.address) {
+                continue
+            }
+            self.push_packet(queue, packet.clone());
+        }
+    }
+
+    /// Take the messages queued for a peer that reconnected, oldest first.
+    pub async fn take(&self, peer: &str) -> Vec<Packet> {
+        let mut queues = self.queues.lock().await;
+        self.prune(&mut queues);
+        match queues.remove(peer) {
+            Some(queue) => queue.packets.into_iter().map(|x| x.packet).collect(),
+            None => vec![],
+        }
+    }
+
+    fn push_packet(&self, queue: &mut PeerQueue, packet: Packet) {
+        if queue.packets.len() >= self.settings.outbound_queue_size {
+            if let Some(dropped) = queue.packets.pop_front() {
+                debug!(target: "net",
+                    "Outbound queue for {} is full, dropping a {} message",
+                    queue.address,
+                    dropped.packet.command
+                );
+            }
+        }
+
+        if self.settings.outbound_queue_size > 0 {
+            queue.packets.push_back(QueuedPacket { packet, queued: Timestamp::current_time() });
+        }
+    }
+
+    /// Drop expired messages, and peers that were away for too long.
+    fn prune(&self, queues: &mut FxHashMap<String, PeerQueue>) {
+        let expiry = self.settings.outbound_queue_expiry_seconds;
+        queues.retain(|_, queue| {
+            queue.packets.retain(|x| x.queued.elapsed() < expiry);
+            queue.disconnected.elapsed() < expiry || !queue.packets.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::net::Settings;
+
+    fn packet(command: &str, payload: u8) -> Packet {
+        Packet { command: String::from(command), payload: vec![payload] }
+    }
+
+    #[test]
+    fn outbound_queue() {
+        let settings = Settings {
+            persistent_messages: vec![String::from("tx")],
+            outbound_queue_size: 2,
+            ..Default::default()
+        };
+        let queue = OutboundQueue::new(Arc::new(settings));
+        let addr = Url::parse("tcp://127.0.0.1:1234").unwrap();
+
+        smol::block_on(async {
+            queue.disconnected(String::from("peer"), addr.clone()).await;
+
+            // Only persistent messages are queued, up to the queue size
+            queue.push(String::from("peer"), addr.clone(), packet("ping", 0)).await;
+            queue.push(String::from("peer"), addr.clone(), packet("tx", 1)).await;
+            queue.push_all(packet("tx", 2), &[]).await;
+            queue.push_all(packet("tx", 3), &[]).await;
+            queue.push_all(packet("tx", 4), &[addr.clone()]).await;
+
+            let payloads: Vec<Vec<u8>> =
+                queue.take("peer").await.into_iter().map(|x| x.payload).collect();
+            assert_eq!(payloads, vec![vec![2], vec![3]]);
+            assert!(queue.take("peer").await.is_empty());
+        });
+
+        // Nothing survives an expiry of 0 seconds
+        let settings = Settings {
+            persistent_messages: vec![String::from("tx")],
+            outbound_queue_expiry_seconds: 0,
+            ..Default::default()
+        };
+        let queue = OutboundQueue::new(Arc::new(settings));
+
+        smol::block_on(async {
+            queue.push(String::from("peer"), addr.clone(), packet("tx", 1)).await;
+            assert!(queue.take("peer").await.is_empty());
+        });
+    }
+}
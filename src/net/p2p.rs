@@ -3,20 +3,23 @@ use std::fmt;
 
 use async_executor::Executor;
 use fxhash::{FxHashMap, FxHashSet};
-use log::debug;
+use log::{debug, info, warn};
 use serde_json::json;
 use url::Url;
 
 use crate::{
     system::{Subscriber, SubscriberPtr, Subscription},
+    util::{async_util, expand_path},
     Error, Result,
 };
 
 use super::{
-    message::Message,
+    bandwidth::{ByteCounter, Throughput},
+    message::{Message, Packet},
+    nat,
     protocol::{register_default_protocols, ProtocolRegistry},
     session::{InboundSession, ManualSession, OutboundSession, SeedSession, Session},
-    Channel, ChannelPtr, Hosts, HostsPtr, Settings, SettingsPtr,
+    Channel, ChannelPtr, Hosts, HostsPtr, OutboundQueue, Settings, SettingsPtr, TransportName,
 };
 
 /// List of channels that are awaiting connection.
@@ -26,6 +29,9 @@ pub type ConnectedChannels = Mutex<fxhash::FxHashMap<Url, Arc<Channel>>>;
 /// Atomic pointer to p2p interface.
 pub type P2pPtr = Arc<P2p>;
 
+/// Seconds between writes of the hosts file
+const HOSTS_SAVE_INTERVAL: u64 = 300;
+
 enum P2pState {
     // The p2p object has been created but not yet started.
     Open,
@@ -61,6 +67,7 @@ pub struct P2p {
     stop_subscriber: SubscriberPtr<Error>,
     hosts: HostsPtr,
     protocol_registry: ProtocolRegistry,
+    outbound_queue: OutboundQueue,
 
     // We keep a reference to the sessions used for get info
     session_manual: Mutex<Option<Arc<ManualSession>>>,
@@ -69,6 +76,12 @@ pub struct P2p {
 
     state: Mutex<P2pState>,
 
+    /// Address we advertise to peers, the configured one or the one of
+    /// our port mapping
+    external_addr: Mutex<Option<Url>>,
+    /// Port mapping on the gateway, deleted when the network stops
+    port_mapping: Mutex<Option<nat::PortMapping>>,
+
     settings: SettingsPtr,
 }
 
@@ -83,17 +96,29 @@ impl P2p {
     pub async fn new(settings: Settings) -> Arc<Self> {
         let settings = Arc::new(settings);
 
+        let hosts = match settings.hosts_file.as_ref().map(|path| expand_path(path)) {
+            Some(Ok(path)) => Hosts::load(path).await,
+            Some(Err(e)) => {
+                warn!(target: "net", "Invalid hosts file, not keeping hosts: {}", e);
+                Hosts::new()
+            }
+            None => Hosts::new(),
+        };
+
         let self_ = Arc::new(Self {
             pending: Mutex::new(FxHashSet::default()),
             channels: Mutex::new(FxHashMap::default()),
             channel_subscriber: Subscriber::new(),
             stop_subscriber: Subscriber::new(),
-            hosts: Hosts::new(),
+            hosts,
             protocol_registry: ProtocolRegistry::new(),
+            outbound_queue: OutboundQueue::new(settings.clone()),
             session_manual: Mutex::new(None),
             session_inbound: Mutex::new(None),
             session_outbound: Mutex::new(None),
             state: Mutex::new(P2pState::Open),
+            external_addr: Mutex::new(settings.external_addr.clone()),
+            port_mapping: Mutex::new(None),
             settings,
         });
 
@@ -110,9 +135,8 @@ impl P2p {
 
     pub async fn get_info(&self) -> serde_json::Value {
         let external_addr = self
-            .settings
-            .external_addr
-            .as_ref()
+            .external_addr()
+            .await
             .map(|addr| serde_json::Value::from(addr.to_string()))
             .unwrap_or(serde_json::Value::Null);
 
@@ -125,12 +149,55 @@ impl P2p {
         })
     }
 
+    /// Bytes sent and received on each connected channel by message type,
+    /// and their totals across channels, along with the bytes per second
+    /// going through each channel lately.
+    pub async fn get_bandwidth_stats(&self) -> serde_json::Value {
+        let mut channels = vec![];
+        let mut messages: FxHashMap<String, ByteCounter> = FxHashMap::default();
+        let mut total = ByteCounter::default();
+        let mut throughput = Throughput::default();
+
+        for channel in self.channels.lock().await.values() {
+            let counters = channel.bandwidth().counters().await;
+            let channel_throughput = channel.bandwidth().throughput().await;
+            throughput.add(&channel_throughput);
+            let mut channel_total = ByteCounter::default();
+            for (command, counter) in &counters {
+                messages.entry(command.clone()).or_default().add(counter);
+                channel_total.add(counter);
+            }
+            total.add(&channel_total);
+
+            channels.push(json!({
+                "address": channel.address().to_string(),
+                "remote_node_id": channel.remote_node_id().await,
+                "messages": counters,
+                "total": channel_total,
+                "throughput": channel_throughput,
+            }));
+        }
+
+        json!({
+            "channels": channels,
+            "messages": messages,
+            "total": total,
+            "throughput": throughput,
+        })
+    }
+
     /// Invoke startup and seeding sequence. Call from constructing thread.
     pub async fn start(self: Arc<Self>, executor: Arc<Executor<'_>>) -> Result<()> {
         debug!(target: "net", "P2p::start() [BEGIN]");
 
         *self.state.lock().await = P2pState::Start;
 
+        // Map our port first, so we advertise its address to the seeds
+        if self.settings.port_mapping {
+            self.map_port().await;
+            executor.spawn(self.clone().refresh_port_mapping()).detach();
+        }
+
         // Start seed session
         let seed = SeedSession::new(Arc::downgrade(&self));
         // This will block until all seed queries have finished
@@ -142,6 +209,131 @@ impl P2p {
         Ok(())
     }
 
+    /// Address peers can reach us on, if we know it.
+    pub async fn external_addr(&self) -> Option<Url> {
+        self.external_addr.lock().await.clone()
+    }
+
+    /// Map the inbound port on the gateway. Unless an external address is
+    /// configured, the gateway's address becomes ours.
+    async fn map_port(&self) {
+        let inbound = match &self.settings.inbound {
+            Some(v) => v,
+            None => {
+                warn!(target: "net", "Port mapping needs an inbound address, skipping it");
+                return
+            }
+        };
+
+        let port = match (TransportName::try_from(inbound.clone()), inbound.port()) {
+            (Ok(TransportName::Tcp(_)), Some(port)) => port,
+            _ => {
+                warn!(target: "net", "Can't map the port of {}, skipping it", inbound);
+                return
+            }
+        };
+
+        let mapping = match nat::map_port(port, self.settings.port_mapping_lease_seconds).await {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(target: "net", "Failed mapping port {} on the gateway: {}", port, e);
+                return
+            }
+        };
+
+        let external_ip = mapping.external_ip;
+        let external_port = mapping.external_port;
+        let protocol = mapping.protocol;
+        *self.port_mapping.lock().await = Some(mapping);
+
+        if self.settings.external_addr.is_none() {
+            let mut addr = inbound.clone();
+            let _ = addr.set_ip_host(external_ip);
+            let _ = addr.set_port(Some(external_port));
+
+            let mut external_addr = self.external_addr.lock().await;
+            if external_addr.as_ref() != Some(&addr) {
+                info!(target: "net", "Mapped port {} to {} ({:?})", port, addr, protocol);
+                *external_addr = Some(addr);
+            }
+        }
+    }
+
+    /// Renew the port mapping halfway through the lifetime the gateway
+    /// granted, until the network stops. Without a mapping, it's retried
+    /// halfway through the configured lease.
+    async fn refresh_port_mapping(self: Arc<Self>) -> Result<()> {
+        let stop_sub = self.subscribe_stop().await;
+
+        loop {
+            let lifetime = match &*self.port_mapping.lock().await {
+                Some(mapping) => mapping.lifetime,
+                None => self.settings.port_mapping_lease_seconds,
+            };
+            let refresh_seconds = (lifetime / 2).max(1);
+
+            let stopped = smol::future::or(
+                async {
+                    stop_sub.receive().await;
+                    true
+                },
+                async {
+                    async_util::sleep(refresh_seconds).await;
+                    false
+                },
+            )
+            .await;
+
+            if stopped {
+                return Ok(())
+            }
+
+            debug!(target: "net", "P2p::refresh_port_mapping() Renewing the port mapping");
+            self.map_port().await;
+        }
+    }
+
+    /// Delete the port mapping from the gateway, if we have one
+    async fn unmap_port(&self) {
+        let mapping = match self.port_mapping.lock().await.take() {
+            Some(v) => v,
+            None => return,
+        };
+
+        match nat::unmap_port(&mapping).await {
+            Ok(()) => info!(target: "net", "Deleted mapping of port {}", mapping.external_port),
+            Err(e) => warn!(target: "net", "Failed deleting port mapping: {}", e),
+        }
+    }
+
+    /// Write the hosts to their file every few minutes, until the network
+    /// stops, so a crash loses little of what was learned
+    async fn save_hosts(self: Arc<Self>) -> Result<()> {
+        let stop_sub = self.subscribe_stop().await;
+
+        loop {
+            let stopped = smol::future::or(
+                async {
+                    stop_sub.receive().await;
+                    true
+                },
+                async {
+                    async_util::sleep(HOSTS_SAVE_INTERVAL).await;
+                    false
+                },
+            )
+            .await;
+
+            if stopped {
+                return Ok(())
+            }
+
+            if let Err(e) = self.hosts.save().await {
+                warn!(target: "net", "Failed saving hosts: {}", e);
+            }
+        }
+    }
+
     pub async fn session_manual(&self) -> Arc<ManualSession> {
         self.session_manual.lock().await.as_ref().unwrap().clone()
     }
@@ -170,6 +362,10 @@ impl P2p {
         let outbound = self.session_outbound().await;
         outbound.clone().start(executor.clone()).await?;
 
+        if self.settings.hosts_file.is_some() {
+            executor.spawn(self.clone().save_hosts()).detach();
+        }
+
         let stop_sub = self.subscribe_stop().await;
         // Wait for stop signal
         stop_sub.receive().await;
@@ -178,13 +374,20 @@ impl P2p {
         manual.stop().await;
         inbound.stop().await;
         outbound.stop().await;
+        self.unmap_port().await;
+
+        if let Err(e) = self.hosts.save().await {
+            warn!(target: "net", "Failed saving hosts: {}", e);
+        }
 
         debug!(target: "net", "P2p::run() [END]");
         Ok(())
     }
 
-    /// Broadcasts a message across all channels.
+    /// Broadcasts a message across all channels. Persistent messages are
+    /// also queued for the peers that are away.
     pub async fn broadcast<M: Message + Clone>(&self, message: M) -> Result<()> {
+        self.queue_broadcast(&message, &[]).await?;
         for channel in self.channels.lock().await.values() {
             channel.send(message.clone()).await?;
         }
@@ -198,6 +401,7 @@ impl P2p {
         message: M,
         exclude_list: &[Url],
     ) -> Result<()> {
+        self.queue_broadcast(&message, exclude_list).await?;
         for channel in self.channels.lock().await.values() {
             if exclude_list.contains(&channel.address()) {
                 continue
@@ -207,15 +411,38 @@ impl P2p {
         Ok(())
     }
 
-    /// Add channel address to the list of connected channels.
+    /// Queue a persistent broadcast message for the peers that are away.
+    async fn queue_broadcast<M: Message>(&self, message: &M, exclude_list: &[Url]) -> Result<()> {
+        if !self.outbound_queue.is_persistent(M::name()) {
+            return Ok(())
+        }
+
+        let mut payload = vec![];
+        message.encode(&mut payload)?;
+        let packet = Packet { command: String::from(M::name()), payload };
+        self.outbound_queue.push_all(packet, exclude_list).await;
+        Ok(())
+    }
+
+    /// Add channel address to the list of connected channels, and send it
+    /// the messages queued while the peer was away.
     pub async fn store(&self, channel: ChannelPtr) {
         self.channels.lock().await.insert(channel.address(), channel.clone());
+
+        for packet in self.outbound_queue.take(&channel.peer_id().await).await {
+            if let Err(e) = channel.send_packet(packet).await {
+                warn!("Failed sending queued message to {}: {}", channel.address(), e);
+                break
+            }
+        }
+
         self.channel_subscriber.notify(Ok(channel)).await;
     }
 
     /// Remove a channel from the list of connected channels.
     pub async fn remove(&self, channel: ChannelPtr) {
         self.channels.lock().await.remove(&channel.address());
+        self.outbound_queue.disconnected(channel.peer_id().await, channel.address()).await;
     }
 
     /// Check whether a channel is stored in the list of connected channels.
@@ -252,6 +479,11 @@ impl P2p {
         &self.protocol_registry
     }
 
+    /// Return the queues of messages for peers that are away.
+    pub fn outbound_queue(&self) -> &OutboundQueue {
+        &self.outbound_queue
+    }
+
     /// Subscribe to a channel.
     pub async fn subscribe_channel(&self) -> Subscription<Result<ChannelPtr>> {
         self.channel_subscriber.clone().subscribe().await
@@ -262,6 +494,13 @@ impl P2p {
         self.stop_subscriber.clone().subscribe().await
     }
 
+    /// Stop the network. The port mapping is deleted before returning, as
+    /// the process may exit right after.
+    pub async fn stop(&self) {
+        self.stop_subscriber.notify(Error::NetworkServiceStopped).await;
+        self.unmap_port().await;
+    }
+
     pub fn channels(&self) -> &ConnectedChannels {
         &self.channels
     }
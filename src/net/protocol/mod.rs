@@ -9,6 +9,12 @@
 /// address information to their local store.
 pub mod protocol_address;
 
+/// Protocol for cover traffic. On channels where both nodes asked for it
+/// in the version handshake, dummy messages of random size are sent at
+/// random intervals, so that passive observers can't tell when actual
+/// messages are sent. The receiving channel drops them.
+pub mod protocol_cover;
+
 /// Manages the tasks for the network protocol. Used by other connection
 /// protocols to handle asynchronous task execution across the network. Runs all
 /// tasks that are handed to it on an executor that has stopping functionality.
@@ -26,6 +32,13 @@ pub mod protocol_jobs_manager;
 /// with a pong.
 pub mod protocol_ping;
 
+/// Protocol for relaying onion packets. Packets received on a channel are
+/// handed to the node's onion relay, which forwards them to the next hop or
+/// delivers their payload to us. Not registered by default, since relaying
+/// needs a keypair.
+#[cfg(feature = "crypto")]
+pub mod protocol_onion;
+
 /// Seed server protocol. Seed server is used when connecting to the network for
 /// the first time. Returns a list of IP addresses that nodes can connect to.
 ///
@@ -50,7 +63,10 @@ pub mod protocol_base;
 pub mod protocol_registry;
 
 pub use protocol_address::ProtocolAddress;
+pub use protocol_cover::ProtocolCover;
 pub use protocol_jobs_manager::{ProtocolJobsManager, ProtocolJobsManagerPtr};
+#[cfg(feature = "crypto")]
+pub use protocol_onion::ProtocolOnion;
 pub use protocol_ping::ProtocolPing;
 pub use protocol_seed::ProtocolSeed;
 pub use protocol_version::ProtocolVersion;
@@ -66,6 +82,7 @@ use super::{
 pub async fn register_default_protocols(p2p: P2pPtr) {
     let registry = p2p.protocol_registry();
     registry.register(SESSION_ALL, ProtocolPing::init).await;
+    registry.register(!SESSION_SEED, ProtocolCover::init).await;
     registry.register(!SESSION_SEED, ProtocolAddress::init).await;
     registry.register(SESSION_SEED, ProtocolSeed::init).await;
 }
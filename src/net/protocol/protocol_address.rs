@@ -3,19 +3,20 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use log::debug;
 use smol::Executor;
-use url::Url;
 
 use crate::{util::async_util, Result};
 
 use super::{
     super::{
         message, message_subscriber::MessageSubscription, ChannelPtr, HostsPtr, P2pPtr,
-        SettingsPtr, SESSION_OUTBOUND,
+        SESSION_OUTBOUND,
     },
     ProtocolBase, ProtocolBasePtr, ProtocolJobsManager, ProtocolJobsManagerPtr,
 };
 
 const SEND_ADDR_SLEEP_SECONDS: u64 = 900;
+/// Most addresses sent in, or taken from, an address message
+const MAX_ADDRS: usize = 100;
 
 /// Defines address and get-address messages.
 pub struct ProtocolAddress {
@@ -24,14 +25,13 @@ pub struct ProtocolAddress {
     get_addrs_sub: MessageSubscription<message::GetAddrsMessage>,
     hosts: HostsPtr,
     jobsman: ProtocolJobsManagerPtr,
-    settings: SettingsPtr,
+    p2p: P2pPtr,
 }
 
 impl ProtocolAddress {
     /// Create a new address protocol. Makes an address and get-address
     /// subscription and adds them to the address protocol instance.
     pub async fn init(channel: ChannelPtr, p2p: P2pPtr) -> ProtocolBasePtr {
-        let settings = p2p.settings();
         let hosts = p2p.hosts();
 
         // Creates a subscription to address message.
@@ -54,33 +54,35 @@ impl ProtocolAddress {
             get_addrs_sub,
             hosts,
             jobsman: ProtocolJobsManager::new("ProtocolAddress", channel),
-            settings,
+            p2p,
         })
     }
 
     /// Handles receiving the address message. Loops to continually recieve
     /// address messages on the address subsciption. Adds the recieved
-    /// addresses to the list of hosts.
+    /// addresses to the list of hosts, ignoring any past the first
+    /// `MAX_ADDRS` so a peer can't flood it.
     async fn handle_receive_addrs(self: Arc<Self>) -> Result<()> {
         debug!(target: "net", "ProtocolAddress::handle_receive_addrs() [START]");
         loop {
             let addrs_msg = self.addrs_sub.receive().await?;
+            let addrs: Vec<_> = addrs_msg.addrs.iter().take(MAX_ADDRS).cloned().collect();
 
             debug!(
             target: "net",
             "ProtocolAddress::handle_receive_addrs() received {} addrs",
             addrs_msg.addrs.len()
             );
-            for (i, addr) in addrs_msg.addrs.iter().enumerate() {
+            for (i, addr) in addrs.iter().enumerate() {
                 debug!("  addr[{}]: {}", i, addr);
             }
-            self.hosts.store(addrs_msg.addrs.clone()).await;
+            self.hosts.store(addrs).await;
         }
     }
 
     /// Handles receiving the get-address message. Continually recieves
     /// get-address messages on the get-address subsciption. Then replies
-    /// with an address message.
+    /// with an address message of up to `MAX_ADDRS` random hosts.
     async fn handle_receive_get_addrs(self: Arc<Self>) -> Result<()> {
         debug!(target: "net", "ProtocolAddress::handle_receive_get_addrs() [START]");
         loop {
@@ -88,8 +90,8 @@ impl ProtocolAddress {
 
             debug!(target: "net", "ProtocolAddress::handle_receive_get_addrs() received GetAddrs message");
 
-            // Loads the list of hosts.
-            let addrs = self.hosts.load_all().await;
+            // Picks a sample of the hosts.
+            let addrs = self.hosts.load_random(MAX_ADDRS).await;
             debug!(
             target: "net",
             "ProtocolAddress::handle_receive_get_addrs() sending {} addrs",
@@ -102,12 +104,15 @@ impl ProtocolAddress {
         }
     }
 
-    async fn send_addrs(self: Arc<Self>, addrs: Vec<Url>) -> Result<()> {
-        debug!(target: "net", "ProtocolAddress::send_addrs() [START]");
+    /// Sends our external address periodically. It's looked up every
+    /// time, as a port mapping can change it.
+    async fn send_self_addr(self: Arc<Self>) -> Result<()> {
+        debug!(target: "net", "ProtocolAddress::send_self_addr() [START]");
         loop {
-            let addrs = addrs.clone();
-            let addr_msg = message::AddrsMessage { addrs };
-            self.channel.clone().send(addr_msg).await?;
+            if let Some(addr) = self.p2p.external_addr().await {
+                let addr_msg = message::AddrsMessage { addrs: vec![addr] };
+                self.channel.clone().send(addr_msg).await?;
+            }
             async_util::sleep(SEND_ADDR_SLEEP_SECONDS).await;
         }
     }
@@ -123,15 +128,9 @@ impl ProtocolBase for ProtocolAddress {
 
         // if it's an outbound session + has an external address
         // send our address
-        if type_id == SESSION_OUTBOUND && self.settings.external_addr.is_some() {
+        if type_id == SESSION_OUTBOUND && self.p2p.external_addr().await.is_some() {
             self.jobsman.clone().start(executor.clone());
-            self.jobsman
-                .clone()
-                .spawn(
-                    self.clone().send_addrs(vec![self.settings.external_addr.clone().unwrap()]),
-                    executor.clone(),
-                )
-                .await;
+            self.jobsman.clone().spawn(self.clone().send_self_addr(), executor.clone()).await;
         }
 
         debug!(target: "net", "ProtocolAddress::start() [START]");
@@ -0,0 +1,70 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use log::debug;
+use rand::{rngs::OsRng, Rng, RngCore};
+use smol::{Executor, Timer};
+
+use crate::Result;
+
+use super::{
+    super::{message, ChannelPtr, P2pPtr, SettingsPtr},
+    ProtocolBase, ProtocolBasePtr, ProtocolJobsManager, ProtocolJobsManagerPtr,
+};
+
+/// Largest amount of random data in a cover message.
+const MAX_COVER_SIZE: usize = 1024;
+
+/// Sends dummy messages on channels where both ends asked for cover traffic.
+pub struct ProtocolCover {
+    channel: ChannelPtr,
+    settings: SettingsPtr,
+    jobsman: ProtocolJobsManagerPtr,
+}
+
+impl ProtocolCover {
+    /// Create a new cover traffic protocol.
+    pub async fn init(channel: ChannelPtr, p2p: P2pPtr) -> ProtocolBasePtr {
+        Arc::new(Self {
+            channel: channel.clone(),
+            settings: p2p.settings(),
+            jobsman: ProtocolJobsManager::new("ProtocolCover", channel),
+        })
+    }
+
+    /// Send a cover message of random size after a random delay, averaging
+    /// to one every `cover_traffic_seconds`, until the channel stops.
+    async fn send_cover_traffic(self: Arc<Self>) -> Result<()> {
+        debug!(target: "net", "ProtocolCover::send_cover_traffic() [START]");
+        let max_delay = u64::from(self.settings.cover_traffic_seconds) * 2000;
+
+        loop {
+            let delay = OsRng.gen_range(0..=max_delay);
+            Timer::after(Duration::from_millis(delay)).await;
+
+            let mut data = vec![0; OsRng.gen_range(0..=MAX_COVER_SIZE)];
+            OsRng.fill_bytes(&mut data);
+            self.channel.send(message::CoverMessage { data }).await?;
+        }
+    }
+}
+
+#[async_trait]
+impl ProtocolBase for ProtocolCover {
+    async fn start(self: Arc<Self>, executor: Arc<Executor<'_>>) -> Result<()> {
+        debug!(target: "net", "ProtocolCover::start() [START]");
+        // The handshake is done by now, so the channel knows whether the
+        // remote node wants cover traffic as well.
+        let capabilities = self.channel.capabilities().await;
+        if capabilities & message::CAPABILITY_COVER_TRAFFIC != 0 {
+            self.jobsman.clone().start(executor.clone());
+            self.jobsman.clone().spawn(self.clone().send_cover_traffic(), executor).await;
+        }
+        debug!(target: "net", "ProtocolCover::start() [END]");
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "ProtocolCover"
+    }
+}
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::{debug, warn};
+use smol::Executor;
+
+use crate::Result;
+
+use super::{
+    super::{
+        message_subscriber::MessageSubscription,
+        onion::{OnionPacket, OnionRelayPtr},
+        ChannelPtr,
+    },
+    ProtocolBase, ProtocolBasePtr, ProtocolJobsManager, ProtocolJobsManagerPtr,
+};
+
+/// Hands the onion packets received on a channel to the onion relay.
+pub struct ProtocolOnion {
+    onion_sub: MessageSubscription<OnionPacket>,
+    relay: OnionRelayPtr,
+    jobsman: ProtocolJobsManagerPtr,
+}
+
+impl ProtocolOnion {
+    /// Create a new onion protocol. Nodes relaying onion packets register
+    /// it with their relay:
+    ///
+    /// ```ignore
+    /// let relay = OnionRelay::new(secret, p2p.clone());
+    /// registry
+    ///     .register(SESSION_ALL, move |channel, _| ProtocolOnion::init(channel, relay.clone()))
+    ///     .await;
+    /// ```
+    pub async fn init(channel: ChannelPtr, relay: OnionRelayPtr) -> ProtocolBasePtr {
+        let msg_subsystem = channel.get_message_subsystem();
+        msg_subsystem.add_dispatch::<OnionPacket>().await;
+
+        let onion_sub =
+            channel.subscribe_msg::<OnionPacket>().await.expect("Missing onion dispatcher!");
+
+        Arc::new(Self {
+            onion_sub,
+            relay,
+            jobsman: ProtocolJobsManager::new("ProtocolOnion", channel),
+        })
+    }
+
+    /// Receive onion packets and relay them. Packets we can't relay are
+    /// dropped, without stopping the channel.
+    async fn handle_receive_packet(self: Arc<Self>) -> Result<()> {
+        debug!(target: "net", "ProtocolOnion::handle_receive_packet() [START]");
+        loop {
+            let packet = self.onion_sub.receive().await?;
+            if let Err(e) = self.relay.handle(&packet).await {
+                warn!(target: "net", "Failed relaying onion packet: {}", e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ProtocolBase for ProtocolOnion {
+    async fn start(self: Arc<Self>, executor: Arc<Executor<'_>>) -> Result<()> {
+        debug!(target: "net", "ProtocolOnion::start() [START]");
+        self.jobsman.clone().start(executor.clone());
+        self.jobsman.clone().spawn(self.clone().handle_receive_packet(), executor).await;
+        debug!(target: "net", "ProtocolOnion::start() [END]");
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "ProtocolOnion"
+    }
+}
@@ -7,9 +7,7 @@ use smol::Executor;
 use crate::Result;
 
 use super::{
-    super::{
-        message, message_subscriber::MessageSubscription, ChannelPtr, HostsPtr, P2pPtr, SettingsPtr,
-    },
+    super::{message, message_subscriber::MessageSubscription, ChannelPtr, HostsPtr, P2pPtr},
     ProtocolBase, ProtocolBasePtr,
 };
 
@@ -17,7 +15,7 @@ use super::{
 pub struct ProtocolSeed {
     channel: ChannelPtr,
     hosts: HostsPtr,
-    settings: SettingsPtr,
+    p2p: P2pPtr,
     addr_sub: MessageSubscription<message::AddrsMessage>,
 }
 
@@ -25,7 +23,6 @@ impl ProtocolSeed {
     /// Create a new seed protocol.
     pub async fn init(channel: ChannelPtr, p2p: P2pPtr) -> ProtocolBasePtr {
         let hosts = p2p.hosts();
-        let settings = p2p.settings();
 
         //// Create a subscription to address message.
         let addr_sub = channel
@@ -34,20 +31,20 @@ impl ProtocolSeed {
             .await
             .expect("Missing addr dispatcher!");
 
-        Arc::new(Self { channel, hosts, settings, addr_sub })
+        Arc::new(Self { channel, hosts, p2p, addr_sub })
     }
 
-    /// Sends own external address over a channel. Imports own external address
-    /// from settings, then adds that address to an address message and
-    /// sends it out over the channel.
+    /// Sends own external address over a channel. Gets own external address
+    /// from p2p, then adds that address to an address message and sends it
+    /// out over the channel.
     pub async fn send_self_address(&self) -> Result<()> {
-        match self.settings.external_addr.clone() {
+        match self.p2p.external_addr().await {
             Some(addr) => {
                 debug!(target: "net", "ProtocolSeed::send_own_address() addr={}", addr);
                 let addr = message::AddrsMessage { addrs: vec![addr] };
                 Ok(self.channel.clone().send(addr).await?)
             }
-            // Do nothing if external address is not configured
+            // Do nothing if external address is not known
             None => Ok(()),
         }
     }
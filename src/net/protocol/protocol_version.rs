@@ -75,7 +75,10 @@ impl ProtocolVersion {
     /// Send version info and wait for version acknowledgement.
     async fn send_version(self: Arc<Self>) -> Result<()> {
         debug!(target: "net", "ProtocolVersion::send_version() [START]");
-        let version = message::VersionMessage { node_id: self.settings.node_id.clone() };
+        let version = message::VersionMessage {
+            node_id: self.settings.node_id.clone(),
+            capabilities: self.settings.capabilities(),
+        };
         self.channel.clone().send(version).await?;
 
         // Wait for version acknowledgement
@@ -92,6 +95,10 @@ impl ProtocolVersion {
         let version = self.version_sub.receive().await?;
         self.channel.set_remote_node_id(version.node_id.clone()).await;
 
+        // Only use the traffic features both of us asked for
+        let capabilities = self.settings.capabilities() & version.capabilities;
+        self.channel.set_capabilities(capabilities).await;
+
         // Check the message is OK
 
         // Send version acknowledgement
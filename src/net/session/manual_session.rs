@@ -13,7 +13,7 @@ use crate::{
 };
 
 use super::{
-    super::{Connector, P2p},
+    super::{ChannelPtr, Connector, P2p},
     Session, SessionBitflag, SESSION_MANUAL,
 };
 
@@ -51,6 +51,30 @@ impl ManualSession {
         self.connect_slots.lock().await.push(task);
     }
 
+    /// Connect to `addr` once, without retrying, and return the channel
+    /// when its handshake is done. The caller stops it when done with it.
+    pub async fn connect_once(
+        self: Arc<Self>,
+        addr: &Url,
+        executor: Arc<Executor<'_>>,
+    ) -> Result<ChannelPtr> {
+        let parent = Arc::downgrade(&self);
+        let connector = Connector::new(self.p2p().settings(), Arc::new(parent));
+
+        self.p2p().add_pending(addr.clone()).await;
+        info!(target: "net", "Connecting to [{}]", addr);
+
+        let result = match connector.connect(addr.clone()).await {
+            Ok(channel) => {
+                self.clone().register_channel(channel.clone(), executor).await.map(|_| channel)
+            }
+            Err(e) => Err(e),
+        };
+
+        self.p2p().remove_pending(addr).await;
+        result
+    }
+
     pub async fn channel_connect_loop(
         self: Arc<Self>,
         addr: Url,
@@ -166,6 +166,7 @@ impl OutboundSession {
 
                     // Remove pending lock since register_channel will add the channel to p2p
                     self.p2p().remove_pending(&addr).await;
+                    self.p2p().hosts().mark_connected(addr.clone()).await;
                     {
                         let info = &mut self.slot_info.lock().await[slot_number as usize];
                         info.channel = Some(channel.clone());
@@ -196,7 +197,7 @@ impl OutboundSession {
     async fn load_address(&self, slot_number: u32) -> Result<Url> {
         loop {
             let p2p = self.p2p();
-            let self_inbound_addr = p2p.settings().external_addr.clone();
+            let self_inbound_addr = p2p.external_addr().await;
 
             let mut addrs;
 
@@ -38,16 +38,14 @@ impl SeedSession {
         if settings.seeds.is_empty() {
             warn!("Skipping seed sync process since no seeds are configured.");
             // Store external address in hosts explicitly
-            match &settings.external_addr {
-                Some(addr) => self.p2p().hosts().store(vec![addr.clone()]).await,
+            match self.p2p().external_addr().await {
+                Some(addr) => self.p2p().hosts().store(vec![addr]).await,
                 None => (),
             }
 
             return Ok(())
         }
 
-        // if cached addresses then quit
-
         let mut tasks = Vec::new();
 
         // This loops through all the seeds and tries to start them.
@@ -1,10 +1,12 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use serde::Deserialize;
 use structopt::StructOpt;
 use structopt_toml::StructOptToml;
 use url::Url;
 
+use super::message::{CapabilityBitflag, CAPABILITY_COVER_TRAFFIC, CAPABILITY_PADDING};
+
 /// Atomic pointer to network settings.
 pub type SettingsPtr = Arc<Settings>;
 
@@ -23,6 +25,18 @@ pub struct Settings {
     pub peers: Vec<Url>,
     pub seeds: Vec<Url>,
     pub node_id: String,
+    pub channel_padding: bool,
+    pub cover_traffic_seconds: u32,
+    pub persistent_messages: Vec<String>,
+    pub outbound_queue_size: usize,
+    pub outbound_queue_expiry_seconds: u64,
+    pub rate_limits: HashMap<String, u64>,
+    pub recv_rate_limit: u64,
+    pub recv_rate_grace_seconds: u64,
+    pub port_mapping: bool,
+    pub port_mapping_lease_seconds: u64,
+    pub socks5_proxy: Option<Url>,
+    pub hosts_file: Option<String>,
 }
 
 impl Default for Settings {
@@ -40,7 +54,34 @@ impl Default for Settings {
             peers: Vec::new(),
             seeds: Vec::new(),
             node_id: String::new(),
+            channel_padding: false,
+            cover_traffic_seconds: 0,
+            persistent_messages: Vec::new(),
+            outbound_queue_size: 64,
+            outbound_queue_expiry_seconds: 120,
+            rate_limits: HashMap::new(),
+            recv_rate_limit: 0,
+            recv_rate_grace_seconds: 30,
+            port_mapping: false,
+            port_mapping_lease_seconds: 3600,
+            socks5_proxy: None,
+            hosts_file: None,
+        }
+    }
+}
+
+impl Settings {
+    /// Traffic features we ask for on our channels, used on the ones where
+    /// the remote node asks for them too.
+    pub fn capabilities(&self) -> CapabilityBitflag {
+        let mut capabilities = 0;
+        if self.channel_padding {
+            capabilities |= CAPABILITY_PADDING;
+        }
+        if self.cover_traffic_seconds > 0 {
+            capabilities |= CAPABILITY_COVER_TRAFFIC;
         }
+        capabilities
     }
 }
 
@@ -70,6 +111,31 @@ pub struct SettingsOpt {
     #[structopt(long)]
     pub seeds: Vec<Url>,
 
+    /// Pad messages to fixed sizes with peers doing the same
+    #[serde(default)]
+    #[structopt(long)]
+    pub channel_padding: bool,
+
+    /// Mean interval in seconds between dummy messages sent to peers
+    /// doing the same (0 to disable)
+    #[structopt(long)]
+    pub cover_traffic_seconds: Option<u32>,
+
+    /// Map the accept port on the gateway through NAT-PMP or UPnP, and
+    /// advertise the gateway's address unless an external one is set
+    #[serde(default)]
+    #[structopt(long)]
+    pub port_mapping: bool,
+
+    /// SOCKS5 proxy to dial peers through, e.g. socks5://127.0.0.1:9050
+    /// for Tor. Onion peers go through Tor's default proxy without one.
+    #[structopt(long)]
+    pub socks5_proxy: Option<Url>,
+
+    /// File to remember the addresses of known peers in across restarts
+    #[structopt(long)]
+    pub hosts_file: Option<String>,
+
     #[structopt(skip)]
     pub manual_attempt_limit: Option<u32>,
     #[structopt(skip)]
@@ -82,10 +148,35 @@ pub struct SettingsOpt {
     pub channel_heartbeat_seconds: Option<u32>,
     #[structopt(skip)]
     pub outbound_retry_seconds: Option<u64>,
+    #[structopt(skip)]
+    pub port_mapping_lease_seconds: Option<u64>,
 
     #[serde(default)]
     #[structopt(skip)]
     pub node_id: String,
+
+    /// Messages queued for peers that disconnected, to be sent once they
+    /// reconnect
+    #[serde(default)]
+    #[structopt(skip)]
+    pub persistent_messages: Vec<String>,
+    #[structopt(skip)]
+    pub outbound_queue_size: Option<usize>,
+    #[structopt(skip)]
+    pub outbound_queue_expiry_seconds: Option<u64>,
+
+    /// Most bytes per second sent on each channel for the given message
+    /// types, so they don't starve the others
+    #[serde(default)]
+    #[structopt(skip)]
+    pub rate_limits: HashMap<String, u64>,
+
+    /// Most bytes per second read from each peer (0 to disable). Peers
+    /// held back by it for longer than the grace period are dropped.
+    #[structopt(long)]
+    pub recv_rate_limit: Option<u64>,
+    #[structopt(skip)]
+    pub recv_rate_grace_seconds: Option<u64>,
 }
 
 impl From<SettingsOpt> for Settings {
@@ -103,6 +194,20 @@ impl From<SettingsOpt> for Settings {
             peers: settings_opt.peers,
             seeds: settings_opt.seeds,
             node_id: settings_opt.node_id,
+            channel_padding: settings_opt.channel_padding,
+            cover_traffic_seconds: settings_opt.cover_traffic_seconds.unwrap_or(0),
+            persistent_messages: settings_opt.persistent_messages,
+            outbound_queue_size: settings_opt.outbound_queue_size.unwrap_or(64),
+            outbound_queue_expiry_seconds: settings_opt
+                .outbound_queue_expiry_seconds
+                .unwrap_or(120),
+            rate_limits: settings_opt.rate_limits,
+            recv_rate_limit: settings_opt.recv_rate_limit.unwrap_or(0),
+            recv_rate_grace_seconds: settings_opt.recv_rate_grace_seconds.unwrap_or(30),
+            port_mapping: settings_opt.port_mapping,
+            port_mapping_lease_seconds: settings_opt.port_mapping_lease_seconds.unwrap_or(3600),
+            socks5_proxy: settings_opt.socks5_proxy,
+            hosts_file: settings_opt.hosts_file,
         }
     }
 }
@@ -17,6 +17,9 @@ pub use tcp::TcpTransport;
 mod tor;
 pub use tor::TorTransport;
 
+mod socks5;
+pub use socks5::Socks5Transport;
+
 mod unix;
 pub use unix::UnixTransport;
 
@@ -26,6 +29,27 @@ pub(crate) fn socket_addr_to_url(addr: SocketAddr, scheme: &str) -> Result<Url>
     Ok(url)
 }
 
+/// Whether the host of an address is a Tor onion service
+pub fn is_onion(url: &Url) -> bool {
+    url.host_str().map_or(false, |host| host.ends_with(".onion"))
+}
+
+/// The SOCKS5 proxy to dial an address through, if any. Everything goes
+/// through `proxy` when one is configured. Otherwise Tor addresses and
+/// onion services, which can't be reached directly, go through the local
+/// Tor proxy, and everything else is dialed directly.
+pub fn dial_proxy(url: &Url, proxy: &Option<Url>) -> Result<Option<Url>> {
+    if proxy.is_some() {
+        return Ok(proxy.clone())
+    }
+
+    if url.scheme().starts_with("tor") || is_onion(url) {
+        return Ok(Some(TorTransport::get_dialer_env()?))
+    }
+
+    Ok(None)
+}
+
 /// Used as wrapper for stream used by  Transport trait
 pub trait TransportStream: AsyncWrite + AsyncRead + Unpin + Send + Sync {}
 
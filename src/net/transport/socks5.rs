@@ -0,0 +1,109 @@
+use async_std::net::{TcpListener, TcpStream};
+use std::{io, pin::Pin, time::Duration};
+
+use fast_socks5::client::{Config, Socks5Stream};
+use futures::prelude::*;
+use futures_rustls::{TlsAcceptor, TlsStream};
+use log::debug;
+use url::Url;
+
+use super::{TlsUpgrade, Transport};
+use crate::{Error, Result};
+
+/// Dials TCP connections through a SOCKS5 proxy, such as the one Tor
+/// runs, so no connection leaves the host but the one to the proxy.
+///
+/// Host names are resolved by the proxy, which is how `.onion` addresses
+/// are reached, and keeps DNS lookups from leaking too. `tcp`, `tls` and
+/// `tor` addresses can be dialed, and upgraded with TLS as usual.
+///
+/// Nothing can be accepted through a proxy, so listening isn't supported.
+#[derive(Clone)]
+pub struct Socks5Transport {
+    /// Url of the proxy, e.g. `socks5://127.0.0.1:9050`, with credentials
+    /// if it asks for them
+    proxy: Url,
+}
+
+impl Transport for Socks5Transport {
+    type Acceptor = TcpListener;
+    type Connector = Socks5Stream<TcpStream>;
+
+    type Listener = Pin<Box<dyn Future<Output = Result<Self::Acceptor>> + Send>>;
+    type Dial = Pin<Box<dyn Future<Output = Result<Self::Connector>> + Send>>;
+
+    type TlsListener = Pin<Box<dyn Future<Output = Result<(TlsAcceptor, Self::Acceptor)>> + Send>>;
+    type TlsDialer = Pin<Box<dyn Future<Output = Result<TlsStream<Self::Connector>>> + Send>>;
+
+    fn listen_on(self, url: Url) -> Result<Self::Listener> {
+        Err(Error::UnsupportedTransport(format!("{} through a SOCKS5 proxy", url.scheme())))
+    }
+
+    fn upgrade_listener(self, acceptor: Self::Acceptor) -> Result<Self::TlsListener> {
+        let tlsupgrade = TlsUpgrade::new();
+        Ok(Box::pin(tlsupgrade.upgrade_listener_tls(acceptor)))
+    }
+
+    fn dial(self, url: Url, timeout: Option<Duration>) -> Result<Self::Dial> {
+        match url.scheme() {
+            "tcp" | "tcp+tls" | "tls" | "tor" | "tor+tls" => {}
+            x => return Err(Error::UnsupportedTransport(x.to_string())),
+        }
+
+        debug!(target: "net", "socks5 transport: dialing {} through {}", url, self.proxy);
+        Ok(Box::pin(self.do_dial(url, timeout)))
+    }
+
+    fn upgrade_dialer(self, connector: Self::Connector) -> Result<Self::TlsDialer> {
+        let tlsupgrade = TlsUpgrade::new();
+        Ok(Box::pin(tlsupgrade.upgrade_dialer_tls(connector)))
+    }
+}
+
+impl Socks5Transport {
+    pub fn new(proxy: Url) -> Result<Self> {
+        if proxy.scheme() != "socks5" {
+            return Err(Error::UnsupportedTransport(proxy.scheme().to_string()))
+        }
+
+        Ok(Self { proxy })
+    }
+
+    async fn do_dial(self, url: Url, timeout: Option<Duration>) -> Result<Socks5Stream<TcpStream>> {
+        let proxy_addr = self.proxy.socket_addrs(|| Some(1080))?[0].to_string();
+        let host = match url.host_str() {
+            Some(v) => v.to_string(),
+            None => return Err(Error::UnsupportedTransport(url.to_string())),
+        };
+        let port = match url.port() {
+            Some(v) => v,
+            None => return Err(Error::UnsupportedTransport(url.to_string())),
+        };
+
+        let config = Config::default();
+        let dial = async {
+            let stream = match self.proxy.password() {
+                Some(password) => {
+                    Socks5Stream::connect_with_password(
+                        proxy_addr,
+                        host,
+                        port,
+                        self.proxy.username().to_string(),
+                        password.to_string(),
+                        config,
+                    )
+                    .await?
+                }
+                None => Socks5Stream::connect(proxy_addr, host, port, config).await?,
+            };
+            Ok::<_, Error>(stream)
+        };
+
+        match timeout {
+            Some(timeout) => async_std::future::timeout(timeout, dial)
+                .await
+                .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?,
+            None => dial.await,
+        }
+    }
+}
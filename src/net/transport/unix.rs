@@ -23,7 +23,13 @@ impl TransportListener for UnixListener {
                 )))
             }
         };
-        let url = Url::parse(&unix_socket_addr_to_string(peer_addr))?;
+        // Clients usually connect from unnamed sockets, so fall back to
+        // our own path to have something to show for the peer.
+        let mut path = unix_socket_addr_to_string(peer_addr);
+        if path.is_empty() {
+            path = unix_socket_addr_to_string(self.local_addr()?);
+        }
+        let url = Url::parse(&format!("unix://{}", path))?;
         Ok((Box::new(stream), url))
     }
 }
@@ -47,7 +53,13 @@ impl UnixTransport {
             return Err(Error::UnsupportedOS)
         }
 
-        let listener = UnixListener::bind(url.as_str()).await?;
+        // Remove the socket left behind by a previous run
+        let path = std::path::Path::new(url.path());
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path).await?;
         debug!("{} transport: listening on {}", url.scheme(), url);
         Ok(listener)
     }
@@ -62,7 +74,7 @@ impl UnixTransport {
             return Err(Error::UnsupportedOS)
         }
 
-        let stream = UnixStream::connect(url.as_str()).await?;
+        let stream = UnixStream::connect(url.path()).await?;
         debug!("{} transport: dialing to {}", url.scheme(), url);
         Ok(stream)
     }
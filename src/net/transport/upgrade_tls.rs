@@ -1,4 +1,4 @@
-use std::time::SystemTime;
+use std::{fs::File, io::BufReader, path::Path, time::SystemTime};
 
 use async_std::{net::TcpListener, sync::Arc};
 use futures::prelude::*;
@@ -13,9 +13,9 @@ use futures_rustls::{
     },
     TlsAcceptor, TlsConnector, TlsStream,
 };
-use rustls_pemfile::pkcs8_private_keys;
+use rustls_pemfile::{certs, pkcs8_private_keys};
 
-use crate::Result;
+use crate::{Error, Result};
 
 const CIPHER_SUITE: &str = "TLS13_CHACHA20_POLY1305_SHA256";
 
@@ -114,6 +114,35 @@ impl TlsUpgrade {
         Self { server_config, client_config }
     }
 
+    /// Like [`TlsUpgrade::new`], but listeners present the certificate chain
+    /// and PKCS#8 private key from the given PEM files.
+    pub fn with_server_cert(cert_path: &Path, key_path: &Path) -> Result<Self> {
+        let certificates = certs(&mut BufReader::new(File::open(cert_path)?))?;
+        if certificates.is_empty() {
+            return Err(Error::ParseFailed("No certificates found in TLS certificate file"))
+        }
+        let certificates = certificates.into_iter().map(rustls::Certificate).collect();
+
+        let mut secret_keys = pkcs8_private_keys(&mut BufReader::new(File::open(key_path)?))?;
+        if secret_keys.is_empty() {
+            return Err(Error::ParseFailed("No PKCS#8 private key found in TLS key file"))
+        }
+        let secret_key = rustls::PrivateKey(secret_keys.remove(0));
+
+        let client_cert_verifier = Arc::new(ClientCertificateVerifier {});
+        let server_config = Arc::new(
+            ServerConfig::builder()
+                .with_cipher_suites(&[cipher_suite()])
+                .with_kx_groups(&[&X25519])
+                .with_protocol_versions(&[&TLS13])
+                .unwrap()
+                .with_client_cert_verifier(client_cert_verifier)
+                .with_single_cert(certificates, secret_key)?,
+        );
+
+        Ok(Self { server_config, client_config: Self::new().client_config })
+    }
+
     pub async fn upgrade_listener_tls(
         self,
         listener: TcpListener,
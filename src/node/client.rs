@@ -1,52 +1,111 @@
-use async_std::sync::{Arc, Mutex};
+use std::collections::BTreeMap;
+
+use async_std::sync::{Arc, Mutex, RwLock};
 use incrementalmerkletree::{bridgetree::BridgeTree, Tree};
 use lazy_init::Lazy;
-use log::{debug, error, info};
+use log::{debug, info};
+use pasta_curves::pallas;
+use rand::rngs::OsRng;
 
-use super::state::{state_transition, State};
+use super::{
+    contract::money::check_transfer, token_registry::TokenRegistryPtr, MemoryState, State,
+};
 use crate::{
     crypto::{
         address::Address,
-        coin::Coin,
         constants::MERKLE_DEPTH,
         keypair::{Keypair, PublicKey},
+        membership_proof::{create_membership_proof, MembershipProof},
         merkle_node::MerkleNode,
-        proof::ProvingKey,
-        token_list::DrkTokenList,
+        mnemonic::Mnemonic,
+        note::Memo,
+        nullifier::Nullifier,
+        proof::{ParallelProver, ProvingKey},
+        token_id::{generate_unique_id, MetadataCommitment},
         types::DrkTokenId,
         OwnCoin,
     },
     tx::{
         builder::{
-            TransactionBuilder, TransactionBuilderClearInputInfo, TransactionBuilderInputInfo,
-            TransactionBuilderOutputInfo,
+            TransactionBuilder, TransactionBuilderClearInputInfo, TransactionBuilderOutputInfo,
+            TransactionBuilderPayment,
         },
+        coin_selection::CoinSelection,
         Transaction,
     },
-    util::serial::Encodable,
-    wallet::walletdb::{Balances, WalletPtr},
-    zk::circuit::{BurnContract, MintContract},
-    ClientFailed, ClientResult, Result,
+    util::{serial::serialize, Timestamp},
+    wallet::walletdb::{Balances, TxDirection, TxHistoryEntry, WalletPtr},
+    zk::circuit::{BurnContract, MembershipContract, MintContract},
+    ClientFailed, ClientResult, Error, Result,
 };
 
+/// Name the wallet passed to [`Client::new`] is registered under
+pub const DEFAULT_WALLET: &str = "default";
+
+/// Seconds the coins spent by a transaction we made stay pending, if it
+/// doesn't reach the chain, before they can be spent again
+pub const PENDING_TIMEOUT: i64 = 60 * 60;
+
 /// The Client structure, used for transaction operations.
 /// This includes, receiving, broadcasting, and building.
 pub struct Client {
     pub main_keypair: Mutex<Keypair>,
-    pub wallet: WalletPtr,
-    pub tokenlist: Arc<DrkTokenList>,
+    /// All open wallets, by name
+    wallets: RwLock<BTreeMap<String, WalletPtr>>,
+    /// Name of the wallet used for keys, balances and transactions
+    active_wallet: RwLock<String>,
+    /// Token lists, which may be refreshed while the node runs
+    pub tokenlist: TokenRegistryPtr,
     mint_pk: Lazy<ProvingKey>,
     burn_pk: Lazy<ProvingKey>,
+    membership_pk: Lazy<ProvingKey>,
+    /// Threads creating the proofs of a transaction concurrently
+    prover_threads: usize,
+    /// How the coins spent by a transaction are picked
+    coin_selection: CoinSelection,
+    prover: Lazy<ParallelProver>,
 }
 
 impl Client {
-    pub async fn new(wallet: WalletPtr, tokenlist: Arc<DrkTokenList>) -> Result<Self> {
-        // Initialize or load the wallet
+    pub async fn new(wallet: WalletPtr, tokenlist: TokenRegistryPtr) -> Result<Self> {
+        let main_keypair = Self::load_wallet(&wallet).await?;
+        info!(target: "client", "Main keypair: {}", Address::from(main_keypair.public));
+
+        let mut wallets = BTreeMap::new();
+        wallets.insert(DEFAULT_WALLET.to_string(), wallet);
+
+        Ok(Self {
+            main_keypair: Mutex::new(main_keypair),
+            wallets: RwLock::new(wallets),
+            active_wallet: RwLock::new(DEFAULT_WALLET.to_string()),
+            tokenlist,
+            mint_pk: Lazy::new(),
+            burn_pk: Lazy::new(),
+            membership_pk: Lazy::new(),
+            prover_threads: 0,
+            coin_selection: CoinSelection::default(),
+            prover: Lazy::new(),
+        })
+    }
+
+    /// Set the number of threads creating the proofs of a transaction
+    /// concurrently, 0 meaning one per logical CPU. Only has an effect
+    /// before the first transaction is built.
+    pub fn set_prover_threads(&mut self, threads: usize) {
+        self.prover_threads = threads;
+    }
+
+    /// Set how the coins spent by a transaction are picked
+    pub fn set_coin_selection(&mut self, coin_selection: CoinSelection) {
+        self.coin_selection = coin_selection;
+    }
+
+    /// Initialize or load a wallet, and return its default keypair.
+    async fn load_wallet(wallet: &WalletPtr) -> Result<Keypair> {
         wallet.init_db().await?;
 
         // Get default keypair or create one
-        let main_keypair = wallet.get_default_keypair_or_create_one().await?;
-        info!(target: "client", "Main keypair: {}", Address::from(main_keypair.public));
+        let keypair = wallet.get_default_keypair_or_create_one().await?;
 
         // Generate merkle tree if we don't have one.
         // TODO: See what to do about this
@@ -54,155 +113,291 @@ impl Client {
             wallet.tree_gen().await?;
         }
 
-        Ok(Self {
-            main_keypair: Mutex::new(main_keypair),
-            wallet,
-            tokenlist,
-            mint_pk: Lazy::new(),
-            burn_pk: Lazy::new(),
-        })
+        Ok(keypair)
+    }
+
+    /// The wallet currently in use.
+    pub async fn wallet(&self) -> WalletPtr {
+        let active = self.active_wallet.read().await;
+        self.wallets.read().await[&*active].clone()
+    }
+
+    /// Every open wallet, so state updates can be applied to all of them.
+    pub async fn wallets(&self) -> Vec<WalletPtr> {
+        self.wallets.read().await.values().cloned().collect()
+    }
+
+    /// Open an additional wallet under `name`.
+    pub async fn add_wallet(&self, name: &str, wallet: WalletPtr) -> Result<()> {
+        if self.wallets.read().await.contains_key(name) {
+            return Err(Error::WalletExists(name.to_string()))
+        }
+
+        Self::load_wallet(&wallet).await?;
+        self.wallets.write().await.insert(name.to_string(), wallet);
+        info!(target: "client", "Opened wallet {}", name);
+        Ok(())
+    }
+
+    /// Names of the open wallets, and whether each is the active one.
+    pub async fn list_wallets(&self) -> Vec<(String, bool)> {
+        let active = self.active_wallet.read().await;
+        self.wallets.read().await.keys().map(|k| (k.clone(), *k == *active)).collect()
+    }
+
+    /// Use the wallet `name` for keys, balances and transactions.
+    pub async fn switch_wallet(&self, name: &str) -> Result<()> {
+        let wallet = match self.wallets.read().await.get(name) {
+            Some(v) => v.clone(),
+            None => return Err(Error::WalletNotFound(name.to_string())),
+        };
+
+        let keypair = wallet.get_default_keypair_or_create_one().await?;
+        *self.active_wallet.write().await = name.to_string();
+        *self.main_keypair.lock().await = keypair;
+        info!(target: "client", "Switched to wallet {}", name);
+        Ok(())
     }
 
     // TODO: Better function name
+    #[allow(clippy::too_many_arguments)]
     async fn build_slab_from_tx(
         &self,
-        pubkey: PublicKey,
-        value: u64,
+        wallet: &WalletPtr,
+        payments: Vec<TransactionBuilderPayment>,
         token_id: DrkTokenId,
         clear_input: bool,
+        issue: Option<MetadataCommitment>,
         state: Arc<Mutex<State>>,
-    ) -> ClientResult<(Transaction, Vec<Coin>)> {
+    ) -> ClientResult<Transaction> {
         debug!("build_slab_from_tx(): Begin building slab from tx");
-        let mut clear_inputs = vec![];
-        let mut inputs = vec![];
-        let mut outputs = vec![];
-        let mut coins = vec![];
-
-        if clear_input {
+        let builder = if clear_input {
             debug!("build_slab_from_tx(): Building clear input");
             let signature_secret = self.main_keypair.lock().await.secret;
-            let input = TransactionBuilderClearInputInfo { value, token_id, signature_secret };
-            clear_inputs.push(input);
+            let input = TransactionBuilderClearInputInfo {
+                value: payments.iter().map(|x| x.value).sum(),
+                token_id,
+                signature_secret,
+                metadata: issue,
+            };
+            let outputs = payments
+                .into_iter()
+                .map(|x| TransactionBuilderOutputInfo {
+                    value: x.value,
+                    token_id,
+                    public: x.public,
+                    memo: x.memo,
+                    metadata: issue,
+                })
+                .collect();
+            TransactionBuilder { clear_inputs: vec![input], inputs: vec![], outputs }
         } else {
             debug!("build_slab_from_tx(): Building tx inputs");
-            let mut inputs_value = 0;
+            Self::release_stale_pending(wallet).await?;
+            let own_coins = wallet.get_own_coins().await?;
+            let change = self.main_keypair.lock().await.public;
             let state_m = state.lock().await;
-            let own_coins = self.wallet.get_own_coins().await?;
-
-            for own_coin in own_coins.iter() {
-                if inputs_value >= value {
-                    debug!("build_slab_from_tx(): inputs_value >= value");
-                    break
-                }
-
-                let leaf_position = own_coin.leaf_position;
-                let root = state_m.tree.root(0).unwrap();
-                let merkle_path = state_m.tree.authentication_path(leaf_position, &root).unwrap();
-                inputs_value += own_coin.note.value;
-
-                let input = TransactionBuilderInputInfo {
-                    leaf_position,
-                    merkle_path,
-                    secret: own_coin.secret,
-                    note: own_coin.note,
-                };
-
-                inputs.push(input);
-                coins.push(own_coin.coin);
-            }
-            // Release state lock
-            drop(state_m);
-
-            if inputs_value < value {
-                error!("build_slab_from_tx(): Not enough value to build tx inputs");
-                return Err(ClientFailed::NotEnoughValue(inputs_value))
-            }
-
-            if inputs_value > value {
-                let return_value = inputs_value - value;
-                outputs.push(TransactionBuilderOutputInfo {
-                    value: return_value,
-                    token_id,
-                    public: self.main_keypair.lock().await.public,
-                });
-            }
-
-            debug!("build_slab_from_tx(): Finished building inputs");
-        }
-
-        outputs.push(TransactionBuilderOutputInfo { value, token_id, public: pubkey });
-        let builder = TransactionBuilder { clear_inputs, inputs, outputs };
-        let mut tx_data = vec![];
+            TransactionBuilder::transfer(
+                token_id,
+                payments,
+                &own_coins,
+                self.coin_selection,
+                change,
+                &state_m.tree,
+            )?
+        };
 
         let mint_pk = self.mint_pk.get_or_create(Client::build_mint_pk);
         let burn_pk = self.burn_pk.get_or_create(Client::build_burn_pk);
-        let tx = builder.build(mint_pk, burn_pk)?;
-        tx.encode(&mut tx_data)?;
+        let prover = self.prover.get_or_create(|| ParallelProver::new(self.prover_threads));
+        let tx = builder.build_parallel(mint_pk, burn_pk, prover)?;
 
         // Check if state transition is valid before broadcasting
         debug!("build_slab_from_tx(): Checking if state transition is valid");
         let state = &*state.lock().await;
         debug!("build_slab_from_tx(): Got state lock");
-        state_transition(state, tx.clone())?;
+        check_transfer(MemoryState::new(state.clone()), &tx)?;
         debug!("build_slab_from_tx(): Successful state transition");
 
-        Ok((tx, coins))
+        Ok(tx)
     }
 
     /// Build a transaction given the required parameters and state machine.
+    /// `memo` is only readable by the recipient.
     pub async fn build_transaction(
         &self,
         pubkey: PublicKey,
         amount: u64,
         token_id: DrkTokenId,
+        memo: Memo,
         clear_input: bool,
         state: Arc<Mutex<State>>,
     ) -> ClientResult<Transaction> {
-        // TODO: Token id debug
-        debug!("send(): Sending {}", amount);
+        let payments = vec![TransactionBuilderPayment { public: pubkey, value: amount, memo }];
+        self.build_multi_transaction(payments, token_id, clear_input, state).await
+    }
 
-        if amount == 0 {
+    /// Build a single transaction making all of `payments` in `token_id`.
+    /// A transaction only carries one token, so payments in other tokens
+    /// need transactions of their own.
+    pub async fn build_multi_transaction(
+        &self,
+        payments: Vec<TransactionBuilderPayment>,
+        token_id: DrkTokenId,
+        clear_input: bool,
+        state: Arc<Mutex<State>>,
+    ) -> ClientResult<Transaction> {
+        if payments.is_empty() || payments.iter().any(|x| x.value == 0) {
             return Err(ClientFailed::InvalidAmount(0))
         }
 
-        if !self.wallet.token_id_exists(token_id).await? && !clear_input {
+        let amount = match payments.iter().try_fold(0u64, |acc, x| acc.checked_add(x.value)) {
+            Some(v) => v,
+            None => return Err(ClientFailed::InvalidAmount(u64::MAX)),
+        };
+
+        // TODO: Token id debug
+        debug!("send(): Sending {} in {} payments", amount, payments.len());
+
+        // Keep using the same wallet even if it's switched meanwhile
+        let wallet = self.wallet().await;
+
+        if !wallet.token_id_exists(token_id).await? && !clear_input {
             return Err(ClientFailed::NotEnoughValue(amount))
         }
 
-        let (tx, coins) =
-            self.build_slab_from_tx(pubkey, amount, token_id, clear_input, state).await?;
-        for coin in coins.iter() {
-            // TODO: This should be more robust. In case our transaction is denied,
-            // we want to revert to be able to send again.
-            self.wallet.confirm_spend_coin(coin).await?;
+        let sent: Vec<(u64, Memo)> = payments.iter().map(|x| (x.value, x.memo)).collect();
+        let tx =
+            self.build_slab_from_tx(&wallet, payments, token_id, clear_input, None, state).await?;
+
+        // Pending until the nullifiers show up on chain, or the transaction
+        // is given up on with `release_pending`.
+        wallet.set_pending(&Self::input_nullifiers(&tx)).await?;
+
+        // One history entry per payment, so each keeps its memo
+        let tx_hash = blake3::hash(&serialize(&tx));
+        for (value, memo) in sent {
+            let entry = TxHistoryEntry {
+                timestamp: Timestamp::current_time(),
+                direction: TxDirection::Sent,
+                value,
+                token_id,
+                tx_hash,
+                memo,
+            };
+            wallet.put_tx_history(&entry).await?;
         }
 
         debug!("send(): Sent {}", amount);
         Ok(tx)
     }
 
+    /// Issue the unique asset with the given metadata to `pubkey`, with a
+    /// clear input signed by our main key, which has to be a cashier or
+    /// faucet key. The asset is then sent like any token, with an amount
+    /// of 1 and the token ID [`generate_unique_id`] gives.
+    pub async fn issue_unique_asset(
+        &self,
+        pubkey: PublicKey,
+        metadata: MetadataCommitment,
+        state: Arc<Mutex<State>>,
+    ) -> ClientResult<Transaction> {
+        let token_id = generate_unique_id(&metadata);
+        debug!("issue_unique_asset(): Issuing {:?}", token_id);
+
+        let wallet = self.wallet().await;
+        let payments =
+            vec![TransactionBuilderPayment { public: pubkey, value: 1, memo: Memo::default() }];
+        self.build_slab_from_tx(&wallet, payments, token_id, true, Some(metadata), state).await
+    }
+
+    /// Prove we own an unspent coin of `token_id`, without spending it or
+    /// revealing which one it is, to a service with the given `scope` that
+    /// asked us to prove it for `challenge`.
+    pub async fn prove_membership(
+        &self,
+        token_id: DrkTokenId,
+        scope: pallas::Base,
+        challenge: pallas::Base,
+        state: Arc<Mutex<State>>,
+    ) -> ClientResult<MembershipProof> {
+        debug!("prove_membership(): Begin");
+        let own_coins = self.wallet().await.get_own_coins().await?;
+        let own_coin = match own_coins.iter().find(|x| x.note.token_id == token_id) {
+            Some(v) => v,
+            None => return Err(ClientFailed::NotEnoughValue(0)),
+        };
+
+        let state_m = state.lock().await;
+        let root = match state_m.tree.root(0) {
+            Some(v) => v,
+            None => return Err(ClientFailed::InternalError("Merkle tree has no root".into())),
+        };
+        let merkle_path = match state_m.tree.authentication_path(own_coin.leaf_position, &root) {
+            Some(v) => v,
+            None => {
+                return Err(ClientFailed::InternalError(format!(
+                    "Merkle tree has no path for leaf position {:?}",
+                    own_coin.leaf_position
+                )))
+            }
+        };
+        drop(state_m);
+
+        let pk = self.membership_pk.get_or_create(Client::build_membership_pk);
+        let proof = create_membership_proof(pk, own_coin, merkle_path, scope, challenge, OsRng)?;
+
+        debug!("prove_membership(): Finished");
+        Ok(proof)
+    }
+
     pub async fn init_db(&self) -> Result<()> {
-        self.wallet.init_db().await
+        self.wallet().await.init_db().await
     }
 
     pub async fn get_own_coins(&self) -> Result<Vec<OwnCoin>> {
-        self.wallet.get_own_coins().await
+        self.wallet().await.get_own_coins().await
+    }
+
+    /// Make the coins spent by a transaction we built spendable again,
+    /// e.g. after it failed to broadcast.
+    pub async fn release_pending(&self, tx: &Transaction) -> Result<()> {
+        let nullifiers = Self::input_nullifiers(tx);
+        for wallet in self.wallets().await {
+            wallet.release_pending(&nullifiers).await?;
+        }
+        Ok(())
+    }
+
+    /// Make the coins of transactions that didn't reach the chain within
+    /// [`PENDING_TIMEOUT`] spendable again.
+    async fn release_stale_pending(wallet: &WalletPtr) -> Result<()> {
+        let since = Timestamp(Timestamp::current_time().0 - PENDING_TIMEOUT);
+        let released = wallet.release_stale_pending(since).await?;
+        if released > 0 {
+            info!(
+                target: "client",
+                "Released {} coins of transactions that didn't reach the chain",
+                released
+            );
+        }
+        Ok(())
     }
 
-    pub async fn confirm_spend_coin(&self, coin: &Coin) -> Result<()> {
-        self.wallet.confirm_spend_coin(coin).await
+    fn input_nullifiers(tx: &Transaction) -> Vec<Nullifier> {
+        tx.inputs.iter().map(|x| x.revealed.nullifier).collect()
     }
 
     pub async fn get_keypairs(&self) -> Result<Vec<Keypair>> {
-        self.wallet.get_keypairs().await
+        self.wallet().await.get_keypairs().await
     }
 
     pub async fn put_keypair(&self, keypair: &Keypair) -> Result<()> {
-        self.wallet.put_keypair(keypair).await
+        self.wallet().await.put_keypair(keypair).await
     }
 
     pub async fn set_default_keypair(&self, public: &PublicKey) -> Result<()> {
-        let kp = self.wallet.set_default_keypair(public).await?;
+        let kp = self.wallet().await.set_default_keypair(public).await?;
         let mut mk = self.main_keypair.lock().await;
         *mk = kp;
         drop(mk);
@@ -210,16 +405,42 @@ impl Client {
     }
 
     pub async fn keygen(&self) -> Result<Address> {
-        let kp = self.wallet.keygen().await?;
+        let kp = self.wallet().await.keygen().await?;
+        Ok(Address::from(kp.public))
+    }
+
+    /// Keep the seed of a mnemonic in the wallet, derive the first `count`
+    /// keys of its first account from it, and make the first one the main
+    /// keypair.
+    pub async fn restore_seed(
+        &self,
+        mnemonic: &Mnemonic,
+        passphrase: &str,
+        count: u32,
+    ) -> Result<Vec<Address>> {
+        let wallet = self.wallet().await;
+        let keypairs = wallet.restore_seed(&mnemonic.to_seed(passphrase), count).await?;
+        *self.main_keypair.lock().await = keypairs[0];
+        Ok(keypairs.iter().map(|x| Address::from(x.public)).collect())
+    }
+
+    pub async fn derive_keypair(&self, account: u32, index: u32) -> Result<Address> {
+        let kp = self.wallet().await.derive_keypair(account, index).await?;
         Ok(Address::from(kp.public))
     }
 
     pub async fn get_balances(&self) -> Result<Balances> {
-        self.wallet.get_balances().await
+        let wallet = self.wallet().await;
+        Self::release_stale_pending(&wallet).await?;
+        wallet.get_balances().await
+    }
+
+    pub async fn get_tx_history(&self, offset: u32, limit: u32) -> Result<Vec<TxHistoryEntry>> {
+        self.wallet().await.get_tx_history(offset, limit).await
     }
 
     pub async fn get_tree(&self) -> Result<BridgeTree<MerkleNode, MERKLE_DEPTH>> {
-        self.wallet.get_tree().await
+        self.wallet().await.get_tree().await
     }
 
     fn build_mint_pk() -> ProvingKey {
@@ -231,4 +452,9 @@ impl Client {
         debug!("Building proving key for BurnContract");
         ProvingKey::build(11, &BurnContract::default())
     }
+
+    fn build_membership_pk() -> ProvingKey {
+        debug!("Building proving key for MembershipContract");
+        ProvingKey::build(11, &MembershipContract::default())
+    }
 }
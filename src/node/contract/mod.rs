@@ -0,0 +1,108 @@
+//! Contracts transactions call into. A contract checks the calls to its
+//! functions against the state it keeps in the [`StateRegistry`], and
+//! returns an update which is applied once the call passed. This is the
+//! state_transition/apply pattern of the DAO prototype in bin/daod/demo,
+//! and coin transfers go through it as calls to the money contract.
+use std::{any::Any, collections::HashMap};
+
+use crate::{Result, VerifyFailed, VerifyResult};
+
+pub mod money;
+use money::MONEY_CONTRACT_ID;
+
+/// Data of a call to a contract function, which only its contract
+/// knows how to interpret.
+pub trait CallDataBase {
+    /// The call data, for its contract to downcast
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Changes a call that passed makes to the state of its contract
+pub trait UpdateBase {
+    /// Apply the changes to the state registered for the contract
+    fn apply(self: Box<Self>, states: &mut StateRegistry) -> Result<()>;
+    /// The update, for callers that need what changed
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// A call to one function of a contract
+pub struct FuncCall {
+    pub contract_id: String,
+    pub func_id: String,
+    pub call_data: Box<dyn CallDataBase>,
+}
+
+/// States of the contracts, by contract ID
+#[derive(Default)]
+pub struct StateRegistry {
+    states: HashMap<String, Box<dyn Any>>,
+}
+
+impl StateRegistry {
+    /// Register the state calls to `contract_id` are checked against,
+    /// replacing the one registered before.
+    pub fn register<S: Any>(&mut self, contract_id: &str, state: S) {
+        self.states.insert(contract_id.to_string(), Box::new(state));
+    }
+
+    /// State of `contract_id`, if one of type `S` is registered
+    pub fn lookup<S: Any>(&self, contract_id: &str) -> Option<&S> {
+        self.states.get(contract_id)?.downcast_ref()
+    }
+
+    pub fn lookup_mut<S: Any>(&mut self, contract_id: &str) -> Option<&mut S> {
+        self.states.get_mut(contract_id)?.downcast_mut()
+    }
+}
+
+/// Check a call against the state of its contract, returning the update
+/// to apply if it passed.
+pub fn state_transition(
+    states: &StateRegistry,
+    call: &FuncCall,
+) -> VerifyResult<Box<dyn UpdateBase>> {
+    match call.contract_id.as_str() {
+        MONEY_CONTRACT_ID => money::call_state_transition(states, call),
+        x => Err(VerifyFailed::UnknownContract(x.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Empty;
+
+    impl CallDataBase for Empty {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn state_registry() {
+        let mut states = StateRegistry::default();
+        states.register("dao", 42u64);
+        assert_eq!(states.lookup::<u64>("dao"), Some(&42));
+        assert_eq!(states.lookup::<u32>("dao"), None);
+        assert_eq!(states.lookup::<u64>(MONEY_CONTRACT_ID), None);
+
+        *states.lookup_mut::<u64>("dao").unwrap() += 1;
+        assert_eq!(states.lookup::<u64>("dao"), Some(&43));
+
+        let call = |contract_id: &str, func_id: &str| FuncCall {
+            contract_id: contract_id.to_string(),
+            func_id: func_id.to_string(),
+            call_data: Box::new(Empty),
+        };
+        assert!(matches!(
+            state_transition(&states, &call("dao", "vote")),
+            Err(VerifyFailed::UnknownContract(_))
+        ));
+        // Money calls need a transaction, and a state to check it against
+        assert!(matches!(
+            state_transition(&states, &call(MONEY_CONTRACT_ID, "transfer")),
+            Err(VerifyFailed::UnknownContract(_))
+        ));
+    }
+}
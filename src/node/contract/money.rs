@@ -0,0 +1,232 @@
+//! The money contract, transferring value between coins. Its only
+//! function takes a [`Transaction`] as call data: clear inputs issue new
+//! value, inputs burn coins, and outputs mint new ones.
+use std::any::Any;
+
+use async_std::sync::Arc;
+use log::{debug, error};
+
+use super::{CallDataBase, FuncCall, StateRegistry, UpdateBase};
+use crate::{
+    crypto::{
+        nullifier::Nullifier,
+        proof::VerifyingKey,
+        token_id::{generate_unique_id, issued_token_nullifier, unique_asset_nullifier},
+    },
+    node::{
+        state::{ProgramState, StateUpdate},
+        vk_registry::{BURN_CIRCUIT_ID, MINT_CIRCUIT_ID},
+        MemoryState,
+    },
+    tx::{Transaction, VerifyChecks},
+    util::serial::serialize,
+    Result, VerifyFailed, VerifyResult,
+};
+
+/// ID of the money contract
+pub const MONEY_CONTRACT_ID: &str = "money";
+/// Function transferring value with a [`Transaction`]
+pub const TRANSFER_FUNC_ID: &str = "transfer";
+
+impl CallDataBase for Transaction {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl UpdateBase for StateUpdate {
+    fn apply(self: Box<Self>, states: &mut StateRegistry) -> Result<()> {
+        match states.lookup_mut::<MemoryState>(MONEY_CONTRACT_ID) {
+            Some(state) => state.apply(*self),
+            None => Err(no_state().into()),
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A call to the money contract transferring value with `tx`
+pub fn transfer_call(tx: Transaction) -> FuncCall {
+    FuncCall {
+        contract_id: MONEY_CONTRACT_ID.to_string(),
+        func_id: TRANSFER_FUNC_ID.to_string(),
+        call_data: Box::new(tx),
+    }
+}
+
+/// Check `tx` as a call to the money contract against `state`, the way
+/// validators check the transactions of blocks.
+pub fn check_transfer(state: MemoryState, tx: &Transaction) -> VerifyResult<()> {
+    let mut states = StateRegistry::default();
+    states.register(MONEY_CONTRACT_ID, state);
+    super::state_transition(&states, &transfer_call(tx.clone()))?;
+    Ok(())
+}
+
+/// [`state_transition`] of a call to the money contract, checked against
+/// the [`MemoryState`] registered for it.
+pub(super) fn call_state_transition(
+    states: &StateRegistry,
+    call: &FuncCall,
+) -> VerifyResult<Box<dyn UpdateBase>> {
+    let tx = match call.call_data.as_any().downcast_ref::<Transaction>() {
+        Some(v) if call.func_id == TRANSFER_FUNC_ID => v,
+        _ => return Err(VerifyFailed::UnknownContract(format!("money::{}", call.func_id))),
+    };
+
+    let state = states.lookup::<MemoryState>(MONEY_CONTRACT_ID).ok_or_else(no_state)?;
+    Ok(Box::new(state_transition(state, tx.clone())?))
+}
+
+fn no_state() -> VerifyFailed {
+    VerifyFailed::InternalError("No state registered for the money contract".to_string())
+}
+
+/// Check the clear input `i` of a transaction doesn't issue more of a
+/// unique asset, and if it issues one, that it issues it alone with a
+/// supply of 1, with the ID of no token issued before. Returns the
+/// nullifier marking the asset, or a token issued for the first time, as
+/// issued. A token issued before the unique asset with its ID would
+/// otherwise add to the supply of the asset.
+///
+/// All clear inputs of a transaction have the same token ID, which the
+/// token commitments check.
+fn check_unique_asset<S: ProgramState>(
+    state: &S,
+    tx: &Transaction,
+    i: usize,
+    nullifiers: &[Nullifier],
+) -> VerifyResult<Option<Nullifier>> {
+    let input = &tx.clear_inputs[i];
+    let exists = |n: &Nullifier| state.nullifier_exists(n) || nullifiers.contains(n);
+
+    let issued = unique_asset_nullifier(input.token_id);
+    if exists(&issued) {
+        return Err(VerifyFailed::UniqueAssetIssued(i))
+    }
+
+    let token_issued = issued_token_nullifier(input.token_id);
+    match &input.metadata {
+        Some(metadata) => {
+            if input.value != 1 ||
+                tx.clear_inputs.len() != 1 ||
+                generate_unique_id(metadata) != input.token_id ||
+                exists(&token_issued)
+            {
+                return Err(VerifyFailed::InvalidUniqueAsset(i))
+            }
+            Ok(Some(issued))
+        }
+        None if exists(&token_issued) => Ok(None),
+        None => Ok(Some(token_issued)),
+    }
+}
+
+/// State transition function
+pub fn state_transition<S: ProgramState>(state: &S, tx: Transaction) -> VerifyResult<StateUpdate> {
+    let nullifiers = check_transition(state, &tx, &mut VerifyChecks::fail_fast())?;
+    debug!(target: "state_transition", "Verified successfully");
+
+    let tx_hash = blake3::hash(&serialize(&tx));
+
+    // Newly created coins for this transaction
+    let mut coins = Vec::with_capacity(tx.outputs.len());
+    let mut enc_notes = Vec::with_capacity(tx.outputs.len());
+    for output in tx.outputs {
+        // Gather all the coins
+        coins.push(output.revealed.coin);
+        enc_notes.push(output.enc_note);
+    }
+
+    Ok(StateUpdate { nullifiers, coins, enc_notes, tx_hash })
+}
+
+/// Diagnostic variant of [`state_transition`]. Instead of returning the
+/// first error, every check is evaluated and all failures are returned,
+/// so it's possible to tell why a transaction would be rejected.
+/// An empty vector means the transaction is valid against this state.
+pub fn state_transition_report<S: ProgramState>(state: &S, tx: &Transaction) -> Vec<VerifyFailed> {
+    let mut checks = VerifyChecks::report();
+    // Only failing fast returns errors
+    let _ = check_transition(state, tx, &mut checks);
+    checks.into_failed()
+}
+
+/// The checks of [`state_transition`] and [`state_transition_report`].
+/// Returns the nullifiers the transaction adds to the state.
+fn check_transition<S: ProgramState>(
+    state: &S,
+    tx: &Transaction,
+    checks: &mut VerifyChecks,
+) -> VerifyResult<Vec<Nullifier>> {
+    // Nullifiers in the transaction
+    let mut nullifiers = Vec::with_capacity(tx.inputs.len());
+
+    // Check the public keys in the clear inputs to see if they're coming
+    // from a valid cashier or faucet.
+    debug!(target: "state_transition", "Iterate clear_inputs");
+    for (i, input) in tx.clear_inputs.iter().enumerate() {
+        let pk = &input.signature_public;
+        // TODO: this depends on the token ID
+        if !state.is_valid_cashier_public_key(pk) && !state.is_valid_faucet_public_key(pk) {
+            error!(target: "state_transition", "Invalid pubkey for clear input: {:?}", pk);
+            checks.fail(VerifyFailed::InvalidCashierOrFaucetKey(i))?;
+        }
+
+        match check_unique_asset(state, tx, i, &nullifiers) {
+            Ok(Some(issued)) => nullifiers.push(issued),
+            Ok(None) => {}
+            Err(e) => checks.fail(e)?,
+        }
+    }
+
+    debug!(target: "state_transition", "Iterate inputs");
+    for (i, input) in tx.inputs.iter().enumerate() {
+        let merkle = &input.revealed.merkle_root;
+
+        // The Merkle root is used to know whether this is a coin that
+        // existed in a previous state.
+        if !state.is_valid_merkle(merkle) {
+            error!(target: "state_transition", "Invalid Merkle root (input {})", i);
+            debug!(target: "state_transition", "root: {:?}", merkle);
+            checks.fail(VerifyFailed::InvalidMerkle(i))?;
+        }
+
+        // The nullifiers should not already exist, in the state or
+        // earlier in this transaction. It is the double-spend protection.
+        let nullifier = &input.revealed.nullifier;
+        if state.nullifier_exists(nullifier) || nullifiers.contains(nullifier) {
+            error!(target: "state_transition", "Duplicate nullifier found (input {})", i);
+            debug!(target: "state_transition", "nullifier: {:?}", nullifier);
+            checks.fail(VerifyFailed::NullifierExists(i))?;
+        }
+
+        nullifiers.push(*nullifier);
+    }
+
+    debug!(target: "state_transition", "Verifying zk proofs");
+    let (mint_vk, burn_vk) = match transaction_vks(state) {
+        Ok(v) => v,
+        Err(e) => {
+            checks.fail(e)?;
+            return Ok(nullifiers)
+        }
+    };
+    tx.run_checks(&mint_vk, &burn_vk, checks)?;
+
+    Ok(nullifiers)
+}
+
+/// Verifying keys of the circuits transactions are proven with
+fn transaction_vks<S: ProgramState>(
+    state: &S,
+) -> VerifyResult<(Arc<VerifyingKey>, Arc<VerifyingKey>)> {
+    let vk = |circuit_id: &str| match state.verifying_key(circuit_id) {
+        Some(v) => Ok(v),
+        None => Err(VerifyFailed::UnknownCircuit(circuit_id.to_string())),
+    };
+
+    Ok((vk(MINT_CIRCUIT_ID)?, vk(BURN_CIRCUIT_ID)?))
+}
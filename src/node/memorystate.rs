@@ -1,10 +1,14 @@
+use async_std::sync::Arc;
 use incrementalmerkletree::{bridgetree::BridgeTree, Tree};
 use log::debug;
 
 use super::state::{ProgramState, State, StateUpdate};
-use crate::crypto::{
-    constants::MERKLE_DEPTH, keypair::PublicKey, merkle_node::MerkleNode, nullifier::Nullifier,
-    proof::VerifyingKey,
+use crate::{
+    crypto::{
+        constants::MERKLE_DEPTH, keypair::PublicKey, merkle_node::MerkleNode, nullifier::Nullifier,
+        proof::VerifyingKey,
+    },
+    Error, Result,
 };
 
 /// In-memory state extension for state transition validations
@@ -37,12 +41,8 @@ impl ProgramState for MemoryState {
         self.canon.nullifier_exists(nullifier) || self.nullifiers.contains(nullifier)
     }
 
-    fn mint_vk(&self) -> &VerifyingKey {
-        self.canon.mint_vk()
-    }
-
-    fn burn_vk(&self) -> &VerifyingKey {
-        self.canon.burn_vk()
+    fn verifying_key(&self, circuit_id: &str) -> Option<Arc<VerifyingKey>> {
+        self.canon.verifying_key(circuit_id)
     }
 }
 
@@ -56,7 +56,7 @@ impl MemoryState {
         }
     }
 
-    pub fn apply(&mut self, update: StateUpdate) {
+    pub fn apply(&mut self, update: StateUpdate) -> Result<()> {
         debug!(target: "state_apply", "(in-memory) Extend nullifier set");
         let mut nfs = update.nullifiers.clone();
         self.nullifiers.append(&mut nfs);
@@ -65,9 +65,10 @@ impl MemoryState {
         for coin in update.coins {
             let node = MerkleNode(coin.0);
             self.tree.append(&node);
-            self.merkle_roots.push(self.tree.root(0).unwrap());
+            self.merkle_roots.push(self.tree.root(0).ok_or(Error::MerkleTreeNoRoot)?);
         }
 
         debug!(target: "state_apply", "(in-memory) Finished apply() successfully.");
+        Ok(())
     }
 }
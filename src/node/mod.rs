@@ -4,5 +4,13 @@ pub use client::Client;
 pub mod state;
 pub use state::State;
 
+pub mod contract;
+
 pub mod memorystate;
 pub use memorystate::MemoryState;
+
+pub mod token_registry;
+pub use token_registry::TokenRegistry;
+
+pub mod vk_registry;
+pub use vk_registry::VerifyingKeyRegistry;
@@ -1,10 +1,14 @@
 use async_std::sync::Arc;
 use incrementalmerkletree::{bridgetree::BridgeTree, Tree};
-use lazy_init::Lazy;
-use log::{debug, error};
+use log::{debug, warn};
+
+use super::vk_registry::VerifyingKeyRegistryPtr;
 
 use crate::{
-    blockchain::{nfstore::NullifierStore, rootstore::RootStore},
+    blockchain::{
+        insert_nullifiers_and_roots, nfstore::NullifierStore, remove_nullifiers_and_roots,
+        rootstore::RootStore,
+    },
     crypto::{
         coin::Coin,
         constants::MERKLE_DEPTH,
@@ -13,15 +17,42 @@ use crate::{
         note::{EncryptedNote, Note},
         nullifier::Nullifier,
         proof::VerifyingKey,
+        token_id::generate_unique_id,
         token_list::DrkTokenList,
+        types::DrkTokenId,
         OwnCoin,
     },
-    tx::Transaction,
-    wallet::walletdb::WalletPtr,
-    zk::circuit::{BurnContract, MintContract},
-    Result, VerifyFailed, VerifyResult,
+    system::SubscriberPtr,
+    util::{
+        serial::{SerialDecodable, SerialEncodable},
+        Timestamp,
+    },
+    wallet::walletdb::{TxDirection, TxHistoryEntry, WalletPtr},
+    Error, Result,
 };
 
+pub use super::contract::money::{state_transition, state_transition_report};
+
+/// Events published while applying state updates to the canonical state.
+#[derive(Clone, Debug)]
+pub enum StateEvent {
+    /// One of our keys received a coin
+    NewCoin {
+        public: PublicKey,
+        coin: Coin,
+        value: u64,
+        token_id: DrkTokenId,
+        tx_hash: blake3::Hash,
+    },
+    /// A transaction was applied to the canonical state in the given slot
+    TxConfirmed { tx_hash: blake3::Hash, slot: u64 },
+    /// A block was appended to the canonical chain
+    BlockApplied { slot: u64, hash: blake3::Hash },
+    /// The blocks after the given slot were removed from the canonical
+    /// chain, and their state changes undone
+    RolledBack { slot: u64 },
+}
+
 /// Trait implementing the state functions used by the state transition.
 pub trait ProgramState {
     /// Check if the public key is coming from a trusted cashier
@@ -32,10 +63,8 @@ pub trait ProgramState {
     fn is_valid_merkle(&self, merkle: &MerkleNode) -> bool;
     /// Check if the nullifier has been seen already
     fn nullifier_exists(&self, nullifier: &Nullifier) -> bool;
-    /// Mint proof verification key
-    fn mint_vk(&self) -> &VerifyingKey;
-    /// Burn proof verification key
-    fn burn_vk(&self) -> &VerifyingKey;
+    /// Verifying key of the circuit with the given ID, if it's known
+    fn verifying_key(&self, circuit_id: &str) -> Option<Arc<VerifyingKey>>;
 }
 
 /// A struct representing a state update.
@@ -48,70 +77,32 @@ pub struct StateUpdate {
     pub coins: Vec<Coin>,
     /// All encrypted notes in a transaction
     pub enc_notes: Vec<EncryptedNote>,
+    /// Hash of the transaction
+    pub tx_hash: blake3::Hash,
 }
 
-/// State transition function
-pub fn state_transition<S: ProgramState>(state: &S, tx: Transaction) -> VerifyResult<StateUpdate> {
-    // Check the public keys in the clear inputs to see if they're coming
-    // from a valid cashier or faucet.
-    debug!(target: "state_transition", "Iterate clear_inputs");
-    for (i, input) in tx.clear_inputs.iter().enumerate() {
-        let pk = &input.signature_public;
-        // TODO: this depends on the token ID
-        if !state.is_valid_cashier_public_key(pk) && !state.is_valid_faucet_public_key(pk) {
-            error!(target: "state_transition", "Invalid pubkey for clear input: {:?}", pk);
-            return Err(VerifyFailed::InvalidCashierOrFaucetKey(i))
-        }
-    }
-
-    // Nullifiers in the transaction
-    let mut nullifiers = Vec::with_capacity(tx.inputs.len());
-
-    debug!(target: "state_transition", "Iterate inputs");
-    for (i, input) in tx.inputs.iter().enumerate() {
-        let merkle = &input.revealed.merkle_root;
-
-        // The Merkle root is used to know whether this is a coin that
-        // existed in a previous state.
-        if !state.is_valid_merkle(merkle) {
-            error!(target: "state_transition", "Invalid Merkle root (input {})", i);
-            debug!(target: "state_transition", "root: {:?}", merkle);
-            return Err(VerifyFailed::InvalidMerkle(i))
-        }
-
-        // The nullifiers should not already exist.
-        // It is the double-spend protection.
-        let nullifier = &input.revealed.nullifier;
-        if state.nullifier_exists(nullifier) ||
-            (1..nullifiers.len()).any(|i| nullifiers[i..].contains(&nullifiers[i - 1]))
-        {
-            error!(target: "state_transition", "Duplicate nullifier found (input {})", i);
-            debug!(target: "state_transition", "nullifier: {:?}", nullifier);
-            return Err(VerifyFailed::NullifierExists(i))
-        }
-
-        nullifiers.push(input.revealed.nullifier);
-    }
-
-    debug!(target: "state_transition", "Verifying zk proofs");
-    match tx.verify(state.mint_vk(), state.burn_vk()) {
-        Ok(()) => debug!(target: "state_transition", "Verified successfully"),
-        Err(e) => {
-            error!(target: "state_transition", "Failed verifying zk proofs: {}", e);
-            return Err(VerifyFailed::ProofVerifyFailed(e.to_string()))
-        }
-    }
+/// The changes applying [`StateUpdate`]s made to the canonical state,
+/// kept per block so they can be undone when the chain reorganizes.
+#[derive(Clone, Debug, Default, SerialEncodable, SerialDecodable)]
+pub struct StateDiff {
+    /// Nullifiers added to the nullifier set
+    pub nullifiers: Vec<Nullifier>,
+    /// Merkle roots added to the root set
+    pub roots: Vec<MerkleNode>,
+    /// Coins added to our wallets
+    pub own_coins: Vec<Coin>,
+    /// Transactions our wallets received coins from
+    pub received: Vec<blake3::Hash>,
+}
 
-    // Newly created coins for this transaction
-    let mut coins = Vec::with_capacity(tx.outputs.len());
-    let mut enc_notes = Vec::with_capacity(tx.outputs.len());
-    for output in tx.outputs {
-        // Gather all the coins
-        coins.push(output.revealed.coin);
-        enc_notes.push(output.enc_note);
+impl StateDiff {
+    /// Append the changes of another diff to this one.
+    pub fn extend(&mut self, other: StateDiff) {
+        self.nullifiers.extend(other.nullifiers);
+        self.roots.extend(other.roots);
+        self.own_coins.extend(other.own_coins);
+        self.received.extend(other.received);
     }
-
-    Ok(StateUpdate { nullifiers, coins, enc_notes })
 }
 
 /// Struct holding the state which we can apply a [`StateUpdate`] onto.
@@ -128,26 +119,70 @@ pub struct State {
     pub cashier_pubkeys: Vec<PublicKey>,
     /// List of Faucet public keys
     pub faucet_pubkeys: Vec<PublicKey>,
-    /// Verifying key for the Mint ZK proof
-    pub mint_vk: Lazy<VerifyingKey>,
-    /// Verifying key for the Burn ZK proof
-    pub burn_vk: Lazy<VerifyingKey>,
+    /// Verifying keys of the circuits transactions are proven with
+    pub verifying_keys: VerifyingKeyRegistryPtr,
 }
 
 impl State {
-    /// Apply a [`StateUpdate`] to some state.
+    /// Apply the [`StateUpdate`]s of a block to some state. Coins are
+    /// scanned for every given wallet, using that wallet's secret keys, and
+    /// received coins are published to `events`. The nullifiers and Merkle
+    /// roots of all the updates are written in one atomic batch.
+    /// Returns the [`StateDiff`] needed to undo the updates.
     pub async fn apply(
+        &mut self,
+        updates: Vec<StateUpdate>,
+        wallets: Vec<(WalletPtr, Vec<SecretKey>)>,
+        events: Option<SubscriberPtr<StateEvent>>,
+        tokenlist: Arc<DrkTokenList>,
+    ) -> Result<StateDiff> {
+        let mut diff = StateDiff::default();
+        for update in updates {
+            diff.extend(
+                self.apply_update(update, &wallets, events.clone(), tokenlist.clone()).await?,
+            );
+        }
+
+        debug!(target: "state_apply", "Write nullifiers and Merkle roots");
+        insert_nullifiers_and_roots(
+            &self.nullifiers,
+            &diff.nullifiers,
+            &self.merkle_roots,
+            &diff.roots,
+        )?;
+
+        debug!(target: "state_apply", "Finished apply() successfully.");
+        Ok(diff)
+    }
+
+    /// Apply a single [`StateUpdate`] to the Merkle tree and the wallets.
+    /// Its nullifiers and Merkle roots are left for [`State::apply`] to
+    /// write along with the rest of the block.
+    async fn apply_update(
         &mut self,
         update: StateUpdate,
-        secret_keys: Vec<SecretKey>,
-        notify: Option<async_channel::Sender<(PublicKey, u64)>>,
-        wallet: WalletPtr,
+        wallets: &[(WalletPtr, Vec<SecretKey>)],
+        events: Option<SubscriberPtr<StateEvent>>,
         tokenlist: Arc<DrkTokenList>,
-    ) -> Result<()> {
+    ) -> Result<StateDiff> {
         debug!(target: "state_apply", "Extend nullifier set");
         debug!("Existing nullifiers: {:#?}", self.nullifiers.get_all()?);
         debug!("Update's nullifiers: {:#?}", update.nullifiers);
-        self.nullifiers.insert(&update.nullifiers)?;
+
+        // Coins we receive from our own transactions are change, and
+        // already accounted for by the send. Transactions replayed on a
+        // reindex are logged already as well.
+        let mut logged = Vec::with_capacity(wallets.len());
+        for (wallet, _) in wallets.iter() {
+            logged.push(wallet.tx_history_contains(&update.tx_hash).await?);
+        }
+
+        // New Merkle roots, written together with the nullifiers
+        let mut roots = Vec::with_capacity(update.coins.len());
+        // Coins we stored in any of the wallets
+        let mut own_coins = vec![];
+        // The transaction, if any of the wallets received a coin from it
+        let mut received = vec![];
 
         debug!(target: "state_apply", "Update Merkle tree and witnesses");
         for (coin, enc_note) in update.coins.into_iter().zip(update.enc_notes.iter()) {
@@ -159,37 +194,108 @@ impl State {
 
             // Keep track of all Merkle roots that have existed
             debug!("Existing merkle roots: {:#?}", self.merkle_roots.get_all()?);
-            debug!("New merkle root: {:#?}", self.tree.root(0).unwrap());
-            self.merkle_roots.insert(&[self.tree.root(0).unwrap()])?;
-
-            for secret in secret_keys.iter() {
-                if let Some(note) = State::try_decrypt_note(enc_note, *secret) {
-                    debug!(target: "state_apply", "Received a coin: amount {}", note.value);
-                    let leaf_position = self.tree.witness().unwrap();
-                    let nullifier = Nullifier::new(*secret, note.serial);
-                    let own_coin =
-                        OwnCoin { coin, note, secret: *secret, nullifier, leaf_position };
-
-                    // FIXME: BUG check values inside the note are correct
-                    // We need to hash them all and check them against the coin
-                    // for them to be accepted.
-                    // Don't trust - verify.
-
-                    wallet.put_own_coin(own_coin, tokenlist.clone()).await?;
-
-                    if let Some(ch) = notify.clone() {
-                        debug!(target: "state_apply", "Send a notification");
-                        let pubkey = PublicKey::from_secret(*secret);
-                        ch.send((pubkey, note.value)).await?;
+            let root = self.tree.root(0).ok_or(Error::MerkleTreeNoRoot)?;
+            debug!("New merkle root: {:#?}", root);
+            roots.push(root);
+
+            for ((wallet, secret_keys), logged) in wallets.iter().zip(logged.iter()) {
+                for secret in secret_keys.iter() {
+                    if let Some(mut note) = State::try_decrypt_note(enc_note, *secret) {
+                        debug!(target: "state_apply", "Received a coin: amount {}", note.value);
+
+                        // The sender is trusted with the metadata no more than
+                        // with the rest of the note, see below
+                        let token_id = note.token_id;
+                        if note.metadata.map_or(false, |x| generate_unique_id(&x) != token_id) {
+                            warn!(target: "state_apply", "Dropping metadata not matching token ID");
+                            note.metadata = None;
+                        }
+
+                        let leaf_position =
+                            self.tree.witness().ok_or(Error::MerkleTreeWitnessFailed)?;
+                        let nullifier = Nullifier::new(*secret, note.serial);
+                        let own_coin =
+                            OwnCoin { coin, note, secret: *secret, nullifier, leaf_position };
+
+                        // FIXME: BUG check values inside the note are correct
+                        // We need to hash them all and check them against the coin
+                        // for them to be accepted.
+                        // Don't trust - verify.
+
+                        wallet.put_own_coin(own_coin, tokenlist.clone()).await?;
+                        if !own_coins.contains(&coin) {
+                            own_coins.push(coin);
+                        }
+                        if !received.contains(&update.tx_hash) {
+                            received.push(update.tx_hash);
+                        }
+
+                        if !logged {
+                            let entry = TxHistoryEntry {
+                                timestamp: Timestamp::current_time(),
+                                direction: TxDirection::Received,
+                                value: note.value,
+                                token_id: note.token_id,
+                                tx_hash: update.tx_hash,
+                                memo: note.memo,
+                            };
+                            wallet.put_tx_history(&entry).await?;
+                        }
+
+                        if let Some(events) = &events {
+                            debug!(target: "state_apply", "Send a notification");
+                            let event = StateEvent::NewCoin {
+                                public: PublicKey::from_secret(*secret),
+                                coin,
+                                value: note.value,
+                                token_id: note.token_id,
+                                tx_hash: update.tx_hash,
+                            };
+                            events.notify(event).await;
+                        }
                     }
                 }
             }
+        }
 
-            // Save updated merkle tree into the wallet.
+        // Save updated merkle tree into the wallets, once for the whole update,
+        // and mark the coins this update spends.
+        for (wallet, _) in wallets.iter() {
+            if !roots.is_empty() {
+                wallet.put_tree(&self.tree).await?;
+            }
+            wallet.confirm_spent(&update.nullifiers).await?;
+        }
+
+        Ok(StateDiff { nullifiers: update.nullifiers, roots, own_coins, received })
+    }
+
+    /// Undo a [`StateDiff`] produced by [`State::apply`]. The Merkle tree
+    /// is rewound to its last checkpoint, so callers must checkpoint the
+    /// tree once before applying the updates covered by the diff.
+    pub async fn rollback(&mut self, diff: &StateDiff, wallets: &[WalletPtr]) -> Result<()> {
+        debug!(target: "state_apply", "Rewind Merkle tree");
+        if !self.tree.rewind() {
+            return Err(Error::MerkleTreeRewindFailed)
+        }
+
+        debug!(target: "state_apply", "Remove nullifiers and Merkle roots");
+        remove_nullifiers_and_roots(
+            &self.nullifiers,
+            &diff.nullifiers,
+            &self.merkle_roots,
+            &diff.roots,
+        )?;
+
+        for wallet in wallets {
+            for coin in &diff.own_coins {
+                wallet.remove_own_coin(coin).await?;
+            }
+            wallet.unconfirm_spent(&diff.nullifiers).await?;
+            wallet.remove_received_tx_history(&diff.received).await?;
             wallet.put_tree(&self.tree).await?;
         }
 
-        debug!(target: "state_apply", "Finished apply() successfully.");
         Ok(())
     }
 
@@ -230,21 +336,107 @@ impl ProgramState for State {
         false
     }
 
-    fn mint_vk(&self) -> &VerifyingKey {
-        self.mint_vk.get_or_create(build_mint_vk)
+    fn verifying_key(&self, circuit_id: &str) -> Option<Arc<VerifyingKey>> {
+        self.verifying_keys.get(circuit_id)
     }
+}
 
-    fn burn_vk(&self) -> &VerifyingKey {
-        self.burn_vk.get_or_create(build_burn_vk)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::{
+            keypair::Keypair,
+            note::Memo,
+            types::{DrkCoinBlind, DrkSerial, DrkValueBlind},
+        },
+        node::vk_registry::VerifyingKeyRegistry,
+        wallet::walletdb::WalletDb,
+    };
+    use pasta_curves::{group::ff::Field, pallas};
+    use rand::rngs::OsRng;
+
+    fn note(value: u64, token_id: DrkTokenId) -> Note {
+        Note {
+            serial: DrkSerial::random(&mut OsRng),
+            value,
+            token_id,
+            coin_blind: DrkCoinBlind::random(&mut OsRng),
+            value_blind: DrkValueBlind::random(&mut OsRng),
+            token_blind: DrkValueBlind::random(&mut OsRng),
+            memo: Memo::default(),
+            metadata: None,
+        }
     }
-}
 
-fn build_mint_vk() -> VerifyingKey {
-    debug!("Building verifying key for MintContract");
-    VerifyingKey::build(8, &MintContract::default())
-}
+    #[async_std::test]
+    async fn apply_and_rollback() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let wallet = WalletDb::new("sqlite::memory:", "darkfi").await?;
+        wallet.init_db().await?;
+        let keypair = Keypair::random(&mut OsRng);
+        wallet.put_keypair(&keypair).await?;
+        let tokenlist = Arc::new(DrkTokenList::new(&[(
+            "drk",
+            include_bytes!("../../contrib/token/darkfi_token_list.min.json"),
+        )])?);
+
+        let mut state = State {
+            tree: wallet.tree_gen().await?,
+            merkle_roots: RootStore::new(&db)?,
+            nullifiers: NullifierStore::new(&db)?,
+            cashier_pubkeys: vec![],
+            faucet_pubkeys: vec![],
+            verifying_keys: VerifyingKeyRegistry::new(),
+        };
+
+        // A coin the wallet already holds, which the block spends
+        let token_id = DrkTokenId::random(&mut OsRng);
+        let spent_note = note(42, token_id);
+        let spent = OwnCoin {
+            coin: Coin(pallas::Base::random(&mut OsRng)),
+            nullifier: Nullifier::new(keypair.secret, spent_note.serial),
+            note: spent_note,
+            secret: keypair.secret,
+            leaf_position: 0.into(),
+        };
+        wallet.put_own_coin(spent, tokenlist.clone()).await?;
 
-fn build_burn_vk() -> VerifyingKey {
-    debug!("Building verifying key for BurnContract");
-    VerifyingKey::build(11, &BurnContract::default())
+        let coins_before = wallet.get_own_coins().await?;
+        let history_before = wallet.get_tx_history(0, 10).await?;
+        let root_before = state.tree.root(0);
+        let digest_before = state.nullifiers.digest()?;
+
+        // The block pays the wallet a coin and spends the old one
+        let update = StateUpdate {
+            nullifiers: vec![spent.nullifier],
+            coins: vec![Coin(pallas::Base::random(&mut OsRng))],
+            enc_notes: vec![note(69, token_id).encrypt(&keypair.public)?],
+            tx_hash: blake3::hash(b"tx"),
+        };
+
+        state.tree.checkpoint();
+        let wallets = vec![(wallet.clone(), vec![keypair.secret])];
+        let diff = state.apply(vec![update.clone()], wallets, None, tokenlist).await?;
+
+        let coins = wallet.get_own_coins().await?;
+        assert_eq!(coins.len(), 1);
+        assert_eq!(coins[0].coin, update.coins[0]);
+        assert_eq!(wallet.get_tx_history(0, 10).await?.len(), 1);
+        assert!(state.nullifiers.contains(&spent.nullifier)?);
+        assert!(state.merkle_roots.contains(&state.tree.root(0).unwrap())?);
+        assert_eq!(diff.received, vec![update.tx_hash]);
+
+        state.rollback(&diff, &[wallet.clone()]).await?;
+
+        assert_eq!(wallet.get_own_coins().await?, coins_before);
+        assert_eq!(wallet.get_tx_history(0, 10).await?, history_before);
+        assert_eq!(state.tree.root(0), root_before);
+        assert_eq!(wallet.get_tree().await?.root(0), root_before);
+        assert_eq!(state.nullifiers.digest()?, digest_before);
+        assert!(!state.nullifiers.contains(&spent.nullifier)?);
+        assert!(state.merkle_roots.get_all()?.is_empty());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,468 @@
+//! Token lists the node resolves tickers and token IDs with. The lists
+//! built into the binary are used until newer ones are fetched from the
+//! configured sources, so new tokens work without a new release. A
+//! fetched list is only used if it matches the hash it's pinned to, or
+//! carries a signature by the key it's pinned to, and it's cached on disk
+//! so it's used again after a restart. Pins hold over the list as served,
+//! or over its canonical JSON, so a mirror re-encoding a list doesn't
+//! break them. Signed lists carry the `version` of the token list standard,
+//! and one older than the list in use is refused, so a mirror can't serve
+//! a list the signer has since corrected.
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_std::net::TcpStream;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use log::{error, info};
+use serde_json::Value;
+use url::Url;
+
+use crate::{
+    crypto::{
+        address::Address,
+        keypair::PublicKey,
+        schnorr::{SchnorrPublic, Signature},
+        token_list::{DrkTokenList, TokenList},
+    },
+    net::transport::{dial_proxy, Socks5Transport, Transport},
+    util::{async_util::sleep, canonical_json::canonical_json, serial::deserialize, NetworkName},
+    Error, Result,
+};
+
+/// Default seconds between two fetches of the token lists
+pub const TOKEN_LIST_REFRESH: u64 = 86400;
+
+/// Seconds a token list has to be fetched in
+const FETCH_TIMEOUT: u64 = 60;
+
+/// Largest response accepted when fetching a token list
+const MAX_RESPONSE_SIZE: usize = 32 * 1024 * 1024;
+
+pub type TokenRegistryPtr = Arc<TokenRegistry>;
+
+/// Major, minor and patch `version` of a token list
+type ListVersion = (u64, u64, u64);
+
+/// What a fetched token list has to match to be used
+#[derive(Clone, Debug)]
+pub enum TokenListPin {
+    /// The list has this blake3 hash
+    Hash(blake3::Hash),
+    /// The list is signed by this key. The hex encoded signature is
+    /// served at the URL of the list with `.sig` appended.
+    Signer(PublicKey),
+}
+
+impl TokenListPin {
+    /// Check `data`, or its canonical JSON, matches the pin, with its
+    /// `signature` when pinned to a signer. Returns the version of a signed
+    /// list, which it needs to have.
+    fn verify(&self, data: &[u8], signature: Option<&[u8]>) -> Result<Option<ListVersion>> {
+        let signature = match self {
+            Self::Hash(_) => None,
+            Self::Signer(_) => {
+                let signature = signature.ok_or_else(|| invalid("Signature is missing"))?;
+                let signature = hex::decode(String::from_utf8_lossy(signature).trim())
+                    .map_err(|_| invalid("Signature isn't hex encoded"))?;
+                Some(deserialize::<Signature>(&signature)?)
+            }
+        };
+
+        let matches = |message: &[u8]| match (self, &signature) {
+            (Self::Hash(hash), _) => blake3::hash(message) == *hash,
+            (Self::Signer(public), Some(signature)) => public.verify(message, signature),
+            (Self::Signer(_), None) => false,
+        };
+
+        let json = serde_json::from_slice::<Value>(data).ok();
+        let canonical = json.as_ref().map(canonical_json);
+        if !matches(data) && !canonical.map_or(false, |c| matches(c.as_bytes())) {
+            return Err(invalid("List doesn't match its pin"))
+        }
+
+        match self {
+            Self::Hash(_) => Ok(None),
+            Self::Signer(_) => match json.as_ref().and_then(list_version) {
+                Some(v) => Ok(Some(v)),
+                None => Err(invalid("Signed list has no version")),
+            },
+        }
+    }
+}
+
+/// The `version` of a token list, as in `{"major": 1, "minor": 0, "patch": 0}`
+fn list_version(list: &Value) -> Option<ListVersion> {
+    let version = list.get("version")?;
+    let part = |name: &str| version.get(name).and_then(Value::as_u64);
+    Some((part("major")?, part("minor")?, part("patch")?))
+}
+
+impl FromStr for TokenListPin {
+    type Err = Error;
+
+    /// Parse `blake3:<hex hash>` or `signer:<address>`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            Some(("blake3", hash)) => Ok(Self::Hash(
+                blake3::Hash::from_hex(hash).map_err(|_| invalid("Invalid blake3 hash"))?,
+            )),
+            Some(("signer", address)) => {
+                Ok(Self::Signer(PublicKey::try_from(Address::from_str(address)?)?))
+            }
+            _ => Err(invalid("Pin has to be `blake3:<hash>` or `signer:<address>`")),
+        }
+    }
+}
+
+/// Where the token list of a network is fetched from
+#[derive(Clone, Debug)]
+pub struct TokenListSource {
+    pub network: NetworkName,
+    pub url: Url,
+    pub pin: TokenListPin,
+}
+
+impl TokenListSource {
+    /// Name of the file the list is cached in
+    fn cache_file(&self) -> String {
+        format!("{}_token_list.json", self.network.to_string().to_lowercase())
+    }
+}
+
+impl FromStr for TokenListSource {
+    type Err = Error;
+
+    /// Parse `<network>,<url>,<pin>`, e.g.
+    /// `sol,https://example.com/solana.tokenlist.json,signer:<address>`
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split(',').map(|p| p.trim()).collect();
+        if parts.len() != 3 {
+            return Err(invalid("Token list source has to be `<network>,<url>,<pin>`"))
+        }
+
+        let url = Url::parse(parts[1])?;
+        match url.scheme() {
+            "http" | "https" => {}
+            x => return Err(Error::UnsupportedTransport(x.to_string())),
+        }
+
+        Ok(Self { network: NetworkName::from_str(parts[0])?, url, pin: parts[2].parse()? })
+    }
+}
+
+pub struct TokenRegistry {
+    lists: RwLock<Arc<DrkTokenList>>,
+    /// Version of the signed list in use for each network
+    versions: RwLock<HashMap<NetworkName, ListVersion>>,
+    sources: Vec<TokenListSource>,
+    /// Directory fetched lists are cached in
+    cache_dir: Option<PathBuf>,
+    /// SOCKS5 proxy lists are fetched through
+    proxy: Option<Url>,
+}
+
+impl TokenRegistry {
+    /// Serve `lists` until others are fetched
+    pub fn new(lists: DrkTokenList) -> Self {
+        Self {
+            lists: RwLock::new(Arc::new(lists)),
+            versions: RwLock::new(HashMap::new()),
+            sources: vec![],
+            cache_dir: None,
+            proxy: None,
+        }
+    }
+
+    /// Fetch the lists of `sources` on refresh, and cache them in
+    /// `cache_dir`
+    pub fn set_sources(&mut self, sources: Vec<TokenListSource>, cache_dir: PathBuf) {
+        self.sources = sources;
+        self.cache_dir = Some(cache_dir);
+    }
+
+    /// Fetch the lists through a SOCKS5 proxy, such as Tor's
+    pub fn set_proxy(&mut self, proxy: Option<Url>) {
+        self.proxy = proxy;
+    }
+
+    /// The token lists in use. Keep the handle for as long as the lists
+    /// have to stay consistent, since a refresh replaces them.
+    pub fn current(&self) -> Arc<DrkTokenList> {
+        self.lists.read().unwrap().clone()
+    }
+
+    /// Use the lists cached by an earlier refresh, if they still match
+    /// their pins. Returns the networks whose list was loaded.
+    pub fn load_cache(&self) -> Result<Vec<NetworkName>> {
+        let cache_dir = match &self.cache_dir {
+            Some(v) => v,
+            None => return Ok(vec![]),
+        };
+
+        let mut loaded = vec![];
+        for source in &self.sources {
+            let path = cache_dir.join(source.cache_file());
+            if !path.exists() {
+                continue
+            }
+
+            let data = fs::read(&path)?;
+            let signature = fs::read(path.with_extension("json.sig")).ok();
+            let applied = source
+                .pin
+                .verify(&data, signature.as_deref())
+                .and_then(|version| self.apply(source, &data, version));
+
+            match applied {
+                Ok(_) => loaded.push(source.network.clone()),
+                Err(e) => error!("Ignoring cached token list {:?}: {}", path, e),
+            }
+        }
+
+        Ok(loaded)
+    }
+
+    /// Fetch the list of every source, and use the ones matching their
+    /// pin. Returns the number of tokens in each list, or why it wasn't
+    /// used.
+    pub async fn refresh(&self) -> Vec<(&TokenListSource, Result<usize>)> {
+        let mut results = vec![];
+        for source in &self.sources {
+            let result = self.refresh_source(source).await;
+            match &result {
+                Ok(n) => info!("Refreshed {} token list: {} tokens", source.network, n),
+                Err(e) => error!("Failed refreshing {} token list: {}", source.network, e),
+            }
+            results.push((source, result));
+        }
+
+        results
+    }
+
+    /// Refresh the lists every `interval` seconds.
+    pub async fn refresh_loop(self: Arc<Self>, interval: u64) -> Result<()> {
+        loop {
+            sleep(interval).await;
+            self.refresh().await;
+        }
+    }
+
+    async fn refresh_source(&self, source: &TokenListSource) -> Result<usize> {
+        let data = fetch(&source.url, &self.proxy).await?;
+        let signature = match source.pin {
+            TokenListPin::Hash(_) => None,
+            TokenListPin::Signer(_) => {
+                let url = Url::parse(&format!("{}.sig", source.url))?;
+                Some(fetch(&url, &self.proxy).await?)
+            }
+        };
+
+        let version = source.pin.verify(&data, signature.as_deref())?;
+        let n = self.apply(source, &data, version)?;
+
+        if let Some(cache_dir) = &self.cache_dir {
+            let path = cache_dir.join(source.cache_file());
+            fs::create_dir_all(cache_dir)?;
+            fs::write(&path, &data)?;
+            if let Some(signature) = signature {
+                fs::write(path.with_extension("json.sig"), signature)?;
+            }
+        }
+
+        Ok(n)
+    }
+
+    /// Parse the list of `source`, and use it in place of the one its
+    /// network had, unless that one is signed with a later `version`.
+    /// Returns the number of tokens in it.
+    fn apply(
+        &self,
+        source: &TokenListSource,
+        data: &[u8],
+        version: Option<ListVersion>,
+    ) -> Result<usize> {
+        let mut versions = self.versions.write().unwrap();
+        if let (Some(version), Some(current)) = (version, versions.get(&source.network)) {
+            if version < *current {
+                return Err(invalid("List is older than the one in use"))
+            }
+        }
+
+        let tokenlist = TokenList::new(&source.network.to_string(), data)?;
+        let n = tokenlist.0.len();
+
+        match version {
+            Some(v) => versions.insert(source.network.clone(), v),
+            None => versions.remove(&source.network),
+        };
+
+        let mut lists = self.lists.write().unwrap();
+        let mut updated = (**lists).clone();
+        updated.insert(source.network.clone(), tokenlist);
+        *lists = Arc::new(updated);
+
+        Ok(n)
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::TokenListError(reason.to_string())
+}
+
+/// Fetch what `url` serves over HTTP or HTTPS, through `proxy` if one is
+/// given. Redirects aren't followed.
+async fn fetch(url: &Url, proxy: &Option<Url>) -> Result<Vec<u8>> {
+    let host = url.host_str().ok_or_else(|| Error::UrlParse(format!("Missing host in {}", url)))?;
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| Error::UrlParse(format!("Missing port in {}", url)))?;
+
+    let fetch = async {
+        match dial_proxy(url, proxy)? {
+            Some(proxy) => {
+                let target = Url::parse(&format!("tcp://{}:{}", host, port))?;
+                let stream = Socks5Transport::new(proxy)?.dial(target, None)?.await?;
+                request(stream, url, host).await
+            }
+            None => request(TcpStream::connect((host, port)).await?, url, host).await,
+        }
+    };
+
+    async_std::future::timeout(Duration::from_secs(FETCH_TIMEOUT), fetch)
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?
+}
+
+async fn request<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    url: &Url,
+    host: &str,
+) -> Result<Vec<u8>> {
+    match url.scheme() {
+        "https" => exchange(async_native_tls::connect(host, stream).await?, url, host).await,
+        _ => exchange(stream, url, host).await,
+    }
+}
+
+/// Send a GET request for `url` and read the response body. HTTP/1.0
+/// keeps the body from being chunked, and has the server close the
+/// connection after it.
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    url: &Url,
+    host: &str,
+) -> Result<Vec<u8>> {
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: darkfi\r\n\r\n", path, host);
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = vec![];
+    let mut buf = vec![0; 8192];
+    loop {
+        let n = match stream.read(&mut buf).await {
+            Ok(n) => n,
+            // Servers may close TLS connections without notifying
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => 0,
+            Err(e) => return Err(e.into()),
+        };
+        if n == 0 {
+            break
+        }
+
+        response.extend_from_slice(&buf[..n]);
+        if response.len() > MAX_RESPONSE_SIZE {
+            return Err(invalid("Response is too large"))
+        }
+    }
+
+    let header_end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid("Malformed HTTP response"))?;
+    let header = String::from_utf8_lossy(&response[..header_end]);
+    let status = header.split_whitespace().nth(1).unwrap_or_default();
+    if status != "200" {
+        return Err(Error::TokenListError(format!("{} returned HTTP status {}", url, status)))
+    }
+
+    Ok(response.split_off(header_end + 4))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::{keypair::Keypair, schnorr::SchnorrSecret},
+        util::serial::serialize,
+    };
+
+    const LIST: &str = r#"{"version": {"major": 1, "minor": 2, "patch": 0},
+        "tokens": [{"address": "So11111111111111111111111111111111111111112",
+        "decimals": 9, "name": "Wrapped SOL", "symbol": "wsol"}]}"#;
+
+    #[test]
+    fn pinned_lists() {
+        let keypair = Keypair::random(&mut rand::rngs::OsRng);
+        let signature = hex::encode(serialize(&keypair.secret.sign(LIST.as_bytes())));
+
+        let hash = format!("blake3:{}", blake3::hash(LIST.as_bytes()));
+        let pin: TokenListPin = hash.parse().unwrap();
+        assert!(pin.verify(LIST.as_bytes(), None).is_ok());
+        assert!(pin.verify(b"{}", None).is_err());
+
+        // Pinned to the canonical JSON, the list can be served re-encoded
+        let list: Value = serde_json::from_str(LIST).unwrap();
+        let hash = format!("blake3:{}", blake3::hash(canonical_json(&list).as_bytes()));
+        let pin: TokenListPin = hash.parse().unwrap();
+        assert!(pin.verify(LIST.as_bytes(), None).is_ok());
+        assert!(pin.verify(serde_json::to_string_pretty(&list).unwrap().as_bytes(), None).is_ok());
+
+        let signer = format!("signer:{}", Address::from(keypair.public));
+        let pin: TokenListPin = signer.parse().unwrap();
+        assert!(pin.verify(LIST.as_bytes(), Some(signature.as_bytes())).is_ok());
+        assert!(pin.verify(LIST.as_bytes(), None).is_err());
+        assert!(pin.verify(b"{}", Some(signature.as_bytes())).is_err());
+
+        // Signed lists need a version
+        let unversioned = r#"{"tokens": []}"#;
+        let unversioned_sig = hex::encode(serialize(&keypair.secret.sign(unversioned.as_bytes())));
+        assert!(pin.verify(unversioned.as_bytes(), Some(unversioned_sig.as_bytes())).is_err());
+
+        assert!("sol,ftp://example.com/list.json,blake3:00".parse::<TokenListSource>().is_err());
+        let source: TokenListSource =
+            format!("sol, https://example.com/list.json, {}", signer).parse().unwrap();
+
+        // Cached lists are checked again when they're loaded
+        let dir = std::env::temp_dir().join("token_registry_pinned_lists");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("solana_token_list.json"), LIST).unwrap();
+        fs::write(dir.join("solana_token_list.json.sig"), "00").unwrap();
+
+        let mut registry = TokenRegistry::new(DrkTokenList::new(&[]).unwrap());
+        registry.set_sources(vec![source], dir.clone());
+        assert!(registry.load_cache().unwrap().is_empty());
+
+        fs::write(dir.join("solana_token_list.json.sig"), &signature).unwrap();
+        let lists = registry.current();
+        assert_eq!(registry.load_cache().unwrap(), vec![NetworkName::Solana]);
+        assert!(registry.current().by_net[&NetworkName::Solana].get("WSOL".into()).is_some());
+        assert!(lists.by_net.is_empty());
+
+        // A signed list older than the one in use is refused
+        let source = &registry.sources[0];
+        let older = LIST.replace(r#""minor": 2"#, r#""minor": 1"#);
+        assert!(registry.apply(source, older.as_bytes(), Some((1, 1, 0))).is_err());
+        assert!(registry.apply(source, LIST.as_bytes(), Some((1, 2, 0))).is_ok());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,208 @@
+//! Verifying keys of the circuits the node checks proofs of, keyed by
+//! circuit ID. The keys of the built-in circuits are built on first use,
+//! and can't be replaced, as that would change which transactions the
+//! node accepts. Keys for new circuits can be loaded from compiled zkas
+//! binaries while the node runs, and are used for every proof checked
+//! after that.
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use log::{debug, info, warn};
+
+use crate::{
+    crypto::proof::VerifyingKey,
+    zk::{
+        circuit::{BurnContract, MembershipContract, MintContract},
+        vm::ZkCircuit,
+        vm_stack::empty_witnesses,
+    },
+    zkas::decoder::ZkBinary,
+    Error, Result,
+};
+
+/// Circuit proving transaction outputs
+pub const MINT_CIRCUIT_ID: &str = "mint";
+/// Circuit proving transaction inputs
+pub const BURN_CIRCUIT_ID: &str = "burn";
+/// Circuit proving coin membership, see [`crate::crypto::membership_proof`]
+pub const MEMBERSHIP_CIRCUIT_ID: &str = "membership";
+
+/// Size of the circuits loaded from zkas binaries
+const ZKVM_K: u32 = 13;
+
+pub type VerifyingKeyRegistryPtr = Arc<VerifyingKeyRegistry>;
+
+#[derive(Default)]
+pub struct VerifyingKeyRegistry {
+    keys: RwLock<HashMap<String, Arc<VerifyingKey>>>,
+}
+
+impl VerifyingKeyRegistry {
+    pub fn new() -> VerifyingKeyRegistryPtr {
+        Arc::new(Self::default())
+    }
+
+    /// The key of the given circuit, or `None` if it's unknown
+    pub fn get(&self, circuit_id: &str) -> Option<Arc<VerifyingKey>> {
+        if let Some(vk) = self.keys.read().unwrap().get(circuit_id) {
+            return Some(vk.clone())
+        }
+
+        let vk = Arc::new(build_builtin(circuit_id)?);
+        // It may have been built or loaded by someone else meanwhile
+        let mut keys = self.keys.write().unwrap();
+        Some(keys.entry(circuit_id.to_string()).or_insert(vk).clone())
+    }
+
+    /// Register the key of a circuit, replacing the one it had. Built-in
+    /// circuits are refused.
+    pub fn insert(&self, circuit_id: &str, vk: VerifyingKey) -> Result<()> {
+        if is_builtin(circuit_id) {
+            return Err(Error::BuiltinCircuit(circuit_id.to_string()))
+        }
+
+        info!("Registered verifying key for circuit {}", circuit_id);
+        self.keys.write().unwrap().insert(circuit_id.to_string(), Arc::new(vk));
+        Ok(())
+    }
+
+    /// IDs of the circuits with a key. Built-in circuits only have one
+    /// once it was needed.
+    pub fn circuit_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.keys.read().unwrap().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Build the key of the compiled zkas circuit at `path`, and register
+    /// it under the file name up to the first dot, e.g. `mint` for
+    /// `mint.zk.bin`. Returns the circuit ID.
+    pub fn load_file(&self, path: &Path) -> Result<String> {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let circuit_id = file_name.split('.').next().unwrap_or_default().to_string();
+        if circuit_id.is_empty() {
+            return Err(Error::ParseFailed("Circuit file name has no circuit ID"))
+        }
+
+        // Checked before the key is built, which takes a while
+        if is_builtin(&circuit_id) {
+            return Err(Error::BuiltinCircuit(circuit_id))
+        }
+
+        let zkbin = ZkBinary::decode(&fs::read(path)?)?;
+        debug!("Building verifying key for circuit {} from {:?}", circuit_id, path);
+        let circuit = ZkCircuit::new(empty_witnesses(&zkbin), zkbin);
+        self.insert(&circuit_id, VerifyingKey::build(ZKVM_K, &circuit))?;
+
+        Ok(circuit_id)
+    }
+
+    /// Load all the `.zk.bin` files in `dir` with [`Self::load_file`].
+    /// Files that fail to load are logged and skipped, so one bad circuit
+    /// doesn't keep the others out. Returns the IDs of the circuits loaded.
+    pub fn load_dir(&self, dir: &Path) -> Result<Vec<String>> {
+        let mut loaded = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if !path.is_file() || !path.to_string_lossy().ends_with(".zk.bin") {
+                continue
+            }
+
+            match self.load_file(&path) {
+                Ok(v) => loaded.push(v),
+                Err(e) => warn!("Skipping circuit {:?}: {}", path, e),
+            }
+        }
+
+        loaded.sort();
+        Ok(loaded)
+    }
+}
+
+fn is_builtin(circuit_id: &str) -> bool {
+    [MINT_CIRCUIT_ID, BURN_CIRCUIT_ID, MEMBERSHIP_CIRCUIT_ID].contains(&circuit_id)
+}
+
+fn build_builtin(circuit_id: &str) -> Option<VerifyingKey> {
+    let vk = match circuit_id {
+        MINT_CIRCUIT_ID => {
+            debug!("Building verifying key for MintContract");
+            VerifyingKey::build(8, &MintContract::default())
+        }
+        BURN_CIRCUIT_ID => {
+            debug!("Building verifying key for BurnContract");
+            VerifyingKey::build(11, &BurnContract::default())
+        }
+        MEMBERSHIP_CIRCUIT_ID => {
+            debug!("Building verifying key for MembershipContract");
+            VerifyingKey::build(11, &MembershipContract::default())
+        }
+        _ => return None,
+    };
+
+    Some(vk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zkas::{analyzer::Analyzer, compiler::Compiler, lexer::Lexer, parser::Parser};
+
+    #[test]
+    fn load_circuits() {
+        let registry = VerifyingKeyRegistry::new();
+        assert!(registry.get("arithmetic").is_none());
+        assert!(registry.circuit_ids().is_empty());
+
+        let source = include_str!("../../proof/arithmetic.zk");
+        let tokens = Lexer::new("arithmetic.zk", source.chars()).lex();
+        let (constants, witnesses, statements) =
+            Parser::new("arithmetic.zk", source.chars(), tokens).parse();
+        let mut analyzer =
+            Analyzer::new("arithmetic.zk", source.chars(), constants, witnesses, statements);
+        analyzer.analyze_types();
+        let bincode = Compiler::new(
+            "arithmetic.zk",
+            source.chars(),
+            analyzer.constants,
+            analyzer.witnesses,
+            analyzer.statements,
+            false,
+        )
+        .compile();
+
+        let dir =
+            std::env::temp_dir().join(format!("vk_registry_load_circuits_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("arithmetic.zk.bin"), &bincode).unwrap();
+        fs::write(dir.join("notes.txt"), "not a circuit").unwrap();
+
+        assert_eq!(registry.load_dir(&dir).unwrap(), vec!["arithmetic"]);
+        assert_eq!(registry.circuit_ids(), vec!["arithmetic"]);
+        let vk = registry.get("arithmetic").unwrap();
+
+        // Loading it again replaces the key
+        registry.load_file(&dir.join("arithmetic.zk.bin")).unwrap();
+        assert!(!Arc::ptr_eq(&vk, &registry.get("arithmetic").unwrap()));
+
+        fs::write(dir.join("broken.zk.bin"), "not a circuit").unwrap();
+        assert!(registry.load_file(&dir.join("broken.zk.bin")).is_err());
+
+        // Built-in circuits can't be replaced
+        fs::write(dir.join("mint.zk.bin"), &bincode).unwrap();
+        assert!(matches!(
+            registry.load_file(&dir.join("mint.zk.bin")),
+            Err(Error::BuiltinCircuit(id)) if id == MINT_CIRCUIT_ID
+        ));
+        assert!(!registry.circuit_ids().contains(&MINT_CIRCUIT_ID.to_string()));
+
+        // Files that fail to load don't keep the others out
+        assert_eq!(registry.load_dir(&dir).unwrap(), vec!["arithmetic"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+//! Access control for JSON-RPC servers.
+use super::jsonrpc::JsonRequest;
+
+/// Access control applied by the JSON-RPC server before a request reaches
+/// the [`RequestHandler`](super::server::RequestHandler). Without a token
+/// every method is open. With one, only methods matching `open_methods`
+/// can be called without passing the token in the request's `auth` member.
+///
+/// Methods set with [`Self::restrict`] always need the token, and can't
+/// be called at all when there's none.
+///
+/// Method patterns are either a full method name, a prefix ending with
+/// `*` such as `blockchain.*`, or `*` to match everything.
+#[derive(Clone, Debug, Default)]
+pub struct RpcAcl {
+    token: Option<String>,
+    open_methods: Vec<String>,
+    restricted_methods: Vec<String>,
+}
+
+impl RpcAcl {
+    pub fn new(token: Option<String>, open_methods: Vec<String>) -> Self {
+        // An empty token in a config file means no token
+        let token = token.filter(|t| !t.is_empty());
+        Self { token, open_methods, restricted_methods: vec![] }
+    }
+
+    /// Require the token for the given method patterns, whatever the open
+    /// methods are.
+    pub fn restrict(mut self, methods: &[&str]) -> Self {
+        self.restricted_methods.extend(methods.iter().map(|m| m.to_string()));
+        self
+    }
+
+    /// Whether the request may be handled.
+    pub fn allows(&self, req: &JsonRequest) -> bool {
+        let restricted = match req.method.as_str() {
+            Some(method) => self.restricted_methods.iter().any(|p| method_matches(p, method)),
+            None => false,
+        };
+
+        let token = match &self.token {
+            Some(v) => v,
+            None => return !restricted,
+        };
+
+        if let Some(auth) = &req.auth {
+            if constant_time_eq(auth.as_bytes(), token.as_bytes()) {
+                return true
+            }
+        }
+
+        if restricted {
+            return false
+        }
+
+        match req.method.as_str() {
+            Some(method) => self.open_methods.iter().any(|p| method_matches(p, method)),
+            None => false,
+        }
+    }
+}
+
+fn method_matches(pattern: &str, method: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => method.starts_with(prefix),
+        None => pattern == method,
+    }
+}
+
+/// Compare two byte strings without leaking where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false
+    }
+
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn acl_allows() {
+        let mut req = JsonRequest::new("wallet.keygen", json!([]));
+        assert!(RpcAcl::default().allows(&req));
+
+        let acl = RpcAcl::new(Some("secret".into()), vec!["ping".into(), "blockchain.*".into()]);
+        assert!(!acl.allows(&req));
+        assert!(acl.allows(&JsonRequest::new("ping", json!([]))));
+        assert!(acl.allows(&JsonRequest::new("blockchain.get_slot", json!([]))));
+        assert!(!acl.allows(&JsonRequest::new("pingpong", json!([]))));
+
+        req.auth = Some("wrong".into());
+        assert!(!acl.allows(&req));
+        req.auth = Some("secret".into());
+        assert!(acl.allows(&req));
+
+        // Restricted methods need the token even if they're open, and
+        // can't be called without one configured
+        let mut req = JsonRequest::new("circuits.load", json!([]));
+        assert!(!RpcAcl::default().restrict(&["circuits.load"]).allows(&req));
+        let acl = RpcAcl::new(Some("secret".into()), vec!["*".into()]).restrict(&["circuits.load"]);
+        assert!(!acl.allows(&req));
+        assert!(acl.allows(&JsonRequest::new("circuits.list", json!([]))));
+        req.auth = Some("secret".into());
+        assert!(acl.allows(&req));
+    }
+}
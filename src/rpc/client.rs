@@ -1,5 +1,5 @@
 //! JSON-RPC client-side implementation.
-use std::time::Duration;
+use std::{io, time::Duration};
 
 use async_std::io::timeout;
 use futures::{select, AsyncReadExt, AsyncWriteExt, FutureExt};
@@ -7,11 +7,11 @@ use log::{debug, error};
 use serde_json::{json, Value};
 use url::Url;
 
-use super::jsonrpc::{ErrorCode, JsonError, JsonRequest, JsonResult};
+use super::jsonrpc::{redact_auth, ErrorCode, JsonError, JsonRequest, JsonResult};
 use crate::{
     net::{
-        transport::Transport, TcpTransport, TorTransport, TransportName, TransportStream,
-        UnixTransport,
+        transport::{dial_proxy, Transport},
+        Socks5Transport, TcpTransport, TransportName, TransportStream, UnixTransport,
     },
     Error, Result,
 };
@@ -19,16 +19,30 @@ use crate::{
 /// JSON-RPC client implementation using asynchronous channels.
 pub struct RpcClient {
     send: async_channel::Sender<Value>,
-    recv: async_channel::Receiver<JsonResult>,
+    recv: async_channel::Receiver<Value>,
     stop_signal: async_channel::Sender<()>,
     url: Url,
+    auth_token: Option<String>,
 }
 
 impl RpcClient {
     /// Instantiate a new JSON-RPC client that will connect to the given URL.
     pub async fn new(url: Url) -> Result<Self> {
-        let (send, recv, stop_signal) = Self::open_channels(&url).await?;
-        Ok(Self { send, recv, stop_signal, url })
+        Self::with_proxy(url, None).await
+    }
+
+    /// Instantiate a new JSON-RPC client that will connect to the given URL
+    /// through a SOCKS5 proxy, such as Tor's. Without one, only onion
+    /// services go through Tor's default proxy.
+    pub async fn with_proxy(url: Url, proxy: Option<Url>) -> Result<Self> {
+        let (send, recv, stop_signal) = Self::open_channels(&url, &proxy).await?;
+        Ok(Self { send, recv, stop_signal, url, auth_token: None })
+    }
+
+    /// Pass the given token along with every request, for servers that
+    /// require authentication.
+    pub fn set_auth_token(&mut self, token: Option<String>) {
+        self.auth_token = token;
     }
 
     /// Close the channels of an instantiated [`RpcClient`].
@@ -38,10 +52,13 @@ impl RpcClient {
     }
 
     /// Send a given JSON-RPC request over the instantiated client.
-    pub async fn request(&self, value: JsonRequest) -> Result<Value> {
+    pub async fn request(&self, mut value: JsonRequest) -> Result<Value> {
         let req_id = value.id.clone().as_u64().unwrap();
+        if value.auth.is_none() {
+            value.auth = self.auth_token.clone();
+        }
 
-        debug!(target: "jsonrpc-client", "--> {}", serde_json::to_string(&value)?);
+        debug!(target: "jsonrpc-client", "--> {}", redact_auth(&json!(value)));
 
         // If the connection is closed, the sender will get an error for
         // sending to a closed channel.
@@ -58,7 +75,8 @@ impl RpcClient {
             return Err(Error::NetworkOperationFailed)
         }
 
-        match reply? {
+        let reply: JsonResult = serde_json::from_value(reply?)?;
+        match reply {
             JsonResult::Response(r) => {
                 // Check if the IDs match
                 let resp_id = r.id.as_u64();
@@ -92,6 +110,69 @@ impl RpcClient {
         }
     }
 
+    /// Send several JSON-RPC requests in a single batch, which the server
+    /// handles concurrently. Results are returned in the order of the
+    /// requests, and an error reply only fails its own request.
+    pub async fn send_batch(&self, reqs: Vec<JsonRequest>) -> Result<Vec<Result<Value>>> {
+        if reqs.is_empty() {
+            return Ok(vec![])
+        }
+
+        let mut batch = Vec::with_capacity(reqs.len());
+        for mut req in reqs {
+            if req.auth.is_none() {
+                req.auth = self.auth_token.clone();
+            }
+            batch.push(req);
+        }
+
+        let batch = json!(batch);
+        debug!(target: "jsonrpc-client", "--> {}", redact_auth(&batch));
+
+        if let Err(e) = self.send.send(batch.clone()).await {
+            error!("JSON-RPC client unable to send to {} (channels closed): {}", self.url, e);
+            return Err(Error::NetworkOperationFailed)
+        }
+
+        let reply = match self.recv.recv().await {
+            Ok(v) => v,
+            Err(_) => {
+                error!("JSON-RPC client unable to recv from {} (channels closed)", self.url);
+                return Err(Error::NetworkOperationFailed)
+            }
+        };
+        debug!(target: "jsonrpc-client", "<-- {}", reply);
+
+        // A server rejecting the whole batch replies with a single error
+        let replies: Vec<JsonResult> = match serde_json::from_value(reply.clone()) {
+            Ok(v) => v,
+            Err(_) => {
+                self.stop_signal.send(()).await?;
+                return match serde_json::from_value(reply)? {
+                    JsonResult::Error(e) => Err(Error::JsonRpcError(e.error.message.to_string())),
+                    _ => Err(Error::JsonRpcError("Unexpected reply".to_string())),
+                }
+            }
+        };
+
+        let mut results = vec![];
+        for req in batch.as_array().unwrap() {
+            let result = replies.iter().find_map(|reply| match reply {
+                JsonResult::Response(r) if r.id == req["id"] => Some(Ok(r.result.clone())),
+                JsonResult::Error(e) if e.id == req["id"] => {
+                    Some(Err(Error::JsonRpcError(e.error.message.to_string())))
+                }
+                _ => None,
+            });
+
+            results.push(result.unwrap_or_else(|| {
+                Err(Error::JsonRpcError(format!("No reply to request {}", req["id"])))
+            }));
+        }
+
+        Ok(results)
+    }
+
     /// Oneshot send a given JSON-RPC request over the instantiated client
     /// and close the channels on reply.
     pub async fn oneshot_request(&self, value: JsonRequest) -> Result<Value> {
@@ -103,9 +184,10 @@ impl RpcClient {
     /// Instantiate channels for a new [`RpcClient`].
     async fn open_channels(
         uri: &Url,
+        proxy: &Option<Url>,
     ) -> Result<(
         async_channel::Sender<Value>,
-        async_channel::Receiver<JsonResult>,
+        async_channel::Receiver<Value>,
         async_channel::Sender<()>,
     )> {
         let (data_send, data_recv) = async_channel::unbounded();
@@ -143,19 +225,23 @@ impl RpcClient {
             }};
         }
 
-        match transport_name {
-            TransportName::Tcp(upgrade) => {
-                let transport = TcpTransport::new(None, 1024);
-                let stream = transport.dial(uri.clone(), None);
+        let proxy = match transport_name {
+            TransportName::Unix => None,
+            _ => dial_proxy(uri, proxy)?,
+        };
+
+        match (transport_name, proxy) {
+            (TransportName::Tcp(upgrade) | TransportName::Tor(upgrade), Some(proxy)) => {
+                let transport = Socks5Transport::new(proxy)?;
+                let stream = transport.clone().dial(uri.clone(), None);
                 reqrep!(stream, transport, upgrade);
             }
-            TransportName::Tor(upgrade) => {
-                let socks5_url = TorTransport::get_dialer_env()?;
-                let transport = TorTransport::new(socks5_url, None)?;
-                let stream = transport.clone().dial(uri.clone(), None);
+            (TransportName::Tcp(upgrade), None) => {
+                let transport = TcpTransport::new(None, 1024);
+                let stream = transport.dial(uri.clone(), None);
                 reqrep!(stream, transport, upgrade);
             }
-            TransportName::Unix => {
+            (TransportName::Unix, _) => {
                 let transport = UnixTransport::new();
                 let stream = transport.dial(uri.clone()).await;
                 if let Err(err) = stream {
@@ -174,7 +260,7 @@ impl RpcClient {
     /// Internal function that loops on a given stream and multiplexes the data.
     async fn reqrep_loop<T: TransportStream>(
         mut stream: T,
-        result_send: async_channel::Sender<JsonResult>,
+        result_send: async_channel::Sender<Value>,
         data_recv: async_channel::Receiver<Value>,
         stop_recv: async_channel::Receiver<()>,
     ) -> Result<()> {
@@ -182,15 +268,11 @@ impl RpcClient {
         let read_timeout = Duration::from_secs(30);
 
         loop {
-            // Nasty size
-            let mut buf = vec![0; 2048 * 10];
-
             select! {
                 data = data_recv.recv().fuse() => {
                     let data_bytes = serde_json::to_vec(&data?)?;
                     stream.write_all(&data_bytes).await?;
-                    let n = timeout(read_timeout, async { stream.read(&mut buf[..]).await }).await?;
-                    let reply: JsonResult = serde_json::from_slice(&buf[0..n])?;
+                    let reply = timeout(read_timeout, read_json(&mut stream)).await?;
                     result_send.send(reply).await?;
                 }
 
@@ -201,3 +283,25 @@ impl RpcClient {
         Ok(())
     }
 }
+
+/// Read from the stream until a whole JSON value came in, since replies
+/// to batches don't necessarily fit in a single read.
+async fn read_json<T: TransportStream>(stream: &mut T) -> io::Result<Value> {
+    let mut data = vec![];
+
+    loop {
+        // Nasty size
+        let mut buf = vec![0; 2048 * 10];
+        let n = stream.read(&mut buf[..]).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into())
+        }
+
+        data.extend_from_slice(&buf[..n]);
+        match serde_json::from_slice(&data) {
+            Ok(v) => return Ok(v),
+            Err(e) if e.is_eof() => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
@@ -14,6 +14,7 @@ pub enum ErrorCode {
     InternalError,
     ServerError(i64),
     InvalidId,
+    Unauthorized,
 }
 
 impl ErrorCode {
@@ -27,6 +28,7 @@ impl ErrorCode {
             // -32000 to -32099
             Self::ServerError(c) => c,
             Self::InvalidId => -32001,
+            Self::Unauthorized => -32002,
         }
     }
 
@@ -39,6 +41,7 @@ impl ErrorCode {
             Self::InternalError => "Internal error",
             Self::ServerError(_) => "",
             Self::InvalidId => "Request ID mismatch",
+            Self::Unauthorized => "Unauthorized",
         };
 
         desc.to_string()
@@ -73,7 +76,7 @@ impl From<JsonNotification> for JsonResult {
 }
 
 /// A JSON-RPC request object.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct JsonRequest {
     /// JSON-RPC version
     pub jsonrpc: Value,
@@ -83,6 +86,9 @@ pub struct JsonRequest {
     pub method: Value,
     /// Request parameters
     pub params: Value,
+    /// Authentication token, for servers requiring one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<String>,
 }
 
 impl JsonRequest {
@@ -94,7 +100,38 @@ impl JsonRequest {
             id: json!(rng.gen::<u64>()),
             method: json!(method),
             params: parameters,
+            auth: None,
+        }
+    }
+}
+
+// Written out to keep the authentication token out of the logs
+impl std::fmt::Debug for JsonRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonRequest")
+            .field("jsonrpc", &self.jsonrpc)
+            .field("id", &self.id)
+            .field("method", &self.method)
+            .field("params", &self.params)
+            .field("auth", &self.auth.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
+
+/// What [`redact_auth`] replaces authentication tokens with
+const REDACTED: &str = "<redacted>";
+
+/// Copy of a request, or a batch of requests, with the authentication
+/// tokens replaced, to be logged.
+pub fn redact_auth(value: &Value) -> Value {
+    match value {
+        Value::Array(reqs) => Value::Array(reqs.iter().map(redact_auth).collect()),
+        Value::Object(obj) if obj.contains_key("auth") => {
+            let mut obj = obj.clone();
+            obj.insert("auth".to_string(), json!(REDACTED));
+            Value::Object(obj)
         }
+        v => v.clone(),
     }
 }
 
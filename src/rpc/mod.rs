@@ -1,11 +1,17 @@
 /// JSON-RPC primitives
 pub mod jsonrpc;
 
+/// Access control for JSON-RPC servers
+pub mod acl;
+
 /// Client-side JSON-RPC implementation
 pub mod client;
 
 /// Server-side JSON-RPC implementation
 pub mod server;
 
+/// WebSocket JSON-RPC server with subscriptions
+pub mod ws_server;
+
 /// Websockets client
 pub mod websockets;
@@ -1,15 +1,22 @@
 //! JSON-RPC server-side implementation.
+use std::{io, path::PathBuf};
+
 use async_std::sync::Arc;
 use async_trait::async_trait;
-use futures::{AsyncReadExt, AsyncWriteExt};
+use futures::{future::join_all, AsyncRead, AsyncReadExt, AsyncWriteExt};
 use log::{debug, error, info, warn};
+use serde_json::{json, Value};
 use url::Url;
 
-use super::jsonrpc::{JsonRequest, JsonResult};
+use super::{
+    acl::RpcAcl,
+    jsonrpc::{redact_auth, ErrorCode, JsonError, JsonRequest, JsonResult},
+};
 use crate::{
     net::{
-        transport::Transport, TcpTransport, TorTransport, TransportListener, TransportName,
-        TransportStream, UnixTransport,
+        transport::{TlsUpgrade, Transport},
+        TcpTransport, TorTransport, TransportListener, TransportName, TransportStream,
+        UnixTransport,
     },
     Error, Result,
 };
@@ -20,45 +27,117 @@ use crate::{
 #[async_trait]
 pub trait RequestHandler: Sync + Send {
     async fn handle_request(&self, req: JsonRequest) -> JsonResult;
+
+    /// Access control checked before requests are handled. Everything is
+    /// allowed by default.
+    fn acl(&self) -> Option<&RpcAcl> {
+        None
+    }
+}
+
+/// Check a request against the handler's access control and handle it.
+pub(super) async fn handle(
+    req: JsonRequest,
+    peer_addr: &Url,
+    rh: &impl RequestHandler,
+) -> JsonResult {
+    match rh.acl() {
+        Some(acl) if !acl.allows(&req) => {
+            warn!("JSON-RPC server denied {:?} request from {}", req.method, peer_addr);
+            JsonError::new(ErrorCode::Unauthorized, None, req.id).into()
+        }
+        _ => rh.handle_request(req).await,
+    }
+}
+
+/// Handle the requests of a JSON-RPC batch concurrently. Replies are in
+/// the same order as the requests, with an error for every request that
+/// couldn't be parsed.
+async fn handle_batch(reqs: Vec<Value>, peer_addr: &Url, rh: &impl RequestHandler) -> Value {
+    if reqs.is_empty() {
+        let reply: JsonResult = JsonError::new(ErrorCode::InvalidRequest, None, Value::Null).into();
+        return json!(reply)
+    }
+
+    let replies = reqs.into_iter().map(|req| async move {
+        match serde_json::from_value::<JsonRequest>(req) {
+            Ok(req) => handle(req, peer_addr, rh).await,
+            Err(_) => JsonError::new(ErrorCode::InvalidRequest, None, Value::Null).into(),
+        }
+    });
+
+    json!(join_all(replies).await)
+}
+
+/// Largest request, or batch of requests, a client may send
+const MAX_REQUEST_SIZE: usize = 1024 * 1024;
+
+/// Read from the stream until a whole JSON value came in, since requests
+/// don't necessarily arrive in a single read. Returns `None` if the
+/// connection was closed before a request started.
+async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<Option<Value>> {
+    let mut data = vec![];
+
+    loop {
+        // Nasty size
+        let mut buf = vec![0; 2048 * 10];
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            if data.is_empty() {
+                return Ok(None)
+            }
+            return Err(io::ErrorKind::UnexpectedEof.into())
+        }
+
+        data.extend_from_slice(&buf[..n]);
+        if data.len() > MAX_REQUEST_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "request too large"))
+        }
+
+        match serde_json::from_slice(&data) {
+            Ok(v) => return Ok(Some(v)),
+            Err(e) if e.is_eof() => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
 }
 
 /// Internal accept function that runs inside a loop for accepting incoming
-/// JSON-RPC requests and passing them to the [`RequestHandler`].
+/// JSON-RPC requests and passing them to the [`RequestHandler`]. Requests
+/// come either alone or in a batch array.
 async fn accept(
     mut stream: Box<dyn TransportStream>,
     peer_addr: Url,
     rh: Arc<impl RequestHandler + 'static>,
 ) -> Result<()> {
     loop {
-        // Nasty size
-        let mut buf = vec![0; 2048 * 10];
-
-        let n = match stream.read(&mut buf).await {
-            Ok(n) if n == 0 => {
-                debug!(target: "jsonrpc-server", "Closed connection for {}", peer_addr);
-                break
+        let r = match read_request(&mut stream).await {
+            Ok(Some(r)) => {
+                debug!(target: "jsonrpc-server", "{} --> {}", peer_addr, redact_auth(&r));
+                r
             }
-            Ok(n) => n,
-            Err(e) => {
-                error!("JSON-RPC server failed reading from {} socket: {}", peer_addr, e);
+            Ok(None) => {
                 debug!(target: "jsonrpc-server", "Closed connection for {}", peer_addr);
                 break
             }
-        };
-
-        let r: JsonRequest = match serde_json::from_slice(&buf[0..n]) {
-            Ok(r) => {
-                debug!(target: "jsonrpc-server", "{} --> {}", peer_addr, String::from_utf8_lossy(&buf));
-                r
-            }
             Err(e) => {
-                warn!("JSON-RPC server received invalid JSON from {}: {}", peer_addr, e);
+                warn!("JSON-RPC server failed reading request from {}: {}", peer_addr, e);
                 debug!(target: "jsonrpc-server", "Closed connection for {}", peer_addr);
                 break
             }
         };
 
-        let reply = rh.handle_request(r).await;
+        let reply = match r {
+            Value::Array(reqs) => handle_batch(reqs, &peer_addr, rh.as_ref()).await,
+            r => match serde_json::from_value::<JsonRequest>(r) {
+                Ok(r) => json!(handle(r, &peer_addr, rh.as_ref()).await),
+                Err(e) => {
+                    warn!("JSON-RPC server received invalid request from {}: {}", peer_addr, e);
+                    debug!(target: "jsonrpc-server", "Closed connection for {}", peer_addr);
+                    break
+                }
+            },
+        };
         let j = serde_json::to_string(&reply).unwrap();
         debug!(target: "jsonrpc-server", "{} <-- {}", peer_addr, j);
 
@@ -91,6 +170,17 @@ async fn run_accept_loop(
 pub async fn listen_and_serve(
     accept_url: Url,
     rh: Arc<impl RequestHandler + 'static>,
+) -> Result<()> {
+    listen_and_serve_with_tls(accept_url, rh, None).await
+}
+
+/// Like [`listen_and_serve`], but TLS listeners present the certificate
+/// chain and private key from the given PEM files, rather than a
+/// certificate generated on startup.
+pub async fn listen_and_serve_with_tls(
+    accept_url: Url,
+    rh: Arc<impl RequestHandler + 'static>,
+    tls_files: Option<(PathBuf, PathBuf)>,
 ) -> Result<()> {
     debug!(target: "jsonrpc-server", "Trying to bind listener on {}", accept_url);
 
@@ -114,7 +204,13 @@ pub async fn listen_and_serve(
                     run_accept_loop(Box::new(listener), rh).await?;
                 }
                 Some(u) if u == "tls" => {
-                    let tls_listener = $transport.upgrade_listener(listener)?.await?;
+                    let tls_listener = match &tls_files {
+                        Some((cert, key)) => {
+                            let upgrade = TlsUpgrade::with_server_cert(cert, key)?;
+                            upgrade.upgrade_listener_tls(listener).await?
+                        }
+                        None => $transport.upgrade_listener(listener)?.await?,
+                    };
                     info!("JSON-RPC listener bound to {}", accept_url);
                     run_accept_loop(Box::new(tls_listener), rh).await?;
                 }
@@ -156,3 +252,56 @@ pub async fn listen_and_serve(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::jsonrpc::JsonResponse;
+
+    struct Echo;
+
+    #[async_trait]
+    impl RequestHandler for Echo {
+        async fn handle_request(&self, req: JsonRequest) -> JsonResult {
+            JsonResponse::new(req.params, req.id).into()
+        }
+    }
+
+    #[async_std::test]
+    async fn batch_replies_in_order() {
+        let peer_addr = Url::parse("tcp://127.0.0.1:1").unwrap();
+        let reqs = vec![
+            json!(JsonRequest::new("a", json!([1]))),
+            json!("garbage"),
+            json!(JsonRequest::new("b", json!([2]))),
+        ];
+
+        let reply = handle_batch(reqs, &peer_addr, &Echo).await;
+        let reply = reply.as_array().unwrap();
+        assert_eq!(reply.len(), 3);
+        assert_eq!(reply[0]["result"], json!([1]));
+        assert_eq!(reply[1]["error"]["code"], json!(ErrorCode::InvalidRequest.code()));
+        assert_eq!(reply[2]["result"], json!([2]));
+
+        let reply = handle_batch(vec![], &peer_addr, &Echo).await;
+        assert_eq!(reply["error"]["code"], json!(ErrorCode::InvalidRequest.code()));
+    }
+
+    #[async_std::test]
+    async fn read_whole_request() {
+        // Larger than a single read
+        let req = json!(JsonRequest::new("a", json!(["x".repeat(50000)])));
+        let data = serde_json::to_vec(&req).unwrap();
+        let mut stream = futures::io::Cursor::new(data.clone());
+        assert_eq!(read_request(&mut stream).await.unwrap(), Some(req));
+        assert!(read_request(&mut stream).await.unwrap().is_none());
+
+        // Connection closed halfway through a request
+        let mut stream = futures::io::Cursor::new(data[..100].to_vec());
+        assert!(read_request(&mut stream).await.is_err());
+
+        let req = json!(JsonRequest::new("a", json!(["x".repeat(MAX_REQUEST_SIZE)])));
+        let mut stream = futures::io::Cursor::new(serde_json::to_vec(&req).unwrap());
+        assert!(read_request(&mut stream).await.is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! JSON-RPC server over WebSocket, with subscriptions.
+//!
+//! Besides plain requests, clients can call the subscription methods of
+//! a [`SubscriptionHandler`]. The reply carries a subscription id, and
+//! notifications are then pushed as they happen, in the form
+//! `{"jsonrpc": "2.0", "method": <method>, "params": {"subscription": <id>, "result": ..}}`.
+//! Calling `unsubscribe` with the id stops them.
+use std::collections::HashMap;
+
+use async_std::{
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+};
+use async_trait::async_trait;
+use futures::{select, FutureExt, SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use rand::Rng;
+use serde_json::{json, Value};
+use tungstenite::Message;
+use url::Url;
+
+use super::{
+    jsonrpc::{
+        redact_auth, ErrorCode, JsonError, JsonNotification, JsonRequest, JsonResponse, JsonResult,
+    },
+    server::{handle, RequestHandler},
+};
+use crate::{Error, Result};
+
+/// A subscription started by [`SubscriptionHandler::subscribe`].
+/// Every value received on `recv` is pushed to the client as a `method`
+/// notification. Dropping the subscription closes `recv`, which the
+/// handler can use to stop publishing to it.
+pub struct WsSubscription {
+    pub method: String,
+    pub recv: async_channel::Receiver<Value>,
+}
+
+/// Handler for JSON-RPC requests coming over WebSocket, where some methods
+/// start subscriptions rather than returning a single reply.
+#[async_trait]
+pub trait SubscriptionHandler: RequestHandler {
+    /// Start a subscription if the request calls a subscription method.
+    /// Returning `None` handles it as a plain request instead.
+    async fn subscribe(
+        &self,
+        req: &JsonRequest,
+    ) -> Option<std::result::Result<WsSubscription, JsonError>>;
+}
+
+/// Start a WebSocket JSON-RPC server bound to the given `ws://` URL.
+/// TLS isn't supported, so put a reverse proxy in front of the listener
+/// for `wss://`.
+pub async fn listen_and_serve_ws(
+    accept_url: Url,
+    rh: Arc<impl SubscriptionHandler + 'static>,
+) -> Result<()> {
+    if accept_url.scheme() != "ws" {
+        return Err(Error::UnsupportedTransport(accept_url.scheme().to_string()))
+    }
+
+    let host = accept_url.host_str().ok_or(Error::NoUrlFound)?;
+    let port = accept_url.port().ok_or(Error::NoUrlFound)?;
+
+    let listener = match TcpListener::bind((host, port)).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("JSON-RPC WebSocket listener bind to {} failed: {}", accept_url, e);
+            return Err(Error::BindFailed(accept_url.as_str().into()))
+        }
+    };
+    info!("JSON-RPC WebSocket listener bound to {}", accept_url);
+
+    loop {
+        let (stream, peer_addr) = match listener.accept().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("JSON-RPC WebSocket server failed accepting connection: {}", e);
+                continue
+            }
+        };
+
+        let peer_addr = Url::parse(&format!("ws://{}", peer_addr))?;
+        info!("JSON-RPC WebSocket server accepted connection from {}", peer_addr);
+
+        let rh = rh.clone();
+        smol::spawn(async move {
+            if let Err(e) = accept_ws(stream, &peer_addr, rh).await {
+                warn!("JSON-RPC WebSocket connection to {} failed: {}", peer_addr, e);
+            }
+            debug!(target: "jsonrpc-server", "Closed connection for {}", peer_addr);
+        })
+        .detach();
+    }
+}
+
+async fn accept_ws(
+    stream: TcpStream,
+    peer_addr: &Url,
+    rh: Arc<impl SubscriptionHandler + 'static>,
+) -> Result<()> {
+    let mut ws = async_tungstenite::accept_async(stream).await?;
+
+    // Notifications of every subscription on this connection are funneled
+    // through a single channel. Dropping a forwarding task ends its
+    // subscription.
+    let (notif_send, notif_recv) = async_channel::unbounded::<JsonNotification>();
+    let mut subscriptions: HashMap<u64, smol::Task<()>> = HashMap::new();
+
+    loop {
+        let msg = select! {
+            msg = ws.next().fuse() => match msg {
+                Some(msg) => msg?,
+                None => break,
+            },
+
+            notif = notif_recv.recv().fuse() => {
+                let notif = serde_json::to_string(&notif?)?;
+                ws.send(Message::Text(notif)).await?;
+                continue
+            }
+        };
+
+        let data = match msg {
+            Message::Text(v) => v,
+            Message::Ping(v) => {
+                ws.send(Message::Pong(v)).await?;
+                continue
+            }
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let req: JsonRequest = match serde_json::from_str(&data) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("JSON-RPC WebSocket server got invalid request from {}: {}", peer_addr, e);
+                break
+            }
+        };
+        debug!(target: "jsonrpc-server", "{} --> {}", peer_addr, redact_auth(&json!(req)));
+
+        let denied = rh.acl().map_or(false, |acl| !acl.allows(&req));
+        let reply: JsonResult = if denied {
+            warn!("JSON-RPC server denied {:?} request from {}", req.method, peer_addr);
+            JsonError::new(ErrorCode::Unauthorized, None, req.id).into()
+        } else if req.method.as_str() == Some("unsubscribe") {
+            let sub_id = req.params.as_array().and_then(|p| p.first()).and_then(|v| v.as_u64());
+            match sub_id {
+                Some(sub_id) => {
+                    let removed = subscriptions.remove(&sub_id).is_some();
+                    JsonResponse::new(json!(removed), req.id).into()
+                }
+                None => JsonError::new(ErrorCode::InvalidParams, None, req.id).into(),
+            }
+        } else {
+            match rh.subscribe(&req).await {
+                Some(Ok(sub)) => {
+                    let sub_id = rand::thread_rng().gen::<u64>();
+                    let notif_send = notif_send.clone();
+                    let task = smol::spawn(async move {
+                        while let Ok(result) = sub.recv.recv().await {
+                            let params = json!({"subscription": sub_id, "result": result});
+                            let notif = JsonNotification::new(&sub.method, params);
+                            if notif_send.send(notif).await.is_err() {
+                                break
+                            }
+                        }
+                    });
+                    subscriptions.insert(sub_id, task);
+                    JsonResponse::new(json!(sub_id), req.id).into()
+                }
+                Some(Err(e)) => e.into(),
+                None => handle(req, peer_addr, rh.as_ref()).await,
+            }
+        };
+
+        let reply = serde_json::to_string(&reply)?;
+        debug!(target: "jsonrpc-server", "{} <-- {}", peer_addr, reply);
+        ws.send(Message::Text(reply)).await?;
+    }
+
+    Ok(())
+}
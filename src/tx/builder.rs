@@ -1,23 +1,32 @@
+use incrementalmerkletree::{bridgetree::BridgeTree, Tree};
 use pasta_curves::group::ff::Field;
-use rand::rngs::OsRng;
+use rand::{
+    rngs::{OsRng, StdRng},
+    RngCore, SeedableRng,
+};
 
 use super::{
+    coin_selection::CoinSelection,
     partial::{PartialTransaction, PartialTransactionClearInput, PartialTransactionInput},
-    Transaction, TransactionClearInput, TransactionInput, TransactionOutput,
+    signing, Transaction, TransactionClearInput, TransactionInput, TransactionOutput,
 };
 use crate::{
     crypto::{
         burn_proof::create_burn_proof,
+        constants::MERKLE_DEPTH,
+        disclosure::PaymentDisclosure,
         keypair::{PublicKey, SecretKey},
         merkle_node::MerkleNode,
         mint_proof::create_mint_proof,
-        note::Note,
-        proof::ProvingKey,
+        note::{Memo, Note},
+        proof::{ParallelProver, ProvingKey},
         schnorr::SchnorrSecret,
+        token_id::MetadataCommitment,
         types::{DrkCoinBlind, DrkSerial, DrkTokenId, DrkValueBlind},
+        OwnCoin,
     },
     util::serial::Encodable,
-    Result,
+    ClientFailed, ClientResult, Result,
 };
 
 pub struct TransactionBuilder {
@@ -30,6 +39,8 @@ pub struct TransactionBuilderClearInputInfo {
     pub value: u64,
     pub token_id: DrkTokenId,
     pub signature_secret: SecretKey,
+    /// Metadata of the unique asset this issues, if it issues one
+    pub metadata: Option<MetadataCommitment>,
 }
 
 pub struct TransactionBuilderInputInfo {
@@ -43,9 +54,118 @@ pub struct TransactionBuilderOutputInfo {
     pub value: u64,
     pub token_id: DrkTokenId,
     pub public: PublicKey,
+    pub memo: Memo,
+    /// Metadata of the unique asset sent, for the recipient's note
+    pub metadata: Option<MetadataCommitment>,
+}
+
+/// A payment to make with [`TransactionBuilder::transfer`]
+pub struct TransactionBuilderPayment {
+    pub public: PublicKey,
+    pub value: u64,
+    pub memo: Memo,
 }
 
 impl TransactionBuilder {
+    /// Pay `payments` in `token_id` out of `own_coins`, spending the coins
+    /// picked by `selection` and sending what is left over back to
+    /// `change`. The Merkle paths of the spent coins are taken from `tree`,
+    /// which has to be the tree the coins were scanned into.
+    ///
+    /// Transactions don't carry fees yet, so the spent coins only have to
+    /// cover the payments.
+    pub fn transfer(
+        token_id: DrkTokenId,
+        payments: Vec<TransactionBuilderPayment>,
+        own_coins: &[OwnCoin],
+        selection: CoinSelection,
+        change: PublicKey,
+        tree: &BridgeTree<MerkleNode, MERKLE_DEPTH>,
+    ) -> ClientResult<Self> {
+        if payments.is_empty() {
+            return Err(ClientFailed::InvalidAmount(0))
+        }
+
+        let mut value: u64 = 0;
+        for payment in &payments {
+            if payment.value == 0 {
+                return Err(ClientFailed::InvalidAmount(0))
+            }
+            value = match value.checked_add(payment.value) {
+                Some(v) => v,
+                None => return Err(ClientFailed::InvalidAmount(payment.value)),
+            };
+        }
+
+        let selected = match selection.select(own_coins, token_id, value) {
+            Some(v) => v,
+            None => {
+                let available: u64 = own_coins
+                    .iter()
+                    .filter(|x| x.note.token_id == token_id)
+                    .map(|x| x.note.value)
+                    .sum();
+                return Err(ClientFailed::NotEnoughValue(available))
+            }
+        };
+
+        let root = match tree.root(0) {
+            Some(v) => v,
+            None => return Err(ClientFailed::InternalError("Merkle tree has no root".into())),
+        };
+
+        let mut inputs = Vec::with_capacity(selected.len());
+        let mut inputs_value = 0;
+        for own_coin in &selected {
+            // The path is derived from the leaf position recorded at scan time.
+            // If the tree doesn't know it, the wallet and state went out of sync.
+            let leaf_position = own_coin.leaf_position;
+            let merkle_path = match tree.authentication_path(leaf_position, &root) {
+                Some(v) => v,
+                None => {
+                    return Err(ClientFailed::InternalError(format!(
+                        "Merkle tree has no path for leaf position {:?}",
+                        leaf_position
+                    )))
+                }
+            };
+            inputs_value += own_coin.note.value;
+
+            inputs.push(TransactionBuilderInputInfo {
+                leaf_position,
+                merkle_path,
+                secret: own_coin.secret,
+                note: own_coin.note,
+            });
+        }
+
+        // Metadata of the unique asset sent, if it is one
+        let metadata = selected.iter().find_map(|x| x.note.metadata);
+
+        let mut outputs = vec![];
+        if inputs_value > value {
+            outputs.push(TransactionBuilderOutputInfo {
+                value: inputs_value - value,
+                token_id,
+                public: change,
+                memo: Memo::default(),
+                metadata,
+            });
+        }
+
+        for payment in payments {
+            outputs.push(TransactionBuilderOutputInfo {
+                value: payment.value,
+                token_id,
+                public: payment.public,
+                memo: payment.memo,
+                metadata,
+            });
+        }
+
+        Ok(Self { clear_inputs: vec![], inputs, outputs })
+    }
+
     fn compute_remainder_blind(
         clear_inputs: &[PartialTransactionClearInput],
         input_blinds: &[DrkValueBlind],
@@ -69,11 +189,43 @@ impl TransactionBuilder {
     }
 
     pub fn build(self, mint_pk: &ProvingKey, burn_pk: &ProvingKey) -> Result<Transaction> {
+        Ok(self.build_with_disclosures(mint_pk, burn_pk, &mut OsRng)?.0)
+    }
+
+    /// Build the transaction, creating its proofs concurrently on `prover`.
+    pub fn build_parallel(
+        self,
+        mint_pk: &ProvingKey,
+        burn_pk: &ProvingKey,
+        prover: &ParallelProver,
+    ) -> Result<Transaction> {
+        Ok(self.build_inner(mint_pk, burn_pk, Some(prover), &mut OsRng)?.0)
+    }
+
+    /// Build the transaction, also returning a [`PaymentDisclosure`] for
+    /// each output, in the same order as `self.outputs`.
+    /// All blinds, keys and proofs are sampled from `rng`.
+    pub fn build_with_disclosures(
+        self,
+        mint_pk: &ProvingKey,
+        burn_pk: &ProvingKey,
+        rng: impl RngCore,
+    ) -> Result<(Transaction, Vec<PaymentDisclosure>)> {
+        self.build_inner(mint_pk, burn_pk, None, rng)
+    }
+
+    fn build_inner(
+        self,
+        mint_pk: &ProvingKey,
+        burn_pk: &ProvingKey,
+        prover: Option<&ParallelProver>,
+        mut rng: impl RngCore,
+    ) -> Result<(Transaction, Vec<PaymentDisclosure>)> {
         let mut clear_inputs = vec![];
-        let token_blind = DrkValueBlind::random(&mut OsRng);
+        let token_blind = DrkValueBlind::random(&mut rng);
         for input in &self.clear_inputs {
             let signature_public = PublicKey::from_secret(input.signature_secret);
-            let value_blind = DrkValueBlind::random(&mut OsRng);
+            let value_blind = DrkValueBlind::random(&mut rng);
 
             let clear_input = PartialTransactionClearInput {
                 value: input.value,
@@ -81,11 +233,14 @@ impl TransactionBuilder {
                 value_blind,
                 token_blind,
                 signature_public,
+                metadata: input.metadata,
             };
             clear_inputs.push(clear_input);
         }
 
-        let mut inputs = vec![];
+        // Everything random is sampled here, in order, so the proofs can be
+        // made in any order with their own seeded RNG.
+        let mut burn_jobs = vec![];
         let mut input_blinds = vec![];
         let mut signature_secrets = vec![];
         for input in self.inputs {
@@ -93,66 +248,83 @@ impl TransactionBuilder {
             // This must be a completely new random value or the value_commit will be the same.
             input_blinds.push(input.note.value_blind);
 
-            let signature_secret = SecretKey::random(&mut OsRng);
-
-            let (proof, revealed) = create_burn_proof(
-                burn_pk,
-                input.note.value,
-                input.note.token_id,
-                input.note.value_blind,
-                token_blind,
-                input.note.serial,
-                input.note.coin_blind,
-                input.secret,
-                input.leaf_position,
-                input.merkle_path,
-                signature_secret,
-            )?;
-
             // First we make the tx then sign after
+            let signature_secret = SecretKey::random(&mut rng);
             signature_secrets.push(signature_secret);
 
-            let input = PartialTransactionInput { burn_proof: proof, revealed };
-            inputs.push(input);
+            burn_jobs.push((input, signature_secret, proof_rng(&mut rng)));
         }
 
-        let mut outputs = vec![];
+        let mut mint_jobs = vec![];
         let mut output_blinds = vec![];
-
         for (i, output) in self.outputs.iter().enumerate() {
             let value_blind = if i == self.outputs.len() - 1 {
                 Self::compute_remainder_blind(&clear_inputs, &input_blinds, &output_blinds)
             } else {
-                DrkValueBlind::random(&mut OsRng)
+                DrkValueBlind::random(&mut rng)
             };
             output_blinds.push(value_blind);
 
-            let serial = DrkSerial::random(&mut OsRng);
-            let coin_blind = DrkCoinBlind::random(&mut OsRng);
-
-            let (mint_proof, revealed) = create_mint_proof(
-                mint_pk,
-                output.value,
-                output.token_id,
-                value_blind,
-                token_blind,
-                serial,
-                coin_blind,
-                output.public,
-            )?;
-
-            // Encrypted note
-
             let note = Note {
-                serial,
+                serial: DrkSerial::random(&mut rng),
                 value: output.value,
                 token_id: output.token_id,
-                coin_blind,
+                coin_blind: DrkCoinBlind::random(&mut rng),
                 value_blind,
                 token_blind,
+                memo: output.memo,
+                metadata: output.metadata,
             };
 
-            let encrypted_note = note.encrypt(&output.public)?;
+            mint_jobs.push((output.public, note, proof_rng(&mut rng)));
+        }
+
+        let burn_proofs = prove_all(prover, burn_jobs, |(input, signature_secret, rng)| {
+            create_burn_proof(
+                burn_pk,
+                input.note.value,
+                input.note.token_id,
+                input.note.value_blind,
+                token_blind,
+                input.note.serial,
+                input.note.coin_blind,
+                input.secret,
+                input.leaf_position,
+                input.merkle_path,
+                signature_secret,
+                rng,
+            )
+        });
+
+        let notes: Vec<_> = mint_jobs.iter().map(|(public, note, _)| (*public, *note)).collect();
+        let mint_proofs = prove_all(prover, mint_jobs, |(public, note, rng)| {
+            create_mint_proof(
+                mint_pk,
+                note.value,
+                note.token_id,
+                note.value_blind,
+                note.token_blind,
+                note.serial,
+                note.coin_blind,
+                public,
+                rng,
+            )
+        });
+
+        let mut inputs = vec![];
+        for result in burn_proofs {
+            let (proof, revealed) = result?;
+            inputs.push(PartialTransactionInput { burn_proof: proof, revealed });
+        }
+
+        let mut outputs = vec![];
+        let mut disclosures = vec![];
+        for (result, (public, note)) in mint_proofs.into_iter().zip(notes) {
+            let (mint_proof, revealed) = result?;
+
+            // Encrypted note
+            let encrypted_note = note.encrypt_with_rng(&public, &mut rng)?;
+            disclosures.push(PaymentDisclosure::new(public, note));
 
             let output = TransactionOutput { mint_proof, revealed, enc_note: encrypted_note };
             outputs.push(output);
@@ -162,11 +334,12 @@ impl TransactionBuilder {
 
         let mut unsigned_tx_data = vec![];
         partial_tx.encode(&mut unsigned_tx_data)?;
+        let signed_data = signing::transfer_signing_payload(&unsigned_tx_data);
 
         let mut clear_inputs = vec![];
         for (input, info) in partial_tx.clear_inputs.into_iter().zip(self.clear_inputs) {
             let secret = info.signature_secret;
-            let signature = secret.sign(&unsigned_tx_data[..]);
+            let signature = secret.sign_with_rng(&mut rng, &signed_data[..]);
             let input = TransactionClearInput::from_partial(input, signature);
             clear_inputs.push(input);
         }
@@ -175,11 +348,123 @@ impl TransactionBuilder {
         for (input, signature_secret) in
             partial_tx.inputs.into_iter().zip(signature_secrets.into_iter())
         {
-            let signature = signature_secret.sign(&unsigned_tx_data[..]);
+            let signature = signature_secret.sign_with_rng(&mut rng, &signed_data[..]);
             let input = TransactionInput::from_partial(input, signature);
             inputs.push(input);
         }
 
-        Ok(Transaction { clear_inputs, inputs, outputs: partial_tx.outputs })
+        Ok((Transaction { clear_inputs, inputs, outputs: partial_tx.outputs }, disclosures))
+    }
+}
+
+/// Seed a separate RNG for a proof from the transaction's RNG.
+fn proof_rng(rng: &mut impl RngCore) -> StdRng {
+    let mut seed = [0u8; 32];
+    rng.fill_bytes(&mut seed);
+    StdRng::from_seed(seed)
+}
+
+/// Make the proofs on `prover` if there is one, or one by one otherwise.
+fn prove_all<J, T, F>(prover: Option<&ParallelProver>, jobs: Vec<J>, prove: F) -> Vec<T>
+where
+    J: Send,
+    T: Send,
+    F: Fn(J) -> T + Send + Sync,
+{
+    match prover {
+        Some(prover) => prover.prove(jobs, prove),
+        None => jobs.into_iter().map(prove).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{coin::Coin, nullifier::Nullifier};
+    use pasta_curves::pallas;
+
+    fn own_coin(
+        tree: &mut BridgeTree<MerkleNode, MERKLE_DEPTH>,
+        value: u64,
+        token_id: DrkTokenId,
+    ) -> OwnCoin {
+        let secret = SecretKey::random(&mut OsRng);
+        let note = Note {
+            serial: DrkSerial::random(&mut OsRng),
+            value,
+            token_id,
+            coin_blind: DrkCoinBlind::random(&mut OsRng),
+            value_blind: DrkValueBlind::random(&mut OsRng),
+            token_blind: DrkValueBlind::random(&mut OsRng),
+            memo: Memo::default(),
+            metadata: None,
+        };
+
+        let coin = Coin(pallas::Base::random(&mut OsRng));
+        tree.append(&MerkleNode(coin.0));
+        let leaf_position = tree.witness().unwrap();
+
+        OwnCoin {
+            coin,
+            note,
+            secret,
+            nullifier: Nullifier::new(secret, note.serial),
+            leaf_position,
+        }
+    }
+
+    #[test]
+    fn test_transfer() {
+        let token_id = DrkTokenId::from(1);
+        let mut tree = BridgeTree::<MerkleNode, MERKLE_DEPTH>::new(100);
+        let coins = vec![own_coin(&mut tree, 30, token_id), own_coin(&mut tree, 50, token_id)];
+
+        let change = PublicKey::from_secret(SecretKey::random(&mut OsRng));
+        let payee = PublicKey::from_secret(SecretKey::random(&mut OsRng));
+        let payment =
+            |value| TransactionBuilderPayment { public: payee, value, memo: Memo::default() };
+
+        let builder = TransactionBuilder::transfer(
+            token_id,
+            vec![payment(60)],
+            &coins,
+            CoinSelection::LargestFirst,
+            change,
+            &tree,
+        )
+        .unwrap();
+        assert_eq!(builder.inputs.len(), 2);
+        assert_eq!(builder.outputs.len(), 2);
+        assert_eq!(builder.outputs[0].value, 20);
+        assert_eq!(builder.outputs[0].public, change);
+        assert_eq!(builder.outputs[1].value, 60);
+        assert_eq!(builder.outputs[1].public, payee);
+
+        // No change output when the coins add up to the payment
+        let builder = TransactionBuilder::transfer(
+            token_id,
+            vec![payment(50)],
+            &coins,
+            CoinSelection::PrivacyPreferring,
+            change,
+            &tree,
+        )
+        .unwrap();
+        assert_eq!(builder.inputs.len(), 1);
+        assert_eq!(builder.outputs.len(), 1);
+
+        let transfer = |payments| {
+            TransactionBuilder::transfer(
+                token_id,
+                payments,
+                &coins,
+                CoinSelection::default(),
+                change,
+                &tree,
+            )
+        };
+        assert!(matches!(transfer(vec![payment(81)]), Err(ClientFailed::NotEnoughValue(80))));
+        assert!(matches!(transfer(vec![payment(0)]), Err(ClientFailed::InvalidAmount(0))));
+        assert!(matches!(transfer(vec![]), Err(ClientFailed::InvalidAmount(0))));
     }
 }
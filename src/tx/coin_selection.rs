@@ -0,0 +1,154 @@
+use std::str::FromStr;
+
+use crate::{
+    crypto::{types::DrkTokenId, OwnCoin},
+    Error,
+};
+
+/// How to pick the coins a transaction spends
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CoinSelection {
+    /// Biggest coins first, spending as few coins as possible
+    LargestFirst,
+    /// Smallest coins first, consolidating the dust in the wallet
+    SmallestFirst,
+    /// The smallest single coin covering the value where there is one, as
+    /// coins spent together are linked to each other by the transaction.
+    /// Falls back to largest first.
+    PrivacyPreferring,
+}
+
+impl Default for CoinSelection {
+    fn default() -> Self {
+        Self::LargestFirst
+    }
+}
+
+impl FromStr for CoinSelection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "largest-first" => Ok(Self::LargestFirst),
+            "smallest-first" => Ok(Self::SmallestFirst),
+            "privacy" => Ok(Self::PrivacyPreferring),
+            _ => Err(Error::ParseFailed("Unknown coin selection strategy")),
+        }
+    }
+}
+
+impl CoinSelection {
+    /// Pick coins of `token_id` adding up to at least `value`, or `None`
+    /// if there isn't enough of it.
+    pub fn select(
+        &self,
+        coins: &[OwnCoin],
+        token_id: DrkTokenId,
+        value: u64,
+    ) -> Option<Vec<OwnCoin>> {
+        let mut candidates: Vec<&OwnCoin> =
+            coins.iter().filter(|x| x.note.token_id == token_id).collect();
+
+        match self {
+            Self::LargestFirst => candidates.sort_by(|a, b| b.note.value.cmp(&a.note.value)),
+            Self::SmallestFirst => candidates.sort_by_key(|x| x.note.value),
+            Self::PrivacyPreferring => {
+                let single = candidates
+                    .iter()
+                    .filter(|x| x.note.value >= value)
+                    .min_by_key(|x| x.note.value);
+                if let Some(coin) = single {
+                    return Some(vec![**coin])
+                }
+                candidates.sort_by(|a, b| b.note.value.cmp(&a.note.value));
+            }
+        }
+
+        let mut selected = vec![];
+        let mut total = 0;
+        for coin in candidates {
+            if total >= value {
+                break
+            }
+            total += coin.note.value;
+            selected.push(*coin);
+        }
+
+        if total < value {
+            return None
+        }
+
+        Some(selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{
+        coin::Coin,
+        keypair::SecretKey,
+        note::{Memo, Note},
+        nullifier::Nullifier,
+        types::{DrkCoinBlind, DrkSerial, DrkValueBlind},
+    };
+    use group::ff::Field;
+    use pasta_curves::pallas;
+    use rand::rngs::OsRng;
+
+    fn dummy_coin(value: u64, token_id: DrkTokenId) -> OwnCoin {
+        let secret = SecretKey::random(&mut OsRng);
+        let note = Note {
+            serial: DrkSerial::random(&mut OsRng),
+            value,
+            token_id,
+            coin_blind: DrkCoinBlind::random(&mut OsRng),
+            value_blind: DrkValueBlind::random(&mut OsRng),
+            token_blind: DrkValueBlind::random(&mut OsRng),
+            memo: Memo::default(),
+            metadata: None,
+        };
+
+        OwnCoin {
+            coin: Coin(pallas::Base::random(&mut OsRng)),
+            note,
+            secret,
+            nullifier: Nullifier::new(secret, note.serial),
+            leaf_position: 0.into(),
+        }
+    }
+
+    fn values(coins: Option<Vec<OwnCoin>>) -> Vec<u64> {
+        coins.unwrap().iter().map(|x| x.note.value).collect()
+    }
+
+    #[test]
+    fn test_coin_selection() {
+        let token_id = DrkTokenId::from(1);
+        let other = DrkTokenId::from(2);
+        let coins = vec![
+            dummy_coin(30, token_id),
+            dummy_coin(5, token_id),
+            dummy_coin(1000, other),
+            dummy_coin(50, token_id),
+            dummy_coin(10, token_id),
+        ];
+
+        let largest = CoinSelection::LargestFirst;
+        assert_eq!(values(largest.select(&coins, token_id, 60)), vec![50, 30]);
+
+        let smallest = CoinSelection::SmallestFirst;
+        assert_eq!(values(smallest.select(&coins, token_id, 12)), vec![5, 10]);
+
+        let privacy = CoinSelection::PrivacyPreferring;
+        assert_eq!(values(privacy.select(&coins, token_id, 25)), vec![30]);
+        assert_eq!(values(privacy.select(&coins, token_id, 85)), vec![50, 30, 10]);
+
+        // Other tokens don't count
+        assert!(largest.select(&coins, token_id, 96).is_none());
+        assert!(privacy.select(&coins, other, 1001).is_none());
+
+        assert_eq!("privacy".parse::<CoinSelection>().unwrap(), privacy);
+        assert!("random".parse::<CoinSelection>().is_err());
+    }
+}
@@ -12,6 +12,7 @@ use crate::{
         proof::VerifyingKey,
         schnorr,
         schnorr::SchnorrPublic,
+        token_id::MetadataCommitment,
         types::{DrkTokenId, DrkValueBlind, DrkValueCommit},
         util::{mod_r_p, pedersen_commitment_scalar, pedersen_commitment_u64},
         BurnRevealedValues, MintRevealedValues, Proof,
@@ -22,7 +23,9 @@ use crate::{
 };
 
 pub mod builder;
+pub mod coin_selection;
 mod partial;
+pub mod signing;
 
 /// A DarkFi transaction
 #[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
@@ -48,6 +51,8 @@ pub struct TransactionClearInput {
     pub token_blind: DrkValueBlind,
     /// Public key for the signature
     pub signature_public: PublicKey,
+    /// Metadata of the unique asset the input issues, if it issues one
+    pub metadata: Option<MetadataCommitment>,
     /// Input's signature
     pub signature: schnorr::Signature,
 }
@@ -74,9 +79,66 @@ pub struct TransactionOutput {
     pub enc_note: EncryptedNote,
 }
 
+/// Failed checks of a verification. Verifying stops at the first failed
+/// check, while diagnostics run all of them and collect the failures, so
+/// both go through the same checks.
+pub(crate) struct VerifyChecks {
+    failed: Vec<VerifyFailed>,
+    fail_fast: bool,
+}
+
+impl VerifyChecks {
+    /// Stop at the first failed check
+    pub(crate) fn fail_fast() -> Self {
+        Self { failed: vec![], fail_fast: true }
+    }
+
+    /// Run all the checks and collect the failures
+    pub(crate) fn report() -> Self {
+        Self { failed: vec![], fail_fast: false }
+    }
+
+    /// Record a failed check. Returns it as an error when failing fast.
+    pub(crate) fn fail(&mut self, e: VerifyFailed) -> VerifyResult<()> {
+        if self.fail_fast {
+            return Err(e)
+        }
+
+        self.failed.push(e);
+        Ok(())
+    }
+
+    /// The failed checks, in order
+    pub(crate) fn into_failed(self) -> Vec<VerifyFailed> {
+        self.failed
+    }
+}
+
 impl Transaction {
-    /// Verify the transaction
+    /// Verify the transaction, stopping at the first failed check
     pub fn verify(&self, mint_vk: &VerifyingKey, burn_vk: &VerifyingKey) -> VerifyResult<()> {
+        self.run_checks(mint_vk, burn_vk, &mut VerifyChecks::fail_fast())
+    }
+
+    /// Verify the transaction, running every check instead of stopping
+    /// at the first failure. Returns all the failed checks, in order.
+    /// It's for diagnostics, use [`Self::verify`] to validate.
+    pub fn verify_all(&self, mint_vk: &VerifyingKey, burn_vk: &VerifyingKey) -> Vec<VerifyFailed> {
+        let mut checks = VerifyChecks::report();
+        // Only failing fast returns errors
+        let _ = self.run_checks(mint_vk, burn_vk, &mut checks);
+        checks.into_failed()
+    }
+
+    /// The checks of [`Self::verify`] and [`Self::verify_all`]. The cheap
+    /// ones come first, so invalid transactions are mostly rejected before
+    /// their proofs are verified.
+    pub(crate) fn run_checks(
+        &self,
+        mint_vk: &VerifyingKey,
+        burn_vk: &VerifyingKey,
+        checks: &mut VerifyChecks,
+    ) -> VerifyResult<()> {
         // Accumulator for the value commitments
         let mut valcom_total = DrkValueCommit::identity();
 
@@ -86,57 +148,79 @@ impl Transaction {
         }
 
         // Add values from the inputs
-        for (i, input) in self.inputs.iter().enumerate() {
-            match verify_burn_proof(burn_vk, &input.burn_proof, &input.revealed) {
-                Ok(()) => valcom_total += &input.revealed.value_commit,
-                Err(e) => {
-                    error!("tx::verify(): Failed to verify burn proof {}: {}", i, e);
-                    return Err(VerifyFailed::BurnProof(i))
-                }
-            }
+        for input in &self.inputs {
+            valcom_total += &input.revealed.value_commit;
         }
 
         // Subtract values from the outputs
-        for (i, output) in self.outputs.iter().enumerate() {
-            match verify_mint_proof(mint_vk, &output.mint_proof, &output.revealed) {
-                Ok(()) => valcom_total -= &output.revealed.value_commit,
-                Err(e) => {
-                    error!("tx::verify(): Failed to verify mint proof {}: {}", i, e);
-                    return Err(VerifyFailed::MintProof(i))
-                }
-            }
+        for output in &self.outputs {
+            valcom_total -= &output.revealed.value_commit;
         }
 
         // If the accumulator is not back in its initial state,
         // there's a value mismatch.
         if valcom_total != DrkValueCommit::identity() {
             error!("tx::verify(): Missing funds");
-            return Err(VerifyFailed::MissingFunds)
+            checks.fail(VerifyFailed::MissingFunds)?;
         }
 
         // Verify that the token commitments match
         if !self.verify_token_commitments() {
             error!("tx::verify(): Token ID mismatch");
-            return Err(VerifyFailed::TokenMismatch)
+            checks.fail(VerifyFailed::TokenMismatch)?;
         }
 
         // Verify the available signatures
         let mut unsigned_tx_data = vec![];
-        self.encode_without_signature(&mut unsigned_tx_data)?;
+        if let Err(e) = self.encode_without_signature(&mut unsigned_tx_data) {
+            return checks.fail(e.into())
+        }
+        let signed_data = signing::transfer_signing_payload(&unsigned_tx_data);
 
         for (i, input) in self.clear_inputs.iter().enumerate() {
             let public = &input.signature_public;
-            if !public.verify(&unsigned_tx_data[..], &input.signature) {
+            if !public.verify(&signed_data[..], &input.signature) {
                 error!("tx::verify(): Failed to verify Clear Input signature {}", i);
-                return Err(VerifyFailed::ClearInputSignature(i))
+                checks.fail(VerifyFailed::ClearInputSignature(i))?;
             }
         }
 
         for (i, input) in self.inputs.iter().enumerate() {
             let public = &input.revealed.signature_public;
-            if !public.verify(&unsigned_tx_data[..], &input.signature) {
+            if !public.verify(&signed_data[..], &input.signature) {
                 error!("tx::verify(): Failed to verify Input signature {}", i);
-                return Err(VerifyFailed::InputSignature(i))
+                checks.fail(VerifyFailed::InputSignature(i))?;
+            }
+        }
+
+        // The proofs are the most expensive to verify, so they come last.
+        // They're verified in one batch, and only one by one to find the
+        // invalid ones when the batch fails.
+        let burn_publics: Vec<_> = self.inputs.iter().map(|x| x.revealed.make_outputs()).collect();
+        let mint_publics: Vec<_> = self.outputs.iter().map(|x| x.revealed.make_outputs()).collect();
+
+        let mut batch = vec![];
+        for (input, publics) in self.inputs.iter().zip(&burn_publics) {
+            batch.push((burn_vk, &input.burn_proof, &publics[..]));
+        }
+        for (output, publics) in self.outputs.iter().zip(&mint_publics) {
+            batch.push((mint_vk, &output.mint_proof, &publics[..]));
+        }
+        if Proof::verify_batch(&batch).is_ok() {
+            return Ok(())
+        }
+
+        for (i, input) in self.inputs.iter().enumerate() {
+            if let Err(e) = verify_burn_proof(burn_vk, &input.burn_proof, &input.revealed) {
+                error!("tx::verify(): Failed to verify burn proof {}: {}", i, e);
+                checks.fail(VerifyFailed::BurnProof(i))?;
+            }
+        }
+
+        for (i, output) in self.outputs.iter().enumerate() {
+            if let Err(e) = verify_mint_proof(mint_vk, &output.mint_proof, &output.revealed) {
+                error!("tx::verify(): Failed to verify mint proof {}: {}", i, e);
+                checks.fail(VerifyFailed::MintProof(i))?;
             }
         }
 
@@ -152,8 +236,22 @@ impl Transaction {
     }
 
     fn verify_token_commitments(&self) -> bool {
-        assert_ne!(self.outputs.len(), 0);
-        let token_commit_value = self.outputs[0].revealed.token_commit;
+        let clear_commits = self
+            .clear_inputs
+            .iter()
+            .map(|input| pedersen_commitment_scalar(mod_r_p(input.token_id), input.token_blind));
+        let token_commit_value = match self
+            .outputs
+            .iter()
+            .map(|output| output.revealed.token_commit)
+            .chain(self.inputs.iter().map(|input| input.revealed.token_commit))
+            .chain(clear_commits)
+            .next()
+        {
+            Some(v) => v,
+            // Nothing to mismatch
+            None => return true,
+        };
 
         let mut failed =
             self.inputs.iter().any(|input| input.revealed.token_commit != token_commit_value);
@@ -181,6 +279,7 @@ impl TransactionClearInput {
             value_blind: partial.value_blind,
             token_blind: partial.token_blind,
             signature_public: partial.signature_public,
+            metadata: partial.metadata,
             signature,
         }
     }
@@ -191,7 +290,8 @@ impl TransactionClearInput {
         len += self.token_id.encode(&mut s)?;
         len += self.value_blind.encode(&mut s)?;
         len += self.token_blind.encode(&mut s)?;
-        len += self.signature_public.encode(s)?;
+        len += self.signature_public.encode(&mut s)?;
+        len += self.metadata.encode(s)?;
         Ok(len)
     }
 }
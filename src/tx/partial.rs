@@ -4,6 +4,7 @@ use super::TransactionOutput;
 use crate::{
     crypto::{
         keypair::PublicKey,
+        token_id::MetadataCommitment,
         types::{DrkTokenId, DrkValueBlind},
         BurnRevealedValues, Proof,
     },
@@ -26,6 +27,7 @@ pub struct PartialTransactionClearInput {
     pub value_blind: DrkValueBlind,
     pub token_blind: DrkValueBlind,
     pub signature_public: PublicKey,
+    pub metadata: Option<MetadataCommitment>,
 }
 
 #[derive(SerialEncodable, SerialDecodable)]
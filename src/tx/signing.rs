@@ -0,0 +1,76 @@
+//! Payloads signed by the inputs of a contract call. Every contract signs
+//! and verifies the same format, so a signature made for one contract,
+//! function or call can't be passed off as one for another.
+use crate::util::serial::{serialize, SerialEncodable};
+
+/// Domain tag of the payloads signed by transaction inputs
+pub const SIGNATURE_DOMAIN: &str = "DarkFi_TxCallSignature";
+
+/// Contract of the native token transfers
+pub const MONEY_CONTRACT_ID: &str = "money";
+/// Function of the money contract moving coins between owners
+pub const MONEY_TRANSFER_FUNC_ID: &str = "transfer";
+
+#[derive(SerialEncodable)]
+struct SigningPayload {
+    domain: String,
+    contract_id: String,
+    func_id: String,
+    call_index: u32,
+    call_data_digest: [u8; 32],
+    tx_digest: [u8; 32],
+}
+
+/// What the inputs of the call at `call_index` sign: the domain tag, the
+/// contract and function IDs, the call index, and the digests of the call
+/// data and of the whole unsigned transaction. The transaction digest
+/// covers all the calls in order, so calls can't be taken out of a
+/// transaction or reordered.
+pub fn signing_payload(
+    contract_id: &str,
+    func_id: &str,
+    call_index: u32,
+    call_data: &[u8],
+    tx_data: &[u8],
+) -> Vec<u8> {
+    serialize(&SigningPayload {
+        domain: SIGNATURE_DOMAIN.to_string(),
+        contract_id: contract_id.to_string(),
+        func_id: func_id.to_string(),
+        call_index,
+        call_data_digest: *blake3::hash(call_data).as_bytes(),
+        tx_digest: *blake3::hash(tx_data).as_bytes(),
+    })
+}
+
+/// Payload signed by the inputs of a money transfer, which is the single
+/// call of its transaction
+pub(crate) fn transfer_signing_payload(unsigned_tx_data: &[u8]) -> Vec<u8> {
+    signing_payload(
+        MONEY_CONTRACT_ID,
+        MONEY_TRANSFER_FUNC_ID,
+        0,
+        unsigned_tx_data,
+        unsigned_tx_data,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn domain_separation() {
+        let payload = signing_payload("dao", "vote", 0, b"call", b"tx");
+        assert_eq!(payload, signing_payload("dao", "vote", 0, b"call", b"tx"));
+
+        assert_ne!(payload, signing_payload("money", "vote", 0, b"call", b"tx"));
+        assert_ne!(payload, signing_payload("dao", "propose", 0, b"call", b"tx"));
+        assert_ne!(payload, signing_payload("dao", "vote", 1, b"call", b"tx"));
+        assert_ne!(payload, signing_payload("dao", "vote", 0, b"other", b"tx"));
+        assert_ne!(payload, signing_payload("dao", "vote", 0, b"call", b"other"));
+
+        // The raw transaction is never what gets signed
+        assert_ne!(transfer_signing_payload(b"tx"), b"tx".to_vec());
+    }
+}
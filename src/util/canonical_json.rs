@@ -0,0 +1,160 @@
+//! Canonical JSON encoding, for hashing and signing JSON payloads. The
+//! same value always encodes to the same bytes, whatever the key order
+//! or formatting it was received with, so a signature over a payload can
+//! be checked after it went through another JSON implementation.
+//!
+//! The encoding follows the JSON Canonicalization Scheme (RFC 8785):
+//! no whitespace, object keys sorted by their UTF-16 code units, strings
+//! escaped as little as possible, and floats written the way ECMAScript
+//! does. Integers are the exception, they're written out in full rather
+//! than as doubles, so amounts above 2^53 don't lose precision.
+use serde_json::{Number, Value};
+
+/// Encode `value` canonically
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value);
+    out
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(v) => out.push_str(if *v { "true" } else { "false" }),
+        Value::Number(v) => write_number(out, v),
+        Value::String(v) => write_string(out, v),
+        Value::Array(v) => {
+            out.push('[');
+            for (i, item) in v.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, item);
+            }
+            out.push(']');
+        }
+        Value::Object(v) => {
+            let mut keys: Vec<&String> = v.keys().collect();
+            keys.sort_by(|a, b| a.encode_utf16().cmp(b.encode_utf16()));
+
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(out, key);
+                out.push(':');
+                write_value(out, &v[key]);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{08}' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\u{0c}' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_number(out: &mut String, n: &Number) {
+    if let Some(v) = n.as_u64() {
+        return out.push_str(&v.to_string())
+    }
+    if let Some(v) = n.as_i64() {
+        return out.push_str(&v.to_string())
+    }
+
+    // serde_json doesn't hold NaN or infinities
+    let v = n.as_f64().unwrap();
+    if v == 0.0 {
+        return out.push('0')
+    }
+    if v < 0.0 {
+        out.push('-');
+    }
+
+    // Shortest digits that read back as the same double, and the
+    // exponent of the first one
+    let sci = format!("{:e}", v.abs());
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    let n = exp.parse::<i32>().unwrap() + 1;
+
+    if k <= n && n <= 21 {
+        out.push_str(&digits);
+        out.push_str(&"0".repeat((n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        out.push_str(&digits[..n as usize]);
+        out.push('.');
+        out.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        out.push_str("0.");
+        out.push_str(&"0".repeat(-n as usize));
+        out.push_str(&digits);
+    } else {
+        out.push_str(&digits[..1]);
+        if k > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        out.push_str(&format!("e{}{}", if n > 0 { "+" } else { "-" }, (n - 1).abs()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn canonical_encoding() {
+        let a: Value =
+            serde_json::from_str(r#"{"b": [1, 2.50, "x"], "a": {"d": null, "c": true}}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"a":{"c":true,"d":null},"b":[1,2.5,"x"]}"#).unwrap();
+        assert_eq!(canonical_json(&a), r#"{"a":{"c":true,"d":null},"b":[1,2.5,"x"]}"#);
+        assert_eq!(canonical_json(&a), canonical_json(&b));
+
+        // Keys sort by UTF-16 code units, so U+1F600 comes before U+FB33
+        let keys = json!({"\u{fb33}": 1, "\u{1f600}": 2, "b": 3, "a\u{0}": 4, "": 5});
+        let sorted = "{\"\":5,\"a\\u0000\":4,\"b\":3,\"\u{1f600}\":2,\"\u{fb33}\":1}";
+        assert_eq!(canonical_json(&keys), sorted);
+
+        let s = json!("\"\\/\u{08}\u{0c}\n\r\t\u{1f}\u{7f}é");
+        assert_eq!(canonical_json(&s), "\"\\\"\\\\/\\b\\f\\n\\r\\t\\u001f\u{7f}é\"");
+
+        // Integers are kept whole, floats are written like ECMAScript does
+        let numbers = json!([
+            u64::MAX,
+            i64::MIN,
+            -0.0,
+            1.0,
+            -1.5,
+            1e21,
+            1e20,
+            123.456,
+            0.000001,
+            1e-7,
+            5e-324,
+            1.7976931348623157e308
+        ]);
+        assert_eq!(
+            canonical_json(&numbers),
+            "[18446744073709551615,-9223372036854775808,0,1,-1.5,1e+21,100000000000000000000,\
+             123.456,0.000001,1e-7,5e-324,1.7976931348623157e+308]"
+        );
+    }
+}
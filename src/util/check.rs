@@ -0,0 +1,263 @@
+//! Startup self-test of the daemons, run with `--check`. A daemon checks
+//! its configuration, databases, keys and ports without starting, then
+//! prints a JSON report on stdout and exits with an error if any check
+//! failed, so deployment pipelines can catch a misconfiguration before
+//! the daemon is restarted. Databases are never written to, and the ones
+//! a running instance holds are left alone.
+use std::{
+    fs,
+    io::ErrorKind,
+    net::{TcpListener, TcpStream},
+    path::Path,
+    time::Duration,
+};
+
+use serde::Serialize;
+use serde_json::json;
+use url::Url;
+
+use super::lock::lock_owner;
+#[cfg(feature = "net")]
+use crate::net::settings::SettingsOpt;
+use crate::{Error, Result};
+
+/// Seconds to wait for an upstream to accept a connection
+pub const UPSTREAM_TIMEOUT: u64 = 5;
+
+/// Outcome of a check
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+    /// Couldn't be run, e.g. on a database a running instance holds
+    Skipped,
+}
+
+/// A check, and what it found or why it failed
+#[derive(Clone, Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// The checks run by a daemon, in the order they ran
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    pub checks: Vec<Check>,
+}
+
+impl CheckReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the outcome of a check, with what it found on success
+    pub fn add(&mut self, name: &str, result: Result<String>) {
+        match result {
+            Ok(v) => self.push(name, CheckStatus::Ok, v),
+            Err(e) => self.push(name, CheckStatus::Failed, e.to_string()),
+        }
+    }
+
+    /// Record a failed check
+    pub fn fail(&mut self, name: &str, reason: String) {
+        self.push(name, CheckStatus::Failed, reason)
+    }
+
+    /// Record a check that couldn't be run
+    pub fn skip(&mut self, name: &str, reason: String) {
+        self.push(name, CheckStatus::Skipped, reason)
+    }
+
+    /// Number of failed checks
+    pub fn failures(&self) -> usize {
+        self.checks.iter().filter(|c| c.status == CheckStatus::Failed).count()
+    }
+
+    /// Check a listener can be set up on the address of `url`. When an
+    /// instance of the daemon is `running`, it holds its own addresses,
+    /// so the ones in use are skipped.
+    pub fn check_listen(&mut self, name: &str, url: &Url, running: bool) {
+        let addrs = match url.socket_addrs(|| None) {
+            Ok(v) => v,
+            Err(e) => return self.fail(name, format!("Can't resolve {}: {}", url, e)),
+        };
+
+        match TcpListener::bind(&*addrs) {
+            Ok(_) => self.push(name, CheckStatus::Ok, format!("{} can be bound", url)),
+            Err(e) if running && e.kind() == ErrorKind::AddrInUse => {
+                self.skip(name, format!("{} is in use, by the running instance presumably", url))
+            }
+            Err(e) => self.fail(name, format!("Can't bind {}: {}", url, e)),
+        }
+    }
+
+    /// Check the host of `url` accepts TCP connections.
+    pub fn check_upstream(&mut self, name: &str, url: &Url) {
+        let addrs = match url.socket_addrs(|| None) {
+            Ok(v) => v,
+            Err(e) => return self.fail(name, format!("Can't resolve {}: {}", url, e)),
+        };
+
+        let timeout = Duration::from_secs(UPSTREAM_TIMEOUT);
+        match addrs.iter().find(|a| TcpStream::connect_timeout(a, timeout).is_ok()) {
+            Some(addr) => self.push(name, CheckStatus::Ok, format!("{} accepts connections", addr)),
+            None => self.fail(name, format!("{} can't be reached", url)),
+        }
+    }
+
+    /// Check each upstream with [`Self::check_upstream`], by the name of
+    /// its setting. With a SOCKS5 `proxy`, the proxy is checked instead,
+    /// and the upstreams dialed through it skipped.
+    pub fn check_upstreams(&mut self, upstreams: &[(&str, &Url)], proxy: Option<&Url>) {
+        if let Some(proxy) = proxy {
+            self.check_upstream("socks5_proxy", proxy);
+        }
+
+        for (name, url) in upstreams {
+            match proxy {
+                Some(_) => self.skip(name, format!("{} is dialed through the SOCKS5 proxy", url)),
+                None => self.check_upstream(name, url),
+            }
+        }
+    }
+
+    /// Check the P2P accept address in `settings` can be bound, and with
+    /// `upstreams`, that the seeds and peers can be reached.
+    #[cfg(feature = "net")]
+    pub fn check_net(&mut self, settings: &SettingsOpt, running: bool, upstreams: bool) {
+        if let Some(url) = &settings.inbound {
+            self.check_listen("accept", url, running);
+        }
+
+        if upstreams {
+            let mut upstreams: Vec<(&str, &Url)> = vec![];
+            upstreams.extend(settings.seeds.iter().map(|u| ("seeds", u)));
+            upstreams.extend(settings.peers.iter().map(|u| ("peers", u)));
+            self.check_upstreams(&upstreams, settings.socks5_proxy.as_ref());
+        }
+    }
+
+    /// Check the file or directory at `path` exists and can be read.
+    pub fn check_path(&mut self, name: &str, path: &Path) {
+        let result = match fs::metadata(path) {
+            Ok(m) if m.is_dir() => fs::read_dir(path).map(|_| "Directory is readable"),
+            Ok(_) => fs::File::open(path).map(|_| "File is readable"),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(v) => self.push(name, CheckStatus::Ok, format!("{}: {}", v, path.display())),
+            Err(e) => self.fail(name, format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    /// Whether the database at `path` is held by a running process, in
+    /// which case its check is recorded as skipped.
+    pub fn skip_locked(&mut self, name: &str, path: &Path) -> bool {
+        match lock_owner(path) {
+            Ok(None) => false,
+            Ok(Some(pid)) => {
+                self.skip(name, format!("{} is in use by PID {}", path.display(), pid));
+                true
+            }
+            Err(e) => {
+                self.add(name, Err(e));
+                true
+            }
+        }
+    }
+
+    /// Check the SQLite wallet at `path` can be opened with `pass`, unless
+    /// a running process holds it. A missing wallet is created on start.
+    #[cfg(feature = "wallet")]
+    pub async fn check_wallet(&mut self, name: &str, path: &Path, pass: &str) {
+        if self.skip_locked(name, path) {
+            return
+        }
+
+        if !path.exists() {
+            return self.push(name, CheckStatus::Ok, format!("Not created yet: {}", path.display()))
+        }
+
+        let tables = crate::wallet::walletdb::check_wallet(path, pass).await;
+        let path = path.display();
+        match tables {
+            Ok(n) => self.push(name, CheckStatus::Ok, format!("{} tables in {}", n, path)),
+            Err(e) => self.fail(name, format!("{}: {}", path, e)),
+        }
+    }
+
+    /// Check the chain in the sled database at `path` starts at the
+    /// `genesis` block, unless a running process holds the database.
+    /// sled can't open a database read-only, but nothing is written to it.
+    #[cfg(feature = "blockchain")]
+    pub fn check_chain_db(&mut self, name: &str, path: &Path, genesis: Option<blake3::Hash>) {
+        if self.skip_locked(name, path) {
+            return
+        }
+
+        if !path.exists() {
+            return self.push(name, CheckStatus::Ok, format!("Not created yet: {}", path.display()))
+        }
+
+        let stored = sled::open(path)
+            .map_err(Error::from)
+            .and_then(|db| crate::blockchain::BlockOrderStore::stored_genesis(&db));
+        let path = path.display();
+        match stored {
+            Ok(Some(stored)) if genesis.is_some() && genesis != Some(stored) => {
+                self.fail(name, format!("Chain in {} doesn't start at the genesis", path))
+            }
+            Ok(Some(stored)) => {
+                self.push(name, CheckStatus::Ok, format!("Chain in {} starts at {}", path, stored))
+            }
+            Ok(None) => self.push(name, CheckStatus::Ok, format!("No chain in {} yet", path)),
+            Err(e) => self.fail(name, format!("{}: {}", path, e)),
+        }
+    }
+
+    /// Print the report as JSON on stdout. Fails if any check did, so the
+    /// daemon exits with an error.
+    pub fn finish(&self) -> Result<()> {
+        let failures = self.failures();
+        let report = json!({ "ok": failures == 0, "checks": self.checks });
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+
+        if failures > 0 {
+            return Err(Error::SelfCheckFailed(failures))
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, name: &str, status: CheckStatus, detail: String) {
+        self.checks.push(Check { name: name.to_string(), status, detail })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_report() {
+        let mut report = CheckReport::new();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("tcp://{}", listener.local_addr().unwrap())).unwrap();
+        report.check_listen("taken", &url, true);
+        report.check_upstream("upstream", &url);
+        report.check_path("tmp", &std::env::temp_dir());
+        assert_eq!(report.failures(), 0);
+        assert_eq!(report.checks[0].status, CheckStatus::Skipped);
+        assert_eq!(report.checks[1].status, CheckStatus::Ok);
+
+        report.check_listen("taken", &url, false);
+        report.check_path("missing", Path::new("/nonexistent/darkfi"));
+        assert_eq!(report.failures(), 2);
+        assert!(matches!(report.finish(), Err(Error::SelfCheckFailed(2))));
+    }
+}
@@ -1,11 +1,13 @@
 use std::{
     env, fs,
-    io::Write,
+    io::{self, Write},
     marker::PhantomData,
     path::{Path, PathBuf},
     str,
 };
 
+use clap::CommandFactory;
+pub use clap_complete::Shell;
 use serde::{de::DeserializeOwned, Serialize};
 use simplelog::ConfigBuilder;
 
@@ -79,6 +81,44 @@ pub fn get_log_config() -> simplelog::Config {
     }
 }
 
+/// Print the completion script of the CLI `C` for `shell`, e.g. for
+/// `drk completions bash > /usr/share/bash-completion/completions/drk`
+pub fn print_completions<C: CommandFactory>(shell: Shell) {
+    let mut cmd = C::command();
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, &mut io::stdout());
+}
+
+/// Ask a yes/no question on the terminal, defaulting to no. With
+/// `assume_yes`, e.g. from a `--yes` flag in scripts, don't ask.
+/// The question goes to stderr, so it doesn't mix with piped output.
+pub fn confirm(question: &str, assume_yes: bool) -> Result<bool> {
+    if assume_yes {
+        return Ok(true)
+    }
+
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    // Nobody to answer, e.g. stdin is closed
+    if io::stdin().read_line(&mut answer)? == 0 {
+        eprintln!();
+        return Ok(false)
+    }
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Ask before overwriting the file at `path`, if there is one
+pub fn confirm_overwrite(path: &Path, assume_yes: bool) -> Result<bool> {
+    if !path.exists() {
+        return Ok(true)
+    }
+
+    confirm(&format!("{} already exists. Overwrite it?", path.display()), assume_yes)
+}
+
 pub const ANSI_LOGO: &str = include_str!("../../contrib/darkfi.ansi");
 
 #[macro_export]
@@ -122,6 +162,7 @@ macro_rules! cli_desc {
 /// use darkfi::{
 ///     async_daemonize, cli_desc,
 ///     util::{
+///         check::CheckReport,
 ///         cli::{get_log_config, get_log_level, spawn_config},
 ///         path::get_config_path,
 ///     },
@@ -139,6 +180,10 @@ macro_rules! cli_desc {
 ///     /// Configuration file to use
 ///     config: Option<String>,
 ///
+///     #[structopt(long)]
+///     /// Check the configuration and environment, print a report and exit
+///     check: bool,
+///
 ///     #[structopt(short, parse(from_occurrences))]
 ///     /// Increase verbosity (-vvv supported)
 ///     verbose: u8,
@@ -146,53 +191,125 @@ macro_rules! cli_desc {
 ///
 /// async_daemonize!(realmain);
 /// async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
+///     if args.check {
+///         return CheckReport::new().finish()
+///     }
+///
 ///     println!("Hello, world!");
 ///     Ok(())
 /// }
 /// ```
+///
+/// With `--check`, no default configuration file is written, and one that
+/// fails to parse is reported as a failed `config` check, see
+/// [`check`](crate::util::check).
+///
+/// Daemons that should be manageable without a service manager pass a
+/// function returning the path of their PID file, see
+/// [`service`](crate::util::service). `Args` then also needs these fields:
+/// ```text
+/// #[structopt(long)]
+/// /// Detach from the terminal and run in the background
+/// daemon: bool,
+///
+/// #[structopt(parse(try_from_str))]
+/// /// Manage the running instance instead: status or stop
+/// service: Option<ServiceCommand>,
+/// ```
+///
+/// With `--check`, the PID file is left alone, so the running instance
+/// can be checked against.
+///
+/// ```text
+/// fn pid_file(args: &Args) -> Result<PathBuf> {
+///     Ok(expand_path(&args.datastore)?.join("daemond.pid"))
+/// }
+///
+/// async_daemonize!(realmain, pid_file);
+/// ```
 #[macro_export]
 macro_rules! async_daemonize {
     ($realmain:ident) => {
         fn main() -> Result<()> {
-            let args = Args::from_args_with_toml("").unwrap();
-            let cfg_path = get_config_path(args.config, CONFIG_FILE)?;
-            spawn_config(&cfg_path, CONFIG_FILE_CONTENTS.as_bytes())?;
-            let args = Args::from_args_with_toml(&std::fs::read_to_string(cfg_path)?).unwrap();
+            let args = $crate::async_daemonize!(@args);
+            $crate::async_daemonize!(@run, $realmain, args)
+        }
+    };
+    ($realmain:ident, $pid_file:ident) => {
+        fn main() -> Result<()> {
+            let args = $crate::async_daemonize!(@args);
+            let pid_path = $pid_file(&args)?;
+            if let Some(command) = args.service {
+                return darkfi::util::service::run_command(command, &pid_path)
+            }
 
-            let log_level = get_log_level(args.verbose.into());
-            let log_config = get_log_config();
+            if args.check {
+                return $crate::async_daemonize!(@run, $realmain, args)
+            }
 
-            let env_log_file_path = match std::env::var("DARKFI_LOG") {
-                Ok(p) => std::fs::File::create(p).unwrap(),
-                Err(_) => std::fs::File::create("/tmp/darkfi.log").unwrap(),
+            // Held until we exit, so no other instance opens the same data
+            let _pid_file = darkfi::util::service::start_service(&pid_path, args.daemon)?;
+            $crate::async_daemonize!(@run, $realmain, args)
+        }
+    };
+    (@args) => {{
+        let args = Args::from_args_with_toml("").unwrap();
+        let cfg_path = get_config_path(args.config, CONFIG_FILE)?;
+        if args.check {
+            // Nothing is written with --check, and a config the daemon
+            // wouldn't start with ends up in the report
+            let parsed = match std::fs::read_to_string(&cfg_path) {
+                Ok(contents) => Args::from_args_with_toml(&contents).map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
             };
 
-            simplelog::CombinedLogger::init(vec![
-                simplelog::TermLogger::new(
-                    log_level,
-                    log_config.clone(),
-                    simplelog::TerminalMode::Mixed,
-                    simplelog::ColorChoice::Auto,
-                ),
-                simplelog::WriteLogger::new(log_level, log_config, env_log_file_path),
-            ])?;
-
-            // https://docs.rs/smol/latest/smol/struct.Executor.html#examples
-            let ex = Arc::new(async_executor::Executor::new());
-            let (signal, shutdown) = async_channel::unbounded::<()>();
-            let (_, result) = easy_parallel::Parallel::new()
-                // Run four executor threads
-                .each(0..4, |_| future::block_on(ex.run(shutdown.recv())))
-                // Run the main future on the current thread.
-                .finish(|| {
-                    future::block_on(async {
-                        $realmain(args, ex.clone()).await?;
-                        drop(signal);
-                        Ok::<(), darkfi::Error>(())
-                    })
-                });
-
-            result
+            match parsed {
+                Ok(v) => v,
+                Err(e) => {
+                    let mut report = $crate::util::check::CheckReport::new();
+                    report.fail("config", format!("{}: {}", cfg_path.display(), e));
+                    return report.finish()
+                }
+            }
+        } else {
+            spawn_config(&cfg_path, CONFIG_FILE_CONTENTS.as_bytes())?;
+            Args::from_args_with_toml(&std::fs::read_to_string(cfg_path)?).unwrap()
         }
-    };
+    }};
+    (@run, $realmain:ident, $args:ident) => {{
+        let log_level = get_log_level($args.verbose.into());
+        let log_config = get_log_config();
+
+        let env_log_file_path = match std::env::var("DARKFI_LOG") {
+            Ok(p) => std::fs::File::create(p).unwrap(),
+            Err(_) => std::fs::File::create("/tmp/darkfi.log").unwrap(),
+        };
+
+        simplelog::CombinedLogger::init(vec![
+            simplelog::TermLogger::new(
+                log_level,
+                log_config.clone(),
+                simplelog::TerminalMode::Mixed,
+                simplelog::ColorChoice::Auto,
+            ),
+            simplelog::WriteLogger::new(log_level, log_config, env_log_file_path),
+        ])?;
+
+        // https://docs.rs/smol/latest/smol/struct.Executor.html#examples
+        let ex = Arc::new(async_executor::Executor::new());
+        let (signal, shutdown) = async_channel::unbounded::<()>();
+        let (_, result) = easy_parallel::Parallel::new()
+            // Run four executor threads
+            .each(0..4, |_| future::block_on(ex.run(shutdown.recv())))
+            // Run the main future on the current thread.
+            .finish(|| {
+                future::block_on(async {
+                    $realmain($args, ex.clone()).await?;
+                    drop(signal);
+                    Ok::<(), darkfi::Error>(())
+                })
+            });
+
+        result
+    }};
 }
@@ -0,0 +1,311 @@
+use std::{
+    mem,
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, warn};
+use rand::seq::SliceRandom;
+use serde_json::json;
+use url::Url;
+
+use super::{async_util::sleep, time::Timestamp};
+use crate::{
+    rpc::{client::RpcClient, jsonrpc::JsonRequest},
+    Error, Result,
+};
+
+// Clock sync parameters
+const RETRIES: u8 = 10;
+const NTP_ADDRESS: &str = "pool.ntp.org:123";
+const EPOCH: i64 = 2208988800; //1900
+
+// Seconds to wait for a peer's clock before leaving it out of the median
+const PEER_TIMEOUT: u64 = 10;
+
+/// Default seconds of drift from the peers past which a warning is logged
+pub const DRIFT_WARN_SECONDS: u64 = 2;
+/// Default seconds of drift from the peers past which blocks aren't produced
+pub const DRIFT_MAX_SECONDS: u64 = 5;
+
+// JsonRPC request to a network peer(randomly selected),
+// to retrieve their current system clock.
+async fn peer_request(peers: &Vec<Url>, proxy: &Option<Url>) -> Result<Option<Timestamp>> {
+    // Select peer, None if vector is empty
+    let peer = peers.choose(&mut rand::thread_rng());
+    match peer {
+        None => Ok(None),
+        Some(p) => {
+            // Create rpc client
+            let rpc_client = RpcClient::with_proxy(p.clone(), proxy.clone()).await?;
+
+            // Execute request
+            let req = JsonRequest::new("clock", json!([]));
+            let rep = rpc_client.oneshot_request(req).await?;
+
+            // Parse response
+            let timestamp: Timestamp = serde_json::from_value(rep)?;
+
+            Ok(Some(timestamp))
+        }
+    }
+}
+
+// Raw ntp request execution
+async fn ntp_request() -> Result<Timestamp> {
+    // Create socket
+    let sock = UdpSocket::bind("0.0.0.0:0")?;
+    sock.set_read_timeout(Some(Duration::from_secs(5)))?;
+    sock.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+    // Execute request
+    let mut packet = [0u8; 48];
+    packet[0] = (3 << 6) | (4 << 3) | 3;
+    sock.send_to(&packet, NTP_ADDRESS)?;
+
+    // Parse response
+    sock.recv(&mut packet[..])?;
+    let (bytes, _) = packet[40..44].split_at(mem::size_of::<u32>());
+    let num = u32::from_be_bytes(bytes.try_into().unwrap());
+    let timestamp = Timestamp(num as i64 - EPOCH);
+
+    Ok(timestamp)
+}
+
+// This is a very simple check to verify that system time is correct.
+// Retry loop is used to in case discrepancies are found.
+// If all retries fail, system clock is considered invalid.
+// With a proxy, peers are asked through it, and the NTP check, which can't
+// go through a proxy, is skipped so it doesn't leak our address.
+pub async fn check_clock(peers: Vec<Url>, proxy: Option<Url>) -> Result<()> {
+    debug!("System clock check started...");
+    let mut r = 0;
+    while r < RETRIES {
+        if let Err(e) = clock_check(&peers, &proxy).await {
+            debug!("Error during clock check: {:#?}", e);
+            r += 1;
+            continue
+        };
+        break
+    }
+
+    debug!("System clock check finished. Retries: {:#?}", r);
+    match r {
+        RETRIES => Err(Error::InvalidClock),
+        _ => Ok(()),
+    }
+}
+
+async fn clock_check(peers: &Vec<Url>, proxy: &Option<Url>) -> Result<()> {
+    // Start elapsed time counter to cover for all requests and processing time
+    let requests_start = Timestamp::current_time();
+    // Poll one of peers for their current UTC timestamp
+    let peer_time = peer_request(peers, proxy).await?;
+
+    if proxy.is_some() {
+        let system_time = Timestamp::current_time();
+        let peer_time = match peer_time {
+            Some(mut p) => {
+                p.add(requests_start.elapsed() as i64);
+                p
+            }
+            None => return Ok(()),
+        };
+
+        debug!("peer_time: {:#?}", peer_time);
+        debug!("system_time: {:#?}", system_time);
+        return match system_time == peer_time {
+            true => Ok(()),
+            false => Err(Error::InvalidClock),
+        }
+    }
+
+    // Start elapsed time counter to cover for ntp request and processing time
+    let ntp_request_start = Timestamp::current_time();
+    // Poll ntp.org for current timestamp
+    let mut ntp_time = ntp_request().await?;
+
+    // Stop elapsed time counters
+    let ntp_elapsed_time = ntp_request_start.elapsed() as i64;
+    let requests_elapsed_time = requests_start.elapsed() as i64;
+
+    // Current system time
+    let system_time = Timestamp::current_time();
+
+    // Add elapsed time to respone times
+    ntp_time.add(ntp_elapsed_time);
+    let peer_time = match peer_time {
+        None => None,
+        Some(p) => {
+            let mut t = p;
+            t.add(requests_elapsed_time);
+            Some(t)
+        }
+    };
+
+    debug!("peer_time: {:#?}", peer_time);
+    debug!("ntp_time: {:#?}", ntp_time);
+    debug!("system_time: {:#?}", system_time);
+
+    // We verify that system time is equal to peer(if exists) and ntp times
+    let check = match peer_time {
+        Some(p) => (system_time == p) && (system_time == ntp_time),
+        None => system_time == ntp_time,
+    };
+    match check {
+        true => Ok(()),
+        false => Err(Error::InvalidClock),
+    }
+}
+
+/// Atomic pointer to the clock drift measurement.
+pub type ClockDriftPtr = Arc<ClockDrift>;
+
+/// How far the system clock is from the median of the peers' clocks, kept
+/// up to date by [`drift_task`]. Consensus nodes check it before producing
+/// blocks, since a drifting clock signs the wrong slots.
+pub struct ClockDrift {
+    /// Seconds of drift past which a warning is logged
+    pub warn_seconds: u64,
+    /// Seconds of drift past which the node refuses to produce blocks
+    pub max_seconds: u64,
+    offset: AtomicI64,
+    measured: AtomicBool,
+}
+
+impl ClockDrift {
+    pub fn new(warn_seconds: u64, max_seconds: u64) -> ClockDriftPtr {
+        Arc::new(Self {
+            warn_seconds,
+            max_seconds,
+            offset: AtomicI64::new(0),
+            measured: AtomicBool::new(false),
+        })
+    }
+
+    /// Last measured drift in seconds, positive when our clock is ahead of
+    /// the peers'. `None` until a peer answered.
+    pub fn offset(&self) -> Option<i64> {
+        match self.measured.load(Ordering::Relaxed) {
+            true => Some(self.offset.load(Ordering::Relaxed)),
+            false => None,
+        }
+    }
+
+    fn set(&self, offset: i64) {
+        self.offset.store(offset, Ordering::Relaxed);
+        self.measured.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the last measured drift is past the safety bound. Nodes that
+    /// have no peers to measure against are trusted, as before.
+    pub fn exceeded(&self) -> bool {
+        self.offset().map_or(false, |offset| offset.unsigned_abs() > self.max_seconds)
+    }
+}
+
+/// Offset of the system clock from each peer's, in seconds, positive when
+/// ours is ahead. Half the round trip is credited to each reply. Peers that
+/// don't answer are left out.
+async fn peer_offsets(peers: &[Url], proxy: &Option<Url>) -> Vec<i64> {
+    let mut offsets = vec![];
+    for peer in peers {
+        let request = async {
+            let rpc_client = RpcClient::with_proxy(peer.clone(), proxy.clone()).await?;
+            let req = JsonRequest::new("clock", json!([]));
+            let rep = rpc_client.oneshot_request(req).await?;
+            Ok::<Timestamp, Error>(serde_json::from_value(rep)?)
+        };
+
+        let start = Instant::now();
+        let timeout = Duration::from_secs(PEER_TIMEOUT);
+        let peer_time = match async_std::future::timeout(timeout, request).await {
+            Ok(Ok(v)) => v,
+            Ok(Err(e)) => {
+                debug!("Failed asking {} for its clock: {}", peer, e);
+                continue
+            }
+            Err(_) => {
+                debug!("Timed out asking {} for its clock", peer);
+                continue
+            }
+        };
+
+        let half_rtt = (start.elapsed().as_millis() / 2000) as i64;
+        offsets.push(Timestamp::current_time().0 - (peer_time.0 + half_rtt));
+    }
+
+    offsets
+}
+
+/// Median of some values, the mean of the middle two for an even count
+pub fn median(values: &mut [i64]) -> Option<i64> {
+    if values.is_empty() {
+        return None
+    }
+
+    values.sort_unstable();
+    let mid = values.len() / 2;
+    match values.len() % 2 {
+        0 => Some((values[mid - 1] + values[mid]) / 2),
+        _ => Some(values[mid]),
+    }
+}
+
+/// Measure the system clock's drift from the median of the peers' clocks
+/// every `interval` seconds, logging once it passes the warning threshold
+/// or the safety bound.
+pub async fn drift_task(
+    peers: Vec<Url>,
+    proxy: Option<Url>,
+    drift: ClockDriftPtr,
+    interval: u64,
+) -> Result<()> {
+    loop {
+        let mut offsets = peer_offsets(&peers, &proxy).await;
+        match median(&mut offsets) {
+            Some(offset) => {
+                debug!("Clock drift from {} peers: {} sec", offsets.len(), offset);
+                drift.set(offset);
+
+                if offset.unsigned_abs() > drift.max_seconds {
+                    error!(
+                        "System clock is {} sec off the peers' median, over the {} sec bound. \
+                         Block production is paused until the clock is fixed.",
+                        offset, drift.max_seconds
+                    );
+                } else if offset.unsigned_abs() > drift.warn_seconds {
+                    warn!("System clock is {} sec off the peers' median", offset);
+                }
+            }
+            None => warn!("No peer answered with its clock, clock drift is unknown"),
+        }
+
+        sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_drift() {
+        assert_eq!(median(&mut []), None);
+        assert_eq!(median(&mut [3, -1, 100]), Some(3));
+        assert_eq!(median(&mut [4, 1, 2, 100]), Some(3));
+
+        let drift = ClockDrift::new(2, 5);
+        assert_eq!(drift.offset(), None);
+        assert!(!drift.exceeded());
+        drift.set(-5);
+        assert!(!drift.exceeded());
+        drift.set(-6);
+        assert_eq!(drift.offset(), Some(-6));
+        assert!(drift.exceeded());
+    }
+}
@@ -0,0 +1,141 @@
+//! Where the chain daemons, darkfid and faucetd, keep their data.
+//! Everything they store for a network goes in that network's directory,
+//! `~/.local/share/darkfi/<network>/` on Linux, or under `--data-dir`
+//! instead of `~/.local/share/darkfi`. Paths configured explicitly are
+//! used as they are. Daemons that don't follow a chain, like cashierd,
+//! taud and ircd, keep using the paths in their configuration.
+//!
+//! Data used to live in `~/.config/darkfi`, shared by all networks. It's
+//! moved to the directory of the network the daemon starts on first.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+
+use super::expand_path;
+use crate::{Error, Result};
+
+/// Data directory of a network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataDir(PathBuf);
+
+impl DataDir {
+    /// Directory of `network`, under `data_dir` if given, otherwise under
+    /// `darkfi` in the platform's data directory.
+    pub fn new(data_dir: Option<&str>, network: &str) -> Result<Self> {
+        let base = match data_dir {
+            Some(v) => expand_path(v)?,
+            None => match dirs::data_dir() {
+                Some(v) => v.join("darkfi"),
+                None => return Err(Error::UnsupportedOS),
+            },
+        };
+
+        Ok(Self(base.join(network)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Path of the file or directory `name` in the network's directory,
+    /// unless `configured` overrides it. If nothing is there yet and the
+    /// legacy location holds it, it's moved over first.
+    pub fn locate(&self, configured: Option<&str>, name: &str, legacy: &str) -> Result<PathBuf> {
+        if let Some(v) = configured {
+            return expand_path(v)
+        }
+
+        let path = self.0.join(name);
+        let legacy = expand_path(legacy)?;
+        if path.exists() || !legacy.exists() {
+            return Ok(path)
+        }
+
+        Ok(migrate(&legacy, &path))
+    }
+
+    /// Where [`Self::locate`] would find `name`, without moving anything.
+    /// Data not moved over yet is found in its legacy location.
+    pub fn find(&self, configured: Option<&str>, name: &str, legacy: &str) -> Result<PathBuf> {
+        if let Some(v) = configured {
+            return expand_path(v)
+        }
+
+        let path = self.0.join(name);
+        let legacy = expand_path(legacy)?;
+        if path.exists() || !legacy.exists() {
+            return Ok(path)
+        }
+
+        Ok(legacy)
+    }
+}
+
+/// Move `legacy` to `path`, returning where the data ended up. Data in
+/// use, or that can't be moved, stays where it is.
+fn migrate(legacy: &Path, path: &Path) -> PathBuf {
+    #[cfg(unix)]
+    {
+        if super::lock::lock_path(legacy).exists() {
+            warn!("{} is in use, not moving it to {}", legacy.display(), path.display());
+            return legacy.to_path_buf()
+        }
+    }
+
+    let moved = match path.parent() {
+        Some(parent) => fs::create_dir_all(parent).and_then(|_| fs::rename(legacy, path)),
+        None => fs::rename(legacy, path),
+    };
+
+    match moved {
+        Ok(()) => {
+            info!("Moved {} to {}", legacy.display(), path.display());
+            path.to_path_buf()
+        }
+        Err(e) => {
+            let (from, to) = (legacy.display(), path.display());
+            warn!("Failed moving {} to {}, keeping it there: {}", from, to, e);
+            legacy.to_path_buf()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_dir_layout() {
+        let dir = std::env::temp_dir().join("darkfi_data_dir_layout");
+        let _ = fs::remove_dir_all(&dir);
+        let data_dir = DataDir::new(dir.join("data").to_str(), "testnet").unwrap();
+        assert_eq!(data_dir.path(), dir.join("data/testnet"));
+
+        let legacy = dir.join("config/darkfid_wallet.db");
+        let legacy = legacy.to_str().unwrap();
+        let path = data_dir.locate(None, "darkfid_wallet.db", legacy).unwrap();
+        assert_eq!(path, dir.join("data/testnet/darkfid_wallet.db"));
+        assert!(!path.exists());
+
+        // Legacy data is moved over, once
+        fs::create_dir_all(dir.join("config")).unwrap();
+        fs::write(legacy, "wallet").unwrap();
+        assert_eq!(data_dir.locate(None, "darkfid_wallet.db", legacy).unwrap(), path);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "wallet");
+        assert!(!Path::new(legacy).exists());
+
+        fs::write(legacy, "another wallet").unwrap();
+        assert_eq!(data_dir.locate(None, "darkfid_wallet.db", legacy).unwrap(), path);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "wallet");
+
+        // Configured paths are left alone
+        let other = dir.join("other.db");
+        let located = data_dir.locate(other.to_str(), "darkfid_wallet.db", legacy).unwrap();
+        assert_eq!(located, other);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
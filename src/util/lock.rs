@@ -0,0 +1,183 @@
+//! Lock files keeping two processes from opening the same database. The
+//! lock file sits next to the database and holds the PID of its owner,
+//! so the error names the process to stop.
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+
+use crate::{Error, Result};
+
+/// Lock on a database, released when dropped. The lock is also released
+/// when the process crashes, except on filesystems without `flock`
+/// support, where the PID in the file is all there is to go by.
+pub struct DbLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl DbLock {
+    /// Lock the database at `db_path`, through `<db_path>.lock`. With
+    /// `force_unlock`, a lock whose owner isn't running anymore is taken
+    /// over. A lock held by a running process is never broken.
+    pub fn acquire(db_path: &Path, force_unlock: bool) -> Result<Self> {
+        let path = lock_path(db_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new().read(true).write(true).create(true).open(&path)?;
+        let owner = read_pid(&mut file);
+
+        match try_lock(&file) {
+            Ok(true) => {
+                if let Some(pid) = owner {
+                    info!("Taking over the lock on {} left by PID {}", db_path.display(), pid);
+                }
+            }
+
+            // The owner may still be alive and holding the lock
+            Ok(false) => {
+                let pid = owner.unwrap_or(0);
+                if is_alive(pid) {
+                    return Err(Error::DatabaseLocked(db_path.display().to_string(), pid))
+                }
+                if !force_unlock {
+                    return Err(Error::StaleDatabaseLock(db_path.display().to_string(), pid))
+                }
+
+                // Whoever holds the lock keeps it on the old file
+                warn!("Breaking the lock on {} held by PID {}", db_path.display(), pid);
+                fs::remove_file(&path)?;
+                return Self::acquire(db_path, false)
+            }
+
+            // No flock support, the PID in the file might be from a crash
+            Err(e) if e.raw_os_error() == Some(libc::ENOLCK) => {
+                if let Some(pid) = owner {
+                    if is_alive(pid) {
+                        return Err(Error::DatabaseLocked(db_path.display().to_string(), pid))
+                    }
+                    if !force_unlock {
+                        return Err(Error::StaleDatabaseLock(db_path.display().to_string(), pid))
+                    }
+                    warn!("Breaking the lock on {} left by PID {}", db_path.display(), pid);
+                }
+            }
+
+            Err(e) => return Err(e.into()),
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+
+        Ok(Self { path, _file: file })
+    }
+}
+
+impl Drop for DbLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// PID of the process holding the lock on the database at `db_path`, if
+/// any. The lock is left for the owner to take if nobody holds it.
+pub fn lock_owner(db_path: &Path) -> Result<Option<i32>> {
+    let mut file = match File::open(lock_path(db_path)) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    // Released when the file is closed
+    if try_lock(&file)? {
+        return Ok(None)
+    }
+
+    Ok(Some(read_pid(&mut file).unwrap_or(0)))
+}
+
+/// Path of the lock file of the database at `db_path`
+pub fn lock_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+/// Try to take an exclusive `flock` on the file, without blocking.
+/// Returns false if another open file holds it.
+pub(crate) fn try_lock(file: &File) -> io::Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true)
+    }
+
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        return Ok(false)
+    }
+
+    Err(err)
+}
+
+/// PID written to the start of the file
+pub(crate) fn read_pid(file: &mut File) -> Option<i32> {
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
+
+/// Whether a process with the given PID exists. Signal 0 only checks
+/// that it could be sent, and EPERM means someone else's process.
+fn is_alive(pid: i32) -> bool {
+    if pid <= 0 {
+        return false
+    }
+
+    unsafe { libc::kill(pid, 0) == 0 } ||
+        io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn database_lock() {
+        let dir = std::env::temp_dir().join("darkfi_database_lock");
+        let _ = fs::remove_dir_all(&dir);
+        let db_path = dir.join("wallet.db");
+        let pid = std::process::id() as i32;
+
+        let lock = DbLock::acquire(&db_path, false).unwrap();
+        assert_eq!(fs::read_to_string(lock_path(&db_path)).unwrap(), format!("{}\n", pid));
+
+        // Locks are held per open file, so this stands in for another
+        // process. The owner is running, so forcing doesn't help.
+        for force_unlock in [false, true] {
+            match DbLock::acquire(&db_path, force_unlock) {
+                Err(Error::DatabaseLocked(path, v)) => {
+                    assert_eq!(path, db_path.display().to_string());
+                    assert_eq!(v, pid);
+                }
+                _ => panic!("Database was locked twice"),
+            }
+        }
+
+        // A lock file left by a crash isn't locked anymore
+        drop(lock);
+        assert!(!lock_path(&db_path).exists());
+        fs::write(lock_path(&db_path), "12345\n").unwrap();
+        let lock = DbLock::acquire(&db_path, false).unwrap();
+        assert_eq!(fs::read_to_string(lock_path(&db_path)).unwrap(), format!("{}\n", pid));
+
+        drop(lock);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -3,18 +3,34 @@ pub mod async_serial;
 #[cfg(feature = "async-runtime")]
 pub mod async_util;
 
+pub mod canonical_json;
+#[cfg(unix)]
+pub mod check;
 pub mod cli;
+pub mod data_dir;
+#[cfg(feature = "rpc")]
+pub mod clock;
 pub mod endian;
+#[cfg(unix)]
+pub mod lock;
 pub mod net_name;
 pub mod parse;
 pub mod path;
 pub mod serial;
+#[cfg(unix)]
+pub mod service;
+#[cfg(feature = "async-runtime")]
+pub mod supervisor;
 pub mod time;
 
 #[cfg(feature = "async-runtime")]
 pub use async_util::sleep;
 
+pub use data_dir::DataDir;
 pub use net_name::NetworkName;
 pub use parse::{decode_base10, encode_base10};
 pub use path::{expand_path, join_config_path, load_keypair_to_str};
-pub use time::{check_clock, unix_timestamp, NanoTimestamp, Timestamp};
+pub use time::{unix_timestamp, NanoTimestamp, Timestamp};
+
+#[cfg(feature = "rpc")]
+pub use clock::{check_clock, ClockDrift, ClockDriftPtr};
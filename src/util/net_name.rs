@@ -15,6 +15,13 @@ pub enum NetworkName {
     Ethereum,
 }
 
+impl NetworkName {
+    /// Whether the network has tokens besides its native coin
+    pub fn has_tokens(&self) -> bool {
+        !matches!(self, Self::Bitcoin)
+    }
+}
+
 impl std::fmt::Display for NetworkName {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
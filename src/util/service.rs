@@ -0,0 +1,208 @@
+//! Helpers to run daemons without a service manager: detaching into the
+//! background, and a locked PID file the `status` and `stop` commands
+//! find the running instance with. The PID file lives next to the data
+//! of the daemon, so the lock also keeps two instances from opening the
+//! same database.
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use super::lock::{read_pid, try_lock};
+use crate::{Error, Result};
+
+/// Seconds `stop` waits for the daemon to shut down
+const STOP_TIMEOUT: u64 = 30;
+
+/// Commands managing a running daemon
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceCommand {
+    /// Print the PID of the running instance
+    Status,
+    /// Ask the running instance to shut down, and wait for it
+    Stop,
+}
+
+impl FromStr for ServiceCommand {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "status" => Ok(Self::Status),
+            "stop" => Ok(Self::Stop),
+            _ => Err(Error::ParseFailed("Unknown command, expected status or stop")),
+        }
+    }
+}
+
+/// PID file locked by the running instance. The lock is released when
+/// the process exits, even if it crashes, so a leftover file is never
+/// mistaken for a running instance.
+pub struct PidFile {
+    path: PathBuf,
+    _file: File,
+}
+
+impl PidFile {
+    /// Lock the PID file and write our PID to it
+    pub fn acquire(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Not truncated before it's locked, it may hold the PID of the
+        // running instance
+        let mut file = OpenOptions::new().read(true).write(true).create(true).open(path)?;
+        if !try_lock(&file)? {
+            return Err(Error::AlreadyRunning(read_pid(&mut file).unwrap_or(0)))
+        }
+
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+
+        Ok(Self { path: path.to_path_buf(), _file: file })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// PID of the instance holding the lock on the PID file, if any
+pub fn running_pid(path: &Path) -> Result<Option<i32>> {
+    let mut file = match File::open(path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    // We got the lock, so nobody is running and the file is stale
+    if try_lock(&file)? {
+        return Ok(None)
+    }
+
+    Ok(read_pid(&mut file))
+}
+
+/// Detach from the terminal and keep running in the background. Only the
+/// calling thread survives the fork, so this must be called before any
+/// other thread is spawned.
+pub fn daemonize() -> Result<()> {
+    // The child starts a new session, without a controlling terminal
+    fork_and_exit_parent()?;
+    if unsafe { libc::setsid() } < 0 {
+        return Err(io::Error::last_os_error().into())
+    }
+
+    // Forking again leaves a process that isn't a session leader, so it
+    // can't acquire a terminal
+    fork_and_exit_parent()?;
+
+    let null = OpenOptions::new().read(true).write(true).open("/dev/null")?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } < 0 {
+            return Err(io::Error::last_os_error().into())
+        }
+    }
+
+    Ok(())
+}
+
+fn fork_and_exit_parent() -> Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error().into()),
+        0 => Ok(()),
+        _ => std::process::exit(0),
+    }
+}
+
+/// Make sure no other instance uses the PID file, detach into the
+/// background if asked, then lock the PID file for as long as the
+/// returned [`PidFile`] lives.
+pub fn start_service(pid_path: &Path, daemon: bool) -> Result<PidFile> {
+    // Checked first, so the error still shows on the terminal
+    if let Some(pid) = running_pid(pid_path)? {
+        return Err(Error::AlreadyRunning(pid))
+    }
+
+    if daemon {
+        daemonize()?;
+    }
+
+    PidFile::acquire(pid_path)
+}
+
+/// Run a command against the instance using the PID file
+pub fn run_command(command: ServiceCommand, pid_path: &Path) -> Result<()> {
+    let pid = running_pid(pid_path)?.ok_or(Error::NotRunning)?;
+
+    match command {
+        ServiceCommand::Status => println!("Running with PID {}", pid),
+        ServiceCommand::Stop => {
+            if unsafe { libc::kill(pid, libc::SIGTERM) } < 0 {
+                return Err(io::Error::last_os_error().into())
+            }
+
+            // The lock is released once the daemon is done cleaning up
+            for _ in 0..STOP_TIMEOUT * 10 {
+                if running_pid(pid_path)?.is_none() {
+                    println!("Stopped PID {}", pid);
+                    return Ok(())
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+
+            println!("PID {} is still shutting down", pid);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pid_file_lock() {
+        let dir = std::env::temp_dir().join("darkfi_pid_file_lock");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("daemon.pid");
+        assert_eq!(running_pid(&path).unwrap(), None);
+
+        let pid_file = PidFile::acquire(&path).unwrap();
+        let pid = std::process::id() as i32;
+        assert_eq!(running_pid(&path).unwrap(), Some(pid));
+
+        // Locks are held per open file, so this stands in for another process
+        match PidFile::acquire(&path) {
+            Err(Error::AlreadyRunning(v)) => assert_eq!(v, pid),
+            _ => panic!("PID file was locked twice"),
+        }
+        assert!(matches!(start_service(&path, false), Err(Error::AlreadyRunning(_))));
+
+        drop(pid_file);
+        assert!(!path.exists());
+        assert!(matches!(run_command(ServiceCommand::Status, &path), Err(Error::NotRunning)));
+
+        // A file left behind by a crashed instance isn't locked
+        fs::write(&path, "12345\n").unwrap();
+        assert_eq!(running_pid(&path).unwrap(), None);
+        let pid_file = start_service(&path, false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", pid));
+
+        drop(pid_file);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+//! Supervision of long-running async tasks.
+//!
+//! Tasks are spawned by name with a [`RestartPolicy`], and the
+//! [`Supervisor`] keeps track of their [`TaskStatus`] so daemons can
+//! report it over JSON-RPC or metrics.
+use std::{
+    any::Any,
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use async_executor::Executor;
+use async_std::sync::{Arc, Mutex};
+use futures::FutureExt;
+use fxhash::FxHashMap;
+use log::{error, info, warn};
+use serde::Serialize;
+
+use super::sleep;
+use crate::Result;
+
+/// Longest wait, in seconds, before restarting a task
+const MAX_BACKOFF: u64 = 60;
+
+/// When a supervised task is started again after it stops.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    /// Run the task once
+    Never,
+    /// Restart the task when it returns an error or panics
+    OnFailure,
+    /// Restart the task whenever it stops
+    Always,
+}
+
+/// What a supervised task is doing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    /// Stopped, and waiting to be restarted
+    Restarting,
+    /// Failed, and won't be restarted
+    Failed,
+}
+
+/// Status of a supervised task, as reported by [`Supervisor::status`].
+#[derive(Clone, Debug, Serialize)]
+pub struct TaskStatus {
+    pub name: String,
+    pub policy: RestartPolicy,
+    pub state: TaskState,
+    /// Times the task was restarted
+    pub restarts: u64,
+    /// Times the task returned an error or panicked
+    pub failures: u64,
+    /// Error of the last failure
+    pub last_error: Option<String>,
+}
+
+pub type SupervisorPtr = Arc<Supervisor>;
+
+/// Spawns named tasks and restarts them according to their policy.
+/// Restarts are delayed by a backoff starting at one second and doubling
+/// after every stop, up to a minute. The backoff is reset once a task
+/// runs for longer than that. Tasks that return successfully and aren't
+/// restarted are forgotten.
+#[derive(Default)]
+pub struct Supervisor {
+    tasks: Mutex<FxHashMap<u64, TaskStatus>>,
+    next_id: AtomicU64,
+}
+
+impl Supervisor {
+    pub fn new() -> SupervisorPtr {
+        Arc::new(Self::default())
+    }
+
+    /// Spawn the task built by `task` on the executor. `task` is called
+    /// again to build the task on every restart.
+    pub fn spawn<'a, F, Fut>(
+        self: &Arc<Self>,
+        executor: &Executor<'a>,
+        name: &str,
+        policy: RestartPolicy,
+        task: F,
+    ) where
+        F: Fn() -> Fut + Send + 'a,
+        Fut: Future<Output = Result<()>> + Send + 'a,
+    {
+        executor.spawn(self.clone().supervise(name.to_string(), policy, task)).detach();
+    }
+
+    /// Run the task built by `task` until the policy says it's done.
+    pub async fn supervise<F, Fut>(self: Arc<Self>, name: String, policy: RestartPolicy, task: F)
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        let status = TaskStatus {
+            name: name.clone(),
+            policy,
+            state: TaskState::Running,
+            restarts: 0,
+            failures: 0,
+            last_error: None,
+        };
+        self.tasks.lock().await.insert(id, status);
+
+        let mut backoff = 1;
+
+        loop {
+            info!(target: "supervisor", "Starting task {}", name);
+            let started = Instant::now();
+
+            let error = match AssertUnwindSafe(task()).catch_unwind().await {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(e.to_string()),
+                Err(e) => Some(format!("panicked: {}", panic_message(&*e))),
+            };
+
+            let restart = match (&error, policy) {
+                (_, RestartPolicy::Always) | (Some(_), RestartPolicy::OnFailure) => true,
+                (_, RestartPolicy::Never) | (None, RestartPolicy::OnFailure) => false,
+            };
+
+            let mut tasks = self.tasks.lock().await;
+            let status = tasks.get_mut(&id).unwrap();
+
+            match &error {
+                Some(e) => {
+                    error!(target: "supervisor", "Task {} failed: {}", name, e);
+                    status.failures += 1;
+                    status.last_error = error.clone();
+                }
+                None if restart => warn!(target: "supervisor", "Task {} exited", name),
+                None => {
+                    info!(target: "supervisor", "Task {} finished", name);
+                    tasks.remove(&id);
+                    return
+                }
+            }
+
+            if !restart {
+                status.state = TaskState::Failed;
+                return
+            }
+
+            if started.elapsed() > Duration::from_secs(MAX_BACKOFF) {
+                backoff = 1;
+            }
+
+            status.state = TaskState::Restarting;
+            drop(tasks);
+
+            info!(target: "supervisor", "Restarting task {} in {} seconds", name, backoff);
+            sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+
+            let mut tasks = self.tasks.lock().await;
+            let status = tasks.get_mut(&id).unwrap();
+            status.state = TaskState::Running;
+            status.restarts += 1;
+        }
+    }
+
+    /// Status of the supervised tasks, ordered by name.
+    pub async fn status(&self) -> Vec<TaskStatus> {
+        let mut ret: Vec<TaskStatus> = self.tasks.lock().await.values().cloned().collect();
+        ret.sort_by(|a, b| a.name.cmp(&b.name));
+        ret
+    }
+}
+
+fn panic_message(e: &(dyn Any + Send)) -> &str {
+    match e.downcast_ref::<&str>() {
+        Some(v) => v,
+        None => e.downcast_ref::<String>().map(|v| v.as_str()).unwrap_or("unknown panic"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    async fn fail() -> Result<()> {
+        Err(Error::ConfigInvalid)
+    }
+
+    async fn boom() -> Result<()> {
+        panic!("boom")
+    }
+
+    #[async_std::test]
+    async fn supervisor_status() {
+        let supervisor = Supervisor::new();
+
+        let ok = || async { Ok(()) };
+        supervisor.clone().supervise("ok".into(), RestartPolicy::OnFailure, ok).await;
+        supervisor.clone().supervise("fail".into(), RestartPolicy::Never, fail).await;
+        supervisor.clone().supervise("boom".into(), RestartPolicy::Never, boom).await;
+
+        // Finished tasks are forgotten, failed ones are kept
+        let status = supervisor.status().await;
+        assert_eq!(status.len(), 2);
+        assert_eq!(status[0].name, "boom");
+        assert_eq!(status[0].last_error.as_deref(), Some("panicked: boom"));
+        assert_eq!(status[1].name, "fail");
+        assert_eq!(status[1].state, TaskState::Failed);
+        assert_eq!(status[1].failures, 1);
+        assert_eq!(status[1].restarts, 0);
+    }
+}
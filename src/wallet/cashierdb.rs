@@ -16,9 +16,12 @@ use crate::{
     },
     util::{
         serial::{deserialize, serialize},
-        NetworkName,
+        NetworkName, Timestamp,
+    },
+    Error::{
+        WalletDepositNotFound, WalletDepositNotRefundable, WalletEmptyPassword,
+        WalletTokenSupplyNotFound, WalletTreeExists,
     },
-    Error::{WalletEmptyPassword, WalletTreeExists},
     Result,
 };
 
@@ -44,6 +47,97 @@ pub struct DepositToken {
     pub mint_address: String,
 }
 
+/// Amounts of a bridged token minted for deposits, and burned for
+/// withdrawals, with 8 decimals
+pub struct TokenSupply {
+    pub network: NetworkName,
+    pub token_id: DrkTokenId,
+    pub mint_address: String,
+    pub minted: u64,
+    pub redeemed: u64,
+    /// The token was bridged before its supply was tracked, so `minted`
+    /// and `redeemed` leave out what happened before
+    pub partial: bool,
+}
+
+impl TokenSupply {
+    /// What is left in circulation
+    pub fn circulating(&self) -> u64 {
+        self.minted.saturating_sub(self.redeemed)
+    }
+}
+
+/// Where a deposit stands in the deposit ledger
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DepositStatus {
+    /// Tokens were minted for the deposit
+    Minted = 0,
+    /// Minting failed for good, leaving the funds in the main wallet
+    Failed = 1,
+    /// A refund was approved and is being sent
+    Refunding = 2,
+    /// The funds were sent back to where they came from
+    Refunded = 3,
+}
+
+impl DepositStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Minted => "minted",
+            Self::Failed => "failed",
+            Self::Refunding => "refunding",
+            Self::Refunded => "refunded",
+        }
+    }
+
+    fn from_i64(status: i64) -> Option<Self> {
+        match status {
+            0 => Some(Self::Minted),
+            1 => Some(Self::Failed),
+            2 => Some(Self::Refunding),
+            3 => Some(Self::Refunded),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for DepositStatus {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "minted" => Ok(Self::Minted),
+            "failed" => Ok(Self::Failed),
+            "refunding" => Ok(Self::Refunding),
+            "refunded" => Ok(Self::Refunded),
+            _ => Err(crate::Error::ParseFailed("Invalid deposit status")),
+        }
+    }
+}
+
+/// A deposit received on a bridged network, as kept in the deposit ledger
+#[derive(Clone, Debug)]
+pub struct DepositRecord {
+    pub id: u64,
+    pub network: NetworkName,
+    pub token_id: DrkTokenId,
+    pub drk_public_key: PublicKey,
+    /// Amount received, with 8 decimals
+    pub amount: u64,
+    /// External address the deposit came from, if the network tells
+    pub sender: Option<String>,
+    pub status: DepositStatus,
+    /// Why minting, or the last refund attempt, failed
+    pub reason: Option<String>,
+    /// Amount sent back, with 8 decimals
+    pub refund_amount: Option<u64>,
+    /// ID of the refund transaction on the external network
+    pub refund_tx: Option<String>,
+    pub received_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
 pub struct CashierDb {
     pub conn: SqlitePool,
 }
@@ -82,6 +176,9 @@ impl CashierDb {
         let main_kps = include_str!("../../script/sql/cashier_main_keypairs.sql");
         let deposit_kps = include_str!("../../script/sql/cashier_deposit_keypairs.sql");
         let withdraw_kps = include_str!("../../script/sql/cashier_withdraw_keypairs.sql");
+        let withdraw_whitelist = include_str!("../../script/sql/cashier_withdraw_whitelist.sql");
+        let token_supply = include_str!("../../script/sql/cashier_token_supply.sql");
+        let deposits = include_str!("../../script/sql/cashier_deposits.sql");
 
         let mut conn = self.conn.acquire().await?;
 
@@ -93,9 +190,51 @@ impl CashierDb {
 
         debug!("Initializing withdraw keypairs table");
         sqlx::query(withdraw_kps).execute(&mut conn).await?;
+
+        debug!("Initializing withdraw whitelist table");
+        sqlx::query(withdraw_whitelist).execute(&mut conn).await?;
+
+        debug!("Initializing token supply table");
+        sqlx::query(token_supply).execute(&mut conn).await?;
+
+        debug!("Initializing deposits table");
+        sqlx::query(deposits).execute(&mut conn).await?;
+
+        // Columns added since the tables were first made
+        self.add_column("deposit_keypairs", "tag", "TEXT").await?;
+        if self.add_column("token_supply", "partial", "INTEGER NOT NULL DEFAULT 0").await? {
+            // Nothing tells which of these tokens were bridged before their
+            // supply was tracked
+            sqlx::query(
+                "UPDATE token_supply SET partial = EXISTS
+                 (SELECT 1 FROM deposit_keypairs AS d
+                  WHERE d.network = token_supply.network
+                  AND d.token_id = token_supply.token_id);",
+            )
+            .execute(&mut conn)
+            .await?;
+        }
         Ok(())
     }
 
+    /// Add a column to a table of a database made before it existed.
+    /// Returns whether it was missing.
+    async fn add_column(&self, table: &str, column: &str, typ: &str) -> Result<bool> {
+        let mut conn = self.conn.acquire().await?;
+        let columns =
+            sqlx::query(&format!("PRAGMA table_info({});", table)).fetch_all(&mut conn).await?;
+
+        if columns.iter().any(|row| row.get::<String, _>("name") == column) {
+            return Ok(false)
+        }
+
+        debug!("Adding {} column to {} table", column, table);
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {};", table, column, typ))
+            .execute(&mut conn)
+            .await?;
+        Ok(true)
+    }
+
     pub async fn tree_gen(&self) -> Result<()> {
         debug!("Attempting to generate merkle tree");
         let mut conn = self.conn.acquire().await?;
@@ -234,6 +373,7 @@ impl CashierDb {
         network: &NetworkName,
         token_id: &DrkTokenId,
         mint_address: String,
+        tag: Option<&str>,
     ) -> Result<()> {
         debug!("Writing deposit keys to database");
         let d_key_public = serialize(d_key_public);
@@ -246,9 +386,9 @@ impl CashierDb {
         sqlx::query(
             "INSERT INTO deposit_keypairs
             (d_key_public, token_key_secret, token_key_public,
-             network, token_id, mint_address, confirm)
+             network, token_id, mint_address, confirm, tag)
             VALUES
-            (?1, ?2, ?3, ?4, ?5, ?6, ?7);",
+            (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8);",
         )
         .bind(d_key_public)
         .bind(token_key_secret)
@@ -257,6 +397,7 @@ impl CashierDb {
         .bind(token_id)
         .bind(mint_address)
         .bind(confirm)
+        .bind(tag)
         .execute(&mut conn)
         .await?;
 
@@ -352,6 +493,45 @@ impl CashierDb {
         Ok(keys)
     }
 
+    /// Unconfirmed deposit keys of the given user tag. Tags are only
+    /// unique per user, so they're looked up along with the user's key.
+    pub async fn get_deposit_token_keys_by_tag(
+        &self,
+        d_key_public: &PublicKey,
+        tag: &str,
+        network: &NetworkName,
+    ) -> Result<Vec<TokenKey>> {
+        debug!("Checking for existing deposit tag");
+        let d_key_public = serialize(d_key_public);
+        let network = serialize(network);
+        let confirm = serialize(&false);
+
+        let mut conn = self.conn.acquire().await?;
+        let rows = sqlx::query(
+            "SELECT token_key_secret, token_key_public
+             FROM deposit_keypairs
+             WHERE d_key_public = ?1
+             AND tag = ?2
+             AND network = ?3
+             AND confirm = ?4;",
+        )
+        .bind(d_key_public)
+        .bind(tag)
+        .bind(network)
+        .bind(confirm)
+        .fetch_all(&mut conn)
+        .await?;
+
+        let mut keys = vec![];
+        for row in rows {
+            let secret_key = row.get("token_key_secret");
+            let public_key = row.get("token_key_public");
+            keys.push(TokenKey { secret_key, public_key });
+        }
+
+        Ok(keys)
+    }
+
     pub async fn get_withdraw_keys_by_token_public_key(
         &self,
         token_key_public: &[u8],
@@ -435,6 +615,348 @@ impl CashierDb {
         Ok(())
     }
 
+    /// Register a withdrawal address of the user with the given DarkFi
+    /// public key. Registering an address again keeps the original
+    /// registration time. Returns the registration time.
+    pub async fn put_whitelisted_address(
+        &self,
+        drk_public: &PublicKey,
+        token_key_public: &[u8],
+        network: &NetworkName,
+    ) -> Result<Timestamp> {
+        debug!("Whitelisting withdraw address");
+        let drk_public = serialize(drk_public);
+        let network = serialize(network);
+
+        let mut conn = self.conn.acquire().await?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO withdraw_whitelist
+             (drk_public_key, token_key_public, network, registered_at)
+             VALUES
+             (?1, ?2, ?3, ?4);",
+        )
+        .bind(drk_public.clone())
+        .bind(token_key_public)
+        .bind(network.clone())
+        .bind(Timestamp::current_time().0)
+        .execute(&mut conn)
+        .await?;
+
+        let row = sqlx::query(
+            "SELECT registered_at FROM withdraw_whitelist
+             WHERE drk_public_key = ?1
+             AND token_key_public = ?2
+             AND network = ?3;",
+        )
+        .bind(drk_public)
+        .bind(token_key_public)
+        .bind(network)
+        .fetch_one(&mut conn)
+        .await?;
+
+        Ok(Timestamp(row.get("registered_at")))
+    }
+
+    /// Get the time the user with the given DarkFi public key registered
+    /// a withdrawal address, if they did.
+    pub async fn get_whitelisted_address(
+        &self,
+        drk_public: &PublicKey,
+        token_key_public: &[u8],
+        network: &NetworkName,
+    ) -> Result<Option<Timestamp>> {
+        debug!("Checking withdraw whitelist");
+        let drk_public = serialize(drk_public);
+        let network = serialize(network);
+
+        let mut conn = self.conn.acquire().await?;
+        let row = sqlx::query(
+            "SELECT registered_at FROM withdraw_whitelist
+             WHERE drk_public_key = ?1
+             AND token_key_public = ?2
+             AND network = ?3;",
+        )
+        .bind(drk_public)
+        .bind(token_key_public)
+        .bind(network)
+        .fetch_optional(&mut conn)
+        .await?;
+
+        Ok(row.map(|r| Timestamp(r.get("registered_at"))))
+    }
+
+    /// Start tracking the supply of a bridged token. Does nothing if it's
+    /// already tracked. A token users already had deposit addresses for
+    /// may have been minted before, and its supply is marked partial.
+    pub async fn put_token_supply(
+        &self,
+        network: &NetworkName,
+        token_id: &DrkTokenId,
+        mint_address: String,
+    ) -> Result<()> {
+        debug!("Tracking token supply");
+        let network = serialize(network);
+        let token_id = serialize(token_id);
+        let mint_address = serialize(&mint_address);
+
+        let mut conn = self.conn.acquire().await?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO token_supply
+             (network, token_id, mint_address, partial)
+             VALUES
+             (?1, ?2, ?3, EXISTS
+              (SELECT 1 FROM deposit_keypairs WHERE network = ?1 AND token_id = ?2));",
+        )
+        .bind(network)
+        .bind(token_id)
+        .bind(mint_address)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Add `amount` to the minted supply of a tracked token
+    pub async fn add_minted(
+        &self,
+        network: &NetworkName,
+        token_id: &DrkTokenId,
+        amount: u64,
+    ) -> Result<()> {
+        self.add_to_supply("minted", network, token_id, amount).await
+    }
+
+    /// Add `amount` to the redeemed supply of a tracked token
+    pub async fn add_redeemed(
+        &self,
+        network: &NetworkName,
+        token_id: &DrkTokenId,
+        amount: u64,
+    ) -> Result<()> {
+        self.add_to_supply("redeemed", network, token_id, amount).await
+    }
+
+    async fn add_to_supply(
+        &self,
+        column: &str,
+        network: &NetworkName,
+        token_id: &DrkTokenId,
+        amount: u64,
+    ) -> Result<()> {
+        debug!("Adding {} to {} supply", amount, column);
+        let network = serialize(network);
+        let token_id = serialize(token_id);
+
+        let mut conn = self.conn.acquire().await?;
+        let query = format!(
+            "UPDATE token_supply
+             SET {0} = {0} + ?1
+             WHERE network = ?2
+             AND token_id = ?3;",
+            column
+        );
+        let result = sqlx::query(&query)
+            .bind(amount as i64)
+            .bind(network)
+            .bind(token_id)
+            .execute(&mut conn)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(WalletTokenSupplyNotFound)
+        }
+
+        Ok(())
+    }
+
+    /// Get the supply of every tracked token
+    pub async fn get_token_supplies(&self) -> Result<Vec<TokenSupply>> {
+        debug!("Getting token supplies");
+
+        let mut conn = self.conn.acquire().await?;
+        let rows = sqlx::query(
+            "SELECT network, token_id, mint_address, minted, redeemed, partial
+             FROM token_supply;",
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        let mut supplies = vec![];
+
+        for row in rows {
+            supplies.push(TokenSupply {
+                network: deserialize(row.get("network"))?,
+                token_id: deserialize(row.get("token_id"))?,
+                mint_address: deserialize(row.get("mint_address"))?,
+                minted: row.get::<i64, _>("minted") as u64,
+                redeemed: row.get::<i64, _>("redeemed") as u64,
+                partial: row.get::<i64, _>("partial") != 0,
+            });
+        }
+
+        Ok(supplies)
+    }
+
+    /// Record a received deposit in the deposit ledger, as minted or
+    /// failed. `id`, the refund fields and `updated_at` are ignored.
+    /// Returns the ID of the record.
+    pub async fn put_deposit(&self, deposit: &DepositRecord) -> Result<u64> {
+        debug!("Recording deposit");
+        let network = serialize(&deposit.network);
+        let token_id = serialize(&deposit.token_id);
+        let d_key_public = serialize(&deposit.drk_public_key);
+
+        let mut conn = self.conn.acquire().await?;
+        let result = sqlx::query(
+            "INSERT INTO deposits
+             (network, token_id, d_key_public, amount, sender,
+              status, reason, received_at, updated_at)
+             VALUES
+             (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8);",
+        )
+        .bind(network)
+        .bind(token_id)
+        .bind(d_key_public)
+        .bind(deposit.amount as i64)
+        .bind(deposit.sender.as_deref())
+        .bind(deposit.status as i64)
+        .bind(deposit.reason.as_deref())
+        .bind(deposit.received_at.0)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    /// Get a deposit from the ledger
+    pub async fn get_deposit(&self, id: u64) -> Result<Option<DepositRecord>> {
+        debug!("Getting deposit {}", id);
+
+        let mut conn = self.conn.acquire().await?;
+        let row = sqlx::query("SELECT * FROM deposits WHERE deposit_id = ?1;")
+            .bind(id as i64)
+            .fetch_optional(&mut conn)
+            .await?;
+
+        row.map(|r| Self::deposit_from_row(&r)).transpose()
+    }
+
+    /// Get the deposits in the ledger, or the ones in `status`, oldest
+    /// first
+    pub async fn get_deposits(&self, status: Option<DepositStatus>) -> Result<Vec<DepositRecord>> {
+        debug!("Getting deposits");
+
+        let mut conn = self.conn.acquire().await?;
+        let rows = match status {
+            Some(status) => {
+                sqlx::query("SELECT * FROM deposits WHERE status = ?1 ORDER BY deposit_id;")
+                    .bind(status as i64)
+                    .fetch_all(&mut conn)
+                    .await?
+            }
+            None => {
+                sqlx::query("SELECT * FROM deposits ORDER BY deposit_id;")
+                    .fetch_all(&mut conn)
+                    .await?
+            }
+        };
+
+        rows.iter().map(Self::deposit_from_row).collect()
+    }
+
+    /// Mark a failed deposit as being refunded with `amount`, so it can't
+    /// be refunded twice. Fails if the deposit isn't in the failed state.
+    pub async fn start_refund(&self, id: u64, amount: u64) -> Result<()> {
+        debug!("Starting refund of deposit {}", id);
+
+        let mut conn = self.conn.acquire().await?;
+        let result = sqlx::query(
+            "UPDATE deposits
+             SET status = ?1, refund_amount = ?2, updated_at = ?3
+             WHERE deposit_id = ?4
+             AND status = ?5;",
+        )
+        .bind(DepositStatus::Refunding as i64)
+        .bind(amount as i64)
+        .bind(Timestamp::current_time().0)
+        .bind(id as i64)
+        .bind(DepositStatus::Failed as i64)
+        .execute(&mut conn)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return match self.get_deposit(id).await? {
+                Some(_) => Err(WalletDepositNotRefundable(id)),
+                None => Err(WalletDepositNotFound(id)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record the transaction a refund was sent in
+    pub async fn confirm_refund(&self, id: u64, refund_tx: &str) -> Result<()> {
+        debug!("Confirming refund of deposit {}", id);
+
+        let mut conn = self.conn.acquire().await?;
+        sqlx::query(
+            "UPDATE deposits
+             SET status = ?1, refund_tx = ?2, updated_at = ?3
+             WHERE deposit_id = ?4;",
+        )
+        .bind(DepositStatus::Refunded as i64)
+        .bind(refund_tx)
+        .bind(Timestamp::current_time().0)
+        .bind(id as i64)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Put a deposit whose refund couldn't be sent back in the failed
+    /// state, so the refund can be tried again
+    pub async fn abort_refund(&self, id: u64, reason: &str) -> Result<()> {
+        debug!("Aborting refund of deposit {}", id);
+
+        let mut conn = self.conn.acquire().await?;
+        sqlx::query(
+            "UPDATE deposits
+             SET status = ?1, reason = ?2, refund_amount = NULL, updated_at = ?3
+             WHERE deposit_id = ?4;",
+        )
+        .bind(DepositStatus::Failed as i64)
+        .bind(reason)
+        .bind(Timestamp::current_time().0)
+        .bind(id as i64)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    fn deposit_from_row(row: &sqlx::sqlite::SqliteRow) -> Result<DepositRecord> {
+        let status = match DepositStatus::from_i64(row.get("status")) {
+            Some(v) => v,
+            None => return Err(crate::Error::ParseFailed("Invalid deposit status")),
+        };
+
+        Ok(DepositRecord {
+            id: row.get::<i64, _>("deposit_id") as u64,
+            network: deserialize(row.get("network"))?,
+            token_id: deserialize(row.get("token_id"))?,
+            drk_public_key: deserialize(row.get("d_key_public"))?,
+            amount: row.get::<i64, _>("amount") as u64,
+            sender: row.get("sender"),
+            status,
+            reason: row.get("reason"),
+            refund_amount: row.get::<Option<i64>, _>("refund_amount").map(|a| a as u64),
+            refund_tx: row.get("refund_tx"),
+            received_at: Timestamp(row.get("received_at")),
+            updated_at: Timestamp(row.get("updated_at")),
+        })
+    }
+
     pub async fn get_deposit_token_keys_by_network(
         &self,
         network: &NetworkName,
@@ -526,6 +1048,7 @@ mod tests {
                 &network,
                 &token_id,
                 String::new(),
+                Some("user-1"),
             )
             .await?;
 
@@ -535,6 +1058,18 @@ mod tests {
         assert_eq!(keys[0].secret_key, token_addr_secret);
         assert_eq!(keys[0].public_key, token_addr_public);
 
+        // get_deposit_token_keys_by_tag()
+        let keys =
+            wallet.get_deposit_token_keys_by_tag(&keypair.public, "user-1", &network).await?;
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].public_key, token_addr_public);
+        let keys =
+            wallet.get_deposit_token_keys_by_tag(&keypair.public, "user-2", &network).await?;
+        assert!(keys.is_empty());
+        let other = Keypair::random(&mut OsRng).public;
+        let keys = wallet.get_deposit_token_keys_by_tag(&other, "user-1", &network).await?;
+        assert!(keys.is_empty());
+
         // get_deposit_token_keys_by_network()
         let resumed_keys = wallet.get_deposit_token_keys_by_network(&network).await?;
         assert_eq!(resumed_keys[0].drk_public_key, keypair.public);
@@ -570,6 +1105,99 @@ mod tests {
             wallet.get_withdraw_keys_by_token_public_key(&token_addr_public, &network).await?;
         assert!(addr.is_none());
 
+        // put_whitelisted_address()
+        let user = keypair.public;
+        let found = wallet.get_whitelisted_address(&user, &token_addr_public, &network).await?;
+        assert!(found.is_none());
+        let registered =
+            wallet.put_whitelisted_address(&user, &token_addr_public, &network).await?;
+        let again = wallet.put_whitelisted_address(&user, &token_addr_public, &network).await?;
+        assert_eq!(registered, again);
+
+        // get_whitelisted_address()
+        let found = wallet.get_whitelisted_address(&user, &token_addr_public, &network).await?;
+        assert_eq!(found, Some(registered));
+        let solana = NetworkName::Solana;
+        let found = wallet.get_whitelisted_address(&user, &token_addr_public, &solana).await?;
+        assert!(found.is_none());
+
+        // Addresses are whitelisted per user
+        let other = Keypair::random(&mut OsRng).public;
+        let found = wallet.get_whitelisted_address(&other, &token_addr_public, &network).await?;
+        assert!(found.is_none());
+
+        // put_token_supply()
+        assert!(wallet.add_minted(&network, &token_id, 100).await.is_err());
+        wallet.put_token_supply(&network, &token_id, String::new()).await?;
+        wallet.put_token_supply(&network, &token_id, String::new()).await?;
+
+        // add_minted(), add_redeemed()
+        wallet.add_minted(&network, &token_id, 100).await?;
+        wallet.add_minted(&network, &token_id, 50).await?;
+        wallet.add_redeemed(&network, &token_id, 30).await?;
+
+        // get_token_supplies()
+        let supplies = wallet.get_token_supplies().await?;
+        assert_eq!(supplies.len(), 1);
+        assert_eq!(supplies[0].network, network);
+        assert_eq!(supplies[0].token_id, token_id);
+        assert_eq!(supplies[0].minted, 150);
+        assert_eq!(supplies[0].redeemed, 30);
+        assert_eq!(supplies[0].circulating(), 120);
+        // Deposit addresses were handed out before its supply was tracked
+        assert!(supplies[0].partial);
+
+        let new_token_id = DrkTokenId::random(&mut OsRng);
+        wallet.put_token_supply(&network, &new_token_id, String::new()).await?;
+        let supplies = wallet.get_token_supplies().await?;
+        let new_supply = supplies.iter().find(|s| s.token_id == new_token_id).unwrap();
+        assert!(!new_supply.partial);
+
+        // put_deposit()
+        let mut deposit = DepositRecord {
+            id: 0,
+            network: network.clone(),
+            token_id,
+            drk_public_key: keypair.public,
+            amount: 150,
+            sender: None,
+            status: DepositStatus::Minted,
+            reason: None,
+            refund_amount: None,
+            refund_tx: None,
+            received_at: Timestamp::current_time(),
+            updated_at: Timestamp::current_time(),
+        };
+        let minted = wallet.put_deposit(&deposit).await?;
+        deposit.sender = Some("mxVFsFW5N4mu1HPkxPttorvocvzeZ7KZyk".into());
+        deposit.status = DepositStatus::Failed;
+        deposit.reason = Some("Deposit is missing the memo".into());
+        let failed = wallet.put_deposit(&deposit).await?;
+
+        // get_deposit(), get_deposits()
+        assert_eq!(wallet.get_deposits(None).await?.len(), 2);
+        let deposits = wallet.get_deposits(Some(DepositStatus::Failed)).await?;
+        assert_eq!(deposits.len(), 1);
+        assert_eq!(deposits[0].id, failed);
+        assert_eq!(deposits[0].sender, deposit.sender);
+        assert!(wallet.get_deposit(failed + 1).await?.is_none());
+
+        // start_refund(), abort_refund(), confirm_refund()
+        assert!(wallet.start_refund(minted, 150).await.is_err());
+        wallet.start_refund(failed, 140).await?;
+        assert!(wallet.start_refund(failed, 140).await.is_err());
+        wallet.abort_refund(failed, "Not enough funds").await?;
+        let record = wallet.get_deposit(failed).await?.unwrap();
+        assert_eq!(record.status, DepositStatus::Failed);
+        assert_eq!(record.refund_amount, None);
+
+        wallet.start_refund(failed, 140).await?;
+        wallet.confirm_refund(failed, "txid").await?;
+        let record = wallet.get_deposit(failed).await?.unwrap();
+        assert_eq!(record.status, DepositStatus::Refunded);
+        assert_eq!(record.refund_amount, Some(140));
+        assert_eq!(record.refund_tx.as_deref(), Some("txid"));
+
         Ok(())
     }
 }
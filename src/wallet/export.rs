@@ -0,0 +1,325 @@
+//! Wallet export files, carrying everything a wallet holds to another node
+//! or wallet implementation: keys, seed, coins with their notes, the
+//! Merkle tree holding their witnesses, and the transaction log. See
+//! `doc/src/clients/wallet_export.md` for the layout.
+//!
+//! Files are encrypted under a passphrase, with a key stretched through
+//! Argon2id, and start with a version so the format can change.
+use std::{io, sync::Arc};
+
+use argon2::Argon2;
+use crypto_api_chachapoly::ChachaPolyIetf;
+use incrementalmerkletree::bridgetree::BridgeTree;
+use log::debug;
+use rand::{rngs::OsRng, RngCore};
+
+use super::walletdb::{CoinState, TxDirection, TxHistoryEntry, WalletDb};
+use crate::{
+    crypto::{
+        coin::Coin,
+        constants::MERKLE_DEPTH,
+        keypair::{Keypair, PublicKey, SecretKey},
+        merkle_node::MerkleNode,
+        note::{Memo, Note},
+        nullifier::Nullifier,
+        token_list::DrkTokenList,
+        types::DrkTokenId,
+        OwnCoin,
+    },
+    impl_vec,
+    util::{
+        serial::{
+            deserialize, serialize, Decodable, Encodable, SerialDecodable, SerialEncodable, VarInt,
+        },
+        Timestamp,
+    },
+    Error, Result,
+};
+
+/// Start of every wallet export file
+pub const EXPORT_MAGIC: &[u8; 8] = b"DRKWLLET";
+/// Version of the file layout written
+pub const EXPORT_VERSION: u8 = 1;
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+const HEADER_SIZE: usize = EXPORT_MAGIC.len() + 1 + SALT_SIZE + NONCE_SIZE;
+
+/// A secret key of the wallet
+#[derive(Clone, Debug, PartialEq, SerialEncodable, SerialDecodable)]
+pub struct ExportedKey {
+    pub secret: SecretKey,
+    pub is_default: bool,
+}
+
+impl_vec!(ExportedKey);
+
+/// A coin of the wallet, spent or not
+#[derive(Clone, Debug, PartialEq, SerialEncodable, SerialDecodable)]
+pub struct ExportedCoin {
+    pub coin: Coin,
+    pub note: Note,
+    pub secret: SecretKey,
+    pub nullifier: Nullifier,
+    pub leaf_position: incrementalmerkletree::Position,
+    /// [`CoinState`] of the coin
+    pub state: u8,
+}
+
+impl_vec!(ExportedCoin);
+
+/// An entry of the transaction log
+#[derive(Clone, Debug, PartialEq, SerialEncodable, SerialDecodable)]
+pub struct ExportedTx {
+    pub timestamp: Timestamp,
+    /// [`TxDirection`] of the transaction
+    pub direction: u8,
+    pub value: u64,
+    pub token_id: DrkTokenId,
+    pub tx_hash: [u8; 32],
+    pub memo: Memo,
+}
+
+impl_vec!(ExportedTx);
+
+/// Contents of a wallet export
+#[derive(Clone, Debug, PartialEq, SerialEncodable, SerialDecodable)]
+pub struct WalletExport {
+    /// When the export was made
+    pub timestamp: Timestamp,
+    /// Slot of the last block the wallet had seen
+    pub slot: u64,
+    /// Hash of the last block the wallet had seen
+    pub block: [u8; 32],
+    pub keys: Vec<ExportedKey>,
+    pub seed: Option<Vec<u8>>,
+    pub coins: Vec<ExportedCoin>,
+    /// Merkle tree as of `block`, in the wallet's own encoding
+    pub tree: Vec<u8>,
+    pub tx_history: Vec<ExportedTx>,
+}
+
+impl WalletExport {
+    /// Encrypt the export under `passphrase` into the file format
+    pub fn encrypt(&self, passphrase: &str) -> Result<Vec<u8>> {
+        let mut salt = [0u8; SALT_SIZE];
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let mut data = Vec::with_capacity(HEADER_SIZE);
+        data.extend_from_slice(EXPORT_MAGIC);
+        data.push(EXPORT_VERSION);
+        data.extend_from_slice(&salt);
+        data.extend_from_slice(&nonce);
+
+        let plaintext = serialize(self);
+        let mut ciphertext = vec![0u8; plaintext.len() + TAG_SIZE];
+        let key = export_key(passphrase, &salt)?;
+        ChachaPolyIetf::aead_cipher()
+            .seal_to(&mut ciphertext, &plaintext, &data, &key, &nonce)
+            .map_err(|_| Error::WalletExportInvalid("encryption failed".into()))?;
+
+        data.extend_from_slice(&ciphertext);
+        Ok(data)
+    }
+
+    /// Decrypt an export file with `passphrase`
+    pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Self> {
+        if data.len() < HEADER_SIZE + TAG_SIZE || &data[..EXPORT_MAGIC.len()] != EXPORT_MAGIC {
+            return Err(Error::WalletExportInvalid("not a wallet export".into()))
+        }
+
+        let version = data[EXPORT_MAGIC.len()];
+        if version != EXPORT_VERSION {
+            return Err(Error::UnsupportedWalletExport(version))
+        }
+
+        let (header, ciphertext) = data.split_at(HEADER_SIZE);
+        let salt = &header[EXPORT_MAGIC.len() + 1..EXPORT_MAGIC.len() + 1 + SALT_SIZE];
+        let nonce = &header[HEADER_SIZE - NONCE_SIZE..];
+
+        let key = export_key(passphrase, salt)?;
+        let mut plaintext = vec![0u8; ciphertext.len()];
+        let len = ChachaPolyIetf::aead_cipher()
+            .open_to(&mut plaintext, ciphertext, header, &key, nonce)
+            .map_err(|_| Error::WalletExportDecryptFailed)?;
+        plaintext.truncate(len);
+
+        deserialize(&plaintext)
+    }
+
+    /// The exported Merkle tree, with the witnesses of the exported coins
+    pub fn merkle_tree(&self) -> Result<BridgeTree<MerkleNode, MERKLE_DEPTH>> {
+        let (tree, _read) =
+            bincode::serde::decode_from_slice(&self.tree, bincode::config::legacy())?;
+        Ok(tree)
+    }
+}
+
+/// Stretch the passphrase into the key of an export
+fn export_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| Error::WalletExportInvalid(e.to_string()))?;
+    Ok(key)
+}
+
+impl WalletDb {
+    /// Gather the wallet's contents. `slot` and `block` are the last block
+    /// the wallet's coins and tree are up to date with.
+    pub async fn export(&self, slot: u64, block: blake3::Hash) -> Result<WalletExport> {
+        debug!("Exporting wallet");
+        let default = self.get_default_keypair().await.ok();
+        let keys = self
+            .get_keypairs()
+            .await?
+            .into_iter()
+            .map(|kp| ExportedKey { secret: kp.secret, is_default: Some(kp) == default })
+            .collect();
+
+        let mut coins = vec![];
+        for state in [CoinState::Unspent, CoinState::Pending, CoinState::Spent] {
+            for oc in self.get_own_coins_in_state(state).await? {
+                coins.push(ExportedCoin {
+                    coin: oc.coin,
+                    note: oc.note,
+                    secret: oc.secret,
+                    nullifier: oc.nullifier,
+                    leaf_position: oc.leaf_position,
+                    state: state as u8,
+                });
+            }
+        }
+
+        let tree = self.get_tree().await?;
+        let tree = bincode::serde::encode_to_vec(&tree, bincode::config::legacy())?;
+
+        // Oldest first, the order they're logged back in on import
+        let mut tx_history: Vec<ExportedTx> = self
+            .get_tx_history(0, u32::MAX)
+            .await?
+            .into_iter()
+            .map(|entry| ExportedTx {
+                timestamp: entry.timestamp,
+                direction: entry.direction as u8,
+                value: entry.value,
+                token_id: entry.token_id,
+                tx_hash: *entry.tx_hash.as_bytes(),
+                memo: entry.memo,
+            })
+            .collect();
+        tx_history.reverse();
+
+        Ok(WalletExport {
+            timestamp: Timestamp::current_time(),
+            slot,
+            block: *block.as_bytes(),
+            keys,
+            seed: self.get_seed().await?,
+            coins,
+            tree,
+            tx_history,
+        })
+    }
+
+    /// Put the keys, seed, coins and transaction log of an export into
+    /// this wallet. The Merkle tree is left to the caller, which knows
+    /// whether it matches the chain.
+    pub async fn import(&self, export: &WalletExport, tokenlist: &DrkTokenList) -> Result<()> {
+        debug!("Importing wallet export of slot {}", export.slot);
+        let existing = self.get_keypairs().await?;
+        for key in &export.keys {
+            let public = PublicKey::from_secret(key.secret);
+            let keypair = Keypair { secret: key.secret, public };
+            if !existing.contains(&keypair) {
+                self.put_keypair(&keypair).await?;
+            }
+            if key.is_default {
+                self.set_default_keypair(&keypair.public).await?;
+            }
+        }
+
+        if let Some(seed) = &export.seed {
+            if self.get_seed().await?.as_ref() != Some(seed) {
+                self.put_seed(seed).await?;
+            }
+        }
+
+        let tokenlist = Arc::new(tokenlist.clone());
+        for coin in &export.coins {
+            let own_coin = OwnCoin {
+                coin: coin.coin,
+                note: coin.note,
+                secret: coin.secret,
+                nullifier: coin.nullifier,
+                leaf_position: coin.leaf_position,
+            };
+            self.put_own_coin(own_coin, tokenlist.clone()).await?;
+
+            match coin.state {
+                s if s == CoinState::Pending as u8 => self.set_pending(&[coin.nullifier]).await?,
+                s if s == CoinState::Spent as u8 => self.confirm_spent(&[coin.nullifier]).await?,
+                _ => {}
+            }
+        }
+
+        for tx in &export.tx_history {
+            let tx_hash = blake3::Hash::from(tx.tx_hash);
+            if self.tx_history_contains(&tx_hash).await? {
+                continue
+            }
+
+            let direction = match tx.direction {
+                0 => TxDirection::Sent,
+                _ => TxDirection::Received,
+            };
+            let entry = TxHistoryEntry {
+                timestamp: tx.timestamp,
+                direction,
+                value: tx.value,
+                token_id: tx.token_id,
+                tx_hash,
+                memo: tx.memo.clone(),
+            };
+            self.put_tx_history(&entry).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_roundtrip() {
+        let export = WalletExport {
+            timestamp: Timestamp(1657000000),
+            slot: 42,
+            block: [7u8; 32],
+            keys: vec![ExportedKey { secret: SecretKey::random(&mut OsRng), is_default: true }],
+            seed: Some(vec![1u8; 64]),
+            coins: vec![],
+            tree: vec![0u8; 8],
+            tx_history: vec![],
+        };
+
+        let data = export.encrypt("correct horse").unwrap();
+        assert_eq!(&data[..EXPORT_MAGIC.len()], EXPORT_MAGIC);
+        assert_eq!(WalletExport::decrypt(&data, "correct horse").unwrap(), export);
+        assert!(matches!(
+            WalletExport::decrypt(&data, "battery staple"),
+            Err(Error::WalletExportDecryptFailed)
+        ));
+
+        let mut tampered = data.clone();
+        tampered[EXPORT_MAGIC.len()] = EXPORT_VERSION + 1;
+        assert!(matches!(
+            WalletExport::decrypt(&tampered, "correct horse"),
+            Err(Error::UnsupportedWalletExport(_))
+        ));
+    }
+}
@@ -1,2 +1,3 @@
 //pub mod cashierdb;
+pub mod export;
 pub mod walletdb;
@@ -1,4 +1,4 @@
-use std::{fs::create_dir_all, path::Path, str::FromStr, time::Duration};
+use std::{collections::HashSet, fs::create_dir_all, path::Path, str::FromStr, time::Duration};
 
 use async_std::sync::Arc;
 use group::ff::PrimeField;
@@ -6,7 +6,7 @@ use incrementalmerkletree::bridgetree::BridgeTree;
 use log::{debug, error, info, warn, LevelFilter};
 use rand::rngs::OsRng;
 use sqlx::{
-    sqlite::{SqliteConnectOptions, SqliteJournalMode},
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteRow},
     ConnectOptions, Row, SqlitePool,
 };
 
@@ -17,8 +17,9 @@ use crate::{
         constants::MERKLE_DEPTH,
         keypair::{Keypair, PublicKey, SecretKey},
         merkle_node::MerkleNode,
-        note::Note,
+        note::{Memo, Note},
         nullifier::Nullifier,
+        token_id::MetadataCommitment,
         token_list::DrkTokenList,
         types::DrkTokenId,
         OwnCoin, OwnCoins,
@@ -26,14 +27,31 @@ use crate::{
     util::{
         expand_path,
         serial::{deserialize, serialize},
-        NetworkName,
+        NetworkName, Timestamp,
     },
-    Error::{WalletEmptyPassword, WalletTreeExists},
+    Error::{WalletEmptyPassword, WalletSeedExists, WalletSeedNotFound, WalletTreeExists},
     Result,
 };
 
 pub type WalletPtr = Arc<WalletDb>;
 
+/// Memo of a row, which is NULL for rows written before memos existed
+fn get_memo(row: &SqliteRow) -> Result<Memo> {
+    match row.get::<Option<Vec<u8>>, _>("memo") {
+        Some(v) => deserialize(&v),
+        None => Ok(Memo::default()),
+    }
+}
+
+/// Metadata commitment of a coin, which is NULL for rows written before
+/// unique assets existed
+fn get_metadata(row: &SqliteRow) -> Result<Option<MetadataCommitment>> {
+    match row.get::<Option<Vec<u8>>, _>("metadata") {
+        Some(v) => deserialize(&v),
+        None => Ok(None),
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Balance {
     pub token_id: DrkTokenId,
@@ -46,6 +64,81 @@ pub struct Balances {
     pub list: Vec<Balance>,
 }
 
+/// Unspent value held in the wallet for a single token ID. The sum of
+/// many coins can exceed a u64, so it's kept in a u128.
+#[derive(Clone, Debug)]
+pub struct TokenBalance {
+    pub token_id: DrkTokenId,
+    pub value: u128,
+    pub coins: usize,
+}
+
+impl Balances {
+    /// Sum up the unspent coins per token ID, in the order the
+    /// tokens were first seen in the wallet.
+    pub fn by_token(&self) -> Vec<TokenBalance> {
+        let mut ret: Vec<TokenBalance> = vec![];
+
+        for balance in &self.list {
+            match ret.iter_mut().find(|b| b.token_id == balance.token_id) {
+                Some(b) => {
+                    b.value += balance.value as u128;
+                    b.coins += 1;
+                }
+                None => ret.push(TokenBalance {
+                    token_id: balance.token_id,
+                    value: balance.value as u128,
+                    coins: 1,
+                }),
+            }
+        }
+
+        ret
+    }
+}
+
+/// Where a coin of the wallet is in its life. Stored in the `is_spent`
+/// column of the coins table, which predates pending spends.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CoinState {
+    /// Can be spent
+    Unspent = 0,
+    /// Its nullifier is on the chain
+    Spent = 1,
+    /// Spent by a transaction we made that isn't on the chain yet
+    Pending = 2,
+}
+
+/// Whether a transaction moved value out of or into the wallet
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TxDirection {
+    Sent = 0,
+    Received = 1,
+}
+
+impl TxDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxDirection::Sent => "sent",
+            TxDirection::Received => "received",
+        }
+    }
+}
+
+/// A single entry of the wallet's transaction log
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxHistoryEntry {
+    pub timestamp: Timestamp,
+    pub direction: TxDirection,
+    pub value: u64,
+    pub token_id: DrkTokenId,
+    pub tx_hash: blake3::Hash,
+    /// Memo of the payment, empty if it had none
+    pub memo: Memo,
+}
+
 pub struct WalletDb {
     pub conn: SqlitePool,
 }
@@ -58,6 +151,24 @@ pub async fn init_wallet(wallet_path: &str, wallet_pass: &str) -> Result<WalletP
     Ok(wallet)
 }
 
+/// Open the SQLite wallet at `wallet_path` read-only, and return the
+/// number of tables in it. Fails if the wallet is missing, or can't be
+/// decrypted with `wallet_pass`.
+pub async fn check_wallet(wallet_path: &Path, wallet_pass: &str) -> Result<i64> {
+    let path = format!("sqlite://{}", wallet_path.to_str().unwrap());
+    let mut connect_opts = SqliteConnectOptions::from_str(&path)?
+        .pragma("key", wallet_pass.to_string())
+        .read_only(true)
+        .create_if_missing(false);
+    connect_opts.log_statements(LevelFilter::Trace);
+
+    let mut conn = connect_opts.connect().await?;
+    let row = sqlx::query("SELECT count(*) FROM sqlite_master WHERE type = 'table';")
+        .fetch_one(&mut conn)
+        .await?;
+    Ok(row.get(0))
+}
+
 impl WalletDb {
     pub async fn new(path: &str, password: &str) -> Result<WalletPtr> {
         if password.trim().is_empty() {
@@ -91,7 +202,9 @@ impl WalletDb {
         info!("Initializing wallet database");
         let tree = include_str!("../../script/sql/tree.sql");
         let keys = include_str!("../../script/sql/keys.sql");
+        let seed = include_str!("../../script/sql/seed.sql");
         let coins = include_str!("../../script/sql/coins.sql");
+        let tx_history = include_str!("../../script/sql/tx_history.sql");
 
         let mut conn = self.conn.acquire().await?;
 
@@ -101,8 +214,37 @@ impl WalletDb {
         debug!("Initializing keys table");
         sqlx::query(keys).execute(&mut conn).await?;
 
+        debug!("Initializing seed table");
+        sqlx::query(seed).execute(&mut conn).await?;
+
         debug!("Initializing coins table");
         sqlx::query(coins).execute(&mut conn).await?;
+
+        debug!("Initializing transaction history table");
+        sqlx::query(tx_history).execute(&mut conn).await?;
+
+        // Columns added since the tables were first made
+        self.add_column("coins", "memo", "BLOB").await?;
+        self.add_column("tx_history", "memo", "BLOB").await?;
+        self.add_column("coins", "metadata", "BLOB").await?;
+        self.add_column("coins", "pending_since", "INTEGER").await?;
+        Ok(())
+    }
+
+    /// Add a column to a table of a wallet made before it existed
+    async fn add_column(&self, table: &str, column: &str, typ: &str) -> Result<()> {
+        let mut conn = self.conn.acquire().await?;
+        let columns =
+            sqlx::query(&format!("PRAGMA table_info({});", table)).fetch_all(&mut conn).await?;
+
+        if columns.iter().any(|row| row.get::<String, _>("name") == column) {
+            return Ok(())
+        }
+
+        debug!("Adding {} column to {} table", column, table);
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {};", table, column, typ))
+            .execute(&mut conn)
+            .await?;
         Ok(())
     }
 
@@ -181,7 +323,12 @@ impl WalletDb {
 
         let keypair = if default_keypair.is_err() {
             let keypairs = self.get_keypairs().await?;
-            let kp = if keypairs.is_empty() { self.keygen().await? } else { keypairs[0] };
+            let kp = match (keypairs.first(), self.get_seed().await?) {
+                (Some(kp), _) => *kp,
+                // Wallets with a seed derive all their keys from it
+                (None, Some(_)) => self.derive_keypair(0, 0).await?,
+                (None, None) => self.keygen().await?,
+            };
             self.set_default_keypair(&kp.public).await?;
             kp
         } else {
@@ -206,6 +353,79 @@ impl WalletDb {
         Ok(keypairs)
     }
 
+    /// Keep the seed keys are derived from. A wallet has at most one seed,
+    /// which can't be replaced, since keys derived from it would no longer
+    /// be restorable from the mnemonic that was backed up.
+    pub async fn put_seed(&self, seed: &[u8]) -> Result<()> {
+        debug!("Writing seed into the wallet database");
+        if self.get_seed().await?.is_some() {
+            return Err(WalletSeedExists)
+        }
+
+        let mut conn = self.conn.acquire().await?;
+        sqlx::query("INSERT INTO seed(seed) VALUES (?1)").bind(seed).execute(&mut conn).await?;
+        Ok(())
+    }
+
+    /// Keep a restored seed, derive the first `count` keys of its first
+    /// account, and make the first one the default, so the main address
+    /// comes back along with the seed. At least the first key is derived.
+    pub async fn restore_seed(&self, seed: &[u8], count: u32) -> Result<Vec<Keypair>> {
+        self.put_seed(seed).await?;
+        let keypairs = self.derive_keypairs(0, 0..count.max(1)).await?;
+        self.set_default_keypair(&keypairs[0].public).await?;
+        Ok(keypairs)
+    }
+
+    pub async fn get_seed(&self) -> Result<Option<Vec<u8>>> {
+        debug!("Returning seed");
+        let mut conn = self.conn.acquire().await?;
+        let row = sqlx::query("SELECT seed FROM seed").fetch_optional(&mut conn).await?;
+        Ok(row.map(|x| x.get("seed")))
+    }
+
+    /// Derive the keypair at `account/index` from the wallet seed, and put
+    /// it in the wallet unless it's there already.
+    pub async fn derive_keypair(&self, account: u32, index: u32) -> Result<Keypair> {
+        Ok(self.derive_keypairs(account, [index]).await?.remove(0))
+    }
+
+    /// Derive the keypairs at `account/index` for every index in `indexes`,
+    /// and put the ones that aren't in the wallet yet in a single transaction.
+    pub async fn derive_keypairs(
+        &self,
+        account: u32,
+        indexes: impl IntoIterator<Item = u32>,
+    ) -> Result<Vec<Keypair>> {
+        debug!("Deriving keypairs of account {}", account);
+        let seed = match self.get_seed().await? {
+            Some(v) => v,
+            None => return Err(WalletSeedNotFound),
+        };
+
+        let mut known: HashSet<Vec<u8>> =
+            self.get_keypairs().await?.iter().map(|x| serialize(&x.public)).collect();
+
+        let mut tx = self.conn.begin().await?;
+        let mut keypairs = vec![];
+        for index in indexes {
+            let keypair = Keypair::derive(&seed, account, index);
+            let pubkey = serialize(&keypair.public);
+            if known.insert(pubkey.clone()) {
+                sqlx::query("INSERT INTO keys(public, secret, is_default) VALUES (?1, ?2, ?3)")
+                    .bind(pubkey)
+                    .bind(serialize(&keypair.secret))
+                    .bind(0)
+                    .execute(&mut tx)
+                    .await?;
+            }
+            keypairs.push(keypair);
+        }
+        tx.commit().await?;
+
+        Ok(keypairs)
+    }
+
     pub async fn tree_gen(&self) -> Result<BridgeTree<MerkleNode, MERKLE_DEPTH>> {
         debug!("Attempting to generate merkle tree");
         let mut conn = self.conn.acquire().await?;
@@ -251,13 +471,17 @@ impl WalletDb {
         Ok(())
     }
 
+    /// The coins that can be spent
     pub async fn get_own_coins(&self) -> Result<OwnCoins> {
-        debug!("Finding own coins");
-        let is_spent = 0;
+        self.get_own_coins_in_state(CoinState::Unspent).await
+    }
+
+    pub async fn get_own_coins_in_state(&self, state: CoinState) -> Result<OwnCoins> {
+        debug!("Finding own coins in state {:?}", state);
 
         let mut conn = self.conn.acquire().await?;
         let rows = sqlx::query("SELECT * FROM coins WHERE is_spent = ?1;")
-            .bind(is_spent)
+            .bind(state as u8)
             .fetch_all(&mut conn)
             .await?;
 
@@ -272,7 +496,18 @@ impl WalletDb {
             let value = deserialize(row.get("value"))?;
             let token_id = deserialize(row.get("drk_address"))?;
             let token_blind = deserialize(row.get("token_blind"))?;
-            let note = Note { serial, value, token_id, coin_blind, value_blind, token_blind };
+            let memo = get_memo(&row)?;
+            let metadata = get_metadata(&row)?;
+            let note = Note {
+                serial,
+                value,
+                token_id,
+                coin_blind,
+                value_blind,
+                token_blind,
+                memo,
+                metadata,
+            };
 
             let secret = deserialize(row.get("secret"))?;
             let nullifier = deserialize(row.get("nullifier"))?;
@@ -303,7 +538,9 @@ impl WalletDb {
         let secret = serialize(&own_coin.secret);
         let nullifier = serialize(&own_coin.nullifier);
         let leaf_position = serialize(&own_coin.leaf_position);
-        let is_spent: u8 = 0;
+        let memo = serialize(&own_coin.note.memo);
+        let metadata = serialize(&own_coin.note.metadata);
+        let is_spent = CoinState::Unspent as u8;
 
         let token_id_enc = bs58::encode(&own_coin.note.token_id.to_repr()).into_string();
 
@@ -322,9 +559,9 @@ impl WalletDb {
             "INSERT OR REPLACE INTO coins
             (coin, serial, coin_blind, valcom_blind, token_blind, value,
              network, drk_address, net_address,
-             secret, is_spent, nullifier, leaf_position)
+             secret, is_spent, nullifier, leaf_position, memo, metadata)
             VALUES
-             (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13);",
+             (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15);",
         )
         .bind(coin)
         .bind(serial)
@@ -339,6 +576,8 @@ impl WalletDb {
         .bind(is_spent)
         .bind(nullifier)
         .bind(leaf_position)
+        .bind(memo)
+        .bind(metadata)
         .execute(&mut conn)
         .await?;
 
@@ -352,24 +591,109 @@ impl WalletDb {
         Ok(())
     }
 
-    pub async fn confirm_spend_coin(&self, coin: &Coin) -> Result<()> {
-        debug!("Confirm spend coin");
-        let is_spent = 1;
-        let coin = serialize(coin);
+    /// Remove a single coin, e.g. when the block containing it is rolled back.
+    pub async fn remove_own_coin(&self, coin: &Coin) -> Result<()> {
+        debug!("Removing own coin from wallet database");
+        let coin = serialize(&coin.to_bytes());
 
         let mut conn = self.conn.acquire().await?;
-        sqlx::query("UPDATE coins SET is_spent = ?1 WHERE coin = ?2;")
-            .bind(is_spent)
-            .bind(coin)
+        sqlx::query("DELETE FROM coins WHERE coin = ?1;").bind(coin).execute(&mut conn).await?;
+
+        Ok(())
+    }
+
+    /// Mark the unspent coins with the given nullifiers as spent by a
+    /// transaction that isn't on the chain yet, so they aren't picked again.
+    pub async fn set_pending(&self, nullifiers: &[Nullifier]) -> Result<()> {
+        debug!("Marking coins pending");
+        let now = Timestamp::current_time();
+
+        let mut conn = self.conn.acquire().await?;
+        for nullifier in nullifiers {
+            sqlx::query(
+                "UPDATE coins SET is_spent = ?1, pending_since = ?2
+                 WHERE nullifier = ?3 AND is_spent = ?4;",
+            )
+            .bind(CoinState::Pending as u8)
+            .bind(now.0)
+            .bind(serialize(nullifier))
+            .bind(CoinState::Unspent as u8)
             .execute(&mut conn)
             .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Make coins that have been pending since before `since` spendable
+    /// again, since the transaction spending them didn't make it to the
+    /// chain in time. Returns how many coins were released.
+    pub async fn release_stale_pending(&self, since: Timestamp) -> Result<u64> {
+        debug!("Releasing stale pending coins");
+        let mut conn = self.conn.acquire().await?;
+        let result = sqlx::query(
+            "UPDATE coins SET is_spent = ?1
+             WHERE is_spent = ?2 AND (pending_since IS NULL OR pending_since < ?3);",
+        )
+        .bind(CoinState::Unspent as u8)
+        .bind(CoinState::Pending as u8)
+        .bind(since.0)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Make coins pending on a transaction that didn't make it, e.g. one
+    /// that failed to broadcast, spendable again.
+    pub async fn release_pending(&self, nullifiers: &[Nullifier]) -> Result<()> {
+        debug!("Releasing pending coins");
+        self.update_coin_state(nullifiers, Some(CoinState::Pending), CoinState::Unspent).await
+    }
+
+    /// Mark the coins whose nullifiers appeared on the chain as spent,
+    /// whether we spent them from this wallet or not.
+    pub async fn confirm_spent(&self, nullifiers: &[Nullifier]) -> Result<()> {
+        debug!("Confirming spent coins");
+        self.update_coin_state(nullifiers, None, CoinState::Spent).await
+    }
+
+    /// Make coins spent by a rolled back block spendable again.
+    pub async fn unconfirm_spent(&self, nullifiers: &[Nullifier]) -> Result<()> {
+        debug!("Unconfirming spent coins");
+        self.update_coin_state(nullifiers, Some(CoinState::Spent), CoinState::Unspent).await
+    }
+
+    /// Move the coins with the given nullifiers that are in state `from`,
+    /// or any state if `None`, to state `to`.
+    async fn update_coin_state(
+        &self,
+        nullifiers: &[Nullifier],
+        from: Option<CoinState>,
+        to: CoinState,
+    ) -> Result<()> {
+        let mut conn = self.conn.acquire().await?;
+        for nullifier in nullifiers {
+            let query = match from {
+                Some(from) => sqlx::query(
+                    "UPDATE coins SET is_spent = ?1 WHERE nullifier = ?2 AND is_spent = ?3;",
+                )
+                .bind(to as u8)
+                .bind(serialize(nullifier))
+                .bind(from as u8),
+                None => sqlx::query("UPDATE coins SET is_spent = ?1 WHERE nullifier = ?2;")
+                    .bind(to as u8)
+                    .bind(serialize(nullifier)),
+            };
+            query.execute(&mut conn).await?;
+        }
 
         Ok(())
     }
 
     pub async fn get_balances(&self) -> Result<Balances> {
         debug!("Getting tokens and balances");
-        let is_spent = 0;
+        let is_spent = CoinState::Unspent as u8;
 
         let mut conn = self.conn.acquire().await?;
         let rows =
@@ -391,9 +715,109 @@ impl WalletDb {
         Ok(Balances { list })
     }
 
+    pub async fn put_tx_history(&self, entry: &TxHistoryEntry) -> Result<()> {
+        debug!("Putting transaction history entry into wallet database");
+        let value = serialize(&entry.value);
+        let token_id = serialize(&entry.token_id);
+        let tx_hash = serialize(&entry.tx_hash);
+        let memo = serialize(&entry.memo);
+
+        let mut conn = self.conn.acquire().await?;
+        sqlx::query(
+            "INSERT INTO tx_history
+            (timestamp, direction, value, token_id, tx_hash, memo)
+            VALUES
+            (?1, ?2, ?3, ?4, ?5, ?6);",
+        )
+        .bind(entry.timestamp.0)
+        .bind(entry.direction as u8)
+        .bind(value)
+        .bind(token_id)
+        .bind(tx_hash)
+        .bind(memo)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch a page of the transaction log, newest entries first.
+    pub async fn get_tx_history(&self, offset: u32, limit: u32) -> Result<Vec<TxHistoryEntry>> {
+        debug!("Getting transaction history");
+
+        let mut conn = self.conn.acquire().await?;
+        let rows = sqlx::query("SELECT * FROM tx_history ORDER BY id DESC LIMIT ?1 OFFSET ?2;")
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&mut conn)
+            .await?;
+
+        let mut entries = vec![];
+        for row in rows {
+            let timestamp = Timestamp(row.get("timestamp"));
+            let direction = match row.get::<u8, _>("direction") {
+                0 => TxDirection::Sent,
+                _ => TxDirection::Received,
+            };
+            let value = deserialize(row.get("value"))?;
+            let token_id = deserialize(row.get("token_id"))?;
+            let tx_hash = deserialize(row.get("tx_hash"))?;
+            let memo = get_memo(&row)?;
+            entries.push(TxHistoryEntry { timestamp, direction, value, token_id, tx_hash, memo });
+        }
+
+        Ok(entries)
+    }
+
+    /// Check if the wallet logged sending the given transaction.
+    pub async fn tx_history_sent(&self, tx_hash: &blake3::Hash) -> Result<bool> {
+        debug!("Checking if transaction was sent by this wallet");
+        let tx_hash = serialize(tx_hash);
+
+        let mut conn = self.conn.acquire().await?;
+        let row = sqlx::query("SELECT id FROM tx_history WHERE tx_hash = ?1 AND direction = ?2;")
+            .bind(tx_hash)
+            .bind(TxDirection::Sent as u8)
+            .fetch_optional(&mut conn)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    /// Remove the coins received from the given transactions from the
+    /// history, e.g. when the block containing them is rolled back. What
+    /// the wallet sent stays, since those transactions can still make it.
+    pub async fn remove_received_tx_history(&self, tx_hashes: &[blake3::Hash]) -> Result<()> {
+        debug!("Removing received transactions from the history");
+        let mut conn = self.conn.acquire().await?;
+        for tx_hash in tx_hashes {
+            sqlx::query("DELETE FROM tx_history WHERE tx_hash = ?1 AND direction = ?2;")
+                .bind(serialize(tx_hash))
+                .bind(TxDirection::Received as u8)
+                .execute(&mut conn)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Check if the wallet logged the given transaction in any direction.
+    pub async fn tx_history_contains(&self, tx_hash: &blake3::Hash) -> Result<bool> {
+        debug!("Checking if transaction is in the wallet history");
+        let tx_hash = serialize(tx_hash);
+
+        let mut conn = self.conn.acquire().await?;
+        let row = sqlx::query("SELECT id FROM tx_history WHERE tx_hash = ?1;")
+            .bind(tx_hash)
+            .fetch_optional(&mut conn)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
     pub async fn get_token_id(&self) -> Result<Vec<DrkTokenId>> {
         debug!("Getting token ID");
-        let is_spent = 0;
+        let is_spent = CoinState::Unspent as u8;
 
         let mut conn = self.conn.acquire().await?;
         let rows = sqlx::query("SELECT drk_address FROM coins WHERE is_spent = ?1;")
@@ -413,7 +837,7 @@ impl WalletDb {
     pub async fn token_id_exists(&self, token_id: DrkTokenId) -> Result<bool> {
         debug!("Checking if token ID exists");
 
-        let is_spent = 0;
+        let is_spent = CoinState::Unspent as u8;
         let id = serialize(&token_id);
 
         let mut conn = self.conn.acquire().await?;
@@ -458,6 +882,8 @@ mod tests {
             coin_blind: DrkCoinBlind::random(&mut OsRng),
             value_blind: DrkValueBlind::random(&mut OsRng),
             token_blind: DrkValueBlind::random(&mut OsRng),
+            memo: Memo::default(),
+            metadata: None,
         };
 
         let coin = Coin(pallas::Base::random(&mut OsRng));
@@ -534,6 +960,43 @@ mod tests {
         assert_eq!(balances.list[2].value, 42);
         assert_eq!(balances.list[3].token_id, token_id);
 
+        let by_token = balances.by_token();
+        assert_eq!(by_token.len(), 1);
+        assert_eq!(by_token[0].token_id, token_id);
+        assert_eq!(by_token[0].value, 69 + 420 + 42 + 11);
+        assert_eq!(by_token[0].coins, 4);
+
+        // put_tx_history()
+        let tx_hash = blake3::hash(b"tx");
+        let sent = TxHistoryEntry {
+            timestamp: Timestamp(1),
+            direction: TxDirection::Sent,
+            value: 69,
+            token_id,
+            tx_hash,
+            memo: Memo::default(),
+        };
+        let received = TxHistoryEntry {
+            timestamp: Timestamp(2),
+            direction: TxDirection::Received,
+            value: 420,
+            token_id,
+            tx_hash: blake3::hash(b"tx2"),
+            memo: Memo::new(b"Invoice #1337")?,
+        };
+        wallet.put_tx_history(&sent).await?;
+        wallet.put_tx_history(&received).await?;
+
+        // get_tx_history()
+        let history = wallet.get_tx_history(0, 10).await?;
+        assert_eq!(history, vec![received.clone(), sent]);
+        assert_eq!(wallet.get_tx_history(0, 1).await?, vec![received.clone()]);
+        assert!(wallet.get_tx_history(2, 10).await?.is_empty());
+        assert!(wallet.tx_history_sent(&tx_hash).await?);
+        assert!(!wallet.tx_history_sent(&received.tx_hash).await?);
+        assert!(wallet.tx_history_contains(&received.tx_hash).await?);
+        assert!(!wallet.tx_history_contains(&blake3::hash(b"tx3")).await?);
+
         /////////////////
         //// keypair ////
         /////////////////
@@ -557,6 +1020,23 @@ mod tests {
         assert_eq!(own_coins[2], c2);
         assert_eq!(own_coins[3], c3);
 
+        // Coin states
+        wallet.set_pending(&[c0.nullifier, c1.nullifier]).await?;
+        assert_eq!(wallet.get_own_coins_in_state(CoinState::Pending).await?, vec![c0, c1]);
+        assert_eq!(wallet.get_balances().await?.list.len(), 2);
+        wallet.release_pending(&[c1.nullifier]).await?;
+        assert_eq!(wallet.release_stale_pending(Timestamp(0)).await?, 0);
+        wallet.set_pending(&[c1.nullifier]).await?;
+        let since = Timestamp(Timestamp::current_time().0 + 1);
+        assert_eq!(wallet.release_stale_pending(since).await?, 2);
+        wallet.set_pending(&[c0.nullifier]).await?;
+        wallet.confirm_spent(&[c0.nullifier, c2.nullifier]).await?;
+        assert_eq!(wallet.get_own_coins().await?, vec![c1, c3]);
+        assert!(wallet.get_own_coins_in_state(CoinState::Pending).await?.is_empty());
+        assert_eq!(wallet.get_own_coins_in_state(CoinState::Spent).await?, vec![c0, c2]);
+        wallet.unconfirm_spent(&[c2.nullifier]).await?;
+        assert_eq!(wallet.get_own_coins().await?, vec![c1, c2, c3]);
+
         // get_tree()
         let tree2 = wallet.get_tree().await?;
         let root2 = tree2.root(0).unwrap();
@@ -570,4 +1050,41 @@ mod tests {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn test_wallet_seed() -> Result<()> {
+        let wallet = WalletDb::new("sqlite::memory:", WPASS).await?;
+        wallet.init_db().await?;
+
+        assert!(matches!(wallet.derive_keypair(0, 0).await, Err(WalletSeedNotFound)));
+
+        let seed = [7u8; 64];
+        wallet.put_seed(&seed).await?;
+        assert_eq!(wallet.get_seed().await?, Some(seed.to_vec()));
+        assert!(matches!(wallet.put_seed(&[8u8; 64]).await, Err(WalletSeedExists)));
+
+        // Deriving the same key twice only puts it in once
+        let keypair = wallet.derive_keypair(0, 0).await?;
+        assert_eq!(keypair, Keypair::derive(&seed, 0, 0));
+        wallet.derive_keypair(0, 0).await?;
+        wallet.derive_keypair(0, 1).await?;
+        assert_eq!(wallet.get_keypairs().await?.len(), 2);
+
+        // A batch skips the keys that are already in the wallet
+        let keypairs = wallet.derive_keypairs(0, 0..4).await?;
+        assert_eq!(keypairs.len(), 4);
+        assert_eq!(keypairs[3], Keypair::derive(&seed, 0, 3));
+        assert_eq!(wallet.get_keypairs().await?.len(), 4);
+
+        // A restored seed brings back the default key, in place of the one
+        // the wallet was made with
+        let wallet = WalletDb::new("sqlite::memory:", WPASS).await?;
+        wallet.init_db().await?;
+        wallet.get_default_keypair_or_create_one().await?;
+        let keypairs = wallet.restore_seed(&seed, 2).await?;
+        assert_eq!(keypairs.len(), 2);
+        assert_eq!(wallet.get_default_keypair().await?, Keypair::derive(&seed, 0, 0));
+
+        Ok(())
+    }
 }
@@ -1,6 +1,9 @@
 pub mod burn_contract;
 pub use burn_contract::BurnContract;
 
+pub mod membership_contract;
+pub use membership_contract::MembershipContract;
+
 pub mod mint_contract;
 pub use mint_contract::MintContract;
 
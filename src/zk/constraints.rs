@@ -0,0 +1,145 @@
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::Value,
+    plonk,
+    plonk::{
+        Advice, Any, Assigned, Assignment, Circuit, Column, ConstraintSystem, Fixed, FloorPlanner,
+        Instance, Selector,
+    },
+};
+use pasta_curves::pallas;
+
+use super::{vm::ZkCircuit, vm_stack::empty_witnesses};
+use crate::{zkas::decoder::ZkBinary, Result};
+
+/// Constraints a circuit is made of
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConstraintCount {
+    /// Rows a gate is enabled on
+    pub gates: usize,
+    /// Pairs of cells constrained to be equal
+    pub copies: usize,
+}
+
+impl ConstraintCount {
+    pub fn total(&self) -> usize {
+        self.gates + self.copies
+    }
+}
+
+/// Count the constraints of a circuit by laying it out without witnesses.
+pub fn constraint_count(zkbin: &ZkBinary) -> Result<ConstraintCount> {
+    let circuit = ZkCircuit::new(empty_witnesses(zkbin), zkbin.clone());
+
+    let mut cs = ConstraintSystem::default();
+    let config = ZkCircuit::configure(&mut cs);
+    let constants = vec![config.constants];
+
+    let mut counter = Counter::default();
+    <ZkCircuit as Circuit<pallas::Base>>::FloorPlanner::synthesize(
+        &mut counter,
+        &circuit,
+        config,
+        constants,
+    )?;
+
+    Ok(counter.0)
+}
+
+/// Layout that only counts enabled selectors and copies
+#[derive(Default)]
+struct Counter(ConstraintCount);
+
+impl<F: Field> Assignment<F> for Counter {
+    fn enter_region<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn exit_region(&mut self) {}
+
+    fn enable_selector<A, AR>(
+        &mut self,
+        _: A,
+        _: &Selector,
+        _: usize,
+    ) -> std::result::Result<(), plonk::Error>
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.0.gates += 1;
+        Ok(())
+    }
+
+    fn query_instance(
+        &self,
+        _: Column<Instance>,
+        _: usize,
+    ) -> std::result::Result<Value<F>, plonk::Error> {
+        Ok(Value::unknown())
+    }
+
+    fn assign_advice<V, VR, A, AR>(
+        &mut self,
+        _: A,
+        _: Column<Advice>,
+        _: usize,
+        _: V,
+    ) -> std::result::Result<(), plonk::Error>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<F>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        Ok(())
+    }
+
+    fn assign_fixed<V, VR, A, AR>(
+        &mut self,
+        _: A,
+        _: Column<Fixed>,
+        _: usize,
+        _: V,
+    ) -> std::result::Result<(), plonk::Error>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<F>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        Ok(())
+    }
+
+    fn copy(
+        &mut self,
+        _: Column<Any>,
+        _: usize,
+        _: Column<Any>,
+        _: usize,
+    ) -> std::result::Result<(), plonk::Error> {
+        self.0.copies += 1;
+        Ok(())
+    }
+
+    fn fill_from_row(
+        &mut self,
+        _: Column<Fixed>,
+        _: usize,
+        _: Value<Assigned<F>>,
+    ) -> std::result::Result<(), plonk::Error> {
+        Ok(())
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self, _: Option<String>) {}
+}
@@ -0,0 +1,214 @@
+use halo2_proofs::{
+    arithmetic::FieldExt,
+    circuit::{AssignedCell, Chip, Layouter},
+    plonk,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Expression, Selector},
+    poly::Rotation,
+};
+use pasta_curves::pallas;
+
+pub trait CondSelectInstruction<F: FieldExt>: Chip<F> {
+    /// Return `a` if `cond` is 1, and `b` if it is 0. `cond` is
+    /// constrained to be boolean.
+    fn conditional_select(
+        &self,
+        layouter: impl Layouter<F>,
+        cond: &AssignedCell<F, F>,
+        a: &AssignedCell<F, F>,
+        b: &AssignedCell<F, F>,
+    ) -> Result<AssignedCell<F, F>, plonk::Error>;
+}
+
+#[derive(Clone, Debug)]
+pub struct CondSelectConfig {
+    cond: Column<Advice>,
+    a: Column<Advice>,
+    b: Column<Advice>,
+    out: Column<Advice>,
+    q_select: Selector,
+}
+
+/// Selection between two Base field elements, witnessed as
+/// `out = b + cond * (a - b)` with a boolean `cond`.
+pub struct CondSelectChip {
+    config: CondSelectConfig,
+}
+
+impl Chip<pallas::Base> for CondSelectChip {
+    type Config = CondSelectConfig;
+    type Loaded = ();
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn loaded(&self) -> &Self::Loaded {
+        &()
+    }
+}
+
+impl CondSelectChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<pallas::Base>,
+        advices: [Column<Advice>; 4],
+    ) -> CondSelectConfig {
+        let [cond, a, b, out] = advices;
+        let q_select = meta.selector();
+
+        meta.create_gate("Conditional selection: out = cond ? a : b", |meta| {
+            let q_select = meta.query_selector(q_select);
+            let cond = meta.query_advice(cond, Rotation::cur());
+            let a = meta.query_advice(a, Rotation::cur());
+            let b = meta.query_advice(b, Rotation::cur());
+            let out = meta.query_advice(out, Rotation::cur());
+
+            let one = Expression::Constant(pallas::Base::one());
+
+            Constraints::with_selector(
+                q_select,
+                [
+                    ("cond is boolean", cond.clone() * (one - cond.clone())),
+                    ("out = b + cond * (a - b)", b.clone() + cond * (a - b) - out),
+                ],
+            )
+        });
+
+        CondSelectConfig { cond, a, b, out, q_select }
+    }
+
+    pub fn construct(config: CondSelectConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl CondSelectInstruction<pallas::Base> for CondSelectChip {
+    fn conditional_select(
+        &self,
+        mut layouter: impl Layouter<pallas::Base>,
+        cond: &AssignedCell<pallas::Base, pallas::Base>,
+        a: &AssignedCell<pallas::Base, pallas::Base>,
+        b: &AssignedCell<pallas::Base, pallas::Base>,
+    ) -> Result<AssignedCell<pallas::Base, pallas::Base>, plonk::Error> {
+        layouter.assign_region(
+            || "out = cond ? a : b",
+            |mut region| {
+                self.config.q_select.enable(&mut region, 0)?;
+
+                cond.copy_advice(|| "copy cond", &mut region, self.config.cond, 0)?;
+                a.copy_advice(|| "copy a", &mut region, self.config.a, 0)?;
+                b.copy_advice(|| "copy b", &mut region, self.config.b, 0)?;
+
+                let out = cond
+                    .value()
+                    .zip(a.value())
+                    .zip(b.value())
+                    .map(|((cond, a), b)| *b + *cond * (*a - *b));
+
+                region.assign_advice(|| "out", self.config.out, 0, || out)
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zk::assign_free_advice;
+    use halo2_proofs::{
+        circuit::{floor_planner, Value},
+        dev::MockProver,
+        plonk::{Circuit, Instance as InstanceColumn},
+    };
+
+    #[derive(Clone)]
+    struct CondSelectCircuitConfig {
+        primary: Column<InstanceColumn>,
+        advices: [Column<Advice>; 4],
+        select_config: CondSelectConfig,
+    }
+
+    #[derive(Default)]
+    struct CondSelectCircuit {
+        cond: Value<pallas::Base>,
+        a: Value<pallas::Base>,
+        b: Value<pallas::Base>,
+    }
+
+    impl Circuit<pallas::Base> for CondSelectCircuit {
+        type Config = CondSelectCircuitConfig;
+        type FloorPlanner = floor_planner::V1;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<pallas::Base>) -> Self::Config {
+            let advices = [
+                meta.advice_column(),
+                meta.advice_column(),
+                meta.advice_column(),
+                meta.advice_column(),
+            ];
+
+            let primary = meta.instance_column();
+            meta.enable_equality(primary);
+
+            for advice in advices.iter() {
+                meta.enable_equality(*advice);
+            }
+
+            let select_config = CondSelectChip::configure(meta, advices);
+
+            CondSelectCircuitConfig { primary, advices, select_config }
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<pallas::Base>,
+        ) -> std::result::Result<(), plonk::Error> {
+            let select_chip = CondSelectChip::construct(config.select_config.clone());
+
+            let cond = assign_free_advice(
+                layouter.namespace(|| "Load cond"),
+                config.advices[0],
+                self.cond,
+            )?;
+            let a = assign_free_advice(layouter.namespace(|| "Load a"), config.advices[1], self.a)?;
+            let b = assign_free_advice(layouter.namespace(|| "Load b"), config.advices[2], self.b)?;
+
+            let out = select_chip.conditional_select(
+                layouter.namespace(|| "cond ? a : b"),
+                &cond,
+                &a,
+                &b,
+            )?;
+            layouter.constrain_instance(out.cell(), config.primary, 0)
+        }
+    }
+
+    fn verifies(cond: u64, a: u64, b: u64, out: u64) -> bool {
+        let circuit = CondSelectCircuit {
+            cond: Value::known(pallas::Base::from(cond)),
+            a: Value::known(pallas::Base::from(a)),
+            b: Value::known(pallas::Base::from(b)),
+        };
+        let public_inputs = vec![pallas::Base::from(out)];
+
+        let prover = MockProver::run(4, &circuit, vec![public_inputs]).unwrap();
+        prover.verify().is_ok()
+    }
+
+    #[test]
+    fn cond_select_circuit() {
+        assert!(verifies(1, 42, 69, 42));
+        assert!(verifies(0, 42, 69, 69));
+
+        // Wrong results don't verify
+        assert!(!verifies(1, 42, 69, 69));
+        assert!(!verifies(0, 42, 69, 42));
+
+        // Neither do conditions that aren't boolean
+        assert!(!verifies(2, 42, 69, 15));
+    }
+}
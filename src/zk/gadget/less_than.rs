@@ -0,0 +1,282 @@
+use halo2_gadgets::{
+    sinsemilla::primitives as sinsemilla, utilities::lookup_range_check::LookupRangeCheckConfig,
+};
+use halo2_proofs::{
+    arithmetic::FieldExt,
+    circuit::{AssignedCell, Chip, Layouter},
+    plonk,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Expression, Selector},
+    poly::Rotation,
+};
+use pasta_curves::pallas;
+
+/// Bit size of the values the chip range checks and compares
+pub const NUM_BITS: usize = 64;
+
+pub trait LessThanInstruction<F: FieldExt>: Chip<F> {
+    /// Constrain `a` to fit in [`NUM_BITS`] bits.
+    fn range_check(
+        &self,
+        layouter: impl Layouter<F>,
+        a: &AssignedCell<F, F>,
+    ) -> Result<(), plonk::Error>;
+
+    /// Return 1 if `a < b`, and 0 otherwise. Both `a` and `b` are
+    /// constrained to fit in [`NUM_BITS`] bits.
+    fn less_than(
+        &self,
+        layouter: impl Layouter<F>,
+        a: &AssignedCell<F, F>,
+        b: &AssignedCell<F, F>,
+    ) -> Result<AssignedCell<F, F>, plonk::Error>;
+}
+
+#[derive(Clone, Debug)]
+pub struct LessThanConfig {
+    a: Column<Advice>,
+    b: Column<Advice>,
+    lt: Column<Advice>,
+    diff: Column<Advice>,
+    q_lt: Selector,
+    range_check: LookupRangeCheckConfig<pallas::Base, { sinsemilla::K }>,
+}
+
+/// Comparison of Base field elements holding [`NUM_BITS`]-bit values.
+///
+/// `a < b` is witnessed as a boolean `lt`, along with
+/// `diff = a - b + lt * 2^NUM_BITS`. `diff` only fits in [`NUM_BITS`] bits
+/// when `lt` is set correctly, which is enforced with the lookup range
+/// check shared with the Sinsemilla chip.
+pub struct LessThanChip {
+    config: LessThanConfig,
+}
+
+impl Chip<pallas::Base> for LessThanChip {
+    type Config = LessThanConfig;
+    type Loaded = ();
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn loaded(&self) -> &Self::Loaded {
+        &()
+    }
+}
+
+impl LessThanChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<pallas::Base>,
+        advices: [Column<Advice>; 4],
+        range_check: LookupRangeCheckConfig<pallas::Base, { sinsemilla::K }>,
+    ) -> LessThanConfig {
+        let [a, b, lt, diff] = advices;
+        let q_lt = meta.selector();
+
+        meta.create_gate("Field element comparison: lt = a < b", |meta| {
+            let q_lt = meta.query_selector(q_lt);
+            let a = meta.query_advice(a, Rotation::cur());
+            let b = meta.query_advice(b, Rotation::cur());
+            let lt = meta.query_advice(lt, Rotation::cur());
+            let diff = meta.query_advice(diff, Rotation::cur());
+
+            let one = Expression::Constant(pallas::Base::one());
+            let two_pow_n = Expression::Constant(pallas::Base::from_u128(1 << NUM_BITS));
+
+            Constraints::with_selector(
+                q_lt,
+                [
+                    ("lt is boolean", lt.clone() * (one - lt.clone())),
+                    ("diff = a - b + lt * 2^n", a - b + lt * two_pow_n - diff),
+                ],
+            )
+        });
+
+        LessThanConfig { a, b, lt, diff, q_lt, range_check }
+    }
+
+    pub fn construct(config: LessThanConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl LessThanInstruction<pallas::Base> for LessThanChip {
+    fn range_check(
+        &self,
+        mut layouter: impl Layouter<pallas::Base>,
+        a: &AssignedCell<pallas::Base, pallas::Base>,
+    ) -> Result<(), plonk::Error> {
+        // Decompose the low bits into words of the lookup table, and then
+        // check the remaining high bits are few enough.
+        let num_words = NUM_BITS / sinsemilla::K;
+        let zs = self.config.range_check.copy_check(
+            layouter.namespace(|| "Decompose low bits"),
+            a.clone(),
+            num_words,
+            false,
+        )?;
+
+        self.config.range_check.copy_short_check(
+            layouter.namespace(|| "Check high bits"),
+            zs[num_words].clone(),
+            NUM_BITS % sinsemilla::K,
+        )
+    }
+
+    fn less_than(
+        &self,
+        mut layouter: impl Layouter<pallas::Base>,
+        a: &AssignedCell<pallas::Base, pallas::Base>,
+        b: &AssignedCell<pallas::Base, pallas::Base>,
+    ) -> Result<AssignedCell<pallas::Base, pallas::Base>, plonk::Error> {
+        self.range_check(layouter.namespace(|| "a range check"), a)?;
+        self.range_check(layouter.namespace(|| "b range check"), b)?;
+
+        let (lt, diff) = layouter.assign_region(
+            || "lt = a < b",
+            |mut region| {
+                self.config.q_lt.enable(&mut region, 0)?;
+
+                a.copy_advice(|| "copy a", &mut region, self.config.a, 0)?;
+                b.copy_advice(|| "copy b", &mut region, self.config.b, 0)?;
+
+                let lt_val = a.value().zip(b.value()).map(|(a, b)| {
+                    if a.get_lower_128() < b.get_lower_128() {
+                        pallas::Base::one()
+                    } else {
+                        pallas::Base::zero()
+                    }
+                });
+                let lt = region.assign_advice(|| "lt", self.config.lt, 0, || lt_val)?;
+
+                let diff_val = a
+                    .value()
+                    .zip(b.value())
+                    .zip(lt_val)
+                    .map(|((a, b), lt)| a - b + lt * pallas::Base::from_u128(1 << NUM_BITS));
+                let diff = region.assign_advice(|| "diff", self.config.diff, 0, || diff_val)?;
+
+                Ok((lt, diff))
+            },
+        )?;
+
+        self.range_check(layouter.namespace(|| "diff range check"), &diff)?;
+
+        Ok(lt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zk::assign_free_advice;
+    use halo2_proofs::{
+        circuit::{floor_planner, Value},
+        dev::MockProver,
+        plonk::{Circuit, Instance as InstanceColumn, TableColumn},
+    };
+
+    #[derive(Clone)]
+    struct LessThanCircuitConfig {
+        primary: Column<InstanceColumn>,
+        advices: [Column<Advice>; 5],
+        table_idx: TableColumn,
+        lessthan_config: LessThanConfig,
+    }
+
+    #[derive(Default)]
+    struct LessThanCircuit {
+        a: Value<pallas::Base>,
+        b: Value<pallas::Base>,
+    }
+
+    impl Circuit<pallas::Base> for LessThanCircuit {
+        type Config = LessThanCircuitConfig;
+        type FloorPlanner = floor_planner::V1;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<pallas::Base>) -> Self::Config {
+            let advices = [
+                meta.advice_column(),
+                meta.advice_column(),
+                meta.advice_column(),
+                meta.advice_column(),
+                meta.advice_column(),
+            ];
+
+            let primary = meta.instance_column();
+            meta.enable_equality(primary);
+
+            for advice in advices.iter() {
+                meta.enable_equality(*advice);
+            }
+
+            let constants = meta.fixed_column();
+            meta.enable_constant(constants);
+
+            let table_idx = meta.lookup_table_column();
+            let range_check = LookupRangeCheckConfig::configure(meta, advices[4], table_idx);
+
+            let lessthan_config =
+                LessThanChip::configure(meta, advices[..4].try_into().unwrap(), range_check);
+
+            LessThanCircuitConfig { primary, advices, table_idx, lessthan_config }
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<pallas::Base>,
+        ) -> std::result::Result<(), plonk::Error> {
+            layouter.assign_table(
+                || "table_idx",
+                |mut table| {
+                    for index in 0..(1 << sinsemilla::K) {
+                        table.assign_cell(
+                            || "table_idx",
+                            config.table_idx,
+                            index,
+                            || Value::known(pallas::Base::from(index as u64)),
+                        )?;
+                    }
+                    Ok(())
+                },
+            )?;
+
+            let lt_chip = LessThanChip::construct(config.lessthan_config.clone());
+
+            let a = assign_free_advice(layouter.namespace(|| "Load a"), config.advices[0], self.a)?;
+            let b = assign_free_advice(layouter.namespace(|| "Load b"), config.advices[1], self.b)?;
+
+            let lt = lt_chip.less_than(layouter.namespace(|| "a < b"), &a, &b)?;
+            layouter.constrain_instance(lt.cell(), config.primary, 0)
+        }
+    }
+
+    fn verifies(a: u64, b: pallas::Base, lt: bool) -> bool {
+        let circuit =
+            LessThanCircuit { a: Value::known(pallas::Base::from(a)), b: Value::known(b) };
+        let public_inputs = vec![pallas::Base::from(lt as u64)];
+
+        let prover = MockProver::run(11, &circuit, vec![public_inputs]).unwrap();
+        prover.verify().is_ok()
+    }
+
+    #[test]
+    fn less_than_circuit() {
+        assert!(verifies(1, pallas::Base::from(2), true));
+        assert!(verifies(2, pallas::Base::from(1), false));
+        assert!(verifies(2, pallas::Base::from(2), false));
+        assert!(verifies(0, pallas::Base::from(u64::MAX), true));
+
+        // Wrong results don't verify
+        assert!(!verifies(1, pallas::Base::from(2), false));
+        assert!(!verifies(2, pallas::Base::from(1), true));
+
+        // Neither do values past 64 bits
+        assert!(!verifies(0, pallas::Base::from_u128(1 << NUM_BITS), true));
+    }
+}
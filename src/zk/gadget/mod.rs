@@ -9,3 +9,9 @@ pub mod even_bits;
 
 /// Comparison gadget
 pub mod cmp;
+
+/// Less than comparison and range check gadget
+pub mod less_than;
+
+/// Conditional selection gadget
+pub mod cond_select;
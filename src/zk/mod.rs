@@ -1,6 +1,8 @@
 /// Halo2 zkas virtual machine
 pub mod vm;
 pub mod vm_stack;
+/// Typed witnesses for provers
+pub mod witness;
 
 /// ZK circuits
 pub mod circuit;
@@ -8,6 +10,9 @@ pub mod circuit;
 /// ZK gadget implementations
 pub mod gadget;
 
+/// Constraint counting
+pub mod constraints;
+
 use halo2_proofs::{
     arithmetic::Field,
     circuit::{AssignedCell, Layouter, Value},
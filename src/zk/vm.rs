@@ -19,14 +19,16 @@ use halo2_gadgets::{
 use halo2_proofs::{
     circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
     plonk,
-    plonk::{Advice, Circuit, Column, ConstraintSystem, Instance as InstanceColumn},
+    plonk::{Advice, Circuit, Column, ConstraintSystem, Fixed, Instance as InstanceColumn},
 };
 use log::debug;
 use pasta_curves::{group::Curve, pallas, Fp};
 
 use super::gadget::{
     arithmetic::{ArithChip, ArithConfig, ArithInstruction},
+    cond_select::{CondSelectChip, CondSelectConfig, CondSelectInstruction},
     even_bits::{EvenBitsChip, EvenBitsConfig},
+    less_than::{LessThanChip, LessThanConfig, LessThanInstruction},
 };
 
 use super::assign_free_advice;
@@ -43,6 +45,8 @@ use crate::{
 #[derive(Clone)]
 pub struct VmConfig {
     primary: Column<InstanceColumn>,
+    /// Fixed column global constants are loaded from
+    pub(crate) constants: Column<Fixed>,
     advices: [Column<Advice>; 10],
     ecc_config: EccConfig<OrchardFixedBases>,
     merkle_cfg1: MerkleConfig<OrchardHashDomains, OrchardCommitDomains, OrchardFixedBases>,
@@ -52,6 +56,8 @@ pub struct VmConfig {
     poseidon_config: PoseidonConfig<pallas::Base, 3, 2>,
     arith_config: ArithConfig,
     evenbits_config: EvenBitsConfig,
+    lessthan_config: LessThanConfig,
+    condselect_config: CondSelectConfig,
     //greaterthan_config: GreaterThanConfig,
 }
 
@@ -98,6 +104,14 @@ impl VmConfig {
         EvenBitsChip::construct(self.evenbits_config.clone())
     }
 
+    fn lessthan_chip(&self) -> LessThanChip {
+        LessThanChip::construct(self.lessthan_config.clone())
+    }
+
+    fn condselect_chip(&self) -> CondSelectChip {
+        CondSelectChip::construct(self.condselect_config.clone())
+    }
+
     //fn greaterthan_chip(&self) -> GreaterThanChip<pallas::Base, 24> {
     //  GreaterThanChip::construct(self.greaterthan_config.clone())
     //    }
@@ -203,6 +217,13 @@ impl Circuit<pallas::Base> for ZkCircuit {
         // Configuration for the EvenBits chip
         let evenbits_config = EvenBitsChip::<pallas::Base, 24>::configure(meta);
 
+        // Configuration for the LessThan chip, sharing the lookup range check
+        let lessthan_config =
+            LessThanChip::configure(meta, advices[..4].try_into().unwrap(), range_check);
+
+        // Configuration for the CondSelect chip
+        let condselect_config = CondSelectChip::configure(meta, advices[..4].try_into().unwrap());
+
         // Configuration for the GreaterThan chip
         //let greaterthan_config =
         //            GreaterThanChip::<pallas::Base, 24>::configure(meta, [advices[8], advices[9]], primary);
@@ -239,6 +260,7 @@ impl Circuit<pallas::Base> for ZkCircuit {
 
         VmConfig {
             primary,
+            constants: lagrange_coeffs[0],
             advices,
             ecc_config,
             merkle_cfg1,
@@ -248,6 +270,8 @@ impl Circuit<pallas::Base> for ZkCircuit {
             poseidon_config,
             arith_config,
             evenbits_config,
+            lessthan_config,
+            condselect_config,
             //greaterthan_config,
         }
     }
@@ -278,6 +302,12 @@ impl Circuit<pallas::Base> for ZkCircuit {
         let eb_chip = config.evenbits_chip();
         eb_chip.alloc_table(&mut layouter.namespace(|| "alloc table"))?;
 
+        // Construct the LessThan chip.
+        let lt_chip = config.lessthan_chip();
+
+        // Construct the CondSelect chip.
+        let cs_chip = config.condselect_chip();
+
         // Construct the GreaterThan chip.
         //let gt_chip = config.greaterthan_chip();
 
@@ -603,6 +633,66 @@ impl Circuit<pallas::Base> for ZkCircuit {
                     stack.push(StackVar::Base(greater_than.0));
                 }
                 */
+                Opcode::LessThan => {
+                    debug!("Executing `LessThan{:?}` opcode", opcode.1);
+                    let args = &opcode.1;
+
+                    let lhs = &stack[args[0]].clone().into();
+                    let rhs = &stack[args[1]].clone().into();
+
+                    let lt = lt_chip.less_than(layouter.namespace(|| "LessThan()"), lhs, rhs)?;
+
+                    debug!("Pushing comparison result to stack index {}", stack.len());
+                    stack.push(StackVar::Base(lt));
+                }
+
+                Opcode::Select => {
+                    debug!("Executing `Select{:?}` opcode", opcode.1);
+                    let args = &opcode.1;
+
+                    let cond = &stack[args[0]].clone().into();
+                    let lhs = &stack[args[1]].clone().into();
+                    let rhs = &stack[args[2]].clone().into();
+
+                    let selected = cs_chip.conditional_select(
+                        layouter.namespace(|| "Select()"),
+                        cond,
+                        lhs,
+                        rhs,
+                    )?;
+
+                    debug!("Pushing selection result to stack index {}", stack.len());
+                    stack.push(StackVar::Base(selected));
+                }
+
+                Opcode::RangeCheck => {
+                    debug!("Executing `RangeCheck{:?}` opcode", opcode.1);
+                    let args = &opcode.1;
+
+                    let var = &stack[args[0]].clone().into();
+
+                    lt_chip.range_check(layouter.namespace(|| "RangeCheck()"), var)?;
+                }
+
+                Opcode::BoolCheck => {
+                    debug!("Executing `BoolCheck{:?}` opcode", opcode.1);
+                    let args = &opcode.1;
+
+                    let var: AssignedCell<Fp, Fp> = stack[args[0]].clone().into();
+
+                    // A boolean is its own square
+                    let square = arith_chip.mul(
+                        layouter.namespace(|| "BoolCheck: var * var"),
+                        &var,
+                        &var,
+                    )?;
+
+                    layouter.assign_region(
+                        || "BoolCheck()",
+                        |mut region| region.constrain_equal(var.cell(), square.cell()),
+                    )?;
+                }
+
                 Opcode::ConstrainInstance => {
                     debug!("Executing `ConstrainInstance{:?}` opcode", opcode.1);
                     let args = &opcode.1;
@@ -0,0 +1,184 @@
+//! Typed witnesses for provers. Instead of lining up a `Vec<Witness>` by
+//! hand in the order of the circuit's `contract` section, a prover declares
+//! the witnesses of the circuit with [`zk_witnesses!`], sets them by name,
+//! and gets an error naming the one it forgot before proving starts.
+//!
+//! `zkas -w circuit.zk` prints the declaration for a circuit.
+//!
+//! [`zk_witnesses!`]: crate::zk_witnesses
+pub use halo2_proofs::circuit::Value;
+
+use super::vm_stack::Witness;
+use crate::{
+    zkas::{decoder::ZkBinary, types::Type},
+    Error, Result,
+};
+
+/// Rust types of the values of each zkas witness type
+pub mod types {
+    use pasta_curves::pallas;
+
+    use crate::crypto::merkle_node::MerkleNode;
+
+    pub type EcPoint = pallas::Point;
+    pub type EcFixedPoint = pallas::Point;
+    pub type Base = pallas::Base;
+    pub type Scalar = pallas::Scalar;
+    pub type MerklePath = [MerkleNode; 32];
+    pub type Uint32 = u32;
+    pub type Uint64 = u64;
+}
+
+/// Declare the witnesses of a circuit, in the order of its `contract`
+/// section, as a struct with a setter for each of them. `build` checks
+/// they are all set and match the circuit, and returns them for
+/// [`ZkCircuit::new`](crate::zk::vm::ZkCircuit::new).
+///
+/// ```ignore
+/// zk_witnesses! {
+///     pub struct ArithmeticWitnesses {
+///         a: Base,
+///         b: Base,
+///     }
+/// }
+///
+/// let witnesses = ArithmeticWitnesses::new().a(a).b(b).build(&zkbin)?;
+/// let circuit = ZkCircuit::new(witnesses, zkbin);
+/// ```
+#[macro_export]
+macro_rules! zk_witnesses {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $($field:ident: $typ:ident),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone, Default)]
+        $vis struct $name {
+            $($field: Option<$crate::zk::witness::types::$typ>,)*
+        }
+
+        impl $name {
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            $(
+                pub fn $field(mut self, value: $crate::zk::witness::types::$typ) -> Self {
+                    self.$field = Some(value);
+                    self
+                }
+            )*
+
+            /// The witnesses in circuit order, once they are all set
+            pub fn build(
+                self,
+                zkbin: &$crate::zkas::decoder::ZkBinary,
+            ) -> $crate::Result<Vec<$crate::zk::vm_stack::Witness>> {
+                $crate::zk::witness::build_witnesses(
+                    zkbin,
+                    vec![$((
+                        stringify!($field),
+                        $crate::zkas::types::Type::$typ,
+                        self.$field.map(|v| {
+                            let value = $crate::zk::witness::Value::known(v);
+                            $crate::zk::vm_stack::Witness::$typ(value)
+                        }),
+                    ),)*],
+                )
+            }
+        }
+    };
+}
+
+/// Check the declared witnesses against the circuit, and that each of them
+/// was given a value. Used by [`zk_witnesses!`](crate::zk_witnesses).
+pub fn build_witnesses(
+    zkbin: &ZkBinary,
+    fields: Vec<(&'static str, Type, Option<Witness>)>,
+) -> Result<Vec<Witness>> {
+    if fields.len() != zkbin.witnesses.len() {
+        return Err(Error::WitnessMismatch(format!(
+            "circuit has {} witnesses, {} are declared",
+            zkbin.witnesses.len(),
+            fields.len()
+        )))
+    }
+
+    let mut ret = Vec::with_capacity(fields.len());
+    for ((name, typ, witness), expected) in fields.into_iter().zip(zkbin.witnesses.iter()) {
+        if typ != *expected {
+            return Err(Error::WitnessMismatch(format!(
+                "`{}` is declared {}, circuit has {}",
+                name,
+                typ.name(),
+                expected.name()
+            )))
+        }
+
+        match witness {
+            Some(v) => ret.push(v),
+            None => return Err(Error::MissingWitness(name)),
+        }
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use pasta_curves::pallas;
+
+    use super::*;
+    use crate::zkas::{analyzer::Analyzer, compiler::Compiler, lexer::Lexer, parser::Parser};
+
+    zk_witnesses! {
+        struct ArithmeticWitnesses {
+            a: Base,
+            b: Base,
+        }
+    }
+
+    zk_witnesses! {
+        struct WrongWitnesses {
+            a: Base,
+            b: Scalar,
+        }
+    }
+
+    #[test]
+    fn zk_witnesses() {
+        let source = include_str!("../../proof/arithmetic.zk");
+        let tokens = Lexer::new("arithmetic.zk", source.chars()).lex();
+        let (constants, witnesses, statements) =
+            Parser::new("arithmetic.zk", source.chars(), tokens).parse();
+        let mut analyzer =
+            Analyzer::new("arithmetic.zk", source.chars(), constants, witnesses, statements);
+        analyzer.analyze_types();
+        let bincode = Compiler::new(
+            "arithmetic.zk",
+            source.chars(),
+            analyzer.constants,
+            analyzer.witnesses,
+            analyzer.statements,
+            false,
+        )
+        .compile();
+        let zkbin = ZkBinary::decode(&bincode).unwrap();
+
+        let a = pallas::Base::from(42);
+        let b = pallas::Base::from(69);
+        let witnesses = ArithmeticWitnesses::new().b(b).a(a).build(&zkbin).unwrap();
+        assert_eq!(witnesses.len(), 2);
+        assert!(matches!(witnesses[0], Witness::Base(_)));
+
+        match ArithmeticWitnesses::new().a(a).build(&zkbin) {
+            Err(Error::MissingWitness(name)) => assert_eq!(name, "b"),
+            _ => panic!("missing witness wasn't caught"),
+        }
+
+        let wrong = WrongWitnesses::new().a(a).b(pallas::Scalar::from(69));
+        assert!(matches!(wrong.build(&zkbin), Err(Error::WitnessMismatch(_))));
+    }
+}
@@ -45,6 +45,17 @@ impl Analyzer {
 
         for statement in &self.statements {
             let mut stmt = statement.clone();
+            let namespace = statement.namespace.as_deref();
+
+            // Errors in included fragments point into their own file
+            let included_error;
+            let error = match &statement.file {
+                Some(file) => {
+                    included_error = ErrorEmitter::from_file("Semantic", file);
+                    &included_error
+                }
+                None => &self.error,
+            };
 
             let (return_types, arg_types) = statement.opcode.arg_types();
             let mut args = vec![];
@@ -53,7 +64,7 @@ impl Analyzer {
             // It's kinda ugly.
             if arg_types[0] == Type::BaseArray || arg_types[0] == Type::ScalarArray {
                 if statement.args.is_empty() {
-                    self.error.emit(
+                    error.emit(
                         format!(
                             "Passed no arguments to `{:?}` call. Expected at least 1.",
                             statement.opcode
@@ -64,7 +75,7 @@ impl Analyzer {
                 }
 
                 for i in &statement.args {
-                    if let Some(v) = self.lookup_var(&i.name) {
+                    if let Some((name, v)) = self.lookup_scoped(&i.name, namespace) {
                         let var_type = match v {
                             Var::Constant(c) => c.typ,
                             Var::Witness(c) => c.typ,
@@ -72,7 +83,7 @@ impl Analyzer {
                         };
 
                         if arg_types[0] == Type::BaseArray && var_type != Type::Base {
-                            self.error.emit(
+                            error.emit(
                                 format!(
                                     "Incorrect argument type. Expected `{:?}`, got `{:?}`",
                                     arg_types[0],
@@ -84,7 +95,7 @@ impl Analyzer {
                         }
 
                         if arg_types[0] == Type::ScalarArray && var_type != Type::Scalar {
-                            self.error.emit(
+                            error.emit(
                                 format!(
                                     "Incorrect argument type. Expected `{:?}`, got `{:?}`",
                                     arg_types[0],
@@ -96,10 +107,11 @@ impl Analyzer {
                         }
 
                         let mut arg = i.clone();
+                        arg.name = name;
                         arg.typ = var_type;
                         args.push(arg);
                     } else {
-                        self.error.emit(
+                        error.emit(
                             format!("Unknown argument reference `{}`.", i.name),
                             i.line,
                             i.column,
@@ -108,7 +120,7 @@ impl Analyzer {
                 }
             } else {
                 if statement.args.len() != arg_types.len() {
-                    self.error.emit(
+                    error.emit(
                         format!(
                             "Incorrent number of args to `{:?}` call. Expected {}, got {}",
                             statement.opcode,
@@ -121,7 +133,7 @@ impl Analyzer {
                 }
 
                 for (idx, i) in statement.args.iter().enumerate() {
-                    if let Some(v) = self.lookup_var(&i.name) {
+                    if let Some((name, v)) = self.lookup_scoped(&i.name, namespace) {
                         let var_type = match v {
                             Var::Constant(c) => c.typ,
                             Var::Witness(c) => c.typ,
@@ -129,7 +141,7 @@ impl Analyzer {
                         };
 
                         if var_type != arg_types[idx] {
-                            self.error.emit(
+                            error.emit(
                                 format!(
                                     "Incorrect argument type. Expected `{:?}`, got `{:?}`",
                                     arg_types[idx], var_type,
@@ -140,10 +152,11 @@ impl Analyzer {
                         }
 
                         let mut arg = i.clone();
+                        arg.name = name;
                         arg.typ = var_type;
                         args.push(arg);
                     } else {
-                        self.error.emit(
+                        error.emit(
                             format!("Unknown argument reference `{}`.", i.name),
                             i.line,
                             i.column,
@@ -156,6 +169,9 @@ impl Analyzer {
                 StatementType::Assignment => {
                     // Currently we just support a single return type.
                     let mut var = statement.variable.clone().unwrap();
+                    if let Some(namespace) = namespace {
+                        var.name = format!("{}::{}", namespace, var.name);
+                    }
                     var.typ = return_types[0];
                     stmt.variable = Some(var.clone());
                     stack.push(var.clone());
@@ -231,6 +247,26 @@ impl Analyzer {
         // println!("{:#?}", self.statements);
     }
 
+    /// Look up a name used in the given namespace. Names in included
+    /// fragments are looked up from their namespace outwards, so `foo` in
+    /// `lib` is `lib::foo` if it was assigned in the fragment, and a global
+    /// name otherwise.
+    fn lookup_scoped(&self, name: &str, namespace: Option<&str>) -> Option<(String, Var)> {
+        let mut scope: Vec<&str> = namespace.map_or(vec![], |x| x.split("::").collect());
+
+        loop {
+            let mut path = scope.clone();
+            path.push(name);
+            let name = path.join("::");
+
+            if let Some(v) = self.lookup_var(&name) {
+                return Some((name, v))
+            }
+
+            scope.pop()?;
+        }
+    }
+
     fn lookup_var(&self, name: &str) -> Option<Var> {
         if let Some(r) = self.lookup_constant(name) {
             return Some(Var::Constant(r))
@@ -31,6 +31,11 @@ pub struct Statement {
     pub opcode: Opcode,
     pub args: Vec<Variable>,
     pub line: usize,
+    /// Namespace of the included fragment the statement comes from, e.g.
+    /// `lib` for `include "lib.zk";`, or `None` for the main source
+    pub namespace: Option<String>,
+    /// File of the included fragment the statement comes from
+    pub file: Option<String>,
 }
 
 impl Default for Statement {
@@ -41,6 +46,8 @@ impl Default for Statement {
             opcode: Opcode::Noop,
             args: vec![],
             line: 0,
+            namespace: None,
+            file: None,
         }
     }
 }
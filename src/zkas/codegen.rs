@@ -0,0 +1,45 @@
+use std::fmt::Write;
+
+use super::ast::Witness;
+
+/// Rust declaration of the witnesses of a circuit for provers, using
+/// [`zk_witnesses!`](crate::zk_witnesses). The struct is named after
+/// `name`, e.g. `MintWitnesses` for "mint".
+pub fn witness_struct(name: &str, witnesses: &[Witness]) -> String {
+    let struct_name: String = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .map(|x| x[..1].to_uppercase() + &x[1..])
+        .collect();
+
+    let mut out = String::new();
+    writeln!(out, "darkfi::zk_witnesses! {{").unwrap();
+    writeln!(out, "    /// Witnesses of {}.zk", name).unwrap();
+    writeln!(out, "    pub struct {}Witnesses {{", struct_name).unwrap();
+    for witness in witnesses {
+        writeln!(out, "        {}: {},", witness.name, witness.typ.name()).unwrap();
+    }
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zkas::{lexer::Lexer, parser::Parser};
+
+    #[test]
+    fn zkas_witness_struct() {
+        let source = include_str!("../../proof/arithmetic.zk");
+        let tokens = Lexer::new("arithmetic.zk", source.chars()).lex();
+        let (_, witnesses, _) = Parser::new("arithmetic.zk", source.chars(), tokens).parse();
+
+        assert_eq!(
+            witness_struct("arith_ops", &witnesses),
+            "darkfi::zk_witnesses! {\n    /// Witnesses of arith_ops.zk\n    \
+             pub struct ArithOpsWitnesses {\n        a: Base,\n        b: Base,\n    }\n}\n"
+        );
+    }
+}
@@ -6,7 +6,9 @@ use super::{
 };
 use crate::util::serial::{serialize, VarInt};
 
-/// Version of the binary
+/// Version of the binary. This has to be bumped whenever the encoding
+/// changes, including opcodes being added or renumbered, and a decoder
+/// for the new version added to `ZkBinary::decode`.
 pub const BINARY_VERSION: u8 = 1;
 /// Magic bytes prepended to the binary
 pub const MAGIC_BYTES: [u8; 4] = [0x0b, 0x00, 0xb1, 0x35];
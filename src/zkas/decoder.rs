@@ -1,12 +1,14 @@
 use super::{compiler::MAGIC_BYTES, opcode::Opcode, types::Type};
 use crate::{
     util::serial::{deserialize_partial, VarInt},
-    Error::ZkasDecoderError,
+    Error::{ZkasDecoderError, ZkasUnsupportedVersion},
     Result,
 };
 
 #[derive(Clone, Debug)]
 pub struct ZkBinary {
+    /// Binary version the circuit was decoded from
+    pub version: u8,
     pub constants: Vec<(Type, String)>,
     pub witnesses: Vec<Type>,
     pub opcodes: Vec<(Opcode, Vec<usize>)>,
@@ -14,13 +16,26 @@ pub struct ZkBinary {
 
 impl ZkBinary {
     pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < MAGIC_BYTES.len() + 1 {
+            return Err(ZkasDecoderError("Binary is too short."))
+        }
+
         let magic_bytes = &bytes[0..4];
         if magic_bytes != MAGIC_BYTES {
             return Err(ZkasDecoderError("Magic bytes are incorrect."))
         }
 
-        let _binary_version = &bytes[4];
+        // Every binary version keeps its own decoder, so that circuits
+        // compiled by older versions of zkas keep working once the
+        // format changes.
+        let version = bytes[4];
+        match version {
+            1 => Self::decode_v1(&bytes[5..]),
+            _ => Err(ZkasUnsupportedVersion(version)),
+        }
+    }
 
+    fn decode_v1(bytes: &[u8]) -> Result<Self> {
         let constants_offset = match find_subslice(bytes, b".constant") {
             Some(v) => v,
             None => return Err(ZkasDecoderError("Could not find .constant section.")),
@@ -62,7 +77,7 @@ impl ZkBinary {
         let opcodes = ZkBinary::parse_circuit(circuit_section)?;
         // TODO: Debug info
 
-        Ok(Self { constants, witnesses, opcodes })
+        Ok(Self { version: 1, constants, witnesses, opcodes })
     }
 
     fn parse_constants(bytes: &[u8]) -> Result<Vec<(Type, String)>> {
@@ -124,3 +139,23 @@ impl ZkBinary {
 fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
     haystack.windows(needle.len()).position(|window| window == needle)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn decode_version() {
+        let mut bincode = MAGIC_BYTES.to_vec();
+        bincode.push(1);
+        bincode.extend_from_slice(b".constant.contract.circuit");
+        let zkbin = ZkBinary::decode(&bincode).unwrap();
+        assert_eq!(zkbin.version, 1);
+        assert!(zkbin.opcodes.is_empty());
+
+        bincode[4] = 0xff;
+        assert!(matches!(ZkBinary::decode(&bincode), Err(Error::ZkasUnsupportedVersion(0xff))));
+        assert!(matches!(ZkBinary::decode(&MAGIC_BYTES), Err(Error::ZkasDecoderError(_))));
+    }
+}
@@ -0,0 +1,166 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use super::{decoder::ZkBinary, disassembler::disassemble};
+#[cfg(feature = "crypto")]
+use crate::zk::constraints::constraint_count;
+use crate::Result;
+
+/// Unchanged lines shown around each change
+const CONTEXT_LINES: usize = 2;
+
+/// Describe what changed between two binaries of a circuit, e.g. before and
+/// after an upgrade: how many constraints the circuit is made of, how many
+/// constants, witnesses and opcodes there are, how many of each opcode, and
+/// a line diff of their disassembly. Returns `None` if the circuits are the
+/// same. Constraints are only counted with the `crypto` feature, which lays
+/// out the circuits.
+///
+/// Witnesses and variables are named after their position in the
+/// disassembly, so inserting one renames all the ones after it.
+pub fn diff(old: &ZkBinary, new: &ZkBinary) -> Result<Option<String>> {
+    let old_source = disassemble(old, "circuit")?;
+    let new_source = disassemble(new, "circuit")?;
+    if old_source == new_source {
+        return Ok(None)
+    }
+
+    let mut out = String::new();
+
+    #[cfg(feature = "crypto")]
+    {
+        let old_count = constraint_count(old)?;
+        let new_count = constraint_count(new)?;
+        writeln!(out, "constraints: {} -> {}", old_count.total(), new_count.total()).unwrap();
+        writeln!(out, "\tgates: {} -> {}", old_count.gates, new_count.gates).unwrap();
+        writeln!(out, "\tcopies: {} -> {}", old_count.copies, new_count.copies).unwrap();
+    }
+
+    writeln!(out, "constants: {} -> {}", old.constants.len(), new.constants.len()).unwrap();
+    writeln!(out, "witnesses: {} -> {}", old.witnesses.len(), new.witnesses.len()).unwrap();
+    writeln!(out, "opcodes: {} -> {}", old.opcodes.len(), new.opcodes.len()).unwrap();
+
+    let old_counts = opcode_counts(old);
+    let new_counts = opcode_counts(new);
+    let mut names: Vec<&str> = old_counts.keys().chain(new_counts.keys()).copied().collect();
+    names.sort_unstable();
+    names.dedup();
+    for name in names {
+        let before = old_counts.get(name).copied().unwrap_or_default();
+        let after = new_counts.get(name).copied().unwrap_or_default();
+        if before != after {
+            writeln!(out, "\t{}: {} -> {}", name, before, after).unwrap();
+        }
+    }
+
+    writeln!(out).unwrap();
+    let old_lines: Vec<&str> = old_source.lines().collect();
+    let new_lines: Vec<&str> = new_source.lines().collect();
+    out += &line_diff(&old_lines, &new_lines);
+
+    Ok(Some(out))
+}
+
+fn opcode_counts(zkbin: &ZkBinary) -> BTreeMap<&'static str, usize> {
+    let mut counts = BTreeMap::new();
+    for (opcode, _) in &zkbin.opcodes {
+        *counts.entry(opcode.name()).or_default() += 1;
+    }
+    counts
+}
+
+/// Lines removed from `old` prefixed with `-`, lines added in `new` with
+/// `+`, and unchanged lines near them with a space. Skipped lines are
+/// marked with `...`.
+fn line_diff(old: &[&str], new: &[&str]) -> String {
+    // Length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+
+    let changed: Vec<usize> =
+        lines.iter().enumerate().filter(|(_, x)| x.0 != ' ').map(|(i, _)| i).collect();
+
+    let mut out = String::new();
+    let mut last_shown = None;
+    for (i, (mark, line)) in lines.iter().enumerate() {
+        let near_change = changed.iter().any(|x| x.abs_diff(i) <= CONTEXT_LINES);
+        if !near_change {
+            continue
+        }
+
+        if last_shown.map_or(i > 0, |x| x + 1 < i) {
+            out += "...\n";
+        }
+        writeln!(out, "{}{}", mark, line).unwrap();
+        last_shown = Some(i);
+    }
+
+    if last_shown.map_or(false, |x| x + 1 < lines.len()) {
+        out += "...\n";
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zkas::compile_source;
+
+    fn compile(source: &str) -> ZkBinary {
+        ZkBinary::decode(&compile_source("test.zk", source)).unwrap()
+    }
+
+    #[test]
+    fn zkas_diff() {
+        let source = include_str!("../../proof/arithmetic.zk");
+        let old = compile(source);
+        assert!(diff(&old, &old).unwrap().is_none());
+
+        let new = compile(&source.replace("base_mul(a, b)", "base_add(a, b)"));
+        let out = diff(&old, &new).unwrap().unwrap();
+        assert!(out.contains("opcodes: 14 -> 14\n"));
+        #[cfg(feature = "crypto")]
+        {
+            let old_count = constraint_count(&old).unwrap();
+            let new_count = constraint_count(&new).unwrap();
+            assert!(old_count.gates > 0 && old_count.copies > 0);
+            assert!(out.starts_with(&format!(
+                "constraints: {} -> {}\n",
+                old_count.total(),
+                new_count.total()
+            )));
+        }
+        assert!(out.contains("\tbase_add: 1 -> 2\n\tbase_mul: 1 -> 0\n"));
+        assert!(out.contains("\n-\tr1 = base_mul(w0, w1);\n+\tr1 = base_add(w0, w1);\n"));
+    }
+
+    #[test]
+    fn zkas_line_diff() {
+        let old = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        let new = ["a", "b", "c", "d", "x", "f", "g", "h"];
+        assert_eq!(line_diff(&old, &new), "...\n c\n d\n-e\n+x\n f\n g\n...\n");
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt::Write;
+
+use super::decoder::ZkBinary;
+use crate::{Error::ZkasDecoderError, Result};
+
+/// Render a decoded binary back as zkas source.
+///
+/// The binary doesn't carry the names of witnesses and assigned variables,
+/// so they are named after their position: witnesses `w0`, `w1`, ... and
+/// variables `r0`, `r1`, ... in order of assignment. Constants keep their
+/// names. `namespace` is used for the section names. Compiling the output
+/// again gives back the same binary.
+pub fn disassemble(zkbin: &ZkBinary, namespace: &str) -> Result<String> {
+    // Names of the values on the heap, in the order the compiler put them
+    let mut heap: Vec<String> = vec![];
+    let mut out = String::new();
+
+    writeln!(out, "constant \"{}\" {{", namespace).unwrap();
+    for (typ, name) in &zkbin.constants {
+        writeln!(out, "\t{} {},", typ.name(), name).unwrap();
+        heap.push(name.clone());
+    }
+    writeln!(out, "}}\n").unwrap();
+
+    writeln!(out, "contract \"{}\" {{", namespace).unwrap();
+    for (i, typ) in zkbin.witnesses.iter().enumerate() {
+        let name = format!("w{}", i);
+        writeln!(out, "\t{} {},", typ.name(), name).unwrap();
+        heap.push(name);
+    }
+    writeln!(out, "}}\n").unwrap();
+
+    writeln!(out, "circuit \"{}\" {{", namespace).unwrap();
+    let mut n_vars = 0;
+    for (opcode, args) in &zkbin.opcodes {
+        let mut call_args = vec![];
+        for arg in args {
+            match heap.get(*arg) {
+                Some(v) => call_args.push(v.as_str()),
+                None => return Err(ZkasDecoderError("Opcode argument is not on the heap")),
+            }
+        }
+        let call = format!("{}({})", opcode.name(), call_args.join(", "));
+
+        // Opcodes with a return value are assignments, which put it on
+        // the heap. The others are plain calls.
+        if opcode.arg_types().0.is_empty() {
+            writeln!(out, "\t{};", call).unwrap();
+            continue
+        }
+
+        let name = format!("r{}", n_vars);
+        n_vars += 1;
+        writeln!(out, "\t{} = {};", name, call).unwrap();
+        heap.push(name);
+    }
+    writeln!(out, "}}").unwrap();
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zkas::compile_source as compile;
+
+    #[test]
+    fn disassembler_round_trip() -> Result<()> {
+        let sources = [
+            ("arithmetic.zk", include_str!("../../proof/arithmetic.zk")),
+            ("burn.zk", include_str!("../../proof/burn.zk")),
+            ("mint.zk", include_str!("../../proof/mint.zk")),
+        ];
+
+        for (filename, source) in sources {
+            let bincode = compile(filename, source);
+            let disassembled = disassemble(&ZkBinary::decode(&bincode)?, "Test")?;
+            assert_eq!(compile("disassembled.zk", &disassembled), bincode);
+        }
+
+        Ok(())
+    }
+}
@@ -1,7 +1,53 @@
-use std::{io, io::Write, process};
+use std::{cell::Cell, fs, io, io::Write, panic, process};
 
 use termion::{color, style};
 
+thread_local! {
+    /// Set while errors are being collected by `collect_errors`
+    static COLLECTING: Cell<bool> = Cell::new(false);
+}
+
+/// An error found in zkas source, kept instead of being reported.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    /// Compilation stage the error comes from, e.g. "Parser"
+    pub namespace: String,
+    /// File the error is in, which is an included one for errors in
+    /// circuit fragments
+    pub file: String,
+    pub msg: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Run `f`, returning the first error it emits rather than exiting the
+/// process. The stages bail out on their first error, so this unwinds out
+/// of `f` when it is emitted.
+pub(super) fn collect_errors<T>(f: impl FnOnce() -> T) -> Result<T, Diagnostic> {
+    let collecting = COLLECTING.with(|c| c.replace(true));
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(f));
+    COLLECTING.with(|c| c.set(collecting));
+
+    result.map_err(|payload| match payload.downcast::<Diagnostic>() {
+        Ok(diagnostic) => *diagnostic,
+        // Anything else is a bug in zkas, but the source can still be
+        // pointed at while it is being edited.
+        Err(payload) => {
+            let msg = match payload.downcast_ref::<&str>() {
+                Some(v) => v.to_string(),
+                None => payload.downcast_ref::<String>().cloned().unwrap_or_default(),
+            };
+            Diagnostic {
+                namespace: "Internal".to_string(),
+                file: String::new(),
+                msg: format!("zkas failed on this source: {}", msg),
+                line: 1,
+                column: 1,
+            }
+        }
+    })
+}
+
 pub(super) struct ErrorEmitter {
     namespace: String,
     file: String,
@@ -13,9 +59,29 @@ impl ErrorEmitter {
         Self { namespace: namespace.to_string(), file: file.to_string(), lines }
     }
 
+    /// Create an emitter for errors in an included file
+    pub fn from_file(namespace: &str, file: &str) -> Self {
+        let source = fs::read_to_string(file).unwrap_or_default();
+        Self::new(namespace, file, source.lines().map(|x| x.to_string()).collect())
+    }
+
     pub fn emit(&self, msg: String, ln: usize, col: usize) {
+        if COLLECTING.with(|c| c.get()) {
+            // resume_unwind() doesn't run the panic hook, so nothing
+            // gets printed on the way to collect_errors().
+            let diagnostic = Diagnostic {
+                namespace: self.namespace.clone(),
+                file: self.file.clone(),
+                msg,
+                line: ln,
+                column: col,
+            };
+            panic::resume_unwind(Box::new(diagnostic));
+        }
+
         let err_msg = format!("{} (line {}, column {})", msg, ln, col);
-        let dbg_msg = format!("{}:{}:{}: {}", self.file, ln, col, self.lines[ln - 1]);
+        let line = self.lines.get(ln.wrapping_sub(1)).map_or("", |x| x.as_str());
+        let dbg_msg = format!("{}:{}:{}: {}", self.file, ln, col, line);
         let pad = dbg_msg.split(": ").next().unwrap().len() + col + 2;
         let caret = format!("{:width$}^", "", width = pad);
         let msg = format!("{}\n{}\n{}\n", err_msg, dbg_msg, caret);
@@ -134,12 +134,14 @@ impl<'a> Lexer<'a> {
                 continue
             }
 
-            if in_string && (is_letter(c) || is_digit(c)) {
+            // Strings also hold file paths for `include`
+            if in_string && (is_letter(c) || is_digit(c) || ['.', '/', '-'].contains(&c)) {
                 strbuf.push(c);
                 continue
             }
 
-            if in_symbol && is_digit(c) {
+            // Colons are for namespaced names, e.g. `lib::foo`
+            if in_symbol && (is_digit(c) || c == ':') {
                 symbuf.push(c);
                 continue
             }
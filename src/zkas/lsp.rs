@@ -0,0 +1,212 @@
+use super::{
+    analyzer::Analyzer,
+    ast::StatementType,
+    error::collect_errors,
+    lexer::{Lexer, Token, TokenType},
+    opcode::Opcode,
+    parser::Parser,
+    types::Type,
+};
+
+pub use super::error::Diagnostic;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Constant,
+    Witness,
+    Variable,
+}
+
+impl SymbolKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Constant => "constant",
+            Self::Witness => "witness",
+            Self::Variable => "variable",
+        }
+    }
+}
+
+/// A name defined in zkas source
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// `Type::Dummy` for variables the analyzer didn't get to
+    pub typ: Type,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A zkas source run through the lexer, parser and analyzer for editor
+/// support. Rather than exiting on the first error like the compiler does,
+/// this keeps it as a diagnostic along with whatever was made out of the
+/// source until then. Lines and columns start at 1, like in zkas errors.
+pub struct Document {
+    /// First error found in the source, if any
+    pub diagnostic: Option<Diagnostic>,
+    tokens: Vec<Token>,
+    symbols: Vec<Symbol>,
+}
+
+impl Document {
+    pub fn analyze(filename: &str, source: &str) -> Self {
+        let tokens = match collect_errors(|| Lexer::new(filename, source.chars()).lex()) {
+            Ok(v) => v,
+            Err(e) => return Self { diagnostic: Some(e), tokens: vec![], symbols: vec![] },
+        };
+
+        let parser = Parser::new(filename, source.chars(), tokens.clone());
+        let (constants, witnesses, statements) = match collect_errors(|| parser.parse()) {
+            Ok(v) => v,
+            Err(e) => return Self { diagnostic: Some(e), tokens, symbols: vec![] },
+        };
+
+        let mut analyzer =
+            Analyzer::new(filename, source.chars(), constants, witnesses, statements.clone());
+        let diagnostic = collect_errors(|| analyzer.analyze_types()).err();
+
+        let mut symbols = vec![];
+        for i in &analyzer.constants {
+            symbols.push(Symbol {
+                name: i.name.clone(),
+                kind: SymbolKind::Constant,
+                typ: i.typ,
+                line: i.line,
+                column: i.column,
+            });
+        }
+
+        for i in &analyzer.witnesses {
+            symbols.push(Symbol {
+                name: i.name.clone(),
+                kind: SymbolKind::Witness,
+                typ: i.typ,
+                line: i.line,
+                column: i.column,
+            });
+        }
+
+        // The analyzer pushes typed variables on its stack as it goes, so
+        // it has the ones before the error even if it didn't finish. Those
+        // from included fragments are in other files, so they're left out.
+        for stmt in &statements {
+            if stmt.typ != StatementType::Assignment || stmt.file.is_some() {
+                continue
+            }
+
+            let var = stmt.variable.as_ref().unwrap();
+            let typ = analyzer
+                .stack
+                .iter()
+                .find(|x| x.name == var.name && x.line == var.line && x.column == var.column)
+                .map_or(Type::Dummy, |x| x.typ);
+
+            symbols.push(Symbol {
+                name: var.name.clone(),
+                kind: SymbolKind::Variable,
+                typ,
+                line: var.line,
+                column: var.column,
+            });
+        }
+
+        Self { diagnostic, tokens, symbols }
+    }
+
+    /// All the names defined in the source
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Definition of the name at the given position
+    pub fn definition(&self, line: usize, column: usize) -> Option<&Symbol> {
+        let (token, _) = self.token_at(line, column)?;
+        self.lookup(token)
+    }
+
+    /// Description of what is at the given position, with the type of a
+    /// name, or the signature of a function.
+    pub fn hover(&self, line: usize, column: usize) -> Option<String> {
+        let (token, next) = self.token_at(line, column)?;
+
+        if next.map_or(false, |x| x.token_type == TokenType::LeftParen) {
+            let opcode = Opcode::from_name(&token.token)?;
+            let (return_types, arg_types) = opcode.arg_types();
+            let args: Vec<&str> = arg_types.iter().map(|x| x.name()).collect();
+            let mut signature = format!("{}({})", opcode.name(), args.join(", "));
+            if let Some(typ) = return_types.first() {
+                signature += &format!(" -> {}", typ.name());
+            }
+            return Some(signature)
+        }
+
+        let symbol = self.lookup(token)?;
+        match symbol.typ {
+            Type::Dummy => Some(format!("{} {}", symbol.kind.name(), symbol.name)),
+            typ => Some(format!("{} {}: {}", symbol.kind.name(), symbol.name, typ.name())),
+        }
+    }
+
+    /// Find the symbol token covering a position, and the token after it
+    fn token_at(&self, line: usize, column: usize) -> Option<(&Token, Option<&Token>)> {
+        let idx = self.tokens.iter().position(|x| {
+            x.token_type == TokenType::Symbol &&
+                x.line == line &&
+                x.column <= column &&
+                column < x.column + x.token.len()
+        })?;
+
+        Some((&self.tokens[idx], self.tokens.get(idx + 1)))
+    }
+
+    /// Resolve a name to the latest definition that isn't after it, since
+    /// variables may be assigned more than once.
+    fn lookup(&self, token: &Token) -> Option<&Symbol> {
+        let definitions = self.symbols.iter().filter(|x| x.name == token.token);
+        let first = definitions.clone().next();
+        definitions.filter(|x| (x.line, x.column) <= (token.line, token.column)).last().or(first)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = include_str!("../../proof/arithmetic.zk");
+
+    #[test]
+    fn lsp_document() {
+        let doc = Document::analyze("arithmetic.zk", SOURCE);
+        assert!(doc.diagnostic.is_none());
+
+        // `a` in `sum = base_add(a, b);` is the witness
+        let def = doc.definition(9, 20).unwrap();
+        assert_eq!((def.name.as_str(), def.kind, def.line), ("a", SymbolKind::Witness, 4));
+        assert_eq!(doc.hover(9, 20).unwrap(), "witness a: Base");
+
+        // `sum` in `constrain_instance(sum);` is the assigned variable
+        let def = doc.definition(10, 25).unwrap();
+        assert_eq!((def.name.as_str(), def.kind, def.line), ("sum", SymbolKind::Variable, 9));
+        assert_eq!(doc.hover(10, 25).unwrap(), "variable sum: Base");
+
+        assert_eq!(doc.hover(9, 12).unwrap(), "base_add(Base, Base) -> Base");
+        assert!(doc.definition(9, 1).is_none());
+    }
+
+    #[test]
+    fn lsp_diagnostics() {
+        let source = SOURCE.replace("base_mul(a, b)", "base_mul(a, c)");
+        let doc = Document::analyze("arithmetic.zk", &source);
+
+        let diagnostic = doc.diagnostic.as_ref().unwrap();
+        assert_eq!(diagnostic.namespace, "Semantic");
+        assert_eq!((diagnostic.line, diagnostic.column), (12, 27));
+
+        // Names before the error are still known
+        assert_eq!(doc.hover(10, 25).unwrap(), "variable sum: Base");
+
+        let doc = Document::analyze("arithmetic.zk", &SOURCE.replace("a_lt_b);", "a_lt_b"));
+        assert_eq!(doc.diagnostic.unwrap().namespace, "Parser");
+    }
+}
@@ -2,17 +2,47 @@
 pub mod analyzer;
 /// AST
 pub mod ast;
+/// Rust code generation
+pub mod codegen;
 /// Compiler
 pub mod compiler;
 /// Binary decoder
 pub mod decoder;
+/// Binary comparison
+pub mod diff;
+/// Binary disassembler
+pub mod disassembler;
 /// Error emitter
 mod error;
 /// Lexer module
 pub mod lexer;
+/// Source inspection for editors
+pub mod lsp;
 /// Language opcodes
 pub mod opcode;
 /// Parser module
 pub mod parser;
 /// Language types
 pub mod types;
+/// Parallel compilation of many circuits
+pub mod workspace;
+
+/// Compile a zkas source to a binary, for tests
+#[cfg(test)]
+pub(crate) fn compile_source(filename: &str, source: &str) -> Vec<u8> {
+    let tokens = lexer::Lexer::new(filename, source.chars()).lex();
+    let (constants, witnesses, statements) =
+        parser::Parser::new(filename, source.chars(), tokens).parse();
+    let mut analyzer =
+        analyzer::Analyzer::new(filename, source.chars(), constants, witnesses, statements);
+    analyzer.analyze_types();
+    compiler::Compiler::new(
+        filename,
+        source.chars(),
+        analyzer.constants,
+        analyzer.witnesses,
+        analyzer.statements,
+        false,
+    )
+    .compile()
+}
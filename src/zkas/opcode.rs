@@ -40,6 +40,18 @@ pub enum Opcode {
     /// Base field greater than comparison
     GreaterThan = 0x33,
 
+    /// Base field less than comparison of 64-bit values
+    LessThan = 0x34,
+
+    /// Select one of two Base field elements with a boolean condition
+    Select = 0x40,
+
+    /// Constrain a Base field element to fit in 64 bits
+    RangeCheck = 0x50,
+
+    /// Constrain a Base field element to be either 0 or 1
+    BoolCheck = 0x51,
+
     /// Constrain a Base field element to a circuit's public input
     ConstrainInstance = 0xf0,
 
@@ -67,6 +79,10 @@ impl Opcode {
             Opcode::BaseMul => (vec![Type::Base], vec![Type::Base, Type::Base]),
             Opcode::BaseSub => (vec![Type::Base], vec![Type::Base, Type::Base]),
             Opcode::GreaterThan => (vec![Type::Base], vec![Type::Base, Type::Base]),
+            Opcode::LessThan => (vec![Type::Base], vec![Type::Base, Type::Base]),
+            Opcode::Select => (vec![Type::Base], vec![Type::Base, Type::Base, Type::Base]),
+            Opcode::RangeCheck => (vec![], vec![Type::Base]),
+            Opcode::BoolCheck => (vec![], vec![Type::Base]),
             Opcode::ConstrainInstance => (vec![], vec![Type::Base]),
             Opcode::Noop => (vec![], vec![]),
         }
@@ -86,8 +102,60 @@ impl Opcode {
             0x31 => Self::BaseMul,
             0x32 => Self::BaseSub,
             0x33 => Self::GreaterThan,
+            0x34 => Self::LessThan,
+            0x40 => Self::Select,
+            0x50 => Self::RangeCheck,
+            0x51 => Self::BoolCheck,
             0xf0 => Self::ConstrainInstance,
             _ => unimplemented!(),
         }
     }
+
+    /// Name of the opcode's function in zkas source
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::EcAdd => "ec_add",
+            Self::EcMul => "ec_mul",
+            Self::EcMulBase => "ec_mul_base",
+            Self::EcMulShort => "ec_mul_short",
+            Self::EcGetX => "ec_get_x",
+            Self::EcGetY => "ec_get_y",
+            Self::PoseidonHash => "poseidon_hash",
+            Self::CalculateMerkleRoot => "calculate_merkle_root",
+            Self::BaseAdd => "base_add",
+            Self::BaseMul => "base_mul",
+            Self::BaseSub => "base_sub",
+            Self::GreaterThan => "greater_than",
+            Self::LessThan => "less_than",
+            Self::Select => "select",
+            Self::RangeCheck => "range_check",
+            Self::BoolCheck => "bool_check",
+            Self::ConstrainInstance => "constrain_instance",
+            Self::Noop => "noop",
+        }
+    }
+
+    /// Opcode for a function name in zkas source
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ec_add" => Some(Self::EcAdd),
+            "ec_mul" => Some(Self::EcMul),
+            "ec_mul_base" => Some(Self::EcMulBase),
+            "ec_mul_short" => Some(Self::EcMulShort),
+            "ec_get_x" => Some(Self::EcGetX),
+            "ec_get_y" => Some(Self::EcGetY),
+            "poseidon_hash" => Some(Self::PoseidonHash),
+            "calculate_merkle_root" => Some(Self::CalculateMerkleRoot),
+            "base_add" => Some(Self::BaseAdd),
+            "base_mul" => Some(Self::BaseMul),
+            "base_sub" => Some(Self::BaseSub),
+            "greater_than" => Some(Self::GreaterThan),
+            "less_than" => Some(Self::LessThan),
+            "select" => Some(Self::Select),
+            "range_check" => Some(Self::RangeCheck),
+            "bool_check" => Some(Self::BoolCheck),
+            "constrain_instance" => Some(Self::ConstrainInstance),
+            _ => None,
+        }
+    }
 }
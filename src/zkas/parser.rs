@@ -1,4 +1,10 @@
-use std::{iter::Peekable, str::Chars};
+use std::{
+    fs,
+    iter::Peekable,
+    path::{Path, PathBuf},
+    str::Chars,
+    sync::Arc,
+};
 
 use fxhash::FxBuildHasher;
 use indexmap::IndexMap;
@@ -10,13 +16,20 @@ use super::{
         UnparsedWitnesses, Variable, Witness, Witnesses,
     },
     error::ErrorEmitter,
-    lexer::{Token, TokenType},
+    lexer::{Lexer, Token, TokenType},
     opcode::Opcode,
     types::Type,
+    workspace::IncludeCache,
 };
 
 pub struct Parser {
     tokens: Vec<Token>,
+    filename: String,
+    /// Files being parsed, from the main source down to this one, used
+    /// to catch circular includes
+    includes: Vec<PathBuf>,
+    /// Included files read by other parsers, when compiling a workspace
+    include_cache: Option<Arc<IncludeCache>>,
     error: ErrorEmitter,
 }
 
@@ -26,8 +39,15 @@ impl Parser {
         // vector so we have references to lines.
         let lines: Vec<String> = source.as_str().lines().map(|x| x.to_string()).collect();
         let error = ErrorEmitter::new("Parser", filename, lines);
+        let includes = vec![canonical_path(Path::new(filename))];
+
+        Parser { tokens, filename: filename.to_string(), includes, include_cache: None, error }
+    }
 
-        Parser { tokens, error }
+    /// Read included files through `cache`, shared with the parsers of
+    /// other circuits.
+    pub fn with_include_cache(self, cache: Arc<IncludeCache>) -> Self {
+        Parser { include_cache: Some(cache), ..self }
     }
 
     pub fn parse(self) -> (Constants, Witnesses, Statements) {
@@ -478,6 +498,23 @@ impl Parser {
         let mut stmts = vec![];
 
         for statement in statements {
+            // include "lib.zk";
+            if statement.first().map_or(false, |x| x.token == "include") &&
+                statement.get(1).map_or(true, |x| x.token_type != TokenType::Assign)
+            {
+                if statement.len() != 2 || statement[1].token_type != TokenType::String {
+                    self.error.emit(
+                        "Include must be followed by a file path, e.g. `include \"lib.zk\";`"
+                            .to_string(),
+                        statement[0].line,
+                        statement[0].column,
+                    );
+                }
+
+                stmts.extend(self.parse_include(&statement[1]));
+                continue
+            }
+
             let (mut left_paren, mut right_paren) = (0, 0);
             for i in &statement {
                 match i.token.as_str() {
@@ -615,6 +652,22 @@ impl Parser {
                         parse_func!(Opcode::GreaterThan);
                     }
 
+                    "less_than" => {
+                        parse_func!(Opcode::LessThan);
+                    }
+
+                    "select" => {
+                        parse_func!(Opcode::Select);
+                    }
+
+                    "range_check" => {
+                        parse_func!(Opcode::RangeCheck);
+                    }
+
+                    "bool_check" => {
+                        parse_func!(Opcode::BoolCheck);
+                    }
+
                     x => {
                         self.error.emit(
                             format!("Unimplemented function call `{}`", x),
@@ -630,6 +683,92 @@ impl Parser {
         stmts
     }
 
+    /// Parse the circuit fragment pulled in by an `include` statement. Its
+    /// path is relative to the including file, and it only holds circuit
+    /// statements. They're put in the namespace named after the file, so
+    /// the variables assigned in `lib.zk` are reachable as `lib::foo`.
+    fn parse_include(&self, path: &Token) -> Vec<Statement> {
+        let dir = Path::new(&self.filename).parent().unwrap_or_else(|| Path::new(""));
+        let file = dir.join(&path.token);
+
+        let canonical = canonical_path(&file);
+        if self.includes.contains(&canonical) {
+            self.error.emit(
+                format!("Circular include of `{}`", path.token),
+                path.line,
+                path.column,
+            );
+        }
+
+        let namespace = file.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        if namespace.is_empty() || !namespace.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            self.error.emit(
+                format!("Included file name `{}` can't be used as a namespace", namespace),
+                path.line,
+                path.column,
+            );
+        }
+
+        let source = match &self.include_cache {
+            Some(cache) => cache.read(&canonical),
+            None => fs::read_to_string(&file),
+        };
+        let source = match source {
+            Ok(v) => v,
+            Err(e) => {
+                self.error.emit(
+                    format!("Failed reading included file `{}`: {}", path.token, e),
+                    path.line,
+                    path.column,
+                );
+                return vec![]
+            }
+        };
+
+        let filename = file.to_string_lossy().to_string();
+        let tokens = Lexer::new(&filename, source.chars()).lex();
+
+        let mut includes = self.includes.clone();
+        includes.push(canonical);
+        let parser = Parser::new(&filename, source.chars(), vec![]);
+        let parser = Parser { includes, include_cache: self.include_cache.clone(), ..parser };
+
+        let mut statements = vec![];
+        let mut statement = vec![];
+        for token in tokens {
+            if token.token_type == TokenType::Semicolon {
+                statements.push(statement);
+                statement = vec![];
+                continue
+            }
+            statement.push(token);
+        }
+
+        if let Some(token) = statement.first() {
+            parser.error.emit(
+                "Included fragment does not end with a semicolon.".to_string(),
+                token.line,
+                token.column,
+            );
+        }
+
+        // Statements from nested includes are already in a namespace, which
+        // goes under this one.
+        let mut stmts = parser.parse_ast_circuit(statements);
+        for stmt in &mut stmts {
+            stmt.namespace = match stmt.namespace.take() {
+                Some(inner) => Some(format!("{}::{}", namespace, inner)),
+                None => Some(namespace.clone()),
+            };
+            if stmt.file.is_none() {
+                stmt.file = Some(filename.clone());
+            }
+        }
+
+        stmts
+    }
+
     fn parse_function_call(
         &self,
         token: &Token,
@@ -676,3 +815,86 @@ impl Parser {
         args
     }
 }
+
+/// Path used to compare included files, falling back to the given one for
+/// files that don't exist.
+fn canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zkas::{analyzer::Analyzer, lsp::Document};
+
+    #[test]
+    fn parse_include() {
+        let dir = std::env::temp_dir().join("zkas_parse_include");
+        fs::create_dir_all(dir.join("lib")).unwrap();
+
+        // `sum` in the fragment doesn't clash with the one in the main
+        // source, and `a` and `b` are the main source's witnesses.
+        fs::write(
+            dir.join("lib/double.zk"),
+            "sum = base_add(a, b);\ndouble = base_add(sum, sum);\n",
+        )
+        .unwrap();
+        let source = r#"constant "Test" {}
+
+contract "Test" {
+    Base a,
+    Base b,
+}
+
+circuit "Test" {
+    sum = base_add(a, b);
+    include "lib/double.zk";
+    constrain_instance(double::double);
+    constrain_instance(sum);
+}
+"#;
+        let filename = dir.join("test.zk").to_string_lossy().to_string();
+        fs::write(&filename, source).unwrap();
+
+        let tokens = Lexer::new(&filename, source.chars()).lex();
+        let (constants, witnesses, statements) =
+            Parser::new(&filename, source.chars(), tokens).parse();
+        let mut analyzer =
+            Analyzer::new(&filename, source.chars(), constants, witnesses, statements);
+        analyzer.analyze_types();
+
+        let names: Vec<Vec<&str>> = analyzer
+            .statements
+            .iter()
+            .map(|x| {
+                let mut names: Vec<&str> = x.variable.iter().map(|x| x.name.as_str()).collect();
+                names.extend(x.args.iter().map(|x| x.name.as_str()));
+                names
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                vec!["sum", "a", "b"],
+                vec!["double::sum", "a", "b"],
+                vec!["double::double", "double::sum", "double::sum"],
+                vec!["double::double"],
+                vec!["sum"],
+            ]
+        );
+
+        // Errors point into the included file
+        fs::write(dir.join("lib/double.zk"), "sum = base_add(a, c);\n").unwrap();
+        let doc = Document::analyze(&filename, source);
+        let diagnostic = doc.diagnostic.unwrap();
+        assert!(diagnostic.file.ends_with("double.zk"));
+        assert_eq!((diagnostic.line, diagnostic.column), (1, 19));
+
+        // Circular includes are caught
+        fs::write(dir.join("lib/double.zk"), "include \"double.zk\";\n").unwrap();
+        let doc = Document::analyze(&filename, source);
+        assert!(doc.diagnostic.unwrap().msg.starts_with("Circular include"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -56,4 +56,22 @@ impl Type {
             _ => unimplemented!(),
         }
     }
+
+    /// Name of the type in zkas source
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::EcPoint => "EcPoint",
+            Self::EcFixedPoint => "EcFixedPoint",
+            Self::EcFixedPointShort => "EcFixedPointShort",
+            Self::EcFixedPointBase => "EcFixedPointBase",
+            Self::Base => "Base",
+            Self::BaseArray => "BaseArray",
+            Self::Scalar => "Scalar",
+            Self::ScalarArray => "ScalarArray",
+            Self::MerklePath => "MerklePath",
+            Self::Uint32 => "Uint32",
+            Self::Uint64 => "Uint64",
+            Self::Dummy => "Dummy",
+        }
+    }
 }
@@ -0,0 +1,194 @@
+//! Compiling all the circuits of a contract repository at once. The
+//! circuits are compiled in parallel, the files they include are read only
+//! once, and an error in one circuit is reported along with the errors in
+//! the others instead of stopping the build.
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use rayon::prelude::*;
+
+use super::{
+    analyzer::Analyzer,
+    compiler::Compiler,
+    error::{collect_errors, Diagnostic},
+    lexer::Lexer,
+    parser::Parser,
+};
+
+/// Contents of the files included by the circuits of a workspace, keyed by
+/// their canonical path.
+#[derive(Default)]
+pub struct IncludeCache {
+    sources: Mutex<HashMap<PathBuf, String>>,
+}
+
+impl IncludeCache {
+    pub fn read(&self, path: &Path) -> io::Result<String> {
+        if let Some(source) = self.sources.lock().unwrap().get(path) {
+            return Ok(source.clone())
+        }
+
+        // Read without holding the lock. Another thread may read the same
+        // file meanwhile, which is harmless.
+        let source = fs::read_to_string(path)?;
+        self.sources.lock().unwrap().insert(path.to_path_buf(), source.clone());
+        Ok(source)
+    }
+}
+
+/// Result of compiling one circuit of a workspace
+pub struct CompiledCircuit {
+    pub file: PathBuf,
+    /// The bincode, or the first error in the circuit
+    pub result: Result<Vec<u8>, Diagnostic>,
+}
+
+/// Find the circuits in `paths`, which are either `.zk` files or
+/// directories searched recursively. Fragments pulled in by `include`
+/// statements have no `circuit` section, and are left out.
+pub fn find_circuits(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut circuits = vec![];
+    for path in paths {
+        if path.is_dir() {
+            find_in_dir(path, &mut circuits)?;
+        } else {
+            circuits.push(path.clone());
+        }
+    }
+
+    circuits.sort();
+    circuits.dedup();
+    Ok(circuits)
+}
+
+fn find_in_dir(dir: &Path, circuits: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_in_dir(&path, circuits)?;
+            continue
+        }
+
+        if path.extension().map_or(true, |x| x != "zk") {
+            continue
+        }
+
+        let source = fs::read_to_string(&path)?;
+        if source.lines().any(|x| x.trim_start().starts_with("circuit ")) {
+            circuits.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Compile `files` on `threads` threads, or one per logical CPU with 0.
+/// The results are in the same order as the files.
+pub fn compile_workspace(files: &[PathBuf], strip: bool, threads: usize) -> Vec<CompiledCircuit> {
+    let cache = Arc::new(IncludeCache::default());
+    let compile = |file: &PathBuf| CompiledCircuit {
+        file: file.clone(),
+        result: compile_circuit(file, strip, cache.clone()),
+    };
+
+    match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool.install(|| files.par_iter().map(compile).collect()),
+        Err(_) => files.iter().map(compile).collect(),
+    }
+}
+
+fn compile_circuit(
+    file: &Path,
+    strip: bool,
+    cache: Arc<IncludeCache>,
+) -> Result<Vec<u8>, Diagnostic> {
+    let filename = file.to_string_lossy().to_string();
+    let source = match fs::read_to_string(file) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(Diagnostic {
+                namespace: "Workspace".to_string(),
+                file: filename,
+                msg: format!("Failed reading circuit: {}", e),
+                line: 1,
+                column: 1,
+            })
+        }
+    };
+
+    collect_errors(|| {
+        let tokens = Lexer::new(&filename, source.chars()).lex();
+        let parser = Parser::new(&filename, source.chars(), tokens).with_include_cache(cache);
+        let (constants, witnesses, statements) = parser.parse();
+
+        let mut analyzer =
+            Analyzer::new(&filename, source.chars(), constants, witnesses, statements);
+        analyzer.analyze_types();
+
+        Compiler::new(
+            &filename,
+            source.chars(),
+            analyzer.constants,
+            analyzer.witnesses,
+            analyzer.statements,
+            !strip,
+        )
+        .compile()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zkas::decoder::ZkBinary;
+
+    #[test]
+    fn compile_workspace() {
+        let dir = std::env::temp_dir().join("zkas_compile_workspace");
+        fs::create_dir_all(dir.join("lib")).unwrap();
+
+        fs::write(
+            dir.join("lib/double.zk"),
+            "sum = base_add(a, b);\ndouble = base_add(sum, sum);\n",
+        )
+        .unwrap();
+        let source = r#"constant "Test" {}
+
+contract "Test" {
+    Base a,
+    Base b,
+}
+
+circuit "Test" {
+    include "lib/double.zk";
+    constrain_instance(double::double);
+}
+"#;
+        fs::write(dir.join("one.zk"), source).unwrap();
+        fs::write(dir.join("two.zk"), source).unwrap();
+        fs::write(dir.join("broken.zk"), source.replace("Base b", "Base c")).unwrap();
+
+        // The fragment isn't a circuit of its own
+        let files = find_circuits(&[dir.clone()]).unwrap();
+        let names: Vec<String> =
+            files.iter().map(|x| x.file_name().unwrap().to_string_lossy().to_string()).collect();
+        assert_eq!(names, vec!["broken.zk", "one.zk", "two.zk"]);
+
+        let compiled = super::compile_workspace(&files, false, 2);
+        assert_eq!(compiled.len(), 3);
+        for circuit in &compiled[1..] {
+            let bincode = circuit.result.as_ref().unwrap();
+            assert_eq!(ZkBinary::decode(bincode).unwrap().witnesses.len(), 2);
+        }
+
+        // The error is in the included file, which refers to `b`
+        let diagnostic = compiled[0].result.as_ref().unwrap_err();
+        assert!(diagnostic.file.ends_with("double.zk"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -32,8 +32,8 @@ fn arithmetic_proof() -> Result<()> {
     // Witness values
     let a = pallas::Base::from(42);
     let b = pallas::Base::from(69);
-    let y_0 = pallas::Base::from(0); // Here we will compare a > b, which is false (0)
-    let y_1 = pallas::Base::from(1); // Here we will compare b > a, which is true (1)
+    let y_0 = pallas::Base::from(1); // Here we will compare a < b, which is true (1)
+    let y_1 = pallas::Base::from(0); // Here we will compare b < a, which is false (0)
 
     let prover_witnesses = vec![Witness::Base(Value::known(a)), Witness::Base(Value::known(b))];
 
@@ -41,8 +41,9 @@ fn arithmetic_proof() -> Result<()> {
     let sum = a + b;
     let product = a * b;
     let difference = a - b;
+    let max = b; // Selected with a < b
 
-    let public_inputs = vec![sum, product, difference, y_0, y_1];
+    let public_inputs = vec![sum, product, difference, y_0, y_1, max];
 
     // Create the circuit
     let circuit = ZkCircuit::new(prover_witnesses, zkbin.clone());
@@ -5,15 +5,12 @@ use darkfi::{
         util::{mod_r_p, pedersen_commitment_scalar, pedersen_commitment_u64},
         Proof,
     },
-    zk::{
-        vm::{Witness, ZkCircuit},
-        vm_stack::empty_witnesses,
-    },
+    zk::{vm::ZkCircuit, vm_stack::empty_witnesses},
+    zk_witnesses,
     zkas::decoder::ZkBinary,
     Result,
 };
 use halo2_gadgets::poseidon::primitives as poseidon;
-use halo2_proofs::circuit::Value;
 use pasta_curves::{
     arithmetic::CurveAffine,
     group::{ff::Field, Curve},
@@ -21,6 +18,20 @@ use pasta_curves::{
 };
 use rand::rngs::OsRng;
 
+zk_witnesses! {
+    /// Witnesses of mint.zk
+    struct MintWitnesses {
+        pub_x: Base,
+        pub_y: Base,
+        value: Base,
+        token: Base,
+        serial: Base,
+        coin_blind: Base,
+        value_blind: Scalar,
+        token_blind: Scalar,
+    }
+}
+
 #[test]
 fn mint_proof() -> Result<()> {
     /* ANCHOR: main */
@@ -41,16 +52,16 @@ fn mint_proof() -> Result<()> {
     let public_key = PublicKey::random(&mut OsRng);
     let coords = public_key.0.to_affine().coordinates().unwrap();
 
-    let prover_witnesses = vec![
-        Witness::Base(Value::known(*coords.x())),
-        Witness::Base(Value::known(*coords.y())),
-        Witness::Base(Value::known(pallas::Base::from(value))),
-        Witness::Base(Value::known(token_id)),
-        Witness::Base(Value::known(serial)),
-        Witness::Base(Value::known(coin_blind)),
-        Witness::Scalar(Value::known(value_blind)),
-        Witness::Scalar(Value::known(token_blind)),
-    ];
+    let prover_witnesses = MintWitnesses::new()
+        .pub_x(*coords.x())
+        .pub_y(*coords.y())
+        .value(pallas::Base::from(value))
+        .token(token_id)
+        .serial(serial)
+        .coin_blind(coin_blind)
+        .value_blind(value_blind)
+        .token_blind(token_blind)
+        .build(&zkbin)?;
 
     // Create the public inputs
     let msgs = [*coords.x(), *coords.y(), pallas::Base::from(value), token_id, serial, coin_blind];
@@ -8,7 +8,7 @@ use async_std::{
 };
 use url::Url;
 
-use darkfi::net::transport::{TcpTransport, TorTransport, Transport};
+use darkfi::net::transport::{dial_proxy, Socks5Transport, TcpTransport, TorTransport, Transport};
 
 #[async_std::test]
 async fn tcp_transport() {
@@ -163,3 +163,21 @@ For example: \'export DARKFI_TOR_COOKIE=\"/var/lib/tor/control_auth_cookie\"\'",
     // Try to reach the host
     let _client = tor_client.dial(hurl, None).unwrap().await.unwrap();
 }
+
+#[test]
+fn socks5_dial_proxy() {
+    let proxy = Some(Url::parse("socks5://127.0.0.1:1080").unwrap());
+    let clearnet = Url::parse("tls://127.0.0.1:8342").unwrap();
+    let onion = Url::parse("tcp://abcdefghij234567.onion:8342").unwrap();
+
+    // A configured proxy takes every connection
+    assert_eq!(dial_proxy(&clearnet, &proxy).unwrap(), proxy);
+    assert_eq!(dial_proxy(&onion, &proxy).unwrap(), proxy);
+
+    // Without one, only onion services go through Tor
+    assert_eq!(dial_proxy(&clearnet, &None).unwrap(), None);
+    assert!(dial_proxy(&onion, &None).unwrap().is_some());
+
+    assert!(Socks5Transport::new(clearnet).is_err());
+    assert!(Socks5Transport::new(proxy.unwrap()).unwrap().listen_on(onion).is_err());
+}